@@ -0,0 +1,75 @@
+//! Which admin endpoints a token may call, by the scopes it is configured
+//! with (`admin.tokens[].scopes`). A token with `all` may call everything;
+//! the others only the endpoints of their scopes, so e.g. on-call staff can
+//! be handed a `read` token that sees stats and job status but changes
+//! nothing.
+
+use super::AdminError;
+use crate::config::AdminScope;
+use axum::http::{Method, StatusCode};
+
+/// The scopes of the admin token that authenticated the request, for
+/// handlers that check them per operation (see [`required_scope`]).
+#[derive(Debug, Clone)]
+pub struct Grant {
+    pub token: String,
+    pub scopes: Vec<AdminScope>,
+}
+
+impl Grant {
+    pub fn allows(&self, scope: AdminScope) -> bool {
+        self.scopes
+            .iter()
+            .any(|s| *s == AdminScope::All || *s == scope)
+    }
+
+    /// `403 AccessDenied` unless the token has `scope`.
+    pub fn check(&self, scope: AdminScope) -> Result<(), AdminError> {
+        if self.allows(scope) {
+            return Ok(());
+        }
+        Err(AdminError::new(
+            StatusCode::FORBIDDEN,
+            "AccessDenied",
+            format!(
+                "admin token {} lacks the {} scope",
+                self.token,
+                scope_name(scope)
+            ),
+        ))
+    }
+}
+
+/// The scope needed for `method` on `route`, the route pattern below
+/// `/admin/v1` (e.g. `/jobs/{name}/run`). `None` for `POST /batch`, whose
+/// operations are checked one by one. Requests that change nothing need
+/// `read`; routes not listed here need `all`.
+pub fn required_scope(method: &Method, route: &str) -> Option<AdminScope> {
+    if method == Method::GET || method == Method::HEAD {
+        return Some(AdminScope::Read);
+    }
+    let scope = match route {
+        "/batch" => return None,
+        "/policy/simulate" => AdminScope::Read,
+        "/service-accounts" | "/service-accounts/{access_key}" => AdminScope::Credentials,
+        "/jobs/{name}/run" => AdminScope::Jobs,
+        "/config/reload" | "/drain" => AdminScope::Server,
+        _ if route.starts_with("/kms/") => AdminScope::Kms,
+        _ if route.starts_with("/buckets/") => AdminScope::Buckets,
+        _ => AdminScope::All,
+    };
+    Some(scope)
+}
+
+/// The name of `scope` as written in the config.
+pub fn scope_name(scope: AdminScope) -> &'static str {
+    match scope {
+        AdminScope::All => "all",
+        AdminScope::Read => "read",
+        AdminScope::Credentials => "credentials",
+        AdminScope::Jobs => "jobs",
+        AdminScope::Buckets => "buckets",
+        AdminScope::Kms => "kms",
+        AdminScope::Server => "server",
+    }
+}
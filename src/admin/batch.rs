@@ -0,0 +1,185 @@
+use super::authz::Grant;
+use super::service_accounts::{CreateServiceAccountRequest, service_error};
+use super::{AdminError, AdminToken};
+use crate::config::AdminScope;
+use crate::models::{CreateBucketHeaders, CreateBucketRequest, S3CommonHeaders, ServiceAccount};
+use crate::server::AppState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Most operations one batch may hold.
+const MAX_BATCH_OPERATIONS: usize = 100;
+
+/// One operation of a batch, named by `op`; the other fields are those of
+/// the single-operation endpoint.
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Operation {
+    CreateBucket {
+        bucket: String,
+        /// Defaults to `region.default`.
+        region: Option<String>,
+    },
+    CreateServiceAccount(CreateServiceAccountRequest),
+}
+
+impl Operation {
+    /// The admin scope the single-operation endpoint needs.
+    fn scope(&self) -> AdminScope {
+        match self {
+            Operation::CreateBucket { .. } => AdminScope::Buckets,
+            Operation::CreateServiceAccount(_) => AdminScope::Credentials,
+        }
+    }
+}
+
+/// The outcome of an applied operation, and what undoing it removes.
+#[derive(Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Applied {
+    CreateBucket {
+        bucket: String,
+    },
+    /// Includes the secret, which is not shown anywhere else.
+    CreateServiceAccount(ServiceAccount),
+}
+
+/// `POST /batch`: applies a JSON array of operations in order, all or
+/// nothing: when one fails, those applied before it are undone in reverse
+/// order and the error names the failed operation. Other requests are not
+/// held off meanwhile, so they can see a batch half applied. Nothing is
+/// applied unless the token has the scopes of every operation.
+pub async fn apply_batch(
+    State(state): State<AppState>,
+    Extension(AdminToken(token)): Extension<AdminToken>,
+    Extension(grant): Extension<Grant>,
+    Json(operations): Json<Vec<Operation>>,
+) -> Response {
+    if operations.is_empty() || operations.len() > MAX_BATCH_OPERATIONS {
+        return AdminError::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            format!(
+                "a batch must hold between 1 and {} operations",
+                MAX_BATCH_OPERATIONS
+            ),
+        )
+        .into_response();
+    }
+    for (index, operation) in operations.iter().enumerate() {
+        if let Err(e) = grant.check(operation.scope()) {
+            let mut body = e.to_json();
+            body["operation"] = json!(index);
+            return (e.status, Json(body)).into_response();
+        }
+    }
+    let count = operations.len();
+    let mut applied = Vec::with_capacity(count);
+    for (index, operation) in operations.into_iter().enumerate() {
+        match apply(&state, &token, operation).await {
+            Ok(outcome) => applied.push(outcome),
+            Err(e) => {
+                let rolled_back = applied.len();
+                let rollback_errors = roll_back(&state, applied).await;
+                warn!(
+                    "Batch by {} failed at operation {} ({}); rolled back {} operations",
+                    token, index, e.message, rolled_back
+                );
+                let mut body = e.to_json();
+                body["operation"] = json!(index);
+                body["rolled_back"] = json!(rolled_back);
+                if !rollback_errors.is_empty() {
+                    body["rollback_errors"] = json!(rollback_errors);
+                }
+                return (e.status, Json(body)).into_response();
+            }
+        }
+    }
+    info!("Applied a batch of {} operations by {}", count, token);
+    Json(json!({ "results": applied })).into_response()
+}
+
+async fn apply(state: &AppState, token: &str, operation: Operation) -> Result<Applied, AdminError> {
+    match operation {
+        Operation::CreateBucket { bucket, region } => {
+            let metadata = state
+                .buckets
+                .create_bucket(CreateBucketRequest {
+                    bucket,
+                    location_constraint: region,
+                    headers: CreateBucketHeaders {
+                        common: S3CommonHeaders {
+                            date: String::new(),
+                            host: String::new(),
+                            authorization: None,
+                        },
+                        acl: None,
+                        object_lock_enabled: None,
+                        object_ownership: None,
+                    },
+                })
+                .await
+                .map_err(service_error)?;
+            Ok(Applied::CreateBucket {
+                bucket: metadata.name,
+            })
+        }
+        Operation::CreateServiceAccount(req) => {
+            let account = state
+                .service_accounts
+                .create(req.into_spec(token.to_string()))
+                .await
+                .map_err(service_error)?;
+            Ok(Applied::CreateServiceAccount(account))
+        }
+    }
+}
+
+/// Undoes `applied` last to first; returns the errors of undos that failed
+/// (e.g. a new bucket written to meanwhile), which are left in place.
+async fn roll_back(state: &AppState, applied: Vec<Applied>) -> Vec<serde_json::Value> {
+    let mut errors = Vec::new();
+    for (index, outcome) in applied.into_iter().enumerate().rev() {
+        let undone = match &outcome {
+            Applied::CreateBucket { bucket } => state
+                .storage
+                .delete_bucket(bucket)
+                .map_err(AdminError::from),
+            Applied::CreateServiceAccount(account) => state
+                .service_accounts
+                .delete(&account.access_key)
+                .await
+                .map(|_| ())
+                .map_err(service_error),
+        };
+        if let Err(e) = undone {
+            warn!(
+                "Rolling back batch operation {} failed: {}",
+                index, e.message
+            );
+            let mut error = e.to_json();
+            error["operation"] = json!(index);
+            errors.push(error);
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operations_reject_unknown_fields() {
+        let parse = |json: &str| serde_json::from_str::<Operation>(json);
+        assert!(parse(r#"{"op": "create_bucket", "bucket": "a"}"#).is_ok());
+        assert!(parse(r#"{"op": "create_service_account", "bucket": "a"}"#).is_ok());
+        assert!(parse(r#"{"op": "create_bucket", "bucket": "a", "regoin": "x"}"#).is_err());
+        assert!(parse(r#"{"op": "create_service_account", "bucket": "a", "ttl": 60}"#).is_err());
+    }
+}
@@ -0,0 +1,460 @@
+use super::{AdminError, AdminResult, AdminToken};
+use crate::jobs::bucket_deletions;
+use crate::models::{BucketFreeze, BucketTombstone, EtagAlgorithm};
+use crate::server::AppState;
+use crate::services::bucket_deletion::BucketDeletionStatus;
+use crate::storage::{ReindexOutcome, Storage, StorageError};
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use chrono::{SecondsFormat, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+/// `Retry-After` sent with writes to a frozen bucket unless the freeze
+/// request sets one.
+const DEFAULT_FREEZE_RETRY_AFTER_SECS: u64 = 60;
+/// A reindex reports progress after every this many keys.
+const REINDEX_PROGRESS_INTERVAL: usize = 1000;
+/// Per-key errors listed in a reindex report; the rest are only counted.
+const MAX_REINDEX_ERRORS: usize = 100;
+
+impl From<StorageError> for AdminError {
+    fn from(e: StorageError) -> Self {
+        let (status, code) = match &e {
+            StorageError::NoSuchBucket(_) => (StatusCode::NOT_FOUND, "NoSuchBucket"),
+            StorageError::NoSuchKey(_) => (StatusCode::NOT_FOUND, "NoSuchKey"),
+            StorageError::NoSuchVersion(_) => (StatusCode::NOT_FOUND, "NoSuchVersion"),
+            StorageError::DeleteMarker(_) => (StatusCode::METHOD_NOT_ALLOWED, "MethodNotAllowed"),
+            StorageError::BucketAlreadyExists(_) => (StatusCode::CONFLICT, "BucketAlreadyExists"),
+            StorageError::BucketAlreadyOwnedByYou(_) => {
+                (StatusCode::CONFLICT, "BucketAlreadyOwnedByYou")
+            }
+            StorageError::BucketNotEmpty(_) => (StatusCode::CONFLICT, "BucketNotEmpty"),
+            StorageError::InvalidBucketName(_) => (StatusCode::BAD_REQUEST, "InvalidBucketName"),
+            StorageError::InvalidObjectName(..) => (StatusCode::BAD_REQUEST, "InvalidObjectName"),
+            StorageError::KeyTooLong(_) => (StatusCode::BAD_REQUEST, "KeyTooLongError"),
+            StorageError::ObjectNotAppendable(_) => (StatusCode::CONFLICT, "ObjectNotAppendable"),
+            StorageError::PositionNotEqualToLength(..) => {
+                (StatusCode::CONFLICT, "PositionNotEqualToLength")
+            }
+            StorageError::BucketFrozen(..) => (StatusCode::CONFLICT, "BucketFrozen"),
+            StorageError::NoSuchUpload(_) => (StatusCode::NOT_FOUND, "NoSuchUpload"),
+            StorageError::InvalidPart(_) => (StatusCode::BAD_REQUEST, "InvalidPart"),
+            StorageError::Io(e) => {
+                // OS errors name local paths; they are for the log only.
+                log::error!("storage error: {}", e);
+                return AdminError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "InternalError",
+                    "internal storage error, see the server log",
+                );
+            }
+        };
+        AdminError::new(status, code, e.to_string())
+    }
+}
+
+#[derive(Serialize)]
+pub struct BucketSummary {
+    name: String,
+    /// Former names that still resolve to this bucket.
+    aliases: Vec<String>,
+    frozen: Option<BucketFreeze>,
+    etag_algorithm: EtagAlgorithm,
+}
+
+pub async fn list_buckets(State(state): State<AppState>) -> AdminResult<Vec<BucketSummary>> {
+    let aliases = state.storage.aliases()?;
+    let frozen = state.storage.frozen_buckets()?;
+    let mut buckets = Vec::new();
+    for name in state.storage.list_buckets()? {
+        buckets.push(BucketSummary {
+            aliases: aliases
+                .iter()
+                .filter(|(_, target)| **target == name)
+                .map(|(alias, _)| alias.clone())
+                .collect(),
+            frozen: frozen.iter().find(|f| f.bucket == name).cloned(),
+            etag_algorithm: state.storage.etag_algorithm(&name)?,
+            name,
+        });
+    }
+    Ok(Json(buckets))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenameBucketRequest {
+    new_name: String,
+    /// Keep the old name as an alias of the new bucket.
+    #[serde(default)]
+    keep_alias: bool,
+}
+
+#[derive(Serialize)]
+pub struct RenameBucketResponse {
+    old_name: String,
+    new_name: String,
+    alias_kept: bool,
+}
+
+pub async fn rename_bucket(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+    Json(req): Json<RenameBucketRequest>,
+) -> AdminResult<RenameBucketResponse> {
+    state
+        .storage
+        .rename_bucket(&bucket, &req.new_name, req.keep_alias)?;
+    Ok(Json(RenameBucketResponse {
+        old_name: bucket,
+        new_name: req.new_name,
+        alias_kept: req.keep_alias,
+    }))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FreezeBucketRequest {
+    retry_after_secs: Option<u64>,
+}
+
+/// `POST /buckets/{bucket}/freeze`: writes to the bucket fail with `503` and
+/// `Retry-After` until it is unfrozen, while reads keep being served. Returns
+/// once writes in flight have finished, so a backup can start right away.
+pub async fn freeze_bucket(
+    State(state): State<AppState>,
+    Extension(AdminToken(token)): Extension<AdminToken>,
+    Path(bucket): Path<String>,
+    req: Option<Json<FreezeBucketRequest>>,
+) -> AdminResult<BucketFreeze> {
+    let Json(req) = req.unwrap_or_default();
+    let freeze = BucketFreeze {
+        bucket: state.storage.resolve_bucket(&bucket)?,
+        frozen: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        frozen_by: token,
+        retry_after_secs: req
+            .retry_after_secs
+            .unwrap_or(DEFAULT_FREEZE_RETRY_AFTER_SECS),
+    };
+    // Waits on the storage write lock.
+    let storage = state.storage.clone();
+    let freeze = tokio::task::spawn_blocking(move || storage.freeze_bucket(freeze))
+        .await
+        .map_err(|e| {
+            AdminError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                e.to_string(),
+            )
+        })??;
+    Ok(Json(freeze))
+}
+
+#[derive(Serialize)]
+pub struct UnfreezeBucketResponse {
+    bucket: String,
+    /// Whether the bucket was frozen.
+    unfrozen: bool,
+}
+
+pub async fn unfreeze_bucket(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+) -> AdminResult<UnfreezeBucketResponse> {
+    let bucket = state.storage.resolve_bucket(&bucket)?;
+    let unfrozen = state.storage.unfreeze_bucket(&bucket)?;
+    Ok(Json(UnfreezeBucketResponse { bucket, unfrozen }))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EtagAlgorithmRequest {
+    algorithm: EtagAlgorithm,
+}
+
+#[derive(Serialize)]
+pub struct EtagAlgorithmResponse {
+    bucket: String,
+    etag_algorithm: EtagAlgorithm,
+}
+
+pub async fn get_etag_algorithm(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+) -> AdminResult<EtagAlgorithmResponse> {
+    let bucket = state.storage.resolve_bucket(&bucket)?;
+    let etag_algorithm = state.storage.etag_algorithm(&bucket)?;
+    Ok(Json(EtagAlgorithmResponse {
+        bucket,
+        etag_algorithm,
+    }))
+}
+
+/// `PUT /buckets/{bucket}/etag-algorithm`: how the ETags of objects written
+/// to the bucket from now on are computed. Objects already stored keep the
+/// ETag of the algorithm they were written with, as do multipart uploads
+/// already started.
+pub async fn put_etag_algorithm(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+    Json(req): Json<EtagAlgorithmRequest>,
+) -> AdminResult<EtagAlgorithmResponse> {
+    let bucket = state.storage.resolve_bucket(&bucket)?;
+    let mut metadata = state.storage.bucket_metadata(&bucket)?;
+    metadata.etag_algorithm = match req.algorithm {
+        EtagAlgorithm::Md5 => None,
+        algorithm => Some(algorithm),
+    };
+    state.storage.update_bucket_metadata(&metadata)?;
+    info!(
+        "ETag algorithm of bucket {} set to {:?}",
+        bucket, req.algorithm
+    );
+    Ok(Json(EtagAlgorithmResponse {
+        bucket,
+        etag_algorithm: req.algorithm,
+    }))
+}
+
+fn deletion_error(e: anyhow::Error) -> AdminError {
+    match e.downcast::<StorageError>() {
+        Ok(e) => e.into(),
+        Err(e) => AdminError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "InternalError",
+            format!("{:#}", e),
+        ),
+    }
+}
+
+/// `DELETE /buckets/{bucket}` deletes an empty bucket like the S3 API does.
+/// With `?force`, the bucket and everything in it is removed by the
+/// `bucket-force-delete` job instead; the response is `202 Accepted` with
+/// the deletion's progress, also listed by `GET /buckets/deletions`. With
+/// `bucket_tombstones` enabled, either kind of deletion moves the bucket
+/// into a tombstone at once (objects included with `?force`), returned with
+/// `200 OK`.
+pub async fn delete_bucket(
+    State(state): State<AppState>,
+    Extension(AdminToken(token)): Extension<AdminToken>,
+    Path(bucket): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<Response, AdminError> {
+    let force = query.contains_key("force");
+    let tombstones = state.config.read().unwrap().bucket_tombstones.clone();
+    if tombstones.enabled {
+        let bucket = state.storage.resolve_bucket(&bucket)?;
+        let tombstone =
+            state
+                .storage
+                .tombstone_bucket(&bucket, &token, tombstones.retention_secs, force)?;
+        return Ok(Json(tombstone).into_response());
+    }
+    if !force {
+        state.storage.delete_bucket(&bucket)?;
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    }
+    let status = state
+        .bucket_deletions
+        .request(&bucket, token)
+        .await
+        .map_err(deletion_error)?;
+    state.jobs.trigger(bucket_deletions::NAME);
+    Ok((StatusCode::ACCEPTED, Json(status)).into_response())
+}
+
+pub async fn list_deletions(
+    State(state): State<AppState>,
+) -> AdminResult<Vec<BucketDeletionStatus>> {
+    Ok(Json(state.bucket_deletions.statuses()))
+}
+
+pub async fn list_tombstones(State(state): State<AppState>) -> AdminResult<Vec<BucketTombstone>> {
+    Ok(Json(state.storage.bucket_tombstones()?))
+}
+
+/// `POST /buckets/{bucket}/restore`: brings a tombstoned bucket back as it
+/// was deleted.
+pub async fn restore_bucket(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+) -> AdminResult<BucketTombstone> {
+    Ok(Json(state.storage.restore_bucket(&bucket)?))
+}
+
+/// `DELETE /buckets/tombstones/{bucket}`: removes a tombstoned bucket for
+/// good without waiting for its retention to pass.
+pub async fn purge_tombstone(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+) -> Result<StatusCode, AdminError> {
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || storage.purge_bucket_tombstone(&bucket))
+        .await
+        .map_err(|e| {
+            AdminError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                e.to_string(),
+            )
+        })??;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// A line of the reindex response: `{"progress": ...}` or `{"report": ...}`.
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ReindexLine {
+    Progress(ReindexProgress),
+    Report(ReindexReport),
+}
+
+impl ReindexLine {
+    fn to_line(&self) -> String {
+        format!("{}\n", serde_json::to_string(self).unwrap_or_default())
+    }
+}
+
+#[derive(Serialize)]
+struct ReindexProgress {
+    checked: usize,
+    total: usize,
+}
+
+#[derive(Serialize)]
+struct ReindexFailure {
+    key: String,
+    error: String,
+}
+
+/// Outcome of a reindex, sent as the last line of the response.
+#[derive(Serialize, Default)]
+struct ReindexReport {
+    bucket: String,
+    keys: usize,
+    consistent: usize,
+    created: usize,
+    repaired: usize,
+    replaced: usize,
+    orphans_removed: usize,
+    failed: usize,
+    errors: Vec<ReindexFailure>,
+    duration_ms: u128,
+}
+
+impl ReindexReport {
+    fn fail(&mut self, key: &str, e: StorageError) {
+        self.failed += 1;
+        if self.errors.len() < MAX_REINDEX_ERRORS {
+            self.errors.push(ReindexFailure {
+                key: key.to_string(),
+                error: e.to_string(),
+            });
+        }
+    }
+}
+
+/// `POST /buckets/{bucket}/reindex`: checks the metadata of every object
+/// against the files on disk, rewriting missing, unreadable or stale
+/// metadata and removing metadata of files that no longer exist. The
+/// response streams JSON lines: `{"progress": ...}` every
+/// [`REINDEX_PROGRESS_INTERVAL`] keys, then `{"report": ...}`. The reindex
+/// runs to completion even if the client goes away.
+pub async fn reindex_bucket(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+) -> Result<Response, AdminError> {
+    let bucket = state.storage.resolve_bucket(&bucket)?;
+    if !state.storage.bucket_exists(&bucket) {
+        return Err(StorageError::NoSuchBucket(bucket).into());
+    }
+    if let Some(freeze) = state
+        .storage
+        .frozen_buckets()?
+        .into_iter()
+        .find(|f| f.bucket == bucket)
+    {
+        return Err(StorageError::BucketFrozen(bucket, freeze.retry_after_secs).into());
+    }
+    let (tx, rx) = mpsc::channel::<String>(16);
+    let storage = state.storage.clone();
+    tokio::task::spawn_blocking(move || {
+        let line = match reindex(&storage, &bucket, &tx) {
+            Ok(report) => {
+                info!(
+                    "Reindexed {}: {} keys, {} created, {} repaired, {} replaced, \
+                     {} orphans removed, {} failed",
+                    bucket,
+                    report.keys,
+                    report.created,
+                    report.repaired,
+                    report.replaced,
+                    report.orphans_removed,
+                    report.failed
+                );
+                ReindexLine::Report(report).to_line()
+            }
+            // The status line has been sent; the error becomes the last line.
+            Err(e) => format!("{}\n", AdminError::from(e).to_json()),
+        };
+        let _ = tx.blocking_send(line);
+    });
+    let lines = futures_util::stream::unfold(rx, |mut rx| async move {
+        let line = rx.recv().await?;
+        Some((Ok::<_, Infallible>(line), rx))
+    });
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+        .into_response())
+}
+
+fn reindex(
+    storage: &Storage,
+    bucket: &str,
+    progress: &mpsc::Sender<String>,
+) -> Result<ReindexReport, StorageError> {
+    let started = Instant::now();
+    let keys = storage.list_keys(bucket, "")?;
+    let mut report = ReindexReport {
+        bucket: bucket.to_string(),
+        keys: keys.len(),
+        ..Default::default()
+    };
+    for (i, key) in keys.iter().enumerate() {
+        match storage.reindex_object(bucket, key) {
+            Ok(ReindexOutcome::Consistent) => report.consistent += 1,
+            Ok(ReindexOutcome::Created) => report.created += 1,
+            Ok(ReindexOutcome::Repaired) => report.repaired += 1,
+            Ok(ReindexOutcome::Replaced) => report.replaced += 1,
+            // Deleted since the listing.
+            Err(StorageError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => report.fail(key, e),
+        }
+        if (i + 1) % REINDEX_PROGRESS_INTERVAL == 0 {
+            let line = ReindexLine::Progress(ReindexProgress {
+                checked: i + 1,
+                total: keys.len(),
+            });
+            // A client that went away does not stop the reindex.
+            let _ = progress.blocking_send(line.to_line());
+        }
+    }
+    for key in storage.orphaned_object_metadata(bucket)? {
+        match storage.remove_object_metadata(bucket, &key) {
+            Ok(()) => report.orphans_removed += 1,
+            Err(e) => report.fail(&key, e),
+        }
+    }
+    report.duration_ms = started.elapsed().as_millis();
+    Ok(report)
+}
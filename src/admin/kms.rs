@@ -0,0 +1,92 @@
+use super::{AdminError, AdminResult, AdminToken};
+use crate::models::KmsKey;
+use crate::server::AppState;
+use crate::storage::StorageError;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use serde::{Deserialize, Serialize};
+
+/// Storage failures keep their own codes; anything else is a rejected request.
+fn kms_error(e: anyhow::Error) -> AdminError {
+    match e.downcast::<StorageError>() {
+        Ok(e) => e.into(),
+        Err(e) => AdminError::new(StatusCode::BAD_REQUEST, "InvalidRequest", e.to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateKmsKeyRequest {
+    #[serde(default)]
+    description: String,
+    /// Optional alias, without the `alias/` prefix.
+    alias: Option<String>,
+}
+
+/// A registry key with the ARN clients send and receive.
+#[derive(Serialize)]
+pub struct KmsKeyView {
+    #[serde(flatten)]
+    key: KmsKey,
+    arn: String,
+}
+
+fn view(state: &AppState, key: KmsKey) -> KmsKeyView {
+    KmsKeyView {
+        arn: state.kms.key_arn(&key),
+        key,
+    }
+}
+
+/// `POST /kms/keys`
+pub async fn create_key(
+    State(state): State<AppState>,
+    Extension(AdminToken(token)): Extension<AdminToken>,
+    Json(req): Json<CreateKmsKeyRequest>,
+) -> Result<impl IntoResponse, AdminError> {
+    let key = state
+        .kms
+        .create_key(req.description, req.alias, token)
+        .await
+        .map_err(kms_error)?;
+    Ok((StatusCode::CREATED, Json(view(&state, key))))
+}
+
+pub async fn list_keys(State(state): State<AppState>) -> AdminResult<Vec<KmsKeyView>> {
+    let keys = state.kms.list_keys().await.map_err(kms_error)?;
+    Ok(Json(
+        keys.into_iter().map(|key| view(&state, key)).collect(),
+    ))
+}
+
+pub async fn disable_key(
+    State(state): State<AppState>,
+    Path(key_id): Path<String>,
+) -> AdminResult<KmsKeyView> {
+    set_enabled(state, key_id, false).await
+}
+
+pub async fn enable_key(
+    State(state): State<AppState>,
+    Path(key_id): Path<String>,
+) -> AdminResult<KmsKeyView> {
+    set_enabled(state, key_id, true).await
+}
+
+async fn set_enabled(state: AppState, key_id: String, enabled: bool) -> AdminResult<KmsKeyView> {
+    match state
+        .kms
+        .set_enabled(&key_id, enabled)
+        .await
+        .map_err(kms_error)?
+    {
+        Some(key) => Ok(Json(view(&state, key))),
+        None => Err(AdminError::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchKmsKey",
+            format!("no KMS key with ID {}", key_id),
+        )),
+    }
+}
@@ -0,0 +1,81 @@
+use super::{AdminError, AdminResult};
+use crate::server::AppState;
+use crate::services::metrics_history::{HistoryQuery, UsageSeries};
+use crate::storage::StorageError;
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryParams {
+    /// RFC 3339; snapshots taken before are left out.
+    since: Option<String>,
+    /// RFC 3339; snapshots taken after are left out.
+    until: Option<String>,
+    bucket: Option<String>,
+    operation: Option<String>,
+    /// Seconds each point sums up; one point per snapshot without it.
+    step: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct HistoryResponse {
+    /// Whether snapshots are being taken (`metrics.snapshots.enabled`).
+    enabled: bool,
+    retention_days: u32,
+    step_secs: Option<u64>,
+    series: Vec<UsageSeries>,
+}
+
+fn invalid(message: String) -> AdminError {
+    AdminError::new(StatusCode::BAD_REQUEST, "InvalidRequest", message)
+}
+
+fn time(name: &str, value: Option<String>) -> Result<Option<DateTime<Utc>>, AdminError> {
+    value
+        .map(|value| {
+            DateTime::parse_from_rfc3339(&value)
+                .map(|time| time.to_utc())
+                .map_err(|e| invalid(format!("{} is not an RFC 3339 time: {}", name, e)))
+        })
+        .transpose()
+}
+
+/// `GET /metrics/history`: the stored metrics snapshots as time series of
+/// request counts and bytes per bucket and operation, for capacity trends
+/// without a Prometheus server.
+pub async fn history(
+    State(state): State<AppState>,
+    Query(params): Query<HistoryParams>,
+) -> AdminResult<HistoryResponse> {
+    if params.step == Some(0) {
+        return Err(invalid("step must be > 0".to_string()));
+    }
+    let query = HistoryQuery {
+        since: time("since", params.since)?,
+        until: time("until", params.until)?,
+        bucket: params.bucket,
+        operation: params.operation,
+        step_secs: params.step,
+    };
+    let series = state.metrics_history.history(&query).await.map_err(|e| {
+        match e.downcast::<StorageError>() {
+            Ok(e) => e.into(),
+            Err(e) => AdminError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "InternalError",
+                format!("{:#}", e),
+            ),
+        }
+    })?;
+    let snapshots = state.config.read().unwrap().metrics.snapshots.clone();
+    Ok(Json(HistoryResponse {
+        enabled: snapshots.enabled,
+        retention_days: snapshots.retention_days,
+        step_secs: query.step_secs,
+        series,
+    }))
+}
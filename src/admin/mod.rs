@@ -0,0 +1,387 @@
+mod authz;
+mod batch;
+mod buckets;
+mod kms;
+mod metrics_history;
+mod policy;
+mod service_accounts;
+
+use crate::config::Config;
+use crate::listener::PeerAddr;
+use crate::server::AppState;
+use crate::services::notification::NotificationQueueStatus;
+use crate::services::system_log::{self, AuditLogEntry};
+use crate::storage::validate_bucket_name;
+use axum::extract::{ConnectInfo, MatchedPath, OriginalUri, Path, RawPathParams, Request, State};
+use axum::http::{Method, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::json;
+use std::sync::atomic::Ordering;
+
+/// Error body for the admin API: `{"error": {"code": ..., "message": ...}}`.
+#[derive(Debug)]
+pub struct AdminError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl AdminError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn unauthorized() -> Self {
+        Self::new(
+            StatusCode::UNAUTHORIZED,
+            "Unauthorized",
+            "a valid admin bearer token is required",
+        )
+    }
+}
+
+impl AdminError {
+    /// The JSON error document sent as the response body.
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({ "error": { "code": self.code, "message": self.message } })
+    }
+}
+
+impl IntoResponse for AdminError {
+    fn into_response(self) -> Response {
+        let body = self.to_json();
+        (self.status, Json(body)).into_response()
+    }
+}
+
+pub type AdminResult<T> = Result<Json<T>, AdminError>;
+
+/// Routes served under `/admin/v1`, guarded by admin token authentication.
+pub fn router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/status", get(status))
+        .route("/batch", post(batch::apply_batch))
+        .route("/buckets", get(buckets::list_buckets))
+        .route("/buckets/deletions", get(buckets::list_deletions))
+        .route("/buckets/tombstones", get(buckets::list_tombstones))
+        .route(
+            "/buckets/tombstones/{bucket}",
+            delete(buckets::purge_tombstone),
+        )
+        .route("/buckets/{bucket}", delete(buckets::delete_bucket))
+        .route("/buckets/{bucket}/rename", post(buckets::rename_bucket))
+        .route("/buckets/{bucket}/freeze", post(buckets::freeze_bucket))
+        .route("/buckets/{bucket}/unfreeze", post(buckets::unfreeze_bucket))
+        .route("/buckets/{bucket}/reindex", post(buckets::reindex_bucket))
+        .route("/buckets/{bucket}/restore", post(buckets::restore_bucket))
+        .route(
+            "/buckets/{bucket}/etag-algorithm",
+            get(buckets::get_etag_algorithm).put(buckets::put_etag_algorithm),
+        )
+        .route("/config/reload", post(reload_config))
+        .route("/drain", post(start_drain).delete(stop_drain))
+        .route("/credentials", get(list_credentials))
+        .route(
+            "/service-accounts",
+            get(service_accounts::list_service_accounts)
+                .post(service_accounts::create_service_account),
+        )
+        .route(
+            "/service-accounts/{access_key}",
+            delete(service_accounts::delete_service_account),
+        )
+        .route("/kms/keys", get(kms::list_keys).post(kms::create_key))
+        .route("/kms/keys/{key_id}/disable", post(kms::disable_key))
+        .route("/kms/keys/{key_id}/enable", post(kms::enable_key))
+        .route("/policy/simulate", post(policy::simulate))
+        .route("/metrics", get(metrics))
+        .route("/metrics/history", get(metrics_history::history))
+        .route("/notifications/queue", get(notification_queue))
+        .route("/jobs", get(list_jobs))
+        .route("/jobs/{name}/run", post(run_job))
+        .layer(middleware::from_fn(valid_bucket_name))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_admin_token,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state,
+            require_admin_api_feature,
+        ))
+}
+
+/// `GET /metrics`: Prometheus text format, for scrapers that send the admin
+/// bearer token.
+async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
+
+/// Name of the admin token that authenticated the request, for handlers that
+/// record who made a change.
+#[derive(Debug, Clone)]
+pub struct AdminToken(pub String);
+
+/// Refuses a `{bucket}` that is not a valid bucket name before a handler
+/// maps it onto the storage directory (see [`validate_bucket_name`]).
+async fn valid_bucket_name(
+    params: RawPathParams,
+    req: Request,
+    next: Next,
+) -> Result<Response, AdminError> {
+    if let Some((_, bucket)) = params.iter().find(|(name, _)| *name == "bucket") {
+        validate_bucket_name(bucket)?;
+    }
+    Ok(next.run(req).await)
+}
+
+/// Refuses every admin request while `features.admin_api` is off. Checked per
+/// request, so the API can be switched off by a reload; switching it back on
+/// then needs a SIGHUP or file-watch reload, or a restart.
+async fn require_admin_api_feature(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Result<Response, AdminError> {
+    if !state.config.read().unwrap().features.admin_api {
+        return Err(AdminError::new(
+            StatusCode::NOT_IMPLEMENTED,
+            "NotImplemented",
+            "the admin API is disabled on this server",
+        ));
+    }
+    Ok(next.run(req).await)
+}
+
+/// Checks `Authorization: Bearer <token>` against `admin.tokens`, and that
+/// the token's scopes cover the endpoint (see [`authz`]). S3 credentials are
+/// never accepted here.
+async fn require_admin_token(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, AdminError> {
+    let presented = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim);
+    let grant = {
+        let cfg = state.config.read().unwrap();
+        let admin = match &cfg.admin {
+            Some(admin) if admin.enabled => admin,
+            _ => {
+                return Err(AdminError::new(
+                    StatusCode::NOT_FOUND,
+                    "AdminApiDisabled",
+                    "the admin API is disabled",
+                ));
+            }
+        };
+        let presented = presented.ok_or_else(AdminError::unauthorized)?;
+        admin
+            .tokens
+            .iter()
+            .find(|t| constant_time_eq(t.token.as_bytes(), presented.as_bytes()))
+            .map(|t| authz::Grant {
+                token: t.name.clone(),
+                scopes: t.scopes.clone(),
+            })
+    };
+    let path = req
+        .extensions()
+        .get::<OriginalUri>()
+        .map(|uri| uri.path().to_string())
+        .unwrap_or_else(|| req.uri().path().to_string());
+    match grant {
+        Some(grant) => {
+            let name = grant.token.clone();
+            info!("admin {} {} by token {}", req.method(), path, name);
+            let method = req.method().clone();
+            let client_ip = req
+                .extensions()
+                .get::<ConnectInfo<PeerAddr>>()
+                .map(|ConnectInfo(PeerAddr(addr))| addr.ip().to_string());
+            let time = system_log::now();
+            let route = req
+                .extensions()
+                .get::<MatchedPath>()
+                .map(MatchedPath::as_str);
+            let route = route
+                .map(|r| r.strip_prefix("/admin/v1").unwrap_or(r))
+                .unwrap_or("");
+            let allowed = match authz::required_scope(&method, route) {
+                Some(scope) => grant.check(scope),
+                None => Ok(()),
+            };
+            let response = match allowed {
+                Ok(()) => {
+                    req.extensions_mut().insert(AdminToken(name.clone()));
+                    req.extensions_mut().insert(grant);
+                    next.run(req).await
+                }
+                Err(e) => {
+                    warn!(
+                        "rejected admin request to {} by token {}: {}",
+                        path, name, e.message
+                    );
+                    e.into_response()
+                }
+            };
+            if method != Method::GET && method != Method::HEAD {
+                state.system_logs.record_audit(&AuditLogEntry {
+                    time,
+                    admin_token: name,
+                    client_ip,
+                    method: method.to_string(),
+                    path,
+                    status: response.status().as_u16(),
+                });
+            }
+            Ok(response)
+        }
+        None => {
+            warn!("rejected admin request to {}: bad token", path);
+            Err(AdminError::unauthorized())
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Serialize)]
+struct StatusResponse {
+    version: &'static str,
+    uptime_seconds: u64,
+    region: String,
+    draining: bool,
+}
+
+async fn status(State(state): State<AppState>) -> AdminResult<StatusResponse> {
+    let cfg = state.config.read().unwrap();
+    Ok(Json(StatusResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        uptime_seconds: state.started.elapsed().as_secs(),
+        region: cfg.region.default.clone(),
+        draining: state.draining.load(Ordering::Relaxed),
+    }))
+}
+
+#[derive(Serialize)]
+struct DrainResponse {
+    draining: bool,
+}
+
+/// Makes `/healthz?drain` fail so load balancers stop routing new traffic
+/// here. Requests keep being served; nothing else changes.
+async fn start_drain(State(state): State<AppState>) -> AdminResult<DrainResponse> {
+    if !state.draining.swap(true, Ordering::Relaxed) {
+        info!("Draining: /healthz?drain now reports failure");
+    }
+    Ok(Json(DrainResponse { draining: true }))
+}
+
+async fn stop_drain(State(state): State<AppState>) -> AdminResult<DrainResponse> {
+    if state.draining.swap(false, Ordering::Relaxed) {
+        info!("Drain cancelled: /healthz?drain reports OK again");
+    }
+    Ok(Json(DrainResponse { draining: false }))
+}
+
+#[derive(Serialize)]
+struct ReloadResponse {
+    reloaded: bool,
+    /// Sections that changed but only take effect after a restart.
+    restart_required: Vec<&'static str>,
+}
+
+async fn reload_config(State(state): State<AppState>) -> AdminResult<ReloadResponse> {
+    if !state.config.read().unwrap().config_reload.api {
+        return Err(AdminError::new(
+            StatusCode::FORBIDDEN,
+            "ReloadDisabled",
+            "config reload via API is disabled (config_reload.api)",
+        ));
+    }
+    let new_cfg = Config::load_from_file(&state.config_path)
+        .map_err(|e| AdminError::new(StatusCode::BAD_REQUEST, "InvalidConfig", e))?;
+    let mut cfg = state.config.write().unwrap();
+    let mut restart_required = Vec::new();
+    // The listeners are bound at startup; the default deadline applies live.
+    if new_cfg.server.http != cfg.server.http || new_cfg.server.https != cfg.server.https {
+        restart_required.push("server");
+    }
+    if new_cfg.admin.as_ref().and_then(|a| a.port) != cfg.admin.as_ref().and_then(|a| a.port) {
+        restart_required.push("admin.port");
+    }
+    if new_cfg.jobs != cfg.jobs {
+        restart_required.push("jobs");
+    }
+    if new_cfg.storage != cfg.storage {
+        restart_required.push("storage");
+    }
+    *cfg = new_cfg;
+    info!("Reloaded config from {} via admin API", state.config_path);
+    Ok(Json(ReloadResponse {
+        reloaded: true,
+        restart_required,
+    }))
+}
+
+#[derive(Serialize)]
+struct CredentialSummary {
+    access_key: String,
+    roles: Vec<String>,
+    /// Own permissions plus those of the roles.
+    permissions: usize,
+}
+
+async fn list_credentials(State(state): State<AppState>) -> AdminResult<Vec<CredentialSummary>> {
+    let cfg = state.config.read().unwrap();
+    Ok(Json(
+        cfg.credentials
+            .iter()
+            .map(|c| CredentialSummary {
+                access_key: c.access_key.clone(),
+                roles: c.roles.clone(),
+                permissions: cfg.effective_permissions(c).len(),
+            })
+            .collect(),
+    ))
+}
+
+async fn notification_queue(State(state): State<AppState>) -> AdminResult<NotificationQueueStatus> {
+    Ok(Json(state.notifications.queue_status()))
+}
+
+async fn list_jobs(State(state): State<AppState>) -> AdminResult<Vec<crate::jobs::JobStatus>> {
+    Ok(Json(state.jobs.statuses()))
+}
+
+async fn run_job(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, AdminError> {
+    if !state.jobs.trigger(&name) {
+        return Err(AdminError::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchJob",
+            format!("no job named {}", name),
+        ));
+    }
+    Ok((StatusCode::ACCEPTED, Json(json!({ "triggered": name }))))
+}
@@ -0,0 +1,89 @@
+use super::{AdminError, AdminResult};
+use crate::api::policy::validate_policy;
+use crate::error::S3Error;
+use crate::models::{BucketPolicy, S3_ARN_PREFIX};
+use crate::server::AppState;
+use crate::services::auth::{AuthDecision, S3_ACTIONS, STS_ACTIONS};
+use crate::storage::StorageError;
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SimulateRequest {
+    /// Access key of the caller; anonymous when omitted.
+    principal: Option<String>,
+    /// E.g. `s3:GetObject`; the `s3:` prefix may be left out.
+    action: String,
+    /// `bucket` or `bucket/key`, or their `arn:aws:s3:::` ARN.
+    resource: String,
+    /// The `prefix` of a listing, for permissions with an `s3:prefix`
+    /// condition; none when omitted.
+    prefix: Option<String>,
+    /// A bucket policy to evaluate instead of the bucket's own, to try a
+    /// change before applying it.
+    policy: Option<BucketPolicy>,
+}
+
+/// `POST /policy/simulate`: whether the principal would be allowed the
+/// action on the resource, and what decided it, evaluated exactly as S3
+/// requests are.
+pub async fn simulate(
+    State(state): State<AppState>,
+    Json(req): Json<SimulateRequest>,
+) -> AdminResult<AuthDecision> {
+    let action = if req.action.contains(':') {
+        req.action
+    } else {
+        format!("s3:{}", req.action)
+    };
+    if !S3_ACTIONS.contains(&action.as_str()) && !STS_ACTIONS.contains(&action.as_str()) {
+        return Err(invalid_request(format!("unknown action {}", action)));
+    }
+    let resource = req
+        .resource
+        .strip_prefix(S3_ARN_PREFIX)
+        .unwrap_or(&req.resource);
+    if resource.is_empty() {
+        return Err(invalid_request("resource must name a bucket or object"));
+    }
+    if let Some(policy) = &req.policy {
+        let bucket = resource.split('/').next().unwrap_or(resource);
+        let bucket = state.storage.resolve_bucket(bucket)?;
+        validate_policy(&bucket, policy).map_err(s3_error)?;
+    }
+    let decision = state
+        .auth
+        .simulate(
+            req.principal.as_deref(),
+            &action,
+            resource,
+            req.prefix.as_deref(),
+            req.policy.as_ref(),
+        )
+        .await
+        .map_err(|e| match e.downcast::<StorageError>() {
+            Ok(e) => e.into(),
+            Err(e) => match e.downcast::<S3Error>() {
+                Ok(e) => s3_error(e),
+                Err(e) => AdminError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "InternalError",
+                    e.to_string(),
+                ),
+            },
+        })?;
+    Ok(Json(decision))
+}
+
+fn invalid_request(message: impl Into<String>) -> AdminError {
+    AdminError::new(StatusCode::BAD_REQUEST, "InvalidRequest", message)
+}
+
+/// S3 errors keep their status and code, e.g. `MalformedPolicy` for a
+/// candidate policy that PutBucketPolicy would refuse.
+fn s3_error(e: S3Error) -> AdminError {
+    AdminError::new(e.status, e.code, e.message)
+}
@@ -0,0 +1,125 @@
+use super::{AdminError, AdminResult, AdminToken};
+use crate::models::ServiceAccount;
+use crate::server::AppState;
+use crate::services::service_account::{NewServiceAccount, is_expired};
+use crate::storage::StorageError;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Storage failures keep their own codes; anything else is a rejected request.
+pub(super) fn service_error(e: anyhow::Error) -> AdminError {
+    match e.downcast::<StorageError>() {
+        Ok(e) => e.into(),
+        Err(e) => AdminError::new(StatusCode::BAD_REQUEST, "InvalidRequest", e.to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateServiceAccountRequest {
+    /// Free-form label, e.g. the CI job the credential is for.
+    #[serde(default)]
+    name: String,
+    bucket: String,
+    /// Key prefix the object actions are limited to; empty for the whole bucket.
+    #[serde(default)]
+    prefix: String,
+    /// Defaults to GetObject, PutObject, DeleteObject and ListBucket.
+    actions: Option<Vec<String>>,
+    /// Lifetime in seconds; the credential never expires when omitted.
+    ttl_seconds: Option<u64>,
+}
+
+impl CreateServiceAccountRequest {
+    pub(super) fn into_spec(self, created_by: String) -> NewServiceAccount {
+        NewServiceAccount {
+            name: self.name,
+            bucket: self.bucket,
+            prefix: self.prefix,
+            actions: self.actions,
+            ttl_seconds: self.ttl_seconds,
+            created_by,
+        }
+    }
+}
+
+/// A service account as listed: everything but the secret.
+#[derive(Serialize)]
+pub struct ServiceAccountSummary {
+    access_key: String,
+    name: String,
+    bucket: String,
+    prefix: String,
+    actions: Vec<String>,
+    created: String,
+    expires: Option<String>,
+    expired: bool,
+    created_by: String,
+}
+
+impl ServiceAccountSummary {
+    fn new(account: ServiceAccount, now: DateTime<Utc>) -> Self {
+        Self {
+            expired: is_expired(&account, now),
+            access_key: account.access_key,
+            name: account.name,
+            bucket: account.bucket,
+            prefix: account.prefix,
+            actions: account.actions,
+            created: account.created,
+            expires: account.expires,
+            created_by: account.created_by,
+        }
+    }
+}
+
+/// `POST /service-accounts`: the response is the only place the secret key
+/// is ever returned.
+pub async fn create_service_account(
+    State(state): State<AppState>,
+    Extension(AdminToken(token)): Extension<AdminToken>,
+    Json(req): Json<CreateServiceAccountRequest>,
+) -> Result<impl IntoResponse, AdminError> {
+    let account = state
+        .service_accounts
+        .create(req.into_spec(token))
+        .await
+        .map_err(service_error)?;
+    Ok((StatusCode::CREATED, Json(account)))
+}
+
+pub async fn list_service_accounts(
+    State(state): State<AppState>,
+) -> AdminResult<Vec<ServiceAccountSummary>> {
+    let accounts = state.service_accounts.list().await.map_err(service_error)?;
+    let now = state.clock.now();
+    Ok(Json(
+        accounts
+            .into_iter()
+            .map(|account| ServiceAccountSummary::new(account, now))
+            .collect(),
+    ))
+}
+
+pub async fn delete_service_account(
+    State(state): State<AppState>,
+    Path(access_key): Path<String>,
+) -> Result<StatusCode, AdminError> {
+    if !state
+        .service_accounts
+        .delete(&access_key)
+        .await
+        .map_err(service_error)?
+    {
+        return Err(AdminError::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchServiceAccount",
+            format!("no service account with access key {}", access_key),
+        ));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
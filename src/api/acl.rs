@@ -0,0 +1,247 @@
+use super::tagging::version_headers;
+use super::{
+    MAX_DOCUMENT_SIZE, RequestOrigin, S3Result, authorize, configuration_changed, from_xml,
+    header_str, read_body, to_xml,
+};
+use crate::error::S3Error;
+use crate::models::{
+    ACL_GROUPS, AccessControlPolicy, AclGrant, AclGrantee, AclPermission, AuthContext, Grantee,
+    Owner, S3_XMLNS, canned_acl,
+};
+use crate::server::AppState;
+use axum::body::Body;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+
+/// Canned ACL of a PUT, copy, CreateMultipartUpload, CreateBucket or
+/// PutBucketAcl/PutObjectAcl, as an alternative to `x-amz-grant-*`.
+pub const ACL_HEADER: &str = "x-amz-acl";
+
+/// `GET /{bucket}?acl`: GetBucketAcl.
+pub async fn get_bucket_acl(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:GetBucketAcl", &bucket).await?;
+    let acl = state.buckets.get_bucket_acl(&bucket).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/xml")],
+        document(&state, &acl)?,
+    )
+        .into_response())
+}
+
+/// `PUT /{bucket}?acl`: PutBucketAcl, replacing the ACL with the
+/// `AccessControlPolicy` body, a canned ACL or grant headers. The change is
+/// audited like other bucket configurations.
+pub async fn put_bucket_acl(
+    state: AppState,
+    auth: AuthContext,
+    origin: RequestOrigin,
+    bucket: String,
+    headers: HeaderMap,
+    body: Body,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:PutBucketAcl", &bucket).await?;
+    let body = read_body(body, MAX_DOCUMENT_SIZE).await?;
+    let acl = requested_acl(&state, &headers, &body)?;
+    let before = document(&state, &state.buckets.get_bucket_acl(&bucket).await?)?;
+    let after = document(&state, &acl)?;
+    state.buckets.put_bucket_acl(&bucket, acl).await?;
+    configuration_changed(&state, &auth, &origin, &bucket, "acl", before, after).await;
+    Ok(StatusCode::OK.into_response())
+}
+
+/// `GET /{bucket}/{key}?acl`: GetObjectAcl.
+pub async fn get_object_acl(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    key: String,
+    query: &HashMap<String, String>,
+) -> S3Result<Response> {
+    let version_id = query.get("versionId").map(String::as_str);
+    let action = match version_id {
+        Some(_) => "s3:GetObjectVersionAcl",
+        None => "s3:GetObjectAcl",
+    };
+    authorize(&state, &auth, action, &format!("{}/{}", bucket, key)).await?;
+    let acl = state
+        .objects
+        .get_object_acl(&bucket, &key, version_id)
+        .await?;
+    Ok((
+        StatusCode::OK,
+        version_headers(version_id)?,
+        [(header::CONTENT_TYPE, "application/xml")],
+        document(&state, &acl)?,
+    )
+        .into_response())
+}
+
+/// `PUT /{bucket}/{key}?acl`: PutObjectAcl, like PutBucketAcl.
+pub async fn put_object_acl(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    key: String,
+    query: &HashMap<String, String>,
+    headers: &HeaderMap,
+    body: Body,
+) -> S3Result<Response> {
+    let version_id = query.get("versionId").map(String::as_str);
+    let action = match version_id {
+        Some(_) => "s3:PutObjectVersionAcl",
+        None => "s3:PutObjectAcl",
+    };
+    authorize(&state, &auth, action, &format!("{}/{}", bucket, key)).await?;
+    let body = read_body(body, MAX_DOCUMENT_SIZE).await?;
+    let acl = requested_acl(&state, headers, &body)?;
+    state
+        .objects
+        .put_object_acl(&bucket, &key, version_id, acl)
+        .await?;
+    Ok((StatusCode::OK, version_headers(version_id)?).into_response())
+}
+
+/// The ACL of `x-amz-acl` or `x-amz-grant-*` on a request creating a
+/// bucket or object; `None` when neither is set, for the default ACL.
+/// Setting an ACL this way also needs `action` (`s3:PutObjectAcl` or
+/// `s3:PutBucketAcl`) on `resource`, as in S3.
+pub async fn acl_header(
+    state: &AppState,
+    auth: &AuthContext,
+    action: &str,
+    resource: &str,
+    headers: &HeaderMap,
+) -> S3Result<Option<Vec<AclGrant>>> {
+    let acl = header_acl(&owner(state), headers)?;
+    if acl.is_some() {
+        authorize(state, auth, action, resource).await?;
+    }
+    Ok(acl)
+}
+
+/// The ACL of a PutBucketAcl or PutObjectAcl: its body, or its headers.
+fn requested_acl(state: &AppState, headers: &HeaderMap, body: &[u8]) -> S3Result<Vec<AclGrant>> {
+    let owner = owner(state);
+    let from_headers = header_acl(&owner, headers)?;
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return from_headers.ok_or_else(|| S3Error::missing_security_header(ACL_HEADER));
+    }
+    if from_headers.is_some() {
+        return Err(S3Error::invalid_request(
+            "Specifying both an AccessControlPolicy and ACL headers is not allowed",
+        ));
+    }
+    let policy: AccessControlPolicy = from_xml(body)?;
+    let mut acl = Vec::new();
+    for grant in policy.access_control_list.grants {
+        acl.push(AclGrant {
+            grantee: grantee(grant.grantee)?,
+            permission: grant.permission,
+        });
+    }
+    Ok(without_owner(&owner, acl))
+}
+
+/// The canned ACL or grant headers of a request, which are exclusive.
+fn header_acl(owner: &Owner, headers: &HeaderMap) -> S3Result<Option<Vec<AclGrant>>> {
+    let mut grants = None;
+    for permission in AclPermission::ALL {
+        let Some(value) = header_str(headers, permission.header()) else {
+            continue;
+        };
+        let grants = grants.get_or_insert_with(Vec::new);
+        for grantee in value.split(',') {
+            grants.push(AclGrant {
+                grantee: header_grantee(grantee.trim())?,
+                permission,
+            });
+        }
+    }
+    match header_str(headers, ACL_HEADER) {
+        Some(_) if grants.is_some() => Err(S3Error::invalid_request(
+            "Specifying both Canned ACLs and Header Grants is not allowed",
+        )),
+        Some(name) => canned_acl(&name).map(Some).ok_or_else(|| {
+            S3Error::invalid_argument(format!("Invalid canned ACL: {}", name))
+                .with_resource("ArgumentName", ACL_HEADER)
+        }),
+        None => Ok(grants.map(|grants| without_owner(owner, grants))),
+    }
+}
+
+/// A grantee of `x-amz-grant-*`: `id="..."`, `uri="..."` or
+/// `emailAddress="..."`.
+fn header_grantee(value: &str) -> S3Result<AclGrantee> {
+    let invalid = || {
+        S3Error::invalid_argument(format!("Invalid grantee in ACL header: {}", value))
+            .with_resource("ArgumentName", "x-amz-grant")
+    };
+    let (kind, name) = value.split_once('=').ok_or_else(invalid)?;
+    let name = name.trim().trim_matches('"').to_string();
+    grantee(match kind.trim() {
+        "id" => Grantee {
+            id: Some(name),
+            ..Default::default()
+        },
+        "uri" => Grantee {
+            uri: Some(name),
+            ..Default::default()
+        },
+        "emailAddress" => Grantee {
+            email_address: Some(name),
+            ..Default::default()
+        },
+        _ => return Err(invalid()),
+    })
+}
+
+/// A grantee of a document or header, by its ID (an access key) or group.
+fn grantee(grantee: Grantee) -> S3Result<AclGrantee> {
+    match grantee {
+        Grantee { id: Some(id), .. } if !id.is_empty() => Ok(AclGrantee::CanonicalUser { id }),
+        Grantee { uri: Some(uri), .. } => {
+            if !ACL_GROUPS.contains(&uri.as_str()) {
+                return Err(
+                    S3Error::invalid_argument(format!("Invalid group uri: {}", uri))
+                        .with_resource("ArgumentName", "uri"),
+                );
+            }
+            Ok(AclGrantee::Group { uri })
+        }
+        Grantee {
+            email_address: Some(_),
+            ..
+        } => Err(S3Error::not_implemented(
+            "Grants by email address are not supported",
+        )),
+        _ => Err(S3Error::malformed_acl_error()),
+    }
+}
+
+/// `acl` less the grants to the owner, whose full control is implied.
+fn without_owner(owner: &Owner, acl: Vec<AclGrant>) -> Vec<AclGrant> {
+    acl.into_iter()
+        .filter(
+            |grant| !matches!(&grant.grantee, AclGrantee::CanonicalUser { id } if *id == owner.id),
+        )
+        .collect()
+}
+
+fn owner(state: &AppState) -> Owner {
+    let cfg = state.config.read().unwrap();
+    Owner {
+        id: cfg.owner.id.clone(),
+        display_name: cfg.owner.display_name.clone(),
+    }
+}
+
+/// The `AccessControlPolicy` of `acl`, as returned by GET.
+fn document(state: &AppState, acl: &[AclGrant]) -> S3Result<String> {
+    to_xml(&AccessControlPolicy::new(S3_XMLNS, owner(state), acl))
+}
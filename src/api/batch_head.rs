@@ -0,0 +1,163 @@
+use super::{MAX_KEY_LIST_SIZE, S3Result, authorize, common_headers, read_body};
+use crate::error::S3Error;
+use crate::models::{AuthContext, GetObjectHeaders, GetObjectRequest, ObjectMetadata};
+use crate::server::AppState;
+use axum::body::Body;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Most keys a batch-head request may name.
+pub const MAX_BATCH_HEAD_KEYS: usize = 10_000;
+
+/// One line of the request body.
+#[derive(Deserialize)]
+struct HeadKey {
+    key: String,
+}
+
+/// One line of the response: the key and its metadata or the error a HEAD
+/// of it would have failed with.
+#[derive(Serialize)]
+struct HeadLine {
+    key: String,
+    #[serde(flatten)]
+    result: HeadResult,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum HeadResult {
+    Object(ObjectStat),
+    Error(HeadError),
+}
+
+/// What a HEAD returns, with user metadata as stored (not encoded).
+#[derive(Serialize)]
+struct ObjectStat {
+    etag: String,
+    size: u64,
+    last_modified: String,
+    content_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_disposition: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_encoding: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_control: Option<String>,
+    storage_class: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server_side_encryption: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<String, String>,
+}
+
+impl ObjectStat {
+    fn new(metadata: ObjectMetadata) -> Self {
+        let attributes = metadata.attributes;
+        Self {
+            storage_class: attributes.storage_class().to_string(),
+            etag: metadata.etag,
+            size: metadata.size,
+            last_modified: metadata.last_modified,
+            content_type: attributes
+                .content_type
+                .unwrap_or_else(|| "binary/octet-stream".to_string()),
+            content_disposition: attributes.content_disposition,
+            content_encoding: attributes.content_encoding,
+            content_language: attributes.content_language,
+            cache_control: attributes.cache_control,
+            server_side_encryption: attributes.server_side_encryption,
+            metadata: attributes.user_metadata,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HeadError {
+    code: String,
+    message: String,
+}
+
+/// `POST /{bucket}?batch-head` (extension): the metadata of many keys in
+/// one request, for clients that would otherwise send a HEAD per key. The
+/// body names one key per line as NDJSON (`{"key": "..."}`); the response
+/// has a line per key, in request order. Every key is authorized and looked
+/// up like a HEAD of its own, and keys that fail are reported in their line
+/// instead of failing the request.
+pub async fn batch_head(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    headers: HeaderMap,
+    body: Body,
+) -> S3Result<Response> {
+    let keys = parse_keys(&read_body(body, MAX_KEY_LIST_SIZE).await?)?;
+    state.buckets.head_bucket(&bucket).await?;
+    let mut out = Vec::new();
+    for key in keys {
+        let resource = format!("{}/{}", bucket, key);
+        let result = match authorize(&state, &auth, "s3:GetObject", &resource).await {
+            Ok(()) => state
+                .objects
+                .head_object(GetObjectRequest {
+                    bucket: bucket.clone(),
+                    key: key.clone(),
+                    version_id: None,
+                    headers: GetObjectHeaders {
+                        common: common_headers(&headers),
+                        range: None,
+                        if_modified_since: None,
+                        if_unmodified_since: None,
+                        if_match: None,
+                        if_none_match: None,
+                    },
+                })
+                .await
+                .map_err(S3Error::from),
+            Err(e) => Err(e),
+        };
+        let line = HeadLine {
+            key,
+            result: match result {
+                Ok(metadata) => HeadResult::Object(ObjectStat::new(metadata)),
+                Err(e) => HeadResult::Error(HeadError {
+                    code: e.code.to_string(),
+                    message: e.message,
+                }),
+            },
+        };
+        serde_json::to_writer(&mut out, &line).expect("batch-head lines serialize");
+        out.push(b'\n');
+    }
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        out,
+    )
+        .into_response())
+}
+
+/// The keys of the body in order; blank lines are skipped.
+fn parse_keys(body: &[u8]) -> S3Result<Vec<String>> {
+    let mut keys = Vec::new();
+    for (i, line) in body.split(|b| *b == b'\n').enumerate() {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let entry: HeadKey = serde_json::from_slice(line).map_err(|e| {
+            S3Error::invalid_request(format!("invalid batch-head line {}: {}", i + 1, e))
+        })?;
+        keys.push(entry.key);
+    }
+    if keys.is_empty() || keys.len() > MAX_BATCH_HEAD_KEYS {
+        return Err(S3Error::invalid_request(format!(
+            "a batch-head request must name between 1 and {} keys",
+            MAX_BATCH_HEAD_KEYS
+        )));
+    }
+    Ok(keys)
+}
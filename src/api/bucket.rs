@@ -0,0 +1,434 @@
+use super::acl::acl_header;
+use super::{
+    MAX_DOCUMENT_SIZE, RequestOrigin, S3Result, authorize, authorize_listing, common_headers,
+    from_xml, header_str, read_body, to_xml,
+};
+use crate::error::S3Error;
+use crate::middleware::Compressible;
+use crate::models::{
+    AuthContext, CreateBucketHeaders, CreateBucketRequest, DeleteBucketHeaders,
+    DeleteBucketRequest, ListBucketsHeaders, ListBucketsRequest, ListObjectVersionsRequest,
+    ListObjectVersionsResponse, ListObjectsHeaders, ListObjectsRequest, ListObjectsResponse,
+    ListObjectsV2Request, ListObjectsV2Response, LocationConstraintResponse,
+};
+use crate::server::AppState;
+use axum::Extension;
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use chrono::DateTime;
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Characters left as-is with `encoding-type=url`.
+pub const URL_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'/');
+
+#[derive(Debug, Default, Deserialize)]
+struct CreateBucketConfiguration {
+    #[serde(rename = "LocationConstraint")]
+    location_constraint: Option<String>,
+}
+
+/// `GET /`
+pub async fn list_buckets(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> S3Result<Response> {
+    if query.contains_key("capabilities") {
+        return super::capabilities::capabilities(state).await;
+    }
+    authorize(&state, &auth, "s3:ListAllMyBuckets", "*").await?;
+    let result = state
+        .buckets
+        .list_buckets(ListBucketsRequest {
+            headers: ListBucketsHeaders {
+                common: common_headers(&headers),
+            },
+        })
+        .await?;
+    xml_response(to_xml(&result)?)
+}
+
+/// `PUT /{bucket}`, PutBucketNotificationConfiguration with
+/// `notification`, PutBucketTagging with `tagging`, PutBucketVersioning
+/// with `versioning`, PutBucketLifecycleConfiguration with `lifecycle`,
+/// PutBucketPolicy with `policy`, PutBucketAcl with `acl`, or PutBucketCors
+/// with `cors`.
+pub async fn create_bucket(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    origin: RequestOrigin,
+    Path(bucket): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Body,
+) -> S3Result<Response> {
+    if query.contains_key("notification") {
+        return super::notification::put_bucket_notification(state, auth, origin, bucket, body)
+            .await;
+    }
+    if query.contains_key("tagging") {
+        return super::tagging::put_bucket_tagging(state, auth, origin, bucket, body).await;
+    }
+    if query.contains_key("versioning") {
+        return super::versioning::put_bucket_versioning(state, auth, origin, bucket, body).await;
+    }
+    if query.contains_key("lifecycle") {
+        return super::lifecycle::put_bucket_lifecycle(state, auth, origin, bucket, body).await;
+    }
+    if query.contains_key("policy") {
+        return super::policy::put_bucket_policy(state, auth, origin, bucket, body).await;
+    }
+    if query.contains_key("acl") {
+        return super::acl::put_bucket_acl(state, auth, origin, bucket, headers, body).await;
+    }
+    if query.contains_key("cors") {
+        return super::cors::put_bucket_cors(state, auth, origin, bucket, body).await;
+    }
+    authorize(&state, &auth, "s3:CreateBucket", &bucket).await?;
+    let acl = acl_header(&state, &auth, "s3:PutBucketAcl", &bucket, &headers).await?;
+    let body = read_body(body, MAX_DOCUMENT_SIZE).await?;
+    let configuration = if body.iter().all(u8::is_ascii_whitespace) {
+        CreateBucketConfiguration::default()
+    } else {
+        from_xml(&body)?
+    };
+    let metadata = state
+        .buckets
+        .create_bucket(CreateBucketRequest {
+            bucket,
+            location_constraint: configuration.location_constraint,
+            headers: CreateBucketHeaders {
+                common: common_headers(&headers),
+                acl,
+                object_lock_enabled: header_str(&headers, "x-amz-bucket-object-lock-enabled")
+                    .map(|v| v.eq_ignore_ascii_case("true")),
+                object_ownership: header_str(&headers, "x-amz-object-ownership"),
+            },
+        })
+        .await?;
+    Ok((
+        StatusCode::OK,
+        [(header::LOCATION, format!("/{}", metadata.name))],
+    )
+        .into_response())
+}
+
+/// `POST /{bucket}`: DeleteObjects with `delete`, the inventory diff
+/// extension against a manifest with `inventory-diff`, or PostObject, a
+/// browser form upload, with a `multipart/form-data` body.
+pub async fn post_bucket(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path(bucket): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Body,
+) -> S3Result<Response> {
+    if query.contains_key("inventory-diff") {
+        return super::inventory::diff_manifest(state, auth, bucket, query, body).await;
+    }
+    if query.contains_key("delete") {
+        return super::object::delete_objects(state, auth, bucket, headers, body).await;
+    }
+    if query.contains_key("batch-head") {
+        return super::batch_head::batch_head(state, auth, bucket, headers, body).await;
+    }
+    if super::form_upload::is_form_upload(&headers) {
+        return super::form_upload::post_object(state, bucket, headers, body).await;
+    }
+    Err(S3Error::method_not_allowed("POST"))
+}
+
+/// `DELETE /{bucket}`, DeleteBucketTagging with `tagging`,
+/// DeleteBucketLifecycle with `lifecycle`, DeleteBucketPolicy with
+/// `policy`, or DeleteBucketCors with `cors`.
+pub async fn delete_bucket(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    origin: RequestOrigin,
+    Path(bucket): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> S3Result<Response> {
+    if query.contains_key("tagging") {
+        return super::tagging::delete_bucket_tagging(state, auth, origin, bucket).await;
+    }
+    if query.contains_key("lifecycle") {
+        return super::lifecycle::delete_bucket_lifecycle(state, auth, origin, bucket).await;
+    }
+    if query.contains_key("policy") {
+        return super::policy::delete_bucket_policy(state, auth, origin, bucket).await;
+    }
+    if query.contains_key("cors") {
+        return super::cors::delete_bucket_cors(state, auth, origin, bucket).await;
+    }
+    authorize(&state, &auth, "s3:DeleteBucket", &bucket).await?;
+    let deleted_by = match &auth {
+        AuthContext::IAMAccount(credentials) => credentials.access_key.clone(),
+        AuthContext::Anonymous => "anonymous".to_string(),
+    };
+    state
+        .buckets
+        .delete_bucket(DeleteBucketRequest {
+            bucket,
+            headers: DeleteBucketHeaders {
+                common: common_headers(&headers),
+            },
+            deleted_by,
+        })
+        .await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// `GET /{bucket}?location`: GetBucketLocation. Never redirected by
+/// `region.redirect`, as SDKs call it to find the region to sign for.
+async fn get_bucket_location(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:GetBucketLocation", &bucket).await?;
+    let metadata = state.buckets.head_bucket(&bucket).await?;
+    let region = metadata
+        .region
+        .unwrap_or_else(|| state.config.read().unwrap().region.default.clone());
+    xml_response(to_xml(&LocationConstraintResponse::new(&region))?)
+}
+
+/// `HEAD /{bucket}`
+pub async fn head_bucket(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path(bucket): Path<String>,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:ListBucket", &bucket).await?;
+    let metadata = state.buckets.head_bucket(&bucket).await?;
+    let region = metadata
+        .region
+        .unwrap_or_else(|| state.config.read().unwrap().region.default.clone());
+    Ok((StatusCode::OK, [("x-amz-bucket-region", region)]).into_response())
+}
+
+/// `GET /{bucket}`: ListObjects, ListObjectsV2 with `list-type=2`,
+/// ListObjectVersions with `versions`, GetBucketNotificationConfiguration
+/// with `notification`, GetBucketTagging with `tagging`, GetBucketVersioning
+/// with `versioning`, GetBucketLifecycleConfiguration with `lifecycle`,
+/// GetBucketPolicy with `policy`, GetBucketAcl with `acl`, GetBucketCors
+/// with `cors`, GetBucketLocation with `location`, or the inventory diff,
+/// checksums and search extensions with `inventory-diff`, `checksums` and
+/// `search`.
+pub async fn get_bucket(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path(bucket): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> S3Result<Response> {
+    if query.contains_key("notification") {
+        return super::notification::get_bucket_notification(state, auth, bucket).await;
+    }
+    if query.contains_key("tagging") {
+        return super::tagging::get_bucket_tagging(state, auth, bucket).await;
+    }
+    if query.contains_key("versioning") {
+        return super::versioning::get_bucket_versioning(state, auth, bucket).await;
+    }
+    if query.contains_key("lifecycle") {
+        return super::lifecycle::get_bucket_lifecycle(state, auth, bucket).await;
+    }
+    if query.contains_key("policy") {
+        return super::policy::get_bucket_policy(state, auth, bucket).await;
+    }
+    if query.contains_key("acl") {
+        return super::acl::get_bucket_acl(state, auth, bucket).await;
+    }
+    if query.contains_key("cors") {
+        return super::cors::get_bucket_cors(state, auth, bucket).await;
+    }
+    if query.contains_key("location") {
+        return get_bucket_location(state, auth, bucket).await;
+    }
+    if query.contains_key("uploads") {
+        return Err(S3Error::not_implemented(
+            "ListMultipartUploads is not implemented",
+        ));
+    }
+    if query.contains_key("inventory-diff") {
+        return super::inventory::diff_buckets(state, auth, bucket, query).await;
+    }
+    if query.contains_key("checksums") {
+        return super::inventory::checksums(state, auth, bucket, query).await;
+    }
+    if query.contains_key("search") {
+        return super::search::search_objects(state, auth, bucket, query).await;
+    }
+    let action = if query.contains_key("versions") {
+        "s3:ListBucketVersions"
+    } else {
+        "s3:ListBucket"
+    };
+    let prefix = query.get("prefix").map(String::as_str).unwrap_or_default();
+    authorize_listing(&state, &auth, action, &bucket, prefix).await?;
+    let url_encode = match query.get("encoding-type").map(String::as_str) {
+        None => false,
+        Some("url") => true,
+        Some(_) => {
+            return Err(
+                S3Error::invalid_argument("Invalid Encoding Method specified in Request")
+                    .with_resource("ArgumentName", "encoding-type"),
+            );
+        }
+    };
+    let max_keys = match query.get("max-keys") {
+        Some(v) => Some(v.parse::<u32>().map_err(|_| {
+            S3Error::invalid_argument("Provided max-keys not an integer or within integer range")
+                .with_resource("ArgumentName", "max-keys")
+        })?),
+        None => None,
+    };
+    let param = |name: &str| query.get(name).cloned();
+    let list_headers = ListObjectsHeaders {
+        common: common_headers(&headers),
+        optional_object_attributes: header_str(&headers, "x-amz-optional-object-attributes"),
+    };
+
+    if query.contains_key("versions") {
+        let as_of = match query.get("as-of") {
+            Some(v) => Some(
+                DateTime::parse_from_rfc3339(v)
+                    .map_err(|_| {
+                        S3Error::invalid_argument("as-of must be an RFC 3339 timestamp")
+                            .with_resource("ArgumentName", "as-of")
+                    })?
+                    .to_utc(),
+            ),
+            None => None,
+        };
+        let mut result = state
+            .buckets
+            .list_object_versions(ListObjectVersionsRequest {
+                bucket,
+                prefix: param("prefix"),
+                delimiter: param("delimiter"),
+                key_marker: param("key-marker"),
+                version_id_marker: param("version-id-marker"),
+                max_keys,
+                as_of,
+                headers: list_headers,
+            })
+            .await?;
+        if url_encode {
+            encode_versions(&mut result);
+        }
+        xml_response(to_xml(&result)?)
+    } else if query.get("list-type").map(String::as_str) == Some("2") {
+        let mut result = state
+            .buckets
+            .list_objects_v2(ListObjectsV2Request {
+                bucket,
+                prefix: param("prefix"),
+                delimiter: param("delimiter"),
+                start_after: param("start-after"),
+                continuation_token: param("continuation-token"),
+                max_keys,
+                fetch_owner: param("fetch-owner").as_deref() == Some("true"),
+                headers: list_headers,
+            })
+            .await?;
+        if url_encode {
+            encode_v2(&mut result);
+        }
+        xml_response(to_xml(&result)?)
+    } else {
+        let mut result = state
+            .buckets
+            .list_objects(ListObjectsRequest {
+                bucket,
+                prefix: param("prefix"),
+                delimiter: param("delimiter"),
+                marker: param("marker"),
+                max_keys,
+                headers: list_headers,
+            })
+            .await?;
+        if url_encode {
+            encode_v1(&mut result);
+        }
+        xml_response(to_xml(&result)?)
+    }
+}
+
+/// Listings and other bucket documents; these may be gzipped.
+fn xml_response(body: String) -> S3Result<Response> {
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/xml")],
+        Extension(Compressible),
+        body,
+    )
+        .into_response())
+}
+
+fn url_encode(value: &mut String) {
+    *value = utf8_percent_encode(value, URL_ENCODE_SET).to_string();
+}
+
+/// Applies `encoding-type=url` to every key-derived field of a V1 listing.
+fn encode_v1(result: &mut ListObjectsResponse) {
+    result.encoding_type = Some("url".to_string());
+    url_encode(&mut result.prefix);
+    url_encode(&mut result.marker);
+    result.next_marker.iter_mut().for_each(url_encode);
+    result.delimiter.iter_mut().for_each(url_encode);
+    result
+        .contents
+        .iter_mut()
+        .for_each(|c| url_encode(&mut c.key));
+    result
+        .common_prefixes
+        .iter_mut()
+        .for_each(|p| url_encode(&mut p.prefix));
+}
+
+/// Applies `encoding-type=url` to every key-derived field of a V2 listing.
+fn encode_v2(result: &mut ListObjectsV2Response) {
+    result.encoding_type = Some("url".to_string());
+    url_encode(&mut result.prefix);
+    result.start_after.iter_mut().for_each(url_encode);
+    result.delimiter.iter_mut().for_each(url_encode);
+    result
+        .contents
+        .iter_mut()
+        .for_each(|c| url_encode(&mut c.key));
+    result
+        .common_prefixes
+        .iter_mut()
+        .for_each(|p| url_encode(&mut p.prefix));
+}
+
+/// Applies `encoding-type=url` to every key-derived field of a version listing.
+fn encode_versions(result: &mut ListObjectVersionsResponse) {
+    result.encoding_type = Some("url".to_string());
+    url_encode(&mut result.prefix);
+    url_encode(&mut result.key_marker);
+    result.next_key_marker.iter_mut().for_each(url_encode);
+    result.delimiter.iter_mut().for_each(url_encode);
+    result
+        .versions
+        .iter_mut()
+        .for_each(|v| url_encode(v.key_mut()));
+    result
+        .common_prefixes
+        .iter_mut()
+        .for_each(|p| url_encode(&mut p.prefix));
+}
@@ -0,0 +1,180 @@
+use super::batch_head::MAX_BATCH_HEAD_KEYS;
+use super::{MAX_PUT_SIZE, S3Result};
+use crate::checksum::ChecksumAlgorithm;
+use crate::error::S3Error;
+use crate::middleware::Feature;
+use crate::server::{AppState, MAX_REQUEST_HEAD_SIZE, MAX_REQUEST_HEADERS};
+use crate::services::bucket::MAX_KEYS;
+use crate::services::multipart::{MIN_PART_SIZE, PART_NUMBERS};
+use crate::sigv4::{self, MAX_CLOCK_SKEW_SECS, MAX_EXPIRES_SECS};
+use crate::storage::{MAX_KEY_LENGTH, MAX_KEY_SEGMENT_LENGTH};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+
+/// Bumped when fields are removed or change meaning; new fields may be
+/// added without.
+const CAPABILITIES_VERSION: u32 = 1;
+
+/// What this server supports, for client tooling to adapt to.
+#[derive(Debug, Serialize)]
+struct Capabilities {
+    capabilities_version: u32,
+    /// The S3 API version implemented.
+    api_version: &'static str,
+    server: ServerInfo,
+    region: String,
+    /// How buckets are addressed: path-style only, as the router does not
+    /// look at `Host`.
+    addressing_styles: Vec<&'static str>,
+    features: Features,
+    limits: Limits,
+    checksums: Checksums,
+    signature_versions: Vec<&'static str>,
+    payload_signing: Vec<&'static str>,
+    storage_classes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ServerInfo {
+    name: &'static str,
+    version: &'static str,
+}
+
+/// Operation groups and extensions usable with the current config.
+#[derive(Debug, Serialize)]
+struct Features {
+    versioning: bool,
+    website: bool,
+    select: bool,
+    multipart_upload: bool,
+    presigned_urls: bool,
+    signed_cookies: bool,
+    aws_chunked: bool,
+    sts_assume_role: bool,
+    notifications: bool,
+    append: bool,
+    chunk_manifest: bool,
+    inventory_diff: bool,
+    batch_head: bool,
+    checksums_listing: bool,
+    search: bool,
+    /// Version lineage only names writers while access logs are recorded.
+    version_lineage: bool,
+    anonymous_access: bool,
+    response_compression: bool,
+    admin_api: bool,
+    region_redirect: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Limits {
+    max_object_size: usize,
+    max_part_size: usize,
+    min_part_size: u64,
+    max_parts: u32,
+    max_key_length: usize,
+    max_key_segment_length: usize,
+    max_user_metadata_size: usize,
+    max_keys: u32,
+    max_batch_head_keys: usize,
+    max_presign_expires_secs: u64,
+    max_clock_skew_secs: i64,
+    max_request_headers: usize,
+    max_request_head_size: usize,
+    multipart_expiry_secs: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct Checksums {
+    /// How ETags are computed by default; those of multipart uploads are of
+    /// the MD5s of their parts.
+    etag: &'static str,
+    /// The algorithms a bucket's ETags can be switched to (admin API).
+    etag_algorithms: Vec<&'static str>,
+    /// `x-amz-checksum-*` algorithms accepted on upload.
+    algorithms: Vec<&'static str>,
+    /// The checksum of the chunk manifest extension.
+    chunk_manifest: &'static str,
+}
+
+/// `GET /?capabilities`: the features, limits and checksum algorithms of
+/// this server as JSON. Needs no permission beyond reaching the API.
+pub async fn capabilities(state: AppState) -> S3Result<Response> {
+    let capabilities = {
+        let config = state.config.read().unwrap();
+        let features = &config.features;
+        let available = |feature: Feature| feature.enabled(features) && feature.implemented();
+        Capabilities {
+            capabilities_version: CAPABILITIES_VERSION,
+            api_version: "2006-03-01",
+            server: ServerInfo {
+                name: env!("CARGO_PKG_NAME"),
+                version: env!("CARGO_PKG_VERSION"),
+            },
+            region: config.region.default.clone(),
+            addressing_styles: vec!["path"],
+            features: Features {
+                versioning: available(Feature::Versioning),
+                website: available(Feature::Website),
+                select: available(Feature::Select),
+                multipart_upload: true,
+                presigned_urls: true,
+                signed_cookies: true,
+                aws_chunked: true,
+                sts_assume_role: true,
+                notifications: !config.notifications.targets.is_empty(),
+                append: true,
+                chunk_manifest: true,
+                inventory_diff: true,
+                batch_head: true,
+                checksums_listing: true,
+                search: available(Feature::Search),
+                version_lineage: config.system_logs.enabled,
+                anonymous_access: features.anonymous_access,
+                response_compression: config.server.compress_responses,
+                admin_api: features.admin_api && config.admin.as_ref().is_some_and(|a| a.enabled),
+                region_redirect: config.region.redirect,
+            },
+            limits: Limits {
+                max_object_size: MAX_PUT_SIZE,
+                max_part_size: MAX_PUT_SIZE,
+                min_part_size: MIN_PART_SIZE,
+                max_parts: *PART_NUMBERS.end(),
+                max_key_length: MAX_KEY_LENGTH,
+                max_key_segment_length: MAX_KEY_SEGMENT_LENGTH,
+                max_user_metadata_size: super::object::MAX_USER_METADATA_SIZE,
+                max_keys: MAX_KEYS,
+                max_batch_head_keys: MAX_BATCH_HEAD_KEYS,
+                max_presign_expires_secs: MAX_EXPIRES_SECS,
+                max_clock_skew_secs: MAX_CLOCK_SKEW_SECS,
+                max_request_headers: MAX_REQUEST_HEADERS,
+                max_request_head_size: MAX_REQUEST_HEAD_SIZE,
+                multipart_expiry_secs: config.multipart.expiry_seconds,
+            },
+            checksums: Checksums {
+                etag: "MD5",
+                etag_algorithms: vec!["MD5", "SHA256"],
+                algorithms: ChecksumAlgorithm::ALL.map(ChecksumAlgorithm::name).to_vec(),
+                chunk_manifest: "SHA256",
+            },
+            signature_versions: vec![sigv4::ALGORITHM],
+            payload_signing: vec![
+                "SHA256",
+                sigv4::UNSIGNED_PAYLOAD,
+                sigv4::STREAMING_PAYLOAD,
+                sigv4::STREAMING_PAYLOAD_TRAILER,
+                sigv4::STREAMING_UNSIGNED_PAYLOAD_TRAILER,
+            ],
+            storage_classes: config.storage_classes.allowed.clone(),
+        }
+    };
+    let body = serde_json::to_vec_pretty(&capabilities)
+        .map_err(|e| S3Error::internal(format!("failed to encode capabilities: {}", e)))?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        body,
+    )
+        .into_response())
+}
@@ -0,0 +1,241 @@
+use super::{
+    MAX_DOCUMENT_SIZE, RequestOrigin, S3Result, authorize, configuration_changed, from_xml,
+    read_body, to_xml,
+};
+use crate::error::S3Error;
+use crate::models::{AuthContext, CORS_METHODS, CorsConfiguration, CorsRule, S3_XMLNS};
+use crate::server::AppState;
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+
+/// S3's limit on the rules of a CORS configuration.
+pub const MAX_CORS_RULES: usize = 100;
+
+/// `GET /{bucket}?cors`: GetBucketCors, `404 NoSuchCORSConfiguration` when
+/// the bucket has none (`default_cors` is not returned).
+pub async fn get_bucket_cors(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:GetBucketCORS", &bucket).await?;
+    let Some(cors) = state.buckets.get_bucket_cors(&bucket).await? else {
+        return Err(S3Error::no_such_cors_configuration(&bucket));
+    };
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/xml")],
+        document(Some(cors))?,
+    )
+        .into_response())
+}
+
+/// `PUT /{bucket}?cors`: PutBucketCors, replacing the rules. The change is
+/// audited like other bucket configurations.
+pub async fn put_bucket_cors(
+    state: AppState,
+    auth: AuthContext,
+    origin: RequestOrigin,
+    bucket: String,
+    body: Body,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:PutBucketCORS", &bucket).await?;
+    let body = read_body(body, MAX_DOCUMENT_SIZE).await?;
+    let mut cors: CorsConfiguration = from_xml(&body)?;
+    validate_cors(&cors)?;
+    cors.xmlns = None;
+    replace_cors(&state, &auth, &origin, &bucket, Some(cors)).await?;
+    Ok(StatusCode::OK.into_response())
+}
+
+/// `DELETE /{bucket}?cors`: DeleteBucketCors, which needs
+/// `s3:PutBucketCORS` as in S3. The bucket falls back to `default_cors`.
+pub async fn delete_bucket_cors(
+    state: AppState,
+    auth: AuthContext,
+    origin: RequestOrigin,
+    bucket: String,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:PutBucketCORS", &bucket).await?;
+    replace_cors(&state, &auth, &origin, &bucket, None).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// `OPTIONS /{bucket}`: a CORS preflight for the bucket.
+pub async fn preflight_bucket(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+    headers: HeaderMap,
+) -> S3Result<Response> {
+    preflight(&state, &bucket, &headers).await
+}
+
+/// `OPTIONS /{bucket}/{key}`: a CORS preflight for an object.
+pub async fn preflight_object(
+    State(state): State<AppState>,
+    Path((bucket, _key)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> S3Result<Response> {
+    preflight(&state, &bucket, &headers).await
+}
+
+/// Answers a preflight with the headers of the first rule allowing its
+/// `Origin`, `Access-Control-Request-Method` and
+/// `Access-Control-Request-Headers`. Preflights are not signed, so they
+/// need no permission.
+async fn preflight(state: &AppState, bucket: &str, headers: &HeaderMap) -> S3Result<Response> {
+    let Some(origin) = headers.get(header::ORIGIN).and_then(|v| v.to_str().ok()) else {
+        return Err(S3Error::bad_request(
+            "Insufficient information. Origin request header needed.",
+        ));
+    };
+    let method = headers
+        .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+        .and_then(|v| v.to_str().ok())
+        .filter(|method| CORS_METHODS.contains(method))
+        .ok_or_else(|| S3Error::bad_request("Invalid Access-Control-Request-Method"))?;
+    let requested = headers
+        .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let requested: Vec<&str> = requested
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    let Some(rule) = allowing_rule(state, bucket, origin, method, &requested).await? else {
+        return Err(S3Error::cors_forbidden(
+            "CORSResponse: This CORS request is not allowed. This is usually because the \
+             evaluation of Origin, request method / Access-Control-Request-Method or \
+             Access-Control-Request-Headers are not whitelisted by the resource's CORS spec.",
+        ));
+    };
+    Ok((StatusCode::OK, cors_headers(&rule, origin, &requested)).into_response())
+}
+
+/// The first rule allowing a request, from the bucket's CORS configuration
+/// or, when it has none, `default_cors`.
+pub async fn allowing_rule(
+    state: &AppState,
+    bucket: &str,
+    origin: &str,
+    method: &str,
+    headers: &[&str],
+) -> S3Result<Option<CorsRule>> {
+    let cors = match state.buckets.get_bucket_cors(bucket).await? {
+        Some(cors) => cors,
+        None => CorsConfiguration {
+            xmlns: None,
+            rules: vec![state.config.read().unwrap().default_cors.rule()],
+        },
+    };
+    Ok(cors.rule_for(origin, method, headers).cloned())
+}
+
+/// The `Access-Control-*` headers `rule` answers a request from `origin`
+/// with; `headers` are the allowed `Access-Control-Request-Headers` of a
+/// preflight.
+pub fn cors_headers(rule: &CorsRule, origin: &str, headers: &[&str]) -> HeaderMap {
+    let mut cors = HeaderMap::new();
+    let mut set = |name, value: &str| {
+        if let Ok(value) = HeaderValue::from_str(value) {
+            cors.insert(name, value);
+        }
+    };
+    if rule.any_origin() {
+        set(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*");
+    } else {
+        set(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        set(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, "true");
+        set(
+            header::VARY,
+            "Origin, Access-Control-Request-Headers, Access-Control-Request-Method",
+        );
+    }
+    set(
+        header::ACCESS_CONTROL_ALLOW_METHODS,
+        &rule.allowed_methods.join(", "),
+    );
+    if !headers.is_empty() {
+        set(header::ACCESS_CONTROL_ALLOW_HEADERS, &headers.join(", "));
+    }
+    if !rule.expose_headers.is_empty() {
+        set(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            &rule.expose_headers.join(", "),
+        );
+    }
+    if let Some(max_age) = rule.max_age_seconds {
+        set(header::ACCESS_CONTROL_MAX_AGE, &max_age.to_string());
+    }
+    cors
+}
+
+async fn replace_cors(
+    state: &AppState,
+    auth: &AuthContext,
+    origin: &RequestOrigin,
+    bucket: &str,
+    cors: Option<CorsConfiguration>,
+) -> S3Result<()> {
+    let before = document(state.buckets.get_bucket_cors(bucket).await?)?;
+    let after = document(cors.clone())?;
+    state.buckets.put_bucket_cors(bucket, cors).await?;
+    configuration_changed(state, auth, origin, bucket, "cors", before, after).await;
+    Ok(())
+}
+
+/// The stored configuration as returned by GET; an empty document when
+/// there is none.
+fn document(cors: Option<CorsConfiguration>) -> S3Result<String> {
+    let mut cors = cors.unwrap_or_default();
+    cors.xmlns = Some(S3_XMLNS.to_string());
+    to_xml(&cors)
+}
+
+/// Checks a configuration against S3's rules.
+fn validate_cors(cors: &CorsConfiguration) -> S3Result<()> {
+    if cors.rules.is_empty() {
+        return Err(S3Error::malformed_xml(
+            "a CORSConfiguration needs at least one CORSRule",
+        ));
+    }
+    if cors.rules.len() > MAX_CORS_RULES {
+        return Err(S3Error::invalid_argument(format!(
+            "CORS configuration cannot have more than {} rules",
+            MAX_CORS_RULES
+        )));
+    }
+    for rule in &cors.rules {
+        if rule.allowed_methods.is_empty() || rule.allowed_origins.is_empty() {
+            return Err(S3Error::malformed_xml(
+                "a CORSRule needs an AllowedMethod and an AllowedOrigin",
+            ));
+        }
+        if let Some(method) = rule
+            .allowed_methods
+            .iter()
+            .find(|method| !CORS_METHODS.contains(&method.as_str()))
+        {
+            return Err(S3Error::invalid_request(format!(
+                "Found unsupported HTTP method in CORS config. Unsupported method is {}",
+                method
+            )));
+        }
+        for (element, values) in [
+            ("AllowedOrigin", &rule.allowed_origins),
+            ("AllowedHeader", &rule.allowed_headers),
+        ] {
+            if let Some(value) = values.iter().find(|value| value.matches('*').count() > 1) {
+                return Err(S3Error::invalid_request(format!(
+                    "{} \"{}\" can not have more than one wildcard.",
+                    element, value
+                )));
+            }
+        }
+    }
+    Ok(())
+}
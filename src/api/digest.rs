@@ -0,0 +1,262 @@
+//! Verification of request bodies against the digests their client sent:
+//! `Content-MD5` (base64 MD5) and an additional checksum
+//! (`x-amz-checksum-*`, see [`crate::checksum`]) of uploads, and the hex
+//! `x-amz-content-sha256` of any signed body, which SDKs sign instead of
+//! `UNSIGNED-PAYLOAD` or a `STREAMING-*` value when they hash the payload
+//! up front.
+
+use super::{S3Result, header_str};
+use crate::checksum::{Checksum, ChecksumAlgorithm, ChecksumHasher};
+use crate::crypto::Sha256;
+use crate::error::S3Error;
+use crate::sigv4::CONTENT_SHA256_HEADER;
+use axum::body::{Body, BodyDataStream};
+use axum::http::HeaderMap;
+use futures_util::StreamExt;
+use md5::{Digest, Md5};
+
+/// Prefix of the headers of additional checksums. `-algorithm`, `-mode`
+/// and `-type` are not checksums but say which one is wanted.
+pub const CHECKSUM_HEADER_PREFIX: &str = "x-amz-checksum-";
+pub const CHECKSUM_ALGORITHM_HEADER: &str = "x-amz-checksum-algorithm";
+/// `FULL_OBJECT` or `COMPOSITE`, sent with checksums.
+pub const CHECKSUM_TYPE_HEADER: &str = "x-amz-checksum-type";
+/// The checksum algorithm SDKs used, sent along with the checksum.
+pub const SDK_CHECKSUM_ALGORITHM_HEADER: &str = "x-amz-sdk-checksum-algorithm";
+
+/// The additional checksum a request was sent with: its one
+/// `x-amz-checksum-*` header, which must agree with
+/// `x-amz-sdk-checksum-algorithm` if that is sent too. `400 InvalidRequest`
+/// for more than one, an algorithm not supported or a value that is no
+/// base64 checksum of its algorithm.
+pub fn checksum(headers: &HeaderMap) -> S3Result<Option<Checksum>> {
+    let mut checksum = None;
+    for (name, value) in headers {
+        let Some(suffix) = name.as_str().strip_prefix(CHECKSUM_HEADER_PREFIX) else {
+            continue;
+        };
+        if matches!(suffix, "algorithm" | "mode" | "type") {
+            continue;
+        }
+        let algorithm = ChecksumAlgorithm::parse(suffix).ok_or_else(|| {
+            S3Error::invalid_request(format!(
+                "The {} checksum algorithm is not supported",
+                suffix.to_ascii_uppercase()
+            ))
+        })?;
+        if checksum.is_some() {
+            return Err(S3Error::invalid_request(
+                "Expecting a single x-amz-checksum- header. Multiple checksum Types are not \
+                 allowed.",
+            ));
+        }
+        let value = value
+            .to_str()
+            .ok()
+            .map(str::trim)
+            .filter(|v| crate::base64::decode(v).is_some_and(|d| d.len() == algorithm.digest_len()))
+            .ok_or_else(|| {
+                S3Error::invalid_request(format!(
+                    "Value for {} header is invalid.",
+                    algorithm.header()
+                ))
+            })?;
+        checksum = Some(Checksum {
+            algorithm,
+            value: value.to_string(),
+        });
+    }
+    if let Some(sdk_algorithm) = header_str(headers, SDK_CHECKSUM_ALGORITHM_HEADER) {
+        // Without a checksum header, the checksum is in a trailer, which
+        // the aws-chunked decoder checks.
+        let consistent = match (ChecksumAlgorithm::parse(&sdk_algorithm), &checksum) {
+            (Some(algorithm), Some(checksum)) => algorithm == checksum.algorithm,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if !consistent {
+            return Err(S3Error::invalid_request(format!(
+                "Value for {} header is invalid.",
+                SDK_CHECKSUM_ALGORITHM_HEADER
+            )));
+        }
+    }
+    Ok(checksum)
+}
+
+/// `x-amz-checksum-algorithm`, asking CreateMultipartUpload and CopyObject
+/// for checksums of an algorithm.
+pub fn checksum_algorithm(headers: &HeaderMap) -> S3Result<Option<ChecksumAlgorithm>> {
+    let Some(name) = header_str(headers, CHECKSUM_ALGORITHM_HEADER) else {
+        return Ok(None);
+    };
+    match ChecksumAlgorithm::parse(&name) {
+        Some(algorithm) => Ok(Some(algorithm)),
+        None => Err(S3Error::invalid_request(format!(
+            "Checksum algorithm provided is unsupported. Please try again with any of the valid \
+             types: [{}]",
+            ChecksumAlgorithm::ALL
+                .map(ChecksumAlgorithm::name)
+                .join(", ")
+        ))),
+    }
+}
+
+/// `body` as it streams in, hashed on the way. Once it has been read to
+/// its end, a digest or checksum that does not match fails it with
+/// `400 BadDigest`, before the upload is stored. A `Content-MD5` that is
+/// not a base64 MD5 fails right away with `400 InvalidDigest`, a malformed
+/// checksum as [`checksum`] does. Bodies without digests are passed on as
+/// they are. The `x-amz-content-sha256` is checked by
+/// [`verify_content_sha256`] for every request.
+pub fn verify(headers: &HeaderMap, body: Body) -> S3Result<Body> {
+    let md5 = match headers.get("content-md5") {
+        Some(value) => {
+            let expected = value
+                .to_str()
+                .ok()
+                .and_then(|v| crate::base64::decode(v.trim()))
+                .filter(|digest| digest.len() == 16)
+                .ok_or_else(S3Error::invalid_digest)?;
+            Some((Md5::new(), expected))
+        }
+        None => None,
+    };
+    let checksum = checksum(headers)?.map(|checksum| (checksum.algorithm.hasher(), checksum));
+    if md5.is_none() && checksum.is_none() {
+        return Ok(body);
+    }
+    Ok(verified(Verifier {
+        body: body.into_data_stream(),
+        md5,
+        sha256: None,
+        checksum,
+        done: false,
+    }))
+}
+
+/// `body` checked against a hex `x-amz-content-sha256` as it streams in:
+/// once read to its end, a body that does not hash to it fails with
+/// `400 XAmzContentSHA256Mismatch`. The signature covers the header, not
+/// the body, so every signed body is wrapped this way, request documents
+/// (tagging, policies, multi-object deletes, ...) as well as uploads.
+/// Bodies sent with another `x-amz-content-sha256`, or none, are passed
+/// on as they are.
+pub fn verify_content_sha256(headers: &HeaderMap, body: Body) -> Body {
+    let expected = headers
+        .get(CONTENT_SHA256_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| v.len() == 64)
+        .and_then(|v| hex::decode(v).ok());
+    match expected {
+        Some(expected) => verified(Verifier {
+            body: body.into_data_stream(),
+            md5: None,
+            sha256: Some((Sha256::new(), expected)),
+            checksum: None,
+            done: false,
+        }),
+        None => body,
+    }
+}
+
+/// The body `verifier` reads, failing at its end if a digest does not match.
+fn verified(verifier: Verifier) -> Body {
+    let chunks = futures_util::stream::unfold(verifier, |mut verifier| async move {
+        if verifier.done {
+            return None;
+        }
+        let chunk = match verifier.body.next().await {
+            Some(Ok(chunk)) => {
+                verifier.update(&chunk);
+                Ok(chunk)
+            }
+            Some(Err(e)) => Err(S3Error::from(e)),
+            None => {
+                verifier.done = true;
+                Err(verifier.check().err()?)
+            }
+        };
+        Some((chunk, verifier))
+    });
+    Body::from_stream(chunks)
+}
+
+struct Verifier {
+    body: BodyDataStream,
+    /// Hasher and the digest it must end up with.
+    md5: Option<(Md5, Vec<u8>)>,
+    sha256: Option<(Sha256, Vec<u8>)>,
+    checksum: Option<(ChecksumHasher, Checksum)>,
+    /// After the end of the body.
+    done: bool,
+}
+
+impl Verifier {
+    fn update(&mut self, data: &[u8]) {
+        if let Some((hasher, _)) = &mut self.md5 {
+            hasher.update(data);
+        }
+        if let Some((hasher, _)) = &mut self.sha256 {
+            hasher.update(data);
+        }
+        if let Some((hasher, _)) = &mut self.checksum {
+            hasher.update(data);
+        }
+    }
+
+    fn check(&mut self) -> S3Result<()> {
+        if let Some((hasher, expected)) = self.md5.take()
+            && hasher.finalize().as_slice() != expected
+        {
+            return Err(S3Error::bad_digest(&crate::base64::encode(&expected)));
+        }
+        if let Some((hasher, expected)) = self.sha256.take()
+            && hasher.finish().as_slice() != expected
+        {
+            return Err(S3Error::content_sha256_mismatch(&hex::encode(expected)));
+        }
+        if let Some((hasher, expected)) = self.checksum.take()
+            && crate::base64::encode(&hasher.finish()) != expected.value
+        {
+            return Err(S3Error::checksum_mismatch(expected.algorithm.name()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::read_body;
+    use axum::http::HeaderValue;
+
+    fn signed_for(document: &[u8]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let hash = crate::crypto::sha256_hex(document);
+        headers.insert(CONTENT_SHA256_HEADER, HeaderValue::from_str(&hash).unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn documents_must_hash_to_the_signed_content_sha256() {
+        let signed = b"<Tagging><TagSet/></Tagging>";
+        let headers = signed_for(signed);
+        let body = verify_content_sha256(&headers, Body::from(signed.as_slice()));
+        assert_eq!(read_body(body, 1024).await.unwrap(), signed.as_slice());
+
+        let sent = b"<Tagging><TagSet><Tag><Key>a</Key><Value>b</Value></Tag></TagSet></Tagging>";
+        let body = verify_content_sha256(&headers, Body::from(sent.as_slice()));
+        let error = read_body(body, 1024).await.unwrap_err();
+        assert_eq!(error.code, "XAmzContentSHA256Mismatch");
+
+        // Unsigned payloads are not hashed.
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_SHA256_HEADER,
+            HeaderValue::from_static("UNSIGNED-PAYLOAD"),
+        );
+        let body = verify_content_sha256(&headers, Body::from(sent.as_slice()));
+        assert_eq!(read_body(body, 1024).await.unwrap(), sent.as_slice());
+    }
+}
@@ -0,0 +1,595 @@
+use super::acl::{ACL_HEADER, acl_header};
+use super::object::{
+    SSE_KMS_KEY_ID_HEADER, content_disposition, content_encoding, encryption_headers, header_value,
+    user_metadata,
+};
+use super::tagging::{TAGGING_HEADER, tagging_header};
+use super::{
+    MAX_DOCUMENT_SIZE, S3Result, authorize, common_headers, digest, from_xml, header_str, to_xml,
+    upload_body,
+};
+use crate::base64;
+use crate::error::S3Error;
+use crate::models::{
+    AuthContext, PostCondition, PostObjectResponse, PostPolicy, PutObjectHeaders, PutObjectRequest,
+    Tagging,
+};
+use crate::server::AppState;
+use crate::services::auth::FormSignature;
+use axum::body::{Body, BodyDataStream, Bytes};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use chrono::DateTime;
+use futures_util::StreamExt;
+use percent_encoding::utf8_percent_encode;
+use std::collections::BTreeMap;
+
+/// Form fields that are taken as the object's headers, as in S3.
+/// `content-md5` is checked against the file.
+const HEADER_FIELDS: &[&str] = &[
+    "cache-control",
+    "content-md5",
+    "content-type",
+    "content-disposition",
+    "content-encoding",
+    "content-language",
+    "expires",
+    "x-amz-storage-class",
+    "x-amz-server-side-encryption",
+    SSE_KMS_KEY_ID_HEADER,
+];
+
+/// Fields the policy need not have a condition for.
+const UNCHECKED_FIELDS: &[&str] = &["policy", "x-amz-signature", "file"];
+
+/// Whether a request is a browser form upload: a `POST /{bucket}` with a
+/// `multipart/form-data` body.
+pub fn is_form_upload(headers: &HeaderMap) -> bool {
+    header_str(headers, "content-type").is_some_and(|content_type| {
+        content_type
+            .to_ascii_lowercase()
+            .starts_with("multipart/form-data")
+    })
+}
+
+/// `POST /{bucket}` with a `multipart/form-data` body: PostObject, the
+/// upload of an HTML form. The form carries the key, the object's headers
+/// and, when signed, a policy restricting what may be uploaded with it, and
+/// ends with the file. Signed forms are authenticated by their fields,
+/// whatever the request's own headers say. The fields are read first, and
+/// the policy and the caller checked before any of the file is; the file
+/// is then streamed to storage like the body of a PutObject.
+pub async fn post_object(
+    state: AppState,
+    bucket: String,
+    headers: HeaderMap,
+    body: Body,
+) -> S3Result<Response> {
+    let content_type = header_str(&headers, "content-type").unwrap_or_default();
+    let body = upload_body(&headers, body)?;
+    let (fields, file) = read_form(&content_type, body).await?;
+    let file = file.ok_or_else(S3Error::incorrect_number_of_files)?;
+    let field = |name: &str| fields.get(name).map(String::as_str);
+    let key = field("key")
+        .ok_or_else(|| {
+            S3Error::invalid_argument("Bucket POST must contain a field named 'key'.")
+                .with_resource("ArgumentName", "key")
+        })?
+        .replace("${filename}", &file.filename);
+    let auth = form_auth(&state, &fields).await?;
+    let length_range = match field("policy") {
+        Some(policy) => check_policy(&state, &bucket, &key, &fields, policy)?,
+        None => None,
+    };
+    let resource = format!("{}/{}", bucket, key);
+    authorize(&state, &auth, "s3:PutObject", &resource).await?;
+
+    let object_headers = form_headers(&fields)?;
+    let mut data = digest::verify(&object_headers, file.data)?;
+    if let Some((min, max)) = length_range {
+        data = within_range(data, min, max);
+    }
+    let put_headers = PutObjectHeaders {
+        common: common_headers(&headers),
+        // Not known before the file has been read.
+        content_length: 0,
+        content_type: header_str(&object_headers, "content-type"),
+        content_disposition: content_disposition(&object_headers),
+        content_encoding: content_encoding(&object_headers),
+        content_language: header_str(&object_headers, "content-language"),
+        cache_control: header_str(&object_headers, "cache-control"),
+        storage_class: header_str(&object_headers, "x-amz-storage-class"),
+        acl: acl_header(&state, &auth, "s3:PutObjectAcl", &resource, &object_headers).await?,
+        server_side_encryption: header_str(&object_headers, "x-amz-server-side-encryption"),
+        sse_kms_key_id: header_str(&object_headers, SSE_KMS_KEY_ID_HEADER),
+        user_metadata: user_metadata(&object_headers)?,
+        tagging: tagging_header(&state, &auth, &resource, &object_headers).await?,
+        // Form fields carry no additional checksums.
+        checksum: None,
+    };
+    let metadata = state
+        .objects
+        .put_object(PutObjectRequest {
+            bucket: bucket.clone(),
+            key: key.clone(),
+            headers: put_headers,
+            body: data,
+        })
+        .await?;
+
+    let location = format!(
+        "http://{}/{}/{}",
+        header_str(&headers, "host").unwrap_or_else(|| "localhost".to_string()),
+        bucket,
+        utf8_percent_encode(&key, super::bucket::URL_ENCODE_SET)
+    );
+    let mut response_headers = encryption_headers(&metadata.attributes)?;
+    response_headers.insert(header::ETAG, header_value(&metadata.etag)?);
+    if let Some(version_id) = &metadata.version_id {
+        response_headers.insert("x-amz-version-id", header_value(version_id)?);
+    }
+    let redirect = field("success_action_redirect")
+        .or_else(|| field("redirect"))
+        .filter(|url| url.starts_with("http://") || url.starts_with("https://"));
+    if let Some(redirect) = redirect {
+        let query = form_urlencoded::Serializer::new(String::new())
+            .append_pair("bucket", &bucket)
+            .append_pair("key", &key)
+            .append_pair("etag", &metadata.etag)
+            .finish();
+        let separator = if redirect.contains('?') { '&' } else { '?' };
+        let target = format!("{}{}{}", redirect, separator, query);
+        response_headers.insert(header::LOCATION, header_value(&target)?);
+        return Ok((StatusCode::SEE_OTHER, response_headers).into_response());
+    }
+    response_headers.insert(header::LOCATION, header_value(&location)?);
+    match field("success_action_status") {
+        Some("200") => Ok((StatusCode::OK, response_headers).into_response()),
+        Some("201") => {
+            let body = to_xml(&PostObjectResponse {
+                location,
+                bucket,
+                key,
+                etag: metadata.etag,
+            })?;
+            Ok((
+                StatusCode::CREATED,
+                response_headers,
+                [(header::CONTENT_TYPE, "application/xml")],
+                body,
+            )
+                .into_response())
+        }
+        _ => Ok((StatusCode::NO_CONTENT, response_headers).into_response()),
+    }
+}
+
+/// The file of a form upload.
+struct FormFile {
+    /// The `filename` of its `Content-Disposition`, for `${filename}`.
+    filename: String,
+    /// Its content, streamed from the request body as it is read.
+    data: Body,
+}
+
+/// The fields of a `multipart/form-data` body, by lowercase name, and its
+/// `file`. Fields after the file are ignored, as in S3. The fields are read
+/// into memory, up to [`MAX_DOCUMENT_SIZE`]; nothing of the file is read.
+async fn read_form(
+    content_type: &str,
+    body: Body,
+) -> S3Result<(BTreeMap<String, String>, Option<FormFile>)> {
+    let boundary = content_type
+        .split(';')
+        .filter_map(|param| param.trim().split_once('='))
+        .find(|(name, _)| name.eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim_matches('"'))
+        .filter(|boundary| !boundary.is_empty())
+        .ok_or_else(S3Error::malformed_post_request)?;
+    let delimiter = format!("\r\n--{}", boundary).into_bytes();
+    let mut form = FormReader {
+        chunks: body.into_data_stream(),
+        buffer: Vec::new(),
+    };
+    // The first delimiter may start the body, without the line break.
+    let mut pos = form.find(&delimiter[2..], 0).await? + delimiter.len() - 2;
+    let mut fields = BTreeMap::new();
+    loop {
+        while form.buffer.len() < pos + 2 {
+            if !form.fill().await? {
+                return Err(S3Error::malformed_post_request());
+            }
+        }
+        let rest = &form.buffer[pos..];
+        if rest.starts_with(b"--") {
+            return Ok((fields, None));
+        }
+        if !rest.starts_with(b"\r\n") {
+            return Err(S3Error::malformed_post_request());
+        }
+        let head_start = pos + 2;
+        let head_end = form.find(b"\r\n\r\n", head_start).await?;
+        let content_start = head_end + 4;
+        let head = std::str::from_utf8(&form.buffer[head_start..head_end])
+            .map_err(|_| S3Error::malformed_post_request())?;
+        let (name, filename) = part_name(head).ok_or_else(S3Error::malformed_post_request)?;
+        if name == "file" {
+            let read = form.buffer.split_off(content_start);
+            return Ok((
+                fields,
+                Some(FormFile {
+                    filename: filename.unwrap_or_default(),
+                    data: file_part(read, form.chunks, delimiter),
+                }),
+            ));
+        }
+        let content_end = form.find(&delimiter, content_start).await?;
+        let value = String::from_utf8(form.buffer[content_start..content_end].to_vec())
+            .map_err(|_| S3Error::malformed_post_request())?;
+        fields.insert(name, value);
+        pos = content_end + delimiter.len();
+    }
+}
+
+/// A form body as read so far.
+struct FormReader {
+    chunks: BodyDataStream,
+    buffer: Vec<u8>,
+}
+
+impl FormReader {
+    /// Reads the next chunk into the buffer; false at the end of the body.
+    async fn fill(&mut self) -> S3Result<bool> {
+        if self.buffer.len() > MAX_DOCUMENT_SIZE {
+            return Err(S3Error::max_message_length_exceeded(MAX_DOCUMENT_SIZE));
+        }
+        match self.chunks.next().await {
+            Some(chunk) => {
+                self.buffer.extend_from_slice(&chunk?);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// The position of `needle` from `from` on, reading as far as needed.
+    async fn find(&mut self, needle: &[u8], from: usize) -> S3Result<usize> {
+        let mut searched = from;
+        loop {
+            if let Some(index) = find(&self.buffer, needle, searched) {
+                return Ok(index);
+            }
+            // A match may still start in the last bytes read.
+            searched = searched.max((self.buffer.len() + 1).saturating_sub(needle.len()));
+            if !self.fill().await? {
+                return Err(S3Error::malformed_post_request());
+            }
+        }
+    }
+}
+
+/// The content of the file part: `read`, the start of it already read with
+/// the fields, then the rest of the body up to the `delimiter` that ends
+/// the part. A body that ends before it is a MalformedPOSTRequest.
+fn file_part(read: Vec<u8>, chunks: BodyDataStream, delimiter: Vec<u8>) -> Body {
+    let state = Some((read, chunks));
+    let data = futures_util::stream::unfold(state, move |state| {
+        let delimiter = delimiter.clone();
+        async move {
+            let (mut pending, mut chunks) = state?;
+            loop {
+                if let Some(end) = find(&pending, &delimiter, 0) {
+                    pending.truncate(end);
+                    return Some((Ok(Bytes::from(pending)), None));
+                }
+                // What could be the start of the delimiter is held back.
+                let complete = (pending.len() + 1).saturating_sub(delimiter.len());
+                if complete > 0 {
+                    let rest = pending.split_off(complete);
+                    return Some((Ok(Bytes::from(pending)), Some((rest, chunks))));
+                }
+                match chunks.next().await {
+                    Some(Ok(chunk)) => pending.extend_from_slice(&chunk),
+                    Some(Err(e)) => return Some((Err(S3Error::from(e)), None)),
+                    None => return Some((Err(S3Error::malformed_post_request()), None)),
+                }
+            }
+        }
+    });
+    Body::from_stream(data)
+}
+
+/// `file` as it streams in, failing with EntityTooLarge once it exceeds
+/// `max` bytes and, at its end, with EntityTooSmall if it has less than
+/// `min`: the `content-length-range` of a policy.
+fn within_range(file: Body, min: u64, max: u64) -> Body {
+    let mut size = 0;
+    let chunks = file
+        .into_data_stream()
+        .map(Some)
+        .chain(futures_util::stream::once(async { None }))
+        .map(move |chunk| match chunk {
+            Some(chunk) => {
+                let chunk = chunk.map_err(S3Error::from)?;
+                size += chunk.len() as u64;
+                if size > max {
+                    return Err(S3Error::entity_above_range(max));
+                }
+                Ok(chunk)
+            }
+            None if size < min => Err(S3Error::entity_below_range(min)),
+            None => Ok(Bytes::new()),
+        });
+    Body::from_stream(chunks)
+}
+
+/// The lowercase `name` and the `filename` of a part's
+/// `Content-Disposition: form-data` header.
+fn part_name(head: &str) -> Option<(String, Option<String>)> {
+    let disposition = head.split("\r\n").find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("content-disposition")
+            .then_some(value)
+    })?;
+    let mut params = disposition.split(';').map(str::trim);
+    if !params.next()?.eq_ignore_ascii_case("form-data") {
+        return None;
+    }
+    let (mut name, mut filename) = (None, None);
+    for param in params {
+        let Some((key, value)) = param.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"').to_string();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "name" => name = Some(value.to_ascii_lowercase()),
+            "filename" => filename = Some(value),
+            _ => {}
+        }
+    }
+    Some((name?, filename))
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|index| from + index)
+}
+
+/// The caller of a form upload: the access key that signed its policy, or
+/// anonymous for a form without `x-amz-signature`.
+async fn form_auth(state: &AppState, fields: &BTreeMap<String, String>) -> S3Result<AuthContext> {
+    let field = |name: &str| fields.get(name).map(String::as_str);
+    if field("awsaccesskeyid").is_some() || field("signature").is_some() {
+        return Err(S3Error::not_implemented(
+            "Form uploads signed with Signature Version 2 are not supported",
+        ));
+    }
+    let Some(signature) = field("x-amz-signature") else {
+        if !state.config.read().unwrap().features.anonymous_access {
+            return Err(S3Error::access_denied_because(
+                "Anonymous access is disabled on this server",
+            ));
+        }
+        return Ok(AuthContext::Anonymous);
+    };
+    let required = |name: &'static str| {
+        field(name).ok_or_else(|| {
+            S3Error::invalid_argument(format!(
+                "Bucket POST must contain a field named '{}'.",
+                name
+            ))
+            .with_resource("ArgumentName", name)
+        })
+    };
+    let form = FormSignature {
+        algorithm: required("x-amz-algorithm")?,
+        credential: required("x-amz-credential")?,
+        date: required("x-amz-date")?,
+        signature,
+        policy: required("policy")?,
+        security_token: field("x-amz-security-token"),
+    };
+    state
+        .auth
+        .authenticate_form(&form)
+        .await
+        .map_err(S3Error::from)
+}
+
+/// Checks the fields of a form upload against its base64 `policy`: it must
+/// not have expired, every condition must hold, and every field must be
+/// named by a condition. Returns the `content-length-range` of the file,
+/// which can only be checked as it is read.
+fn check_policy(
+    state: &AppState,
+    bucket: &str,
+    key: &str,
+    fields: &BTreeMap<String, String>,
+    policy: &str,
+) -> S3Result<Option<(u64, u64)>> {
+    let document = base64::decode(policy)
+        .ok_or_else(|| S3Error::invalid_policy_document("Invalid Base64."))?;
+    let policy: PostPolicy = serde_json::from_slice(&document)
+        .map_err(|_| S3Error::invalid_policy_document("Invalid JSON."))?;
+    let expiration = DateTime::parse_from_rfc3339(&policy.expiration)
+        .map_err(|_| S3Error::invalid_policy_document("Invalid 'expiration' value."))?;
+    if state.clock.now() > expiration {
+        return Err(S3Error::access_denied_because(
+            "Invalid according to Policy: Policy expired.",
+        ));
+    }
+    let conditions = policy
+        .parse_conditions()
+        .map_err(S3Error::invalid_policy_document)?;
+    let value = |name: &str| match name {
+        "bucket" => Some(bucket),
+        "key" => Some(key),
+        _ => fields.get(name).map(String::as_str),
+    };
+    let mut length_range = None;
+    for condition in &conditions {
+        let holds = match condition {
+            PostCondition::Eq(name, expected) => value(name) == Some(expected.as_str()),
+            PostCondition::StartsWith(name, prefix) => {
+                value(name).is_some_and(|value| value.starts_with(prefix.as_str()))
+            }
+            PostCondition::ContentLengthRange(min, max) => {
+                length_range = Some((*min, *max));
+                true
+            }
+        };
+        if !holds {
+            return Err(S3Error::access_denied_because(format!(
+                "Invalid according to Policy: Policy Condition failed: {}",
+                condition
+            )));
+        }
+    }
+    let checked = |name: &str| {
+        conditions.iter().any(|condition| match condition {
+            PostCondition::Eq(field, _) | PostCondition::StartsWith(field, _) => field == name,
+            PostCondition::ContentLengthRange(..) => false,
+        })
+    };
+    if let Some(extra) = fields.keys().find(|name| {
+        !UNCHECKED_FIELDS.contains(&name.as_str())
+            && !name.starts_with("x-ignore-")
+            && !checked(name)
+    }) {
+        return Err(S3Error::access_denied_because(format!(
+            "Invalid according to Policy: Extra input fields: {}",
+            extra
+        )));
+    }
+    Ok(length_range)
+}
+
+/// The fields of a form upload that S3 takes as request headers: those of
+/// [`HEADER_FIELDS`], `x-amz-meta-*`, `acl` as `x-amz-acl` and the
+/// `tagging` document as `x-amz-tagging`.
+fn form_headers(fields: &BTreeMap<String, String>) -> S3Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in fields {
+        let name = match name.as_str() {
+            "acl" => ACL_HEADER,
+            "tagging" => continue,
+            name if HEADER_FIELDS.contains(&name) || name.starts_with("x-amz-meta-") => name,
+            _ => continue,
+        };
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+            S3Error::invalid_argument("Invalid form field name").with_resource("ArgumentName", name)
+        })?;
+        let value = HeaderValue::from_bytes(value.as_bytes()).map_err(|_| {
+            S3Error::invalid_argument("Invalid form field value")
+                .with_resource("ArgumentName", name.as_str().to_string())
+        })?;
+        headers.insert(name, value);
+    }
+    if let Some(tagging) = fields.get("tagging").filter(|tagging| !tagging.is_empty()) {
+        let tagging: Tagging = from_xml(tagging.as_bytes())?;
+        let mut tags = form_urlencoded::Serializer::new(String::new());
+        for tag in &tagging.tag_set.tags {
+            tags.append_pair(&tag.key, &tag.value);
+        }
+        headers.insert(TAGGING_HEADER, header_value(&tags.finish())?);
+    }
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT_TYPE: &str = "multipart/form-data; boundary=xYzZY";
+
+    /// `body` as a stream of `size`-byte chunks, failing after the last.
+    fn chunked(body: &[u8], size: usize) -> Body {
+        let mut chunks: Vec<Result<Bytes, std::io::Error>> = body
+            .chunks(size)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+        chunks.push(Err(std::io::Error::other("read past the form")));
+        Body::from_stream(futures_util::stream::iter(chunks))
+    }
+
+    fn form(file: &[u8]) -> Vec<u8> {
+        [
+            b"--xYzZY\r\nContent-Disposition: form-data; name=\"key\"\r\n\r\n".as_slice(),
+            b"uploads/${filename}\r\n",
+            b"--xYzZY\r\nContent-Disposition: form-data; name=\"Policy\"\r\n\r\neyJ9\r\n",
+            b"--xYzZY\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n",
+            b"Content-Type: text/plain\r\n\r\n",
+            file,
+            b"\r\n--xYzZY\r\nContent-Disposition: form-data; name=\"acl\"\r\n\r\nprivate\r\n",
+            b"--xYzZY--\r\n",
+        ]
+        .concat()
+    }
+
+    #[tokio::test]
+    async fn form_files_are_streamed_after_the_fields() {
+        let file: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        for chunk_size in [1, 7, 4096, usize::MAX] {
+            let body = form(&file);
+            let chunk_size = chunk_size.min(body.len());
+            let (fields, form_file) = read_form(CONTENT_TYPE, chunked(&body, chunk_size))
+                .await
+                .unwrap();
+            assert_eq!(fields.get("key").unwrap(), "uploads/${filename}");
+            assert_eq!(fields.get("policy").unwrap(), "eyJ9");
+            assert!(!fields.contains_key("acl"));
+            let form_file = form_file.unwrap();
+            assert_eq!(form_file.filename, "a.txt");
+            // Read up to the end of the file part only, never to the failing end.
+            let data = axum::body::to_bytes(form_file.data, usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(data, file);
+        }
+    }
+
+    #[tokio::test]
+    async fn form_fields_are_checked_before_the_file_is_read() {
+        let body = form(b"data");
+        let file_start = find(&body, b"\r\n\r\ndata", 0).unwrap() + 4;
+        // The body fails right where the file starts, which the fields
+        // never get to.
+        let mut chunks = vec![Ok(Bytes::copy_from_slice(&body[..file_start]))];
+        chunks.push(Err(std::io::Error::other("file not sent yet")));
+        let body = Body::from_stream(futures_util::stream::iter(chunks));
+        let (fields, file) = read_form(CONTENT_TYPE, body).await.unwrap();
+        assert_eq!(fields.len(), 2);
+        assert!(
+            axum::body::to_bytes(file.unwrap().data, usize::MAX)
+                .await
+                .is_err()
+        );
+
+        let huge = vec![b'a'; MAX_DOCUMENT_SIZE + 1];
+        let body = [
+            b"--xYzZY\r\nContent-Disposition: form-data; name=\"x\"\r\n\r\n",
+            &huge[..],
+        ];
+        let e = read_form(CONTENT_TYPE, chunked(&body.concat(), 64 * 1024))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(e.code, "MaxMessageLengthExceeded");
+    }
+
+    #[tokio::test]
+    async fn content_length_ranges_are_checked_as_the_file_streams() {
+        let read = |size: usize, min, max| {
+            let file = within_range(Body::from(vec![0; size]), min, max);
+            axum::body::to_bytes(file, usize::MAX)
+        };
+        let code = |result: Result<Bytes, axum::Error>| S3Error::from(result.unwrap_err()).code;
+        assert_eq!(read(100, 10, 100).await.unwrap().len(), 100);
+        assert_eq!(code(read(101, 0, 100).await), "EntityTooLarge");
+        assert_eq!(code(read(9, 10, 100).await), "EntityTooSmall");
+    }
+}
@@ -0,0 +1,300 @@
+use super::{MAX_KEY_LIST_SIZE, S3Result, authorize_listing, read_body};
+use crate::error::S3Error;
+use crate::models::{AuthContext, InventoryEntry};
+use crate::server::AppState;
+use axum::body::Body;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::iter::Peekable;
+use std::vec;
+
+/// One line of the diff. Keys are reported in key order, then the summary.
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum DiffLine {
+    SourceOnly(InventoryEntry),
+    TargetOnly(InventoryEntry),
+    Different {
+        source: InventoryEntry,
+        target: InventoryEntry,
+    },
+    Summary(DiffSummary),
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct DiffSummary {
+    source_keys: u64,
+    target_keys: u64,
+    matching: u64,
+    source_only: u64,
+    target_only: u64,
+    different: u64,
+}
+
+/// Merge-joins two inventories sorted by key, yielding NDJSON lines for the
+/// keys that differ and a final summary.
+struct Diff {
+    source: Peekable<vec::IntoIter<InventoryEntry>>,
+    target: Peekable<vec::IntoIter<InventoryEntry>>,
+    summary: DiffSummary,
+    done: bool,
+}
+
+impl Diff {
+    fn new(source: Vec<InventoryEntry>, target: Vec<InventoryEntry>) -> Self {
+        let summary = DiffSummary {
+            source_keys: source.len() as u64,
+            target_keys: target.len() as u64,
+            ..DiffSummary::default()
+        };
+        Self {
+            source: source.into_iter().peekable(),
+            target: target.into_iter().peekable(),
+            summary,
+            done: false,
+        }
+    }
+}
+
+impl Iterator for Diff {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        loop {
+            let line = match (self.source.peek(), self.target.peek()) {
+                (None, None) if self.done => return None,
+                (None, None) => {
+                    self.done = true;
+                    DiffLine::Summary(self.summary.clone())
+                }
+                (Some(source), Some(target)) if source.key == target.key => {
+                    let (source, target) = (self.source.next()?, self.target.next()?);
+                    if entries_match(&source, &target) {
+                        self.summary.matching += 1;
+                        continue;
+                    }
+                    self.summary.different += 1;
+                    DiffLine::Different { source, target }
+                }
+                (Some(source), target) if target.is_none_or(|target| source.key < target.key) => {
+                    self.summary.source_only += 1;
+                    DiffLine::SourceOnly(self.source.next()?)
+                }
+                _ => {
+                    self.summary.target_only += 1;
+                    DiffLine::TargetOnly(self.target.next()?)
+                }
+            };
+            let json = serde_json::to_string(&line).expect("diff lines serialize");
+            return Some(format!("{}\n", json));
+        }
+    }
+}
+
+/// ETags (quoted or not) and sizes are compared where both sides have them.
+fn entries_match(source: &InventoryEntry, target: &InventoryEntry) -> bool {
+    let etags_match = match (&source.etag, &target.etag) {
+        (Some(a), Some(b)) => a.trim_matches('"') == b.trim_matches('"'),
+        _ => true,
+    };
+    let sizes_match = match (source.size, target.size) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
+    };
+    etags_match && sizes_match
+}
+
+/// `GET /{bucket}?inventory-diff&target={bucket}[&prefix=P]` (extension):
+/// compares the keys and ETags of two buckets.
+pub async fn diff_buckets(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    query: HashMap<String, String>,
+) -> S3Result<Response> {
+    let target_bucket = query.get("target").cloned().ok_or_else(|| {
+        S3Error::invalid_argument("inventory-diff requires a target bucket or a manifest body")
+            .with_resource("ArgumentName", "target")
+    })?;
+    let prefix = query.get("prefix").cloned().unwrap_or_default();
+    authorize_listing(&state, &auth, "s3:ListBucket", &bucket, &prefix).await?;
+    authorize_listing(&state, &auth, "s3:ListBucket", &target_bucket, &prefix).await?;
+    let source = state.buckets.inventory(&bucket, &prefix).await?;
+    let target = state.buckets.inventory(&target_bucket, &prefix).await?;
+    Ok(diff_response(Diff::new(source, target)))
+}
+
+/// `POST /{bucket}?inventory-diff[&prefix=P]` (extension): compares the
+/// bucket with a manifest sent as the body, one JSON object per line with
+/// `key` and optionally `etag` and `size`.
+pub async fn diff_manifest(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    query: HashMap<String, String>,
+    body: Body,
+) -> S3Result<Response> {
+    let prefix = query.get("prefix").cloned().unwrap_or_default();
+    authorize_listing(&state, &auth, "s3:ListBucket", &bucket, &prefix).await?;
+    let body = read_body(body, MAX_KEY_LIST_SIZE).await?;
+    let target = parse_manifest(&body, &prefix)?;
+    let source = state.buckets.inventory(&bucket, &prefix).await?;
+    Ok(diff_response(Diff::new(source, target)))
+}
+
+/// Parses a manifest into an inventory: entries outside `prefix` are
+/// dropped, and the last entry of a key repeated wins.
+fn parse_manifest(body: &[u8], prefix: &str) -> S3Result<Vec<InventoryEntry>> {
+    let mut entries = BTreeMap::new();
+    for (i, line) in body.split(|b| *b == b'\n').enumerate() {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let entry: InventoryEntry = serde_json::from_slice(line).map_err(|e| {
+            S3Error::invalid_request(format!("invalid manifest line {}: {}", i + 1, e))
+        })?;
+        if entry.key.starts_with(prefix) {
+            entries.insert(entry.key.clone(), entry);
+        }
+    }
+    Ok(entries.into_values().collect())
+}
+
+/// Output formats of the checksums extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ChecksumsFormat {
+    Ndjson,
+    Msgpack,
+}
+
+/// A key as listed by the checksums extension; the ETag without its quotes.
+#[derive(Serialize)]
+struct ChecksumEntry {
+    key: String,
+    etag: String,
+    size: u64,
+    last_modified: String,
+}
+
+impl ChecksumEntry {
+    fn from_inventory(entry: InventoryEntry) -> Self {
+        Self {
+            key: entry.key,
+            etag: entry.etag.unwrap_or_default().trim_matches('"').to_string(),
+            size: entry.size.unwrap_or_default(),
+            last_modified: entry.last_modified.unwrap_or_default(),
+        }
+    }
+
+    fn encode(&self, format: ChecksumsFormat) -> Vec<u8> {
+        match format {
+            ChecksumsFormat::Ndjson => {
+                let mut line = serde_json::to_vec(self).expect("checksum entries serialize");
+                line.push(b'\n');
+                line
+            }
+            ChecksumsFormat::Msgpack => {
+                // A map of the same four fields.
+                let mut out = vec![0x84];
+                msgpack_str(&mut out, "key");
+                msgpack_str(&mut out, &self.key);
+                msgpack_str(&mut out, "etag");
+                msgpack_str(&mut out, &self.etag);
+                msgpack_str(&mut out, "size");
+                msgpack_uint(&mut out, self.size);
+                msgpack_str(&mut out, "last_modified");
+                msgpack_str(&mut out, &self.last_modified);
+                out
+            }
+        }
+    }
+}
+
+fn msgpack_str(out: &mut Vec<u8>, value: &str) {
+    let len = value.len();
+    match len {
+        0..32 => out.push(0xa0 | len as u8),
+        32..256 => out.extend([0xd9, len as u8]),
+        256..65536 => {
+            out.push(0xda);
+            out.extend((len as u16).to_be_bytes());
+        }
+        _ => {
+            out.push(0xdb);
+            out.extend((len as u32).to_be_bytes());
+        }
+    }
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn msgpack_uint(out: &mut Vec<u8>, value: u64) {
+    match value {
+        0..128 => out.push(value as u8),
+        128..256 => out.extend([0xcc, value as u8]),
+        256..65536 => {
+            out.push(0xcd);
+            out.extend((value as u16).to_be_bytes());
+        }
+        65536..4294967296 => {
+            out.push(0xce);
+            out.extend((value as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(0xcf);
+            out.extend(value.to_be_bytes());
+        }
+    }
+}
+
+/// `GET /{bucket}?checksums[&prefix=P][&start-after=K][&format=msgpack]`
+/// (extension): every key with its ETag, size and modification time, in
+/// key order in one streamed response, so sync tools need a single request
+/// where paged XML listings take one per 1000 keys.
+pub async fn checksums(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    query: HashMap<String, String>,
+) -> S3Result<Response> {
+    let prefix = query.get("prefix").cloned().unwrap_or_default();
+    authorize_listing(&state, &auth, "s3:ListBucket", &bucket, &prefix).await?;
+    let format = match query.get("format").map(String::as_str) {
+        None | Some("ndjson") => ChecksumsFormat::Ndjson,
+        Some("msgpack") => ChecksumsFormat::Msgpack,
+        Some(_) => {
+            return Err(
+                S3Error::invalid_argument("format must be ndjson or msgpack")
+                    .with_resource("ArgumentName", "format"),
+            );
+        }
+    };
+    let start_after = query.get("start-after").cloned().unwrap_or_default();
+    let entries = state.buckets.inventory(&bucket, &prefix).await?;
+    let lines = entries
+        .into_iter()
+        .filter(move |entry| entry.key > start_after)
+        .map(move |entry| Ok::<_, Infallible>(ChecksumEntry::from_inventory(entry).encode(format)));
+    let content_type = match format {
+        ChecksumsFormat::Ndjson => "application/x-ndjson",
+        ChecksumsFormat::Msgpack => "application/vnd.msgpack",
+    };
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, content_type)],
+        Body::from_stream(futures_util::stream::iter(lines)),
+    )
+        .into_response())
+}
+
+fn diff_response(diff: Diff) -> Response {
+    let lines = futures_util::stream::iter(diff.map(Ok::<_, Infallible>));
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+        .into_response()
+}
@@ -0,0 +1,242 @@
+use super::{
+    MAX_DOCUMENT_SIZE, RequestOrigin, S3Result, authorize, configuration_changed, from_xml,
+    read_body, to_xml,
+};
+use crate::error::S3Error;
+use crate::models::{
+    AuthContext, LifecycleConfiguration, LifecycleFilter, LifecycleRule, S3_XMLNS,
+};
+use crate::server::AppState;
+use crate::services::lifecycle::expiration_date;
+use axum::body::Body;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use chrono::Timelike;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// S3's limits on lifecycle configurations.
+pub const MAX_LIFECYCLE_RULES: usize = 1000;
+pub const MAX_RULE_ID_LENGTH: usize = 255;
+
+/// `GET /{bucket}?lifecycle`: GetBucketLifecycleConfiguration,
+/// `404 NoSuchLifecycleConfiguration` when the bucket has none.
+pub async fn get_bucket_lifecycle(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:GetLifecycleConfiguration", &bucket).await?;
+    let Some(lifecycle) = state.buckets.get_bucket_lifecycle(&bucket).await? else {
+        return Err(S3Error::no_such_lifecycle_configuration(&bucket));
+    };
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/xml")],
+        document(Some(lifecycle))?,
+    )
+        .into_response())
+}
+
+/// `PUT /{bucket}?lifecycle`: PutBucketLifecycleConfiguration, replacing
+/// the rules. Rules without an `ID` get a generated one. The change is
+/// audited like other bucket configurations.
+pub async fn put_bucket_lifecycle(
+    state: AppState,
+    auth: AuthContext,
+    origin: RequestOrigin,
+    bucket: String,
+    body: Body,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:PutLifecycleConfiguration", &bucket).await?;
+    let body = read_body(body, MAX_DOCUMENT_SIZE).await?;
+    let mut lifecycle: LifecycleConfiguration = from_xml(&body)?;
+    validate_lifecycle(&lifecycle)?;
+    for rule in &mut lifecycle.rules {
+        rule.id.get_or_insert_with(|| Uuid::new_v4().to_string());
+    }
+    lifecycle.xmlns = None;
+    replace_lifecycle(&state, &auth, &origin, &bucket, Some(lifecycle)).await?;
+    Ok(StatusCode::OK.into_response())
+}
+
+/// `DELETE /{bucket}?lifecycle`: DeleteBucketLifecycle, which needs
+/// `s3:PutLifecycleConfiguration` as in S3.
+pub async fn delete_bucket_lifecycle(
+    state: AppState,
+    auth: AuthContext,
+    origin: RequestOrigin,
+    bucket: String,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:PutLifecycleConfiguration", &bucket).await?;
+    replace_lifecycle(&state, &auth, &origin, &bucket, None).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn replace_lifecycle(
+    state: &AppState,
+    auth: &AuthContext,
+    origin: &RequestOrigin,
+    bucket: &str,
+    lifecycle: Option<LifecycleConfiguration>,
+) -> S3Result<()> {
+    let before = document(state.buckets.get_bucket_lifecycle(bucket).await?)?;
+    let after = document(lifecycle.clone())?;
+    state
+        .buckets
+        .put_bucket_lifecycle(bucket, lifecycle)
+        .await?;
+    configuration_changed(state, auth, origin, bucket, "lifecycle", before, after).await;
+    Ok(())
+}
+
+/// The stored configuration as returned by GET; an empty document when
+/// there is none.
+fn document(lifecycle: Option<LifecycleConfiguration>) -> S3Result<String> {
+    let mut lifecycle = lifecycle.unwrap_or_default();
+    lifecycle.xmlns = Some(S3_XMLNS.to_string());
+    to_xml(&lifecycle)
+}
+
+/// Checks a configuration against S3's rules. Transitions and noncurrent
+/// version expiry are refused, as storage classes and the age of
+/// noncurrent versions are not tracked.
+fn validate_lifecycle(lifecycle: &LifecycleConfiguration) -> S3Result<()> {
+    if lifecycle.rules.is_empty() {
+        return Err(S3Error::malformed_xml(
+            "a LifecycleConfiguration needs at least one Rule",
+        ));
+    }
+    if lifecycle.rules.len() > MAX_LIFECYCLE_RULES {
+        return Err(S3Error::invalid_argument(format!(
+            "Lifecycle configuration cannot have more than {} rules",
+            MAX_LIFECYCLE_RULES
+        )));
+    }
+    let mut ids = HashSet::new();
+    for rule in &lifecycle.rules {
+        if let Some(id) = &rule.id {
+            if id.chars().count() > MAX_RULE_ID_LENGTH {
+                return Err(S3Error::invalid_argument(format!(
+                    "ID length should not exceed allowed limit of {}",
+                    MAX_RULE_ID_LENGTH
+                )));
+            }
+            if !ids.insert(id) {
+                return Err(S3Error::invalid_argument(
+                    "Rule ID must be unique. Found same ID for more than one rule",
+                ));
+            }
+        }
+        validate_rule(rule)?;
+    }
+    Ok(())
+}
+
+fn validate_rule(rule: &LifecycleRule) -> S3Result<()> {
+    match (&rule.filter, &rule.prefix) {
+        (Some(_), Some(_)) => {
+            return Err(S3Error::malformed_xml("a Rule has both Filter and Prefix"));
+        }
+        (None, None) => return Err(S3Error::malformed_xml("a Rule needs a Filter or a Prefix")),
+        (Some(filter), None) => validate_filter(filter)?,
+        (None, Some(_)) => {}
+    }
+    if !rule.transitions.is_empty() || !rule.noncurrent_version_transitions.is_empty() {
+        return Err(S3Error::not_implemented(
+            "Lifecycle transitions are not implemented",
+        ));
+    }
+    if !rule.noncurrent_version_expiration.is_empty() {
+        return Err(S3Error::not_implemented(
+            "NoncurrentVersionExpiration is not implemented",
+        ));
+    }
+    if rule.expiration.is_none() && rule.abort_incomplete_multipart_upload.is_none() {
+        return Err(S3Error::invalid_request(
+            "At least one action needs to be specified in a rule",
+        ));
+    }
+    let tagged = !rule.tags().is_empty();
+    if let Some(expiration) = &rule.expiration {
+        let delete_marker = expiration.expired_object_delete_marker == Some(true);
+        let given = [
+            expiration.days.is_some(),
+            expiration.date.is_some(),
+            delete_marker,
+        ];
+        if given.iter().filter(|given| **given).count() != 1 {
+            return Err(S3Error::malformed_xml(
+                "an Expiration needs exactly one of Days, Date and ExpiredObjectDeleteMarker",
+            ));
+        }
+        if expiration.days == Some(0) {
+            return Err(S3Error::invalid_argument(
+                "'Days' for Expiration action must be a positive integer",
+            ));
+        }
+        if let Some(date) = &expiration.date {
+            match expiration_date(date) {
+                Some(date) if date.num_seconds_from_midnight() == 0 && date.nanosecond() == 0 => {}
+                Some(_) => {
+                    return Err(S3Error::invalid_argument("'Date' must be at midnight GMT"));
+                }
+                None => {
+                    return Err(S3Error::invalid_argument(
+                        "'Date' must be an ISO 8601 date and time",
+                    ));
+                }
+            }
+        }
+        if delete_marker && tagged {
+            return Err(S3Error::invalid_request(
+                "ExpiredObjectDeleteMarker cannot be specified with Tags.",
+            ));
+        }
+    }
+    if let Some(abort) = &rule.abort_incomplete_multipart_upload {
+        if abort.days_after_initiation == 0 {
+            return Err(S3Error::invalid_argument(
+                "'DaysAfterInitiation' for AbortIncompleteMultipartUpload action must be a \
+                 positive integer",
+            ));
+        }
+        if tagged {
+            return Err(S3Error::invalid_request(
+                "AbortIncompleteMultipartUpload cannot be specified with Tags.",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// A filter has at most one condition, or several under `And`.
+fn validate_filter(filter: &LifecycleFilter) -> S3Result<()> {
+    let conditions = [
+        filter.prefix.is_some(),
+        filter.tag.is_some(),
+        filter.object_size_greater_than.is_some(),
+        filter.object_size_less_than.is_some(),
+        filter.and.is_some(),
+    ];
+    if conditions.iter().filter(|given| **given).count() > 1 {
+        return Err(S3Error::malformed_xml(
+            "a Filter with several conditions needs them under And",
+        ));
+    }
+    let (greater_than, less_than) = match &filter.and {
+        Some(and) => (and.object_size_greater_than, and.object_size_less_than),
+        None => (
+            filter.object_size_greater_than,
+            filter.object_size_less_than,
+        ),
+    };
+    if let (Some(greater_than), Some(less_than)) = (greater_than, less_than)
+        && greater_than >= less_than
+    {
+        return Err(S3Error::invalid_argument(
+            "ObjectSizeGreaterThan must be less than ObjectSizeLessThan",
+        ));
+    }
+    Ok(())
+}
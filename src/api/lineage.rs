@@ -0,0 +1,43 @@
+use super::{S3Result, authorize_listing};
+use crate::error::S3Error;
+use crate::models::AuthContext;
+use crate::server::AppState;
+use crate::services::system_log::VersionLineage;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Lineage {
+    bucket: String,
+    key: String,
+    /// Newest first.
+    versions: Vec<VersionLineage>,
+}
+
+/// `GET /{bucket}/{key}?lineage` (extension): every version and delete
+/// marker of the key, newest first, each with who wrote it, from which IP
+/// and through which operation, as recorded in the access log, for
+/// incident forensics. Needs `s3:ListBucketVersions`, as the version
+/// listing does, for a prefix covering the key.
+pub async fn get_object_lineage(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    key: String,
+) -> S3Result<Response> {
+    authorize_listing(&state, &auth, "s3:ListBucketVersions", &bucket, &key).await?;
+    let versions = state.system_logs.lineage(&bucket, &key).await?;
+    let body = serde_json::to_vec(&Lineage {
+        bucket,
+        key,
+        versions,
+    })
+    .map_err(|e| S3Error::internal(format!("failed to encode lineage: {}", e)))?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        body,
+    )
+        .into_response())
+}
@@ -0,0 +1,192 @@
+use super::{S3Result, authorize, header_str};
+use crate::crypto::{hmac_sha256, sha256_hex};
+use crate::error::S3Error;
+use crate::models::{AuthContext, GetObjectHeaders, GetObjectRequest};
+use crate::server::AppState;
+use crate::sigv4::{MAX_EXPIRES_SECS, Presign};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use chrono::{Duration, SecondsFormat};
+use serde::Serialize;
+use std::collections::HashMap;
+use tokio::io::AsyncReadExt;
+
+/// Header carrying the hex HMAC-SHA256 of the manifest body, keyed with the
+/// requester's secret key.
+pub const MANIFEST_SIGNATURE_HEADER: &str = "x-s3clone-manifest-signature";
+
+const DEFAULT_CHUNK_SIZE: u64 = 8 << 20;
+const MIN_CHUNK_SIZE: u64 = 64 << 10;
+const DEFAULT_EXPIRES_SECS: u64 = 3600;
+
+#[derive(Serialize)]
+struct ChunkManifest {
+    bucket: String,
+    key: String,
+    size: u64,
+    etag: String,
+    last_modified: String,
+    chunk_size: u64,
+    /// When the chunk URLs stop working.
+    expires: String,
+    chunks: Vec<Chunk>,
+}
+
+#[derive(Serialize)]
+struct Chunk {
+    index: usize,
+    offset: u64,
+    size: u64,
+    sha256: String,
+    /// `Range` header to send with `url`.
+    range: String,
+    url: String,
+}
+
+/// `GET /{bucket}/{key}?chunk-manifest[&chunk-size=N][&expires=S]`
+/// (extension): splits the object into fixed-size chunks and returns, for
+/// each, its offset, size and SHA-256 with a URL presigned with the
+/// caller's credentials, so downloaders can fetch the chunks in parallel
+/// with range requests and verify every one.
+pub async fn chunk_manifest(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    key: String,
+    query: HashMap<String, String>,
+    headers: HeaderMap,
+) -> S3Result<Response> {
+    authorize(
+        &state,
+        &auth,
+        "s3:GetObject",
+        &format!("{}/{}", bucket, key),
+    )
+    .await?;
+    // The chunk URLs are presigned, which needs a secret key.
+    let AuthContext::IAMAccount(credentials) = auth else {
+        return Err(S3Error::access_denied());
+    };
+    let chunk_size = query_u64(&query, "chunk-size", DEFAULT_CHUNK_SIZE)?;
+    if chunk_size < MIN_CHUNK_SIZE {
+        return Err(S3Error::invalid_argument(format!(
+            "chunk-size must be at least {} bytes",
+            MIN_CHUNK_SIZE
+        ))
+        .with_resource("ArgumentName", "chunk-size"));
+    }
+    let expires_secs = query_u64(&query, "expires", DEFAULT_EXPIRES_SECS)?;
+    if !(1..=MAX_EXPIRES_SECS).contains(&expires_secs) {
+        return Err(S3Error::invalid_argument(format!(
+            "expires must be between 1 and {} seconds",
+            MAX_EXPIRES_SECS
+        ))
+        .with_resource("ArgumentName", "expires"));
+    }
+
+    let object = state
+        .objects
+        .get_object_range(GetObjectRequest {
+            bucket: bucket.clone(),
+            key: key.clone(),
+            version_id: None,
+            headers: GetObjectHeaders {
+                common: super::common_headers(&headers),
+                range: None,
+                if_modified_since: None,
+                if_unmodified_since: None,
+                if_match: None,
+                if_none_match: None,
+            },
+        })
+        .await?;
+
+    let (region, default_host) = {
+        let cfg = state.config.read().unwrap();
+        let http = &cfg.server.http;
+        (
+            cfg.region.default.clone(),
+            format!("{}:{}", http.host, http.port),
+        )
+    };
+    let host = header_str(&headers, "host").unwrap_or(default_host);
+    let now = state.clock.now();
+    let url = Presign {
+        method: "GET",
+        host: &host,
+        path: &format!("/{}/{}", bucket, key),
+        params: &[],
+        access_key: &credentials.access_key,
+        secret_key: &credentials.secret_key,
+        session_token: credentials.session_token.as_deref(),
+        region: &region,
+        time: now,
+        expires_secs,
+    }
+    .url();
+
+    // Read a chunk at a time, so only one is held in memory.
+    let (metadata, mut data) = (object.metadata, object.data);
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::new();
+    for index in 0.. {
+        chunk.clear();
+        (&mut data)
+            .take(chunk_size)
+            .read_to_end(&mut chunk)
+            .await
+            .map_err(|e| S3Error::internal(format!("failed to read object: {}", e)))?;
+        if chunk.is_empty() {
+            break;
+        }
+        let offset = index as u64 * chunk_size;
+        let size = chunk.len() as u64;
+        chunks.push(Chunk {
+            index,
+            offset,
+            size,
+            sha256: sha256_hex(&chunk),
+            range: format!("bytes={}-{}", offset, offset + size - 1),
+            url: url.clone(),
+        });
+    }
+    let manifest = ChunkManifest {
+        bucket,
+        key,
+        size: metadata.size,
+        etag: metadata.etag,
+        last_modified: metadata.last_modified,
+        chunk_size,
+        expires: (now + Duration::seconds(expires_secs as i64))
+            .to_rfc3339_opts(SecondsFormat::Secs, true),
+        chunks,
+    };
+    let body = serde_json::to_vec(&manifest)
+        .map_err(|e| S3Error::internal(format!("failed to serialize manifest: {}", e)))?;
+    let signature = hex::encode(hmac_sha256(credentials.secret_key.as_bytes(), &body));
+    Ok((
+        StatusCode::OK,
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            ),
+            (
+                header::HeaderName::from_static(MANIFEST_SIGNATURE_HEADER),
+                HeaderValue::from_str(&signature).map_err(|e| S3Error::internal(e.to_string()))?,
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+fn query_u64(query: &HashMap<String, String>, name: &'static str, default: u64) -> S3Result<u64> {
+    match query.get(name) {
+        None => Ok(default),
+        Some(value) => value.parse().map_err(|_| {
+            S3Error::invalid_argument(format!("{} must be a number", name))
+                .with_resource("ArgumentName", name)
+        }),
+    }
+}
@@ -0,0 +1,359 @@
+pub mod acl;
+pub mod batch_head;
+pub mod bucket;
+pub mod capabilities;
+pub mod cors;
+pub mod digest;
+pub mod form_upload;
+pub mod inventory;
+pub mod lifecycle;
+pub mod lineage;
+pub mod manifest;
+pub mod multipart;
+pub mod notification;
+pub mod object;
+pub mod policy;
+pub mod search;
+pub mod sts;
+pub mod tagging;
+pub mod versioning;
+
+use crate::error::S3Error;
+use crate::middleware::{self, ClientIp, RequestId};
+use crate::models::{AuthContext, S3CommonHeaders};
+use crate::server::AppState;
+use crate::services::notification::ConfigurationChange;
+use crate::services::system_log::{self, ConfigurationChangeLogEntry};
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::extract::{DefaultBodyLimit, FromRequestParts};
+use axum::http::HeaderMap;
+use axum::http::request::Parts;
+use axum::routing::get;
+use futures_util::StreamExt;
+
+pub type S3Result<T> = Result<T, S3Error>;
+
+/// The S3 5 GiB limit of a single PUT (and part). Objects, parts and
+/// appends are streamed to storage (see [`upload_body`]).
+pub const MAX_PUT_SIZE: usize = 5 * 1024 * 1024 * 1024;
+
+/// The limit of request documents read into memory, such as tagging, ACL
+/// and bucket configuration documents (see [`read_body`]).
+pub const MAX_DOCUMENT_SIZE: usize = 1024 * 1024;
+
+/// The limit of request documents that name many keys or parts:
+/// DeleteObjects, CompleteMultipartUpload (10,000 parts with checksums),
+/// batch-head and inventory-diff manifests.
+pub const MAX_KEY_LIST_SIZE: usize = 16 * 1024 * 1024;
+
+/// S3 REST API routes (path-style).
+pub fn router(state: AppState) -> Router<AppState> {
+    let bucket_routes = get(bucket::get_bucket)
+        .head(bucket::head_bucket)
+        .put(bucket::create_bucket)
+        .post(bucket::post_bucket)
+        .delete(bucket::delete_bucket)
+        .options(cors::preflight_bucket);
+    Router::new()
+        .route("/", get(bucket::list_buckets).post(sts::post_root))
+        .route("/{bucket}", bucket_routes.clone())
+        .route("/{bucket}/", bucket_routes)
+        .route(
+            "/{bucket}/{*key}",
+            get(object::get_object)
+                .head(object::head_object)
+                .put(object::put_object)
+                .post(object::post_object)
+                .delete(object::delete_object)
+                .options(cors::preflight_object),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::upload_limits,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::authenticate,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::bucket_region,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::cors,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::features,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::deadline,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::retry_after,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::mirror,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::catch_panic,
+        ))
+        .layer(axum::middleware::from_fn(middleware::bucket_name))
+        .layer(axum::middleware::from_fn(middleware::request_id))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::compress,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::response_headers,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::metrics,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::access_log,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state,
+            middleware::client_ip,
+        ))
+        .layer(DefaultBodyLimit::max(MAX_DOCUMENT_SIZE))
+}
+
+/// Checks that the caller may perform `action` (e.g. `s3:GetObject`) on
+/// `resource` (`bucket` or `bucket/key`).
+pub async fn authorize(
+    state: &AppState,
+    ctx: &AuthContext,
+    action: &str,
+    resource: &str,
+) -> S3Result<()> {
+    state
+        .auth
+        .authorize(ctx, action, resource)
+        .await
+        .map_err(S3Error::from)
+}
+
+/// Checks that the caller may perform the listing `action` (e.g.
+/// `s3:ListBucket`) on `bucket` with `prefix`.
+pub async fn authorize_listing(
+    state: &AppState,
+    ctx: &AuthContext,
+    action: &str,
+    bucket: &str,
+    prefix: &str,
+) -> S3Result<()> {
+    state
+        .auth
+        .authorize_listing(ctx, action, bucket, prefix)
+        .await
+        .map_err(S3Error::from)
+}
+
+/// Where a request came from, for audit records; extracted from the
+/// extensions set by the [`middleware::request_id`] and
+/// [`middleware::client_ip`] layers.
+#[derive(Debug, Clone)]
+pub struct RequestOrigin {
+    pub request_id: RequestId,
+    pub client_ip: ClientIp,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestOrigin {
+    type Rejection = S3Error;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let request_id = parts.extensions.get::<RequestId>().cloned();
+        let client_ip = parts.extensions.get::<ClientIp>().copied();
+        match (request_id, client_ip) {
+            (Some(request_id), Some(client_ip)) => Ok(Self {
+                request_id,
+                client_ip,
+            }),
+            _ => Err(S3Error::internal("request origin is not known")),
+        }
+    }
+}
+
+/// Records the replacement of a bucket configuration (`notification`, ...)
+/// in the audit log and publishes it as an `s3:BucketConfiguration:Put`
+/// event. Nothing happens when the document did not change.
+pub async fn configuration_changed(
+    state: &AppState,
+    auth: &AuthContext,
+    origin: &RequestOrigin,
+    bucket: &str,
+    configuration: &'static str,
+    before: String,
+    after: String,
+) {
+    if before == after {
+        return;
+    }
+    let principal = match auth {
+        AuthContext::IAMAccount(credentials) => Some(credentials.access_key.clone()),
+        AuthContext::Anonymous => None,
+    };
+    let (state, origin, bucket) = (state.clone(), origin.clone(), bucket.to_string());
+    // Both the log entry and the event's messages are written to storage.
+    state
+        .storage
+        .clone()
+        .blocking(move |storage| {
+            let bucket = storage.resolve_bucket(&bucket).unwrap_or(bucket);
+            state
+                .system_logs
+                .record_configuration_change(&ConfigurationChangeLogEntry {
+                    time: system_log::now(),
+                    request_id: Some(origin.request_id.0.clone()),
+                    client_ip: Some(origin.client_ip.0.to_string()),
+                    requester: principal.clone(),
+                    bucket: bucket.clone(),
+                    configuration: configuration.to_string(),
+                    before: before.clone(),
+                    after: after.clone(),
+                });
+            state
+                .notifications
+                .publish_configuration_change(&ConfigurationChange {
+                    bucket,
+                    configuration,
+                    principal,
+                    before,
+                    after,
+                });
+        })
+        .await
+}
+
+/// The payload size a request declares; aws-chunked uploads declare it
+/// separately from their encoded length.
+pub fn declared_length(headers: &HeaderMap) -> Option<u64> {
+    ["x-amz-decoded-content-length", "content-length"]
+        .iter()
+        .find_map(|name| headers.get(*name)?.to_str().ok()?.parse().ok())
+}
+
+/// An upload `body` as streamed to storage, which fails with EntityTooLarge
+/// once it exceeds [`MAX_PUT_SIZE`] (right away if it declares to). Bodies
+/// extracted as such are not held to the router's body limit.
+pub fn upload_body(headers: &HeaderMap, body: Body) -> S3Result<Body> {
+    let max = MAX_PUT_SIZE as u64;
+    if declared_length(headers).is_some_and(|length| length > max) {
+        return Err(S3Error::entity_above_range(max));
+    }
+    let mut received = 0;
+    let chunks = body.into_data_stream().map(move |chunk| {
+        let chunk = chunk.map_err(S3Error::from)?;
+        received += chunk.len() as u64;
+        if received > max {
+            return Err(S3Error::entity_above_range(max));
+        }
+        Ok(chunk)
+    });
+    Ok(Body::from_stream(chunks))
+}
+
+/// A request document read into memory, which fails with
+/// MaxMessageLengthExceeded once more than `max` bytes arrived. Handlers
+/// read it only once the request is authorized, so callers that may not
+/// make it never get to send a body.
+pub async fn read_body(body: Body, max: usize) -> S3Result<Bytes> {
+    let mut chunks = body.into_data_stream();
+    let mut data = Vec::new();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk?;
+        if data.len() + chunk.len() > max {
+            return Err(S3Error::max_message_length_exceeded(max));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data.into())
+}
+
+pub fn header_str(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+pub fn common_headers(headers: &HeaderMap) -> S3CommonHeaders {
+    S3CommonHeaders {
+        date: header_str(headers, "x-amz-date")
+            .or_else(|| header_str(headers, "date"))
+            .unwrap_or_default(),
+        host: header_str(headers, "host").unwrap_or_default(),
+        authorization: header_str(headers, "authorization"),
+    }
+}
+
+/// Parses an XML request body. Bodies that are not well-formed or do not
+/// match the expected document fail with `400 MalformedXML`, naming the
+/// problem and, for syntax errors, its byte offset.
+pub fn from_xml<T: serde::de::DeserializeOwned>(body: &[u8]) -> S3Result<T> {
+    let body = std::str::from_utf8(body).map_err(|e| {
+        S3Error::malformed_xml(format!("invalid UTF-8 at byte {}", e.valid_up_to()))
+    })?;
+    quick_xml::de::from_str(body).map_err(|e| {
+        let detail = match syntax_error_position(body) {
+            Some(position) => format!("{} at byte {}", e, position),
+            None => e.to_string(),
+        };
+        S3Error::malformed_xml(detail)
+    })
+}
+
+/// Offset of the first syntax error in `body`, if it is not well-formed.
+/// The deserializer's errors do not carry one.
+fn syntax_error_position(body: &str) -> Option<u64> {
+    let mut reader = quick_xml::Reader::from_str(body);
+    loop {
+        match reader.read_event() {
+            Ok(quick_xml::events::Event::Eof) => return None,
+            Ok(_) => {}
+            Err(_) => return Some(reader.error_position()),
+        }
+    }
+}
+
+/// Serializes an S3 XML response document with the XML declaration.
+pub fn to_xml<T: serde::Serialize>(value: &T) -> S3Result<String> {
+    let body = quick_xml::se::to_string(value)
+        .map_err(|e| S3Error::internal(format!("failed to serialize response: {}", e)))?;
+    Ok(format!(r#"<?xml version="1.0" encoding="UTF-8"?>{}"#, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunked(chunks: usize, size: usize) -> Body {
+        let chunk = Bytes::from(vec![b'x'; size]);
+        let chunks = (0..chunks).map(move |_| Ok::<_, std::io::Error>(chunk.clone()));
+        Body::from_stream(futures_util::stream::iter(chunks))
+    }
+
+    #[tokio::test]
+    async fn documents_are_read_up_to_their_limit() {
+        let body = read_body(chunked(16, 64 * 1024), MAX_DOCUMENT_SIZE)
+            .await
+            .unwrap();
+        assert_eq!(body.len(), MAX_DOCUMENT_SIZE);
+        let e = read_body(chunked(17, 64 * 1024), MAX_DOCUMENT_SIZE)
+            .await
+            .unwrap_err();
+        assert_eq!(e.code, "MaxMessageLengthExceeded");
+        assert_eq!(e.status, 400);
+    }
+}
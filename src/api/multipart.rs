@@ -0,0 +1,287 @@
+use super::acl::acl_header;
+use super::digest::{self, CHECKSUM_ALGORITHM_HEADER, CHECKSUM_TYPE_HEADER};
+use super::object::{
+    SSE_KMS_KEY_ID_HEADER, content_disposition, content_encoding, encryption_headers, header_value,
+    http_date, user_metadata,
+};
+use super::tagging::tagging_header;
+use super::{
+    MAX_KEY_LIST_SIZE, S3Result, authorize, common_headers, declared_length, from_xml, header_str,
+    read_body, to_xml,
+};
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use crate::error::S3Error;
+use crate::models::{
+    AbortMultipartUploadHeaders, AbortMultipartUploadRequest, AuthContext, ChecksumElements,
+    CompleteMultipartUploadHeaders, CompleteMultipartUploadRequest,
+    CompleteMultipartUploadResponse, InitiateMultipartUploadHeaders,
+    InitiateMultipartUploadRequest, InitiateMultipartUploadResponse, S3_XMLNS, UploadPartHeaders,
+    UploadPartRequest,
+};
+use crate::server::AppState;
+use crate::services::lifecycle::abort_date;
+use axum::body::Body;
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use percent_encoding::utf8_percent_encode;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Body of CompleteMultipartUpload.
+#[derive(Debug, Deserialize)]
+struct CompleteMultipartUpload {
+    #[serde(rename = "Part", default)]
+    parts: Vec<CompletedPart>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletedPart {
+    #[serde(rename = "PartNumber")]
+    part_number: u32,
+    #[serde(rename = "ETag")]
+    etag: String,
+    #[serde(rename = "ChecksumCRC32")]
+    checksum_crc32: Option<String>,
+    #[serde(rename = "ChecksumCRC32C")]
+    checksum_crc32c: Option<String>,
+    #[serde(rename = "ChecksumSHA1")]
+    checksum_sha1: Option<String>,
+    #[serde(rename = "ChecksumSHA256")]
+    checksum_sha256: Option<String>,
+}
+
+impl CompletedPart {
+    /// The part's checksum, of the first algorithm given one.
+    fn into_part(self) -> crate::models::CompletedPart {
+        let checksum = [
+            (ChecksumAlgorithm::Crc32, self.checksum_crc32),
+            (ChecksumAlgorithm::Crc32c, self.checksum_crc32c),
+            (ChecksumAlgorithm::Sha1, self.checksum_sha1),
+            (ChecksumAlgorithm::Sha256, self.checksum_sha256),
+        ]
+        .into_iter()
+        .find_map(|(algorithm, value)| {
+            Some(Checksum {
+                algorithm,
+                value: value?,
+            })
+        });
+        crate::models::CompletedPart {
+            part_number: self.part_number,
+            etag: self.etag,
+            checksum,
+        }
+    }
+}
+
+/// `POST /{bucket}/{key}?uploads`: CreateMultipartUpload.
+pub async fn initiate_multipart_upload(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    key: String,
+    headers: HeaderMap,
+) -> S3Result<Response> {
+    let resource = format!("{}/{}", bucket, key);
+    authorize(&state, &auth, "s3:PutObject", &resource).await?;
+    let tagging = tagging_header(&state, &auth, &resource, &headers).await?;
+    let acl = acl_header(&state, &auth, "s3:PutObjectAcl", &resource, &headers).await?;
+    let upload = state
+        .multipart
+        .initiate_multipart_upload(InitiateMultipartUploadRequest {
+            bucket: bucket.clone(),
+            key,
+            headers: InitiateMultipartUploadHeaders {
+                common: common_headers(&headers),
+                content_type: header_str(&headers, "content-type"),
+                content_disposition: content_disposition(&headers),
+                content_encoding: content_encoding(&headers),
+                content_language: header_str(&headers, "content-language"),
+                cache_control: header_str(&headers, "cache-control"),
+                storage_class: header_str(&headers, "x-amz-storage-class"),
+                acl,
+                server_side_encryption: header_str(&headers, "x-amz-server-side-encryption"),
+                sse_kms_key_id: header_str(&headers, SSE_KMS_KEY_ID_HEADER),
+                user_metadata: user_metadata(&headers)?,
+                tagging,
+                checksum_algorithm: digest::checksum_algorithm(&headers)?,
+            },
+        })
+        .await?;
+    let mut response_headers = encryption_headers(&upload.attributes)?;
+    if let Some(algorithm) = &upload.attributes.checksum_algorithm {
+        response_headers.insert(CHECKSUM_ALGORITHM_HEADER, header_value(algorithm)?);
+        response_headers.insert(CHECKSUM_TYPE_HEADER, HeaderValue::from_static("COMPOSITE"));
+    }
+    // As in S3, the client learns when a lifecycle rule will abort the upload.
+    if let Some(lifecycle) = state.buckets.get_bucket_lifecycle(&bucket).await?
+        && let Some((date, rule_id)) = abort_date(&lifecycle, &upload.key, &upload.initiated)
+    {
+        let date = http_date(&date.to_rfc3339());
+        response_headers.insert("x-amz-abort-date", header_value(&date)?);
+        response_headers.insert("x-amz-abort-rule-id", header_value(rule_id)?);
+    }
+    let body = to_xml(&InitiateMultipartUploadResponse {
+        xmlns: S3_XMLNS,
+        bucket,
+        key: upload.key,
+        upload_id: upload.upload_id,
+    })?;
+    Ok((
+        StatusCode::OK,
+        response_headers,
+        [(header::CONTENT_TYPE, "application/xml")],
+        body,
+    )
+        .into_response())
+}
+
+/// `PUT /{bucket}/{key}?partNumber=N&uploadId=ID`: UploadPart.
+pub async fn upload_part(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    key: String,
+    query: HashMap<String, String>,
+    headers: HeaderMap,
+    body: Body,
+) -> S3Result<Response> {
+    authorize(
+        &state,
+        &auth,
+        "s3:PutObject",
+        &format!("{}/{}", bucket, key),
+    )
+    .await?;
+    if headers.contains_key("x-amz-copy-source") {
+        return Err(S3Error::not_implemented(
+            "UploadPartCopy is not implemented",
+        ));
+    }
+    // Verified as the body is read.
+    let checksum = digest::checksum(&headers)?;
+    // 0 is out of range, so a missing or malformed number fails the same way.
+    let part_number = query
+        .get("partNumber")
+        .and_then(|part_number| part_number.parse::<u32>().ok())
+        .unwrap_or(0);
+    let part = state
+        .multipart
+        .upload_part(UploadPartRequest {
+            bucket,
+            key,
+            upload_id: query.get("uploadId").cloned().unwrap_or_default(),
+            part_number,
+            headers: UploadPartHeaders {
+                common: common_headers(&headers),
+                content_length: declared_length(&headers).unwrap_or(0),
+                content_md5: header_str(&headers, "content-md5"),
+                checksum: checksum.clone(),
+            },
+            body,
+        })
+        .await?;
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::ETAG, header_value(&part.etag)?);
+    if let Some(checksum) = checksum {
+        response_headers.insert(checksum.algorithm.header(), header_value(&checksum.value)?);
+    }
+    Ok((StatusCode::OK, response_headers).into_response())
+}
+
+/// `POST /{bucket}/{key}?uploadId=ID`: CompleteMultipartUpload.
+pub async fn complete_multipart_upload(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    key: String,
+    upload_id: String,
+    headers: HeaderMap,
+    body: Body,
+) -> S3Result<Response> {
+    authorize(
+        &state,
+        &auth,
+        "s3:PutObject",
+        &format!("{}/{}", bucket, key),
+    )
+    .await?;
+    let body = read_body(body, MAX_KEY_LIST_SIZE).await?;
+    let document: CompleteMultipartUpload = from_xml(&body)?;
+    let metadata = state
+        .multipart
+        .complete_multipart_upload(CompleteMultipartUploadRequest {
+            bucket: bucket.clone(),
+            key,
+            upload_id,
+            headers: CompleteMultipartUploadHeaders {
+                common: common_headers(&headers),
+            },
+            parts: document
+                .parts
+                .into_iter()
+                .map(CompletedPart::into_part)
+                .collect(),
+        })
+        .await?;
+    let location = format!(
+        "http://{}/{}/{}",
+        header_str(&headers, "host").unwrap_or_else(|| "localhost".to_string()),
+        bucket,
+        utf8_percent_encode(&metadata.key, super::bucket::URL_ENCODE_SET)
+    );
+    let mut response_headers = encryption_headers(&metadata.attributes)?;
+    if let Some(version_id) = &metadata.version_id {
+        response_headers.insert("x-amz-version-id", header_value(version_id)?);
+    }
+    let checksum = ChecksumElements::new(metadata.attributes.checksum());
+    let body = to_xml(&CompleteMultipartUploadResponse {
+        xmlns: S3_XMLNS,
+        location,
+        bucket,
+        key: metadata.key,
+        etag: metadata.etag,
+        checksum_crc32: checksum.crc32,
+        checksum_crc32c: checksum.crc32c,
+        checksum_sha1: checksum.sha1,
+        checksum_sha256: checksum.sha256,
+        checksum_type: checksum.checksum_type,
+    })?;
+    Ok((
+        StatusCode::OK,
+        response_headers,
+        [(header::CONTENT_TYPE, "application/xml")],
+        body,
+    )
+        .into_response())
+}
+
+/// `DELETE /{bucket}/{key}?uploadId=ID`: AbortMultipartUpload.
+pub async fn abort_multipart_upload(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    key: String,
+    upload_id: String,
+    headers: HeaderMap,
+) -> S3Result<Response> {
+    authorize(
+        &state,
+        &auth,
+        "s3:AbortMultipartUpload",
+        &format!("{}/{}", bucket, key),
+    )
+    .await?;
+    state
+        .multipart
+        .abort_multipart_upload(AbortMultipartUploadRequest {
+            bucket,
+            key,
+            upload_id,
+            headers: AbortMultipartUploadHeaders {
+                common: common_headers(&headers),
+            },
+        })
+        .await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
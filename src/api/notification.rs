@@ -0,0 +1,62 @@
+use super::{
+    MAX_DOCUMENT_SIZE, RequestOrigin, S3Result, authorize, configuration_changed, from_xml,
+    read_body, to_xml,
+};
+use crate::models::{AuthContext, NotificationConfiguration, S3_XMLNS};
+use crate::server::AppState;
+use axum::body::Body;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+
+/// `GET /{bucket}?notification`
+pub async fn get_bucket_notification(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:GetBucketNotification", &bucket).await?;
+    let config = state.notifications.get_configuration(&bucket).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/xml")],
+        document(config)?,
+    )
+        .into_response())
+}
+
+fn document(mut config: NotificationConfiguration) -> S3Result<String> {
+    config.xmlns = Some(S3_XMLNS.to_string());
+    to_xml(&config)
+}
+
+/// `PUT /{bucket}?notification`: replaces the configuration; an empty
+/// `NotificationConfiguration` turns notifications off. The change is
+/// audited with the stored documents before and after.
+pub async fn put_bucket_notification(
+    state: AppState,
+    auth: AuthContext,
+    origin: RequestOrigin,
+    bucket: String,
+    body: Body,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:PutBucketNotification", &bucket).await?;
+    let body = read_body(body, MAX_DOCUMENT_SIZE).await?;
+    let config = from_xml(&body)?;
+    let before = state.notifications.get_configuration(&bucket).await?;
+    state
+        .notifications
+        .put_configuration(&bucket, config)
+        .await?;
+    let after = state.notifications.get_configuration(&bucket).await?;
+    configuration_changed(
+        &state,
+        &auth,
+        &origin,
+        &bucket,
+        "notification",
+        document(before)?,
+        document(after)?,
+    )
+    .await;
+    Ok(StatusCode::OK.into_response())
+}
@@ -0,0 +1,992 @@
+use super::acl::acl_header;
+use super::digest::CHECKSUM_TYPE_HEADER;
+use super::tagging::{TAGGING_COUNT_HEADER, tagging_header};
+use super::{
+    MAX_KEY_LIST_SIZE, S3Result, authorize, common_headers, declared_length, digest, from_xml,
+    header_str, read_body, to_xml, upload_body,
+};
+use crate::aws_chunked;
+use crate::bandwidth::{self, ConnectionBandwidth};
+use crate::compression;
+use crate::error::S3Error;
+use crate::header_encoding::{self, encode_word, header_text};
+use crate::middleware::Feature;
+use crate::models::{
+    AppendObjectRequest, AuthContext, ChecksumElements, CopyObjectHeaders, CopyObjectRequest,
+    CopyObjectResponse, CopySourceConditions, DeleteObjectError, DeleteObjectHeaders,
+    DeleteObjectRequest, DeleteObjectsResponse, DeletedObject, Directive, ERROR_NO_SUCH_KEY,
+    GetObjectAttributesResponse, GetObjectHeaders, GetObjectRequest, ObjectAttributes,
+    ObjectMetadata, ObjectParts, PutObjectHeaders, PutObjectRequest, S3_XMLNS,
+    STANDARD_STORAGE_CLASS,
+};
+use crate::server::AppState;
+use crate::storage::ObjectReader;
+use axum::Extension;
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+const USER_METADATA_PREFIX: &str = "x-amz-meta-";
+/// Number of stored user metadata entries that could not be returned as
+/// `x-amz-meta-*` headers.
+const MISSING_META_HEADER: &str = "x-amz-missing-meta";
+/// S3's limit on user metadata: the UTF-8 bytes of all names (without the
+/// prefix) and values together.
+pub const MAX_USER_METADATA_SIZE: usize = 2048;
+pub const SSE_KMS_KEY_ID_HEADER: &str = "x-amz-server-side-encryption-aws-kms-key-id";
+/// Extension: the position the next append to an appendable object must use.
+pub const NEXT_APPEND_POSITION_HEADER: &str = "x-s3clone-next-append-position";
+/// Extension: `Appendable` for objects created by append.
+pub const OBJECT_TYPE_HEADER: &str = "x-s3clone-object-type";
+
+/// `PUT /{bucket}/{key}`: PutObject, CopyObject when `x-amz-copy-source` is
+/// set, UploadPart with `uploadId`, or the append extension with
+/// `?append&position=N`. An `aws-chunked` body is decoded as it streams in,
+/// and checked against `Content-MD5` and `x-amz-content-sha256`.
+/// Objects, parts and appends are streamed to storage; tagging and ACL
+/// documents are read once the request is authorized.
+pub async fn put_object(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    chunked: Option<Extension<aws_chunked::Payload>>,
+    Path((bucket, key)): Path<(String, String)>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Body,
+) -> S3Result<Response> {
+    let body = match chunked {
+        Some(Extension(payload)) => aws_chunked::decode(&headers, payload, body)?,
+        None => body,
+    };
+    if query.contains_key("tagging") {
+        let body = digest::verify(&headers, body)?;
+        return super::tagging::put_object_tagging(state, auth, bucket, key, &query, body).await;
+    }
+    if query.contains_key("acl") {
+        let body = digest::verify(&headers, body)?;
+        return super::acl::put_object_acl(state, auth, bucket, key, &query, &headers, body).await;
+    }
+    let body = digest::verify(&headers, upload_body(&headers, body)?)?;
+    if query.contains_key("uploadId") {
+        return super::multipart::upload_part(state, auth, bucket, key, query, headers, body).await;
+    }
+    if headers.contains_key("x-amz-copy-source") {
+        return copy_object(state, auth, bucket, key, headers).await;
+    }
+    let resource = format!("{}/{}", bucket, key);
+    authorize(&state, &auth, "s3:PutObject", &resource).await?;
+    let put_headers = PutObjectHeaders {
+        common: common_headers(&headers),
+        content_length: declared_length(&headers).unwrap_or(0),
+        content_type: header_str(&headers, "content-type"),
+        content_disposition: content_disposition(&headers),
+        content_encoding: content_encoding(&headers),
+        content_language: header_str(&headers, "content-language"),
+        cache_control: header_str(&headers, "cache-control"),
+        storage_class: header_str(&headers, "x-amz-storage-class"),
+        acl: acl_header(&state, &auth, "s3:PutObjectAcl", &resource, &headers).await?,
+        server_side_encryption: header_str(&headers, "x-amz-server-side-encryption"),
+        sse_kms_key_id: header_str(&headers, SSE_KMS_KEY_ID_HEADER),
+        user_metadata: user_metadata(&headers)?,
+        tagging: tagging_header(&state, &auth, &resource, &headers).await?,
+        checksum: digest::checksum(&headers)?,
+    };
+    if query.contains_key("append") {
+        return append_object(state, bucket, key, &query, put_headers, body).await;
+    }
+    let req = PutObjectRequest {
+        bucket,
+        key,
+        headers: put_headers,
+        body,
+    };
+    let metadata = state.objects.put_object(req).await?;
+    let mut response_headers = encryption_headers(&metadata.attributes)?;
+    response_headers.extend(checksum_headers(&metadata.attributes)?);
+    response_headers.insert(header::ETAG, header_value(&metadata.etag)?);
+    if let Some(version_id) = &metadata.version_id {
+        response_headers.insert("x-amz-version-id", header_value(version_id)?);
+    }
+    Ok((StatusCode::OK, response_headers).into_response())
+}
+
+/// Appends the body to an appendable object, modeled after Alibaba OSS
+/// AppendObject: `position` must be the current size (0 creates the object),
+/// and the response carries the next position.
+async fn append_object(
+    state: AppState,
+    bucket: String,
+    key: String,
+    query: &HashMap<String, String>,
+    headers: PutObjectHeaders,
+    body: Body,
+) -> S3Result<Response> {
+    let position = query
+        .get("position")
+        .and_then(|position| position.parse::<u64>().ok())
+        .ok_or_else(|| {
+            S3Error::invalid_argument("Append requires a numeric position")
+                .with_resource("ArgumentName", "position")
+        })?;
+    let metadata = state
+        .objects
+        .append_object(AppendObjectRequest {
+            bucket,
+            key,
+            position,
+            headers,
+            body,
+        })
+        .await?;
+    let mut response_headers = encryption_headers(&metadata.attributes)?;
+    response_headers.insert(header::ETAG, header_value(&metadata.etag)?);
+    response_headers.insert(
+        NEXT_APPEND_POSITION_HEADER,
+        HeaderValue::from(metadata.size),
+    );
+    Ok((StatusCode::OK, response_headers).into_response())
+}
+
+async fn copy_object(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    key: String,
+    headers: HeaderMap,
+) -> S3Result<Response> {
+    let (source_bucket, source_key, source_version_id) =
+        parse_copy_source(&header_str(&headers, "x-amz-copy-source").unwrap_or_default())?;
+    authorize(
+        &state,
+        &auth,
+        read_action(&source_version_id),
+        &format!("{}/{}", source_bucket, source_key),
+    )
+    .await?;
+    let resource = format!("{}/{}", bucket, key);
+    authorize(&state, &auth, "s3:PutObject", &resource).await?;
+    let tagging_directive = directive(&headers, "x-amz-tagging-directive")?;
+    let tagging = match tagging_directive {
+        Directive::Copy => BTreeMap::new(),
+        Directive::Replace => tagging_header(&state, &auth, &resource, &headers).await?,
+    };
+
+    let copy_source_version_id = source_version_id.clone();
+    let req = CopyObjectRequest {
+        source_bucket,
+        source_key,
+        source_version_id,
+        bucket,
+        key,
+        headers: CopyObjectHeaders {
+            common: common_headers(&headers),
+            metadata_directive: directive(&headers, "x-amz-metadata-directive")?,
+            tagging_directive,
+            content_type: header_str(&headers, "content-type"),
+            content_disposition: content_disposition(&headers),
+            content_encoding: content_encoding(&headers),
+            content_language: header_str(&headers, "content-language"),
+            cache_control: header_str(&headers, "cache-control"),
+            user_metadata: user_metadata(&headers)?,
+            tagging,
+            acl: acl_header(&state, &auth, "s3:PutObjectAcl", &resource, &headers).await?,
+            storage_class: header_str(&headers, "x-amz-storage-class"),
+            server_side_encryption: header_str(&headers, "x-amz-server-side-encryption"),
+            sse_kms_key_id: header_str(&headers, SSE_KMS_KEY_ID_HEADER),
+            conditions: CopySourceConditions {
+                if_match: header_str(&headers, "x-amz-copy-source-if-match"),
+                if_none_match: header_str(&headers, "x-amz-copy-source-if-none-match"),
+                if_modified_since: header_str(&headers, "x-amz-copy-source-if-modified-since"),
+                if_unmodified_since: header_str(&headers, "x-amz-copy-source-if-unmodified-since"),
+            },
+            checksum_algorithm: digest::checksum_algorithm(&headers)?,
+        },
+    };
+    let metadata = state.objects.copy_object(req).await?;
+    let mut response_headers = encryption_headers(&metadata.attributes)?;
+    if let Some(version_id) = &metadata.version_id {
+        response_headers.insert("x-amz-version-id", header_value(version_id)?);
+    }
+    if let Some(version_id) = &copy_source_version_id {
+        response_headers.insert("x-amz-copy-source-version-id", header_value(version_id)?);
+    }
+    let checksum = ChecksumElements::new(metadata.attributes.checksum());
+    let body = to_xml(&CopyObjectResponse {
+        last_modified: metadata.last_modified,
+        etag: metadata.etag,
+        checksum_crc32: checksum.crc32,
+        checksum_crc32c: checksum.crc32c,
+        checksum_sha1: checksum.sha1,
+        checksum_sha256: checksum.sha256,
+        checksum_type: checksum.checksum_type,
+    })?;
+    Ok((
+        StatusCode::OK,
+        response_headers,
+        [(header::CONTENT_TYPE, "application/xml")],
+        body,
+    )
+        .into_response())
+}
+
+/// `POST /{bucket}/{key}`: CreateMultipartUpload with `uploads`, or
+/// CompleteMultipartUpload with `uploadId`.
+pub async fn post_object(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path((bucket, key)): Path<(String, String)>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Body,
+) -> S3Result<Response> {
+    if query.contains_key("uploads") {
+        return super::multipart::initiate_multipart_upload(state, auth, bucket, key, headers)
+            .await;
+    }
+    if let Some(upload_id) = query.get("uploadId") {
+        let upload_id = upload_id.clone();
+        return super::multipart::complete_multipart_upload(
+            state, auth, bucket, key, upload_id, headers, body,
+        )
+        .await;
+    }
+    Err(S3Error::method_not_allowed("POST"))
+}
+
+/// `GET /{bucket}/{key}`
+pub async fn get_object(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    connection: Option<Extension<ConnectionBandwidth>>,
+    Path((bucket, key)): Path<(String, String)>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> S3Result<Response> {
+    if query.contains_key("uploadId") {
+        return Err(S3Error::not_implemented("ListParts is not implemented"));
+    }
+    if query.contains_key("tagging") {
+        return super::tagging::get_object_tagging(state, auth, bucket, key, &query).await;
+    }
+    if query.contains_key("acl") {
+        return super::acl::get_object_acl(state, auth, bucket, key, &query).await;
+    }
+    if query.contains_key("attributes") {
+        return get_object_attributes(state, auth, bucket, key, &query, &headers).await;
+    }
+    if query.contains_key("lineage") {
+        return super::lineage::get_object_lineage(state, auth, bucket, key).await;
+    }
+    let version_id = query.get("versionId").cloned();
+    authorize(
+        &state,
+        &auth,
+        read_action(&version_id),
+        &format!("{}/{}", bucket, key),
+    )
+    .await?;
+    if query.contains_key("chunk-manifest") {
+        return super::manifest::chunk_manifest(state, auth, bucket, key, query, headers).await;
+    }
+    let precompressed = precompressed_variants(&state, &bucket);
+    let variant = match &version_id {
+        None if precompressed => {
+            precompressed_variant(&state, &auth, &bucket, &key, &headers).await?
+        }
+        _ => None,
+    };
+    let (object, mut response_headers) = match variant {
+        Some(variant) => {
+            let object = state
+                .objects
+                .get_object_range(get_request(bucket, variant.key.clone(), None, &headers))
+                .await?;
+            let response_headers = variant_headers(&object.metadata, &variant)?;
+            (object, response_headers)
+        }
+        None => {
+            let object = state
+                .objects
+                .get_object_range(get_request(bucket, key, version_id.clone(), &headers))
+                .await?;
+            let response_headers = object_headers(&object.metadata, version_id.as_deref())?;
+            (object, response_headers)
+        }
+    };
+    if precompressed {
+        response_headers.insert(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+    }
+    let metadata = object.metadata;
+    let tag_count = metadata.attributes.tags.len();
+    if tag_count > 0 {
+        response_headers.insert(TAGGING_COUNT_HEADER, HeaderValue::from(tag_count));
+    }
+    let (status, length) = match object.range {
+        Some((first, last)) => {
+            response_headers.insert(
+                header::CONTENT_RANGE,
+                header_value(&format!("bytes {}-{}/{}", first, last, metadata.size))?,
+            );
+            (StatusCode::PARTIAL_CONTENT, last - first + 1)
+        }
+        None => {
+            // A range has no checksum of its own.
+            if checksum_mode(&headers) {
+                response_headers.extend(checksum_headers(&metadata.attributes)?);
+            }
+            (StatusCode::OK, metadata.size)
+        }
+    };
+    // Bodies are streamed, so the length is not known otherwise.
+    response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+    let body = download(&state, &auth, connection.map(|Extension(c)| c), object.data);
+    Ok((status, response_headers, body).into_response())
+}
+
+/// `data` as a body held to the `server.bandwidth` limits of the connection
+/// and of the caller's access key.
+fn download(
+    state: &AppState,
+    auth: &AuthContext,
+    connection: Option<ConnectionBandwidth>,
+    data: ObjectReader,
+) -> Body {
+    let limits = state.config.read().unwrap().server.bandwidth.clone();
+    let mut buckets = Vec::new();
+    if let Some(connection) = connection
+        && limits.download_per_connection > 0
+    {
+        buckets.push((connection.download, limits.download_per_connection));
+    }
+    if let AuthContext::IAMAccount(credentials) = auth
+        && limits.download_per_access_key > 0
+    {
+        buckets.push((
+            state.bandwidth.download(&credentials.access_key),
+            limits.download_per_access_key,
+        ));
+    }
+    bandwidth::throttle(data, buckets)
+}
+
+/// `HEAD /{bucket}/{key}`
+pub async fn head_object(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path((bucket, key)): Path<(String, String)>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> S3Result<Response> {
+    let version_id = query.get("versionId").cloned();
+    authorize(
+        &state,
+        &auth,
+        read_action(&version_id),
+        &format!("{}/{}", bucket, key),
+    )
+    .await?;
+    let precompressed = precompressed_variants(&state, &bucket);
+    let variant = match &version_id {
+        None if precompressed => {
+            precompressed_variant(&state, &auth, &bucket, &key, &headers).await?
+        }
+        _ => None,
+    };
+    let (metadata, mut response_headers) = match variant {
+        Some(variant) => {
+            let metadata = state
+                .objects
+                .head_object(get_request(bucket, variant.key.clone(), None, &headers))
+                .await?;
+            let response_headers = variant_headers(&metadata, &variant)?;
+            (metadata, response_headers)
+        }
+        None => {
+            let metadata = state
+                .objects
+                .head_object(get_request(bucket, key, version_id.clone(), &headers))
+                .await?;
+            let response_headers = object_headers(&metadata, version_id.as_deref())?;
+            (metadata, response_headers)
+        }
+    };
+    if precompressed {
+        response_headers.insert(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+    }
+    if checksum_mode(&headers) {
+        response_headers.extend(checksum_headers(&metadata.attributes)?);
+    }
+    response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(metadata.size));
+    Ok((StatusCode::OK, response_headers).into_response())
+}
+
+/// What GetObjectAttributes returns, as named in `x-amz-object-attributes`.
+const OBJECT_ATTRIBUTE_NAMES: [&str; 5] = [
+    "ETag",
+    "Checksum",
+    "ObjectParts",
+    "StorageClass",
+    "ObjectSize",
+];
+
+/// `GET /{bucket}/{key}?attributes`: GetObjectAttributes, the attributes
+/// listed in `x-amz-object-attributes` (comma-separated) without the data.
+/// Needs `s3:GetObjectAttributes` as well as `s3:GetObject`, or
+/// `s3:GetObjectVersionAttributes` and `s3:GetObjectVersion` with
+/// `versionId`.
+async fn get_object_attributes(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    key: String,
+    query: &HashMap<String, String>,
+    headers: &HeaderMap,
+) -> S3Result<Response> {
+    let version_id = query.get("versionId").cloned();
+    let resource = format!("{}/{}", bucket, key);
+    authorize(&state, &auth, read_action(&version_id), &resource).await?;
+    let action = match version_id {
+        Some(_) => "s3:GetObjectVersionAttributes",
+        None => "s3:GetObjectAttributes",
+    };
+    authorize(&state, &auth, action, &resource).await?;
+    let invalid = |message: &str| {
+        S3Error::invalid_argument(message).with_resource("ArgumentName", "x-amz-object-attributes")
+    };
+    let mut wanted = Vec::new();
+    for value in headers.get_all("x-amz-object-attributes") {
+        let value = value
+            .to_str()
+            .map_err(|_| invalid("Invalid attribute name specified."))?;
+        for name in value
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            if !OBJECT_ATTRIBUTE_NAMES.contains(&name) {
+                return Err(invalid("Invalid attribute name specified."));
+            }
+            wanted.push(name.to_string());
+        }
+    }
+    if wanted.is_empty() {
+        return Err(invalid(
+            "The x-amz-object-attributes header specifying the attributes to be retrieved is \
+             either missing or empty",
+        ));
+    }
+    let metadata = state
+        .objects
+        .head_object(get_request(bucket, key, version_id.clone(), headers))
+        .await?;
+    let wants = |name: &str| wanted.iter().any(|wanted| wanted == name);
+    let etag = metadata.etag.trim_matches('"');
+    // Multipart ETags end in the number of parts.
+    let parts_count = etag
+        .rsplit_once('-')
+        .and_then(|(_, count)| count.parse::<u32>().ok());
+    let body = to_xml(&GetObjectAttributesResponse {
+        xmlns: S3_XMLNS,
+        etag: wants("ETag").then(|| etag.to_string()),
+        checksum: metadata
+            .attributes
+            .checksum()
+            .filter(|_| wants("Checksum"))
+            .map(|checksum| ChecksumElements::new(Some(checksum))),
+        object_parts: parts_count
+            .filter(|_| wants("ObjectParts"))
+            .map(|parts_count| ObjectParts { parts_count }),
+        storage_class: wants("StorageClass")
+            .then(|| metadata.attributes.storage_class().to_string()),
+        object_size: wants("ObjectSize").then_some(metadata.size),
+    })?;
+    let mut response_headers = HeaderMap::new();
+    if let Some(version_id) = metadata.version_id.as_deref().or(version_id.as_deref()) {
+        response_headers.insert("x-amz-version-id", header_value(version_id)?);
+    }
+    response_headers.insert(
+        header::LAST_MODIFIED,
+        header_value(&http_date(&metadata.last_modified))?,
+    );
+    Ok((
+        StatusCode::OK,
+        response_headers,
+        [(header::CONTENT_TYPE, "application/xml")],
+        body,
+    )
+        .into_response())
+}
+
+/// `DELETE /{bucket}/{key}`
+pub async fn delete_object(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path((bucket, key)): Path<(String, String)>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> S3Result<Response> {
+    if let Some(upload_id) = query.get("uploadId") {
+        let upload_id = upload_id.clone();
+        return super::multipart::abort_multipart_upload(
+            state, auth, bucket, key, upload_id, headers,
+        )
+        .await;
+    }
+    if query.contains_key("tagging") {
+        return super::tagging::delete_object_tagging(state, auth, bucket, key, &query).await;
+    }
+    let version_id = query.get("versionId").cloned();
+    // Removing a specific version is permanent, so it needs its own action.
+    let action = match version_id {
+        Some(_) => "s3:DeleteObjectVersion",
+        None => "s3:DeleteObject",
+    };
+    authorize(&state, &auth, action, &format!("{}/{}", bucket, key)).await?;
+    let deleted = state
+        .objects
+        .delete_object(DeleteObjectRequest {
+            bucket,
+            key,
+            version_id,
+            headers: DeleteObjectHeaders {
+                common: common_headers(&headers),
+            },
+        })
+        .await?;
+    let mut response_headers = HeaderMap::new();
+    if deleted.delete_marker {
+        response_headers.insert("x-amz-delete-marker", HeaderValue::from_static("true"));
+    }
+    if let Some(version_id) = &deleted.version_id {
+        response_headers.insert("x-amz-version-id", header_value(version_id)?);
+    }
+    Ok((StatusCode::NO_CONTENT, response_headers).into_response())
+}
+
+/// Most keys a DeleteObjects request may name.
+const MAX_DELETE_OBJECTS: usize = 1000;
+
+/// Body of DeleteObjects.
+#[derive(Debug, Deserialize)]
+struct Delete {
+    #[serde(rename = "Quiet", default)]
+    quiet: bool,
+    #[serde(rename = "Object", default)]
+    objects: Vec<ObjectIdentifier>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ObjectIdentifier {
+    #[serde(rename = "Key")]
+    key: String,
+    #[serde(rename = "VersionId")]
+    version_id: Option<String>,
+}
+
+/// `POST /{bucket}?delete`: DeleteObjects. Every key is authorized and
+/// deleted like a DeleteObject of its own; keys that fail are reported in
+/// the result instead of failing the request. `Quiet` leaves out the keys
+/// deleted.
+pub async fn delete_objects(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    headers: HeaderMap,
+    body: Body,
+) -> S3Result<Response> {
+    let request: Delete = from_xml(&read_body(body, MAX_KEY_LIST_SIZE).await?)?;
+    if request.objects.is_empty() || request.objects.len() > MAX_DELETE_OBJECTS {
+        return Err(S3Error::malformed_xml(format!(
+            "a Delete request must name between 1 and {} objects",
+            MAX_DELETE_OBJECTS
+        )));
+    }
+    state.buckets.head_bucket(&bucket).await?;
+    // Version IDs in the body are not seen by the `features` layer.
+    let versioning = Feature::Versioning.enabled(&state.config.read().unwrap().features);
+    let mut result = DeleteObjectsResponse {
+        xmlns: S3_XMLNS,
+        deleted: Vec::new(),
+        errors: Vec::new(),
+    };
+    for object in request.objects {
+        let action = match object.version_id {
+            Some(_) => "s3:DeleteObjectVersion",
+            None => "s3:DeleteObject",
+        };
+        let resource = format!("{}/{}", bucket, object.key);
+        let allowed = match object.version_id {
+            Some(_) if !versioning => Err(S3Error::not_implemented(format!(
+                "{} is disabled on this server",
+                Feature::Versioning.name()
+            ))),
+            _ => authorize(&state, &auth, action, &resource).await,
+        };
+        let deleted = match allowed {
+            Ok(()) => state
+                .objects
+                .delete_object(DeleteObjectRequest {
+                    bucket: bucket.clone(),
+                    key: object.key.clone(),
+                    version_id: object.version_id.clone(),
+                    headers: DeleteObjectHeaders {
+                        common: common_headers(&headers),
+                    },
+                })
+                .await
+                .map_err(S3Error::from),
+            Err(e) => Err(e),
+        };
+        match deleted {
+            Ok(_) if request.quiet => {}
+            Ok(deleted) => result.deleted.push(DeletedObject {
+                key: object.key,
+                delete_marker: deleted.delete_marker.then_some(true),
+                // The marker deleted, or the one the delete created.
+                delete_marker_version_id: deleted.version_id.filter(|_| deleted.delete_marker),
+                version_id: object.version_id,
+            }),
+            Err(e) => result.errors.push(DeleteObjectError {
+                key: object.key,
+                version_id: object.version_id,
+                code: e.code.to_string(),
+                message: e.message,
+            }),
+        }
+    }
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/xml")],
+        to_xml(&result)?,
+    )
+        .into_response())
+}
+
+/// Reading a specific version needs `s3:GetObjectVersion` rather than `s3:GetObject`.
+fn read_action(version_id: &Option<String>) -> &'static str {
+    match version_id {
+        Some(_) => "s3:GetObjectVersion",
+        None => "s3:GetObject",
+    }
+}
+
+fn get_request(
+    bucket: String,
+    key: String,
+    version_id: Option<String>,
+    headers: &HeaderMap,
+) -> GetObjectRequest {
+    GetObjectRequest {
+        bucket,
+        key,
+        version_id,
+        headers: GetObjectHeaders {
+            common: common_headers(headers),
+            range: header_str(headers, "range"),
+            if_modified_since: header_str(headers, "if-modified-since"),
+            if_unmodified_since: header_str(headers, "if-unmodified-since"),
+            if_match: header_str(headers, "if-match"),
+            if_none_match: header_str(headers, "if-none-match"),
+        },
+    }
+}
+
+/// Suffixes of the pre-compressed variants of a key and their codings, in
+/// order of preference.
+const PRECOMPRESSED_VARIANTS: [(&str, &str); 2] = [(".br", "br"), (".gz", "gzip")];
+
+/// Whether `bucket` serves pre-compressed variants
+/// (`server.precompressed_variants`).
+fn precompressed_variants(state: &AppState, bucket: &str) -> bool {
+    let config = state.config.read().unwrap();
+    config
+        .server
+        .precompressed_variants
+        .iter()
+        .any(|b| b == bucket)
+}
+
+/// A pre-compressed variant to send instead of the object asked for.
+struct Variant {
+    key: String,
+    /// Its `Content-Encoding`.
+    coding: &'static str,
+    /// That of the object asked for, as the variant's own describes the
+    /// compressed file.
+    content_type: Option<String>,
+}
+
+/// The pre-compressed variant to answer a GET or HEAD of `bucket/key` with:
+/// `{key}.br` or `{key}.gz`, when `key` exists, the client's
+/// `Accept-Encoding` allows the coding and the caller may read the variant.
+/// Range requests get the stored object, as their offsets refer to it.
+async fn precompressed_variant(
+    state: &AppState,
+    auth: &AuthContext,
+    bucket: &str,
+    key: &str,
+    headers: &HeaderMap,
+) -> S3Result<Option<Variant>> {
+    let Some(accept_encoding) = header_str(headers, "accept-encoding") else {
+        return Ok(None);
+    };
+    if headers.contains_key(header::RANGE) {
+        return Ok(None);
+    }
+    // Without conditions, which are for the representation sent.
+    let plain = |key: String| GetObjectRequest {
+        bucket: bucket.to_string(),
+        key,
+        version_id: None,
+        headers: GetObjectHeaders {
+            common: common_headers(headers),
+            range: None,
+            if_modified_since: None,
+            if_unmodified_since: None,
+            if_match: None,
+            if_none_match: None,
+        },
+    };
+    for (suffix, coding) in PRECOMPRESSED_VARIANTS {
+        if !compression::accepts(&accept_encoding, coding) {
+            continue;
+        }
+        let variant = format!("{}{}", key, suffix);
+        let resource = format!("{}/{}", bucket, variant);
+        if authorize(state, auth, "s3:GetObject", &resource)
+            .await
+            .is_err()
+        {
+            continue;
+        }
+        match state
+            .objects
+            .head_object(plain(variant.clone()))
+            .await
+            .map_err(S3Error::from)
+        {
+            Ok(_) => {}
+            Err(e) if e.code == ERROR_NO_SUCH_KEY => continue,
+            Err(e) => return Err(e),
+        }
+        let original = state.objects.head_object(plain(key.to_string())).await?;
+        return Ok(Some(Variant {
+            key: variant,
+            coding,
+            content_type: original.attributes.content_type,
+        }));
+    }
+    Ok(None)
+}
+
+/// [`object_headers`] of a pre-compressed variant, sent as the object it is
+/// a variant of.
+fn variant_headers(metadata: &ObjectMetadata, variant: &Variant) -> S3Result<HeaderMap> {
+    let mut headers = object_headers(metadata, None)?;
+    let content_type = variant
+        .content_type
+        .as_deref()
+        .unwrap_or("binary/octet-stream");
+    headers.insert(header::CONTENT_TYPE, header_value(content_type)?);
+    headers.insert(
+        header::CONTENT_ENCODING,
+        HeaderValue::from_static(variant.coding),
+    );
+    Ok(headers)
+}
+
+/// Response headers describing a stored object (GET and HEAD). The version
+/// ID is sent for objects written with versioning enabled, and echoed when
+/// one was requested.
+fn object_headers(metadata: &ObjectMetadata, version_id: Option<&str>) -> S3Result<HeaderMap> {
+    let mut headers = encryption_headers(&metadata.attributes)?;
+    if let Some(version_id) = metadata.version_id.as_deref().or(version_id) {
+        headers.insert("x-amz-version-id", header_value(version_id)?);
+    }
+    let content_type = metadata
+        .attributes
+        .content_type
+        .as_deref()
+        .unwrap_or("binary/octet-stream");
+    headers.insert(header::CONTENT_TYPE, header_value(content_type)?);
+    let attributes = &metadata.attributes;
+    for (name, value) in [
+        (header::CONTENT_DISPOSITION, &attributes.content_disposition),
+        (header::CONTENT_ENCODING, &attributes.content_encoding),
+        (header::CONTENT_LANGUAGE, &attributes.content_language),
+        (header::CACHE_CONTROL, &attributes.cache_control),
+    ] {
+        if let Some(value) = value {
+            headers.insert(name, header_value(value)?);
+        }
+    }
+    headers.insert(header::ETAG, header_value(&metadata.etag)?);
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    // As in S3, the header is left out for STANDARD.
+    let storage_class = metadata.attributes.storage_class();
+    if storage_class != STANDARD_STORAGE_CLASS {
+        headers.insert("x-amz-storage-class", header_value(storage_class)?);
+    }
+    if metadata.attributes.appendable {
+        headers.insert(OBJECT_TYPE_HEADER, HeaderValue::from_static("Appendable"));
+        headers.insert(
+            NEXT_APPEND_POSITION_HEADER,
+            HeaderValue::from(metadata.size),
+        );
+    }
+    headers.insert(
+        header::LAST_MODIFIED,
+        header_value(&http_date(&metadata.last_modified))?,
+    );
+    // Metadata stored outside the REST API (a hand-edited metadata file, a
+    // restore) may have names that are no header name; as in S3, those are
+    // left out and counted in `x-amz-missing-meta`.
+    let mut missing = 0;
+    for (name, value) in &metadata.attributes.user_metadata {
+        let name = format!("{}{}", USER_METADATA_PREFIX, name);
+        match (
+            HeaderName::try_from(name),
+            HeaderValue::try_from(encode_word(value)),
+        ) {
+            (Ok(name), Ok(value)) => {
+                headers.insert(name, value);
+            }
+            _ => missing += 1,
+        }
+    }
+    if missing > 0 {
+        headers.insert(MISSING_META_HEADER, HeaderValue::from(missing));
+    }
+    Ok(headers)
+}
+
+/// Whether a GET or HEAD asks for the additional checksum with
+/// `x-amz-checksum-mode: ENABLED`.
+fn checksum_mode(headers: &HeaderMap) -> bool {
+    header_str(headers, "x-amz-checksum-mode").is_some_and(|mode| mode == "ENABLED")
+}
+
+/// The additional checksum of an object, if it has one, and its type.
+pub fn checksum_headers(attributes: &ObjectAttributes) -> S3Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    if let Some(checksum) = attributes.checksum() {
+        headers.insert(checksum.algorithm.header(), header_value(&checksum.value)?);
+        headers.insert(
+            CHECKSUM_TYPE_HEADER,
+            HeaderValue::from_static(checksum.checksum_type()),
+        );
+    }
+    Ok(headers)
+}
+
+/// `x-amz-server-side-encryption` and, for SSE-KMS, the key ARN.
+pub fn encryption_headers(attributes: &ObjectAttributes) -> S3Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    if let Some(sse) = &attributes.server_side_encryption {
+        headers.insert("x-amz-server-side-encryption", header_value(sse)?);
+    }
+    if let Some(key_id) = &attributes.sse_kms_key_id {
+        headers.insert(SSE_KMS_KEY_ID_HEADER, header_value(key_id)?);
+    }
+    Ok(headers)
+}
+
+pub fn header_value(value: &str) -> S3Result<HeaderValue> {
+    HeaderValue::from_str(value).map_err(|e| S3Error::internal(e.to_string()))
+}
+
+/// Formats a stored RFC 3339 timestamp as an HTTP date.
+pub fn http_date(rfc3339: &str) -> String {
+    DateTime::parse_from_rfc3339(rfc3339)
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_default()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+/// `x-amz-meta-*` headers without the prefix. Values may be raw UTF-8; they
+/// are stored decoded and returned as RFC 2047 encoded words, as in S3.
+pub fn user_metadata(headers: &HeaderMap) -> S3Result<HashMap<String, String>> {
+    let mut metadata = HashMap::new();
+    for (name, value) in headers {
+        let Some(key) = name.as_str().strip_prefix(USER_METADATA_PREFIX) else {
+            continue;
+        };
+        let value = header_text(value).ok_or_else(|| {
+            S3Error::invalid_argument("User metadata values must be US-ASCII or UTF-8")
+                .with_resource("ArgumentName", name.as_str().to_string())
+        })?;
+        metadata.insert(key.to_string(), value.to_string());
+    }
+    let size = metadata
+        .iter()
+        .map(|(name, value)| name.len() + value.len())
+        .sum();
+    if size > MAX_USER_METADATA_SIZE {
+        return Err(S3Error::metadata_too_large(size));
+    }
+    Ok(metadata)
+}
+
+/// `Content-Encoding` without the `aws-chunked` coding of the request
+/// body, which S3 does not store; `None` when nothing else is left.
+pub fn content_encoding(headers: &HeaderMap) -> Option<String> {
+    let value = header_str(headers, "content-encoding")?;
+    let codings: Vec<&str> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|coding| {
+            !coding.is_empty() && !coding.eq_ignore_ascii_case(aws_chunked::CONTENT_CODING)
+        })
+        .collect();
+    (!codings.is_empty()).then(|| codings.join(","))
+}
+
+/// `Content-Disposition`, with a non-ASCII filename turned into a
+/// `filename*` parameter so it can be returned as sent.
+pub fn content_disposition(headers: &HeaderMap) -> Option<String> {
+    let value = header_text(headers.get(header::CONTENT_DISPOSITION)?)?;
+    Some(header_encoding::content_disposition(value))
+}
+
+fn directive(headers: &HeaderMap, name: &str) -> S3Result<Directive> {
+    match header_str(headers, name).as_deref() {
+        None | Some("COPY") => Ok(Directive::Copy),
+        Some("REPLACE") => Ok(Directive::Replace),
+        Some(other) => Err(S3Error::invalid_argument(format!(
+            "Unknown {} value: {}",
+            name, other
+        ))),
+    }
+}
+
+/// Parses `x-amz-copy-source`: `[/]bucket/key`, URL-encoded, optionally
+/// followed by `?versionId=`.
+fn parse_copy_source(value: &str) -> S3Result<(String, String, Option<String>)> {
+    let (path, version_id) = match value.split_once('?') {
+        Some((path, query)) => (
+            path,
+            form_urlencoded::parse(query.as_bytes())
+                .find(|(name, _)| name == "versionId")
+                .map(|(_, version_id)| version_id.into_owned()),
+        ),
+        None => (value, None),
+    };
+    let decoded = percent_decode_str(path.trim_start_matches('/'))
+        .decode_utf8()
+        .map_err(|_| S3Error::invalid_argument("Invalid copy source encoding"))?;
+    match decoded.split_once('/') {
+        Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => {
+            Ok((bucket.to_string(), key.to_string(), version_id))
+        }
+        _ => Err(S3Error::invalid_argument(
+            "Copy Source must mention the source bucket and key: sourcebucket/sourcekey",
+        )
+        .with_resource("ArgumentName", "x-amz-copy-source")),
+    }
+}
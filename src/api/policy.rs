@@ -0,0 +1,189 @@
+use super::{
+    MAX_DOCUMENT_SIZE, RequestOrigin, S3Result, authorize, configuration_changed, read_body,
+};
+use crate::error::S3Error;
+use crate::models::{
+    AuthContext, BucketPolicy, POLICY_VERSIONS, PolicyPrincipal, PolicyStatement, S3_ARN_PREFIX,
+};
+use crate::server::AppState;
+use crate::services::auth::is_known_action;
+use axum::body::Body;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use std::collections::HashSet;
+
+/// S3's limit on the size of a bucket policy, in bytes.
+pub const MAX_POLICY_SIZE: usize = 20 * 1024;
+
+/// `GET /{bucket}?policy`: GetBucketPolicy, `404 NoSuchBucketPolicy` when
+/// the bucket has none.
+pub async fn get_bucket_policy(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:GetBucketPolicy", &bucket).await?;
+    let Some(policy) = state.buckets.get_bucket_policy(&bucket).await? else {
+        return Err(S3Error::no_such_bucket_policy(&bucket));
+    };
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        document(Some(&policy))?,
+    )
+        .into_response())
+}
+
+/// `PUT /{bucket}?policy`: PutBucketPolicy, replacing the policy with the
+/// JSON document of the body. The change is audited like other bucket
+/// configurations.
+pub async fn put_bucket_policy(
+    state: AppState,
+    auth: AuthContext,
+    origin: RequestOrigin,
+    bucket: String,
+    body: Body,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:PutBucketPolicy", &bucket).await?;
+    let body = read_body(body, MAX_DOCUMENT_SIZE).await?;
+    if body.len() > MAX_POLICY_SIZE {
+        return Err(S3Error::malformed_policy(format!(
+            "Policies must not exceed {} bytes",
+            MAX_POLICY_SIZE
+        )));
+    }
+    let policy: BucketPolicy = serde_json::from_slice(&body).map_err(|e| {
+        S3Error::malformed_policy(format!(
+            "Policies must be valid JSON and the first byte must be '{{': {}",
+            e
+        ))
+    })?;
+    let name = {
+        let bucket = bucket.clone();
+        state
+            .storage
+            .blocking(move |storage| storage.resolve_bucket(&bucket))
+            .await?
+    };
+    validate_policy(&name, &policy)?;
+    replace_policy(&state, &auth, &origin, &bucket, Some(policy)).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// `DELETE /{bucket}?policy`: DeleteBucketPolicy.
+pub async fn delete_bucket_policy(
+    state: AppState,
+    auth: AuthContext,
+    origin: RequestOrigin,
+    bucket: String,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:DeleteBucketPolicy", &bucket).await?;
+    replace_policy(&state, &auth, &origin, &bucket, None).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn replace_policy(
+    state: &AppState,
+    auth: &AuthContext,
+    origin: &RequestOrigin,
+    bucket: &str,
+    policy: Option<BucketPolicy>,
+) -> S3Result<()> {
+    let before = document(state.buckets.get_bucket_policy(bucket).await?.as_ref())?;
+    let after = document(policy.as_ref())?;
+    state.buckets.put_bucket_policy(bucket, policy).await?;
+    configuration_changed(state, auth, origin, bucket, "policy", before, after).await;
+    Ok(())
+}
+
+/// The stored policy as returned by GET; empty when there is none.
+fn document(policy: Option<&BucketPolicy>) -> S3Result<String> {
+    match policy {
+        Some(policy) => serde_json::to_string(policy)
+            .map_err(|e| S3Error::internal(format!("failed to serialize policy: {}", e))),
+        None => Ok(String::new()),
+    }
+}
+
+/// Checks a policy of `bucket` (its name, not an alias). Conditions and the
+/// negated `NotPrincipal`, `NotAction` and `NotResource` are refused, as
+/// they are not evaluated.
+pub fn validate_policy(bucket: &str, policy: &BucketPolicy) -> S3Result<()> {
+    if let Some(version) = &policy.version
+        && !POLICY_VERSIONS.contains(&version.as_str())
+    {
+        return Err(S3Error::malformed_policy(
+            "The policy must contain a valid version string",
+        ));
+    }
+    if policy.statements().is_empty() {
+        return Err(S3Error::malformed_policy(
+            "Could not parse the policy: Statement is empty!",
+        ));
+    }
+    let mut sids = HashSet::new();
+    for statement in policy.statements() {
+        if let Some(sid) = &statement.sid
+            && !sids.insert(sid)
+        {
+            return Err(S3Error::malformed_policy(
+                "Statement IDs (SID) in a single policy must be unique",
+            ));
+        }
+        validate_statement(bucket, statement)?;
+    }
+    Ok(())
+}
+
+fn validate_statement(bucket: &str, statement: &PolicyStatement) -> S3Result<()> {
+    if statement.condition.is_some() {
+        return Err(S3Error::not_implemented(
+            "Conditions in bucket policies are not implemented",
+        ));
+    }
+    if statement.not_principal.is_some()
+        || statement.not_action.is_some()
+        || statement.not_resource.is_some()
+    {
+        return Err(S3Error::not_implemented(
+            "NotPrincipal, NotAction and NotResource are not implemented",
+        ));
+    }
+    match &statement.principal {
+        None => {
+            return Err(S3Error::malformed_policy(
+                "Missing required field Principal",
+            ));
+        }
+        Some(PolicyPrincipal::Everyone(principal)) if principal != "*" => {
+            return Err(S3Error::malformed_policy("Invalid principal in policy"));
+        }
+        Some(_) => {}
+    }
+    if statement.actions().is_empty() {
+        return Err(S3Error::malformed_policy("Missing required field Action"));
+    }
+    for action in statement.actions() {
+        if !(action == "*" || action.starts_with("s3:")) || !is_known_action(action) {
+            return Err(S3Error::malformed_policy(format!(
+                "Policy has invalid action: {}",
+                action
+            )));
+        }
+    }
+    if statement.resources().is_empty() {
+        return Err(S3Error::malformed_policy("Missing required field Resource"));
+    }
+    for resource in statement.resources() {
+        let named = resource
+            .strip_prefix(S3_ARN_PREFIX)
+            .map(|resource| resource.split('/').next().unwrap_or_default());
+        if named != Some(bucket) {
+            return Err(S3Error::malformed_policy(format!(
+                "Policy has invalid resource: {}",
+                resource
+            )));
+        }
+    }
+    Ok(())
+}
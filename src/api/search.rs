@@ -0,0 +1,141 @@
+use super::{S3Result, authorize, authorize_listing};
+use crate::error::S3Error;
+use crate::models::{AuthContext, ObjectMetadata, SearchObjectsRequest, SearchPredicate};
+use crate::server::AppState;
+use crate::services::bucket::{MAX_KEYS, decode_continuation_token, encode_continuation_token};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Serialize)]
+struct SearchResult {
+    objects: Vec<SearchHit>,
+    is_truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_continuation_token: Option<String>,
+}
+
+/// A matching object, with user metadata as stored (not encoded). `tags`
+/// is left out unless the caller may read the object's tags.
+#[derive(Serialize)]
+struct SearchHit {
+    key: String,
+    etag: String,
+    size: u64,
+    last_modified: String,
+    content_type: String,
+    storage_class: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<BTreeMap<String, String>>,
+}
+
+impl SearchHit {
+    fn new(metadata: ObjectMetadata, tags_visible: bool) -> Self {
+        let attributes = metadata.attributes;
+        Self {
+            storage_class: attributes.storage_class().to_string(),
+            key: metadata.key,
+            etag: metadata.etag,
+            size: metadata.size,
+            last_modified: metadata.last_modified,
+            content_type: attributes
+                .content_type
+                .unwrap_or_else(|| "binary/octet-stream".to_string()),
+            metadata: attributes.user_metadata,
+            tags: tags_visible.then_some(attributes.tags),
+        }
+    }
+}
+
+/// `GET /{bucket}?search&query=Q[&prefix=P][&max-keys=N][&continuation-token=T]`
+/// (extension): the objects whose key, content type, storage class, tags or
+/// user metadata match every predicate of `Q`, in key order and paged like
+/// ListObjectsV2, so tools find tagged objects without listing the bucket
+/// and fetching every key's metadata themselves.
+///
+/// Searching needs `s3:ListBucket`; an object is only returned if the
+/// caller may `s3:GetObject` it, and only matches tag predicates (and has
+/// its tags returned) if the caller may `s3:GetObjectTagging` it, so a
+/// search reveals nothing a HEAD or GetObjectTagging would not.
+pub async fn search_objects(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    query: HashMap<String, String>,
+) -> S3Result<Response> {
+    let prefix = query.get("prefix").cloned().unwrap_or_default();
+    authorize_listing(&state, &auth, "s3:ListBucket", &bucket, &prefix).await?;
+    let predicates = query
+        .get("query")
+        .ok_or_else(|| "the search query is missing".to_string())
+        .and_then(|q| SearchPredicate::parse_query(q))
+        .map_err(|e| S3Error::invalid_argument(e).with_resource("ArgumentName", "query"))?;
+    let max_keys = match query.get("max-keys") {
+        Some(v) => v.parse::<u32>().map_err(|_| {
+            S3Error::invalid_argument("Provided max-keys not an integer or within integer range")
+                .with_resource("ArgumentName", "max-keys")
+        })?,
+        None => MAX_KEYS,
+    }
+    .min(MAX_KEYS) as usize;
+    let start_after = match query.get("continuation-token") {
+        Some(token) => Some(decode_continuation_token(token)?),
+        None => None,
+    };
+    let has_tag_predicates = predicates.iter().any(SearchPredicate::is_tag);
+    let matches = state
+        .buckets
+        .search_objects(SearchObjectsRequest {
+            bucket: bucket.clone(),
+            prefix,
+            predicates,
+            start_after,
+        })
+        .await?;
+
+    let mut objects = Vec::new();
+    let mut is_truncated = false;
+    for metadata in matches {
+        let resource = format!("{}/{}", bucket, metadata.key);
+        if authorize(&state, &auth, "s3:GetObject", &resource)
+            .await
+            .is_err()
+        {
+            continue;
+        }
+        let tags_visible = authorize(&state, &auth, "s3:GetObjectTagging", &resource)
+            .await
+            .is_ok();
+        if has_tag_predicates && !tags_visible {
+            continue;
+        }
+        // One more match than fits tells that the page is truncated.
+        if objects.len() == max_keys {
+            is_truncated = true;
+            break;
+        }
+        objects.push(SearchHit::new(metadata, tags_visible));
+    }
+    let result = SearchResult {
+        next_continuation_token: is_truncated
+            .then(|| {
+                objects
+                    .last()
+                    .map(|hit| encode_continuation_token(&hit.key))
+            })
+            .flatten(),
+        objects,
+        is_truncated,
+    };
+    let body = serde_json::to_vec(&result)
+        .map_err(|e| S3Error::internal(format!("failed to encode search result: {}", e)))?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        body,
+    )
+        .into_response())
+}
@@ -0,0 +1,96 @@
+use super::{MAX_DOCUMENT_SIZE, S3Result, authorize, read_body, to_xml};
+use crate::error::{S3Error, xml_escape};
+use crate::middleware::RequestId;
+use crate::models::{AssumeRoleRequest, AuthContext, ERROR_INVALID_ACTION};
+use crate::server::AppState;
+use crate::services::sts::role_name;
+use axum::Extension;
+use axum::body::Body;
+use axum::extract::{Query, State};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+
+/// `POST /`: the STS query API (`Action=AssumeRole`). Parameters come from
+/// the form body or the query string. Errors use the STS `ErrorResponse`
+/// document rather than the S3 one, so SDKs see the right error codes.
+pub async fn post_root(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Extension(RequestId(request_id)): Extension<RequestId>,
+    Query(query): Query<HashMap<String, String>>,
+    body: Body,
+) -> Response {
+    let body = match read_body(body, MAX_DOCUMENT_SIZE).await {
+        Ok(body) => body,
+        Err(e) => return error_response(&e, &request_id),
+    };
+    let mut params = query;
+    params.extend(form_urlencoded::parse(&body).map(|(k, v)| (k.into_owned(), v.into_owned())));
+    match dispatch(&state, &auth, &request_id, params).await {
+        Ok(response) => response,
+        Err(e) => error_response(&e, &request_id),
+    }
+}
+
+async fn dispatch(
+    state: &AppState,
+    auth: &AuthContext,
+    request_id: &str,
+    mut params: HashMap<String, String>,
+) -> S3Result<Response> {
+    match params.get("Action").map(String::as_str) {
+        Some("AssumeRole") => {}
+        action => {
+            return Err(S3Error::new(
+                StatusCode::BAD_REQUEST,
+                ERROR_INVALID_ACTION,
+                format!("Could not find operation {} ", action.unwrap_or_default()),
+            ));
+        }
+    }
+    let role_arn = params.remove("RoleArn").unwrap_or_default();
+    authorize(state, auth, "sts:AssumeRole", &role_name(&role_arn)?).await?;
+    let duration_seconds = match params.get("DurationSeconds") {
+        Some(v) => Some(
+            v.parse::<u32>()
+                .map_err(|_| S3Error::invalid_argument(format!("Invalid DurationSeconds {}", v)))?,
+        ),
+        None => None,
+    };
+    let result = state
+        .sts
+        .assume_role(
+            AssumeRoleRequest {
+                role_arn,
+                role_session_name: params.remove("RoleSessionName").unwrap_or_default(),
+                policy: params.remove("Policy"),
+                duration_seconds,
+            },
+            request_id,
+        )
+        .await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/xml")],
+        to_xml(&result)?,
+    )
+        .into_response())
+}
+
+fn error_response(e: &S3Error, request_id: &str) -> Response {
+    let kind = if e.status.is_server_error() {
+        "Receiver"
+    } else {
+        "Sender"
+    };
+    let body = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><ErrorResponse xmlns="{}"><Error><Type>{}</Type><Code>{}</Code><Message>{}</Message></Error><RequestId>{}</RequestId></ErrorResponse>"#,
+        crate::models::STS_XMLNS,
+        kind,
+        e.code,
+        xml_escape(&e.message),
+        request_id
+    );
+    (e.status, [(header::CONTENT_TYPE, "text/xml")], body).into_response()
+}
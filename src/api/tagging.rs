@@ -0,0 +1,248 @@
+use super::object::header_value;
+use super::{
+    MAX_DOCUMENT_SIZE, RequestOrigin, S3Result, authorize, configuration_changed, from_xml,
+    header_str, read_body, to_xml,
+};
+use crate::error::S3Error;
+use crate::models::{AuthContext, S3_XMLNS, Tagging};
+use crate::server::AppState;
+use axum::body::Body;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use std::collections::{BTreeMap, HashMap};
+
+/// Tags set with a PUT, copy or CreateMultipartUpload, URL query encoded.
+pub const TAGGING_HEADER: &str = "x-amz-tagging";
+/// Number of tags of the object, sent on GET when it has any.
+pub const TAGGING_COUNT_HEADER: &str = "x-amz-tagging-count";
+/// S3's limits on tags; lengths are in characters.
+pub const MAX_OBJECT_TAGS: usize = 10;
+pub const MAX_BUCKET_TAGS: usize = 50;
+pub const MAX_TAG_KEY_LENGTH: usize = 128;
+pub const MAX_TAG_VALUE_LENGTH: usize = 256;
+
+/// `GET /{bucket}/{key}?tagging`: GetObjectTagging.
+pub async fn get_object_tagging(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    key: String,
+    query: &HashMap<String, String>,
+) -> S3Result<Response> {
+    let version_id = query.get("versionId").map(String::as_str);
+    let action = match version_id {
+        Some(_) => "s3:GetObjectVersionTagging",
+        None => "s3:GetObjectTagging",
+    };
+    authorize(&state, &auth, action, &format!("{}/{}", bucket, key)).await?;
+    let tags = state
+        .objects
+        .get_object_tagging(&bucket, &key, version_id)
+        .await?;
+    Ok((
+        StatusCode::OK,
+        version_headers(version_id)?,
+        [(header::CONTENT_TYPE, "application/xml")],
+        to_xml(&Tagging::new(S3_XMLNS, &tags))?,
+    )
+        .into_response())
+}
+
+/// `PUT /{bucket}/{key}?tagging`: PutObjectTagging, replacing all tags of
+/// the object with those of the `Tagging` body.
+pub async fn put_object_tagging(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    key: String,
+    query: &HashMap<String, String>,
+    body: Body,
+) -> S3Result<Response> {
+    let version_id = query.get("versionId").map(String::as_str);
+    let action = match version_id {
+        Some(_) => "s3:PutObjectVersionTagging",
+        None => "s3:PutObjectTagging",
+    };
+    authorize(&state, &auth, action, &format!("{}/{}", bucket, key)).await?;
+    let body = read_body(body, MAX_DOCUMENT_SIZE).await?;
+    let tagging: Tagging = from_xml(&body)?;
+    let tags = validate_tags(tag_pairs(tagging), "Object", MAX_OBJECT_TAGS)?;
+    state
+        .objects
+        .put_object_tagging(&bucket, &key, version_id, tags)
+        .await?;
+    Ok((StatusCode::OK, version_headers(version_id)?).into_response())
+}
+
+/// `DELETE /{bucket}/{key}?tagging`: DeleteObjectTagging.
+pub async fn delete_object_tagging(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+    key: String,
+    query: &HashMap<String, String>,
+) -> S3Result<Response> {
+    let version_id = query.get("versionId").map(String::as_str);
+    let action = match version_id {
+        Some(_) => "s3:DeleteObjectVersionTagging",
+        None => "s3:DeleteObjectTagging",
+    };
+    authorize(&state, &auth, action, &format!("{}/{}", bucket, key)).await?;
+    state
+        .objects
+        .delete_object_tagging(&bucket, &key, version_id)
+        .await?;
+    Ok((StatusCode::NO_CONTENT, version_headers(version_id)?).into_response())
+}
+
+/// The tags of `x-amz-tagging`, checked like a PutObjectTagging body.
+/// Setting tags this way also needs `s3:PutObjectTagging` on `resource`,
+/// as in S3.
+pub async fn tagging_header(
+    state: &AppState,
+    auth: &AuthContext,
+    resource: &str,
+    headers: &HeaderMap,
+) -> S3Result<BTreeMap<String, String>> {
+    let Some(value) = header_str(headers, TAGGING_HEADER) else {
+        return Ok(BTreeMap::new());
+    };
+    let tags = validate_tags(
+        form_urlencoded::parse(value.as_bytes()).map(|(k, v)| (k.into_owned(), v.into_owned())),
+        "Object",
+        MAX_OBJECT_TAGS,
+    )?;
+    if !tags.is_empty() {
+        authorize(state, auth, "s3:PutObjectTagging", resource).await?;
+    }
+    Ok(tags)
+}
+
+/// `GET /{bucket}?tagging`: GetBucketTagging, `404 NoSuchTagSet` when the
+/// bucket has no tags.
+pub async fn get_bucket_tagging(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:GetBucketTagging", &bucket).await?;
+    let tags = state.buckets.get_bucket_tagging(&bucket).await?;
+    if tags.is_empty() {
+        return Err(S3Error::no_such_tag_set(&bucket));
+    }
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/xml")],
+        to_xml(&Tagging::new(S3_XMLNS, &tags))?,
+    )
+        .into_response())
+}
+
+/// `PUT /{bucket}?tagging`: PutBucketTagging, replacing the tag set. The
+/// change is audited like other bucket configurations.
+pub async fn put_bucket_tagging(
+    state: AppState,
+    auth: AuthContext,
+    origin: RequestOrigin,
+    bucket: String,
+    body: Body,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:PutBucketTagging", &bucket).await?;
+    let body = read_body(body, MAX_DOCUMENT_SIZE).await?;
+    let tagging: Tagging = from_xml(&body)?;
+    let tags = validate_tags(tag_pairs(tagging), "Bucket", MAX_BUCKET_TAGS)?;
+    replace_bucket_tags(&state, &auth, &origin, &bucket, tags).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// `DELETE /{bucket}?tagging`: DeleteBucketTagging, which needs
+/// `s3:PutBucketTagging` as in S3.
+pub async fn delete_bucket_tagging(
+    state: AppState,
+    auth: AuthContext,
+    origin: RequestOrigin,
+    bucket: String,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:PutBucketTagging", &bucket).await?;
+    replace_bucket_tags(&state, &auth, &origin, &bucket, BTreeMap::new()).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn replace_bucket_tags(
+    state: &AppState,
+    auth: &AuthContext,
+    origin: &RequestOrigin,
+    bucket: &str,
+    tags: BTreeMap<String, String>,
+) -> S3Result<()> {
+    let before = state.buckets.get_bucket_tagging(bucket).await?;
+    let document = |tags: &BTreeMap<String, String>| to_xml(&Tagging::new(S3_XMLNS, tags));
+    let (before, after) = (document(&before)?, document(&tags)?);
+    state.buckets.put_bucket_tagging(bucket, tags).await?;
+    configuration_changed(state, auth, origin, bucket, "tagging", before, after).await;
+    Ok(())
+}
+
+fn tag_pairs(tagging: Tagging) -> impl Iterator<Item = (String, String)> {
+    tagging
+        .tag_set
+        .tags
+        .into_iter()
+        .map(|tag| (tag.key, tag.value))
+}
+
+/// Checks tags against S3's limits: at most `max` (for `kind`, `Object` or
+/// `Bucket`), keys unique, not empty and not in the reserved `aws:`
+/// namespace.
+fn validate_tags(
+    tags: impl IntoIterator<Item = (String, String)>,
+    kind: &str,
+    max: usize,
+) -> S3Result<BTreeMap<String, String>> {
+    let mut checked = BTreeMap::new();
+    for (key, value) in tags {
+        if key.is_empty() {
+            return Err(S3Error::invalid_tag(
+                "The TagKey you have provided is invalid",
+            ));
+        }
+        if key.chars().count() > MAX_TAG_KEY_LENGTH {
+            return Err(S3Error::invalid_tag(format!(
+                "The TagKey you have provided is too long, max {}",
+                MAX_TAG_KEY_LENGTH
+            )));
+        }
+        if value.chars().count() > MAX_TAG_VALUE_LENGTH {
+            return Err(S3Error::invalid_tag(format!(
+                "The TagValue you have provided is too long, max {}",
+                MAX_TAG_VALUE_LENGTH
+            )));
+        }
+        if key.starts_with("aws:") {
+            return Err(S3Error::invalid_tag(
+                "System tags cannot be added/updated by requester",
+            ));
+        }
+        if checked.insert(key, value).is_some() {
+            return Err(S3Error::invalid_tag(
+                "Cannot provide multiple Tags with the same key",
+            ));
+        }
+    }
+    if checked.len() > max {
+        return Err(S3Error::invalid_tag(format!(
+            "{} tags cannot be greater than {}",
+            kind, max
+        )));
+    }
+    Ok(checked)
+}
+
+/// `x-amz-version-id`, echoed when a version was named.
+pub fn version_headers(version_id: Option<&str>) -> S3Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    if let Some(version_id) = version_id {
+        headers.insert("x-amz-version-id", header_value(version_id)?);
+    }
+    Ok(headers)
+}
@@ -0,0 +1,69 @@
+use super::{
+    MAX_DOCUMENT_SIZE, RequestOrigin, S3Result, authorize, configuration_changed, from_xml,
+    read_body, to_xml,
+};
+use crate::error::S3Error;
+use crate::models::{AuthContext, S3_XMLNS, VersioningConfiguration, VersioningStatus};
+use crate::server::AppState;
+use axum::body::Body;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+
+/// `GET /{bucket}?versioning`: GetBucketVersioning. A bucket never
+/// versioned gets a configuration without `Status`, as in S3.
+pub async fn get_bucket_versioning(
+    state: AppState,
+    auth: AuthContext,
+    bucket: String,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:GetBucketVersioning", &bucket).await?;
+    let status = state.buckets.get_bucket_versioning(&bucket).await?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/xml")],
+        to_xml(&configuration(status))?,
+    )
+        .into_response())
+}
+
+/// `PUT /{bucket}?versioning`: PutBucketVersioning, enabling or suspending
+/// versioning. Once enabled it can only be suspended, never turned off.
+/// The change is audited like other bucket configurations.
+pub async fn put_bucket_versioning(
+    state: AppState,
+    auth: AuthContext,
+    origin: RequestOrigin,
+    bucket: String,
+    body: Body,
+) -> S3Result<Response> {
+    authorize(&state, &auth, "s3:PutBucketVersioning", &bucket).await?;
+    let body = read_body(body, MAX_DOCUMENT_SIZE).await?;
+    let requested: VersioningConfiguration = from_xml(&body)?;
+    if requested.mfa_delete.as_deref() == Some("Enabled") {
+        return Err(S3Error::not_implemented("MFA Delete is not implemented"));
+    }
+    let status = requested
+        .status
+        .ok_or_else(|| S3Error::malformed_xml("a VersioningConfiguration needs a Status"))?;
+    let before = state.buckets.get_bucket_versioning(&bucket).await?;
+    state.buckets.put_bucket_versioning(&bucket, status).await?;
+    configuration_changed(
+        &state,
+        &auth,
+        &origin,
+        &bucket,
+        "versioning",
+        to_xml(&configuration(before))?,
+        to_xml(&configuration(Some(status)))?,
+    )
+    .await;
+    Ok(StatusCode::OK.into_response())
+}
+
+fn configuration(status: Option<VersioningStatus>) -> VersioningConfiguration {
+    VersioningConfiguration {
+        xmlns: Some(S3_XMLNS.to_string()),
+        status,
+        mfa_delete: None,
+    }
+}
@@ -0,0 +1,450 @@
+//! `Content-Encoding: aws-chunked` request bodies, as sent by SDKs that sign
+//! a streamed upload chunk by chunk (`STREAMING-AWS4-HMAC-SHA256-PAYLOAD`):
+//!
+//! ```text
+//! 10000;chunk-signature=ad80c730a21e5b8d04586a2213dd63b9a0e99e0e2307b0ade35a65485a288648\r\n
+//! <65536 bytes>\r\n
+//! 0;chunk-signature=b6c6ea8a5354eaf15b3cb7646744f4275b71ea724fed81ceb9323e279d449df9\r\n
+//! \r\n
+//! ```
+//!
+//! With `-TRAILER` payloads, trailing headers follow the final chunk, ended
+//! by an empty line: the checksum announced in `x-amz-trailer` and, when the
+//! chunks are signed, `x-amz-trailer-signature`. Unsigned chunks
+//! (`STREAMING-UNSIGNED-PAYLOAD-TRAILER`) have only their size in the header
+//! line:
+//!
+//! ```text
+//! 10000\r\n
+//! <65536 bytes>\r\n
+//! 0\r\n
+//! x-amz-checksum-crc32c:sOO8/Q==\r\n
+//! \r\n
+//! ```
+
+use crate::api::digest::{self, CHECKSUM_HEADER_PREFIX};
+use crate::checksum::{ChecksumAlgorithm, ChecksumHasher};
+use crate::crypto::constant_time_eq;
+use crate::error::S3Error;
+use crate::sigv4::ChunkSigner;
+use axum::body::{Body, BodyDataStream, Bytes};
+use axum::http::HeaderMap;
+use bytes::BytesMut;
+use futures_util::StreamExt;
+
+/// The `Content-Encoding` coding of such bodies.
+pub const CONTENT_CODING: &str = "aws-chunked";
+/// Names the trailing headers a body ends with.
+pub const TRAILER_HEADER: &str = "x-amz-trailer";
+
+const CHUNK_SIGNATURE: &str = ";chunk-signature=";
+const TRAILER_SIGNATURE: &str = "x-amz-trailer-signature";
+
+/// Longest chunk header or trailer line accepted; real ones are under 100
+/// bytes.
+const MAX_CHUNK_HEADER: usize = 1024;
+
+/// How an `aws-chunked` body is encoded, as its `x-amz-content-sha256`
+/// says.
+#[derive(Clone)]
+pub struct Payload {
+    /// Verifies the signatures of the chunks and the trailer; `None` when
+    /// the chunks are unsigned.
+    pub signer: Option<ChunkSigner>,
+    /// Whether trailing headers follow the final chunk.
+    pub trailer: bool,
+}
+
+/// The payload of an `aws-chunked` body, decoded as the body streams in:
+/// each chunk is passed on once its signature is verified, so no more than
+/// a chunk is held in memory at a time. The payload must have the length
+/// declared in `x-amz-decoded-content-length`, and match the checksum of a
+/// trailer; the body fails with the [`S3Error`] of the first malformed
+/// chunk, signature that does not match or checksum that does not.
+pub fn decode(headers: &HeaderMap, payload: Payload, body: Body) -> Result<Body, S3Error> {
+    let decoded_length = headers
+        .get("x-amz-decoded-content-length")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .ok_or_else(|| {
+            S3Error::invalid_request(
+                "Missing required header for this request: x-amz-decoded-content-length",
+            )
+        })?;
+    let checksum = match payload.trailer {
+        true => trailer_checksum(headers)?,
+        false => None,
+    };
+    let decoder = Decoder {
+        body: body.into_data_stream(),
+        buffer: BytesMut::new(),
+        signer: payload.signer,
+        trailer: payload.trailer,
+        checksum: checksum.map(|algorithm| (algorithm, algorithm.hasher())),
+        remaining: decoded_length,
+        done: false,
+    };
+    let chunks = futures_util::stream::unfold(decoder, |mut decoder| async move {
+        let chunk = match decoder.next_chunk().await {
+            Ok(chunk) => Ok(chunk?),
+            Err(e) => {
+                decoder.done = true;
+                Err(e)
+            }
+        };
+        Some((chunk, decoder))
+    });
+    Ok(Body::from_stream(chunks))
+}
+
+/// The algorithm of the checksum `x-amz-trailer` announces, if any. Other
+/// trailers are not supported, and a checksum is sent either as a header
+/// or as a trailer.
+fn trailer_checksum(headers: &HeaderMap) -> Result<Option<ChecksumAlgorithm>, S3Error> {
+    let Some(value) = headers.get(TRAILER_HEADER) else {
+        return Ok(None);
+    };
+    let value = value.to_str().map_err(|_| {
+        S3Error::invalid_request(format!("Value for {} is invalid.", TRAILER_HEADER))
+    })?;
+    let mut checksum = None;
+    for name in value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let algorithm = name
+            .to_ascii_lowercase()
+            .strip_prefix(CHECKSUM_HEADER_PREFIX)
+            .and_then(ChecksumAlgorithm::parse)
+            .ok_or_else(|| {
+                S3Error::invalid_request(format!("The {} trailer is not supported", name))
+            })?;
+        if checksum.is_some() || digest::checksum(headers)?.is_some() {
+            return Err(S3Error::invalid_request(
+                "Expecting a single x-amz-checksum- header. Multiple checksum Types are not \
+                 allowed.",
+            ));
+        }
+        checksum = Some(algorithm);
+    }
+    Ok(checksum)
+}
+
+struct Decoder {
+    body: BodyDataStream,
+    /// Received but not yet decoded.
+    buffer: BytesMut,
+    /// `None` for unsigned chunks.
+    signer: Option<ChunkSigner>,
+    /// Whether trailing headers follow the final chunk.
+    trailer: bool,
+    /// The checksum the trailer carries, and the hasher of the payload.
+    checksum: Option<(ChecksumAlgorithm, ChecksumHasher)>,
+    /// Payload bytes still to come.
+    remaining: u64,
+    /// After the final chunk, or an error.
+    done: bool,
+}
+
+impl Decoder {
+    /// The data of the next chunk, `None` after the final (empty) one.
+    async fn next_chunk(&mut self) -> Result<Option<Bytes>, S3Error> {
+        if self.done {
+            return Ok(None);
+        }
+        let line = self.line().await?;
+        let (size, signature) = std::str::from_utf8(&line)
+            .ok()
+            .and_then(|line| match &self.signer {
+                Some(_) => line
+                    .split_once(CHUNK_SIGNATURE)
+                    .map(|(size, signature)| (size, Some(signature.to_string()))),
+                // Chunk extensions carry nothing to check.
+                None => line.split(';').next().map(|size| (size, None)),
+            })
+            .and_then(|(size, signature)| Some((usize::from_str_radix(size, 16).ok()?, signature)))
+            .ok_or_else(invalid_chunk_header)?;
+        if size as u64 > self.remaining {
+            return Err(S3Error::incomplete_body());
+        }
+        // The trailer follows the final chunk's header line right away.
+        let chunk = if size == 0 && self.trailer {
+            BytesMut::new()
+        } else {
+            while self.buffer.len() < size + 2 {
+                self.fill().await?;
+            }
+            let mut chunk = self.buffer.split_to(size + 2);
+            if !chunk.ends_with(b"\r\n") {
+                return Err(S3Error::invalid_request(
+                    "Chunk data is not followed by CRLF",
+                ));
+            }
+            chunk.truncate(size);
+            chunk
+        };
+        if let (Some(signer), Some(signature)) = (&mut self.signer, signature) {
+            let string_to_sign = signer.string_to_sign(&chunk);
+            if !constant_time_eq(signer.sign(&chunk).as_bytes(), signature.as_bytes()) {
+                return Err(S3Error::signature_does_not_match(&string_to_sign));
+            }
+        }
+        if size == 0 {
+            self.done = true;
+            if self.remaining != 0 {
+                return Err(S3Error::incomplete_body());
+            }
+            if self.trailer {
+                self.check_trailer().await?;
+            }
+            return Ok(None);
+        }
+        self.remaining -= size as u64;
+        if let Some((_, hasher)) = &mut self.checksum {
+            hasher.update(&chunk);
+        }
+        Ok(Some(chunk.freeze()))
+    }
+
+    /// Reads the trailing headers up to the empty line ending the body (or
+    /// its end), and checks them: their signature, if the chunks are signed,
+    /// and the checksum of the payload, if one was announced.
+    async fn check_trailer(&mut self) -> Result<(), S3Error> {
+        let mut trailer = String::new();
+        let mut signature = None;
+        let mut checksum = None;
+        loop {
+            if self.buffer.is_empty() && !self.read().await? {
+                break;
+            }
+            let line = self.line().await?;
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = std::str::from_utf8(&line)
+                .ok()
+                .and_then(|line| line.split_once(':'))
+                .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+                .ok_or_else(|| S3Error::invalid_request("Invalid trailer in aws-chunked body"))?;
+            if name == TRAILER_SIGNATURE {
+                signature = Some(value);
+                continue;
+            }
+            trailer.push_str(&format!("{}:{}\n", name, value));
+            if self
+                .checksum
+                .as_ref()
+                .is_some_and(|(algorithm, _)| algorithm.header() == name)
+            {
+                checksum = Some(value);
+            }
+        }
+        if let Some(signer) = &self.signer {
+            let string_to_sign = signer.trailer_string_to_sign(trailer.as_bytes());
+            let matches = signature.is_some_and(|signature| {
+                constant_time_eq(
+                    signer.sign_trailer(trailer.as_bytes()).as_bytes(),
+                    signature.as_bytes(),
+                )
+            });
+            if !matches {
+                return Err(S3Error::signature_does_not_match(&string_to_sign));
+            }
+        }
+        if let Some((algorithm, hasher)) = self.checksum.take() {
+            let Some(expected) = checksum else {
+                return Err(S3Error::invalid_request(format!(
+                    "The {} trailer announced in {} was not sent",
+                    algorithm.header(),
+                    TRAILER_HEADER
+                )));
+            };
+            if crate::base64::encode(&hasher.finish()) != expected {
+                return Err(S3Error::checksum_mismatch(algorithm.name()));
+            }
+        }
+        Ok(())
+    }
+
+    /// The next line of the body, without its CRLF.
+    async fn line(&mut self) -> Result<BytesMut, S3Error> {
+        let end = loop {
+            if let Some(end) = self.buffer.windows(2).position(|w| w == b"\r\n") {
+                break end;
+            }
+            if self.buffer.len() > MAX_CHUNK_HEADER {
+                return Err(invalid_chunk_header());
+            }
+            self.fill().await?;
+        };
+        let mut line = self.buffer.split_to(end + 2);
+        line.truncate(end);
+        Ok(line)
+    }
+
+    /// Reads more of the body into the buffer; a body ending before the
+    /// final chunk is incomplete.
+    async fn fill(&mut self) -> Result<(), S3Error> {
+        match self.read().await? {
+            true => Ok(()),
+            false => Err(S3Error::incomplete_body()),
+        }
+    }
+
+    /// Reads more of the body into the buffer; `false` at its end.
+    async fn read(&mut self) -> Result<bool, S3Error> {
+        match self.body.next().await {
+            Some(data) => {
+                self.buffer.extend_from_slice(&data?);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+fn invalid_chunk_header() -> S3Error {
+    S3Error::invalid_request("Invalid chunk header in aws-chunked body")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::read_body;
+    use crate::sigv4::{SERVICE, parse_amz_date};
+    use axum::http::HeaderValue;
+
+    // The STREAMING-AWS4-HMAC-SHA256-PAYLOAD-TRAILER example of the S3
+    // SigV4 documentation: 66560 bytes of `a` in chunks of 64 KiB and 1 KiB.
+    const SECRET_KEY: &str = "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY";
+    const SEED: &str = "106e2a8a18243abcf37539882f36619c00e2dfc72633413f02d3b74544bfeb8e";
+    const SIZE: usize = 66560;
+    const CHUNK_SIGNATURES: [(usize, &str); 3] = [
+        (
+            65536,
+            "b474d8862b1487a5145d686f57f013e54db672cee1c953b3010fb58501ef5aa2",
+        ),
+        (
+            1024,
+            "1c1344b170168f8e65b41376b44b20fe354e373826ccbbe2c1d40a8cae51e5c7",
+        ),
+        (
+            0,
+            "2ca2aba2005185cf7159c6277faf83795951dd77a3a99e6e65d5c9f85863f992",
+        ),
+    ];
+    const TRAILER_SIGNATURE: &str =
+        "d81f82fc3505edab99d459891051a732e8730629a2e4a59689829ca17fe2e435";
+
+    fn signed() -> Payload {
+        let time = parse_amz_date("20130524T000000Z").unwrap();
+        Payload {
+            signer: Some(ChunkSigner::new(
+                SECRET_KEY,
+                time,
+                "us-east-1",
+                SERVICE,
+                SEED,
+            )),
+            trailer: true,
+        }
+    }
+
+    fn unsigned() -> Payload {
+        Payload {
+            signer: None,
+            trailer: true,
+        }
+    }
+
+    fn headers(trailer: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-amz-decoded-content-length", HeaderValue::from(SIZE));
+        headers.insert(TRAILER_HEADER, HeaderValue::from_static(trailer));
+        headers
+    }
+
+    /// The example body, its chunk headers as `header` makes them from the
+    /// chunk size and `trailer` after the final chunk.
+    fn example(header: impl Fn(usize) -> String, trailer: &str) -> Body {
+        let mut body = Vec::new();
+        for size in [65536, 1024] {
+            body.extend_from_slice(header(size).as_bytes());
+            body.resize(body.len() + size, b'a');
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(header(0).as_bytes());
+        body.extend_from_slice(trailer.as_bytes());
+        Body::from(body)
+    }
+
+    fn signed_header(size: usize) -> String {
+        let (_, signature) = CHUNK_SIGNATURES.iter().find(|(s, _)| *s == size).unwrap();
+        format!("{:x};chunk-signature={}\r\n", size, signature)
+    }
+
+    fn unsigned_header(size: usize) -> String {
+        format!("{:x}\r\n", size)
+    }
+
+    async fn decoded(headers: &HeaderMap, payload: Payload, body: Body) -> Result<Bytes, S3Error> {
+        read_body(decode(headers, payload, body).unwrap(), SIZE).await
+    }
+
+    #[tokio::test]
+    async fn signed_trailers_match_the_aws_example() {
+        let headers = headers("x-amz-checksum-crc32c");
+        let trailer = |signature: &str| {
+            format!(
+                "x-amz-checksum-crc32c:sOO8/Q==\r\nx-amz-trailer-signature:{}\r\n\r\n",
+                signature
+            )
+        };
+        let body = example(signed_header, &trailer(TRAILER_SIGNATURE));
+        let data = decoded(&headers, signed(), body).await.unwrap();
+        assert_eq!(data, vec![b'a'; SIZE]);
+
+        let body = example(signed_header, &trailer(&"0".repeat(64)));
+        let error = decoded(&headers, signed(), body).await.unwrap_err();
+        assert_eq!(error.code, "SignatureDoesNotMatch");
+
+        // The signature covers the checksum.
+        let body = example(
+            signed_header,
+            &trailer(TRAILER_SIGNATURE).replace("sOO8/Q==", "AAAAAA=="),
+        );
+        let error = decoded(&headers, signed(), body).await.unwrap_err();
+        assert_eq!(error.code, "SignatureDoesNotMatch");
+    }
+
+    #[tokio::test]
+    async fn unsigned_trailers_carry_the_checksum() {
+        let headers = headers("x-amz-checksum-crc32c");
+        let body = example(unsigned_header, "x-amz-checksum-crc32c:sOO8/Q==\r\n\r\n");
+        let data = decoded(&headers, unsigned(), body).await.unwrap();
+        assert_eq!(data, vec![b'a'; SIZE]);
+
+        let body = example(unsigned_header, "x-amz-checksum-crc32c:AAAAAA==\r\n\r\n");
+        let error = decoded(&headers, unsigned(), body).await.unwrap_err();
+        assert_eq!(error.code, "BadDigest");
+
+        let body = example(unsigned_header, "\r\n");
+        let error = decoded(&headers, unsigned(), body).await.unwrap_err();
+        assert_eq!(error.code, "InvalidRequest");
+    }
+
+    #[test]
+    fn only_checksum_trailers_are_supported() {
+        let body = || example(unsigned_header, "");
+        let error = decode(&headers("x-amz-meta-color"), unsigned(), body())
+            .err()
+            .unwrap();
+        assert_eq!(error.code, "InvalidRequest");
+
+        let mut headers = headers("x-amz-checksum-crc32c");
+        headers.insert("x-amz-checksum-crc32", HeaderValue::from_static("AAAAAA=="));
+        let error = decode(&headers, unsigned(), body()).err().unwrap();
+        assert_eq!(error.code, "InvalidRequest");
+    }
+}
@@ -0,0 +1,188 @@
+//! Token buckets limiting the bandwidth of object downloads and uploads,
+//! per connection and per access key, and the cap on concurrent part
+//! uploads (see [`BandwidthConfig`](crate::config::BandwidthConfig)).
+
+use crate::storage::ObjectReader;
+use axum::body::Body;
+use bytes::BytesMut;
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+
+/// Downloads are sent in pieces of this size, each taking its tokens
+/// before it is sent.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Downloads without limits are read in larger pieces.
+const UNLIMITED_CHUNK_SIZE: usize = 256 * 1024;
+
+/// A token bucket holding at most one second's worth of bytes. The rate is
+/// given on every [`take`](TokenBucket::take), so config reloads apply to
+/// new transfers without replacing the buckets.
+#[derive(Debug, Default)]
+pub struct TokenBucket {
+    state: Mutex<TokenState>,
+}
+
+#[derive(Debug, Default)]
+struct TokenState {
+    tokens: f64,
+    /// `None` until the first take, when the bucket starts full.
+    updated: Option<Instant>,
+}
+
+impl TokenBucket {
+    /// Takes `bytes` tokens at `rate` bytes per second, waiting until they
+    /// have been refilled. Tokens are taken up front, so concurrent takers
+    /// wait their turn behind each other.
+    pub async fn take(&self, bytes: usize, rate: u64) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            let rate = rate as f64;
+            let tokens = match state.updated {
+                Some(updated) => {
+                    (state.tokens + now.duration_since(updated).as_secs_f64() * rate).min(rate)
+                }
+                None => rate,
+            };
+            state.tokens = tokens - bytes as f64;
+            state.updated = Some(now);
+            (-state.tokens / rate).max(0.0)
+        };
+        if wait > 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+        }
+    }
+}
+
+/// The token buckets of a client connection, attached to its requests as
+/// an extension by [`server::serve`](crate::server).
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionBandwidth {
+    pub download: Arc<TokenBucket>,
+    pub upload: Arc<TokenBucket>,
+}
+
+/// The token buckets of access keys, and the part uploads in progress per
+/// upload ID.
+#[derive(Debug, Default)]
+pub struct Bandwidth {
+    downloads: KeyBuckets,
+    uploads: KeyBuckets,
+    parts: Mutex<HashMap<String, usize>>,
+}
+
+#[derive(Debug, Default)]
+struct KeyBuckets(Mutex<HashMap<String, Arc<TokenBucket>>>);
+
+impl KeyBuckets {
+    /// The bucket of `access_key`. Buckets of keys without a transfer in
+    /// progress are dropped when a new key is added, as they refill within
+    /// a second anyway.
+    fn get(&self, access_key: &str) -> Arc<TokenBucket> {
+        let mut access_keys = self.0.lock().unwrap();
+        if let Some(bucket) = access_keys.get(access_key) {
+            return bucket.clone();
+        }
+        access_keys.retain(|_, bucket| Arc::strong_count(bucket) > 1);
+        let bucket = Arc::new(TokenBucket::default());
+        access_keys.insert(access_key.to_string(), bucket.clone());
+        bucket
+    }
+}
+
+impl Bandwidth {
+    /// The bucket shared by the downloads of `access_key`.
+    pub fn download(&self, access_key: &str) -> Arc<TokenBucket> {
+        self.downloads.get(access_key)
+    }
+
+    /// The bucket shared by the uploads of `access_key`.
+    pub fn upload(&self, access_key: &str) -> Arc<TokenBucket> {
+        self.uploads.get(access_key)
+    }
+
+    /// Counts a part upload to `upload_id` as in progress until the slot is
+    /// dropped; `None` when `max` of them already are.
+    pub fn start_part(&self, upload_id: &str, max: usize) -> Option<PartSlot<'_>> {
+        let mut parts = self.parts.lock().unwrap();
+        let count = parts.entry(upload_id.to_string()).or_default();
+        if *count >= max {
+            return None;
+        }
+        *count += 1;
+        Some(PartSlot {
+            bandwidth: self,
+            upload_id: upload_id.to_string(),
+        })
+    }
+}
+
+/// A part upload in progress, see [`Bandwidth::start_part`].
+pub struct PartSlot<'a> {
+    bandwidth: &'a Bandwidth,
+    upload_id: String,
+}
+
+impl Drop for PartSlot<'_> {
+    fn drop(&mut self) {
+        let mut parts = self.bandwidth.parts.lock().unwrap();
+        if let Some(count) = parts.get_mut(&self.upload_id) {
+            *count -= 1;
+            if *count == 0 {
+                parts.remove(&self.upload_id);
+            }
+        }
+    }
+}
+
+/// `data` as a body read and sent no faster than every one of `limits` (a
+/// bucket and its rate in bytes per second) allows, in pieces that each
+/// take their tokens before the next one is read.
+pub fn throttle(data: ObjectReader, limits: Vec<(Arc<TokenBucket>, u64)>) -> Body {
+    let size = if limits.is_empty() {
+        UNLIMITED_CHUNK_SIZE
+    } else {
+        CHUNK_SIZE
+    };
+    let chunks = futures_util::stream::unfold(Some((data, limits)), move |state| async move {
+        let (mut data, limits) = state?;
+        let mut chunk = BytesMut::with_capacity(size);
+        match data.read_buf(&mut chunk).await {
+            Ok(0) => return None,
+            Ok(_) => {}
+            // Ends the body, cutting the response short.
+            Err(e) => return Some((Err(e), None)),
+        }
+        for (bucket, rate) in &limits {
+            bucket.take(chunk.len(), *rate).await;
+        }
+        Some((Ok(chunk.freeze()), Some((data, limits))))
+    });
+    Body::from_stream(chunks)
+}
+
+/// A request `body` read no faster than `limits` allow: each piece takes
+/// its tokens before the next one is read, so a fast client is slowed down
+/// by TCP flow control rather than buffered.
+pub fn throttle_upload(body: Body, limits: Vec<(Arc<TokenBucket>, u64)>) -> Body {
+    if limits.is_empty() {
+        return body;
+    }
+    let chunks = futures_util::stream::unfold(
+        (body.into_data_stream(), limits),
+        |(mut stream, limits)| async move {
+            let chunk = stream.next().await?;
+            if let Ok(chunk) = &chunk {
+                for (bucket, rate) in &limits {
+                    bucket.take(chunk.len(), *rate).await;
+                }
+            }
+            Some((chunk, (stream, limits)))
+        },
+    );
+    Body::from_stream(chunks)
+}
@@ -0,0 +1,310 @@
+//! The additional checksums of S3 (`x-amz-checksum-*`): CRC32, CRC32C
+//! (Castagnoli), SHA-1 and SHA-256 of object data, sent big-endian and
+//! base64-encoded. Newer SDKs send one with every upload and check the one
+//! returned on downloads.
+
+use crate::crypto::Sha256;
+
+/// An algorithm of `x-amz-checksum-*`, named as in
+/// `x-amz-sdk-checksum-algorithm` and `x-amz-checksum-algorithm`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Crc32,
+    Crc32c,
+    Sha1,
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    pub const ALL: [ChecksumAlgorithm; 4] = [
+        ChecksumAlgorithm::Crc32,
+        ChecksumAlgorithm::Crc32c,
+        ChecksumAlgorithm::Sha1,
+        ChecksumAlgorithm::Sha256,
+    ];
+
+    /// Names are case-insensitive, as in S3.
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Crc32 => "CRC32",
+            ChecksumAlgorithm::Crc32c => "CRC32C",
+            ChecksumAlgorithm::Sha1 => "SHA1",
+            ChecksumAlgorithm::Sha256 => "SHA256",
+        }
+    }
+
+    /// The header carrying a checksum of the algorithm.
+    pub fn header(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Crc32 => "x-amz-checksum-crc32",
+            ChecksumAlgorithm::Crc32c => "x-amz-checksum-crc32c",
+            ChecksumAlgorithm::Sha1 => "x-amz-checksum-sha1",
+            ChecksumAlgorithm::Sha256 => "x-amz-checksum-sha256",
+        }
+    }
+
+    /// Length of a checksum in bytes, before base64.
+    pub fn digest_len(self) -> usize {
+        match self {
+            ChecksumAlgorithm::Crc32 | ChecksumAlgorithm::Crc32c => 4,
+            ChecksumAlgorithm::Sha1 => 20,
+            ChecksumAlgorithm::Sha256 => 32,
+        }
+    }
+
+    pub fn hasher(self) -> ChecksumHasher {
+        match self {
+            ChecksumAlgorithm::Crc32 => ChecksumHasher::Crc32(!0),
+            ChecksumAlgorithm::Crc32c => ChecksumHasher::Crc32c(!0),
+            ChecksumAlgorithm::Sha1 => ChecksumHasher::Sha1(Sha1::new()),
+            ChecksumAlgorithm::Sha256 => ChecksumHasher::Sha256(Sha256::new()),
+        }
+    }
+
+    /// The base64 checksum of `data`.
+    pub fn checksum(self, data: &[u8]) -> String {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        crate::base64::encode(&hasher.finish())
+    }
+}
+
+/// A checksum as stored with an object or part and sent in headers: base64,
+/// followed by `-` and the number of parts for the composite checksum of a
+/// multipart upload (see [`composite`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: ChecksumAlgorithm,
+    pub value: String,
+}
+
+impl Checksum {
+    /// Base64 has no `-`, so only composite checksums have one.
+    pub fn is_composite(&self) -> bool {
+        self.value.contains('-')
+    }
+
+    /// `FULL_OBJECT` or `COMPOSITE`, as S3 reports it in
+    /// `x-amz-checksum-type` and `ChecksumType`.
+    pub fn checksum_type(&self) -> &'static str {
+        if self.is_composite() {
+            "COMPOSITE"
+        } else {
+            "FULL_OBJECT"
+        }
+    }
+}
+
+/// The checksum of a multipart upload as S3 computes it from the part
+/// checksums: the checksum of their concatenated bytes, followed by `-` and
+/// the number of parts. `None` if a part has no (valid) checksum.
+pub fn composite<'a>(
+    algorithm: ChecksumAlgorithm,
+    parts: impl ExactSizeIterator<Item = Option<&'a str>>,
+) -> Option<String> {
+    let count = parts.len();
+    let mut hasher = algorithm.hasher();
+    for part in parts {
+        let digest = crate::base64::decode(part?)?;
+        hasher.update(&digest);
+    }
+    Some(format!(
+        "{}-{}",
+        crate::base64::encode(&hasher.finish()),
+        count
+    ))
+}
+
+/// A checksum being computed over data that arrives in pieces.
+pub enum ChecksumHasher {
+    Crc32(u32),
+    Crc32c(u32),
+    Sha1(Sha1),
+    Sha256(Sha256),
+}
+
+impl ChecksumHasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            ChecksumHasher::Crc32(crc) => *crc = crc_update(&CRC32_TABLE, *crc, data),
+            ChecksumHasher::Crc32c(crc) => *crc = crc_update(&CRC32C_TABLE, *crc, data),
+            ChecksumHasher::Sha1(hasher) => hasher.update(data),
+            ChecksumHasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    /// The checksum, big-endian.
+    pub fn finish(self) -> Vec<u8> {
+        match self {
+            ChecksumHasher::Crc32(crc) | ChecksumHasher::Crc32c(crc) => {
+                (!crc).to_be_bytes().to_vec()
+            }
+            ChecksumHasher::Sha1(hasher) => hasher.finish().to_vec(),
+            ChecksumHasher::Sha256(hasher) => hasher.finish().to_vec(),
+        }
+    }
+}
+
+/// Lookup tables of the reflected CRC-32 polynomials of CRC32 (ISO-HDLC, as
+/// in zlib and gzip) and CRC32C.
+pub static CRC32_TABLE: [u32; 256] = crc_table(0xedb8_8320);
+static CRC32C_TABLE: [u32; 256] = crc_table(0x82f6_3b78);
+
+/// The lookup table of a reflected CRC-32 `polynomial`, for [`crc_update`].
+pub const fn crc_table(polynomial: u32) -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ polynomial
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+}
+
+/// Feeds `data` into `crc`, kept inverted: start from `!0` and invert the
+/// result.
+pub fn crc_update(table: &[u32; 256], crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, &byte| {
+        table[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+const SHA1_H0: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+const BLOCK_SIZE: usize = 64;
+
+/// Incremental SHA-1 (FIPS 180-4). Only used for checksums of data, never
+/// for anything that has to resist collisions.
+#[derive(Clone)]
+pub struct Sha1 {
+    state: [u32; 5],
+    buffer: Vec<u8>,
+    length: u64,
+}
+
+impl Default for Sha1 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sha1 {
+    pub fn new() -> Self {
+        Self {
+            state: SHA1_H0,
+            buffer: Vec::with_capacity(BLOCK_SIZE),
+            length: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        if !self.buffer.is_empty() {
+            let take = (BLOCK_SIZE - self.buffer.len()).min(data.len());
+            self.buffer.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.buffer.len() < BLOCK_SIZE {
+                return;
+            }
+            let block: [u8; BLOCK_SIZE] = self.buffer[..].try_into().unwrap();
+            sha1_compress(&mut self.state, &block);
+            self.buffer.clear();
+        }
+        let mut blocks = data.chunks_exact(BLOCK_SIZE);
+        for block in &mut blocks {
+            sha1_compress(&mut self.state, block.try_into().unwrap());
+        }
+        self.buffer.extend_from_slice(blocks.remainder());
+    }
+
+    pub fn finish(mut self) -> [u8; 20] {
+        let bit_length = self.length.wrapping_mul(8);
+        // Padded as SHA-256 is.
+        let padding = (BLOCK_SIZE + 55 - self.buffer.len()) % BLOCK_SIZE + 1;
+        let mut tail = vec![0u8; padding];
+        tail[0] = 0x80;
+        self.update(&tail);
+        self.update(&bit_length.to_be_bytes());
+        debug_assert!(self.buffer.is_empty());
+        let mut digest = [0u8; 20];
+        for (out, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            out.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+}
+
+fn sha1_compress(state: &mut [u32; 5], block: &[u8; BLOCK_SIZE]) {
+    let mut w = [0u32; 80];
+    for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes(bytes.try_into().unwrap());
+    }
+    for i in 16..80 {
+        w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e] = *state;
+    for (i, word) in w.into_iter().enumerate() {
+        let (f, k) = match i {
+            0..20 => ((b & c) | (!b & d), 0x5a827999),
+            20..40 => (b ^ c ^ d, 0x6ed9eba1),
+            40..60 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+            _ => (b ^ c ^ d, 0xca62c1d6),
+        };
+        let temp = a
+            .rotate_left(5)
+            .wrapping_add(f)
+            .wrapping_add(e)
+            .wrapping_add(k)
+            .wrapping_add(word);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+    for (state, value) in state.iter_mut().zip([a, b, c, d, e]) {
+        *state = state.wrapping_add(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checksum(algorithm: ChecksumAlgorithm, data: &[u8]) -> String {
+        let mut hasher = algorithm.hasher();
+        let (head, tail) = data.split_at(data.len() / 2);
+        hasher.update(head);
+        hasher.update(tail);
+        hex::encode(hasher.finish())
+    }
+
+    #[test]
+    fn check_values() {
+        assert_eq!(checksum(ChecksumAlgorithm::Crc32, b"123456789"), "cbf43926");
+        assert_eq!(
+            checksum(ChecksumAlgorithm::Crc32c, b"123456789"),
+            "e3069283"
+        );
+        assert_eq!(
+            checksum(ChecksumAlgorithm::Sha1, b"abc"),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! Gzip encoding for generated XML responses: a single DEFLATE block with the
+//! fixed Huffman codes and greedy LZ77 matching. Listings are highly
+//! repetitive, so this gets most of what a full encoder would.
+
+use crate::checksum::{CRC32_TABLE, crc_update};
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+/// How many earlier positions with the same hash are tried per match.
+const MAX_CHAIN: usize = 32;
+
+/// Base lengths of the length codes 257..=285, followed by their extra bits.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+fn crc32(data: &[u8]) -> u32 {
+    !crc_update(&CRC32_TABLE, !0, data)
+}
+
+/// Whether an `Accept-Encoding` value allows `coding` (or `*`) with a
+/// non-zero quality.
+pub fn accepts(accept_encoding: &str, coding: &str) -> bool {
+    accept_encoding.split(',').any(|entry| {
+        let mut parts = entry.split(';').map(str::trim);
+        let name = parts.next().unwrap_or_default();
+        let quality = parts
+            .find_map(|p| p.strip_prefix("q=").or_else(|| p.strip_prefix("Q=")))
+            .map(|q| q.parse::<f32>().unwrap_or(0.0))
+            .unwrap_or(1.0);
+        quality > 0.0 && (name.eq_ignore_ascii_case(coding) || name == "*")
+    })
+}
+
+/// Whether an `Accept-Encoding` value allows gzip (`gzip`, `x-gzip` or `*`
+/// with a non-zero quality).
+pub fn accepts_gzip(accept_encoding: &str) -> bool {
+    accepts(accept_encoding, "gzip") || accepts(accept_encoding, "x-gzip")
+}
+
+/// Compresses `data` into a gzip member (RFC 1952).
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    // No file name or modification time; OS "unknown".
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+    out.extend(deflate(data));
+    out.extend(crc32(data).to_le_bytes());
+    out.extend((data.len() as u32).to_le_bytes());
+    out
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    /// Writes the low `count` bits of `value`, least significant first.
+    fn write(&mut self, value: u32, count: u32) {
+        self.bits |= u64::from(value) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are packed most significant bit first.
+    fn write_code(&mut self, code: u32, length: u32) {
+        self.write(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+
+    fn literal(&mut self, symbol: u16) {
+        let symbol = u32::from(symbol);
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn copy(&mut self, length: usize, distance: usize) {
+        let code = LENGTH_BASE
+            .iter()
+            .rposition(|&b| usize::from(b) <= length)
+            .unwrap();
+        self.literal(257 + code as u16);
+        self.write(
+            (length - usize::from(LENGTH_BASE[code])) as u32,
+            u32::from(LENGTH_EXTRA[code]),
+        );
+        let code = DISTANCE_BASE
+            .iter()
+            .rposition(|&b| usize::from(b) <= distance)
+            .unwrap();
+        self.write_code(code as u32, 5);
+        self.write(
+            (distance - usize::from(DISTANCE_BASE[code])) as u32,
+            u32::from(DISTANCE_EXTRA[code]),
+        );
+    }
+}
+
+fn hash(data: &[u8]) -> usize {
+    let v = u32::from(data[0]) << 16 | u32::from(data[1]) << 8 | u32::from(data[2]);
+    (v.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Records `pos` as the latest position with its 3-byte hash.
+fn insert(data: &[u8], pos: usize, head: &mut [usize], prev: &mut [usize]) {
+    if pos + MIN_MATCH <= data.len() {
+        let h = hash(&data[pos..]);
+        prev[pos] = head[h];
+        head[h] = pos;
+    }
+}
+
+/// Raw DEFLATE stream (RFC 1951) of one final, fixed-Huffman block.
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {
+        out: Vec::with_capacity(data.len() / 4),
+        bits: 0,
+        count: 0,
+    };
+    writer.write(1, 1); // BFINAL
+    writer.write(1, 2); // BTYPE = fixed Huffman
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; data.len()];
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let (mut best_len, mut best_dist) = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let mut candidate = head[hash(&data[pos..])];
+            let max_len = MAX_MATCH.min(data.len() - pos);
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || pos - candidate > WINDOW_SIZE {
+                    break;
+                }
+                let len = data[candidate..]
+                    .iter()
+                    .zip(&data[pos..pos + max_len])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best_len {
+                    (best_len, best_dist) = (len, pos - candidate);
+                    if len == max_len {
+                        break;
+                    }
+                }
+                candidate = prev[candidate];
+            }
+        }
+        if best_len >= MIN_MATCH {
+            writer.copy(best_len, best_dist);
+            for p in pos..pos + best_len {
+                insert(data, p, &mut head, &mut prev);
+            }
+            pos += best_len;
+        } else {
+            writer.literal(u16::from(data[pos]));
+            insert(data, pos, &mut head, &mut prev);
+            pos += 1;
+        }
+    }
+    writer.literal(256); // end of block
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepted_codings_need_a_non_zero_quality() {
+        assert!(accepts("gzip, br;q=0.8", "br"));
+        assert!(accepts("BR", "br"));
+        assert!(accepts("*;q=0.1", "br"));
+        assert!(!accepts("gzip, br;q=0", "br"));
+        assert!(!accepts("gzip;q=0.5, deflate", "br"));
+        assert!(accepts_gzip("x-gzip"));
+        assert!(!accepts_gzip("br, gzip;q=0"));
+    }
+
+    #[test]
+    fn gzip_trailer_carries_crc32_and_length() {
+        let out = gzip(b"123456789");
+        let trailer = &out[out.len() - 8..];
+        assert_eq!(trailer[..4], 0xcbf4_3926u32.to_le_bytes());
+        assert_eq!(trailer[4..], 9u32.to_le_bytes());
+    }
+}
@@ -0,0 +1,199 @@
+//! Migration of older config layouts to the current one (`config_version`).
+//! Config files are migrated in memory when they are loaded, so servers keep
+//! starting after an upgrade; `s3-clone --migrate-config <path>` writes the
+//! migrated file out.
+//!
+//! Versions:
+//! - 1: the first layout, with `server.host`, `server.port` and
+//!   `server.storage_path`, and credentials with `access_key_id`,
+//!   `secret_access_key` and unprefixed action names such as `ListBuckets`.
+//! - 2: the current layout.
+
+use serde_yaml::{Mapping, Value};
+
+/// The layout this server reads.
+pub const CONFIG_VERSION: u64 = 2;
+
+/// Action names of version 1 that have another name now.
+const RENAMED_ACTIONS: [(&str, &str); 4] = [
+    ("ListBuckets", "s3:ListAllMyBuckets"),
+    ("ListObjects", "s3:ListBucket"),
+    ("ListObjectsV2", "s3:ListBucket"),
+    ("UploadPart", "s3:PutObject"),
+];
+
+/// The `config_version` of a parsed config file. Files without one are
+/// version 1 if they have a field only that layout had, else current.
+pub fn version(config: &Value) -> Result<u64, String> {
+    match config.get("config_version") {
+        Some(version) => version
+            .as_u64()
+            .filter(|version| *version >= 1)
+            .ok_or_else(|| "config_version must be a positive integer".to_string()),
+        None => {
+            let old_server = config
+                .get("server")
+                .is_some_and(|server| server.get("storage_path").is_some());
+            let old_credentials = config
+                .get("credentials")
+                .and_then(Value::as_sequence)
+                .is_some_and(|creds| creds.iter().any(|cred| cred.get("access_key_id").is_some()));
+            Ok(if old_server || old_credentials {
+                1
+            } else {
+                CONFIG_VERSION
+            })
+        }
+    }
+}
+
+/// Brings a parsed config file to [`CONFIG_VERSION`] in place and returns
+/// the version it was in. Files of a later version are refused rather than
+/// read with fields this server does not know.
+pub fn migrate(config: &mut Value) -> Result<u64, String> {
+    let from = version(config)?;
+    if from > CONFIG_VERSION {
+        return Err(format!(
+            "config_version {} is newer than this server supports ({})",
+            from, CONFIG_VERSION
+        ));
+    }
+    let Some(root) = config.as_mapping_mut() else {
+        return Err("the config file is not a YAML mapping".to_string());
+    };
+    if from < 2 {
+        migrate_v1(root)?;
+    }
+    root.insert("config_version".into(), CONFIG_VERSION.into());
+    // Sections moved or added by a migration go where the sample config
+    // has them, so written out files read like it.
+    order(root, &SECTION_ORDER);
+    Ok(from)
+}
+
+/// The order of the sections of the sample config.
+const SECTION_ORDER: [&str; 9] = [
+    "config_version",
+    "storage",
+    "region",
+    "server",
+    "credentials",
+    "default_acls",
+    "default_cors",
+    "multipart",
+    "config_reload",
+];
+
+/// Version 1 to 2: the server section is split into `storage.location` and
+/// `server.http`, credentials get their current field and action names, and
+/// the sections version 1 did not have get what that server did: no CORS,
+/// no config reload, private ACLs.
+fn migrate_v1(root: &mut Mapping) -> Result<(), String> {
+    let mut server = match root.remove("server") {
+        Some(Value::Mapping(server)) => server,
+        Some(_) => return Err("server must be a mapping".to_string()),
+        None => Mapping::new(),
+    };
+    if let Some(location) = server.remove("storage_path") {
+        let storage = section(root, "storage")?;
+        if !storage.contains_key("location") {
+            storage.insert("location".into(), location);
+        }
+    }
+    let host = server.remove("host");
+    let port = server.remove("port");
+    if !server.contains_key("http") {
+        let mut http = Mapping::new();
+        http.insert("enabled".into(), true.into());
+        http.insert("port".into(), port.unwrap_or_else(|| 8000.into()));
+        http.insert("host".into(), host.unwrap_or_else(|| "127.0.0.1".into()));
+        server.insert("http".into(), http.into());
+    }
+    root.insert("server".into(), server.into());
+
+    if let Some(Value::Sequence(credentials)) = root.get_mut("credentials") {
+        for credential in credentials.iter_mut().filter_map(Value::as_mapping_mut) {
+            rename(credential, "access_key_id", "access_key");
+            rename(credential, "secret_access_key", "secret_key");
+            order(credential, &["access_key", "secret_key"]);
+            let Some(Value::Sequence(permissions)) = credential.get_mut("permissions") else {
+                continue;
+            };
+            for permission in permissions.iter_mut().filter_map(Value::as_mapping_mut) {
+                let Some(Value::String(action)) = permission.get_mut("action") else {
+                    continue;
+                };
+                if let Some((_, renamed)) = RENAMED_ACTIONS.iter().find(|(old, _)| old == action) {
+                    *action = renamed.to_string();
+                }
+            }
+        }
+    }
+
+    default(root, "region", &[("default", "de-muc-01".into())])?;
+    default(
+        root,
+        "default_acls",
+        &[
+            ("public", false.into()),
+            ("allowed_ips", Value::Sequence(Vec::new())),
+        ],
+    )?;
+    default(
+        root,
+        "default_cors",
+        &[
+            ("allowed_origins", Value::Sequence(Vec::new())),
+            ("allowed_methods", Value::Sequence(Vec::new())),
+        ],
+    )?;
+    default(root, "multipart", &[("expiry_seconds", 86400.into())])?;
+    default(
+        root,
+        "config_reload",
+        &[
+            ("sighup", false.into()),
+            ("api", false.into()),
+            ("fsevents", false.into()),
+        ],
+    )
+}
+
+/// The mapping of a top-level section, created if missing.
+fn section<'a>(root: &'a mut Mapping, name: &str) -> Result<&'a mut Mapping, String> {
+    root.entry(name.into())
+        .or_insert_with(|| Mapping::new().into())
+        .as_mapping_mut()
+        .ok_or_else(|| format!("{} must be a mapping", name))
+}
+
+/// Sets the fields of a section that are missing.
+fn default(root: &mut Mapping, name: &str, fields: &[(&str, Value)]) -> Result<(), String> {
+    let section = section(root, name)?;
+    for (field, value) in fields {
+        if !section.contains_key(*field) {
+            section.insert((*field).into(), value.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Moves the `first` fields, those present, to the front of `mapping`.
+fn order(mapping: &mut Mapping, first: &[&str]) {
+    let mut ordered = Mapping::new();
+    for field in first {
+        if let Some(value) = mapping.remove(*field) {
+            ordered.insert((*field).into(), value);
+        }
+    }
+    ordered.extend(std::mem::take(mapping));
+    *mapping = ordered;
+}
+
+fn rename(mapping: &mut Mapping, from: &str, to: &str) {
+    if let Some(value) = mapping.remove(from)
+        && !mapping.contains_key(to)
+    {
+        mapping.insert(to.into(), value);
+    }
+}
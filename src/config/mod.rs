@@ -1,35 +1,363 @@
-use log::debug;
+mod migrate;
+
+pub use migrate::CONFIG_VERSION;
+
+use log::{debug, warn};
 use serde::Deserialize;
 use std::cmp::PartialEq;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::path::Path;
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct Config {
+    /// The layout of the file; older layouts are migrated when loaded (see
+    /// [`Config::migrated_file`]).
+    #[serde(default = "default_config_version")]
+    pub config_version: u64,
     pub storage: StorageConfig,
     pub region: RegionConfig,
+    #[serde(default)]
+    pub owner: OwnerConfig,
     pub server: ServerConfig,
+    #[serde(default)]
+    pub roles: HashMap<String, RoleConfig>,
     pub credentials: Vec<Credential>,
+    #[serde(default)]
+    pub auth: AuthConfig,
     pub default_acls: DefaultAcls,
     pub default_cors: DefaultCors,
     pub multipart: MultipartConfig,
     pub config_reload: ConfigReload,
+    #[serde(default)]
+    pub jobs: HashMap<String, JobConfig>,
+    pub admin: Option<AdminConfig>,
+    #[serde(default)]
+    pub response_headers: ResponseHeadersConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub system_logs: SystemLogsConfig,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    #[serde(default)]
+    pub features: FeaturesConfig,
+    #[serde(default)]
+    pub storage_classes: StorageClassesConfig,
+    pub mirror: Option<MirrorConfig>,
+    #[serde(default)]
+    pub warmup: WarmupConfig,
+    #[serde(default)]
+    pub bucket_tombstones: BucketTombstonesConfig,
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct StorageConfig {
     pub location: String,
+    #[serde(default)]
+    pub read_cache: ReadCacheConfig,
+    #[serde(default)]
+    pub tracing: StorageTracingConfig,
+    #[serde(default)]
+    pub eventual_consistency: EventualConsistencyConfig,
+    /// Store keys with characters some filesystems refuse or alter escaped
+    /// as `%XX` in their file names, rather than rejecting them (on
+    /// Windows). Changes the file names of existing keys containing `%`, so
+    /// it is meant for new storage locations. Read at startup only.
+    #[serde(default)]
+    pub escape_keys: bool,
+    /// Where the files of buckets and objects are kept. Read at startup only.
+    #[serde(default)]
+    pub backend: StorageBackendKind,
+    /// Whether writes are flushed to disk before they are acknowledged. Read
+    /// at startup only.
+    #[serde(default)]
+    pub durability: Durability,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Durability {
+    /// Flushing is left to the OS, so a power loss can undo the latest
+    /// writes (though never leave part of one, as files are renamed into
+    /// place).
+    #[default]
+    None,
+    /// Data and metadata files are fsynced before they are renamed into
+    /// place, and their directories after, so an acknowledged write
+    /// survives a power loss.
+    Fsync,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackendKind {
+    /// Files below `storage.location`.
+    #[default]
+    Filesystem,
+    /// Kept in memory and lost on restart, for tests and throwaway servers;
+    /// `storage.location` only names the root of the paths.
+    Memory,
+}
+
+/// Small objects kept in memory once read, so repeated GETs of the same
+/// keys are served without touching the filesystem. Read at startup only.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ReadCacheConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Larger objects are always read from disk.
+    #[serde(default = "default_read_cache_max_object_size")]
+    pub max_object_size: u64,
+    /// Total size of the cached data; least recently used objects go first.
+    #[serde(default = "default_read_cache_max_size")]
+    pub max_size: u64,
+    /// Bounds how long changes made outside the server (to files in the
+    /// bucket directories) can go unseen.
+    #[serde(default = "default_read_cache_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+impl Default for ReadCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_object_size: default_read_cache_max_object_size(),
+            max_size: default_read_cache_max_size(),
+            ttl_secs: default_read_cache_ttl_secs(),
+        }
+    }
+}
+
+fn default_read_cache_max_object_size() -> u64 {
+    64 << 10
+}
+
+fn default_read_cache_max_size() -> u64 {
+    256 << 20
+}
+
+fn default_read_cache_ttl_secs() -> u64 {
+    60
+}
+
+/// Spans around storage operations, logged with their bucket, key and
+/// duration so slow disks show up per operation. Read at startup only.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct StorageTracingConfig {
+    /// Operations taking at least this long are logged at `warn`, the
+    /// others at `trace`; 0 turns the warnings off.
+    #[serde(default = "default_slow_operation_ms")]
+    pub slow_operation_ms: u64,
+    /// Distinct keys logged as they are; further keys are logged as a hash,
+    /// so log indexes see a bounded set of values.
+    #[serde(default = "default_max_traced_keys")]
+    pub max_traced_keys: usize,
+}
+
+impl Default for StorageTracingConfig {
+    fn default() -> Self {
+        Self {
+            slow_operation_ms: default_slow_operation_ms(),
+            max_traced_keys: default_max_traced_keys(),
+        }
+    }
+}
+
+fn default_slow_operation_ms() -> u64 {
+    1000
+}
+
+fn default_max_traced_keys() -> usize {
+    10_000
+}
+
+/// Test mode simulating the eventual consistency S3 had before December
+/// 2020: for a while after a write, reads see the object as it was and
+/// listings the keys as they were. Read at startup only.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct EventualConsistencyConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// How long GET and HEAD return the previous object after an overwrite
+    /// or delete. New keys are readable at once, as they were in S3.
+    #[serde(default = "default_consistency_delay_ms")]
+    pub read_delay_ms: u64,
+    /// How long listings leave out new keys, keep deleted ones and report
+    /// overwritten ones as they were.
+    #[serde(default = "default_consistency_delay_ms")]
+    pub listing_delay_ms: u64,
+    /// Previous data is kept in memory for stale GETs only up to this size;
+    /// GETs of larger objects are always current.
+    #[serde(default = "default_consistency_max_object_size")]
+    pub max_object_size: u64,
+}
+
+impl Default for EventualConsistencyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            read_delay_ms: default_consistency_delay_ms(),
+            listing_delay_ms: default_consistency_delay_ms(),
+            max_object_size: default_consistency_max_object_size(),
+        }
+    }
+}
+
+fn default_consistency_delay_ms() -> u64 {
+    1000
+}
+
+fn default_consistency_max_object_size() -> u64 {
+    1 << 20
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct RegionConfig {
     pub default: String,
+    /// Answer requests signed for another region than their bucket's with
+    /// `301 PermanentRedirect` naming the bucket's region, so SDKs re-sign
+    /// for it. Off by default, as the region of the signature is otherwise
+    /// not checked. Applies on reload.
+    #[serde(default)]
+    pub redirect: bool,
+    /// Refuse signatures whose credential scope names a region other than
+    /// `default` or one of `allowed`, as AWS does, so SDKs configured for
+    /// the wrong region fail loudly. Off by default. Applies on reload.
+    #[serde(default)]
+    pub validate: bool,
+    /// Further regions signatures may be scoped to with `validate`, such as
+    /// those of buckets created with another `LocationConstraint`.
+    #[serde(default)]
+    pub allowed: Vec<String>,
+}
+
+impl RegionConfig {
+    /// Whether a signature scoped to `region` is accepted.
+    pub fn accepts(&self, region: &str) -> bool {
+        !self.validate || region == self.default || self.allowed.iter().any(|r| r == region)
+    }
+}
+
+/// Identity reported as the owner of buckets and objects (ListBuckets,
+/// ListObjects, ACL documents). Kept separate from credentials so every
+/// requester sees the same owner.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct OwnerConfig {
+    pub id: String,
+    pub display_name: String,
+}
+
+impl Default for OwnerConfig {
+    fn default() -> Self {
+        Self {
+            id: "s3-clone".to_string(),
+            display_name: "s3-clone".to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct ServerConfig {
     pub http: HttpConfig,
     pub https: Option<HttpsConfig>,
+    /// Deadline for S3 requests that do not send `x-s3clone-deadline-ms`.
+    /// No deadline when unset. Applies on reload.
+    #[serde(default)]
+    pub default_deadline_ms: Option<u64>,
+    /// Time a listing may spend reading metadata before it returns the
+    /// entries it has so far as a truncated page. No budget when unset.
+    /// Applies on reload.
+    #[serde(default)]
+    pub listing_time_budget_ms: Option<u64>,
+    /// Addresses or CIDR ranges of reverse proxies whose `X-Forwarded-For`
+    /// is believed when determining the client IP. Applies on reload.
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    /// Gzip listing and error responses for clients that accept it. Object
+    /// payloads are never compressed. Applies on reload.
+    #[serde(default)]
+    pub compress_responses: bool,
+    /// Buckets whose GETs and HEADs of `{key}` are answered with `{key}.br`
+    /// or `{key}.gz`, when stored and accepted by the client, as static
+    /// hosting does. Applies on reload.
+    #[serde(default)]
+    pub precompressed_variants: Vec<String>,
+    /// How `Retry-After` is computed for `503` responses. Applies on reload.
+    #[serde(default)]
+    pub retry_after: RetryAfterConfig,
+    /// Download and upload bandwidth limits. Applies on reload, to
+    /// transfers started afterwards.
+    #[serde(default)]
+    pub bandwidth: BandwidthConfig,
+}
+
+/// Limits on the rate object data is sent to and received from clients
+/// with, in bytes per second; 0 for no limit. A transfer is held to both
+/// limits of its direction that apply.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+pub struct BandwidthConfig {
+    /// Per client connection, shared by its requests.
+    #[serde(default)]
+    pub download_per_connection: u64,
+    /// Per access key, shared by all its downloads. Anonymous requests
+    /// have only the per-connection limit.
+    #[serde(default)]
+    pub download_per_access_key: u64,
+    /// Per client connection, for the bodies of PUT and POST requests on
+    /// objects.
+    #[serde(default)]
+    pub upload_per_connection: u64,
+    /// Per access key, for the same requests.
+    #[serde(default)]
+    pub upload_per_access_key: u64,
+    /// Most UploadPart requests in progress per upload ID; more fail with
+    /// `503 SlowDown`. 0 for no limit.
+    #[serde(default)]
+    pub max_concurrent_parts: usize,
+}
+
+/// `Retry-After` sent with `503`s that do not carry their own (frozen
+/// buckets do): `min_secs` plus one second for every `requests_per_sec`
+/// requests in flight, capped at `max_secs`, so clients back off further the
+/// busier the server is.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct RetryAfterConfig {
+    #[serde(default = "default_retry_after_min_secs")]
+    pub min_secs: u64,
+    #[serde(default = "default_retry_after_max_secs")]
+    pub max_secs: u64,
+    /// Roughly how many requests the server completes per second.
+    #[serde(default = "default_retry_after_requests_per_sec")]
+    pub requests_per_sec: u64,
+}
+
+impl Default for RetryAfterConfig {
+    fn default() -> Self {
+        Self {
+            min_secs: default_retry_after_min_secs(),
+            max_secs: default_retry_after_max_secs(),
+            requests_per_sec: default_retry_after_requests_per_sec(),
+        }
+    }
+}
+
+impl RetryAfterConfig {
+    pub fn secs(&self, in_flight: u64) -> u64 {
+        (self.min_secs + in_flight / self.requests_per_sec).min(self.max_secs)
+    }
+}
+
+fn default_retry_after_min_secs() -> u64 {
+    1
+}
+
+fn default_retry_after_max_secs() -> u64 {
+    60
+}
+
+fn default_retry_after_requests_per_sec() -> u64 {
+    50
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
@@ -37,6 +365,10 @@ pub struct HttpConfig {
     pub enabled: bool,
     pub port: u16,
     pub host: String,
+    /// Expect a PROXY protocol (v1 or v2) header on every connection, as
+    /// sent by HAProxy (`send-proxy-v2`) or nginx (`proxy_protocol on`).
+    #[serde(default)]
+    pub proxy_protocol: bool,
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
@@ -53,10 +385,22 @@ pub struct LetsEncryptConfig {
     pub do_token: String,
 }
 
+/// A named permission set, defined once under `roles` and attached to
+/// credentials by name.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct RoleConfig {
+    pub permissions: Vec<Permission>,
+}
+
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct Credential {
     pub access_key: String,
     pub secret_key: String,
+    /// Names of `roles` whose permissions this credential gets in addition
+    /// to its own.
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
     pub permissions: Vec<Permission>,
 }
 
@@ -78,6 +422,19 @@ pub struct DefaultCors {
     pub allowed_methods: Vec<String>,
 }
 
+impl DefaultCors {
+    /// The rule of buckets without a CORS configuration. Any headers may
+    /// be requested, as signed requests need several.
+    pub fn rule(&self) -> crate::models::CorsRule {
+        crate::models::CorsRule {
+            allowed_methods: self.allowed_methods.clone(),
+            allowed_origins: self.allowed_origins.clone(),
+            allowed_headers: vec!["*".to_string()],
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct MultipartConfig {
     pub expiry_seconds: u64,
@@ -90,19 +447,655 @@ pub struct ConfigReload {
     pub fsevents: bool,
 }
 
+/// Admin API (`/admin/v1/...`). Authenticated with bearer tokens that are
+/// separate from S3 credentials.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct AdminConfig {
+    pub enabled: bool,
+    /// Serve the admin API on its own port instead of under the S3 listener.
+    pub port: Option<u16>,
+    pub tokens: Vec<AdminToken>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct AdminToken {
+    pub name: String,
+    pub token: String,
+    /// What the token may do; everything when omitted.
+    #[serde(default = "default_admin_scopes")]
+    pub scopes: Vec<AdminScope>,
+}
+
+fn default_admin_scopes() -> Vec<AdminScope> {
+    vec![AdminScope::All]
+}
+
+/// A group of admin endpoints a token can be allowed (see
+/// `admin::authz::required_scope`).
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminScope {
+    /// Every endpoint.
+    All,
+    /// `GET` endpoints (status, listings, metrics, job status) and policy
+    /// simulation; nothing that changes state.
+    Read,
+    /// Minting and revoking service accounts.
+    Credentials,
+    /// Running background jobs.
+    Jobs,
+    /// Deleting, renaming, freezing, reindexing and restoring buckets;
+    /// creating them in a batch.
+    Buckets,
+    Kms,
+    /// Config reload and draining.
+    Server,
+}
+
+/// Extra headers added to S3 API responses, e.g. security headers or
+/// `X-Env: staging`. Per-bucket headers take precedence over global ones;
+/// neither replaces a header the handler already set. Applies on reload.
+#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
+pub struct ResponseHeadersConfig {
+    #[serde(default)]
+    pub global: BTreeMap<String, String>,
+    #[serde(default)]
+    pub buckets: HashMap<String, BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct MetricsConfig {
+    /// How many buckets get their own metric label; the rest are reported
+    /// as `__other__`. Applies on reload to buckets not yet labelled.
+    #[serde(default = "default_max_bucket_labels")]
+    pub max_bucket_labels: usize,
+    #[serde(default)]
+    pub snapshots: MetricsSnapshotsConfig,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            max_bucket_labels: default_max_bucket_labels(),
+            snapshots: MetricsSnapshotsConfig::default(),
+        }
+    }
+}
+
+fn default_max_bucket_labels() -> usize {
+    100
+}
+
+/// Request counts and bytes per bucket and operation, stored by the
+/// `metrics-snapshot` job for `GET /admin/v1/metrics/history`, so usage
+/// trends are kept without a Prometheus server. Applies on reload.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct MetricsSnapshotsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Days of snapshots kept; older ones are removed by the job.
+    #[serde(default = "default_metrics_retention_days")]
+    pub retention_days: u32,
+}
+
+impl Default for MetricsSnapshotsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            retention_days: default_metrics_retention_days(),
+        }
+    }
+}
+
+fn default_metrics_retention_days() -> u32 {
+    30
+}
+
+/// Access and audit logs kept per day and published as objects into a
+/// reserved bucket by the `system-log-publish` job.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct SystemLogsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Created on first publish; clients cannot create a bucket of this name.
+    #[serde(default = "default_system_logs_bucket")]
+    pub bucket: String,
+}
+
+impl Default for SystemLogsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bucket: default_system_logs_bucket(),
+        }
+    }
+}
+
+fn default_system_logs_bucket() -> String {
+    "s3clone-system".to_string()
+}
+
+/// Operation groups that can be switched off to reduce the exposed surface.
+/// S3 requests of a disabled group fail with `501 NotImplemented` before
+/// authentication; everything is enabled by default. Applies on reload.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct FeaturesConfig {
+    /// `?versions`, `?versioning` and `versionId` on object requests.
+    #[serde(default = "default_true")]
+    pub versioning: bool,
+    /// `?website`; not implemented, so always `NotImplemented`.
+    #[serde(default = "default_true")]
+    pub website: bool,
+    /// `POST ?select`; not implemented, so always `NotImplemented`.
+    #[serde(default = "default_true")]
+    pub select: bool,
+    /// The `/admin/v1` API, even when `admin.enabled` is set.
+    #[serde(default = "default_true")]
+    pub admin_api: bool,
+    /// Unsigned requests; refused with `403 AccessDenied` when off, whatever
+    /// the bucket ACLs allow.
+    #[serde(default = "default_true")]
+    pub anonymous_access: bool,
+    /// The metadata search extension, `GET /{bucket}?search`.
+    #[serde(default = "default_true")]
+    pub search: bool,
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        Self {
+            versioning: true,
+            website: true,
+            select: true,
+            admin_api: true,
+            anonymous_access: true,
+            search: true,
+        }
+    }
+}
+
+/// Storage classes accepted in `x-amz-storage-class`, and the class given to
+/// objects stored without one. Classes are recorded and reported only; every
+/// object is stored the same way. Applies on reload.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct StorageClassesConfig {
+    /// Anything else fails with `400 InvalidStorageClass`.
+    #[serde(default = "default_allowed_storage_classes")]
+    pub allowed: Vec<String>,
+    #[serde(default = "default_storage_class")]
+    pub default: String,
+    /// Per-bucket defaults, overriding `default`.
+    #[serde(default)]
+    pub buckets: HashMap<String, String>,
+}
+
+impl Default for StorageClassesConfig {
+    fn default() -> Self {
+        Self {
+            allowed: default_allowed_storage_classes(),
+            default: default_storage_class(),
+            buckets: HashMap::new(),
+        }
+    }
+}
+
+impl StorageClassesConfig {
+    pub fn is_allowed(&self, class: &str) -> bool {
+        self.allowed.iter().any(|c| c == class)
+    }
+
+    /// Class of objects stored in `bucket` without `x-amz-storage-class`.
+    pub fn default_for(&self, bucket: &str) -> &str {
+        self.buckets.get(bucket).unwrap_or(&self.default)
+    }
+}
+
+/// The storage classes S3 accepts.
+fn default_allowed_storage_classes() -> Vec<String> {
+    [
+        "STANDARD",
+        "REDUCED_REDUNDANCY",
+        "STANDARD_IA",
+        "ONEZONE_IA",
+        "INTELLIGENT_TIERING",
+        "GLACIER",
+        "DEEP_ARCHIVE",
+        "OUTPOSTS",
+        "GLACIER_IR",
+        "SNOW",
+        "EXPRESS_ONEZONE",
+    ]
+    .iter()
+    .map(|c| c.to_string())
+    .collect()
+}
+
+fn default_storage_class() -> String {
+    "STANDARD".to_string()
+}
+
+fn validate_storage_classes(classes: &StorageClassesConfig) -> Result<(), String> {
+    if classes.allowed.is_empty() {
+        debug!("storage_classes.allowed is empty");
+        return Err("storage_classes.allowed must not be empty".to_string());
+    }
+    if !classes.is_allowed(&classes.default) {
+        debug!(
+            "storage_classes.default {:?} is not allowed",
+            classes.default
+        );
+        return Err(format!(
+            "storage_classes.default: {} is not in storage_classes.allowed",
+            classes.default
+        ));
+    }
+    for (bucket, class) in &classes.buckets {
+        if !classes.is_allowed(class) {
+            debug!(
+                "storage_classes.buckets.{} {:?} is not allowed",
+                bucket, class
+            );
+            return Err(format!(
+                "storage_classes.buckets.{}: {} is not in storage_classes.allowed",
+                bucket, class
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Delivery of bucket event notifications. Events are queued on disk and
+/// POSTed to the target configured for their destination ARN, retried with
+/// exponential backoff and moved to a dead-letter area after `max_attempts`.
+/// Applies on reload.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct NotificationsConfig {
+    /// Destinations that bucket notification configurations may name.
+    #[serde(default)]
+    pub targets: Vec<NotificationTarget>,
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled after every failed attempt.
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// Per-attempt deadline, including connecting.
+    #[serde(default = "default_delivery_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            targets: Vec::new(),
+            max_attempts: default_max_attempts(),
+            initial_backoff_ms: default_initial_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+            timeout_ms: default_delivery_timeout_ms(),
+        }
+    }
+}
+
+impl NotificationsConfig {
+    pub fn target(&self, arn: &str) -> Option<&NotificationTarget> {
+        self.targets.iter().find(|t| t.arn == arn)
+    }
+}
+
+/// A webhook standing in for an SNS topic, SQS queue or Lambda function.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct NotificationTarget {
+    pub arn: String,
+    /// `http://host[:port]/path` receiving the S3 event message as JSON.
+    pub endpoint: String,
+}
+
+fn default_max_attempts() -> u32 {
+    10
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    1000
+}
+
+fn default_max_backoff_ms() -> u64 {
+    600_000
+}
+
+fn default_delivery_timeout_ms() -> u64 {
+    5000
+}
+
+fn validate_notifications(notifications: &NotificationsConfig) -> Result<(), String> {
+    if notifications.max_attempts == 0 {
+        debug!("notifications.max_attempts is 0");
+        return Err("notifications.max_attempts must be > 0".to_string());
+    }
+    if notifications.initial_backoff_ms == 0
+        || notifications.max_backoff_ms < notifications.initial_backoff_ms
+    {
+        debug!("notifications backoff is invalid");
+        return Err(
+            "notifications.initial_backoff_ms must be > 0 and <= max_backoff_ms".to_string(),
+        );
+    }
+    if notifications.timeout_ms == 0 {
+        debug!("notifications.timeout_ms is 0");
+        return Err("notifications.timeout_ms must be > 0".to_string());
+    }
+    for (i, target) in notifications.targets.iter().enumerate() {
+        if !target.arn.starts_with("arn:") {
+            debug!("notification target ARN {:?} is invalid", target.arn);
+            return Err(format!(
+                "notifications.targets: {} is not an ARN",
+                target.arn
+            ));
+        }
+        if notifications.targets[..i]
+            .iter()
+            .any(|t| t.arn == target.arn)
+        {
+            debug!("notification target {:?} is defined twice", target.arn);
+            return Err(format!(
+                "notifications.targets: {} is defined twice",
+                target.arn
+            ));
+        }
+        if let Err(e) = crate::webhook::Endpoint::parse(&target.endpoint) {
+            debug!(
+                "notification target endpoint {:?} is invalid",
+                target.endpoint
+            );
+            return Err(format!("notifications.targets: {}: {}", target.arn, e));
+        }
+    }
+    Ok(())
+}
+
+/// Mirroring of a sample of S3 requests to a second server, e.g. a new build
+/// under load test. Copies are sent after the original request was served
+/// and their responses are discarded. Applies on reload.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct MirrorConfig {
+    /// `http://host[:port]` of the server receiving the copies.
+    pub endpoint: String,
+    /// Share of requests mirrored, from 0 to 100.
+    pub sample_percent: f64,
+    /// Copies pending at once; requests sampled beyond this are not mirrored.
+    #[serde(default = "default_mirror_max_in_flight")]
+    pub max_in_flight: usize,
+    /// Per copy, including connecting and sending the body.
+    #[serde(default = "default_mirror_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_mirror_max_in_flight() -> usize {
+    64
+}
+
+fn default_mirror_timeout_ms() -> u64 {
+    30_000
+}
+
+/// Buckets walked at startup, before the listeners open, so their first
+/// listings do not all hit cold filesystem caches at once after a deploy.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct WarmupConfig {
+    #[serde(default)]
+    pub buckets: Vec<String>,
+    /// The listeners open after this even if the walk is not done.
+    #[serde(default = "default_warmup_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for WarmupConfig {
+    fn default() -> Self {
+        Self {
+            buckets: Vec::new(),
+            timeout_secs: default_warmup_timeout_secs(),
+        }
+    }
+}
+
+fn default_warmup_timeout_secs() -> u64 {
+    60
+}
+
+/// Deleted buckets kept as tombstones instead of being removed at once: the
+/// name stays reserved and the bucket, with its configuration (and, for
+/// forced deletes through the admin API, its objects), can be restored
+/// through the admin API until the retention has passed. Applies on reload,
+/// to later deletions.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct BucketTombstonesConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// How long a deleted bucket can be restored; the
+    /// `bucket-tombstone-expiry` job removes it after that.
+    #[serde(default = "default_tombstone_retention_secs")]
+    pub retention_secs: u64,
+}
+
+impl Default for BucketTombstonesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            retention_secs: default_tombstone_retention_secs(),
+        }
+    }
+}
+
+fn default_tombstone_retention_secs() -> u64 {
+    7 * 24 * 3600
+}
+
+/// Where the credentials of signed requests come from, asked in order until
+/// one knows the access key. Service accounts and STS sessions are looked
+/// up after them. Read at startup only.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct AuthConfig {
+    #[serde(default = "default_auth_providers")]
+    pub providers: Vec<AuthProviderConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthProviderConfig {
+    /// The `credentials` section; changes apply on reload.
+    Config,
+    /// A YAML file listing credentials as in the `credentials` section,
+    /// re-read whenever it changes.
+    File { path: String },
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            providers: default_auth_providers(),
+        }
+    }
+}
+
+fn default_auth_providers() -> Vec<AuthProviderConfig> {
+    vec![AuthProviderConfig::Config]
+}
+
+fn validate_mirror(mirror: &MirrorConfig) -> Result<(), String> {
+    match crate::webhook::Endpoint::parse(&mirror.endpoint) {
+        Ok(endpoint) if endpoint.path == "/" => {}
+        Ok(_) => {
+            debug!("mirror endpoint {:?} has a path", mirror.endpoint);
+            return Err("mirror.endpoint must not have a path".to_string());
+        }
+        Err(e) => {
+            debug!("mirror endpoint {:?} is invalid", mirror.endpoint);
+            return Err(format!("mirror.endpoint: {}", e));
+        }
+    }
+    if !(0.0..=100.0).contains(&mirror.sample_percent) {
+        debug!("mirror.sample_percent is {}", mirror.sample_percent);
+        return Err("mirror.sample_percent must be between 0 and 100".to_string());
+    }
+    if mirror.max_in_flight == 0 {
+        debug!("mirror.max_in_flight is 0");
+        return Err("mirror.max_in_flight must be > 0".to_string());
+    }
+    if mirror.timeout_ms == 0 {
+        debug!("mirror.timeout_ms is 0");
+        return Err("mirror.timeout_ms must be > 0".to_string());
+    }
+    Ok(())
+}
+
+/// Headers that describe the message framing or carry S3 semantics, which
+/// injection rules must not touch.
+const RESERVED_RESPONSE_HEADERS: &[&str] = &[
+    "connection",
+    "content-encoding",
+    "content-length",
+    "content-range",
+    "content-type",
+    "etag",
+    "transfer-encoding",
+];
+
+fn validate_response_headers(
+    owner: &str,
+    headers: &BTreeMap<String, String>,
+) -> Result<(), String> {
+    for (name, value) in headers {
+        let lower = name.to_ascii_lowercase();
+        if axum::http::HeaderName::from_bytes(name.as_bytes()).is_err()
+            || axum::http::HeaderValue::from_str(value).is_err()
+        {
+            debug!("{}: invalid header {:?}", owner, name);
+            return Err(format!("{}: {} is not a valid header", owner, name));
+        }
+        if RESERVED_RESPONSE_HEADERS.contains(&lower.as_str()) || lower.starts_with("x-amz-") {
+            debug!("{}: reserved header {:?}", owner, name);
+            return Err(format!("{}: header {} cannot be injected", owner, name));
+        }
+    }
+    Ok(())
+}
+
+/// Per-job overrides for the background scheduler, keyed by job name.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct JobConfig {
+    /// Cron expression, `@daily`-style shortcut or `@every 10m`; the job's
+    /// built-in default is used when omitted.
+    pub schedule: Option<String>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_config_version() -> u64 {
+    CONFIG_VERSION
+}
+
+fn validate_permissions(owner: &str, permissions: &[Permission]) -> Result<(), String> {
+    for permission in permissions {
+        if !crate::services::auth::is_known_action(&permission.action) {
+            debug!("unknown permission action {:?}", permission.action);
+            return Err(format!(
+                "{}: permission action {} matches no known S3 or STS action",
+                owner, permission.action
+            ));
+        }
+    }
+    Ok(())
+}
+
 impl Config {
-    /// Load config from file and parse YAML
+    /// Load config from file and parse YAML. Files of an older
+    /// `config_version` are migrated in memory, with a warning.
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
         debug!("Loading config from {:?}", path.as_ref());
         let content = fs::read_to_string(path.as_ref())
             .map_err(|e| format!("Failed to read config file: {}", e))?;
-        let config: Self = serde_yaml::from_str(&content)
+        let mut value: serde_yaml::Value = serde_yaml::from_str(&content)
             .map_err(|e| format!("Failed to parse config file: {}", e))?;
+        let from = migrate::migrate(&mut value)?;
+        // Parsing the text again keeps line numbers in errors.
+        let config: Self = if from == CONFIG_VERSION {
+            serde_yaml::from_str(&content)
+        } else {
+            warn!(
+                "{:?} has the layout of config_version {}; migrated it to {} in memory. \
+                 Run s3-clone --migrate-config <path> to write out the migrated file",
+                path.as_ref(),
+                from,
+                CONFIG_VERSION
+            );
+            serde_yaml::from_value(value)
+        }
+        .map_err(|e| format!("Failed to parse config file: {}", e))?;
         config.validate()?;
         Ok(config)
     }
 
+    /// The config file at `path` migrated to [`CONFIG_VERSION`], as YAML,
+    /// or `None` if it has that layout already. Comments are not kept.
+    pub fn migrated_file<P: AsRef<Path>>(path: P) -> Result<Option<String>, String> {
+        let content = fs::read_to_string(path.as_ref())
+            .map_err(|e| format!("Failed to read config file: {}", e))?;
+        let mut value: serde_yaml::Value = serde_yaml::from_str(&content)
+            .map_err(|e| format!("Failed to parse config file: {}", e))?;
+        if migrate::migrate(&mut value)? == CONFIG_VERSION {
+            return Ok(None);
+        }
+        // Only files that load are written out.
+        serde_yaml::from_value::<Self>(value.clone())
+            .map_err(|e| format!("Failed to parse migrated config: {}", e))?
+            .validate()?;
+        serde_yaml::to_string(&value)
+            .map(Some)
+            .map_err(|e| e.to_string())
+    }
+
+    /// A credential's own permissions followed by those of its roles.
+    /// Unknown role names are rejected by [`Config::validate`].
+    pub fn effective_permissions(&self, credential: &Credential) -> Vec<Permission> {
+        let mut permissions = credential.permissions.clone();
+        for role in &credential.roles {
+            if let Some(role) = self.roles.get(role) {
+                permissions.extend(role.permissions.iter().cloned());
+            }
+        }
+        permissions
+    }
+
     /// Validate required fields and value ranges
+    /// Checks a credential of the `credentials` section or of a credentials
+    /// file against the `roles` of this config.
+    pub fn validate_credential(&self, cred: &Credential) -> Result<(), String> {
+        if cred.access_key.is_empty() || cred.secret_key.is_empty() {
+            debug!("credential access_key and secret_key must not be empty");
+            return Err("credential access_key and secret_key must not be empty".to_string());
+        }
+        for role in &cred.roles {
+            if !self.roles.contains_key(role) {
+                debug!(
+                    "credential {} references unknown role {:?}",
+                    cred.access_key, role
+                );
+                return Err(format!(
+                    "credential {}: role {} is not defined under roles",
+                    cred.access_key, role
+                ));
+            }
+        }
+        validate_permissions(
+            &format!("credential {}", cred.access_key),
+            &cred.permissions,
+        )
+    }
+
     pub fn validate(&self) -> Result<(), String> {
         debug!("validating config");
         if self.storage.location.is_empty() {
@@ -114,6 +1107,36 @@ impl Config {
             debug!("region.default is empty");
             return Err("region.default must not be empty".to_string());
         }
+        if self.owner.id.is_empty() || self.owner.display_name.is_empty() {
+            debug!("owner.id or owner.display_name is empty");
+            return Err("owner.id and owner.display_name must not be empty".to_string());
+        }
+        if self.server.default_deadline_ms == Some(0) {
+            debug!("server.default_deadline_ms is 0");
+            return Err("server.default_deadline_ms must be > 0".to_string());
+        }
+        if self.server.listing_time_budget_ms == Some(0) {
+            debug!("server.listing_time_budget_ms is 0");
+            return Err("server.listing_time_budget_ms must be > 0".to_string());
+        }
+        if self.metrics.snapshots.retention_days == 0 {
+            debug!("metrics.snapshots.retention_days is 0");
+            return Err("metrics.snapshots.retention_days must be > 0".to_string());
+        }
+        let retry_after = &self.server.retry_after;
+        if retry_after.requests_per_sec == 0 || retry_after.min_secs > retry_after.max_secs {
+            debug!("server.retry_after is invalid");
+            return Err(
+                "server.retry_after.requests_per_sec must be > 0 and min_secs <= max_secs"
+                    .to_string(),
+            );
+        }
+        for proxy in &self.server.trusted_proxies {
+            if let Err(e) = crate::listener::Cidr::parse(proxy) {
+                debug!("invalid trusted proxy {:?}", proxy);
+                return Err(format!("server.trusted_proxies: {}", e));
+            }
+        }
         if self.server.http.port == 0 {
             debug!("server.http.port is 0");
             return Err("server.http.port must be > 0".to_string());
@@ -123,30 +1146,211 @@ impl Config {
                 debug!("server.https.port is 0");
                 return Err("server.https.port must be > 0".to_string());
             }
-            if let Some(le) = &https.letsencrypt {
-                if le.email.is_empty() || le.domains.is_empty() || le.do_token.is_empty() {
-                    debug!("letsencrypt config fields must not be empty");
-                    return Err("letsencrypt config fields must not be empty".to_string());
-                }
+            if let Some(le) = &https.letsencrypt
+                && (le.email.is_empty() || le.domains.is_empty() || le.do_token.is_empty())
+            {
+                debug!("letsencrypt config fields must not be empty");
+                return Err("letsencrypt config fields must not be empty".to_string());
+            }
+        }
+        if self.auth.providers.is_empty() {
+            debug!("auth.providers is empty");
+            return Err("auth.providers must not be empty".to_string());
+        }
+        let mut files = HashSet::new();
+        for provider in &self.auth.providers {
+            if let AuthProviderConfig::File { path } = provider
+                && (path.is_empty() || !files.insert(path))
+            {
+                debug!("auth file provider {:?} is invalid", path);
+                return Err("auth.providers: file paths must be non-empty and unique".to_string());
             }
         }
-        if self.credentials.is_empty() {
+        // Credentials may all come from files.
+        if self.credentials.is_empty() && files.is_empty() {
             debug!("credentials must not be empty");
             return Err("at least one credential must be defined".to_string());
         }
         for cred in &self.credentials {
-            if cred.access_key.is_empty() || cred.secret_key.is_empty() {
-                debug!("credential access_key and secret_key must not be empty");
-                return Err("credential access_key and secret_key must not be empty".to_string());
+            self.validate_credential(cred)?;
+        }
+        for (name, role) in &self.roles {
+            if name.is_empty() {
+                debug!("role name is empty");
+                return Err("role names must not be empty".to_string());
+            }
+            validate_permissions(&format!("role {}", name), &role.permissions)?;
+        }
+        for method in &self.default_cors.allowed_methods {
+            if !crate::models::CORS_METHODS.contains(&method.as_str()) {
+                debug!("default_cors method {:?} is unsupported", method);
+                return Err(format!(
+                    "default_cors.allowed_methods: unsupported method {}",
+                    method
+                ));
             }
         }
+        if let Some(origin) = self
+            .default_cors
+            .allowed_origins
+            .iter()
+            .find(|origin| origin.matches('*').count() > 1)
+        {
+            debug!("default_cors origin {:?} has several wildcards", origin);
+            return Err(format!(
+                "default_cors.allowed_origins: {} can not have more than one wildcard",
+                origin
+            ));
+        }
         if self.multipart.expiry_seconds == 0 {
             debug!("multipart.expiry_seconds must be > 0");
             return Err("multipart.expiry_seconds must be > 0".to_string());
         }
+        if let Some(admin) = &self.admin
+            && admin.enabled
+        {
+            if admin.port == Some(0) {
+                debug!("admin.port is 0");
+                return Err("admin.port must be > 0".to_string());
+            }
+            if admin.port == Some(self.server.http.port) {
+                debug!("admin.port equals server.http.port");
+                return Err("admin.port must differ from server.http.port".to_string());
+            }
+            if admin.tokens.is_empty() {
+                debug!("admin.tokens must not be empty");
+                return Err("at least one admin token must be defined".to_string());
+            }
+            for token in &admin.tokens {
+                if token.name.is_empty() || token.token.len() < 16 {
+                    debug!("admin token {:?} is invalid", token.name);
+                    return Err(
+                        "admin tokens need a name and a token of at least 16 characters"
+                            .to_string(),
+                    );
+                }
+                if token.scopes.is_empty() {
+                    debug!("admin token {:?} has no scopes", token.name);
+                    return Err(format!(
+                        "admin token {} needs at least one scope",
+                        token.name
+                    ));
+                }
+                if self.credentials.iter().any(|c| c.secret_key == token.token) {
+                    debug!("admin token {:?} reuses an S3 secret key", token.name);
+                    return Err(format!(
+                        "admin token {} must not reuse an S3 secret key",
+                        token.name
+                    ));
+                }
+            }
+        }
+        validate_response_headers("response_headers.global", &self.response_headers.global)?;
+        for (bucket, headers) in &self.response_headers.buckets {
+            validate_response_headers(&format!("response_headers.buckets.{}", bucket), headers)?;
+        }
+        if let Err(e) = crate::storage::validate_bucket_name(&self.system_logs.bucket) {
+            debug!("system_logs.bucket is invalid");
+            return Err(format!("system_logs.bucket: {}", e));
+        }
+        validate_notifications(&self.notifications)?;
+        if let Some(mirror) = &self.mirror {
+            validate_mirror(mirror)?;
+        }
+        validate_storage_classes(&self.storage_classes)?;
+        for (name, job) in &self.jobs {
+            if let Some(schedule) = &job.schedule {
+                crate::jobs::Schedule::parse(schedule).map_err(|e| {
+                    debug!("jobs.{}.schedule is invalid", name);
+                    format!("jobs.{}.schedule: {}", name, e)
+                })?;
+            }
+        }
 
         debug!("config is valid");
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+impl Config {
+    /// A config for unit tests: the memory backend, one credential
+    /// (`AKIA...`/`SECRET...`) allowed everything, region `de-muc-01` and
+    /// defaults for the rest.
+    pub fn for_tests() -> Self {
+        Self {
+            config_version: default_config_version(),
+            storage: StorageConfig {
+                location: "/data".to_string(),
+                read_cache: ReadCacheConfig::default(),
+                tracing: StorageTracingConfig::default(),
+                eventual_consistency: EventualConsistencyConfig::default(),
+                escape_keys: false,
+                backend: StorageBackendKind::Memory,
+                durability: Durability::default(),
+            },
+            region: RegionConfig {
+                default: "de-muc-01".to_string(),
+                redirect: false,
+                validate: false,
+                allowed: Vec::new(),
+            },
+            owner: OwnerConfig::default(),
+            server: ServerConfig {
+                http: HttpConfig {
+                    enabled: true,
+                    port: 8088,
+                    host: "127.0.0.1".to_string(),
+                    proxy_protocol: false,
+                },
+                https: None,
+                default_deadline_ms: None,
+                listing_time_budget_ms: None,
+                trusted_proxies: Vec::new(),
+                compress_responses: false,
+                precompressed_variants: Vec::new(),
+                retry_after: RetryAfterConfig::default(),
+                bandwidth: BandwidthConfig::default(),
+            },
+            roles: HashMap::new(),
+            credentials: vec![Credential {
+                access_key: "AKIA...".to_string(),
+                secret_key: "SECRET...".to_string(),
+                roles: Vec::new(),
+                permissions: vec![Permission {
+                    action: "*".to_string(),
+                    resource: "*".to_string(),
+                }],
+            }],
+            auth: AuthConfig::default(),
+            default_acls: DefaultAcls {
+                public: false,
+                allowed_ips: Vec::new(),
+            },
+            default_cors: DefaultCors {
+                allowed_origins: Vec::new(),
+                allowed_methods: Vec::new(),
+            },
+            multipart: MultipartConfig {
+                expiry_seconds: 86400,
+            },
+            config_reload: ConfigReload {
+                sighup: false,
+                api: false,
+                fsevents: false,
+            },
+            jobs: HashMap::new(),
+            admin: None,
+            response_headers: ResponseHeadersConfig::default(),
+            metrics: MetricsConfig::default(),
+            system_logs: SystemLogsConfig::default(),
+            notifications: NotificationsConfig::default(),
+            features: FeaturesConfig::default(),
+            storage_classes: StorageClassesConfig::default(),
+            mirror: None,
+            warmup: WarmupConfig::default(),
+            bucket_tombstones: BucketTombstonesConfig::default(),
+        }
+    }
+}
@@ -0,0 +1,660 @@
+use crate::models::responses::*;
+use crate::storage::StorageError;
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use std::fmt;
+
+/// An S3 error as returned to clients: HTTP status plus the `<Error>` XML
+/// document. The request ID is filled in by the request-id middleware, which
+/// renders the body (see [`S3Error::to_xml`]).
+#[derive(Debug, Clone)]
+pub struct S3Error {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+    /// Extra element naming the resource, e.g. `("Key", "photo.jpg")`.
+    pub resource: Option<(&'static str, String)>,
+    /// Seconds sent as `Retry-After` (on `503`s).
+    pub retry_after: Option<u64>,
+    /// Version ID of the delete marker the request ran into, sent with
+    /// `x-amz-delete-marker: true`. Boxed to keep errors small.
+    pub delete_marker: Option<Box<str>>,
+}
+
+impl S3Error {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            resource: None,
+            retry_after: None,
+            delete_marker: None,
+        }
+    }
+
+    pub fn with_resource(mut self, element: &'static str, value: impl Into<String>) -> Self {
+        self.resource = Some((element, value.into()));
+        self
+    }
+
+    pub fn with_delete_marker(mut self, version_id: &str) -> Self {
+        self.delete_marker = Some(version_id.into());
+        self
+    }
+
+    pub fn access_denied() -> Self {
+        Self::new(StatusCode::FORBIDDEN, ERROR_ACCESS_DENIED, "Access Denied")
+    }
+
+    /// AccessDenied with a more specific message, e.g. for expired URLs.
+    pub fn access_denied_because(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, ERROR_ACCESS_DENIED, message)
+    }
+
+    pub fn invalid_access_key_id() -> Self {
+        Self::new(
+            StatusCode::FORBIDDEN,
+            ERROR_INVALID_ACCESS_KEY_ID,
+            "The AWS Access Key Id you provided does not exist in our records.",
+        )
+    }
+
+    pub fn invalid_token() -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_INVALID_TOKEN,
+            "The provided token is malformed or otherwise invalid.",
+        )
+    }
+
+    pub fn expired_token() -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_EXPIRED_TOKEN,
+            "The provided token has expired.",
+        )
+    }
+
+    pub fn request_time_too_skewed(request_time: &str) -> Self {
+        Self::new(
+            StatusCode::FORBIDDEN,
+            ERROR_REQUEST_TIME_TOO_SKEWED,
+            "The difference between the request time and the current time is too large.",
+        )
+        .with_resource("RequestTime", request_time)
+    }
+
+    pub fn authorization_query_parameters_error(message: impl Into<String>) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_AUTHORIZATION_QUERY_PARAMETERS_ERROR,
+            message,
+        )
+    }
+
+    pub fn authorization_header_malformed(message: impl Into<String>) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_AUTHORIZATION_HEADER_MALFORMED,
+            format!("The authorization header is malformed; {}", message.into()),
+        )
+    }
+
+    /// The signature does not match the one computed over `string_to_sign`,
+    /// which is returned so clients can compare it with their own.
+    pub fn signature_does_not_match(string_to_sign: &str) -> Self {
+        Self::new(
+            StatusCode::FORBIDDEN,
+            ERROR_SIGNATURE_DOES_NOT_MATCH,
+            "The request signature we calculated does not match the signature you provided. \
+             Check your key and signing method.",
+        )
+        .with_resource("StringToSign", string_to_sign)
+    }
+
+    pub fn incomplete_body() -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_INCOMPLETE_BODY,
+            "You did not provide the number of bytes specified by the Content-Length HTTP header.",
+        )
+    }
+
+    pub fn invalid_digest() -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_INVALID_DIGEST,
+            "The Content-MD5 you specified is not valid.",
+        )
+    }
+
+    pub fn bad_digest(expected: &str) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_BAD_DIGEST,
+            "The Content-MD5 you specified did not match what we received.",
+        )
+        .with_resource("ExpectedDigest", expected)
+    }
+
+    /// The body does not hash to the `x-amz-content-sha256` it was signed
+    /// with, which is returned as the client's.
+    pub fn content_sha256_mismatch(client_computed: &str) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_CONTENT_SHA256_MISMATCH,
+            "The provided 'x-amz-content-sha256' header does not match what was computed.",
+        )
+        .with_resource("ClientComputedContentSHA256", client_computed)
+    }
+
+    /// The body does not match its `x-amz-checksum-*` header; `algorithm`
+    /// is the name, as in `CRC32`.
+    pub fn checksum_mismatch(algorithm: &str) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_BAD_DIGEST,
+            format!(
+                "The {} you specified did not match the calculated checksum.",
+                algorithm
+            ),
+        )
+    }
+
+    pub fn no_such_bucket(bucket: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            ERROR_NO_SUCH_BUCKET,
+            "The specified bucket does not exist",
+        )
+        .with_resource("BucketName", bucket)
+    }
+
+    pub fn no_such_key(key: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            ERROR_NO_SUCH_KEY,
+            "The specified key does not exist.",
+        )
+        .with_resource("Key", key)
+    }
+
+    pub fn no_such_version(version_id: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            ERROR_NO_SUCH_VERSION,
+            "The specified version does not exist.",
+        )
+        .with_resource("VersionId", version_id)
+    }
+
+    pub fn no_such_upload(upload_id: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            ERROR_NO_SUCH_UPLOAD,
+            "The specified upload does not exist. The upload ID may be invalid, or the upload \
+             may have been aborted or completed.",
+        )
+        .with_resource("UploadId", upload_id)
+    }
+
+    pub fn no_such_tag_set(bucket: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            ERROR_NO_SUCH_TAG_SET,
+            "The TagSet does not exist",
+        )
+        .with_resource("BucketName", bucket)
+    }
+
+    pub fn no_such_lifecycle_configuration(bucket: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            ERROR_NO_SUCH_LIFECYCLE_CONFIGURATION,
+            "The lifecycle configuration does not exist",
+        )
+        .with_resource("BucketName", bucket)
+    }
+
+    pub fn no_such_bucket_policy(bucket: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            ERROR_NO_SUCH_BUCKET_POLICY,
+            "The bucket policy does not exist",
+        )
+        .with_resource("BucketName", bucket)
+    }
+
+    pub fn no_such_cors_configuration(bucket: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            ERROR_NO_SUCH_CORS_CONFIGURATION,
+            "The CORS configuration does not exist",
+        )
+        .with_resource("BucketName", bucket)
+    }
+
+    /// A CORS preflight that no rule of the bucket allows.
+    pub fn cors_forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, ERROR_ACCESS_FORBIDDEN, message)
+    }
+
+    /// A CORS preflight without `Origin` or a valid
+    /// `Access-Control-Request-Method`.
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, ERROR_BAD_REQUEST, message)
+    }
+
+    pub fn invalid_storage_class(storage_class: &str) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_INVALID_STORAGE_CLASS,
+            "The storage class you specified is not valid",
+        )
+        .with_resource("StorageClassRequested", storage_class)
+    }
+
+    pub fn invalid_tag(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, ERROR_INVALID_TAG, message)
+    }
+
+    pub fn invalid_object_name(key: &str, reason: &str) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, ERROR_INVALID_OBJECT_NAME, reason)
+            .with_resource("Key", key)
+    }
+
+    pub fn key_too_long(len: usize) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_KEY_TOO_LONG,
+            "Your key is too long",
+        )
+        .with_resource("Size", len.to_string())
+    }
+
+    pub fn metadata_too_large(size: usize) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_METADATA_TOO_LARGE,
+            "Your metadata headers exceed the maximum allowed metadata size",
+        )
+        .with_resource("Size", size.to_string())
+    }
+
+    pub fn invalid_argument(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, ERROR_INVALID_ARGUMENT, message)
+    }
+
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, ERROR_INVALID_REQUEST, message)
+    }
+
+    /// MalformedXML with S3's message followed by what failed to parse.
+    pub fn malformed_xml(detail: impl fmt::Display) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_MALFORMED_XML,
+            format!(
+                "The XML you provided was not well-formed or did not validate against our \
+                 published schema: {}",
+                detail
+            ),
+        )
+    }
+
+    pub fn invalid_part(part_number: u32) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_INVALID_PART,
+            "One or more of the specified parts could not be found. The part may not have been \
+             uploaded, or the specified entity tag may not match the part's entity tag.",
+        )
+        .with_resource("PartNumber", part_number.to_string())
+    }
+
+    pub fn invalid_part_order() -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_INVALID_PART_ORDER,
+            "The list of parts was not in ascending order. Parts must be ordered by part number.",
+        )
+    }
+
+    /// A part other than the last one is below the multipart minimum size.
+    pub fn entity_too_small(part_number: u32) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_ENTITY_TOO_SMALL,
+            "Your proposed upload is smaller than the minimum allowed object size.",
+        )
+        .with_resource("PartNumber", part_number.to_string())
+    }
+
+    /// A form upload whose file is below the `content-length-range` of its
+    /// policy.
+    pub fn entity_below_range(min: u64) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_ENTITY_TOO_SMALL,
+            "Your proposed upload is smaller than the minimum allowed size",
+        )
+        .with_resource("MinSizeAllowed", min.to_string())
+    }
+
+    /// A form upload whose file is above the `content-length-range` of its
+    /// policy.
+    pub fn entity_above_range(max: u64) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_ENTITY_TOO_LARGE,
+            "Your proposed upload exceeds the maximum allowed size",
+        )
+        .with_resource("MaxSizeAllowed", max.to_string())
+    }
+
+    /// A request document (not an upload) longer than `max` bytes.
+    pub fn max_message_length_exceeded(max: usize) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_MAX_MESSAGE_LENGTH_EXCEEDED,
+            format!(
+                "Your request was too big; request documents are limited to {} bytes.",
+                max
+            ),
+        )
+    }
+
+    /// A form upload whose body is not valid `multipart/form-data`.
+    pub fn malformed_post_request() -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_MALFORMED_POST_REQUEST,
+            "The body of your POST request is not well-formed multipart/form-data.",
+        )
+    }
+
+    /// A form upload policy that is not base64, not JSON or not a valid
+    /// policy.
+    pub fn invalid_policy_document(message: impl Into<String>) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_INVALID_POLICY_DOCUMENT,
+            format!("Invalid Policy: {}", message.into()),
+        )
+    }
+
+    /// A form upload without a `file` field.
+    pub fn incorrect_number_of_files() -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_INCORRECT_NUMBER_OF_FILES,
+            "POST requires exactly one file upload per request.",
+        )
+    }
+
+    pub fn precondition_failed(condition: &'static str) -> Self {
+        Self::new(
+            StatusCode::PRECONDITION_FAILED,
+            ERROR_PRECONDITION_FAILED,
+            "At least one of the pre-conditions you specified did not hold",
+        )
+        .with_resource("Condition", condition)
+    }
+
+    pub fn invalid_range(size: u64) -> Self {
+        Self::new(
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            ERROR_INVALID_RANGE,
+            "The requested range is not satisfiable",
+        )
+        .with_resource("ActualObjectSize", size.to_string())
+    }
+
+    /// A bucket policy that is not valid JSON or not a valid policy.
+    pub fn malformed_policy(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, ERROR_MALFORMED_POLICY, message)
+    }
+
+    /// An ACL document with a grant that names no grantee or permission.
+    pub fn malformed_acl_error() -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_MALFORMED_ACL,
+            "The XML you provided was not well-formed or did not validate against our \
+             published schema",
+        )
+    }
+
+    /// A PutBucketAcl or PutObjectAcl with neither an ACL document nor ACL
+    /// headers.
+    pub fn missing_security_header(header: &str) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_MISSING_SECURITY_HEADER,
+            "Your request was missing a required header",
+        )
+        .with_resource("MissingHeaderName", header)
+    }
+
+    pub fn not_implemented(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_IMPLEMENTED, ERROR_NOT_IMPLEMENTED, message)
+    }
+
+    /// The operation does not exist for the resource, e.g. a plain `POST` on
+    /// an object.
+    pub fn method_not_allowed(method: &str) -> Self {
+        Self::new(
+            StatusCode::METHOD_NOT_ALLOWED,
+            ERROR_METHOD_NOT_ALLOWED,
+            "The specified method is not allowed against this resource.",
+        )
+        .with_resource("Method", method)
+    }
+
+    /// A request signed for another region than its bucket's, with
+    /// `region.redirect`. The region goes in `x-amz-bucket-region`.
+    pub fn permanent_redirect(bucket: &str) -> Self {
+        Self::new(
+            StatusCode::MOVED_PERMANENTLY,
+            ERROR_PERMANENT_REDIRECT,
+            "The bucket you are attempting to access must be addressed using the specified \
+             endpoint. Please send all future requests to this endpoint.",
+        )
+        .with_resource("Bucket", bucket)
+    }
+
+    pub fn deadline_exceeded(deadline_ms: u64) -> Self {
+        Self::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ERROR_DEADLINE_EXCEEDED,
+            format!(
+                "The request did not complete within its {} ms deadline.",
+                deadline_ms
+            ),
+        )
+    }
+
+    /// More concurrent UploadPart requests to one upload than
+    /// `server.bandwidth.max_concurrent_parts` allows.
+    pub fn slow_down() -> Self {
+        Self::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ERROR_SLOW_DOWN,
+            "Please reduce your request rate.",
+        )
+    }
+
+    pub fn bucket_frozen(bucket: &str, retry_after_secs: u64) -> Self {
+        Self {
+            retry_after: Some(retry_after_secs),
+            ..Self::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                ERROR_SERVICE_UNAVAILABLE,
+                "The bucket is frozen and does not accept writes. Please retry later.",
+            )
+            .with_resource("BucketName", bucket)
+        }
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ERROR_INTERNAL_ERROR,
+            message,
+        )
+    }
+
+    pub fn to_xml(&self, request_id: &str) -> String {
+        let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        xml.push_str("<Error>");
+        xml.push_str(&format!("<Code>{}</Code>", self.code));
+        xml.push_str(&format!("<Message>{}</Message>", xml_escape(&self.message)));
+        if let Some((element, value)) = &self.resource {
+            xml.push_str(&format!("<{0}>{1}</{0}>", element, xml_escape(value)));
+        }
+        xml.push_str(&format!("<RequestId>{}</RequestId>", request_id));
+        xml.push_str(&format!("<HostId>{}</HostId>", request_id));
+        xml.push_str("</Error>");
+        xml
+    }
+}
+
+impl fmt::Display for S3Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for S3Error {}
+
+impl IntoResponse for S3Error {
+    fn into_response(self) -> Response {
+        // The body is rendered by the request-id middleware once the request ID
+        // is known; a bare response is only seen if that layer is missing.
+        let mut response = (self.status, [("content-type", "application/xml")]).into_response();
+        if let Some(secs) = self.retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, secs.into());
+        }
+        if let Some(version_id) = &self.delete_marker {
+            let headers = response.headers_mut();
+            headers.insert("x-amz-delete-marker", HeaderValue::from_static("true"));
+            if let Ok(value) = HeaderValue::from_str(version_id) {
+                headers.insert("x-amz-version-id", value);
+            }
+        }
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+impl From<StorageError> for S3Error {
+    fn from(e: StorageError) -> Self {
+        match e {
+            StorageError::NoSuchBucket(bucket) => S3Error::no_such_bucket(&bucket),
+            StorageError::NoSuchKey(key) => S3Error::no_such_key(&key),
+            StorageError::NoSuchVersion(version_id) => S3Error::no_such_version(&version_id),
+            // As in S3: a delete marker has no data or metadata to return.
+            StorageError::DeleteMarker(version_id) => S3Error::new(
+                StatusCode::METHOD_NOT_ALLOWED,
+                ERROR_METHOD_NOT_ALLOWED,
+                "The specified method is not allowed against this resource.",
+            )
+            .with_delete_marker(&version_id),
+            StorageError::BucketAlreadyExists(bucket) => S3Error::new(
+                StatusCode::CONFLICT,
+                ERROR_BUCKET_ALREADY_EXISTS,
+                "The requested bucket name is not available.",
+            )
+            .with_resource("BucketName", bucket),
+            StorageError::BucketAlreadyOwnedByYou(bucket) => S3Error::new(
+                StatusCode::CONFLICT,
+                ERROR_BUCKET_ALREADY_OWNED_BY_YOU,
+                "Your previous request to create the named bucket succeeded and you already own it.",
+            )
+            .with_resource("BucketName", bucket),
+            StorageError::BucketNotEmpty(bucket) => S3Error::new(
+                StatusCode::CONFLICT,
+                ERROR_BUCKET_NOT_EMPTY,
+                "The bucket you tried to delete is not empty",
+            )
+            .with_resource("BucketName", bucket),
+            StorageError::InvalidBucketName(msg) => {
+                S3Error::new(StatusCode::BAD_REQUEST, ERROR_INVALID_BUCKET_NAME, msg)
+            }
+            StorageError::InvalidObjectName(key, reason) => {
+                S3Error::invalid_object_name(&key, &reason)
+            }
+            StorageError::KeyTooLong(len) => S3Error::key_too_long(len),
+            StorageError::ObjectNotAppendable(key) => S3Error::new(
+                StatusCode::CONFLICT,
+                ERROR_OBJECT_NOT_APPENDABLE,
+                "The object is not appendable",
+            )
+            .with_resource("Key", key),
+            StorageError::PositionNotEqualToLength(_, size) => S3Error::new(
+                StatusCode::CONFLICT,
+                ERROR_POSITION_NOT_EQUAL_TO_LENGTH,
+                "Position is not equal to file length",
+            )
+            .with_resource("NextAppendPosition", size.to_string()),
+            StorageError::BucketFrozen(bucket, retry_after_secs) => {
+                S3Error::bucket_frozen(&bucket, retry_after_secs)
+            }
+            StorageError::NoSuchUpload(upload_id) => S3Error::no_such_upload(&upload_id),
+            StorageError::InvalidPart(part_number) => S3Error::invalid_part(part_number),
+            // OS errors name local paths; they are for the log only.
+            StorageError::Io(e) => {
+                log::error!("storage error: {}", e);
+                S3Error::internal("We encountered an internal error. Please try again.")
+            }
+        }
+    }
+}
+
+/// Maps service errors to S3 errors: explicit [`S3Error`]s and
+/// [`StorageError`]s keep their meaning, anything else is an InternalError.
+impl From<anyhow::Error> for S3Error {
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<S3Error>() {
+            Ok(s3) => return s3,
+            Err(e) => e,
+        };
+        match e.downcast::<StorageError>() {
+            Ok(storage) => storage.into(),
+            Err(e) => {
+                log::error!("internal error: {:#}", e);
+                S3Error::internal("We encountered an internal error. Please try again.")
+            }
+        }
+    }
+}
+
+/// Maps request body errors: the [`S3Error`] a decoding or limiting body
+/// stream failed with, found through the streams wrapping it (e.g.
+/// bandwidth throttling), and IncompleteBody otherwise, as the client went
+/// away or sent less than it declared.
+impl From<axum::Error> for S3Error {
+    fn from(e: axum::Error) -> Self {
+        let mut error = e.into_inner();
+        loop {
+            error = match error.downcast::<S3Error>() {
+                Ok(s3) => return *s3,
+                Err(other) => match other.downcast::<axum::Error>() {
+                    Ok(wrapped) => wrapped.into_inner(),
+                    Err(_) => return S3Error::incomplete_body(),
+                },
+            };
+        }
+    }
+}
+
+pub fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,137 @@
+//! Header values outside printable US-ASCII: RFC 2047 encoded words, in
+//! which S3 returns non-ASCII user metadata, and RFC 6266 `filename*`
+//! parameters (RFC 8187 extended values) for `Content-Disposition`.
+
+use crate::base64;
+use axum::http::HeaderValue;
+use percent_encoding::{AsciiSet, CONTROLS, NON_ALPHANUMERIC, utf8_percent_encode};
+
+/// Everything but RFC 8187 `attr-char` is percent-encoded in an extended
+/// value.
+const ATTR_CHAR: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'!')
+    .remove(b'#')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'+')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'^')
+    .remove(b'_')
+    .remove(b'`')
+    .remove(b'|')
+    .remove(b'~');
+
+/// The text of a header value. Clients may send raw UTF-8, which
+/// [`HeaderValue::to_str`] refuses; `None` for bytes that are not UTF-8.
+pub fn header_text(value: &HeaderValue) -> Option<&str> {
+    std::str::from_utf8(value.as_bytes()).ok()
+}
+
+/// Whether `text` can be sent as a header value unchanged.
+fn is_printable_ascii(text: &str) -> bool {
+    text.bytes()
+        .all(|b| b == b'\t' || (b' '..=b'~').contains(&b))
+}
+
+/// `text` unchanged when it is printable US-ASCII, otherwise as one RFC 2047
+/// encoded word (`=?UTF-8?B?...?=`), the way S3 returns such metadata.
+pub fn encode_word(text: &str) -> String {
+    if is_printable_ascii(text) {
+        return text.to_string();
+    }
+    format!("=?UTF-8?B?{}?=", base64::encode(text.as_bytes()))
+}
+
+/// Rewrites a `Content-Disposition` value with non-ASCII text into the form
+/// of RFC 6266: `attachment; filename="naïve.txt"` becomes `attachment;
+/// filename="na_ve.txt"; filename*=UTF-8''na%C3%AFve.txt`, which browsers
+/// and SDKs decode back to the original name. An extended parameter the
+/// client sent (`filename*=UTF-8''...`) is kept, with raw non-ASCII bytes
+/// percent-encoded. ASCII values are returned unchanged.
+pub fn content_disposition(value: &str) -> String {
+    if is_printable_ascii(value) {
+        return value.to_string();
+    }
+    let mut parts = split_params(value).into_iter();
+    let mut out = encode_word(parts.next().unwrap_or_default().trim());
+    let params: Vec<(String, String)> = parts
+        .filter_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            Some((name.trim().to_string(), value.trim().to_string()))
+        })
+        .collect();
+    for (name, value) in &params {
+        if name.ends_with('*') {
+            out += &format!("; {}={}", name, utf8_percent_encode(value, CONTROLS));
+            continue;
+        }
+        let text = unquote(value);
+        if is_printable_ascii(&text) {
+            out += &format!("; {}={}", name, value);
+            continue;
+        }
+        let fallback: String = text
+            .chars()
+            .map(|c| {
+                if c.is_ascii() && !c.is_ascii_control() {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        out += &format!("; {}={}", name, quote(&fallback));
+        let extended = format!("{}*", name);
+        if !params
+            .iter()
+            .any(|(n, _)| n.eq_ignore_ascii_case(&extended))
+        {
+            out += &format!(
+                "; {}=UTF-8''{}",
+                extended,
+                utf8_percent_encode(&text, ATTR_CHAR)
+            );
+        }
+    }
+    out
+}
+
+/// Splits at the `;`s outside quoted strings.
+fn split_params(value: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                parts.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+fn unquote(value: &str) -> String {
+    let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) else {
+        return value.to_string();
+    };
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,37 @@
+use super::Job;
+use crate::services::bucket_deletion::BucketDeletionService;
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Name the admin API triggers after queueing a forced bucket deletion.
+pub const NAME: &str = "bucket-force-delete";
+
+/// Removes buckets queued with `DELETE /admin/v1/buckets/{bucket}?force`.
+/// Triggered on every request; the schedule only resumes deletions that were
+/// interrupted by a restart.
+pub struct BucketForceDelete {
+    deletions: Arc<dyn BucketDeletionService>,
+}
+
+impl BucketForceDelete {
+    pub fn new(deletions: Arc<dyn BucketDeletionService>) -> Self {
+        Self { deletions }
+    }
+}
+
+#[async_trait::async_trait]
+impl Job for BucketForceDelete {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        "@every 5m"
+    }
+
+    async fn run(&self, shutdown: &watch::Receiver<bool>) -> Result<String> {
+        let deleted = self.deletions.run_pending(shutdown).await?;
+        Ok(format!("deleted {} bucket(s)", deleted))
+    }
+}
@@ -0,0 +1,33 @@
+use super::Job;
+use crate::services::bucket_deletion::BucketDeletionService;
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Removes deleted buckets kept as tombstones (`bucket_tombstones`) once
+/// their retention has passed, freeing their names.
+pub struct BucketTombstoneExpiry {
+    deletions: Arc<dyn BucketDeletionService>,
+}
+
+impl BucketTombstoneExpiry {
+    pub fn new(deletions: Arc<dyn BucketDeletionService>) -> Self {
+        Self { deletions }
+    }
+}
+
+#[async_trait::async_trait]
+impl Job for BucketTombstoneExpiry {
+    fn name(&self) -> &'static str {
+        "bucket-tombstone-expiry"
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        "@every 1h"
+    }
+
+    async fn run(&self, shutdown: &watch::Receiver<bool>) -> Result<String> {
+        let purged = self.deletions.purge_expired_tombstones(shutdown).await?;
+        Ok(format!("purged {} expired bucket tombstone(s)", purged))
+    }
+}
@@ -0,0 +1,36 @@
+use super::Job;
+use crate::services::lifecycle::LifecycleService;
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Applies bucket lifecycle rules: expires objects and aborts incomplete
+/// multipart uploads once they are due.
+pub struct LifecycleExpiry {
+    lifecycle: Arc<dyn LifecycleService>,
+}
+
+impl LifecycleExpiry {
+    pub fn new(lifecycle: Arc<dyn LifecycleService>) -> Self {
+        Self { lifecycle }
+    }
+}
+
+#[async_trait::async_trait]
+impl Job for LifecycleExpiry {
+    fn name(&self) -> &'static str {
+        "lifecycle"
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        "@every 1h"
+    }
+
+    async fn run(&self, shutdown: &watch::Receiver<bool>) -> Result<String> {
+        let sweep = self.lifecycle.apply_rules(shutdown).await?;
+        Ok(format!(
+            "expired {} object(s), removed {} delete marker(s), aborted {} upload(s)",
+            sweep.expired, sweep.delete_markers_removed, sweep.uploads_aborted
+        ))
+    }
+}
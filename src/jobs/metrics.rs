@@ -0,0 +1,39 @@
+use super::Job;
+use crate::services::metrics_history::MetricsHistoryService;
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Stores a snapshot of the request counts per bucket and operation when
+/// `metrics.snapshots` is enabled, and removes those past their retention.
+/// The schedule sets the resolution of `GET /admin/v1/metrics/history`.
+pub struct MetricsSnapshot {
+    metrics_history: Arc<dyn MetricsHistoryService>,
+}
+
+impl MetricsSnapshot {
+    pub fn new(metrics_history: Arc<dyn MetricsHistoryService>) -> Self {
+        Self { metrics_history }
+    }
+}
+
+#[async_trait::async_trait]
+impl Job for MetricsSnapshot {
+    fn name(&self) -> &'static str {
+        "metrics-snapshot"
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        "@every 5m"
+    }
+
+    async fn run(&self, _shutdown: &watch::Receiver<bool>) -> Result<String> {
+        Ok(match self.metrics_history.snapshot().await? {
+            Some(run) => format!(
+                "stored usage of {} bucket operation(s), removed {} day(s) of snapshots",
+                run.series, run.days_removed
+            ),
+            None => "snapshots are disabled".to_string(),
+        })
+    }
+}
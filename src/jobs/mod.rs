@@ -0,0 +1,279 @@
+pub mod bucket_deletions;
+mod bucket_tombstones;
+mod lifecycle;
+mod metrics;
+mod multipart;
+mod schedule;
+mod service_accounts;
+mod system_logs;
+
+pub use schedule::Schedule;
+
+use crate::config::Config;
+use crate::services::bucket_deletion::BucketDeletionService;
+use crate::services::lifecycle::LifecycleService;
+use crate::services::metrics_history::MetricsHistoryService;
+use crate::services::multipart::MultipartService;
+use crate::services::service_account::ServiceAccountService;
+use crate::services::system_log::SystemLogService;
+use anyhow::Result;
+use chrono::Utc;
+use log::{error, info, warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use tokio::sync::{Notify, watch};
+use tokio::task::JoinHandle;
+
+/// A named unit of background work run by the [`Scheduler`].
+#[async_trait::async_trait]
+pub trait Job: Send + Sync {
+    /// Name used in config (`jobs.<name>`) and in status reports.
+    fn name(&self) -> &'static str;
+    /// Schedule used when the config does not override it.
+    fn default_schedule(&self) -> &'static str;
+    /// Runs one pass of the job and returns a short human-readable summary.
+    /// Long-running jobs should check `*shutdown.borrow()` between units of
+    /// work and return early once it is set.
+    async fn run(&self, shutdown: &watch::Receiver<bool>) -> Result<String>;
+}
+
+/// Last-run bookkeeping for one job.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub name: String,
+    pub schedule: String,
+    pub enabled: bool,
+    pub running: bool,
+    pub runs: u64,
+    pub failures: u64,
+    pub last_started: Option<String>,
+    pub last_finished: Option<String>,
+    pub last_duration_ms: Option<u64>,
+    pub last_result: Option<String>,
+    pub last_error: Option<String>,
+    pub next_run: Option<String>,
+}
+
+/// Jobs built into the server. Features needing background work register here.
+pub fn builtin_jobs(
+    service_accounts: Arc<dyn ServiceAccountService>,
+    system_logs: Arc<dyn SystemLogService>,
+    bucket_deletions: Arc<dyn BucketDeletionService>,
+    multipart: Arc<dyn MultipartService>,
+    lifecycle: Arc<dyn LifecycleService>,
+    metrics_history: Arc<dyn MetricsHistoryService>,
+) -> Vec<Arc<dyn Job>> {
+    vec![
+        Arc::new(service_accounts::ServiceAccountExpiry::new(
+            service_accounts,
+        )),
+        Arc::new(system_logs::SystemLogPublish::new(system_logs)),
+        Arc::new(bucket_deletions::BucketForceDelete::new(
+            bucket_deletions.clone(),
+        )),
+        Arc::new(bucket_tombstones::BucketTombstoneExpiry::new(
+            bucket_deletions,
+        )),
+        Arc::new(multipart::MultipartExpiry::new(multipart)),
+        Arc::new(lifecycle::LifecycleExpiry::new(lifecycle)),
+        Arc::new(metrics::MetricsSnapshot::new(metrics_history)),
+    ]
+}
+
+/// Runs registered jobs on their schedules until shutdown is signalled.
+pub struct Scheduler {
+    handle: JobsHandle,
+    handles: Vec<JoinHandle<()>>,
+}
+
+/// Cheap, cloneable view of the scheduler for status reporting and manual runs.
+#[derive(Clone)]
+pub struct JobsHandle {
+    statuses: Arc<RwLock<BTreeMap<String, JobStatus>>>,
+    triggers: Arc<BTreeMap<String, Arc<Notify>>>,
+}
+
+impl JobsHandle {
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        self.statuses.read().unwrap().values().cloned().collect()
+    }
+
+    /// Requests an immediate run of `name`. Returns false for unknown jobs.
+    pub fn trigger(&self, name: &str) -> bool {
+        match self.triggers.get(name) {
+            Some(trigger) => {
+                trigger.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Scheduler {
+    pub fn start(cfg: &Config, jobs: Vec<Arc<dyn Job>>, shutdown: watch::Receiver<bool>) -> Self {
+        for name in cfg.jobs.keys() {
+            if !jobs.iter().any(|job| job.name() == name) {
+                warn!("jobs.{} is configured but no such job exists", name);
+            }
+        }
+
+        let statuses = Arc::new(RwLock::new(BTreeMap::new()));
+        let mut triggers = BTreeMap::new();
+        let mut handles = Vec::new();
+        for job in jobs {
+            let job_cfg = cfg.jobs.get(job.name());
+            let expr = job_cfg
+                .and_then(|c| c.schedule.clone())
+                .unwrap_or_else(|| job.default_schedule().to_string());
+            let enabled = job_cfg.is_none_or(|c| c.enabled);
+            // Config validation already parsed the override, so only a broken
+            // built-in default can fail here.
+            let schedule = match Schedule::parse(&expr) {
+                Ok(schedule) => schedule,
+                Err(e) => {
+                    error!("job {} has an invalid schedule: {}", job.name(), e);
+                    continue;
+                }
+            };
+            statuses.write().unwrap().insert(
+                job.name().to_string(),
+                JobStatus {
+                    name: job.name().to_string(),
+                    schedule: expr,
+                    enabled,
+                    running: false,
+                    runs: 0,
+                    failures: 0,
+                    last_started: None,
+                    last_finished: None,
+                    last_duration_ms: None,
+                    last_result: None,
+                    last_error: None,
+                    next_run: None,
+                },
+            );
+            let trigger = Arc::new(Notify::new());
+            triggers.insert(job.name().to_string(), trigger.clone());
+            let worker = Worker {
+                job,
+                schedule,
+                enabled,
+                trigger,
+                statuses: statuses.clone(),
+                shutdown: shutdown.clone(),
+            };
+            handles.push(tokio::spawn(worker.run()));
+        }
+        info!("Started job scheduler with {} job(s)", handles.len());
+        Self {
+            handle: JobsHandle {
+                statuses,
+                triggers: Arc::new(triggers),
+            },
+            handles,
+        }
+    }
+
+    pub fn handle(&self) -> JobsHandle {
+        self.handle.clone()
+    }
+
+    /// Waits for all job loops to exit. Call after shutdown has been signalled;
+    /// a job that is mid-run finishes its current pass first.
+    pub async fn shutdown(self) {
+        for handle in self.handles {
+            if let Err(e) = handle.await {
+                error!("job task panicked: {}", e);
+            }
+        }
+        for status in self.handle.statuses() {
+            info!(
+                "job {}: {} run(s), {} failure(s)",
+                status.name, status.runs, status.failures
+            );
+        }
+        info!("Job scheduler stopped");
+    }
+}
+
+struct Worker {
+    job: Arc<dyn Job>,
+    schedule: Schedule,
+    enabled: bool,
+    trigger: Arc<Notify>,
+    statuses: Arc<RwLock<BTreeMap<String, JobStatus>>>,
+    shutdown: watch::Receiver<bool>,
+}
+
+impl Worker {
+    async fn run(mut self) {
+        let name = self.job.name();
+        loop {
+            if *self.shutdown.borrow() {
+                break;
+            }
+            let next = if self.enabled {
+                self.schedule.next_after(Utc::now())
+            } else {
+                None
+            };
+            self.update(|s| s.next_run = next.map(|t| t.to_rfc3339()));
+            let sleep = async {
+                match next {
+                    Some(next) => {
+                        let wait = (next - Utc::now()).to_std().unwrap_or_default();
+                        tokio::time::sleep(wait).await
+                    }
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                _ = sleep => {}
+                _ = self.trigger.notified() => info!("job {} triggered manually", name),
+                _ = self.shutdown.changed() => break,
+            }
+            self.run_once().await;
+        }
+        info!("job {} stopped", name);
+    }
+
+    async fn run_once(&self) {
+        let name = self.job.name();
+        let started = Instant::now();
+        self.update(|s| {
+            s.running = true;
+            s.last_started = Some(Utc::now().to_rfc3339());
+        });
+        let result = self.job.run(&self.shutdown).await;
+        let elapsed = started.elapsed();
+        match &result {
+            Ok(summary) => info!("job {} finished in {:?}: {}", name, elapsed, summary),
+            Err(e) => error!("job {} failed after {:?}: {:#}", name, elapsed, e),
+        }
+        self.update(|s| {
+            s.running = false;
+            s.runs += 1;
+            s.last_finished = Some(Utc::now().to_rfc3339());
+            s.last_duration_ms = Some(elapsed.as_millis() as u64);
+            match result {
+                Ok(summary) => {
+                    s.last_result = Some(summary);
+                    s.last_error = None;
+                }
+                Err(e) => {
+                    s.failures += 1;
+                    s.last_error = Some(format!("{:#}", e));
+                }
+            }
+        });
+    }
+
+    fn update(&self, f: impl FnOnce(&mut JobStatus)) {
+        if let Some(status) = self.statuses.write().unwrap().get_mut(self.job.name()) {
+            f(status);
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use super::Job;
+use crate::services::multipart::MultipartService;
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Aborts multipart uploads older than `multipart.expiry_seconds`, deleting
+/// their parts, so abandoned uploads do not hold disk space forever.
+pub struct MultipartExpiry {
+    multipart: Arc<dyn MultipartService>,
+}
+
+impl MultipartExpiry {
+    pub fn new(multipart: Arc<dyn MultipartService>) -> Self {
+        Self { multipart }
+    }
+}
+
+#[async_trait::async_trait]
+impl Job for MultipartExpiry {
+    fn name(&self) -> &'static str {
+        "multipart-expiry"
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        "@every 1h"
+    }
+
+    async fn run(&self, shutdown: &watch::Receiver<bool>) -> Result<String> {
+        let aborted = self.multipart.abort_expired(shutdown).await?;
+        Ok(format!("aborted {} expired upload(s)", aborted))
+    }
+}
@@ -0,0 +1,235 @@
+use chrono::{DateTime, Datelike, Duration, DurationRound, Timelike, Utc};
+
+/// When a background job should run.
+///
+/// Accepts standard 5-field cron expressions (`minute hour day-of-month month
+/// day-of-week`, evaluated in UTC), the `@hourly`/`@daily`/`@weekly`/`@monthly`/
+/// `@yearly` shortcuts, and `@every <n>s|m|h` for fixed intervals.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    Cron(CronExpr),
+    Every(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronExpr {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    dom_restricted: bool,
+    dow_restricted: bool,
+}
+
+impl Schedule {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let expr = expr.trim();
+        let expanded = match expr {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            _ => expr,
+        };
+        if let Some(interval) = expanded.strip_prefix("@every ") {
+            return parse_interval(interval.trim()).map(Schedule::Every);
+        }
+        CronExpr::parse(expanded).map(Schedule::Cron)
+    }
+
+    /// First run time strictly after `after`.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Every(interval) => Some(after + *interval),
+            Schedule::Cron(cron) => cron.next_after(after),
+        }
+    }
+}
+
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid interval '{}', expected e.g. 30s, 15m or 6h", s);
+    let (value, unit) = s.split_at(s.len().saturating_sub(1));
+    let value: i64 = value.parse().map_err(|_| invalid())?;
+    if value <= 0 {
+        return Err(invalid());
+    }
+    match unit {
+        "s" => Ok(Duration::seconds(value)),
+        "m" => Ok(Duration::minutes(value)),
+        "h" => Ok(Duration::hours(value)),
+        _ => Err(invalid()),
+    }
+}
+
+impl CronExpr {
+    fn parse(expr: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "invalid schedule '{}': expected 5 cron fields or an @shortcut",
+                expr
+            ));
+        }
+        // Day-of-week accepts 0-7; both 0 and 7 mean Sunday.
+        let mut days_of_week = parse_field(fields[4], 0, 7)?;
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days_of_month: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            days_of_week,
+            // As in Vixie cron, a field starting with `*` (`*/2` too) is not
+            // restricted: its days narrow the other field's rather than add
+            // to them.
+            dom_restricted: !fields[2].starts_with('*'),
+            dow_restricted: !fields[4].starts_with('*'),
+        })
+    }
+
+    fn day_matches(&self, t: DateTime<Utc>) -> bool {
+        let dom = bit(self.days_of_month, t.day());
+        let dow = bit(self.days_of_week, t.weekday().num_days_from_sunday());
+        // Classic cron semantics: when both day fields are restricted, either
+        // may match; otherwise both must (a `*` field matches every day).
+        if self.dom_restricted && self.dow_restricted {
+            dom || dow
+        } else {
+            dom && dow
+        }
+    }
+
+    fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut t = after.duration_trunc(Duration::minutes(1)).ok()? + Duration::minutes(1);
+        // Four years covers every valid day-of-month/month combination (Feb 29).
+        let limit = after + Duration::days(4 * 366);
+        while t <= limit {
+            if !bit(self.months, t.month()) {
+                t = start_of_next_month(t)?;
+                continue;
+            }
+            if !self.day_matches(t) {
+                t = (t + Duration::days(1))
+                    .duration_trunc(Duration::days(1))
+                    .ok()?;
+                continue;
+            }
+            if !bit(self.hours, t.hour()) {
+                t = (t + Duration::hours(1))
+                    .duration_trunc(Duration::hours(1))
+                    .ok()?;
+                continue;
+            }
+            if !bit(self.minutes, t.minute()) {
+                t += Duration::minutes(1);
+                continue;
+            }
+            return Some(t);
+        }
+        None
+    }
+}
+
+fn bit(mask: u64, n: u32) -> bool {
+    mask & (1 << n) != 0
+}
+
+fn start_of_next_month(t: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let (year, month) = if t.month() == 12 {
+        (t.year() + 1, 1)
+    } else {
+        (t.year(), t.month() + 1)
+    };
+    t.with_day(1)?
+        .with_year(year)?
+        .with_month(month)?
+        .duration_trunc(Duration::days(1))
+        .ok()
+}
+
+/// Parses one cron field (`*`, `5`, `1-5`, `*/15`, `10-40/10`, comma lists) into a bitmask.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let invalid = || format!("invalid cron field '{}'", field);
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(invalid());
+        }
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (
+                a.parse().map_err(|_| invalid())?,
+                b.parse().map_err(|_| invalid())?,
+            )
+        } else {
+            let n: u32 = range.parse().map_err(|_| invalid())?;
+            // `5/10` means "from 5 to the end, every 10".
+            (n, if part.contains('/') { max } else { n })
+        };
+        if start < min || end > max || start > end {
+            return Err(format!(
+                "cron field '{}' out of range {}-{}",
+                field, min, max
+            ));
+        }
+        for n in (start..=end).step_by(step as usize) {
+            mask |= 1 << n;
+        }
+    }
+    Ok(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next(expr: &str, after: &str) -> String {
+        let after = DateTime::parse_from_rfc3339(after).unwrap().to_utc();
+        let next = Schedule::parse(expr).unwrap().next_after(after).unwrap();
+        next.to_rfc3339()
+    }
+
+    #[test]
+    fn stepped_day_of_month_narrows_day_of_week() {
+        // 2024-01-01 is a Monday: the 3rd is in */2 but no Monday, the 8th
+        // a Monday not in */2.
+        assert_eq!(
+            next("0 0 */2 * 1", "2024-01-01T00:00:00Z"),
+            "2024-01-15T00:00:00+00:00"
+        );
+        assert_eq!(
+            next("0 0 * * 1", "2024-01-01T00:00:00Z"),
+            "2024-01-08T00:00:00+00:00"
+        );
+        assert_eq!(
+            next("0 0 3 * */2", "2024-01-01T00:00:00Z"),
+            "2024-02-03T00:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn restricted_day_fields_match_either() {
+        // The 3rd (a Wednesday) through day-of-month, the 8th through Monday.
+        assert_eq!(
+            next("0 0 3 * 1", "2024-01-01T00:00:00Z"),
+            "2024-01-03T00:00:00+00:00"
+        );
+        assert_eq!(
+            next("0 0 3 * 1", "2024-01-03T00:00:00Z"),
+            "2024-01-08T00:00:00+00:00"
+        );
+        assert_eq!(
+            next("0 0 */2 * *", "2024-01-01T00:00:00Z"),
+            "2024-01-03T00:00:00+00:00"
+        );
+    }
+}
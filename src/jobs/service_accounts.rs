@@ -0,0 +1,33 @@
+use super::Job;
+use crate::services::service_account::ServiceAccountService;
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Deletes service accounts past their TTL. Expired accounts are already
+/// rejected at authentication; this only keeps the stored list short.
+pub struct ServiceAccountExpiry {
+    service_accounts: Arc<dyn ServiceAccountService>,
+}
+
+impl ServiceAccountExpiry {
+    pub fn new(service_accounts: Arc<dyn ServiceAccountService>) -> Self {
+        Self { service_accounts }
+    }
+}
+
+#[async_trait::async_trait]
+impl Job for ServiceAccountExpiry {
+    fn name(&self) -> &'static str {
+        "service-account-expiry"
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        "@every 10m"
+    }
+
+    async fn run(&self, _shutdown: &watch::Receiver<bool>) -> Result<String> {
+        let removed = self.service_accounts.purge_expired().await?;
+        Ok(format!("removed {} expired service account(s)", removed))
+    }
+}
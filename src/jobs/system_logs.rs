@@ -0,0 +1,34 @@
+use super::Job;
+use crate::services::system_log::SystemLogService;
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Publishes the access and audit logs of completed days as objects into the
+/// system bucket. Runs hourly so a day missed while the server was down is
+/// picked up soon after restart.
+pub struct SystemLogPublish {
+    system_logs: Arc<dyn SystemLogService>,
+}
+
+impl SystemLogPublish {
+    pub fn new(system_logs: Arc<dyn SystemLogService>) -> Self {
+        Self { system_logs }
+    }
+}
+
+#[async_trait::async_trait]
+impl Job for SystemLogPublish {
+    fn name(&self) -> &'static str {
+        "system-log-publish"
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        "@hourly"
+    }
+
+    async fn run(&self, _shutdown: &watch::Receiver<bool>) -> Result<String> {
+        let published = self.system_logs.publish_completed_days().await?;
+        Ok(format!("published {} log object(s)", published))
+    }
+}
@@ -0,0 +1,25 @@
+pub mod admin;
+pub mod api;
+pub mod aws_chunked;
+pub mod bandwidth;
+pub mod base64;
+pub mod checksum;
+pub mod clock;
+pub mod compression;
+pub mod config;
+pub mod crypto;
+pub mod error;
+pub mod header_encoding;
+pub mod jobs;
+pub mod listener;
+pub mod metrics;
+pub mod middleware;
+pub mod mirror;
+pub mod models;
+pub mod server;
+pub mod services;
+pub mod sigv4;
+pub mod storage;
+pub mod systemd;
+pub mod warmup;
+pub mod webhook;
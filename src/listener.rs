@@ -0,0 +1,265 @@
+use axum::serve::Listener;
+use log::{debug, warn};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// How long a client may take to send its PROXY protocol header.
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+/// The longest v1 header allowed by the spec, CRLF included.
+const V1_MAX_LENGTH: usize = 107;
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The connection's peer: the socket address, or with PROXY protocol the
+/// source address the proxy reported. Available to handlers as
+/// `ConnectInfo<PeerAddr>`.
+#[derive(Debug, Clone, Copy)]
+pub struct PeerAddr(pub SocketAddr);
+
+/// TCP listener that optionally expects a PROXY protocol (v1 or v2) header
+/// at the start of every connection. Headers are read off the accept path,
+/// so slow clients cannot hold up other connections; connections without a
+/// valid header are closed.
+pub struct ProxyListener {
+    local_addr: SocketAddr,
+    mode: Mode,
+}
+
+enum Mode {
+    Plain(TcpListener),
+    Proxy {
+        accepted: mpsc::Receiver<(TcpStream, SocketAddr)>,
+        acceptor: JoinHandle<()>,
+    },
+}
+
+impl ProxyListener {
+    pub async fn bind(addr: &str, proxy_protocol: bool) -> io::Result<Self> {
+        Self::new(TcpListener::bind(addr).await?, proxy_protocol)
+    }
+
+    /// Serves an already listening socket, e.g. one passed by systemd.
+    pub fn from_std(listener: std::net::TcpListener, proxy_protocol: bool) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+        Self::new(TcpListener::from_std(listener)?, proxy_protocol)
+    }
+
+    fn new(listener: TcpListener, proxy_protocol: bool) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        if !proxy_protocol {
+            return Ok(Self {
+                local_addr,
+                mode: Mode::Plain(listener),
+            });
+        }
+        let (tx, accepted) = mpsc::channel(128);
+        let acceptor = tokio::spawn(async move {
+            loop {
+                let (mut stream, socket_addr) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        warn!("accept failed: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HEADER_TIMEOUT, read_header(&mut stream)).await {
+                        Ok(Ok(source)) => {
+                            let _ = tx.send((stream, source.unwrap_or(socket_addr))).await;
+                        }
+                        Ok(Err(e)) => debug!("rejected {}: {}", socket_addr, e),
+                        Err(_) => debug!("rejected {}: no PROXY header in time", socket_addr),
+                    }
+                });
+            }
+        });
+        Ok(Self {
+            local_addr,
+            mode: Mode::Proxy { accepted, acceptor },
+        })
+    }
+}
+
+impl Drop for ProxyListener {
+    fn drop(&mut self) {
+        if let Mode::Proxy { acceptor, .. } = &self.mode {
+            acceptor.abort();
+        }
+    }
+}
+
+impl Listener for ProxyListener {
+    type Io = TcpStream;
+    type Addr = PeerAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match &mut self.mode {
+            Mode::Plain(listener) => {
+                let (stream, addr) = Listener::accept(listener).await;
+                (stream, PeerAddr(addr))
+            }
+            Mode::Proxy { accepted, .. } => match accepted.recv().await {
+                Some((stream, addr)) => (stream, PeerAddr(addr)),
+                // The acceptor only stops when the listener is dropped.
+                None => std::future::pending().await,
+            },
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(PeerAddr(self.local_addr))
+    }
+}
+
+/// Reads a PROXY protocol header and returns the reported source address.
+/// `None` means the header carries no address (v2 `LOCAL`, v1 `UNKNOWN`, or
+/// a non-IP family), e.g. for the proxy's own health checks.
+async fn read_header(stream: &mut TcpStream) -> io::Result<Option<SocketAddr>> {
+    let mut start = [0u8; 5];
+    stream.read_exact(&mut start).await?;
+    if &start == b"PROXY" {
+        read_v1(stream).await
+    } else if start == V2_SIGNATURE[..5] {
+        read_v2(stream).await
+    } else {
+        Err(invalid("missing PROXY protocol header"))
+    }
+}
+
+/// `PROXY TCP4 <src> <dst> <src port> <dst port>\r\n`, after `PROXY`.
+async fn read_v1(stream: &mut TcpStream) -> io::Result<Option<SocketAddr>> {
+    let mut line = Vec::new();
+    while !line.ends_with(b"\r\n") {
+        if line.len() + 5 >= V1_MAX_LENGTH {
+            return Err(invalid("PROXY v1 header too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+    let line = std::str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| invalid("PROXY v1 header is not ASCII"))?;
+    let fields: Vec<&str> = line.split(' ').skip(1).collect();
+    match fields.as_slice() {
+        ["UNKNOWN", ..] => Ok(None),
+        ["TCP4" | "TCP6", source, _, port, _] => {
+            let ip: IpAddr = source
+                .parse()
+                .map_err(|_| invalid("bad PROXY v1 address"))?;
+            let port: u16 = port.parse().map_err(|_| invalid("bad PROXY v1 port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("malformed PROXY v1 header")),
+    }
+}
+
+/// The binary v2 header, after the first 5 signature bytes.
+async fn read_v2(stream: &mut TcpStream) -> io::Result<Option<SocketAddr>> {
+    let mut header = [0u8; 11];
+    stream.read_exact(&mut header).await?;
+    if header[..7] != V2_SIGNATURE[5..] {
+        return Err(invalid("bad PROXY v2 signature"));
+    }
+    let (version_command, family) = (header[7], header[8]);
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+    let mut payload = vec![0u8; u16::from_be_bytes([header[9], header[10]]) as usize];
+    stream.read_exact(&mut payload).await?;
+    match (version_command & 0x0f, family >> 4) {
+        (0x0, _) => Ok(None), // LOCAL
+        (0x1, 0x1) if payload.len() >= 12 => {
+            let ip = Ipv4Addr::new(payload[0], payload[1], payload[2], payload[3]);
+            let port = u16::from_be_bytes([payload[8], payload[9]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        (0x1, 0x2) if payload.len() >= 36 => {
+            let octets: [u8; 16] = payload[..16].try_into().unwrap();
+            let port = u16::from_be_bytes([payload[32], payload[33]]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(octets).into(), port)))
+        }
+        (0x1, _) => Ok(None), // AF_UNIX or AF_UNSPEC
+        _ => Err(invalid("unsupported PROXY v2 command")),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// An address range such as `10.0.0.0/8`, `fd00::/8` or a single address.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value, None),
+        };
+        let network: IpAddr = addr
+            .parse()
+            .map_err(|_| format!("{} is not an IP address or CIDR range", value))?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| format!("{} has an invalid prefix length", value))?,
+            None => max,
+        };
+        Ok(Self { network, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => prefix_eq(
+                u32::from(network).into(),
+                u32::from(ip).into(),
+                self.prefix,
+                32,
+            ),
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                prefix_eq(u128::from(network), u128::from(ip), self.prefix, 128)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_eq(a: u128, b: u128, prefix: u8, bits: u32) -> bool {
+    let shift = bits - u32::from(prefix);
+    shift >= bits || (a >> shift) == (b >> shift)
+}
+
+/// The client address of a request. Starting from the peer, hops are taken
+/// from the right of `X-Forwarded-For` for as long as the current hop is a
+/// trusted proxy, so clients cannot spoof their address by sending the
+/// header themselves.
+pub fn client_ip(peer: IpAddr, forwarded_for: &[&str], trusted: &[Cidr]) -> IpAddr {
+    let is_trusted = |ip: IpAddr| trusted.iter().any(|cidr| cidr.contains(ip));
+    let mut client = peer.to_canonical();
+    let hops = forwarded_for
+        .iter()
+        .rev()
+        .flat_map(|header| header.rsplit(','))
+        .map(str::trim);
+    for hop in hops {
+        if !is_trusted(client) {
+            break;
+        }
+        match hop.parse::<IpAddr>() {
+            Ok(ip) => client = ip.to_canonical(),
+            Err(_) => break,
+        }
+    }
+    client
+}
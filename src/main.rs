@@ -1,13 +1,265 @@
-use crate::config::Config;
-use log::info;
-
-mod config;
-mod server;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use s3_clone::bandwidth::Bandwidth;
+use s3_clone::clock::{Clock, FakeClock, SystemClock};
+use s3_clone::config::{CONFIG_VERSION, Config};
+use s3_clone::jobs;
+use s3_clone::metrics::Metrics;
+use s3_clone::mirror::Mirror;
+use s3_clone::server::{self, AppState};
+use s3_clone::services::auth::AuthServiceImpl;
+use s3_clone::services::auth_provider;
+use s3_clone::services::bucket::BucketServiceImpl;
+use s3_clone::services::bucket_deletion::{BucketDeletionService, BucketDeletionServiceImpl};
+use s3_clone::services::kms::{KmsService, KmsServiceImpl};
+use s3_clone::services::lifecycle::{LifecycleService, LifecycleServiceImpl};
+use s3_clone::services::metrics_history::{MetricsHistoryService, MetricsHistoryServiceImpl};
+use s3_clone::services::multipart::{MultipartService, MultipartServiceImpl};
+use s3_clone::services::notification::{NotificationService, NotificationServiceImpl};
+use s3_clone::services::object::ObjectServiceImpl;
+use s3_clone::services::service_account::{ServiceAccountService, ServiceAccountServiceImpl};
+use s3_clone::services::sts::{StsService, StsServiceImpl};
+use s3_clone::services::system_log::{SystemLogService, SystemLogServiceImpl};
+use s3_clone::storage::Storage;
+use s3_clone::systemd;
+use s3_clone::warmup;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
 
 #[tokio::main]
 async fn main() {
     env_logger::init();
-    let cfg = Config::load_from_file("config.yaml").unwrap();
-    info!("Loaded config from config.yaml");
-    server::run(cfg).await;
+    let args = parse_args();
+    let config_path = "config.yaml".to_string();
+    if let Some(out) = args.migrate_config {
+        migrate_config(&config_path, &out);
+        return;
+    }
+    let cfg = Config::load_from_file(&config_path).unwrap();
+    info!("Loaded config from {}", config_path);
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutdown signal received");
+        systemd::notify("STOPPING=1");
+        let _ = shutdown_tx.send(true);
+    });
+    if let Some(interval) = systemd::watchdog_interval() {
+        info!(
+            "Pinging the systemd watchdog every {} ms",
+            interval.as_millis()
+        );
+        tokio::spawn(watchdog(interval, shutdown_rx.clone()));
+    }
+
+    let clock: Arc<dyn Clock> = match args.fake_time {
+        Some(time) => {
+            warn!("Using a fake clock starting at {}", time.to_rfc3339());
+            Arc::new(FakeClock::starting_at(time))
+        }
+        None => Arc::new(SystemClock),
+    };
+    let metrics = Arc::new(Metrics::new());
+    let storage = Arc::new(Storage::new(&cfg.storage, clock.clone(), metrics.clone()).unwrap());
+    info!(
+        "Using {} storage at {}",
+        storage.backend_name(),
+        cfg.storage.location
+    );
+
+    let service_accounts: Arc<dyn ServiceAccountService> =
+        Arc::new(ServiceAccountServiceImpl::new(storage.clone(), clock.clone()).unwrap());
+
+    let config = Arc::new(RwLock::new(cfg));
+    let system_logs: Arc<dyn SystemLogService> =
+        Arc::new(SystemLogServiceImpl::new(storage.clone(), config.clone()));
+    let bucket_deletions: Arc<dyn BucketDeletionService> =
+        Arc::new(BucketDeletionServiceImpl::new(storage.clone(), clock.clone()).unwrap());
+
+    let sts: Arc<dyn StsService> = Arc::new(StsServiceImpl::new(config.clone(), clock.clone()));
+    let kms: Arc<dyn KmsService> =
+        Arc::new(KmsServiceImpl::new(storage.clone(), config.clone()).unwrap());
+    let notifications: Arc<dyn NotificationService> = Arc::new(
+        NotificationServiceImpl::new(storage.clone(), config.clone(), clock.clone()).unwrap(),
+    );
+    let multipart: Arc<dyn MultipartService> = Arc::new(MultipartServiceImpl::new(
+        storage.clone(),
+        config.clone(),
+        kms.clone(),
+        notifications.clone(),
+        clock.clone(),
+    ));
+    let lifecycle: Arc<dyn LifecycleService> = Arc::new(LifecycleServiceImpl::new(
+        storage.clone(),
+        notifications.clone(),
+        clock.clone(),
+    ));
+    let metrics_history: Arc<dyn MetricsHistoryService> = Arc::new(MetricsHistoryServiceImpl::new(
+        storage.clone(),
+        config.clone(),
+        metrics.clone(),
+        clock.clone(),
+    ));
+
+    let scheduler = jobs::Scheduler::start(
+        &config.read().unwrap(),
+        jobs::builtin_jobs(
+            service_accounts.clone(),
+            system_logs.clone(),
+            bucket_deletions.clone(),
+            multipart.clone(),
+            lifecycle,
+            metrics_history.clone(),
+        ),
+        shutdown_rx.clone(),
+    );
+    let dispatcher = tokio::spawn({
+        let notifications = notifications.clone();
+        let shutdown = shutdown_rx.clone();
+        async move { notifications.run_dispatcher(shutdown).await }
+    });
+    let state = AppState {
+        auth: Arc::new(AuthServiceImpl::new(
+            auth_provider::providers(&config, service_accounts.clone(), sts.clone()),
+            storage.clone(),
+            config.clone(),
+            clock.clone(),
+        )),
+        buckets: Arc::new(BucketServiceImpl::new(storage.clone(), config.clone())),
+        objects: Arc::new(ObjectServiceImpl::new(
+            storage.clone(),
+            config.clone(),
+            kms.clone(),
+            notifications.clone(),
+        )),
+        mirror: Arc::new(Mirror::new(config.clone(), storage.clone())),
+        multipart,
+        kms,
+        notifications,
+        service_accounts,
+        sts,
+        config,
+        config_path,
+        jobs: scheduler.handle(),
+        storage,
+        started: Instant::now(),
+        draining: Arc::new(AtomicBool::new(false)),
+        metrics,
+        metrics_history,
+        bandwidth: Arc::new(Bandwidth::default()),
+        system_logs,
+        bucket_deletions,
+        clock,
+    };
+    let warmup_config = state.config.read().unwrap().warmup.clone();
+    warmup::run(state.storage.clone(), &warmup_config).await;
+    server::run(state, shutdown_rx).await;
+    scheduler.shutdown().await;
+    let _ = dispatcher.await;
+}
+
+struct Args {
+    fake_time: Option<DateTime<Utc>>,
+    migrate_config: Option<String>,
+}
+
+/// Parses the command line: `--fake-time <RFC 3339>` starts the server's
+/// clock at the given time instead of now (it then advances normally), to
+/// try out expiry by hand. Job schedules keep following the real clock.
+/// `--migrate-config <path>` writes the config migrated to the current
+/// `config_version` to `path` instead of starting the server.
+fn parse_args() -> Args {
+    let mut parsed = Args {
+        fake_time: None,
+        migrate_config: None,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (arg.clone(), None),
+        };
+        if name != "--fake-time" && name != "--migrate-config" {
+            usage(&format!("unknown argument {}", arg));
+        }
+        let Some(value) = value.or_else(|| args.next()) else {
+            usage(&format!("{} needs a value", name));
+        };
+        if name == "--migrate-config" {
+            parsed.migrate_config = Some(value);
+            continue;
+        }
+        match DateTime::parse_from_rfc3339(&value) {
+            Ok(time) => parsed.fake_time = Some(time.to_utc()),
+            Err(e) => usage(&format!("invalid --fake-time {}: {}", value, e)),
+        }
+    }
+    parsed
+}
+
+fn usage(error: &str) -> ! {
+    eprintln!("error: {}", error);
+    eprintln!("usage: s3-clone [--fake-time <RFC 3339 time>] [--migrate-config <path>]");
+    std::process::exit(2);
+}
+
+/// Writes the config at `config_path`, migrated, to `out`, which must not
+/// exist yet; nothing is written for configs in the current layout.
+fn migrate_config(config_path: &str, out: &str) {
+    let migrated = match Config::migrated_file(config_path) {
+        Ok(Some(migrated)) => migrated,
+        Ok(None) => {
+            eprintln!(
+                "{} already has config_version {}; nothing to migrate",
+                config_path, CONFIG_VERSION
+            );
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let written = std::fs::File::create_new(out)
+        .and_then(|mut file| std::io::Write::write_all(&mut file, migrated.as_bytes()));
+    if let Err(e) = written {
+        eprintln!("error: cannot write {}: {}", out, e);
+        std::process::exit(1);
+    }
+    eprintln!(
+        "Wrote {} migrated to config_version {} to {}",
+        config_path, CONFIG_VERSION, out
+    );
+}
+
+/// Sends `WATCHDOG=1` every `interval` until shutdown; pings stop if the
+/// runtime stalls, so systemd restarts a hung server.
+async fn watchdog(interval: Duration, mut shutdown: watch::Receiver<bool>) {
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => systemd::notify("WATCHDOG=1"),
+            _ = shutdown.wait_for(|stop| *stop) => return,
+        }
+    }
+}
+
+/// Resolves on SIGTERM or Ctrl-C.
+#[cfg(unix)]
+async fn shutdown_signal() {
+    use tokio::signal::unix::{SignalKind, signal};
+    let mut sigterm = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+    tokio::select! {
+        _ = sigterm.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+/// Resolves on Ctrl-C.
+#[cfg(not(unix))]
+async fn shutdown_signal() {
+    let _ = tokio::signal::ctrl_c().await;
 }
@@ -0,0 +1,336 @@
+//! In-process request and storage metrics, rendered in the Prometheus text
+//! format by `GET /admin/v1/metrics`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Upper bounds of the size histogram buckets, in bytes: 1 KiB, 64 KiB,
+/// 1 MiB, 16 MiB, 128 MiB, 1 GiB and the 5 GiB single-PUT limit.
+pub const SIZE_BUCKETS: &[u64] = &[
+    1 << 10,
+    64 << 10,
+    1 << 20,
+    16 << 20,
+    128 << 20,
+    1 << 30,
+    5 << 30,
+];
+
+/// Upper bounds of the storage operation duration buckets, in
+/// microseconds: 100 µs (page cache hits) up to 5 s (a stalled disk).
+pub const DURATION_BUCKETS_MICROS: &[u64] = &[
+    100, 1_000, 5_000, 25_000, 100_000, 500_000, 1_000_000, 5_000_000,
+];
+
+/// Label used for buckets beyond the `metrics.max_bucket_labels` limit.
+pub const OTHER_BUCKET: &str = "__other__";
+
+/// Operations requests are counted under: their HTTP methods, with any
+/// other method counted as `OTHER`.
+const OPERATIONS: &[&str] = &["GET", "HEAD", "PUT", "POST", "DELETE", "OPTIONS"];
+
+/// Requests and payload bytes of one bucket and operation (the HTTP
+/// method), since the server started or over one snapshot interval.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub bucket: String,
+    pub operation: String,
+    pub requests: u64,
+    /// Requests answered with an error status.
+    pub errors: u64,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct RequestCounts {
+    requests: u64,
+    errors: u64,
+    request_bytes: u64,
+    response_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Per-bucket (non-cumulative) counts; the last entry is `+Inf`.
+    counts: [u64; SIZE_BUCKETS.len() + 1],
+    sum: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: u64) {
+        let slot = SIZE_BUCKETS
+            .iter()
+            .position(|&bound| value <= bound)
+            .unwrap_or(SIZE_BUCKETS.len());
+        self.counts[slot] += 1;
+        self.sum += value;
+    }
+
+    fn render(&self, out: &mut String, name: &str, bucket: &str) {
+        let mut cumulative = 0;
+        for (i, count) in self.counts.iter().enumerate() {
+            cumulative += count;
+            let le = SIZE_BUCKETS
+                .get(i)
+                .map_or_else(|| "+Inf".to_string(), u64::to_string);
+            let _ = writeln!(
+                out,
+                "{}_bucket{{bucket=\"{}\",le=\"{}\"}} {}",
+                name, bucket, le, cumulative
+            );
+        }
+        let _ = writeln!(out, "{}_sum{{bucket=\"{}\"}} {}", name, bucket, self.sum);
+        let _ = writeln!(
+            out,
+            "{}_count{{bucket=\"{}\"}} {}",
+            name, bucket, cumulative
+        );
+    }
+}
+
+/// Like [`Histogram`] for durations, rendered in seconds.
+#[derive(Debug, Clone, Default)]
+struct DurationHistogram {
+    counts: [u64; DURATION_BUCKETS_MICROS.len() + 1],
+    sum_micros: u64,
+}
+
+impl DurationHistogram {
+    fn observe(&mut self, duration: Duration) {
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        let slot = DURATION_BUCKETS_MICROS
+            .iter()
+            .position(|&bound| micros <= bound)
+            .unwrap_or(DURATION_BUCKETS_MICROS.len());
+        self.counts[slot] += 1;
+        self.sum_micros = self.sum_micros.saturating_add(micros);
+    }
+
+    fn render(&self, out: &mut String, name: &str, operation: &str) {
+        let mut cumulative = 0;
+        for (i, count) in self.counts.iter().enumerate() {
+            cumulative += count;
+            let le = DURATION_BUCKETS_MICROS
+                .get(i)
+                .map_or_else(|| "+Inf".to_string(), |&b| seconds(b).to_string());
+            let _ = writeln!(
+                out,
+                "{}_bucket{{operation=\"{}\",le=\"{}\"}} {}",
+                name, operation, le, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_sum{{operation=\"{}\"}} {}",
+            name,
+            operation,
+            seconds(self.sum_micros)
+        );
+        let _ = writeln!(
+            out,
+            "{}_count{{operation=\"{}\"}} {}",
+            name, operation, cumulative
+        );
+    }
+}
+
+fn seconds(micros: u64) -> f64 {
+    micros as f64 / 1e6
+}
+
+#[derive(Debug, Clone, Default)]
+struct BucketMetrics {
+    request: Histogram,
+    response: Histogram,
+    requests: BTreeMap<&'static str, RequestCounts>,
+}
+
+/// Request and response payload sizes and request counts per bucket (and
+/// operation). Only the first
+/// `max_bucket_labels` buckets that see a successful request get their own
+/// label; everything else is counted under [`OTHER_BUCKET`], so clients
+/// probing random bucket names cannot blow up the label set.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    buckets: Mutex<HashMap<String, BucketMetrics>>,
+    /// Durations of [`Storage`](crate::storage::Storage) operations by
+    /// method name; a fixed set, so no label cap is needed.
+    storage: Mutex<HashMap<&'static str, DurationHistogram>>,
+    in_flight: AtomicU64,
+    /// Requests whose handler panicked.
+    panics: AtomicU64,
+}
+
+/// Counts a request as in flight until dropped.
+pub struct InFlight<'a>(&'a AtomicU64);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe_request(
+        &self,
+        bucket: &str,
+        method: &str,
+        success: bool,
+        request_bytes: u64,
+        response_bytes: u64,
+        max_bucket_labels: usize,
+    ) {
+        let mut buckets = self.buckets.lock().unwrap();
+        let labelled = buckets.len() - usize::from(buckets.contains_key(OTHER_BUCKET));
+        let label = if buckets.contains_key(bucket) || (success && labelled < max_bucket_labels) {
+            bucket
+        } else {
+            OTHER_BUCKET
+        };
+        let entry = buckets.entry(label.to_string()).or_default();
+        entry.request.observe(request_bytes);
+        entry.response.observe(response_bytes);
+        let operation = OPERATIONS
+            .iter()
+            .find(|operation| **operation == method)
+            .unwrap_or(&"OTHER");
+        let counts = entry.requests.entry(operation).or_default();
+        counts.requests += 1;
+        counts.errors += u64::from(!success);
+        counts.request_bytes += request_bytes;
+        counts.response_bytes += response_bytes;
+    }
+
+    /// Request counts and bytes since the server started, by bucket and
+    /// operation.
+    pub fn usage(&self) -> Vec<Usage> {
+        let buckets = self.buckets.lock().unwrap();
+        let mut usage: Vec<Usage> = buckets
+            .iter()
+            .flat_map(|(bucket, metrics)| {
+                metrics.requests.iter().map(|(operation, counts)| Usage {
+                    bucket: bucket.clone(),
+                    operation: operation.to_string(),
+                    requests: counts.requests,
+                    errors: counts.errors,
+                    request_bytes: counts.request_bytes,
+                    response_bytes: counts.response_bytes,
+                })
+            })
+            .collect();
+        usage.sort_by(|a, b| (&a.bucket, &a.operation).cmp(&(&b.bucket, &b.operation)));
+        usage
+    }
+
+    pub fn observe_storage(&self, operation: &'static str, duration: Duration) {
+        let mut storage = self.storage.lock().unwrap();
+        storage.entry(operation).or_default().observe(duration);
+    }
+
+    pub fn start_request(&self) -> InFlight<'_> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight(&self.in_flight)
+    }
+
+    pub fn record_panic(&self) {
+        self.panics.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// S3 requests currently being served.
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// All metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let metrics = self.buckets.lock().unwrap();
+        let mut buckets: Vec<&String> = metrics.keys().collect();
+        buckets.sort();
+        let mut out = String::new();
+        for (name, help, pick) in [
+            (
+                "s3clone_request_size_bytes",
+                "Size of S3 request bodies by bucket.",
+                (|s: &BucketMetrics| &s.request) as fn(&BucketMetrics) -> &Histogram,
+            ),
+            (
+                "s3clone_response_size_bytes",
+                "Size of S3 response bodies by bucket.",
+                |s: &BucketMetrics| &s.response,
+            ),
+        ] {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} histogram", name);
+            for bucket in &buckets {
+                pick(&metrics[*bucket]).render(&mut out, name, bucket);
+            }
+        }
+        for (name, help, pick) in [
+            (
+                "s3clone_requests_total",
+                "S3 requests by bucket and operation.",
+                (|c: &RequestCounts| c.requests) as fn(&RequestCounts) -> u64,
+            ),
+            (
+                "s3clone_request_errors_total",
+                "S3 requests answered with an error by bucket and operation.",
+                |c: &RequestCounts| c.errors,
+            ),
+        ] {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for bucket in &buckets {
+                for (operation, counts) in &metrics[*bucket].requests {
+                    let _ = writeln!(
+                        out,
+                        "{}{{bucket=\"{}\",operation=\"{}\"}} {}",
+                        name,
+                        bucket,
+                        operation,
+                        pick(counts)
+                    );
+                }
+            }
+        }
+        drop(metrics);
+        let storage = self.storage.lock().unwrap();
+        let mut operations: Vec<&&str> = storage.keys().collect();
+        operations.sort();
+        let name = "s3clone_storage_operation_duration_seconds";
+        let _ = writeln!(
+            out,
+            "# HELP {} Duration of storage operations by operation.",
+            name
+        );
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for operation in operations {
+            storage[*operation].render(&mut out, name, operation);
+        }
+        let _ = writeln!(
+            out,
+            "# HELP s3clone_requests_in_flight S3 requests being served."
+        );
+        let _ = writeln!(out, "# TYPE s3clone_requests_in_flight gauge");
+        let _ = writeln!(out, "s3clone_requests_in_flight {}", self.in_flight());
+        let _ = writeln!(
+            out,
+            "# HELP s3clone_panics_total S3 requests whose handler panicked."
+        );
+        let _ = writeln!(out, "# TYPE s3clone_panics_total counter");
+        let _ = writeln!(
+            out,
+            "s3clone_panics_total {}",
+            self.panics.load(Ordering::Relaxed)
+        );
+        out
+    }
+}
@@ -1,23 +1,816 @@
-use crate::services::auth::AuthService;
+use crate::api::{cors, digest, form_upload};
+use crate::bandwidth;
+use crate::compression;
+use crate::config::FeaturesConfig;
+use crate::error::S3Error;
+use crate::listener::{self, Cidr, PeerAddr};
+use crate::mirror::{self, BodySource, RequestHead};
 use crate::models::AuthContext;
+use crate::server::AppState;
+use crate::services::auth::Authentication;
+use crate::services::system_log::{self, AccessLogEntry};
+use crate::sigv4::{CONTENT_SHA256_HEADER, SignedRequest};
+use crate::storage::validate_bucket_name;
+use axum::body::{Body, HttpBody};
+use axum::extract::{ConnectInfo, Query, RawPathParams, Request, State};
+use axum::http::{HeaderName, HeaderValue, Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use futures_util::StreamExt;
+use log::{debug, error, warn};
+use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
 
-// Pseudocode: Replace with actual middleware for your web framework (e.g., Axum, Actix, etc.)
-pub struct AuthMiddleware<S> {
-    pub service: Arc<dyn AuthService>,
-    pub inner: S,
+pub const REQUEST_ID_HEADER: &str = "x-amz-request-id";
+/// Extension header: milliseconds the client is willing to wait.
+pub const DEADLINE_HEADER: &str = "x-s3clone-deadline-ms";
+const BUCKET_REGION_HEADER: &str = "x-amz-bucket-region";
+/// Largest body hashed for a signature when `x-amz-content-sha256` is
+/// missing; such requests (STS calls) are small forms.
+const MAX_UNHASHED_BODY: usize = 1024 * 1024;
+
+/// Request ID assigned by [`request_id`], available to handlers as an extension.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Client address resolved by [`client_ip`], available to handlers as an
+/// extension.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+/// Access key that authenticated the request, attached to the response for
+/// the outer logging layers.
+#[derive(Debug, Clone)]
+pub struct Requester(pub String);
+
+/// Response extension marking generated bodies (listings, errors) that
+/// [`compress`] may gzip. Object payloads never carry it.
+#[derive(Debug, Clone, Copy)]
+pub struct Compressible;
+
+/// Bodies smaller than this are not worth the gzip framing.
+const MIN_COMPRESS_SIZE: usize = 256;
+
+/// Resolves the client address from the connection peer (as reported by the
+/// PROXY protocol, if enabled) and `X-Forwarded-For` hops added by
+/// `server.trusted_proxies`.
+pub async fn client_ip(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    if let Some(ConnectInfo(PeerAddr(peer))) = req.extensions().get::<ConnectInfo<PeerAddr>>() {
+        let trusted: Vec<Cidr> = state
+            .config
+            .read()
+            .unwrap()
+            .server
+            .trusted_proxies
+            .iter()
+            .filter_map(|proxy| Cidr::parse(proxy).ok())
+            .collect();
+        let forwarded_for: Vec<&str> = req
+            .headers()
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect();
+        let ip = listener::client_ip(peer.ip(), &forwarded_for, &trusted);
+        req.extensions_mut().insert(ClientIp(ip));
+    }
+    next.run(req).await
+}
+
+/// Assigns every S3 request an ID, echoes it in `x-amz-request-id` and
+/// renders [`S3Error`] bodies (which need the ID) on the way out.
+pub async fn request_id(mut req: Request, next: Next) -> Response {
+    let id = Uuid::new_v4().simple().to_string()[..16].to_uppercase();
+    match req.extensions().get::<ClientIp>() {
+        Some(ClientIp(ip)) => debug!("{} {} {} from {}", id, req.method(), req.uri(), ip),
+        None => debug!("{} {} {}", id, req.method(), req.uri()),
+    }
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut response = next.run(req).await;
+    if let Some(err) = response.extensions_mut().remove::<S3Error>() {
+        debug!("{} failed: {}", id, err);
+        let (mut parts, _) = response.into_parts();
+        let body = err.to_xml(&id);
+        parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+        parts.extensions.insert(Compressible);
+        response = Response::from_parts(parts, Body::from(body));
+    }
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Refuses requests whose `{bucket}` is not a valid bucket name with
+/// `400 InvalidBucketName` before any handler maps it onto the storage
+/// directory. The name is checked as decoded, so encoded separators
+/// (`foo%2F..%2F..`) cannot reach paths outside the bucket.
+pub async fn bucket_name(params: RawPathParams, req: Request, next: Next) -> Response {
+    if let Some((_, bucket)) = params.iter().find(|(name, _)| *name == "bucket")
+        && let Err(e) = validate_bucket_name(bucket)
+    {
+        debug!("refusing {}: {}", req.uri(), e);
+        return S3Error::from(e).into_response();
+    }
+    next.run(req).await
+}
+
+/// Runs the rest of the chain as a task of its own, so a panicking handler
+/// fails its request with `500 InternalError` (carrying the request ID, as
+/// this runs inside [`request_id`]) instead of silently dropping the
+/// connection. The panic is logged and counted in `s3clone_panics_total`.
+/// Guards held by the request, such as its in-flight count and storage
+/// locks, are released as the task unwinds. Panics while a response body
+/// is streamed come too late for an error response and still end the
+/// connection.
+pub async fn catch_panic(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let (method, uri) = (req.method().clone(), req.uri().clone());
+    let request_id = req.extensions().get::<RequestId>().cloned();
+    let task = tokio::spawn(next.run(req));
+    // Dropped with this future when the client goes away, which stops the
+    // task as dropping the handler would have.
+    let _abort = AbortOnDrop(task.abort_handle());
+    match task.await {
+        Ok(response) => response,
+        Err(e) if e.is_panic() => {
+            let payload = e.into_panic();
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("non-string panic payload");
+            error!(
+                "{} {} {} panicked: {}",
+                request_id.map(|RequestId(id)| id).unwrap_or_default(),
+                method,
+                uri,
+                message
+            );
+            state.metrics.record_panic();
+            S3Error::internal("We encountered an internal error. Please try again.").into_response()
+        }
+        Err(e) => S3Error::internal(e.to_string()).into_response(),
+    }
+}
+
+/// Aborts a task when dropped.
+struct AbortOnDrop(tokio::task::AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Gzips [`Compressible`] responses when `server.compress_responses` is on
+/// and the client's `Accept-Encoding` allows it.
+pub async fn compress(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let accepts_gzip = req
+        .headers()
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(compression::accepts_gzip);
+    let response = next.run(req).await;
+    if response.extensions().get::<Compressible>().is_none()
+        || !state.config.read().unwrap().server.compress_responses
+    {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    parts
+        .headers
+        .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    if !accepts_gzip || parts.headers.contains_key(header::CONTENT_ENCODING) {
+        return Response::from_parts(parts, body);
+    }
+    // Listing and error bodies are already fully rendered in memory.
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("failed to buffer response for compression: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    if bytes.len() < MIN_COMPRESS_SIZE {
+        return Response::from_parts(parts, Body::from(bytes));
+    }
+    let compressed = compression::gzip(&bytes);
+    debug!(
+        "gzipped response from {} to {} bytes",
+        bytes.len(),
+        compressed.len()
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts
+        .headers
+        .insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    Response::from_parts(parts, Body::from(compressed))
+}
+
+/// Adds the `response_headers` configured globally and for the request's
+/// bucket, without replacing headers the handler set.
+pub async fn response_headers(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let bucket = path_bucket(&req);
+    let mut response = next.run(req).await;
+    let cfg = state.config.read().unwrap();
+    let rules = &cfg.response_headers;
+    let injected = rules
+        .buckets
+        .get(&bucket)
+        .into_iter()
+        .flatten()
+        .chain(&rules.global);
+    for (name, value) in injected {
+        // Validated with the config, so these only fail on a broken config.
+        let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) else {
+            continue;
+        };
+        response.headers_mut().entry(name).or_insert(value);
+    }
+    response
+}
+
+/// Adds the `Access-Control-*` headers of the CORS rule allowing a request
+/// with an `Origin` to its response, from the bucket's CORS configuration
+/// or `default_cors`. Preflights are answered by
+/// [`cors::preflight_bucket`] and [`cors::preflight_object`].
+pub async fn cors(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let bucket = path_bucket(&req);
+    let origin = req
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let method = req.method().clone();
+    let mut response = next.run(req).await;
+    let Some(origin) = origin else {
+        return response;
+    };
+    if bucket.is_empty() || method == Method::OPTIONS {
+        return response;
+    }
+    // A missing bucket gets no headers, as it has no rules.
+    if let Ok(Some(rule)) =
+        cors::allowing_rule(&state, &bucket, &origin, method.as_str(), &[]).await
+    {
+        response
+            .headers_mut()
+            .extend(cors::cors_headers(&rule, &origin, &[]));
+    }
+    response
+}
+
+/// Records request counts and request and response body sizes per bucket
+/// in [`Metrics`](crate::metrics::Metrics). Sizes come from the declared
+/// lengths, or what was read of chunked request bodies that declare none;
+/// bucket-less requests (ListBuckets, STS) are not recorded.
+pub async fn metrics(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let bucket = path_bucket(&req);
+    let method = req.method().clone();
+    let (req, request_bytes) = RequestBytes::of(req);
+    let response = next.run(req).await;
+    let request_bytes = request_bytes.get();
+    if bucket.is_empty() {
+        return response;
+    }
+    let response_bytes = response_bytes(&response);
+    let max_bucket_labels = state.config.read().unwrap().metrics.max_bucket_labels;
+    state.metrics.observe_request(
+        &bucket,
+        method.as_str(),
+        response.status().is_success(),
+        request_bytes,
+        response_bytes,
+        max_bucket_labels,
+    );
+    response
+}
+
+/// Appends an entry per request to the access log when `system_logs` is
+/// enabled.
+pub async fn access_log(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if !state.system_logs.enabled() {
+        return next.run(req).await;
+    }
+    let started = Instant::now();
+    let time = system_log::now();
+    let bucket = path_bucket(&req);
+    let method = req.method().to_string();
+    let uri = req.uri().to_string();
+    let client_ip = req
+        .extensions()
+        .get::<ClientIp>()
+        .map(|ClientIp(ip)| ip.to_string());
+    let (req, request_bytes) = RequestBytes::of(req);
+    let response = next.run(req).await;
+    let request_bytes = request_bytes.get();
+    state.system_logs.record_access(&AccessLogEntry {
+        time,
+        request_id: response
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+        client_ip,
+        requester: response
+            .extensions()
+            .get::<Requester>()
+            .map(|Requester(key)| key.clone()),
+        method,
+        uri,
+        bucket: Some(bucket).filter(|b| !b.is_empty()),
+        version_id: response
+            .headers()
+            .get("x-amz-version-id")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+        status: response.status().as_u16(),
+        request_bytes,
+        response_bytes: response_bytes(&response),
+        duration_ms: started.elapsed().as_millis() as u64,
+    });
+    response
+}
+
+/// The bucket of a path-style request; empty for `/`.
+fn path_bucket(req: &Request) -> String {
+    req.uri()
+        .path()
+        .trim_start_matches('/')
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Request payload size. Bodies sent with `Transfer-Encoding: chunked` and
+/// no declared length are counted as the handler reads them.
+enum RequestBytes {
+    /// Aws-chunked uploads declare the payload size separately.
+    Declared(u64),
+    Counted(Arc<AtomicU64>),
+}
+
+impl RequestBytes {
+    fn of(req: Request) -> (Request, Self) {
+        let declared = ["x-amz-decoded-content-length", "content-length"]
+            .iter()
+            .find_map(|name| header_u64(req.headers(), name));
+        if declared.is_some() || !req.headers().contains_key(header::TRANSFER_ENCODING) {
+            return (req, Self::Declared(declared.unwrap_or(0)));
+        }
+        let counted = Arc::new(AtomicU64::new(0));
+        let counter = counted.clone();
+        let req = req.map(|body| {
+            Body::from_stream(body.into_data_stream().inspect(move |chunk| {
+                if let Ok(chunk) = chunk {
+                    counter.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                }
+            }))
+        });
+        (req, Self::Counted(counted))
+    }
+
+    fn get(&self) -> u64 {
+        match self {
+            Self::Declared(bytes) => *bytes,
+            Self::Counted(bytes) => bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn response_bytes(response: &Response) -> u64 {
+    header_u64(response.headers(), "content-length")
+        .or_else(|| HttpBody::size_hint(response.body()).exact())
+        .unwrap_or(0)
+}
+
+fn header_u64(headers: &axum::http::HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
+/// Sends a copy of sampled requests to the `mirror` endpoint once they were
+/// served; see [`crate::mirror`].
+pub async fn mirror(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let Some(config) = state.mirror.sample() else {
+        return next.run(req).await;
+    };
+    let head = RequestHead::of(&req);
+    let source = BodySource::of(&req);
+    let (req, buffered) = if source == BodySource::Buffered {
+        let (parts, body) = req.into_parts();
+        match axum::body::to_bytes(body, mirror::MAX_BUFFERED_BODY as usize).await {
+            Ok(body) => (
+                Request::from_parts(parts, Body::from(body.clone())),
+                Some(body),
+            ),
+            Err(_) => return S3Error::incomplete_body().into_response(),
+        }
+    } else {
+        (req, None)
+    };
+    let response = next.run(req).await;
+    state
+        .mirror
+        .send(config, head, source, buffered, response.status());
+    response
+}
+
+/// Resolves the caller via [`AuthService`](crate::services::auth::AuthService)
+/// and attaches the resulting [`AuthContext`] to the request extensions.
+/// Authorization of the specific action happens in the handlers. The body
+/// of an authenticated request is checked against a hex
+/// `x-amz-content-sha256` as the handler reads it, whatever the handler
+/// does with it. With `region.redirect`, requests signed for a region other
+/// than their bucket's get no further (see [`redirect`]).
+pub async fn authenticate(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let query: HashMap<String, String> = Query::try_from_uri(req.uri())
+        .map(|Query(q)| q)
+        .unwrap_or_default();
+    let mut body_sha256 = None;
+    if req.headers().contains_key(header::AUTHORIZATION)
+        && !req.headers().contains_key(CONTENT_SHA256_HEADER)
+    {
+        let (parts, body) = req.into_parts();
+        let Ok(body) = axum::body::to_bytes(body, MAX_UNHASHED_BODY).await else {
+            return S3Error::invalid_request(format!(
+                "Missing required header for this request: {}",
+                CONTENT_SHA256_HEADER
+            ))
+            .into_response();
+        };
+        body_sha256 = Some(crate::crypto::sha256_hex(&body));
+        req = Request::from_parts(parts, Body::from(body));
+    }
+    let signed = SignedRequest {
+        method: req.method().as_str(),
+        uri: req.uri(),
+        headers: req.headers(),
+        body_sha256,
+    };
+    let result = state.auth.authenticate(&signed, &query).await;
+    // Browsers never sign CORS preflights, and form uploads are signed by
+    // their fields, which the handler checks.
+    let unsigned = req.method() == Method::OPTIONS
+        || (req.method() == Method::POST && form_upload::is_form_upload(req.headers()));
+    match result {
+        Ok(Authentication {
+            context: AuthContext::Anonymous,
+            ..
+        }) if !unsigned && !state.config.read().unwrap().features.anonymous_access => {
+            S3Error::access_denied_because("Anonymous access is disabled on this server")
+                .into_response()
+        }
+        Ok(Authentication {
+            context: ctx,
+            chunked,
+            region,
+        }) => {
+            let requester = match &ctx {
+                AuthContext::IAMAccount(credentials) => Some(credentials.access_key.clone()),
+                AuthContext::Anonymous => None,
+            };
+            req.extensions_mut().insert::<AuthContext>(ctx);
+            let (parts, body) = req.into_parts();
+            let body = digest::verify_content_sha256(&parts.headers, body);
+            req = Request::from_parts(parts, body);
+            if let Some(payload) = chunked {
+                req.extensions_mut().insert(payload);
+            }
+            if let Some(region) = region
+                && let Some(response) = redirect(&state, &path_bucket(&req), &query, &region).await
+            {
+                return response;
+            }
+            let mut response = next.run(req).await;
+            if let Some(access_key) = requester {
+                response.extensions_mut().insert(Requester(access_key));
+            }
+            response
+        }
+        Err(e) => S3Error::from(e).into_response(),
+    }
+}
+
+/// With `region.redirect`, the `301 PermanentRedirect` for a request signed
+/// for `signed` on a bucket in another region. GetBucketLocation
+/// (`?location`), which SDKs use to find the region to sign for, is never
+/// redirected, nor are requests on buckets that do not exist.
+async fn redirect(
+    state: &AppState,
+    bucket: &str,
+    query: &HashMap<String, String>,
+    signed: &str,
+) -> Option<Response> {
+    if bucket.is_empty()
+        || query.contains_key("location")
+        || !state.config.read().unwrap().region.redirect
+    {
+        return None;
+    }
+    let region = region_of(state, bucket).await?;
+    if region == signed {
+        return None;
+    }
+    debug!(
+        "{} is in {}, redirecting a request signed for {}",
+        bucket, region, signed
+    );
+    let mut response = S3Error::permanent_redirect(bucket).into_response();
+    insert_bucket_region(&mut response, &region);
+    Some(response)
 }
 
-impl<S> AuthMiddleware<S> {
-    pub fn new(service: Arc<dyn AuthService>, inner: S) -> Self {
-        Self { service, inner }
+/// Adds `x-amz-bucket-region` to client error responses of requests on an
+/// existing bucket, authentication failures included, so SDKs can tell the
+/// region to sign for.
+pub async fn bucket_region(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let bucket = path_bucket(&req);
+    let mut response = next.run(req).await;
+    if !bucket.is_empty()
+        && response.status().is_client_error()
+        && !response.headers().contains_key(BUCKET_REGION_HEADER)
+        && let Some(region) = region_of(&state, &bucket).await
+    {
+        insert_bucket_region(&mut response, &region);
     }
+    response
 }
 
-// Example pseudocode for middleware logic:
-// - Extract headers and query params from the request
-// - Call service.authenticate(headers, query)
-// - Attach AuthContext to request extensions or state
-// - Call next handler
-//
-// Actual implementation will depend on your chosen web framework 
\ No newline at end of file
+/// The region of `bucket` (or the bucket an alias names); `None` if it does
+/// not exist.
+async fn region_of(state: &AppState, bucket: &str) -> Option<String> {
+    let metadata = state.buckets.head_bucket(bucket).await.ok()?;
+    Some(
+        metadata
+            .region
+            .unwrap_or_else(|| state.config.read().unwrap().region.default.clone()),
+    )
+}
+
+fn insert_bucket_region(response: &mut Response, region: &str) {
+    if let Ok(value) = HeaderValue::from_str(region) {
+        response.headers_mut().insert(BUCKET_REGION_HEADER, value);
+    }
+}
+
+/// Holds the bodies of PUT and POST requests on objects (PutObject,
+/// UploadPart, appends, ...) and of form uploads to the upload limits of
+/// `server.bandwidth`, and fails UploadPart requests beyond
+/// `max_concurrent_parts` for their upload ID with `503 SlowDown` before
+/// their body is read. Runs after [`authenticate`], for the access key;
+/// form uploads, signed by their fields, get the connection limit only.
+pub async fn upload_limits(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let on_object = req
+        .uri()
+        .path()
+        .trim_start_matches('/')
+        .split_once('/')
+        .is_some_and(|(_, key)| !key.is_empty());
+    let form = req.method() == Method::POST && form_upload::is_form_upload(req.headers());
+    if !(on_object || form) || !matches!(*req.method(), Method::PUT | Method::POST) {
+        return next.run(req).await;
+    }
+    let limits = state.config.read().unwrap().server.bandwidth.clone();
+    let query: HashMap<String, String> = Query::try_from_uri(req.uri())
+        .map(|Query(q)| q)
+        .unwrap_or_default();
+    let _part = match query.get("uploadId") {
+        Some(upload_id) if req.method() == Method::PUT && limits.max_concurrent_parts > 0 => {
+            match state
+                .bandwidth
+                .start_part(upload_id, limits.max_concurrent_parts)
+            {
+                Some(part) => Some(part),
+                None => {
+                    debug!("Too many parts in progress for upload {}", upload_id);
+                    return S3Error::slow_down().into_response();
+                }
+            }
+        }
+        _ => None,
+    };
+    let mut buckets = Vec::new();
+    if let Some(connection) = req.extensions().get::<bandwidth::ConnectionBandwidth>()
+        && limits.upload_per_connection > 0
+    {
+        buckets.push((connection.upload.clone(), limits.upload_per_connection));
+    }
+    if let Some(AuthContext::IAMAccount(credentials)) = req.extensions().get::<AuthContext>()
+        && limits.upload_per_access_key > 0
+    {
+        buckets.push((
+            state.bandwidth.upload(&credentials.access_key),
+            limits.upload_per_access_key,
+        ));
+    }
+    let (parts, body) = req.into_parts();
+    let req = Request::from_parts(parts, bandwidth::throttle_upload(body, buckets));
+    next.run(req).await
+}
+
+/// S3 operation groups that can be switched off under `features`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Feature {
+    Versioning,
+    Website,
+    Select,
+    Search,
+}
+
+impl Feature {
+    /// The group a request belongs to, if any.
+    fn of(req: &Request, query: &HashMap<String, String>) -> Option<Self> {
+        if query.contains_key("website") {
+            return Some(Feature::Website);
+        }
+        if query.contains_key("select") {
+            return Some(Feature::Select);
+        }
+        if query.contains_key("search") {
+            return Some(Feature::Search);
+        }
+        let copies_version = req
+            .headers()
+            .get("x-amz-copy-source")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split_once('?'))
+            .is_some_and(|(_, q)| {
+                form_urlencoded::parse(q.as_bytes()).any(|(name, _)| name == "versionId")
+            });
+        if ["versions", "versioning", "versionId"]
+            .iter()
+            .any(|name| query.contains_key(*name))
+            || copies_version
+        {
+            return Some(Feature::Versioning);
+        }
+        None
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Versioning => "Versioning",
+            Feature::Website => "Static website hosting",
+            Feature::Select => "S3 Select",
+            Feature::Search => "Metadata search",
+        }
+    }
+
+    pub fn enabled(self, features: &FeaturesConfig) -> bool {
+        match self {
+            Feature::Versioning => features.versioning,
+            Feature::Website => features.website,
+            Feature::Select => features.select,
+            Feature::Search => features.search,
+        }
+    }
+
+    /// Groups that can be enabled but have no handlers yet.
+    pub fn implemented(self) -> bool {
+        matches!(self, Feature::Versioning | Feature::Search)
+    }
+}
+
+/// Fails requests of a [`Feature`] that `features` switches off, or that is
+/// not implemented, with `501 NotImplemented`. Runs before authentication so
+/// disabled operations are never evaluated.
+pub async fn features(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let query: HashMap<String, String> = Query::try_from_uri(req.uri())
+        .map(|Query(q)| q)
+        .unwrap_or_default();
+    if let Some(feature) = Feature::of(&req, &query) {
+        if !feature.enabled(&state.config.read().unwrap().features) {
+            debug!("{} is disabled, refusing {}", feature.name(), req.uri());
+            return S3Error::not_implemented(format!(
+                "{} is disabled on this server",
+                feature.name()
+            ))
+            .into_response();
+        }
+        if !feature.implemented() {
+            return S3Error::not_implemented(format!("{} is not implemented", feature.name()))
+                .into_response();
+        }
+    }
+    next.run(req).await
+}
+
+/// Counts the request as in flight and adds a load-based `Retry-After` (see
+/// [`RetryAfterConfig`](crate::config::RetryAfterConfig)) to `503`s that do
+/// not carry one, so retrying clients spread out rather than return at once.
+pub async fn retry_after(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let in_flight = state.metrics.start_request();
+    let mut response = next.run(req).await;
+    drop(in_flight);
+    if response.status() == StatusCode::SERVICE_UNAVAILABLE
+        && !response.headers().contains_key(header::RETRY_AFTER)
+    {
+        let secs = state
+            .config
+            .read()
+            .unwrap()
+            .server
+            .retry_after
+            .secs(state.metrics.in_flight());
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, secs.into());
+    }
+    response
+}
+
+/// Enforces `x-s3clone-deadline-ms`, or `server.default_deadline_ms` when the
+/// header is absent: requests still running at the deadline get
+/// `503 DeadlineExceeded`.
+///
+/// Storage calls run on the blocking pool (see [`Storage::blocking`]), so a
+/// hung filesystem call only pins its thread and the deadline can still fire
+/// here; the call itself cannot be interrupted and finishes in the
+/// background, so a write may still land after the client was told it
+/// failed.
+///
+/// [`Storage::blocking`]: crate::storage::Storage::blocking
+pub async fn deadline(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let deadline_ms = match req.headers().get(DEADLINE_HEADER) {
+        Some(value) => match value.to_str().ok().and_then(|v| v.parse::<u64>().ok()) {
+            Some(ms) if ms > 0 => Some(ms),
+            _ => {
+                return S3Error::invalid_argument(format!(
+                    "{} must be a positive integer",
+                    DEADLINE_HEADER
+                ))
+                .with_resource("ArgumentName", DEADLINE_HEADER)
+                .into_response();
+            }
+        },
+        None => state.config.read().unwrap().server.default_deadline_ms,
+    };
+    let Some(deadline_ms) = deadline_ms else {
+        return next.run(req).await;
+    };
+
+    let (method, uri) = (req.method().clone(), req.uri().clone());
+    match tokio::time::timeout(Duration::from_millis(deadline_ms), next.run(req)).await {
+        Ok(response) => response,
+        Err(_) => {
+            warn!(
+                "{} {} exceeded its {} ms deadline",
+                method, uri, deadline_ms
+            );
+            S3Error::deadline_exceeded(deadline_ms).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    async fn get_status(uri: &str) -> (StatusCode, Option<&'static str>) {
+        let app = Router::new()
+            .route("/", get(|| async { "buckets" }))
+            .route("/{bucket}/{*key}", get(|| async { "object" }))
+            .layer(axum::middleware::from_fn(bucket_name));
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let code = response.extensions().get::<S3Error>().map(|e| e.code);
+        (response.status(), code)
+    }
+
+    #[tokio::test]
+    async fn bucket_name_refuses_encoded_parent_segments() {
+        assert_eq!(
+            get_status("/foo%2F..%2F../secret.txt").await,
+            (StatusCode::BAD_REQUEST, Some("InvalidBucketName"))
+        );
+        assert_eq!(
+            get_status("/..%2F..%2Fetc/passwd").await,
+            (StatusCode::BAD_REQUEST, Some("InvalidBucketName"))
+        );
+        assert_eq!(
+            get_status("/.s3clone/buckets/x.json").await,
+            (StatusCode::BAD_REQUEST, Some("InvalidBucketName"))
+        );
+    }
+
+    #[tokio::test]
+    async fn bucket_name_passes_valid_names_and_routes_without_bucket() {
+        assert_eq!(
+            get_status("/photos/a%2F..%2Fb").await,
+            (StatusCode::OK, None)
+        );
+        assert_eq!(get_status("/").await, (StatusCode::OK, None));
+    }
+}
@@ -0,0 +1,304 @@
+//! Mirroring of sampled S3 requests to a second server (`mirror` in the
+//! config), to load-test a new build with real traffic. Copies are sent in
+//! the background once the original request was served, one per connection,
+//! and their responses are ignored. Object and part bodies are not kept in
+//! memory: the mirror re-reads what was stored.
+
+use crate::config::{Config, MirrorConfig};
+use crate::sigv4::CONTENT_SHA256_HEADER;
+use crate::storage::{ObjectReader, Storage};
+use crate::webhook::{self, Endpoint};
+use anyhow::{Context, Result, bail};
+use axum::body::Bytes;
+use axum::extract::{Query, Request};
+use axum::http::{HeaderMap, Method, StatusCode, header};
+use log::debug;
+use percent_encoding::percent_decode_str;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Largest body, other than of PutObject and UploadPart, buffered to be
+/// mirrored. Such bodies are configuration documents and delete lists.
+pub const MAX_BUFFERED_BODY: u64 = 64 * 1024;
+
+/// Headers of the original request that only apply to its own connection.
+/// The copy is sent in one go, so `Expect: 100-continue` is dropped too.
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "expect",
+    "keep-alive",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Where the body of a mirrored request comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BodySource {
+    /// The body as received, which is empty or at most
+    /// [`MAX_BUFFERED_BODY`] long.
+    Buffered,
+    /// PutObject: the object as stored.
+    Object { bucket: String, key: String },
+    /// UploadPart: the part as stored.
+    Part {
+        bucket: String,
+        upload_id: String,
+        part_number: u32,
+    },
+    /// A body that cannot be reproduced: appends, aws-chunked payloads and
+    /// large bodies of other requests. Such requests are not mirrored.
+    Unavailable,
+}
+
+impl BodySource {
+    pub fn of(req: &Request) -> Self {
+        let headers = req.headers();
+        let streaming = headers
+            .get(CONTENT_SHA256_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("STREAMING-"));
+        if streaming {
+            return Self::Unavailable;
+        }
+        if req.method() == Method::PUT
+            && !headers.contains_key("x-amz-copy-source")
+            && let Some((bucket, key)) = object_path(req.uri().path())
+        {
+            let query: HashMap<String, String> = Query::try_from_uri(req.uri())
+                .map(|Query(q)| q)
+                .unwrap_or_default();
+            let params = query.keys().filter(|k| !k.starts_with("X-Amz-")).count();
+            let upload_id = query.get("uploadId");
+            let part_number = query.get("partNumber").and_then(|n| n.parse().ok());
+            match (upload_id, part_number) {
+                (Some(upload_id), Some(part_number)) if params == 2 => {
+                    return Self::Part {
+                        bucket,
+                        upload_id: upload_id.clone(),
+                        part_number,
+                    };
+                }
+                _ if params == 0 => return Self::Object { bucket, key },
+                _ => {}
+            }
+        }
+        let length = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        match length {
+            Some(length) if length <= MAX_BUFFERED_BODY => Self::Buffered,
+            None if !headers.contains_key(header::TRANSFER_ENCODING) => Self::Buffered,
+            _ => Self::Unavailable,
+        }
+    }
+}
+
+/// Decoded bucket and key of an object path, `/{bucket}/{key}`.
+fn object_path(path: &str) -> Option<(String, String)> {
+    let (bucket, key) = path.strip_prefix('/')?.split_once('/')?;
+    let decode = |part: &str| {
+        percent_decode_str(part)
+            .decode_utf8()
+            .ok()
+            .map(|s| s.into_owned())
+    };
+    let (bucket, key) = (decode(bucket)?, decode(key)?);
+    (!bucket.is_empty() && !key.is_empty()).then_some((bucket, key))
+}
+
+/// What is copied of the original request besides the body.
+#[derive(Debug, Clone)]
+pub struct RequestHead {
+    pub method: Method,
+    /// Path and query as received, still percent-encoded.
+    pub path_and_query: String,
+    pub headers: HeaderMap,
+}
+
+impl RequestHead {
+    pub fn of(req: &Request) -> Self {
+        Self {
+            method: req.method().clone(),
+            path_and_query: req
+                .uri()
+                .path_and_query()
+                .map_or_else(|| "/".to_string(), |p| p.as_str().to_string()),
+            headers: req.headers().clone(),
+        }
+    }
+}
+
+enum Body {
+    Bytes(Bytes),
+    /// Stored data and its length.
+    Stored(ObjectReader, u64),
+}
+
+pub struct Mirror {
+    config: Arc<RwLock<Config>>,
+    storage: Arc<Storage>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+/// A copy being sent, counted in `in_flight` until dropped, also when its
+/// task panics.
+struct Pending(Arc<AtomicUsize>);
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Mirror {
+    pub fn new(config: Arc<RwLock<Config>>, storage: Arc<Storage>) -> Self {
+        Self {
+            config,
+            storage,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// The mirror settings if the current request is to be mirrored.
+    pub fn sample(&self) -> Option<MirrorConfig> {
+        let cfg = self.config.read().unwrap();
+        let mirror = cfg.mirror.as_ref()?;
+        // Uniform in [0, 100) with a resolution of 0.01 percent.
+        let roll = (uuid::Uuid::new_v4().as_u128() % 10_000) as f64 / 100.0;
+        (roll < mirror.sample_percent).then(|| mirror.clone())
+    }
+
+    /// Sends a copy of a served request in the background. `buffered` is the
+    /// body of [`BodySource::Buffered`] requests; stored objects and parts
+    /// are only mirrored when the original succeeded.
+    pub fn send(
+        &self,
+        config: MirrorConfig,
+        head: RequestHead,
+        source: BodySource,
+        buffered: Option<Bytes>,
+        status: StatusCode,
+    ) {
+        let body = match self.body(source, buffered, status) {
+            Ok(body) => body,
+            Err(e) => {
+                debug!(
+                    "not mirroring {} {}: {}",
+                    head.method, head.path_and_query, e
+                );
+                return;
+            }
+        };
+        let Ok(endpoint) = Endpoint::parse(&config.endpoint) else {
+            return;
+        };
+        if self.in_flight.fetch_add(1, Ordering::SeqCst) >= config.max_in_flight {
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            debug!(
+                "not mirroring {} {}: {} copies pending",
+                head.method, head.path_and_query, config.max_in_flight
+            );
+            return;
+        }
+        let pending = Pending(self.in_flight.clone());
+        let timeout = Duration::from_millis(config.timeout_ms);
+        tokio::spawn(async move {
+            let result = tokio::time::timeout(timeout, deliver(&endpoint, &head, body)).await;
+            drop(pending);
+            match result {
+                Ok(Ok(status)) => debug!(
+                    "mirrored {} {}: {}",
+                    head.method, head.path_and_query, status
+                ),
+                Ok(Err(e)) => debug!(
+                    "failed to mirror {} {}: {:#}",
+                    head.method, head.path_and_query, e
+                ),
+                Err(_) => debug!(
+                    "failed to mirror {} {}: no response within {} ms",
+                    head.method,
+                    head.path_and_query,
+                    timeout.as_millis()
+                ),
+            }
+        });
+    }
+
+    fn body(
+        &self,
+        source: BodySource,
+        buffered: Option<Bytes>,
+        status: StatusCode,
+    ) -> Result<Body> {
+        match source {
+            BodySource::Buffered => Ok(Body::Bytes(buffered.unwrap_or_default())),
+            BodySource::Unavailable => bail!("the body cannot be replayed"),
+            _ if !status.is_success() => bail!("the request failed with {}", status),
+            BodySource::Object { bucket, key } => {
+                let bucket = self.storage.resolve_bucket(&bucket)?;
+                if crate::storage::is_folder_marker(&key) {
+                    return Ok(Body::Bytes(Bytes::new()));
+                }
+                let (metadata, reader) = self.storage.open_object(&bucket, &key)?;
+                Ok(Body::Stored(reader, metadata.size))
+            }
+            BodySource::Part {
+                bucket,
+                upload_id,
+                part_number,
+            } => {
+                let bucket = self.storage.resolve_bucket(&bucket)?;
+                let (reader, size) = self.storage.open_part(&bucket, &upload_id, part_number)?;
+                Ok(Body::Stored(reader, size))
+            }
+        }
+    }
+}
+
+async fn deliver(endpoint: &Endpoint, head: &RequestHead, body: Body) -> Result<u16> {
+    let mut stream = TcpStream::connect(&endpoint.authority)
+        .await
+        .with_context(|| format!("failed to connect to {}", endpoint.authority))?;
+    let length = match &body {
+        Body::Bytes(bytes) => bytes.len() as u64,
+        Body::Stored(_, length) => *length,
+    };
+    let mut out = format!("{} {} HTTP/1.1\r\n", head.method, head.path_and_query).into_bytes();
+    for (name, value) in &head.headers {
+        if name == header::CONTENT_LENGTH || HOP_BY_HOP_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        out.extend_from_slice(name.as_str().as_bytes());
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(value.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+    // The original Host is kept, since signatures cover it.
+    if !head.headers.contains_key(header::HOST) {
+        out.extend_from_slice(format!("Host: {}\r\n", endpoint.authority).as_bytes());
+    }
+    out.extend_from_slice(
+        format!("Content-Length: {}\r\nConnection: close\r\n\r\n", length).as_bytes(),
+    );
+    stream.write_all(&out).await?;
+    match body {
+        Body::Bytes(bytes) => stream.write_all(&bytes).await?,
+        Body::Stored(reader, length) => {
+            let copied = tokio::io::copy(&mut reader.take(length), &mut stream).await?;
+            if copied < length {
+                bail!("the stored body shrank from {} to {} bytes", length, copied);
+            }
+        }
+    }
+    stream.flush().await?;
+    webhook::read_status(&mut stream).await
+}
@@ -1,7 +1,25 @@
+pub mod acl;
+pub mod cors;
 pub mod domain;
+pub mod lifecycle;
+pub mod notification;
+pub mod policy;
+pub mod post_policy;
 pub mod requests;
 pub mod responses;
+pub mod search;
+pub mod tagging;
+pub mod versioning;
 
+pub use acl::*;
+pub use cors::*;
 pub use domain::*;
+pub use lifecycle::*;
+pub use notification::*;
+pub use policy::*;
+pub use post_policy::*;
 pub use requests::*;
-pub use responses::*; 
\ No newline at end of file
+pub use responses::*;
+pub use search::*;
+pub use tagging::*;
+pub use versioning::*;
@@ -0,0 +1,210 @@
+use super::Owner;
+use serde::{Deserialize, Serialize};
+
+/// Namespace of the `xsi:type` attribute of grantees.
+pub const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+/// Predefined groups that can be granted access.
+pub const ALL_USERS_GROUP: &str = "http://acs.amazonaws.com/groups/global/AllUsers";
+pub const AUTHENTICATED_USERS_GROUP: &str =
+    "http://acs.amazonaws.com/groups/global/AuthenticatedUsers";
+pub const LOG_DELIVERY_GROUP: &str = "http://acs.amazonaws.com/groups/s3/LogDelivery";
+pub const ACL_GROUPS: &[&str] = &[
+    ALL_USERS_GROUP,
+    AUTHENTICATED_USERS_GROUP,
+    LOG_DELIVERY_GROUP,
+];
+
+/// The `AccessControlPolicy` document of `PUT /{bucket}?acl`,
+/// `PUT /{bucket}/{key}?acl` and their GETs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename = "AccessControlPolicy")]
+pub struct AccessControlPolicy {
+    #[serde(rename = "@xmlns", default, skip_serializing_if = "Option::is_none")]
+    pub xmlns: Option<String>,
+    #[serde(rename = "Owner", default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Owner>,
+    #[serde(rename = "AccessControlList", default)]
+    pub access_control_list: AccessControlList,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccessControlList {
+    #[serde(rename = "Grant", default)]
+    pub grants: Vec<Grant>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Grant {
+    #[serde(rename = "Grantee")]
+    pub grantee: Grantee,
+    #[serde(rename = "Permission")]
+    pub permission: AclPermission,
+}
+
+/// A grantee as written in documents. Which of `ID`, `URI` and
+/// `EmailAddress` is set tells its type; `xsi:type` is only written.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Grantee {
+    #[serde(
+        rename = "@xmlns:xsi",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub xmlns_xsi: Option<String>,
+    #[serde(rename = "@xsi:type", default, skip_serializing_if = "Option::is_none")]
+    pub xsi_type: Option<String>,
+    #[serde(rename = "ID", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(
+        rename = "DisplayName",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub display_name: Option<String>,
+    #[serde(
+        rename = "EmailAddress",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub email_address: Option<String>,
+    #[serde(rename = "URI", default, skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AclPermission {
+    FullControl,
+    Write,
+    WriteAcp,
+    Read,
+    ReadAcp,
+}
+
+/// A grant as stored with a bucket's or object's metadata. The owner's
+/// full control is implied and never stored, so an empty list is the
+/// `private` ACL.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AclGrant {
+    pub grantee: AclGrantee,
+    pub permission: AclPermission,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AclGrantee {
+    /// An access key, as canonical user IDs are.
+    CanonicalUser { id: String },
+    /// One of [`ACL_GROUPS`].
+    Group { uri: String },
+}
+
+impl AclPermission {
+    pub const ALL: [AclPermission; 5] = [
+        AclPermission::FullControl,
+        AclPermission::Write,
+        AclPermission::WriteAcp,
+        AclPermission::Read,
+        AclPermission::ReadAcp,
+    ];
+
+    /// The permission as written in `x-amz-grant-*` header names.
+    pub fn header(self) -> &'static str {
+        match self {
+            AclPermission::FullControl => "x-amz-grant-full-control",
+            AclPermission::Write => "x-amz-grant-write",
+            AclPermission::WriteAcp => "x-amz-grant-write-acp",
+            AclPermission::Read => "x-amz-grant-read",
+            AclPermission::ReadAcp => "x-amz-grant-read-acp",
+        }
+    }
+}
+
+impl AclGrantee {
+    /// Whether the grantee includes the caller with `access_key`, `None`
+    /// for anonymous callers.
+    pub fn includes(&self, access_key: Option<&str>) -> bool {
+        match self {
+            AclGrantee::CanonicalUser { id } => Some(id.as_str()) == access_key,
+            AclGrantee::Group { uri } => match uri.as_str() {
+                ALL_USERS_GROUP => true,
+                AUTHENTICATED_USERS_GROUP => access_key.is_some(),
+                _ => false,
+            },
+        }
+    }
+}
+
+impl AclGrant {
+    fn group(uri: &str, permission: AclPermission) -> Self {
+        Self {
+            grantee: AclGrantee::Group {
+                uri: uri.to_string(),
+            },
+            permission,
+        }
+    }
+}
+
+/// The grants of a canned ACL (`x-amz-acl`), `None` for unknown names.
+/// As there is a single owner, the `bucket-owner-*` ACLs of objects are
+/// the same as `private`.
+pub fn canned_acl(name: &str) -> Option<Vec<AclGrant>> {
+    Some(match name {
+        "private" | "bucket-owner-read" | "bucket-owner-full-control" => Vec::new(),
+        "public-read" => vec![AclGrant::group(ALL_USERS_GROUP, AclPermission::Read)],
+        "public-read-write" => vec![
+            AclGrant::group(ALL_USERS_GROUP, AclPermission::Read),
+            AclGrant::group(ALL_USERS_GROUP, AclPermission::Write),
+        ],
+        "authenticated-read" => vec![AclGrant::group(
+            AUTHENTICATED_USERS_GROUP,
+            AclPermission::Read,
+        )],
+        "log-delivery-write" => vec![
+            AclGrant::group(LOG_DELIVERY_GROUP, AclPermission::Write),
+            AclGrant::group(LOG_DELIVERY_GROUP, AclPermission::ReadAcp),
+        ],
+        _ => return None,
+    })
+}
+
+impl AccessControlPolicy {
+    /// The document of `grants`, led by the owner's full control.
+    pub fn new(xmlns: &str, owner: Owner, grants: &[AclGrant]) -> Self {
+        let owner_grant = Grant {
+            grantee: Grantee {
+                xmlns_xsi: Some(XSI_NAMESPACE.to_string()),
+                xsi_type: Some("CanonicalUser".to_string()),
+                id: Some(owner.id.clone()),
+                display_name: Some(owner.display_name.clone()),
+                ..Default::default()
+            },
+            permission: AclPermission::FullControl,
+        };
+        let grants = grants.iter().map(|grant| Grant {
+            grantee: match &grant.grantee {
+                AclGrantee::CanonicalUser { id } => Grantee {
+                    xmlns_xsi: Some(XSI_NAMESPACE.to_string()),
+                    xsi_type: Some("CanonicalUser".to_string()),
+                    id: Some(id.clone()),
+                    ..Default::default()
+                },
+                AclGrantee::Group { uri } => Grantee {
+                    xmlns_xsi: Some(XSI_NAMESPACE.to_string()),
+                    xsi_type: Some("Group".to_string()),
+                    uri: Some(uri.clone()),
+                    ..Default::default()
+                },
+            },
+            permission: grant.permission,
+        });
+        Self {
+            xmlns: Some(xmlns.to_string()),
+            owner: Some(owner),
+            access_control_list: AccessControlList {
+                grants: std::iter::once(owner_grant).chain(grants).collect(),
+            },
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+
+/// Methods a CORS rule can allow.
+pub const CORS_METHODS: &[&str] = &["GET", "PUT", "POST", "DELETE", "HEAD"];
+
+/// The `CORSConfiguration` document of `PUT /{bucket}?cors`, as stored with
+/// the bucket's metadata and returned by its GET.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "CORSConfiguration")]
+pub struct CorsConfiguration {
+    #[serde(rename = "@xmlns", default, skip_serializing_if = "Option::is_none")]
+    pub xmlns: Option<String>,
+    #[serde(rename = "CORSRule", default)]
+    pub rules: Vec<CorsRule>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CorsRule {
+    #[serde(rename = "ID", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "AllowedMethod", default)]
+    pub allowed_methods: Vec<String>,
+    /// Origins with at most one `*` wildcard each.
+    #[serde(rename = "AllowedOrigin", default)]
+    pub allowed_origins: Vec<String>,
+    /// Headers of `Access-Control-Request-Headers`, with at most one `*`
+    /// wildcard each.
+    #[serde(
+        rename = "AllowedHeader",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub allowed_headers: Vec<String>,
+    #[serde(
+        rename = "ExposeHeader",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub expose_headers: Vec<String>,
+    #[serde(
+        rename = "MaxAgeSeconds",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_age_seconds: Option<u64>,
+}
+
+impl CorsConfiguration {
+    /// The first rule allowing a request from `origin` with `method` and,
+    /// for preflights, the `Access-Control-Request-Headers`.
+    pub fn rule_for(&self, origin: &str, method: &str, headers: &[&str]) -> Option<&CorsRule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.allows(origin, method, headers))
+    }
+}
+
+impl CorsRule {
+    pub fn allows(&self, origin: &str, method: &str, headers: &[&str]) -> bool {
+        self.allowed_methods.iter().any(|m| m == method)
+            && self.allowed_origins.iter().any(|o| cors_match(o, origin))
+            && headers.iter().all(|header| {
+                self.allowed_headers
+                    .iter()
+                    .any(|allowed| cors_match(&allowed.to_ascii_lowercase(), header))
+            })
+    }
+
+    /// Whether the rule allows any origin, so `Access-Control-Allow-Origin`
+    /// is `*` rather than the request's origin.
+    pub fn any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|o| o == "*")
+    }
+}
+
+/// Matches `text` against `pattern` with at most one `*` wildcard, which
+/// matches any run of characters.
+fn cors_match(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => {
+            text.len() >= prefix.len() + suffix.len()
+                && text.starts_with(prefix)
+                && text.ends_with(suffix)
+        }
+        None => pattern == text,
+    }
+}
@@ -1,3 +1,21 @@
+use super::{
+    AclGrant, BucketPolicy, CorsConfiguration, LifecycleConfiguration, NotificationConfiguration,
+    VersioningStatus,
+};
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Storage class reported for objects without a recorded one.
+pub const STANDARD_STORAGE_CLASS: &str = "STANDARD";
+
+/// Storage classes whose objects S3 only serves after a RestoreObject.
+pub const ARCHIVE_STORAGE_CLASSES: [&str; 2] = ["GLACIER", "DEEP_ARCHIVE"];
+
+/// Version ID of objects written while a bucket is not versioned, or while
+/// versioning is suspended. Stored as no version ID at all.
+pub const NULL_VERSION_ID: &str = "null";
+
 #[derive(Debug, Clone)]
 pub struct Bucket {
     pub name: String,
@@ -11,36 +29,256 @@ pub struct Object {
     // Add more fields as needed
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Part {
     pub part_number: u32,
-    pub etag: String,
+    pub etag: String, // quoted MD5 hex of the part
     pub size: u64,
-    // Add more fields as needed
+    pub last_modified: String, // RFC 3339 with milliseconds, UTC
+    /// The part's additional checksum (base64), of the upload's algorithm.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+/// A multipart upload started with CreateMultipartUpload, persisted until it
+/// is completed or aborted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultipartUpload {
+    pub upload_id: String,
+    pub key: String,
+    pub initiated: String, // RFC 3339 with milliseconds, UTC
+    /// Given to the object on completion.
+    pub attributes: ObjectAttributes,
+    /// The bucket's when the upload started, for its parts and the object;
+    /// `None` (MD5) for uploads started before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag_algorithm: Option<EtagAlgorithm>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BucketMetadata {
     pub name: String,
-    pub region: String,
-    pub created: String,
-    pub created_by: String,
-    // ACLs, CORS, etc.
+    pub region: Option<String>,     // None: the configured default region
+    pub created: String,            // RFC 3339 with milliseconds, UTC
+    pub created_by: Option<String>, // access key of the creator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notification: Option<NotificationConfiguration>,
+    /// Set with PutBucketTagging; empty when the bucket has no tag set.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// Set with PutBucketVersioning; `None` until versioning is first enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub versioning: Option<VersioningStatus>,
+    /// Set with PutBucketLifecycleConfiguration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lifecycle: Option<LifecycleConfiguration>,
+    /// Set with PutBucketPolicy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<BucketPolicy>,
+    /// Set with PutBucketAcl or on creation; empty for `private`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub acl: Vec<AclGrant>,
+    /// Set with PutBucketCors; `default_cors` applies while it is `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cors: Option<CorsConfiguration>,
+    /// Set through the admin API; `None` for MD5. Applies to objects
+    /// written afterwards.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag_algorithm: Option<EtagAlgorithm>,
 }
 
-#[derive(Debug, Clone)]
+/// How the ETags of a bucket's objects are computed. Each object records
+/// the algorithm of its ETag, so objects written before a bucket switched
+/// keep theirs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EtagAlgorithm {
+    /// The hex MD5 of the data, as in S3: what clients compare with the
+    /// MD5 of a local file. For multipart uploads, the MD5 of the part MD5s
+    /// followed by `-` and the number of parts.
+    #[default]
+    Md5,
+    /// The hex SHA-256 of the data, and for multipart uploads of the part
+    /// SHA-256s, with the number of parts. Clients that check ETags against
+    /// an MD5 reject these.
+    Sha256,
+}
+
+/// Client-supplied attributes stored alongside an object's data.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ObjectAttributes {
+    pub content_type: Option<String>,
+    /// Stored in ASCII form, non-ASCII filenames as `filename*` (see
+    /// [`crate::header_encoding::content_disposition`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_disposition: Option<String>,
+    /// As sent, less the `aws-chunked` transfer coding.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_encoding: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<String>,
+    #[serde(default)]
+    pub user_metadata: BTreeMap<String, String>, // x-amz-meta-* without the prefix
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// `AES256` or `aws:kms`, as requested with `x-amz-server-side-encryption`.
+    /// Recorded and echoed only: data is not encrypted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_side_encryption: Option<String>,
+    /// ARN of the registry key for `aws:kms`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sse_kms_key_id: Option<String>,
+    /// Created by the append extension; only such objects can be appended
+    /// to, and a regular PUT or copy makes them normal objects again.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub appendable: bool,
+    /// As requested with `x-amz-storage-class` or defaulted from the config;
+    /// objects stored before classes were recorded read as `STANDARD`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_class: Option<String>,
+    /// Set with PutObjectAcl or on creation; empty for `private`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub acl: Vec<AclGrant>,
+    /// Algorithm of the additional checksum (`x-amz-checksum-*`) the object
+    /// was uploaded with, listed as `ChecksumAlgorithm`. For a multipart
+    /// upload in progress, the algorithm its parts are uploaded with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum_algorithm: Option<String>,
+    /// The additional checksum itself, base64; composite for multipart
+    /// uploads (see [`crate::checksum::composite`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+}
+
+impl ObjectAttributes {
+    pub fn storage_class(&self) -> &str {
+        self.storage_class
+            .as_deref()
+            .unwrap_or(STANDARD_STORAGE_CLASS)
+    }
+
+    /// Whether S3 would keep the data in an archive until restored. Data is
+    /// never archived here; the class only decides what listings report.
+    pub fn is_archived(&self) -> bool {
+        ARCHIVE_STORAGE_CLASSES.contains(&self.storage_class())
+    }
+
+    pub fn checksum(&self) -> Option<Checksum> {
+        Some(Checksum {
+            algorithm: ChecksumAlgorithm::parse(self.checksum_algorithm.as_deref()?)?,
+            value: self.checksum.clone()?,
+        })
+    }
+
+    /// Sets the additional checksum, or removes it for `None`.
+    pub fn set_checksum(&mut self, checksum: Option<Checksum>) {
+        self.checksum_algorithm = checksum.as_ref().map(|c| c.algorithm.name().to_string());
+        self.checksum = checksum.map(|c| c.value);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ObjectMetadata {
     pub key: String,
+    /// `None` for the `null` version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<String>,
     pub size: u64,
-    pub etag: String,
-    pub last_modified: String,
-    // Add more fields as needed
+    pub etag: String,          // quoted hex digest, as sent in the ETag header
+    pub last_modified: String, // RFC 3339 with milliseconds, UTC
+    /// The algorithm of `etag`; `None` for MD5, as for objects written
+    /// before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag_algorithm: Option<EtagAlgorithm>,
+    #[serde(flatten)]
+    pub attributes: ObjectAttributes,
 }
 
-#[derive(Debug, Clone)]
+impl ObjectMetadata {
+    pub fn version_id(&self) -> &str {
+        self.version_id.as_deref().unwrap_or(NULL_VERSION_ID)
+    }
+}
+
+/// A version of a key: an object, or a delete marker left by a delete in a
+/// versioned bucket. Noncurrent versions and delete markers are kept in the
+/// key's version history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ObjectVersion {
+    Object {
+        #[serde(flatten)]
+        metadata: Box<ObjectMetadata>,
+        /// File of a noncurrent version's data in the bucket's version
+        /// data directory; `None` for the current version and for folder
+        /// markers, which have no data.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        data: Option<String>,
+    },
+    DeleteMarker {
+        key: String,
+        /// `None` for the `null` version.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        version_id: Option<String>,
+        last_modified: String, // RFC 3339 with milliseconds, UTC
+    },
+}
+
+impl ObjectVersion {
+    pub fn version_id(&self) -> &str {
+        match self {
+            ObjectVersion::Object { metadata, .. } => metadata.version_id(),
+            ObjectVersion::DeleteMarker { version_id, .. } => {
+                version_id.as_deref().unwrap_or(NULL_VERSION_ID)
+            }
+        }
+    }
+
+    pub fn is_delete_marker(&self) -> bool {
+        matches!(self, ObjectVersion::DeleteMarker { .. })
+    }
+
+    pub fn last_modified(&self) -> &str {
+        match self {
+            ObjectVersion::Object { metadata, .. } => &metadata.last_modified,
+            ObjectVersion::DeleteMarker { last_modified, .. } => last_modified,
+        }
+    }
+}
+
+/// One key of a bucket inventory, as compared by the inventory diff
+/// extension and listed by the checksums extension. Entries of a
+/// client-supplied manifest may leave out everything but the key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InventoryEntry {
+    pub key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+/// Canonical owner of buckets and objects, taken from the `owner` config section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Owner {
+    #[serde(rename = "ID")]
+    pub id: String,
+    #[serde(rename = "DisplayName", default)]
+    pub display_name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Permission {
     pub action: String,
     pub resource: String,
+    /// An `s3:prefix` condition, on listing actions: only listings whose
+    /// `prefix` starts with it are granted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -48,10 +286,82 @@ pub struct Credentials {
     pub access_key: String,
     pub secret_key: String,
     pub permissions: Vec<Permission>,
+    /// Set for temporary credentials from STS; requests must present it in
+    /// `x-amz-security-token`.
+    pub session_token: Option<String>,
+    /// Session policy passed to AssumeRole. When set, an action must be
+    /// allowed by both `permissions` and this policy.
+    pub session_policy: Option<Vec<Permission>>,
+}
+
+/// A credential minted through the admin API, restricted to one bucket and
+/// key prefix. The secret is handed out once at creation and kept only for
+/// signature verification.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceAccount {
+    pub access_key: String,
+    pub secret_key: String,
+    pub name: String,
+    pub bucket: String,
+    pub prefix: String,
+    pub actions: Vec<String>,
+    pub created: String,         // RFC 3339, UTC
+    pub expires: Option<String>, // RFC 3339, UTC; None never expires
+    pub created_by: String,      // admin token name
+}
+
+/// A key in the local KMS registry used to emulate SSE-KMS.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KmsKey {
+    pub key_id: String, // UUID, as in AWS KMS
+    /// Without the `alias/` prefix, e.g. `aws/s3` for the default key.
+    pub alias: Option<String>,
+    pub description: String,
+    pub enabled: bool,
+    pub created: String,    // RFC 3339, UTC
+    pub created_by: String, // admin token name, or "system" for the default key
+}
+
+/// A forced deletion of a bucket requested through the admin API, persisted
+/// until the background job has removed the bucket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BucketDeletion {
+    pub bucket: String,
+    pub requested: String,    // RFC 3339, UTC
+    pub requested_by: String, // admin token name
+}
+
+/// A deleted bucket kept with `bucket_tombstones` enabled: its name stays
+/// reserved and the admin API can restore it until `expires`, when the
+/// `bucket-tombstone-expiry` job removes it for good.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BucketTombstone {
+    pub bucket: String,
+    pub deleted: String,    // RFC 3339, UTC
+    pub deleted_by: String, // access key, "anonymous" or admin token name
+    pub expires: String,    // RFC 3339, UTC
+    /// Former names that resolved to the bucket, given back on restore.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Deleted with its objects (`?force` through the admin API); otherwise
+    /// the bucket was empty and only its configuration is kept.
+    #[serde(default)]
+    pub with_objects: bool,
+}
+
+/// A bucket frozen through the admin API, e.g. while it is backed up: writes
+/// are refused with `503` until it is unfrozen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BucketFreeze {
+    pub bucket: String,
+    pub frozen: String,    // RFC 3339, UTC
+    pub frozen_by: String, // admin token name
+    /// Sent as `Retry-After` with refused writes.
+    pub retry_after_secs: u64,
 }
 
 #[derive(Debug, Clone)]
 pub enum AuthContext {
     Anonymous,
     IAMAccount(Credentials),
-} 
\ No newline at end of file
+}
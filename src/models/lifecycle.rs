@@ -0,0 +1,172 @@
+use super::Tag;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The `LifecycleConfiguration` document of `PUT /{bucket}?lifecycle`, as
+/// stored with the bucket's metadata and returned by its GET.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "LifecycleConfiguration")]
+pub struct LifecycleConfiguration {
+    #[serde(rename = "@xmlns", default, skip_serializing_if = "Option::is_none")]
+    pub xmlns: Option<String>,
+    #[serde(rename = "Rule", default)]
+    pub rules: Vec<LifecycleRule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LifecycleRule {
+    #[serde(rename = "ID", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "Filter", default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<LifecycleFilter>,
+    /// The older form of `Filter` with just a prefix.
+    #[serde(rename = "Prefix", default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(rename = "Status")]
+    pub status: LifecycleRuleStatus,
+    #[serde(
+        rename = "Expiration",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expiration: Option<LifecycleExpiration>,
+    #[serde(
+        rename = "AbortIncompleteMultipartUpload",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub abort_incomplete_multipart_upload: Option<AbortIncompleteMultipartUpload>,
+    /// Actions that are parsed only to be refused, since storage classes
+    /// and noncurrent version expiry are not implemented.
+    #[serde(rename = "Transition", default, skip_serializing)]
+    pub transitions: Vec<IgnoredAny>,
+    #[serde(rename = "NoncurrentVersionTransition", default, skip_serializing)]
+    pub noncurrent_version_transitions: Vec<IgnoredAny>,
+    #[serde(rename = "NoncurrentVersionExpiration", default, skip_serializing)]
+    pub noncurrent_version_expiration: Vec<IgnoredAny>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LifecycleRuleStatus {
+    Enabled,
+    Disabled,
+}
+
+/// Objects a rule applies to: one condition, or several under `And`.
+/// An empty filter matches the whole bucket.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LifecycleFilter {
+    #[serde(rename = "Prefix", default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(rename = "Tag", default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<Tag>,
+    #[serde(
+        rename = "ObjectSizeGreaterThan",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub object_size_greater_than: Option<u64>,
+    #[serde(
+        rename = "ObjectSizeLessThan",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub object_size_less_than: Option<u64>,
+    #[serde(rename = "And", default, skip_serializing_if = "Option::is_none")]
+    pub and: Option<LifecycleAnd>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LifecycleAnd {
+    #[serde(rename = "Prefix", default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(rename = "Tag", default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<Tag>,
+    #[serde(
+        rename = "ObjectSizeGreaterThan",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub object_size_greater_than: Option<u64>,
+    #[serde(
+        rename = "ObjectSizeLessThan",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub object_size_less_than: Option<u64>,
+}
+
+/// When current versions expire: `Days` after they were written, on a
+/// `Date` (midnight UTC, RFC 3339), or, with `ExpiredObjectDeleteMarker`,
+/// delete markers left without noncurrent versions.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LifecycleExpiration {
+    #[serde(rename = "Days", default, skip_serializing_if = "Option::is_none")]
+    pub days: Option<u32>,
+    #[serde(rename = "Date", default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    #[serde(
+        rename = "ExpiredObjectDeleteMarker",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expired_object_delete_marker: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AbortIncompleteMultipartUpload {
+    #[serde(rename = "DaysAfterInitiation")]
+    pub days_after_initiation: u32,
+}
+
+impl LifecycleRule {
+    pub fn enabled(&self) -> bool {
+        self.status == LifecycleRuleStatus::Enabled
+    }
+
+    /// Key prefix of the objects the rule applies to, from the filter or the
+    /// older top-level `Prefix`.
+    pub fn prefix(&self) -> &str {
+        let from_filter = self.filter.as_ref().and_then(|filter| match &filter.and {
+            Some(and) => and.prefix.as_deref(),
+            None => filter.prefix.as_deref(),
+        });
+        from_filter.or(self.prefix.as_deref()).unwrap_or_default()
+    }
+
+    /// Tags an object needs for the rule to apply.
+    pub fn tags(&self) -> Vec<&Tag> {
+        match &self.filter {
+            Some(LifecycleFilter { and: Some(and), .. }) => and.tags.iter().collect(),
+            Some(filter) => filter.tag.iter().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Sizes, in bytes, the object has to be strictly between.
+    fn size_bounds(&self) -> (Option<u64>, Option<u64>) {
+        match &self.filter {
+            Some(LifecycleFilter { and: Some(and), .. }) => {
+                (and.object_size_greater_than, and.object_size_less_than)
+            }
+            Some(filter) => (
+                filter.object_size_greater_than,
+                filter.object_size_less_than,
+            ),
+            None => (None, None),
+        }
+    }
+
+    /// Whether the rule applies to an object of `key`, `size` and `tags`.
+    pub fn matches(&self, key: &str, size: u64, tags: &BTreeMap<String, String>) -> bool {
+        let (greater_than, less_than) = self.size_bounds();
+        key.starts_with(self.prefix())
+            && greater_than.is_none_or(|bound| size > bound)
+            && less_than.is_none_or(|bound| size < bound)
+            && self
+                .tags()
+                .iter()
+                .all(|tag| tags.get(&tag.key) == Some(&tag.value))
+    }
+}
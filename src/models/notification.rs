@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+
+/// A bucket's `NotificationConfiguration` document, as sent to
+/// `PUT /{bucket}?notification` and stored with the bucket's metadata.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "NotificationConfiguration")]
+pub struct NotificationConfiguration {
+    #[serde(rename = "@xmlns", default, skip_serializing_if = "Option::is_none")]
+    pub xmlns: Option<String>,
+    #[serde(
+        rename = "TopicConfiguration",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub topic_configurations: Vec<TopicConfiguration>,
+    #[serde(
+        rename = "QueueConfiguration",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub queue_configurations: Vec<QueueConfiguration>,
+    #[serde(
+        rename = "CloudFunctionConfiguration",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub lambda_function_configurations: Vec<LambdaFunctionConfiguration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopicConfiguration {
+    #[serde(rename = "Id", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "Topic")]
+    pub topic_arn: String,
+    #[serde(rename = "Event", default)]
+    pub events: Vec<String>,
+    #[serde(rename = "Filter", default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<NotificationFilter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueueConfiguration {
+    #[serde(rename = "Id", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "Queue")]
+    pub queue_arn: String,
+    #[serde(rename = "Event", default)]
+    pub events: Vec<String>,
+    #[serde(rename = "Filter", default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<NotificationFilter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LambdaFunctionConfiguration {
+    #[serde(rename = "Id", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "CloudFunction")]
+    pub lambda_function_arn: String,
+    #[serde(rename = "Event", default)]
+    pub events: Vec<String>,
+    #[serde(rename = "Filter", default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<NotificationFilter>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NotificationFilter {
+    #[serde(rename = "S3Key", default)]
+    pub key: S3KeyFilter,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct S3KeyFilter {
+    #[serde(rename = "FilterRule", default)]
+    pub rules: Vec<FilterRule>,
+}
+
+/// `prefix` or `suffix` rule on the object key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilterRule {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Value")]
+    pub value: String,
+}
+
+/// One configuration of any destination kind, borrowed for evaluation.
+#[derive(Debug, Clone, Copy)]
+pub struct NotificationRule<'a> {
+    pub id: &'a str,
+    pub destination_arn: &'a str,
+    pub events: &'a [String],
+    pub filter: Option<&'a NotificationFilter>,
+}
+
+impl<'a> NotificationRule<'a> {
+    fn new(
+        id: &'a Option<String>,
+        destination_arn: &'a str,
+        events: &'a [String],
+        filter: &'a Option<NotificationFilter>,
+    ) -> Self {
+        Self {
+            id: id.as_deref().unwrap_or_default(),
+            destination_arn,
+            events,
+            filter: filter.as_ref(),
+        }
+    }
+}
+
+impl NotificationConfiguration {
+    /// Every topic, queue and function configuration. IDs are assigned when
+    /// the configuration is stored, so a missing one reads as empty.
+    pub fn rules(&self) -> Vec<NotificationRule<'_>> {
+        let topics = self
+            .topic_configurations
+            .iter()
+            .map(|c| NotificationRule::new(&c.id, &c.topic_arn, &c.events, &c.filter));
+        let queues = self
+            .queue_configurations
+            .iter()
+            .map(|c| NotificationRule::new(&c.id, &c.queue_arn, &c.events, &c.filter));
+        let functions = self
+            .lambda_function_configurations
+            .iter()
+            .map(|c| NotificationRule::new(&c.id, &c.lambda_function_arn, &c.events, &c.filter));
+        topics.chain(queues).chain(functions).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.topic_configurations.is_empty()
+            && self.queue_configurations.is_empty()
+            && self.lambda_function_configurations.is_empty()
+    }
+}
+
+impl NotificationFilter {
+    /// Value of the `prefix` or `suffix` rule. Rule names are
+    /// case-insensitive, as in S3.
+    pub fn rule(&self, name: &str) -> Option<&str> {
+        self.key
+            .rules
+            .iter()
+            .find(|r| r.name.eq_ignore_ascii_case(name))
+            .map(|r| r.value.as_str())
+    }
+}
+
+/// An event message waiting for delivery to one destination, persisted in
+/// the storage's event queue until delivered or moved to the dead-letter
+/// area.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedEvent {
+    /// Position in the queue; also the record's `sequencer`.
+    pub seq: u64,
+    pub destination_arn: String,
+    /// The S3 event message (`{"Records": [...]}`) sent as the body.
+    pub message: serde_json::Value,
+    pub queued: String, // RFC 3339, UTC
+    pub attempts: u32,
+    pub next_attempt: String, // RFC 3339, UTC
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
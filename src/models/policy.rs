@@ -0,0 +1,110 @@
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+
+/// Prefix of the S3 resource ARNs naming buckets and objects in policies.
+pub const S3_ARN_PREFIX: &str = "arn:aws:s3:::";
+
+/// Policy language versions accepted in `Version`.
+pub const POLICY_VERSIONS: &[&str] = &["2012-10-17", "2008-10-17"];
+
+/// The JSON document of `PUT /{bucket}?policy`, as stored with the bucket's
+/// metadata and returned by its GET.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BucketPolicy {
+    #[serde(rename = "Version", default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(rename = "Id", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "Statement")]
+    pub statements: OneOrMany<PolicyStatement>,
+}
+
+/// A policy element given either as a single value or as a list.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyStatement {
+    #[serde(rename = "Sid", default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    #[serde(rename = "Effect")]
+    pub effect: PolicyEffect,
+    #[serde(rename = "Principal", default, skip_serializing_if = "Option::is_none")]
+    pub principal: Option<PolicyPrincipal>,
+    #[serde(rename = "Action", default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<OneOrMany<String>>,
+    #[serde(rename = "Resource", default, skip_serializing_if = "Option::is_none")]
+    pub resource: Option<OneOrMany<String>>,
+    /// Elements that are parsed only to be refused, since conditions and
+    /// negated elements are not evaluated.
+    #[serde(rename = "Condition", default, skip_serializing)]
+    pub condition: Option<IgnoredAny>,
+    #[serde(rename = "NotPrincipal", default, skip_serializing)]
+    pub not_principal: Option<IgnoredAny>,
+    #[serde(rename = "NotAction", default, skip_serializing)]
+    pub not_action: Option<IgnoredAny>,
+    #[serde(rename = "NotResource", default, skip_serializing)]
+    pub not_resource: Option<IgnoredAny>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PolicyEffect {
+    Allow,
+    Deny,
+}
+
+/// Who a statement applies to: `"*"` for everyone, anonymous callers
+/// included, or `{"AWS": ...}` with access keys (or `"*"`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PolicyPrincipal {
+    Everyone(String),
+    Aws {
+        #[serde(rename = "AWS")]
+        aws: OneOrMany<String>,
+    },
+}
+
+impl<T> OneOrMany<T> {
+    pub fn as_slice(&self) -> &[T] {
+        match self {
+            OneOrMany::One(value) => std::slice::from_ref(value),
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+impl BucketPolicy {
+    pub fn statements(&self) -> &[PolicyStatement] {
+        self.statements.as_slice()
+    }
+}
+
+impl PolicyStatement {
+    pub fn actions(&self) -> &[String] {
+        self.action.as_ref().map_or(&[], OneOrMany::as_slice)
+    }
+
+    pub fn resources(&self) -> &[String] {
+        self.resource.as_ref().map_or(&[], OneOrMany::as_slice)
+    }
+
+    /// Whether the statement names the caller with access key `access_key`,
+    /// `None` for anonymous callers.
+    pub fn names(&self, access_key: Option<&str>) -> bool {
+        match &self.principal {
+            Some(PolicyPrincipal::Everyone(principal)) => principal == "*",
+            Some(PolicyPrincipal::Aws { aws }) => aws
+                .as_slice()
+                .iter()
+                .any(|principal| principal == "*" || Some(principal.as_str()) == access_key),
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+
+/// The policy document of a browser form upload (`POST /{bucket}`), sent
+/// base64-encoded in the `policy` field and signed by `x-amz-signature`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PostPolicy {
+    /// ISO 8601, e.g. `2026-01-01T12:00:00.000Z`.
+    pub expiration: String,
+    #[serde(default)]
+    pub conditions: Vec<Value>,
+}
+
+/// A condition of a [`PostPolicy`] on the form fields. Field names are
+/// lowercase and without the `$` of the array forms.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostCondition {
+    /// `{"field": "value"}` or `["eq", "$field", "value"]`.
+    Eq(String, String),
+    /// `["starts-with", "$field", "prefix"]`; an empty prefix allows any
+    /// value.
+    StartsWith(String, String),
+    /// `["content-length-range", min, max]`, in bytes of the file.
+    ContentLengthRange(u64, u64),
+}
+
+/// As written in a policy, for error messages.
+impl fmt::Display for PostCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Eq(field, value) => write!(f, "[\"eq\", \"${}\", \"{}\"]", field, value),
+            Self::StartsWith(field, prefix) => {
+                write!(f, "[\"starts-with\", \"${}\", \"{}\"]", field, prefix)
+            }
+            Self::ContentLengthRange(min, max) => {
+                write!(f, "[\"content-length-range\", {}, {}]", min, max)
+            }
+        }
+    }
+}
+
+impl PostPolicy {
+    /// The conditions of the policy, or what is wrong with one of them.
+    pub fn parse_conditions(&self) -> Result<Vec<PostCondition>, String> {
+        let mut conditions = Vec::new();
+        for condition in &self.conditions {
+            match condition {
+                Value::Object(fields) => {
+                    for (field, value) in fields {
+                        let value = value
+                            .as_str()
+                            .ok_or_else(|| format!("Invalid condition value for {}", field))?;
+                        conditions.push(PostCondition::Eq(
+                            field.to_ascii_lowercase(),
+                            value.to_string(),
+                        ));
+                    }
+                }
+                Value::Array(items) => conditions.push(array_condition(items)?),
+                _ => return Err("Conditions must be objects or arrays".to_string()),
+            }
+        }
+        Ok(conditions)
+    }
+}
+
+fn array_condition(items: &[Value]) -> Result<PostCondition, String> {
+    let operator = items
+        .first()
+        .and_then(Value::as_str)
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    if operator == "content-length-range" {
+        let bound = |index: usize| items.get(index).and_then(length_bound);
+        return match (items.len(), bound(1), bound(2)) {
+            (3, Some(min), Some(max)) if min <= max => {
+                Ok(PostCondition::ContentLengthRange(min, max))
+            }
+            _ => Err("Invalid content-length-range condition".to_string()),
+        };
+    }
+    let (Some(field), Some(value), 3) = (
+        items.get(1).and_then(Value::as_str),
+        items.get(2).and_then(Value::as_str),
+        items.len(),
+    ) else {
+        return Err(format!("Invalid {} condition", operator));
+    };
+    let Some(field) = field.strip_prefix('$') else {
+        return Err(format!("Condition field {} must start with $", field));
+    };
+    let field = field.to_ascii_lowercase();
+    match operator.as_str() {
+        "eq" => Ok(PostCondition::Eq(field, value.to_string())),
+        "starts-with" => Ok(PostCondition::StartsWith(field, value.to_string())),
+        _ => Err(format!("Invalid condition operator: {}", operator)),
+    }
+}
+
+/// A `content-length-range` bound, given as a number or a numeric string.
+fn length_bound(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
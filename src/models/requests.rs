@@ -1,5 +1,8 @@
-use std::collections::HashMap;
-use hyper::Body;
+use super::{AclGrant, SearchPredicate};
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use axum::body::Body;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Debug, Clone)]
 pub struct S3CommonHeaders {
@@ -13,10 +16,17 @@ pub struct PutObjectHeaders {
     pub common: S3CommonHeaders,
     pub content_length: u64,
     pub content_type: Option<String>,
+    pub content_disposition: Option<String>,
+    pub content_encoding: Option<String>,
+    pub content_language: Option<String>,
+    pub cache_control: Option<String>,
     pub storage_class: Option<String>,
-    pub acl: Option<String>,
+    pub acl: Option<Vec<AclGrant>>, // x-amz-acl or x-amz-grant-*
     pub server_side_encryption: Option<String>,
+    pub sse_kms_key_id: Option<String>, // x-amz-server-side-encryption-aws-kms-key-id
     pub user_metadata: HashMap<String, String>, // x-amz-meta-*
+    pub tagging: BTreeMap<String, String>, // x-amz-tagging
+    pub checksum: Option<Checksum>,     // x-amz-checksum-*, verified
 }
 
 #[derive(Debug, Clone)]
@@ -29,6 +39,44 @@ pub struct GetObjectHeaders {
     pub if_none_match: Option<String>,
 }
 
+/// Whether CopyObject keeps the source's metadata/tags or takes them from the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Directive {
+    Copy,
+    Replace,
+}
+
+/// `x-amz-copy-source-if-*` conditions, evaluated against the source object.
+#[derive(Debug, Clone, Default)]
+pub struct CopySourceConditions {
+    pub if_match: Option<String>,
+    pub if_none_match: Option<String>,
+    pub if_modified_since: Option<String>,
+    pub if_unmodified_since: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CopyObjectHeaders {
+    pub common: S3CommonHeaders,
+    pub metadata_directive: Directive,
+    pub tagging_directive: Directive,
+    pub content_type: Option<String>,
+    pub content_disposition: Option<String>, // used with metadata_directive REPLACE
+    pub content_encoding: Option<String>,    // used with metadata_directive REPLACE
+    pub content_language: Option<String>,    // used with metadata_directive REPLACE
+    pub cache_control: Option<String>,       // used with metadata_directive REPLACE
+    pub user_metadata: HashMap<String, String>, // used with metadata_directive REPLACE
+    pub tagging: BTreeMap<String, String>,   // used with tagging_directive REPLACE
+    pub acl: Option<Vec<AclGrant>>,          // not copied from the source
+    pub storage_class: Option<String>,
+    pub server_side_encryption: Option<String>, // applies to the copy, not taken from the source
+    pub sse_kms_key_id: Option<String>,
+    pub conditions: CopySourceConditions,
+    /// `x-amz-checksum-algorithm`: the copy gets a checksum of its own
+    /// rather than the source's.
+    pub checksum_algorithm: Option<ChecksumAlgorithm>,
+}
+
 #[derive(Debug, Clone)]
 pub struct DeleteObjectHeaders {
     pub common: S3CommonHeaders,
@@ -37,14 +85,9 @@ pub struct DeleteObjectHeaders {
 #[derive(Debug, Clone)]
 pub struct CreateBucketHeaders {
     pub common: S3CommonHeaders,
-    pub acl: Option<String>,
+    pub acl: Option<Vec<AclGrant>>, // x-amz-acl or x-amz-grant-*
     pub object_lock_enabled: Option<bool>,
     pub object_ownership: Option<String>,
-    pub grant_full_control: Option<String>,
-    pub grant_read: Option<String>,
-    pub grant_read_acp: Option<String>,
-    pub grant_write: Option<String>,
-    pub grant_write_acp: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -60,14 +103,36 @@ pub struct ListBucketsHeaders {
 #[derive(Debug, Clone)]
 pub struct ListObjectsHeaders {
     pub common: S3CommonHeaders,
+    /// `x-amz-optional-object-attributes`: `RestoreObject` adds a
+    /// `RestoreStatus` to objects in archive storage classes.
+    pub optional_object_attributes: Option<String>,
+}
+
+impl ListObjectsHeaders {
+    pub fn restore_status(&self) -> bool {
+        self.optional_object_attributes
+            .as_deref()
+            .is_some_and(|attributes| attributes.split(',').any(|a| a.trim() == "RestoreObject"))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct InitiateMultipartUploadHeaders {
     pub common: S3CommonHeaders,
+    pub content_type: Option<String>,
+    pub content_disposition: Option<String>,
+    pub content_encoding: Option<String>,
+    pub content_language: Option<String>,
+    pub cache_control: Option<String>,
     pub storage_class: Option<String>,
-    pub acl: Option<String>,
+    pub acl: Option<Vec<AclGrant>>,
+    pub server_side_encryption: Option<String>,
+    pub sse_kms_key_id: Option<String>,
     pub user_metadata: HashMap<String, String>,
+    pub tagging: BTreeMap<String, String>,
+    /// `x-amz-checksum-algorithm`, which every part must then be uploaded
+    /// with.
+    pub checksum_algorithm: Option<ChecksumAlgorithm>,
 }
 
 #[derive(Debug, Clone)]
@@ -75,6 +140,7 @@ pub struct UploadPartHeaders {
     pub common: S3CommonHeaders,
     pub content_length: u64,
     pub content_md5: Option<String>,
+    pub checksum: Option<Checksum>, // x-amz-checksum-*, verified
 }
 
 #[derive(Debug, Clone)]
@@ -98,6 +164,8 @@ pub struct CreateBucketRequest {
 pub struct DeleteBucketRequest {
     pub bucket: String,
     pub headers: DeleteBucketHeaders,
+    /// Access key of the caller, or `anonymous`; recorded in tombstones.
+    pub deleted_by: String,
 }
 
 #[derive(Debug, Clone)]
@@ -123,14 +191,52 @@ pub struct ListObjectsV2Request {
     pub start_after: Option<String>,
     pub continuation_token: Option<String>,
     pub max_keys: Option<u32>,
+    pub fetch_owner: bool,
     pub headers: ListObjectsHeaders,
 }
 
 #[derive(Debug, Clone)]
+pub struct ListObjectVersionsRequest {
+    pub bucket: String,
+    pub prefix: Option<String>,
+    pub delimiter: Option<String>,
+    pub key_marker: Option<String>,
+    pub version_id_marker: Option<String>,
+    pub max_keys: Option<u32>,
+    /// Extension `as-of`: list the version of each key that was current
+    /// at this time instead of every version.
+    pub as_of: Option<DateTime<Utc>>,
+    pub headers: ListObjectsHeaders,
+}
+
+/// Extension: `GET /{bucket}?search&query=...`.
+#[derive(Debug, Clone)]
+pub struct SearchObjectsRequest {
+    pub bucket: String,
+    pub prefix: String,
+    pub predicates: Vec<SearchPredicate>,
+    /// Only keys after this one, from a continuation token.
+    pub start_after: Option<String>,
+}
+
+#[derive(Debug)]
 pub struct PutObjectRequest {
     pub bucket: String,
     pub key: String,
     pub headers: PutObjectHeaders,
+    /// Read a piece at a time, never held in memory as a whole.
+    pub body: Body,
+}
+
+/// Extension: `PUT /{bucket}/{key}?append&position=N`.
+#[derive(Debug)]
+pub struct AppendObjectRequest {
+    pub bucket: String,
+    pub key: String,
+    /// Must equal the current object size (0 to create the object).
+    pub position: u64,
+    pub headers: PutObjectHeaders,
+    /// Read a piece at a time, like the body of a PUT.
     pub body: Body,
 }
 
@@ -138,13 +244,25 @@ pub struct PutObjectRequest {
 pub struct GetObjectRequest {
     pub bucket: String,
     pub key: String,
+    pub version_id: Option<String>,
     pub headers: GetObjectHeaders,
 }
 
+#[derive(Debug, Clone)]
+pub struct CopyObjectRequest {
+    pub source_bucket: String,
+    pub source_key: String,
+    pub source_version_id: Option<String>,
+    pub bucket: String,
+    pub key: String,
+    pub headers: CopyObjectHeaders,
+}
+
 #[derive(Debug, Clone)]
 pub struct DeleteObjectRequest {
     pub bucket: String,
     pub key: String,
+    pub version_id: Option<String>,
     pub headers: DeleteObjectHeaders,
 }
 
@@ -155,13 +273,14 @@ pub struct InitiateMultipartUploadRequest {
     pub headers: InitiateMultipartUploadHeaders,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct UploadPartRequest {
     pub bucket: String,
     pub key: String,
     pub upload_id: String,
     pub part_number: u32,
     pub headers: UploadPartHeaders,
+    /// Read a piece at a time, like [`PutObjectRequest::body`].
     pub body: Body,
 }
 
@@ -171,7 +290,16 @@ pub struct CompleteMultipartUploadRequest {
     pub key: String,
     pub upload_id: String,
     pub headers: CompleteMultipartUploadHeaders,
-    pub parts: Vec<(u32, String)>, // (part_number, etag)
+    pub parts: Vec<CompletedPart>,
+}
+
+/// A part of a CompleteMultipartUpload, which must match the uploaded one.
+#[derive(Debug, Clone)]
+pub struct CompletedPart {
+    pub part_number: u32,
+    pub etag: String,
+    /// `ChecksumCRC32` and so on, if the client sent one.
+    pub checksum: Option<Checksum>,
 }
 
 #[derive(Debug, Clone)]
@@ -182,18 +310,29 @@ pub struct AbortMultipartUploadRequest {
     pub headers: AbortMultipartUploadHeaders,
 }
 
+/// STS `AssumeRole` (query API), sent as `POST /` with a form body.
 #[derive(Debug, Clone)]
+pub struct AssumeRoleRequest {
+    pub role_arn: String,
+    pub role_session_name: String,
+    pub policy: Option<String>, // inline session policy (IAM JSON)
+    pub duration_seconds: Option<u32>,
+}
+
+#[derive(Debug)]
 pub enum Request {
     CreateBucket(CreateBucketRequest),
     DeleteBucket(DeleteBucketRequest),
     ListBuckets(ListBucketsRequest),
     ListObjects(ListObjectsRequest),
     ListObjectsV2(ListObjectsV2Request),
+    ListObjectVersions(ListObjectVersionsRequest),
     PutObject(PutObjectRequest),
+    CopyObject(CopyObjectRequest),
     GetObject(GetObjectRequest),
     DeleteObject(DeleteObjectRequest),
     InitiateMultipartUpload(InitiateMultipartUploadRequest),
     UploadPart(UploadPartRequest),
     CompleteMultipartUpload(CompleteMultipartUploadRequest),
     AbortMultipartUpload(AbortMultipartUploadRequest),
-}
\ No newline at end of file
+}
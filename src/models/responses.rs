@@ -1,5 +1,7 @@
-use hyper::Body;
-use std::collections::HashMap;
+use crate::checksum::{Checksum, ChecksumAlgorithm};
+use crate::models::Owner;
+use bytes::Bytes;
+use serde::Serialize;
 
 #[derive(Debug, Clone)]
 pub struct S3ErrorResponse {
@@ -18,48 +20,256 @@ pub struct CreateBucketResponse {
 #[derive(Debug, Clone)]
 pub struct DeleteBucketResponse;
 
-#[derive(Debug, Clone)]
+/// Namespace of S3 response documents.
+pub const S3_XMLNS: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename = "ListAllMyBucketsResult")]
 pub struct ListBucketsResponse {
-    pub owner_id: String,
-    pub owner_display_name: String,
-    pub buckets: Vec<BucketSummary>,
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    #[serde(rename = "Owner")]
+    pub owner: Owner,
+    #[serde(rename = "Buckets")]
+    pub buckets: BucketList,
 }
 
-#[derive(Debug, Clone)]
+impl ListBucketsResponse {
+    pub fn new(owner: Owner, buckets: Vec<BucketSummary>) -> Self {
+        Self {
+            xmlns: S3_XMLNS,
+            owner,
+            buckets: BucketList { bucket: buckets },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BucketList {
+    #[serde(rename = "Bucket")]
+    pub bucket: Vec<BucketSummary>,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct BucketSummary {
+    #[serde(rename = "Name")]
     pub name: String,
+    #[serde(rename = "CreationDate")]
     pub creation_date: String,
+    #[serde(rename = "BucketRegion")]
+    pub bucket_region: String,
 }
 
-#[derive(Debug, Clone)]
+/// `LocationConstraint` for GetBucketLocation: the bucket's region, empty
+/// for `us-east-1` as in S3.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename = "LocationConstraint")]
+pub struct LocationConstraintResponse {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    #[serde(rename = "$text")]
+    pub region: String,
+}
+
+impl LocationConstraintResponse {
+    pub fn new(region: &str) -> Self {
+        Self {
+            xmlns: S3_XMLNS,
+            region: if region == "us-east-1" {
+                String::new()
+            } else {
+                region.to_string()
+            },
+        }
+    }
+}
+
+/// `ListBucketResult` for ListObjects (V1).
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename = "ListBucketResult")]
 pub struct ListObjectsResponse {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    #[serde(rename = "Name")]
     pub name: String,
-    pub prefix: Option<String>,
-    pub marker: Option<String>,
+    #[serde(rename = "Prefix")]
+    pub prefix: String,
+    #[serde(rename = "Marker")]
+    pub marker: String,
+    #[serde(rename = "NextMarker", skip_serializing_if = "Option::is_none")]
+    pub next_marker: Option<String>, // only with a delimiter, as in S3
+    #[serde(rename = "MaxKeys")]
     pub max_keys: u32,
+    #[serde(rename = "Delimiter", skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<String>,
+    #[serde(rename = "EncodingType", skip_serializing_if = "Option::is_none")]
+    pub encoding_type: Option<String>,
+    #[serde(rename = "IsTruncated")]
     pub is_truncated: bool,
+    #[serde(rename = "Contents")]
     pub contents: Vec<ObjectSummary>,
+    #[serde(rename = "CommonPrefixes")]
+    pub common_prefixes: Vec<CommonPrefix>,
 }
 
-#[derive(Debug, Clone)]
+/// `ListBucketResult` for ListObjectsV2.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename = "ListBucketResult")]
 pub struct ListObjectsV2Response {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    #[serde(rename = "Name")]
     pub name: String,
-    pub prefix: Option<String>,
-    pub key_count: u32,
-    pub max_keys: u32,
+    #[serde(rename = "Prefix")]
+    pub prefix: String,
+    #[serde(rename = "Delimiter", skip_serializing_if = "Option::is_none")]
     pub delimiter: Option<String>,
+    #[serde(rename = "MaxKeys")]
+    pub max_keys: u32,
+    #[serde(rename = "EncodingType", skip_serializing_if = "Option::is_none")]
+    pub encoding_type: Option<String>,
+    #[serde(rename = "KeyCount")]
+    pub key_count: u32, // Contents plus CommonPrefixes
+    #[serde(rename = "IsTruncated")]
     pub is_truncated: bool,
-    pub contents: Vec<ObjectSummary>,
+    #[serde(rename = "ContinuationToken", skip_serializing_if = "Option::is_none")]
+    pub continuation_token: Option<String>,
+    #[serde(
+        rename = "NextContinuationToken",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub next_continuation_token: Option<String>,
+    #[serde(rename = "StartAfter", skip_serializing_if = "Option::is_none")]
+    pub start_after: Option<String>,
+    #[serde(rename = "Contents")]
+    pub contents: Vec<ObjectSummary>,
+    #[serde(rename = "CommonPrefixes")]
+    pub common_prefixes: Vec<CommonPrefix>,
 }
 
-#[derive(Debug, Clone)]
+/// `ListVersionsResult` for ListObjectVersions.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename = "ListVersionsResult")]
+pub struct ListObjectVersionsResponse {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Prefix")]
+    pub prefix: String,
+    #[serde(rename = "KeyMarker")]
+    pub key_marker: String,
+    #[serde(rename = "VersionIdMarker")]
+    pub version_id_marker: String,
+    #[serde(rename = "NextKeyMarker", skip_serializing_if = "Option::is_none")]
+    pub next_key_marker: Option<String>,
+    #[serde(
+        rename = "NextVersionIdMarker",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub next_version_id_marker: Option<String>,
+    #[serde(rename = "MaxKeys")]
+    pub max_keys: u32,
+    #[serde(rename = "Delimiter", skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<String>,
+    #[serde(rename = "EncodingType", skip_serializing_if = "Option::is_none")]
+    pub encoding_type: Option<String>,
+    #[serde(rename = "IsTruncated")]
+    pub is_truncated: bool,
+    /// `Version` and `DeleteMarker` elements, in listing order.
+    #[serde(rename = "$value")]
+    pub versions: Vec<VersionEntry>,
+    #[serde(rename = "CommonPrefixes")]
+    pub common_prefixes: Vec<CommonPrefix>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum VersionEntry {
+    Version(ObjectVersionSummary),
+    DeleteMarker(DeleteMarkerEntry),
+}
+
+impl VersionEntry {
+    pub fn key_mut(&mut self) -> &mut String {
+        match self {
+            VersionEntry::Version(version) => &mut version.key,
+            VersionEntry::DeleteMarker(marker) => &mut marker.key,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ObjectVersionSummary {
+    #[serde(rename = "Key")]
+    pub key: String,
+    #[serde(rename = "VersionId")]
+    pub version_id: String,
+    #[serde(rename = "IsLatest")]
+    pub is_latest: bool,
+    #[serde(rename = "LastModified")]
+    pub last_modified: String,
+    #[serde(rename = "ETag")]
+    pub etag: String,
+    #[serde(rename = "ChecksumAlgorithm", skip_serializing_if = "Option::is_none")]
+    pub checksum_algorithm: Option<String>,
+    #[serde(rename = "Size")]
+    pub size: u64,
+    #[serde(rename = "StorageClass")]
+    pub storage_class: String,
+    #[serde(rename = "Owner")]
+    pub owner: Owner,
+    #[serde(rename = "RestoreStatus", skip_serializing_if = "Option::is_none")]
+    pub restore_status: Option<RestoreStatus>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeleteMarkerEntry {
+    #[serde(rename = "Key")]
+    pub key: String,
+    #[serde(rename = "VersionId")]
+    pub version_id: String,
+    #[serde(rename = "IsLatest")]
+    pub is_latest: bool,
+    #[serde(rename = "LastModified")]
+    pub last_modified: String,
+    #[serde(rename = "Owner")]
+    pub owner: Owner,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct ObjectSummary {
+    #[serde(rename = "Key")]
     pub key: String,
+    #[serde(rename = "LastModified")]
     pub last_modified: String,
+    #[serde(rename = "ETag")]
     pub etag: String,
+    #[serde(rename = "ChecksumAlgorithm", skip_serializing_if = "Option::is_none")]
+    pub checksum_algorithm: Option<String>,
+    #[serde(rename = "Size")]
     pub size: u64,
+    #[serde(rename = "StorageClass")]
     pub storage_class: String,
+    #[serde(rename = "Owner", skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Owner>, // V1 always, V2 only with fetch-owner=true
+    #[serde(rename = "RestoreStatus", skip_serializing_if = "Option::is_none")]
+    pub restore_status: Option<RestoreStatus>,
+}
+
+/// `RestoreStatus` of a listed object in an archive storage class, sent
+/// when the listing asks for it with `x-amz-optional-object-attributes:
+/// RestoreObject`. Nothing is archived here, so such objects always read as
+/// restored and without an expiry.
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreStatus {
+    #[serde(rename = "IsRestoreInProgress")]
+    pub is_restore_in_progress: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommonPrefix {
+    #[serde(rename = "Prefix")]
+    pub prefix: String,
 }
 
 #[derive(Debug, Clone)]
@@ -67,7 +277,7 @@ pub struct GetObjectResponse {
     pub content_type: String,
     pub content_length: u64,
     pub etag: String,
-    pub body: Body,
+    pub body: Bytes,
 }
 
 #[derive(Debug, Clone)]
@@ -75,13 +285,168 @@ pub struct PutObjectResponse {
     pub etag: String,
 }
 
-#[derive(Debug, Clone)]
-pub struct DeleteObjectResponse;
+#[derive(Debug, Clone, Default)]
+pub struct DeleteObjectResponse {
+    /// The version deleted, or the one the delete created, is a delete marker.
+    pub delete_marker: bool,
+    /// That version; `None` for deletes in unversioned buckets.
+    pub version_id: Option<String>,
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename = "CopyObjectResult")]
+pub struct CopyObjectResponse {
+    #[serde(rename = "LastModified")]
+    pub last_modified: String,
+    #[serde(rename = "ETag")]
+    pub etag: String,
+    #[serde(rename = "ChecksumCRC32", skip_serializing_if = "Option::is_none")]
+    pub checksum_crc32: Option<String>,
+    #[serde(rename = "ChecksumCRC32C", skip_serializing_if = "Option::is_none")]
+    pub checksum_crc32c: Option<String>,
+    #[serde(rename = "ChecksumSHA1", skip_serializing_if = "Option::is_none")]
+    pub checksum_sha1: Option<String>,
+    #[serde(rename = "ChecksumSHA256", skip_serializing_if = "Option::is_none")]
+    pub checksum_sha256: Option<String>,
+    #[serde(rename = "ChecksumType", skip_serializing_if = "Option::is_none")]
+    pub checksum_type: Option<&'static str>,
+}
+
+/// An additional checksum as S3 puts it in XML: in the element of its
+/// algorithm, with its `ChecksumType`. Results that have these elements
+/// next to others repeat the fields, taking them from here.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChecksumElements {
+    #[serde(rename = "ChecksumCRC32", skip_serializing_if = "Option::is_none")]
+    pub crc32: Option<String>,
+    #[serde(rename = "ChecksumCRC32C", skip_serializing_if = "Option::is_none")]
+    pub crc32c: Option<String>,
+    #[serde(rename = "ChecksumSHA1", skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+    #[serde(rename = "ChecksumSHA256", skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(rename = "ChecksumType", skip_serializing_if = "Option::is_none")]
+    pub checksum_type: Option<&'static str>,
+}
+
+impl ChecksumElements {
+    pub fn new(checksum: Option<Checksum>) -> Self {
+        let mut elements = Self::default();
+        let Some(checksum) = checksum else {
+            return elements;
+        };
+        elements.checksum_type = Some(checksum.checksum_type());
+        let slot = match checksum.algorithm {
+            ChecksumAlgorithm::Crc32 => &mut elements.crc32,
+            ChecksumAlgorithm::Crc32c => &mut elements.crc32c,
+            ChecksumAlgorithm::Sha1 => &mut elements.sha1,
+            ChecksumAlgorithm::Sha256 => &mut elements.sha256,
+        };
+        *slot = Some(checksum.value);
+        elements
+    }
+}
+
+/// Result of GetObjectAttributes: the attributes asked for with
+/// `x-amz-object-attributes`, the others left out.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename = "GetObjectAttributesResponse")]
+pub struct GetObjectAttributesResponse {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    /// Without quotes, unlike everywhere else.
+    #[serde(rename = "ETag", skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(rename = "Checksum", skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<ChecksumElements>,
+    #[serde(rename = "ObjectParts", skip_serializing_if = "Option::is_none")]
+    pub object_parts: Option<ObjectParts>,
+    #[serde(rename = "StorageClass", skip_serializing_if = "Option::is_none")]
+    pub storage_class: Option<String>,
+    #[serde(rename = "ObjectSize", skip_serializing_if = "Option::is_none")]
+    pub object_size: Option<u64>,
+}
+
+/// The parts of an object uploaded in parts. Parts are not kept once the
+/// upload is complete, so only their number is known.
+#[derive(Debug, Clone, Serialize)]
+pub struct ObjectParts {
+    #[serde(rename = "PartsCount")]
+    pub parts_count: u32,
+}
+
+/// The body of a browser form upload with `success_action_status=201`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename = "PostResponse")]
+pub struct PostObjectResponse {
+    #[serde(rename = "Location")]
+    pub location: String,
+    #[serde(rename = "Bucket")]
+    pub bucket: String,
+    #[serde(rename = "Key")]
+    pub key: String,
+    #[serde(rename = "ETag")]
+    pub etag: String,
+}
+
+/// Namespace of STS response documents.
+pub const STS_XMLNS: &str = "https://sts.amazonaws.com/doc/2011-06-15/";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename = "AssumeRoleResponse")]
+pub struct AssumeRoleResponse {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    #[serde(rename = "AssumeRoleResult")]
+    pub result: AssumeRoleResult,
+    #[serde(rename = "ResponseMetadata")]
+    pub response_metadata: ResponseMetadata,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AssumeRoleResult {
+    #[serde(rename = "Credentials")]
+    pub credentials: SessionCredentials,
+    #[serde(rename = "AssumedRoleUser")]
+    pub assumed_role_user: AssumedRoleUser,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionCredentials {
+    #[serde(rename = "AccessKeyId")]
+    pub access_key_id: String,
+    #[serde(rename = "SecretAccessKey")]
+    pub secret_access_key: String,
+    #[serde(rename = "SessionToken")]
+    pub session_token: String,
+    #[serde(rename = "Expiration")]
+    pub expiration: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AssumedRoleUser {
+    #[serde(rename = "Arn")]
+    pub arn: String,
+    #[serde(rename = "AssumedRoleId")]
+    pub assumed_role_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResponseMetadata {
+    #[serde(rename = "RequestId")]
+    pub request_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename = "InitiateMultipartUploadResult")]
 pub struct InitiateMultipartUploadResponse {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    #[serde(rename = "Bucket")]
     pub bucket: String,
+    #[serde(rename = "Key")]
     pub key: String,
+    #[serde(rename = "UploadId")]
     pub upload_id: String,
 }
 
@@ -90,17 +455,74 @@ pub struct UploadPartResponse {
     pub etag: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename = "CompleteMultipartUploadResult")]
 pub struct CompleteMultipartUploadResponse {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    #[serde(rename = "Location")]
     pub location: String,
+    #[serde(rename = "Bucket")]
     pub bucket: String,
+    #[serde(rename = "Key")]
     pub key: String,
+    #[serde(rename = "ETag")]
     pub etag: String,
+    #[serde(rename = "ChecksumCRC32", skip_serializing_if = "Option::is_none")]
+    pub checksum_crc32: Option<String>,
+    #[serde(rename = "ChecksumCRC32C", skip_serializing_if = "Option::is_none")]
+    pub checksum_crc32c: Option<String>,
+    #[serde(rename = "ChecksumSHA1", skip_serializing_if = "Option::is_none")]
+    pub checksum_sha1: Option<String>,
+    #[serde(rename = "ChecksumSHA256", skip_serializing_if = "Option::is_none")]
+    pub checksum_sha256: Option<String>,
+    #[serde(rename = "ChecksumType", skip_serializing_if = "Option::is_none")]
+    pub checksum_type: Option<&'static str>,
 }
 
 #[derive(Debug, Clone)]
 pub struct AbortMultipartUploadResponse;
 
+/// Result of DeleteObjects: the keys deleted (left out in quiet mode) and
+/// the keys that could not be.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename = "DeleteResult")]
+pub struct DeleteObjectsResponse {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    #[serde(rename = "Deleted")]
+    pub deleted: Vec<DeletedObject>,
+    #[serde(rename = "Error")]
+    pub errors: Vec<DeleteObjectError>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeletedObject {
+    #[serde(rename = "Key")]
+    pub key: String,
+    #[serde(rename = "VersionId", skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<String>,
+    #[serde(rename = "DeleteMarker", skip_serializing_if = "Option::is_none")]
+    pub delete_marker: Option<bool>,
+    #[serde(
+        rename = "DeleteMarkerVersionId",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub delete_marker_version_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeleteObjectError {
+    #[serde(rename = "Key")]
+    pub key: String,
+    #[serde(rename = "VersionId", skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<String>,
+    #[serde(rename = "Code")]
+    pub code: String,
+    #[serde(rename = "Message")]
+    pub message: String,
+}
+
 #[derive(Debug, Clone)]
 pub enum Response {
     CreateBucket(Result<CreateBucketResponse, S3ErrorResponse>),
@@ -108,8 +530,10 @@ pub enum Response {
     ListBuckets(Result<ListBucketsResponse, S3ErrorResponse>),
     ListObjects(Result<ListObjectsResponse, S3ErrorResponse>),
     ListObjectsV2(Result<ListObjectsV2Response, S3ErrorResponse>),
+    ListObjectVersions(Result<ListObjectVersionsResponse, S3ErrorResponse>),
     GetObject(Result<GetObjectResponse, S3ErrorResponse>),
     PutObject(Result<PutObjectResponse, S3ErrorResponse>),
+    CopyObject(Result<CopyObjectResponse, S3ErrorResponse>),
     DeleteObject(Result<DeleteObjectResponse, S3ErrorResponse>),
     InitiateMultipartUpload(Result<InitiateMultipartUploadResponse, S3ErrorResponse>),
     UploadPart(Result<UploadPartResponse, S3ErrorResponse>),
@@ -119,14 +543,69 @@ pub enum Response {
 
 // S3 error code constants
 pub const ERROR_ACCESS_DENIED: &str = "AccessDenied";
+pub const ERROR_ACCESS_FORBIDDEN: &str = "AccessForbidden";
 pub const ERROR_NO_SUCH_BUCKET: &str = "NoSuchBucket";
 pub const ERROR_NO_SUCH_KEY: &str = "NoSuchKey";
+pub const ERROR_NO_SUCH_VERSION: &str = "NoSuchVersion";
 pub const ERROR_NO_SUCH_UPLOAD: &str = "NoSuchUpload";
+pub const ERROR_NO_SUCH_TAG_SET: &str = "NoSuchTagSet";
+pub const ERROR_NO_SUCH_LIFECYCLE_CONFIGURATION: &str = "NoSuchLifecycleConfiguration";
+pub const ERROR_NO_SUCH_BUCKET_POLICY: &str = "NoSuchBucketPolicy";
+pub const ERROR_NO_SUCH_CORS_CONFIGURATION: &str = "NoSuchCORSConfiguration";
 pub const ERROR_BUCKET_ALREADY_EXISTS: &str = "BucketAlreadyExists";
 pub const ERROR_BUCKET_ALREADY_OWNED_BY_YOU: &str = "BucketAlreadyOwnedByYou";
-pub const ERROR_BUCKET_NOT_EMPTY: &str = "BucketNotEmpty"; 
+pub const ERROR_BUCKET_NOT_EMPTY: &str = "BucketNotEmpty";
 pub const ERROR_INVALID_BUCKET_NAME: &str = "InvalidBucketName";
 pub const ERROR_INVALID_OBJECT_NAME: &str = "InvalidObjectName";
+pub const ERROR_KEY_TOO_LONG: &str = "KeyTooLongError";
+pub const ERROR_METADATA_TOO_LARGE: &str = "MetadataTooLarge";
 pub const ERROR_INVALID_PART: &str = "InvalidPart";
 pub const ERROR_INVALID_PART_ORDER: &str = "InvalidPartOrder";
-pub const ERROR_INVALID_RANGE: &str = "InvalidRange";
\ No newline at end of file
+pub const ERROR_ENTITY_TOO_SMALL: &str = "EntityTooSmall";
+pub const ERROR_ENTITY_TOO_LARGE: &str = "EntityTooLarge";
+pub const ERROR_INVALID_RANGE: &str = "InvalidRange";
+pub const ERROR_INVALID_STORAGE_CLASS: &str = "InvalidStorageClass";
+pub const ERROR_INVALID_TAG: &str = "InvalidTag";
+pub const ERROR_MALFORMED_XML: &str = "MalformedXML";
+pub const ERROR_MAX_MESSAGE_LENGTH_EXCEEDED: &str = "MaxMessageLengthExceeded";
+pub const ERROR_MALFORMED_POLICY: &str = "MalformedPolicy";
+pub const ERROR_MALFORMED_ACL: &str = "MalformedACLError";
+pub const ERROR_MALFORMED_POST_REQUEST: &str = "MalformedPOSTRequest";
+pub const ERROR_INVALID_POLICY_DOCUMENT: &str = "InvalidPolicyDocument";
+pub const ERROR_INCORRECT_NUMBER_OF_FILES: &str = "IncorrectNumberOfFilesInPostRequest";
+pub const ERROR_MISSING_SECURITY_HEADER: &str = "MissingSecurityHeader";
+pub const ERROR_INVALID_ACCESS_KEY_ID: &str = "InvalidAccessKeyId";
+pub const ERROR_INVALID_ARGUMENT: &str = "InvalidArgument";
+pub const ERROR_INVALID_REQUEST: &str = "InvalidRequest";
+pub const ERROR_BAD_REQUEST: &str = "BadRequest";
+pub const ERROR_INCOMPLETE_BODY: &str = "IncompleteBody";
+pub const ERROR_INVALID_DIGEST: &str = "InvalidDigest";
+pub const ERROR_BAD_DIGEST: &str = "BadDigest";
+pub const ERROR_CONTENT_SHA256_MISMATCH: &str = "XAmzContentSHA256Mismatch";
+pub const ERROR_PRECONDITION_FAILED: &str = "PreconditionFailed";
+pub const ERROR_NOT_IMPLEMENTED: &str = "NotImplemented";
+pub const ERROR_METHOD_NOT_ALLOWED: &str = "MethodNotAllowed";
+pub const ERROR_PERMANENT_REDIRECT: &str = "PermanentRedirect";
+pub const ERROR_INTERNAL_ERROR: &str = "InternalError";
+pub const ERROR_SERVICE_UNAVAILABLE: &str = "ServiceUnavailable";
+pub const ERROR_SLOW_DOWN: &str = "SlowDown";
+pub const ERROR_INVALID_TOKEN: &str = "InvalidToken";
+pub const ERROR_EXPIRED_TOKEN: &str = "ExpiredToken";
+pub const ERROR_REQUEST_TIME_TOO_SKEWED: &str = "RequestTimeTooSkewed";
+pub const ERROR_AUTHORIZATION_QUERY_PARAMETERS_ERROR: &str = "AuthorizationQueryParametersError";
+pub const ERROR_AUTHORIZATION_HEADER_MALFORMED: &str = "AuthorizationHeaderMalformed";
+pub const ERROR_SIGNATURE_DOES_NOT_MATCH: &str = "SignatureDoesNotMatch";
+/// Extension: the request ran past its `x-s3clone-deadline-ms` deadline.
+pub const ERROR_DEADLINE_EXCEEDED: &str = "DeadlineExceeded";
+/// Extension (as in Alibaba OSS): appending to an object not created by append.
+pub const ERROR_OBJECT_NOT_APPENDABLE: &str = "ObjectNotAppendable";
+/// Extension (as in Alibaba OSS): the append position is not the object size.
+pub const ERROR_POSITION_NOT_EQUAL_TO_LENGTH: &str = "PositionNotEqualToLength";
+pub const ERROR_KMS_NOT_FOUND: &str = "KMS.NotFoundException";
+pub const ERROR_KMS_DISABLED: &str = "KMS.DisabledException";
+
+// STS error code constants
+pub const ERROR_INVALID_ACTION: &str = "InvalidAction";
+pub const ERROR_MALFORMED_POLICY_DOCUMENT: &str = "MalformedPolicyDocument";
+pub const ERROR_PACKED_POLICY_TOO_LARGE: &str = "PackedPolicyTooLarge";
+pub const ERROR_VALIDATION_ERROR: &str = "ValidationError";
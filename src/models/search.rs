@@ -0,0 +1,102 @@
+use super::ObjectMetadata;
+
+/// Most predicates one search may combine.
+pub const MAX_SEARCH_PREDICATES: usize = 10;
+
+/// What a [`SearchPredicate`] compares.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchField {
+    Key,
+    ContentType,
+    StorageClass,
+    /// The value of an object tag.
+    Tag(String),
+    /// The value of user metadata (`x-amz-meta-*`, without the prefix).
+    Metadata(String),
+}
+
+/// One condition of `GET /{bucket}?search&query=...`: `field=value` for
+/// equality, `field^=value` for values starting with `value`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchPredicate {
+    pub field: SearchField,
+    pub value: String,
+    pub prefix: bool,
+}
+
+impl SearchPredicate {
+    /// Parses a search query: predicates separated by `;`, all of which an
+    /// object must match. Fields are `key`, `content-type`, `storage-class`,
+    /// `tag:<name>` and `meta:<name>`; metadata names are case-insensitive,
+    /// tag names are not.
+    pub fn parse_query(query: &str) -> Result<Vec<SearchPredicate>, String> {
+        let predicates = query
+            .split(';')
+            .filter(|part| !part.is_empty())
+            .map(SearchPredicate::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if predicates.is_empty() {
+            return Err("the search query has no predicates".to_string());
+        }
+        if predicates.len() > MAX_SEARCH_PREDICATES {
+            return Err(format!(
+                "a search query has at most {} predicates",
+                MAX_SEARCH_PREDICATES
+            ));
+        }
+        Ok(predicates)
+    }
+
+    fn parse(predicate: &str) -> Result<SearchPredicate, String> {
+        let Some((field, value)) = predicate.split_once('=') else {
+            return Err(format!("predicate {} has no = or ^=", predicate));
+        };
+        let (field, prefix) = match field.strip_suffix('^') {
+            Some(field) => (field, true),
+            None => (field, false),
+        };
+        let field = match field {
+            "key" => SearchField::Key,
+            "content-type" => SearchField::ContentType,
+            "storage-class" => SearchField::StorageClass,
+            _ => match field.split_once(':') {
+                Some(("tag", name)) if !name.is_empty() => SearchField::Tag(name.to_string()),
+                Some(("meta", name)) if !name.is_empty() => {
+                    SearchField::Metadata(name.to_ascii_lowercase())
+                }
+                _ => return Err(format!("unknown search field {}", field)),
+            },
+        };
+        Ok(SearchPredicate {
+            field,
+            value: value.to_string(),
+            prefix,
+        })
+    }
+
+    pub fn matches(&self, object: &ObjectMetadata) -> bool {
+        let attributes = &object.attributes;
+        let actual = match &self.field {
+            SearchField::Key => Some(object.key.as_str()),
+            // As served: objects stored without a type are binary.
+            SearchField::ContentType => Some(
+                attributes
+                    .content_type
+                    .as_deref()
+                    .unwrap_or("binary/octet-stream"),
+            ),
+            SearchField::StorageClass => Some(attributes.storage_class()),
+            SearchField::Tag(name) => attributes.tags.get(name).map(String::as_str),
+            SearchField::Metadata(name) => attributes.user_metadata.get(name).map(String::as_str),
+        };
+        match actual {
+            Some(actual) if self.prefix => actual.starts_with(&self.value),
+            Some(actual) => actual == self.value,
+            None => false,
+        }
+    }
+
+    pub fn is_tag(&self) -> bool {
+        matches!(self.field, SearchField::Tag(_))
+    }
+}
@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+/// Versioning state of a bucket, as set with `PUT /{bucket}?versioning`.
+/// Buckets that never had versioning enabled have none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VersioningStatus {
+    /// Every write creates a new version and deletes leave delete markers.
+    Enabled,
+    /// Writes replace the `null` version; older versions are kept.
+    Suspended,
+}
+
+/// The `VersioningConfiguration` document of `PUT /{bucket}?versioning`
+/// and its GET, which leaves out `Status` for buckets never versioned.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "VersioningConfiguration")]
+pub struct VersioningConfiguration {
+    #[serde(rename = "@xmlns", default, skip_serializing_if = "Option::is_none")]
+    pub xmlns: Option<String>,
+    #[serde(rename = "Status", default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<VersioningStatus>,
+    #[serde(rename = "MfaDelete", default, skip_serializing_if = "Option::is_none")]
+    pub mfa_delete: Option<String>,
+}
@@ -1,18 +1,202 @@
+use crate::admin;
+use crate::api;
+use crate::bandwidth::{Bandwidth, ConnectionBandwidth};
+use crate::clock::Clock;
 use crate::config::Config;
+use crate::jobs::JobsHandle;
+use crate::listener::ProxyListener;
+use crate::metrics::Metrics;
+use crate::mirror::Mirror;
+use crate::services::auth::AuthService;
+use crate::services::bucket::BucketService;
+use crate::services::bucket_deletion::BucketDeletionService;
+use crate::services::kms::KmsService;
+use crate::services::metrics_history::MetricsHistoryService;
+use crate::services::multipart::MultipartService;
+use crate::services::notification::NotificationService;
+use crate::services::object::ObjectService;
+use crate::services::service_account::ServiceAccountService;
+use crate::services::sts::StsService;
+use crate::services::system_log::SystemLogService;
+use crate::storage::Storage;
+use crate::systemd::{self, ActivatedSockets};
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::{Request, StatusCode};
+use axum::serve::Listener;
 use axum::{Router, routing::get};
-use log::info;
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper_util::rt::TokioIo;
+use log::{debug, info};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use tokio::sync::watch;
+use tower::ServiceExt;
 
-async fn healthz() -> &'static str {
-    "OK"
+/// Most headers a request may have. SDKs send a dozen signed `x-amz-*`
+/// headers before any user metadata, so hyper's default of 100 is easily
+/// reached and answered with a bare `431`.
+pub const MAX_REQUEST_HEADERS: usize = 500;
+/// Largest request head (request line plus headers) in bytes, for long
+/// presigned URLs and signed headers; also caps the connection's read buffer.
+pub const MAX_REQUEST_HEAD_SIZE: usize = 1024 * 1024;
+
+/// Shared state handed to every handler.
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<RwLock<Config>>,
+    pub config_path: String,
+    pub jobs: JobsHandle,
+    pub storage: Arc<Storage>,
+    pub auth: Arc<dyn AuthService>,
+    pub buckets: Arc<dyn BucketService>,
+    pub objects: Arc<dyn ObjectService>,
+    pub multipart: Arc<dyn MultipartService>,
+    pub kms: Arc<dyn KmsService>,
+    pub notifications: Arc<dyn NotificationService>,
+    pub service_accounts: Arc<dyn ServiceAccountService>,
+    pub sts: Arc<dyn StsService>,
+    pub started: Instant,
+    /// Set through the admin API before taking the instance out of rotation.
+    pub draining: Arc<AtomicBool>,
+    pub metrics: Arc<Metrics>,
+    pub metrics_history: Arc<dyn MetricsHistoryService>,
+    /// Download token buckets of access keys; those of connections are
+    /// request extensions.
+    pub bandwidth: Arc<Bandwidth>,
+    pub mirror: Arc<Mirror>,
+    pub system_logs: Arc<dyn SystemLogService>,
+    pub bucket_deletions: Arc<dyn BucketDeletionService>,
+    pub clock: Arc<dyn Clock>,
+}
+
+/// `GET /healthz` is a liveness check and always succeeds while the server
+/// runs. `GET /healthz?drain` is for load balancers: it fails with
+/// `503 DRAINING` once draining starts, while requests keep being served.
+async fn healthz(
+    State(state): State<AppState>,
+    Query(query): Query<HashMap<String, String>>,
+) -> (StatusCode, &'static str) {
+    if query.contains_key("drain") && state.draining.load(Ordering::Relaxed) {
+        (StatusCode::SERVICE_UNAVAILABLE, "DRAINING")
+    } else {
+        (StatusCode::OK, "OK")
+    }
 }
 
-pub async fn run(cfg: Config) {
-    let app = Router::new()
-    .route("/healthz", get(healthz));
-    let addr = format!("{}:{}", cfg.server.http.host, cfg.server.http.port);
+pub async fn run(state: AppState, shutdown: watch::Receiver<bool>) {
+    let (http, admin_cfg) = {
+        let cfg = state.config.read().unwrap();
+        (cfg.server.http.clone(), cfg.admin.clone())
+    };
+
+    let mut app = Router::new().route("/healthz", get(healthz));
 
-    info!("Starting HTTP server on http://{}", addr);
+    // Activated sockets replace the configured host and ports, so systemd
+    // can keep them open across restarts.
+    let mut sockets = ActivatedSockets::from_env();
+    let mut admin_server = None;
+    if let Some(admin_cfg) = admin_cfg.filter(|a| a.enabled) {
+        let admin_app = Router::new().nest("/admin/v1", admin::router(state.clone()));
+        match admin_cfg.port {
+            Some(port) => {
+                let listener = match sockets.take(systemd::ADMIN_SOCKET_NAME) {
+                    Some(socket) => {
+                        info!("Starting admin API on the socket passed by systemd");
+                        ProxyListener::from_std(socket, false).unwrap()
+                    }
+                    None => {
+                        let addr = format!("{}:{}", http.host, port);
+                        info!("Starting admin API on http://{}/admin/v1", addr);
+                        ProxyListener::bind(&addr, false).await.unwrap()
+                    }
+                };
+                let admin_app = admin_app.with_state(state.clone());
+                admin_server = Some(tokio::spawn(serve(listener, admin_app, shutdown.clone())));
+            }
+            None => {
+                info!("Serving admin API under /admin/v1");
+                app = app.merge(admin_app);
+            }
+        }
+    }
 
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
-}
\ No newline at end of file
+    let app = app.merge(api::router(state.clone())).with_state(state);
+    let listener = match sockets.take_s3() {
+        Some(socket) => {
+            info!("Starting HTTP server on the socket passed by systemd");
+            ProxyListener::from_std(socket, http.proxy_protocol).unwrap()
+        }
+        None => {
+            let addr = format!("{}:{}", http.host, http.port);
+            info!("Starting HTTP server on http://{}", addr);
+            ProxyListener::bind(&addr, http.proxy_protocol)
+                .await
+                .unwrap()
+        }
+    };
+    if http.proxy_protocol {
+        info!("Expecting PROXY protocol headers");
+    }
+
+    systemd::notify("READY=1");
+    serve(listener, app, shutdown).await;
+    if let Some(admin_server) = admin_server {
+        let _ = admin_server.await;
+    }
+    info!("HTTP server stopped");
+}
+
+/// Serves HTTP/1.1 connections until `shutdown` is set, then waits for
+/// in-flight requests to finish. Like `axum::serve`, but with the header
+/// limits raised (see [`MAX_REQUEST_HEADERS`]).
+async fn serve(mut listener: ProxyListener, app: Router, shutdown: watch::Receiver<bool>) {
+    let stopped = |mut shutdown: watch::Receiver<bool>| async move {
+        let _ = shutdown.wait_for(|stop| *stop).await;
+    };
+    // Every connection task holds a receiver; closed once all are done.
+    let (done_tx, done_rx) = watch::channel(());
+    loop {
+        let (io, peer) = tokio::select! {
+            conn = listener.accept() => conn,
+            _ = stopped(shutdown.clone()) => break,
+        };
+        let app = app.clone();
+        let bandwidth = ConnectionBandwidth::default();
+        let service = hyper::service::service_fn(move |req: Request<Incoming>| {
+            let mut req = req.map(Body::new);
+            req.extensions_mut().insert(ConnectInfo(peer));
+            req.extensions_mut().insert(bandwidth.clone());
+            app.clone().oneshot(req)
+        });
+        let shutdown = shutdown.clone();
+        let done_rx = done_rx.clone();
+        tokio::spawn(async move {
+            let mut builder = http1::Builder::new();
+            builder
+                .max_headers(MAX_REQUEST_HEADERS)
+                .max_buf_size(MAX_REQUEST_HEAD_SIZE);
+            let conn = builder
+                .serve_connection(TokioIo::new(io), service)
+                .with_upgrades();
+            tokio::pin!(conn);
+            let result = tokio::select! {
+                result = conn.as_mut() => result,
+                _ = stopped(shutdown) => {
+                    conn.as_mut().graceful_shutdown();
+                    conn.await
+                }
+            };
+            if let Err(e) = result {
+                debug!("connection from {} failed: {}", peer.0, e);
+            }
+            drop(done_rx);
+        });
+    }
+    drop(listener);
+    drop(done_rx);
+    done_tx.closed().await;
+}
@@ -1,24 +1,1334 @@
+use crate::aws_chunked;
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::crypto::{constant_time_eq, hmac_sha256};
+use crate::error::S3Error;
+use crate::models::{
+    AclPermission, AuthContext, BucketPolicy, Credentials, Permission, PolicyEffect, S3_ARN_PREFIX,
+};
+use crate::services::auth_provider::AuthProvider;
+use crate::sigv4::{
+    self, Authorization, CONTENT_SHA256_HEADER, ChunkSigner, Credential, MAX_CLOCK_SKEW_SECS,
+    MAX_EXPIRES_SECS, STREAMING_PAYLOAD, STREAMING_PAYLOAD_TRAILER,
+    STREAMING_UNSIGNED_PAYLOAD_TRAILER, SignedRequest, UNSIGNED_PAYLOAD,
+};
+use crate::storage::{Storage, StorageError};
 use anyhow::Result;
-use crate::models::{AuthContext, Credentials, Permission};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
 use http::HeaderMap;
+use log::debug;
+use percent_encoding::{NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use serde::Serialize;
 use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// S3 actions understood by the permission model. As in IAM, the
+/// version-specific actions are separate from their unversioned counterparts:
+/// `GetObject` does not grant reading old versions, `DeleteObject` does not
+/// allow permanently removing one and `ListBucket` does not list versions.
+pub const S3_ACTIONS: &[&str] = &[
+    "s3:ListAllMyBuckets",
+    "s3:CreateBucket",
+    "s3:DeleteBucket",
+    "s3:ListBucket",
+    "s3:ListBucketVersions",
+    "s3:GetBucketLocation",
+    "s3:GetBucketNotification",
+    "s3:PutBucketNotification",
+    "s3:GetBucketTagging",
+    "s3:PutBucketTagging",
+    "s3:GetBucketVersioning",
+    "s3:PutBucketVersioning",
+    "s3:GetLifecycleConfiguration",
+    "s3:PutLifecycleConfiguration",
+    "s3:GetBucketPolicy",
+    "s3:PutBucketPolicy",
+    "s3:DeleteBucketPolicy",
+    "s3:GetBucketAcl",
+    "s3:PutBucketAcl",
+    "s3:GetBucketCORS",
+    "s3:PutBucketCORS",
+    "s3:GetObject",
+    "s3:GetObjectVersion",
+    "s3:GetObjectAttributes",
+    "s3:GetObjectVersionAttributes",
+    "s3:PutObject",
+    "s3:DeleteObject",
+    "s3:DeleteObjectVersion",
+    "s3:GetObjectTagging",
+    "s3:GetObjectVersionTagging",
+    "s3:PutObjectTagging",
+    "s3:PutObjectVersionTagging",
+    "s3:DeleteObjectTagging",
+    "s3:DeleteObjectVersionTagging",
+    "s3:GetObjectAcl",
+    "s3:GetObjectVersionAcl",
+    "s3:PutObjectAcl",
+    "s3:PutObjectVersionAcl",
+    "s3:ListBucketMultipartUploads",
+    "s3:ListMultipartUploadParts",
+    "s3:AbortMultipartUpload",
+];
+
+/// Actions allowed by the ACL grants of a bucket, on the bucket and its
+/// objects: `READ` lists it, `WRITE` creates, overwrites and deletes its
+/// objects. `FULL_CONTROL` allows the actions of every other permission.
+const BUCKET_ACL_ACTIONS: &[(AclPermission, &[&str])] = &[
+    (
+        AclPermission::Read,
+        &[
+            "s3:ListBucket",
+            "s3:ListBucketVersions",
+            "s3:ListBucketMultipartUploads",
+        ],
+    ),
+    (
+        AclPermission::Write,
+        &[
+            "s3:PutObject",
+            "s3:DeleteObject",
+            "s3:DeleteObjectVersion",
+            "s3:AbortMultipartUpload",
+        ],
+    ),
+    (AclPermission::ReadAcp, &["s3:GetBucketAcl"]),
+    (AclPermission::WriteAcp, &["s3:PutBucketAcl"]),
+];
+
+/// Actions allowed by the ACL grants of an object, on the object. `WRITE`
+/// allows nothing, as in S3.
+const OBJECT_ACL_ACTIONS: &[(AclPermission, &[&str])] = &[
+    (
+        AclPermission::Read,
+        &[
+            "s3:GetObject",
+            "s3:GetObjectVersion",
+            "s3:GetObjectAttributes",
+            "s3:GetObjectVersionAttributes",
+        ],
+    ),
+    (
+        AclPermission::ReadAcp,
+        &["s3:GetObjectAcl", "s3:GetObjectVersionAcl"],
+    ),
+    (
+        AclPermission::WriteAcp,
+        &["s3:PutObjectAcl", "s3:PutObjectVersionAcl"],
+    ),
+];
+
+/// STS actions. Their resource is a role name from the `roles` config section.
+pub const STS_ACTIONS: &[&str] = &["sts:AssumeRole"];
+
+/// Signed cookies, set by a web app so browsers can read every object under
+/// a prefix without presigned URLs: the access key that signed them...
+pub const COOKIE_KEY_ID: &str = "s3clone-key-id";
+/// ...the `<bucket>/<key pattern>` readable with them (percent-encoded, `*`
+/// and `?` as in permissions)...
+pub const COOKIE_RESOURCE: &str = "s3clone-resource";
+/// ...the Unix time they expire at...
+pub const COOKIE_EXPIRES: &str = "s3clone-expires";
+/// ...and the [`cookie_signature`] over the two.
+pub const COOKIE_SIGNATURE: &str = "s3clone-signature";
+
+/// Longest validity of signed cookies, as of presigned URLs.
+pub const MAX_COOKIE_LIFETIME_SECS: u64 = MAX_EXPIRES_SECS;
+
+/// Hex HMAC-SHA256 of `S3CLONE-COOKIE\n<resource>\n<expires>` under the
+/// secret key, with `resource` decoded.
+pub fn cookie_signature(secret_key: &str, resource: &str, expires: i64) -> String {
+    let message = format!("S3CLONE-COOKIE\n{}\n{}", resource, expires);
+    hex::encode(hmac_sha256(secret_key.as_bytes(), message.as_bytes()))
+}
+
+/// The four signed cookies, named and with their values, granting the
+/// holder of `access_key` reads of `resource` (decoded) for `lifetime_secs`
+/// from `now`; at most [`MAX_COOKIE_LIFETIME_SECS`].
+pub fn issue_cookies(
+    access_key: &str,
+    secret_key: &str,
+    resource: &str,
+    now: DateTime<Utc>,
+    lifetime_secs: u64,
+) -> Result<[(&'static str, String); 4], S3Error> {
+    if lifetime_secs > MAX_COOKIE_LIFETIME_SECS {
+        return Err(S3Error::invalid_argument(format!(
+            "Signed cookies can be valid for at most {} seconds",
+            MAX_COOKIE_LIFETIME_SECS
+        )));
+    }
+    let expires = now.timestamp() + lifetime_secs as i64;
+    Ok([
+        (COOKIE_KEY_ID, access_key.to_string()),
+        (
+            COOKIE_RESOURCE,
+            utf8_percent_encode(resource, NON_ALPHANUMERIC).to_string(),
+        ),
+        (COOKIE_EXPIRES, expires.to_string()),
+        (
+            COOKIE_SIGNATURE,
+            cookie_signature(secret_key, resource, expires),
+        ),
+    ])
+}
+
+/// The caller of a request, and how to verify the rest of its body.
+pub struct Authentication {
+    pub context: AuthContext,
+    /// Set for an `aws-chunked` body (a `STREAMING-*` payload), its signer
+    /// seeded with the verified request signature.
+    pub chunked: Option<aws_chunked::Payload>,
+    /// The region of the signature's credential scope; `None` for anonymous
+    /// requests and signed cookies.
+    pub region: Option<String>,
+}
+
+/// The signing fields of a browser form upload (`POST /{bucket}`).
+pub struct FormSignature<'a> {
+    /// `x-amz-algorithm`
+    pub algorithm: &'a str,
+    /// `x-amz-credential`
+    pub credential: &'a str,
+    /// `x-amz-date`
+    pub date: &'a str,
+    /// `x-amz-signature`
+    pub signature: &'a str,
+    /// The base64 `policy` field, which is what is signed.
+    pub policy: &'a str,
+    /// `x-amz-security-token`
+    pub security_token: Option<&'a str>,
+}
+
+/// What decided whether an action is allowed, in the order they are
+/// consulted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decider {
+    /// A statement of the bucket policy naming the caller; a deny wins over
+    /// everything, an allow over the lack of permissions.
+    BucketPolicy,
+    /// The caller's permissions, narrowed by its session policy if any.
+    Permissions,
+    /// A grant of the bucket's or object's ACL.
+    Acl,
+    /// Nothing allows the action.
+    ImplicitDeny,
+}
+
+/// A bucket policy statement that applies to a request.
+#[derive(Debug, Clone, Serialize)]
+pub struct StatementMatch {
+    /// Position in `Statement`, from 0.
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    pub effect: PolicyEffect,
+}
+
+/// How [`AuthService::authorize`] decides on a request, as reported by
+/// [`AuthService::simulate`].
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthDecision {
+    pub allowed: bool,
+    pub decided_by: Decider,
+    /// For [`Decider::BucketPolicy`]: the deciding statement, the first
+    /// deny or else the first allow.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statement: Option<StatementMatch>,
+    /// For [`Decider::Permissions`]: the first permission granting the
+    /// action.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permission: Option<Permission>,
+}
 
 #[async_trait::async_trait]
 pub trait AuthService: Send + Sync {
-    async fn authenticate(&self, headers: &HeaderMap, query: &HashMap<String, String>) -> Result<AuthContext>;
+    /// Identifies the caller of a SigV4-signed request (header or presigned
+    /// form), verifying its signature; unsigned requests are anonymous.
+    async fn authenticate(
+        &self,
+        req: &SignedRequest<'_>,
+        query: &HashMap<String, String>,
+    ) -> Result<Authentication>;
+    /// Identifies the caller of a form upload, verifying the signature of
+    /// its policy. The policy's expiration and conditions are left to the
+    /// caller.
+    async fn authenticate_form(&self, form: &FormSignature<'_>) -> Result<AuthContext>;
     async fn authorize(&self, ctx: &AuthContext, action: &str, resource: &str) -> Result<()>;
+    /// Like [`authorize`](Self::authorize) for a listing of `bucket` with
+    /// `prefix`, which permissions with an `s3:prefix` condition need.
+    async fn authorize_listing(
+        &self,
+        ctx: &AuthContext,
+        action: &str,
+        bucket: &str,
+        prefix: &str,
+    ) -> Result<()>;
+    /// How [`authorize`](Self::authorize) decides on `action` on `resource`
+    /// for the caller with `access_key` (`None` for anonymous callers),
+    /// with `policy` in place of the bucket's policy if given, and `prefix`
+    /// as the listing prefix. Unknown access keys fail with
+    /// `InvalidAccessKeyId`.
+    async fn simulate(
+        &self,
+        access_key: Option<&str>,
+        action: &str,
+        resource: &str,
+        prefix: Option<&str>,
+        policy: Option<&BucketPolicy>,
+    ) -> Result<AuthDecision>;
+}
+
+/// Authenticates against a chain of [`AuthProvider`]s, by default the
+/// `credentials` section of the live config, the service accounts minted
+/// through the admin API and the temporary credentials issued by STS (see
+/// [`crate::services::auth_provider::providers`]). Authorizes with the
+/// caller's permissions and the bucket policies and ACLs kept in
+/// [`Storage`].
+pub struct AuthServiceImpl {
+    providers: Vec<Arc<dyn AuthProvider>>,
+    storage: Arc<Storage>,
+    config: Arc<RwLock<Config>>,
+    clock: Arc<dyn Clock>,
 }
 
-pub struct AuthServiceImpl;
+impl AuthServiceImpl {
+    pub fn new(
+        providers: Vec<Arc<dyn AuthProvider>>,
+        storage: Arc<Storage>,
+        config: Arc<RwLock<Config>>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            providers,
+            storage,
+            config,
+            clock,
+        }
+    }
+
+    /// With `region.validate`, the region signatures should be scoped to
+    /// when `credential` names one that is not accepted.
+    fn wrong_region(&self, credential: &Credential) -> Option<String> {
+        let config = self.config.read().unwrap();
+        (!config.region.accepts(&credential.region)).then(|| config.region.default.clone())
+    }
+
+    /// What the bucket policy of the bucket in `resource` (or `policy`,
+    /// if given) says about the caller with `access_key` (`None` when
+    /// anonymous) performing `action`: the first statement denying it, else
+    /// the first allowing it. `None` for buckets without a policy, missing
+    /// buckets and actions on no bucket.
+    fn policy_statement(
+        storage: &Storage,
+        policy: Option<&BucketPolicy>,
+        access_key: Option<&str>,
+        action: &str,
+        resource: &str,
+    ) -> Result<Option<StatementMatch>> {
+        if !action.starts_with("s3:") || resource == "*" {
+            return Ok(None);
+        }
+        let (bucket, key) = match resource.split_once('/') {
+            Some((bucket, key)) => (bucket, Some(key)),
+            None => (resource, None),
+        };
+        let bucket = storage.resolve_bucket(bucket)?;
+        let stored;
+        let policy = match policy {
+            Some(policy) => policy,
+            None => {
+                stored = match storage.bucket_metadata(&bucket) {
+                    Ok(metadata) => metadata.policy,
+                    Err(StorageError::NoSuchBucket(_)) => None,
+                    Err(e) => return Err(e.into()),
+                };
+                let Some(policy) = &stored else {
+                    return Ok(None);
+                };
+                policy
+            }
+        };
+        let arn = match key {
+            Some(key) => format!("{}{}/{}", S3_ARN_PREFIX, bucket, key),
+            None => format!("{}{}", S3_ARN_PREFIX, bucket),
+        };
+        let mut allow = None;
+        for (index, statement) in policy.statements().iter().enumerate() {
+            let applies = statement.names(access_key)
+                && statement
+                    .actions()
+                    .iter()
+                    .any(|pattern| action_matches(pattern, action))
+                && statement
+                    .resources()
+                    .iter()
+                    .any(|pattern| wildcard_match(pattern, &arn));
+            if !applies {
+                continue;
+            }
+            let found = StatementMatch {
+                index,
+                sid: statement.sid.clone(),
+                effect: statement.effect,
+            };
+            if statement.effect == PolicyEffect::Deny {
+                return Ok(Some(found));
+            }
+            allow.get_or_insert(found);
+        }
+        Ok(allow)
+    }
+
+    /// Fails with AccessDenied unless the caller may perform `action` on
+    /// `resource`, in a listing with `prefix` if given.
+    async fn check(
+        &self,
+        ctx: &AuthContext,
+        action: &str,
+        resource: &str,
+        prefix: Option<&str>,
+    ) -> Result<()> {
+        let credentials = match ctx {
+            AuthContext::Anonymous => None,
+            AuthContext::IAMAccount(credentials) => Some(credentials),
+        };
+        if self
+            .decide(credentials, action, resource, prefix, None)
+            .await?
+            .allowed
+        {
+            Ok(())
+        } else {
+            debug!("denied {} on {}", action, resource);
+            Err(S3Error::access_denied().into())
+        }
+    }
+
+    /// How a request of the caller with `credentials` (`None` when
+    /// anonymous) for `action` on `resource` is decided. As in IAM, an
+    /// explicit deny wins and either policy may allow, as may an ACL grant.
+    async fn decide(
+        &self,
+        credentials: Option<&Credentials>,
+        action: &str,
+        resource: &str,
+        prefix: Option<&str>,
+        policy: Option<&BucketPolicy>,
+    ) -> Result<AuthDecision> {
+        let credentials = credentials.cloned();
+        let (action, resource) = (action.to_string(), resource.to_string());
+        let (prefix, policy) = (prefix.map(str::to_string), policy.cloned());
+        self.storage
+            .blocking(move |storage| {
+                Self::decide_with(
+                    storage,
+                    credentials.as_ref(),
+                    &action,
+                    &resource,
+                    prefix.as_deref(),
+                    policy.as_ref(),
+                )
+            })
+            .await
+    }
+
+    /// [`Self::decide`], looking up policies and ACLs in `storage`.
+    fn decide_with(
+        storage: &Storage,
+        credentials: Option<&Credentials>,
+        action: &str,
+        resource: &str,
+        prefix: Option<&str>,
+        policy: Option<&BucketPolicy>,
+    ) -> Result<AuthDecision> {
+        // Requests that are no listing have no `s3:prefix` to meet a
+        // condition on it.
+        let within = |required: &str| prefix.is_some_and(|prefix| prefix.starts_with(required));
+        let granting = |permissions: &[Permission]| {
+            permissions
+                .iter()
+                .find(|p| {
+                    action_matches(&p.action, action)
+                        && wildcard_match(&p.resource, resource)
+                        && p.prefix.as_deref().is_none_or(within)
+                })
+                .cloned()
+        };
+        // A session policy can only narrow what the role allows.
+        let permission = credentials.and_then(|credentials| {
+            let permission = granting(&credentials.permissions)?;
+            match &credentials.session_policy {
+                Some(session_policy) => granting(session_policy).and(Some(permission)),
+                None => Some(permission),
+            }
+        });
+        let access_key = credentials.map(|credentials| credentials.access_key.as_str());
+        let decision = |allowed, decided_by| AuthDecision {
+            allowed,
+            decided_by,
+            statement: None,
+            permission: None,
+        };
+        Ok(
+            match Self::policy_statement(storage, policy, access_key, action, resource)? {
+                Some(statement) => AuthDecision {
+                    statement: Some(statement.clone()),
+                    ..decision(
+                        statement.effect == PolicyEffect::Allow,
+                        Decider::BucketPolicy,
+                    )
+                },
+                None if permission.is_some() => AuthDecision {
+                    permission,
+                    ..decision(true, Decider::Permissions)
+                },
+                None if Self::acl_allows(storage, access_key, action, resource)? => {
+                    decision(true, Decider::Acl)
+                }
+                None => decision(false, Decider::ImplicitDeny),
+            },
+        )
+    }
+
+    /// Whether an ACL grant to the caller with `access_key` (`None` when
+    /// anonymous) allows `action` on `resource`: one of the bucket's for
+    /// listings and object writes, one of the object's current version for
+    /// reads of the object or its ACL. Missing buckets and objects grant
+    /// nothing.
+    fn acl_allows(
+        storage: &Storage,
+        access_key: Option<&str>,
+        action: &str,
+        resource: &str,
+    ) -> Result<bool> {
+        let (bucket, key) = match resource.split_once('/') {
+            Some((bucket, key)) => (bucket, Some(key)),
+            None => (resource, None),
+        };
+        let on = |table: &[(AclPermission, &[&str])]| {
+            table.iter().any(|(_, actions)| actions.contains(&action))
+        };
+        let bucket = storage.resolve_bucket(bucket)?;
+        let (acl, table) = match key {
+            Some(key) if on(OBJECT_ACL_ACTIONS) => match storage.head_object(&bucket, key) {
+                Ok(metadata) => (metadata.attributes.acl, OBJECT_ACL_ACTIONS),
+                Err(StorageError::NoSuchBucket(_) | StorageError::NoSuchKey(_)) => {
+                    return Ok(false);
+                }
+                Err(e) => return Err(e.into()),
+            },
+            _ if on(BUCKET_ACL_ACTIONS) => match storage.bucket_metadata(&bucket) {
+                Ok(metadata) => (metadata.acl, BUCKET_ACL_ACTIONS),
+                Err(StorageError::NoSuchBucket(_)) => return Ok(false),
+                Err(e) => return Err(e.into()),
+            },
+            _ => return Ok(false),
+        };
+        Ok(acl.iter().any(|grant| {
+            grant.grantee.includes(access_key)
+                && table.iter().any(|(permission, actions)| {
+                    (grant.permission == *permission
+                        || grant.permission == AclPermission::FullControl)
+                        && actions.contains(&action)
+                })
+        }))
+    }
+
+    /// The date of a header-signed request, which must be within 15 minutes
+    /// of the server's clock.
+    fn request_time(&self, headers: &HeaderMap) -> Result<DateTime<Utc>, S3Error> {
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+        let (raw, date) = if let Some(raw) = header("x-amz-date") {
+            (raw, sigv4::parse_amz_date(raw))
+        } else if let Some(raw) = header("date") {
+            let date = DateTime::parse_from_rfc2822(raw).ok().map(|t| t.to_utc());
+            (raw, date)
+        } else {
+            ("", None)
+        };
+        let date = date.ok_or_else(|| {
+            S3Error::access_denied_because(
+                "AWS authentication requires a valid Date or x-amz-date header",
+            )
+        })?;
+        if (self.clock.now() - date).num_seconds().abs() > MAX_CLOCK_SKEW_SECS {
+            return Err(S3Error::request_time_too_skewed(raw));
+        }
+        Ok(date)
+    }
+
+    /// The signature of a request with an `Authorization` header.
+    fn header_signature(
+        &self,
+        header: &http::HeaderValue,
+        req: &SignedRequest<'_>,
+    ) -> Result<Signature, S3Error> {
+        let header = header
+            .to_str()
+            .map_err(|_| S3Error::invalid_argument("Invalid Authorization header"))?;
+        let authorization = Authorization::parse(header)
+            .ok_or_else(|| S3Error::invalid_argument("Unsupported Authorization header format"))?
+            .map_err(S3Error::authorization_header_malformed)?;
+        let service = signing_service(req);
+        if authorization.credential.service != service {
+            return Err(S3Error::authorization_header_malformed(format!(
+                "Credential should be scoped to correct service: '{}'.",
+                service
+            )));
+        }
+        if let Some(expected) = self.wrong_region(&authorization.credential) {
+            return Err(S3Error::authorization_header_malformed(format!(
+                "the region '{}' is wrong; expecting '{}'",
+                authorization.credential.region, expected
+            ))
+            .with_resource("Region", expected));
+        }
+        let time = self.request_time(req.headers)?;
+        if authorization.credential.date != time.format("%Y%m%d").to_string() {
+            return Err(S3Error::authorization_header_malformed(
+                "Invalid credential date. Date is not the same as X-Amz-Date.",
+            ));
+        }
+        let payload_hash = req
+            .headers
+            .get(CONTENT_SHA256_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+            .or_else(|| req.body_sha256.clone())
+            .ok_or_else(|| {
+                S3Error::invalid_request(format!(
+                    "Missing required header for this request: {}",
+                    CONTENT_SHA256_HEADER
+                ))
+            })?;
+        let supported = [
+            STREAMING_PAYLOAD,
+            STREAMING_PAYLOAD_TRAILER,
+            STREAMING_UNSIGNED_PAYLOAD_TRAILER,
+        ];
+        if payload_hash.starts_with("STREAMING-") && !supported.contains(&payload_hash.as_str()) {
+            return Err(S3Error::not_implemented(format!(
+                "{} payloads are not supported",
+                payload_hash
+            )));
+        }
+        Ok(Signature {
+            credential: authorization.credential,
+            signed_headers: authorization.signed_headers,
+            signature: authorization.signature,
+            time,
+            payload_hash,
+        })
+    }
+
+    /// The signature of a presigned URL, whose payload is never signed.
+    fn query_signature(
+        &self,
+        req: &SignedRequest<'_>,
+        query: &HashMap<String, String>,
+    ) -> Result<Signature, S3Error> {
+        let param = |name: &str| {
+            query.get(name).ok_or_else(|| {
+                S3Error::authorization_query_parameters_error(
+                    "Query-string authentication version 4 requires the X-Amz-Algorithm, \
+                     X-Amz-Credential, X-Amz-Signature, X-Amz-Date, X-Amz-SignedHeaders, \
+                     and X-Amz-Expires parameters.",
+                )
+            })
+        };
+        if param("X-Amz-Algorithm")? != sigv4::ALGORITHM {
+            return Err(S3Error::authorization_query_parameters_error(format!(
+                "X-Amz-Algorithm only supports \"{}\"",
+                sigv4::ALGORITHM
+            )));
+        }
+        let credential = Credential::parse(param("X-Amz-Credential")?).ok_or_else(|| {
+            S3Error::authorization_query_parameters_error(
+                "Error parsing the X-Amz-Credential parameter; the Credential is mal-formed; \
+                 expecting \"<YOUR-AKID>/YYYYMMDD/REGION/SERVICE/aws4_request\".",
+            )
+        })?;
+        let service = signing_service(req);
+        if credential.service != service {
+            return Err(S3Error::authorization_query_parameters_error(format!(
+                "Error parsing the X-Amz-Credential parameter; incorrect service \"{}\". \
+                 This endpoint belongs to \"{}\".",
+                credential.service, service
+            )));
+        }
+        if let Some(expected) = self.wrong_region(&credential) {
+            return Err(S3Error::authorization_query_parameters_error(format!(
+                "Error parsing the X-Amz-Credential parameter; the region '{}' is wrong; \
+                 expecting '{}'",
+                credential.region, expected
+            ))
+            .with_resource("Region", expected));
+        }
+        let signed_headers: Vec<String> = param("X-Amz-SignedHeaders")?
+            .split(';')
+            .map(str::to_ascii_lowercase)
+            .collect();
+        if !sigv4::signs_host(&signed_headers) {
+            return Err(S3Error::authorization_query_parameters_error(
+                "X-Amz-SignedHeaders must include host",
+            ));
+        }
+        let signature = param("X-Amz-Signature")?.clone();
+        let time = self.check_presign_expiry(query)?;
+        if credential.date != time.format("%Y%m%d").to_string() {
+            return Err(S3Error::authorization_query_parameters_error(
+                "Invalid credential date. Date is not the same as X-Amz-Date.",
+            ));
+        }
+        Ok(Signature {
+            credential,
+            signed_headers,
+            signature,
+            time,
+            payload_hash: UNSIGNED_PAYLOAD.to_string(),
+        })
+    }
+
+    /// Enforces `X-Amz-Date` + `X-Amz-Expires` of a presigned URL and
+    /// returns the former.
+    fn check_presign_expiry(
+        &self,
+        query: &HashMap<String, String>,
+    ) -> Result<DateTime<Utc>, S3Error> {
+        let date = query
+            .get("X-Amz-Date")
+            .and_then(|date| sigv4::parse_amz_date(date))
+            .ok_or_else(|| {
+                S3Error::authorization_query_parameters_error(
+                    "X-Amz-Date must be in the ISO8601 Long Format \"yyyyMMdd'T'HHmmss'Z'\"",
+                )
+            })?;
+        let expires_secs = query
+            .get("X-Amz-Expires")
+            .and_then(|expires| expires.parse::<u64>().ok())
+            .ok_or_else(|| {
+                S3Error::authorization_query_parameters_error("X-Amz-Expires should be a number")
+            })?;
+        if expires_secs > MAX_EXPIRES_SECS {
+            return Err(S3Error::authorization_query_parameters_error(format!(
+                "X-Amz-Expires must be less than a week (in seconds) that is; the maximum \
+                 expires is {} seconds",
+                MAX_EXPIRES_SECS
+            )));
+        }
+        let now = self.clock.now();
+        if date - now > Duration::seconds(MAX_CLOCK_SKEW_SECS) {
+            return Err(S3Error::access_denied_because("Request is not valid yet"));
+        }
+        let expires = date + Duration::seconds(expires_secs as i64);
+        if now > expires {
+            return Err(
+                S3Error::access_denied_because("Request has expired").with_resource(
+                    "Expires",
+                    expires.to_rfc3339_opts(SecondsFormat::Secs, true),
+                ),
+            );
+        }
+        Ok(date)
+    }
+
+    /// The credentials of a request carrying signed cookies, whose session
+    /// policy allows only `s3:GetObject` on the cookie's resource, so they
+    /// grant no more than the signing key can read there.
+    async fn cookie_credentials(&self, cookies: &HashMap<&str, String>) -> Result<Credentials> {
+        let (Some(access_key), Some(resource), Some(expires), Some(signature)) = (
+            cookies.get(COOKIE_KEY_ID),
+            cookies.get(COOKIE_RESOURCE),
+            cookies.get(COOKIE_EXPIRES),
+            cookies.get(COOKIE_SIGNATURE),
+        ) else {
+            return Err(S3Error::access_denied_because(format!(
+                "Signed cookies require {}, {}, {} and {}",
+                COOKIE_KEY_ID, COOKIE_RESOURCE, COOKIE_EXPIRES, COOKIE_SIGNATURE
+            ))
+            .into());
+        };
+        let expires = expires.parse::<i64>().map_err(|_| {
+            S3Error::access_denied_because(format!("{} must be a Unix time", COOKIE_EXPIRES))
+        })?;
+        let now = self.clock.now().timestamp();
+        if now > expires {
+            let expired = DateTime::from_timestamp(expires, 0).unwrap_or_default();
+            return Err(
+                S3Error::access_denied_because("Signed cookies have expired")
+                    .with_resource(
+                        "Expires",
+                        expired.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    )
+                    .into(),
+            );
+        }
+        // Cookies carry no signing time: ones that would still be valid
+        // longer than they could have been issued for are refused.
+        if expires - now > MAX_COOKIE_LIFETIME_SECS as i64 + MAX_CLOCK_SKEW_SECS {
+            return Err(S3Error::access_denied_because(format!(
+                "Signed cookies can be valid for at most {} seconds",
+                MAX_COOKIE_LIFETIME_SECS
+            ))
+            .into());
+        }
+        let mut credentials = self
+            .find_credentials(access_key)
+            .await?
+            .ok_or_else(S3Error::invalid_access_key_id)?;
+        if credentials.session_token.is_some() {
+            return Err(S3Error::access_denied_because(
+                "Signed cookies cannot be signed with temporary credentials",
+            )
+            .into());
+        }
+        let resource = percent_decode_str(resource).decode_utf8_lossy();
+        let expected = cookie_signature(&credentials.secret_key, &resource, expires);
+        if !constant_time_eq(expected.as_bytes(), signature.as_bytes()) {
+            return Err(S3Error::access_denied_because("The signed cookies are not valid").into());
+        }
+        credentials.session_policy = Some(vec![Permission {
+            action: "s3:GetObject".to_string(),
+            resource: resource.into_owned(),
+            prefix: None,
+        }]);
+        Ok(credentials)
+    }
+
+    /// The credentials of `access_key` from the first provider that knows it.
+    async fn find_credentials(&self, access_key: &str) -> Result<Option<Credentials>> {
+        for provider in &self.providers {
+            if let Some(credentials) = provider.find(access_key).await? {
+                debug!(
+                    "access key {} found by the {} provider",
+                    access_key,
+                    provider.name()
+                );
+                return Ok(Some(credentials));
+            }
+        }
+        Ok(None)
+    }
+}
 
 #[async_trait::async_trait]
 impl AuthService for AuthServiceImpl {
-    async fn authenticate(&self, _headers: &HeaderMap, _query: &HashMap<String, String>) -> Result<AuthContext> {
-        // TODO: Implement authentication logic
-        unimplemented!()
+    async fn authenticate(
+        &self,
+        req: &SignedRequest<'_>,
+        query: &HashMap<String, String>,
+    ) -> Result<Authentication> {
+        let signature = if let Some(header) = req.headers.get(http::header::AUTHORIZATION) {
+            self.header_signature(header, req)?
+        } else if query.contains_key("X-Amz-Credential") {
+            self.query_signature(req, query)?
+        } else if let Some(cookies) = signed_cookies(req.headers) {
+            let credentials = self.cookie_credentials(&cookies).await?;
+            debug!(
+                "request carries cookies signed by {}",
+                credentials.access_key
+            );
+            return Ok(Authentication {
+                context: AuthContext::IAMAccount(credentials),
+                chunked: None,
+                region: None,
+            });
+        } else {
+            return Ok(Authentication {
+                context: AuthContext::Anonymous,
+                chunked: None,
+                region: None,
+            });
+        };
+        let credential = &signature.credential;
+        debug!("request signed with access key {}", credential.access_key);
+        let credentials = self
+            .find_credentials(&credential.access_key)
+            .await?
+            .ok_or_else(S3Error::invalid_access_key_id)?;
+        let canonical_request =
+            req.canonical_request(&signature.signed_headers, &signature.payload_hash);
+        let string_to_sign = sigv4::string_to_sign(
+            &canonical_request,
+            &signature.time,
+            &credential.region,
+            &credential.service,
+        );
+        let expected = sigv4::signature(
+            &string_to_sign,
+            &credentials.secret_key,
+            &signature.time,
+            &credential.region,
+            &credential.service,
+        );
+        if !constant_time_eq(expected.as_bytes(), signature.signature.as_bytes()) {
+            debug!(
+                "signature mismatch; canonical request:\n{}",
+                canonical_request
+            );
+            return Err(S3Error::signature_does_not_match(&string_to_sign).into());
+        }
+        let headers = req.headers;
+        if let Some(expected) = &credentials.session_token {
+            let presented = headers
+                .get("x-amz-security-token")
+                .and_then(|v| v.to_str().ok())
+                .or_else(|| query.get("X-Amz-Security-Token").map(String::as_str));
+            if !token_matches(expected, presented) {
+                return Err(S3Error::invalid_token().into());
+            }
+        }
+        let signer = || {
+            ChunkSigner::new(
+                &credentials.secret_key,
+                signature.time,
+                &credential.region,
+                &credential.service,
+                &signature.signature,
+            )
+        };
+        let chunked = match signature.payload_hash.as_str() {
+            STREAMING_PAYLOAD | STREAMING_PAYLOAD_TRAILER => Some(aws_chunked::Payload {
+                signer: Some(signer()),
+                trailer: signature.payload_hash == STREAMING_PAYLOAD_TRAILER,
+            }),
+            STREAMING_UNSIGNED_PAYLOAD_TRAILER => Some(aws_chunked::Payload {
+                signer: None,
+                trailer: true,
+            }),
+            _ => None,
+        };
+        Ok(Authentication {
+            context: AuthContext::IAMAccount(credentials),
+            chunked,
+            region: Some(credential.region.clone()),
+        })
     }
-    async fn authorize(&self, _ctx: &AuthContext, _action: &str, _resource: &str) -> Result<()> {
-        // TODO: Implement authorization logic
-        unimplemented!()
+
+    async fn authenticate_form(&self, form: &FormSignature<'_>) -> Result<AuthContext> {
+        if form.algorithm != sigv4::ALGORITHM {
+            return Err(S3Error::invalid_argument(format!(
+                "x-amz-algorithm only supports \"{}\"",
+                sigv4::ALGORITHM
+            ))
+            .with_resource("ArgumentName", "x-amz-algorithm")
+            .into());
+        }
+        let credential = Credential::parse(form.credential).ok_or_else(|| {
+            S3Error::invalid_argument(
+                "Error parsing the x-amz-credential field; the Credential is mal-formed; \
+                 expecting \"<YOUR-AKID>/YYYYMMDD/REGION/SERVICE/aws4_request\".",
+            )
+            .with_resource("ArgumentName", "x-amz-credential")
+        })?;
+        if credential.service != sigv4::SERVICE {
+            return Err(S3Error::invalid_argument(format!(
+                "Error parsing the x-amz-credential field; incorrect service \"{}\". \
+                 This endpoint belongs to \"{}\".",
+                credential.service,
+                sigv4::SERVICE
+            ))
+            .with_resource("ArgumentName", "x-amz-credential")
+            .into());
+        }
+        if let Some(expected) = self.wrong_region(&credential) {
+            return Err(S3Error::invalid_argument(format!(
+                "Error parsing the x-amz-credential field; the region '{}' is wrong; \
+                 expecting '{}'",
+                credential.region, expected
+            ))
+            .with_resource("ArgumentName", "x-amz-credential")
+            .into());
+        }
+        let time = sigv4::parse_amz_date(form.date)
+            .filter(|time| credential.date == time.format("%Y%m%d").to_string())
+            .ok_or_else(|| {
+                S3Error::invalid_argument(
+                    "x-amz-date must be in the ISO8601 Long Format \"yyyyMMdd'T'HHmmss'Z'\" \
+                     and on the date of the credential",
+                )
+                .with_resource("ArgumentName", "x-amz-date")
+            })?;
+        debug!(
+            "form upload signed with access key {}",
+            credential.access_key
+        );
+        let credentials = self
+            .find_credentials(&credential.access_key)
+            .await?
+            .ok_or_else(S3Error::invalid_access_key_id)?;
+        let key = sigv4::signing_key(
+            &credentials.secret_key,
+            &time,
+            &credential.region,
+            &credential.service,
+        );
+        let expected = hex::encode(hmac_sha256(&key, form.policy.as_bytes()));
+        if !constant_time_eq(expected.as_bytes(), form.signature.as_bytes()) {
+            return Err(S3Error::signature_does_not_match(form.policy).into());
+        }
+        if let Some(expected) = &credentials.session_token
+            && !token_matches(expected, form.security_token)
+        {
+            return Err(S3Error::invalid_token().into());
+        }
+        Ok(AuthContext::IAMAccount(credentials))
+    }
+
+    async fn authorize(&self, ctx: &AuthContext, action: &str, resource: &str) -> Result<()> {
+        self.check(ctx, action, resource, None).await
     }
-} 
\ No newline at end of file
+
+    async fn authorize_listing(
+        &self,
+        ctx: &AuthContext,
+        action: &str,
+        bucket: &str,
+        prefix: &str,
+    ) -> Result<()> {
+        self.check(ctx, action, bucket, Some(prefix)).await
+    }
+
+    async fn simulate(
+        &self,
+        access_key: Option<&str>,
+        action: &str,
+        resource: &str,
+        prefix: Option<&str>,
+        policy: Option<&BucketPolicy>,
+    ) -> Result<AuthDecision> {
+        let credentials = match access_key {
+            Some(access_key) => Some(
+                self.find_credentials(access_key)
+                    .await?
+                    .ok_or_else(S3Error::invalid_access_key_id)?,
+            ),
+            None => None,
+        };
+        self.decide(credentials.as_ref(), action, resource, prefix, policy)
+            .await
+    }
+}
+
+/// What a request was signed with, from either form.
+struct Signature {
+    credential: Credential,
+    signed_headers: Vec<String>,
+    signature: String,
+    time: DateTime<Utc>,
+    payload_hash: String,
+}
+
+/// The service the credential of `req` must be scoped to: STS for the STS
+/// API at `POST /`, S3 for everything else.
+fn signing_service(req: &SignedRequest<'_>) -> &'static str {
+    if req.method == "POST" && req.uri.path() == "/" {
+        sigv4::STS_SERVICE
+    } else {
+        sigv4::SERVICE
+    }
+}
+
+/// Whether the session token `presented` is the `expected` one, compared
+/// in constant time like signatures.
+fn token_matches(expected: &str, presented: Option<&str>) -> bool {
+    presented.is_some_and(|token| constant_time_eq(expected.as_bytes(), token.as_bytes()))
+}
+
+/// The [`COOKIE_KEY_ID`] cookies and the ones next to it, if any.
+fn signed_cookies(headers: &HeaderMap) -> Option<HashMap<&'static str, String>> {
+    let mut cookies = HashMap::new();
+    for value in headers.get_all(http::header::COOKIE) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for (name, value) in value
+            .split(';')
+            .filter_map(|pair| pair.trim().split_once('='))
+        {
+            if let Some(name) = [
+                COOKIE_KEY_ID,
+                COOKIE_RESOURCE,
+                COOKIE_EXPIRES,
+                COOKIE_SIGNATURE,
+            ]
+            .into_iter()
+            .find(|cookie| *cookie == name)
+            {
+                cookies.insert(name, value.trim_matches('"').to_string());
+            }
+        }
+    }
+    cookies.contains_key(COOKIE_KEY_ID).then_some(cookies)
+}
+
+/// Whether a permission action (possibly a wildcard) grants any known action,
+/// to catch typos such as `GetObjectVersions` in the config.
+pub fn is_known_action(pattern: &str) -> bool {
+    S3_ACTIONS
+        .iter()
+        .chain(STS_ACTIONS)
+        .any(|action| action_matches(pattern, action))
+}
+
+/// S3 actions may be written with or without the `s3:` prefix; other
+/// services' actions need their own prefix, so `s3:*` never grants them.
+fn action_matches(pattern: &str, action: &str) -> bool {
+    match action.strip_prefix("s3:") {
+        Some(action) => wildcard_match(pattern.strip_prefix("s3:").unwrap_or(pattern), action),
+        None => wildcard_match(pattern, action),
+    }
+}
+
+/// Glob-style match where `*` matches any run of characters and `?` one character.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            backtrack = Some((pi, ti));
+            pi += 1;
+        } else if let Some((star, matched)) = backtrack {
+            pi = star + 1;
+            ti = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+    use crate::metrics::Metrics;
+    use crate::models::ServiceAccount;
+    use crate::services::auth_provider::ConfigProvider;
+    use crate::services::service_account;
+    use crate::sigv4::Presign;
+    use axum::extract::Query;
+    use http::Uri;
+
+    /// The credential and region of [`Config::for_tests`].
+    const ACCESS_KEY: &str = "AKIA...";
+    const SECRET_KEY: &str = "SECRET...";
+    const REGION: &str = "de-muc-01";
+    const HOST: &str = "s3.example.test";
+
+    fn service() -> AuthServiceImpl {
+        let config = Config::for_tests();
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let metrics = Arc::new(Metrics::new());
+        let storage = Arc::new(Storage::new(&config.storage, clock.clone(), metrics).unwrap());
+        let config = Arc::new(RwLock::new(config));
+        let providers: Vec<Arc<dyn AuthProvider>> =
+            vec![Arc::new(ConfigProvider::new(config.clone()))];
+        AuthServiceImpl::new(providers, storage, config, clock)
+    }
+
+    /// The S3 error code `result` failed with; `None` if it succeeded.
+    fn error_code(result: Result<Authentication>) -> Option<&'static str> {
+        result.err().map(|e| e.downcast::<S3Error>().unwrap().code)
+    }
+
+    async fn authenticate(uri: &str, headers: &HeaderMap) -> Result<Authentication> {
+        let uri: Uri = uri.parse().unwrap();
+        let query: HashMap<String, String> = Query::try_from_uri(&uri).unwrap().0;
+        let req = SignedRequest {
+            method: "GET",
+            uri: &uri,
+            headers,
+            body_sha256: None,
+        };
+        service().authenticate(&req, &query).await
+    }
+
+    /// A GET of `/photos/cat.jpg` signed for S3 in its `Authorization`
+    /// header, over `signed_headers`.
+    async fn header_signed(signed_headers: &[&str]) -> Result<Authentication> {
+        header_signed_for("s3", signed_headers).await
+    }
+
+    async fn header_signed_for(service: &str, signed_headers: &[&str]) -> Result<Authentication> {
+        let now = Utc::now();
+        let uri: Uri = "/photos/cat.jpg".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HOST.parse().unwrap());
+        headers.insert(
+            "x-amz-date",
+            now.format("%Y%m%dT%H%M%SZ").to_string().parse().unwrap(),
+        );
+        headers.insert(CONTENT_SHA256_HEADER, UNSIGNED_PAYLOAD.parse().unwrap());
+        let signed: Vec<String> = signed_headers.iter().map(|name| name.to_string()).collect();
+        let canonical_request = SignedRequest {
+            method: "GET",
+            uri: &uri,
+            headers: &headers,
+            body_sha256: None,
+        }
+        .canonical_request(&signed, UNSIGNED_PAYLOAD);
+        let signature = sigv4::sign(&canonical_request, SECRET_KEY, &now, REGION, service);
+        let authorization = format!(
+            "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+            sigv4::ALGORITHM,
+            ACCESS_KEY,
+            sigv4::credential_scope(&now, REGION, service),
+            signed.join(";"),
+            signature
+        );
+        headers.insert(http::header::AUTHORIZATION, authorization.parse().unwrap());
+        authenticate("/photos/cat.jpg", &headers).await
+    }
+
+    #[tokio::test]
+    async fn header_signatures_must_cover_host() {
+        assert_eq!(
+            error_code(header_signed(&["host", "x-amz-date"]).await),
+            None
+        );
+        assert_eq!(
+            error_code(header_signed(&["x-amz-content-sha256", "x-amz-date"]).await),
+            Some("AuthorizationHeaderMalformed")
+        );
+    }
+
+    #[tokio::test]
+    async fn credentials_must_be_scoped_to_s3() {
+        for service in ["sts", "ec2"] {
+            assert_eq!(
+                error_code(header_signed_for(service, &["host", "x-amz-date"]).await),
+                Some("AuthorizationHeaderMalformed"),
+                "{}",
+                service
+            );
+        }
+    }
+
+    #[test]
+    fn session_tokens_must_match_exactly() {
+        assert!(token_matches("FQoGZXIvYXdzE", Some("FQoGZXIvYXdzE")));
+        assert!(!token_matches("FQoGZXIvYXdzE", Some("FQoGZXIvYXdzF")));
+        assert!(!token_matches("FQoGZXIvYXdzE", Some("FQoGZXIvYXdz")));
+        assert!(!token_matches("FQoGZXIvYXdzE", None));
+    }
+
+    #[tokio::test]
+    async fn presigned_urls_must_sign_host() {
+        let url = Presign {
+            method: "GET",
+            host: HOST,
+            path: "/photos/cat.jpg",
+            params: &[],
+            access_key: ACCESS_KEY,
+            secret_key: SECRET_KEY,
+            session_token: None,
+            region: REGION,
+            time: Utc::now(),
+            expires_secs: 300,
+        }
+        .url();
+        let path = &url[url.find("/photos").unwrap()..];
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HOST.parse().unwrap());
+        assert_eq!(error_code(authenticate(path, &headers).await), None);
+        let unsigned_host = path.replace("X-Amz-SignedHeaders=host", "X-Amz-SignedHeaders=range");
+        assert_eq!(
+            error_code(authenticate(&unsigned_host, &headers).await),
+            Some("AuthorizationQueryParametersError")
+        );
+    }
+
+    fn cookie_header(cookies: &[(&str, String)]) -> HeaderMap {
+        let cookie = cookies
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::COOKIE, cookie.parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn signed_cookies_last_at_most_a_week() {
+        let now = Utc::now();
+        let week = MAX_COOKIE_LIFETIME_SECS;
+        let issue = |lifetime| issue_cookies(ACCESS_KEY, SECRET_KEY, "photos/*", now, lifetime);
+        assert_eq!(issue(week + 1).unwrap_err().code, "InvalidArgument");
+        let cookies = issue(week).unwrap();
+        let result = authenticate("/photos/cat.jpg", &cookie_header(&cookies)).await;
+        assert_eq!(error_code(result), None);
+
+        // Signed by hand, past what can be issued.
+        let expires = now.timestamp() + 8 * 24 * 3600;
+        let cookies = [
+            (COOKIE_KEY_ID, ACCESS_KEY.to_string()),
+            (COOKIE_RESOURCE, "photos%2F%2A".to_string()),
+            (COOKIE_EXPIRES, expires.to_string()),
+            (
+                COOKIE_SIGNATURE,
+                cookie_signature(SECRET_KEY, "photos/*", expires),
+            ),
+        ];
+        let result = authenticate("/photos/cat.jpg", &cookie_header(&cookies)).await;
+        assert_eq!(error_code(result), Some("AccessDenied"));
+    }
+
+    #[tokio::test]
+    async fn prefix_scoped_service_accounts_only_list_their_prefix() {
+        let account = ServiceAccount {
+            access_key: "SA0000000000000000000".to_string(),
+            secret_key: "secret".to_string(),
+            name: "ci".to_string(),
+            bucket: "artifacts".to_string(),
+            prefix: "builds/".to_string(),
+            actions: vec!["s3:GetObject".to_string(), "s3:ListBucket".to_string()],
+            created: "2024-01-01T00:00:00Z".to_string(),
+            expires: None,
+            created_by: "ops".to_string(),
+        };
+        let ctx = AuthContext::IAMAccount(Credentials {
+            access_key: account.access_key.clone(),
+            secret_key: account.secret_key.clone(),
+            permissions: service_account::permissions(&account),
+            session_token: None,
+            session_policy: None,
+        });
+        let auth = service();
+        let list = |prefix| auth.authorize_listing(&ctx, "s3:ListBucket", "artifacts", prefix);
+        assert!(list("builds/").await.is_ok());
+        assert!(list("builds/nightly/").await.is_ok());
+        for prefix in ["", "build", "secrets/"] {
+            let e = list(prefix)
+                .await
+                .unwrap_err()
+                .downcast::<S3Error>()
+                .unwrap();
+            assert_eq!((e.status.as_u16(), e.code), (403, "AccessDenied"));
+        }
+        // Without a prefix to check, the condition is not met.
+        assert!(
+            auth.authorize(&ctx, "s3:ListBucket", "artifacts")
+                .await
+                .is_err()
+        );
+        assert!(
+            auth.authorize(&ctx, "s3:GetObject", "artifacts/builds/a")
+                .await
+                .is_ok()
+        );
+        assert!(
+            auth.authorize(&ctx, "s3:GetObject", "artifacts/secrets/a")
+                .await
+                .is_err()
+        );
+    }
+}
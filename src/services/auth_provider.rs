@@ -0,0 +1,235 @@
+use crate::config::{AuthProviderConfig, Config, Credential};
+use crate::models::{Credentials, Permission};
+use crate::services::service_account::{self, ServiceAccountService};
+use crate::services::sts::StsService;
+use anyhow::Result;
+use log::{info, warn};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+
+/// A source of credentials for signed requests: the secret key to verify
+/// the signature of an access key with, and the permissions it grants.
+/// Providers are asked in the order of `auth.providers`; a site-specific
+/// source is added by implementing this trait and listing it in
+/// [`providers`].
+#[async_trait::async_trait]
+pub trait AuthProvider: Send + Sync {
+    /// Named in logs.
+    fn name(&self) -> &str;
+    /// The credentials of `access_key`; `None` when the provider does not
+    /// know it, so the next one is asked. An error fails the request.
+    async fn find(&self, access_key: &str) -> Result<Option<Credentials>>;
+}
+
+/// The chain configured under `auth.providers`, followed by the service
+/// accounts minted through the admin API and the STS sessions.
+pub fn providers(
+    config: &Arc<RwLock<Config>>,
+    service_accounts: Arc<dyn ServiceAccountService>,
+    sts: Arc<dyn StsService>,
+) -> Vec<Arc<dyn AuthProvider>> {
+    let configured = config.read().unwrap().auth.providers.clone();
+    let mut providers: Vec<Arc<dyn AuthProvider>> = configured
+        .into_iter()
+        .map(|provider| -> Arc<dyn AuthProvider> {
+            match provider {
+                AuthProviderConfig::Config => Arc::new(ConfigProvider::new(config.clone())),
+                AuthProviderConfig::File { path } => {
+                    Arc::new(FileProvider::new(PathBuf::from(path), config.clone()))
+                }
+            }
+        })
+        .collect();
+    providers.push(Arc::new(ServiceAccountProvider(service_accounts)));
+    providers.push(Arc::new(StsProvider(sts)));
+    providers
+}
+
+/// `credential` as authenticated requests carry it. Roles are resolved on
+/// every lookup, so role edits apply on reload.
+fn credentials(config: &Config, credential: &Credential) -> Credentials {
+    Credentials {
+        access_key: credential.access_key.clone(),
+        secret_key: credential.secret_key.clone(),
+        permissions: config
+            .effective_permissions(credential)
+            .into_iter()
+            .map(|p| Permission {
+                action: p.action,
+                resource: p.resource,
+                prefix: None,
+            })
+            .collect(),
+        session_token: None,
+        session_policy: None,
+    }
+}
+
+/// The `credentials` section of the live config, so credential changes
+/// apply on reload.
+pub struct ConfigProvider {
+    config: Arc<RwLock<Config>>,
+}
+
+impl ConfigProvider {
+    pub fn new(config: Arc<RwLock<Config>>) -> Self {
+        Self { config }
+    }
+}
+
+#[async_trait::async_trait]
+impl AuthProvider for ConfigProvider {
+    fn name(&self) -> &str {
+        "config"
+    }
+
+    async fn find(&self, access_key: &str) -> Result<Option<Credentials>> {
+        let cfg = self.config.read().unwrap();
+        Ok(cfg
+            .credentials
+            .iter()
+            .find(|c| c.access_key == access_key)
+            .map(|c| credentials(&cfg, c)))
+    }
+}
+
+/// Credentials kept in a YAML file of their own, in the format of the
+/// `credentials` section, for sites that generate them from another
+/// system. The file is re-read on the first lookup after its modification
+/// time changes. Entries that fail validation are skipped, and a file that
+/// cannot be read or parsed leaves the previous entries in place; both are
+/// logged as warnings. A missing file provides no credentials.
+pub struct FileProvider {
+    path: PathBuf,
+    config: Arc<RwLock<Config>>,
+    loaded: Mutex<Loaded>,
+}
+
+#[derive(Default)]
+struct Loaded {
+    /// Modification time of the file when last read; `None` if missing.
+    modified: Option<SystemTime>,
+    credentials: Vec<Credential>,
+}
+
+impl FileProvider {
+    pub fn new(path: PathBuf, config: Arc<RwLock<Config>>) -> Self {
+        let provider = Self {
+            path,
+            config,
+            loaded: Mutex::new(Loaded::default()),
+        };
+        let mut loaded = provider.loaded.lock().unwrap();
+        provider.refresh(&mut loaded, true);
+        drop(loaded);
+        provider
+    }
+
+    /// Re-reads the file if it changed since it was last read, or always
+    /// with `force`.
+    fn refresh(&self, loaded: &mut Loaded, force: bool) {
+        let modified = match fs::metadata(&self.path).and_then(|m| m.modified()) {
+            Ok(modified) => Some(modified),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                warn!("Cannot read credentials file {:?}: {}", self.path, e);
+                return;
+            }
+        };
+        if !force && modified == loaded.modified {
+            return;
+        }
+        loaded.modified = modified;
+        if modified.is_none() {
+            warn!("Credentials file {:?} does not exist", self.path);
+            loaded.credentials.clear();
+            return;
+        }
+        let parsed = fs::read_to_string(&self.path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| {
+                serde_yaml::from_str::<Vec<Credential>>(&content).map_err(|e| e.to_string())
+            });
+        let entries = match parsed {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!(
+                    "Keeping the previous credentials of {:?}, which cannot be loaded: {}",
+                    self.path, e
+                );
+                return;
+            }
+        };
+        let cfg = self.config.read().unwrap();
+        loaded.credentials = entries
+            .into_iter()
+            .filter(|credential| match cfg.validate_credential(credential) {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("Skipping a credential of {:?}: {}", self.path, e);
+                    false
+                }
+            })
+            .collect();
+        info!(
+            "Loaded {} credential(s) from {:?}",
+            loaded.credentials.len(),
+            self.path
+        );
+    }
+}
+
+#[async_trait::async_trait]
+impl AuthProvider for FileProvider {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    async fn find(&self, access_key: &str) -> Result<Option<Credentials>> {
+        let mut loaded = self.loaded.lock().unwrap();
+        self.refresh(&mut loaded, false);
+        let cfg = self.config.read().unwrap();
+        Ok(loaded
+            .credentials
+            .iter()
+            .find(|c| c.access_key == access_key)
+            .map(|c| credentials(&cfg, c)))
+    }
+}
+
+/// Service accounts minted through the admin API.
+pub struct ServiceAccountProvider(pub Arc<dyn ServiceAccountService>);
+
+#[async_trait::async_trait]
+impl AuthProvider for ServiceAccountProvider {
+    fn name(&self) -> &str {
+        "service_accounts"
+    }
+
+    async fn find(&self, access_key: &str) -> Result<Option<Credentials>> {
+        Ok(self.0.find(access_key).map(|account| Credentials {
+            access_key: account.access_key.clone(),
+            secret_key: account.secret_key.clone(),
+            permissions: service_account::permissions(&account),
+            session_token: None,
+            session_policy: None,
+        }))
+    }
+}
+
+/// Temporary credentials issued by STS.
+pub struct StsProvider(pub Arc<dyn StsService>);
+
+#[async_trait::async_trait]
+impl AuthProvider for StsProvider {
+    fn name(&self) -> &str {
+        "sts"
+    }
+
+    async fn find(&self, access_key: &str) -> Result<Option<Credentials>> {
+        self.0.find(access_key)
+    }
+}
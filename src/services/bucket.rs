@@ -1,27 +1,1168 @@
+use crate::config::Config;
+use crate::error::S3Error;
+use crate::models::{
+    AclGrant, BucketMetadata, BucketPolicy, BucketSummary, CommonPrefix, CorsConfiguration,
+    CreateBucketRequest, DeleteBucketRequest, DeleteMarkerEntry, InventoryEntry,
+    LifecycleConfiguration, ListBucketsRequest, ListBucketsResponse, ListObjectVersionsRequest,
+    ListObjectVersionsResponse, ListObjectsRequest, ListObjectsResponse, ListObjectsV2Request,
+    ListObjectsV2Response, ObjectAttributes, ObjectMetadata, ObjectSummary, ObjectVersion,
+    ObjectVersionSummary, Owner, RestoreStatus, S3_XMLNS, SearchObjectsRequest, VersionEntry,
+    VersioningStatus,
+};
+use crate::services::object::acl;
+use crate::storage::{Storage, StorageError};
 use anyhow::Result;
-use crate::models::Bucket;
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{debug, info};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Upper bound (and default) for `max-keys`, as in S3.
+pub const MAX_KEYS: u32 = 1000;
 
 #[async_trait::async_trait]
 pub trait BucketService: Send + Sync {
-    async fn create_bucket(&self, name: &str) -> Result<Bucket>;
-    async fn delete_bucket(&self, name: &str) -> Result<()>;
-    async fn list_buckets(&self) -> Result<Vec<Bucket>>;
+    async fn create_bucket(&self, req: CreateBucketRequest) -> Result<BucketMetadata>;
+    /// Deletes an empty bucket, or with `bucket_tombstones` enabled keeps it
+    /// as a tombstone the admin API can restore.
+    async fn delete_bucket(&self, req: DeleteBucketRequest) -> Result<()>;
+    async fn head_bucket(&self, bucket: &str) -> Result<BucketMetadata>;
+    async fn list_buckets(&self, req: ListBucketsRequest) -> Result<ListBucketsResponse>;
+    async fn list_objects(&self, req: ListObjectsRequest) -> Result<ListObjectsResponse>;
+    async fn list_objects_v2(&self, req: ListObjectsV2Request) -> Result<ListObjectsV2Response>;
+    async fn list_object_versions(
+        &self,
+        req: ListObjectVersionsRequest,
+    ) -> Result<ListObjectVersionsResponse>;
+    /// Key, ETag and size of every object under `prefix`, in key order.
+    async fn inventory(&self, bucket: &str, prefix: &str) -> Result<Vec<InventoryEntry>>;
+    /// The metadata of the objects under the prefix, after `start_after`,
+    /// that match every predicate, in key order.
+    async fn search_objects(&self, req: SearchObjectsRequest) -> Result<Vec<ObjectMetadata>>;
+    /// The bucket's tags; empty when it has no tag set.
+    async fn get_bucket_tagging(&self, bucket: &str) -> Result<BTreeMap<String, String>>;
+    /// Replaces the bucket's tags; an empty set removes the tag set.
+    async fn put_bucket_tagging(&self, bucket: &str, tags: BTreeMap<String, String>) -> Result<()>;
+    /// The bucket's versioning state; `None` if versioning was never enabled.
+    async fn get_bucket_versioning(&self, bucket: &str) -> Result<Option<VersioningStatus>>;
+    /// Enables or suspends versioning.
+    async fn put_bucket_versioning(&self, bucket: &str, status: VersioningStatus) -> Result<()>;
+    /// The bucket's lifecycle configuration, if it has one.
+    async fn get_bucket_lifecycle(&self, bucket: &str) -> Result<Option<LifecycleConfiguration>>;
+    /// Replaces the lifecycle configuration; `None` removes it.
+    async fn put_bucket_lifecycle(
+        &self,
+        bucket: &str,
+        lifecycle: Option<LifecycleConfiguration>,
+    ) -> Result<()>;
+    /// The bucket's policy, if it has one.
+    async fn get_bucket_policy(&self, bucket: &str) -> Result<Option<BucketPolicy>>;
+    /// Replaces the bucket policy; `None` removes it.
+    async fn put_bucket_policy(&self, bucket: &str, policy: Option<BucketPolicy>) -> Result<()>;
+    /// The grants of the bucket's ACL besides the owner's; empty for
+    /// `private`.
+    async fn get_bucket_acl(&self, bucket: &str) -> Result<Vec<AclGrant>>;
+    /// Replaces the bucket's ACL.
+    async fn put_bucket_acl(&self, bucket: &str, acl: Vec<AclGrant>) -> Result<()>;
+    /// The bucket's CORS configuration, if it has one.
+    async fn get_bucket_cors(&self, bucket: &str) -> Result<Option<CorsConfiguration>>;
+    /// Replaces the CORS configuration; `None` removes it.
+    async fn put_bucket_cors(&self, bucket: &str, cors: Option<CorsConfiguration>) -> Result<()>;
+}
+
+pub struct BucketServiceImpl {
+    storage: Arc<Storage>,
+    config: Arc<RwLock<Config>>,
 }
 
-pub struct BucketServiceImpl;
+impl BucketServiceImpl {
+    pub fn new(storage: Arc<Storage>, config: Arc<RwLock<Config>>) -> Self {
+        Self { storage, config }
+    }
+
+    fn owner(&self) -> Owner {
+        let cfg = self.config.read().unwrap();
+        Owner {
+            id: cfg.owner.id.clone(),
+            display_name: cfg.owner.display_name.clone(),
+        }
+    }
+
+    /// When a listing starting now has to stop reading metadata, with
+    /// `server.listing_time_budget_ms`.
+    fn listing_deadline(&self) -> Option<Instant> {
+        let budget_ms = self.config.read().unwrap().server.listing_time_budget_ms?;
+        Some(Instant::now() + Duration::from_millis(budget_ms))
+    }
+
+    /// The metadata of `bucket`, or of the bucket it is an alias of.
+    async fn bucket_metadata(&self, bucket: &str) -> Result<BucketMetadata> {
+        let bucket = bucket.to_string();
+        self.storage
+            .blocking(
+                move |storage| Ok(storage.bucket_metadata(&storage.resolve_bucket(&bucket)?)?),
+            )
+            .await
+    }
+
+    /// Changes the metadata of `bucket` with `update`; returns the name of
+    /// the bucket changed, which `bucket` may be an alias of.
+    async fn update_bucket_metadata(
+        &self,
+        bucket: &str,
+        update: impl FnOnce(&mut BucketMetadata) + Send + 'static,
+    ) -> Result<String> {
+        let bucket = bucket.to_string();
+        self.storage
+            .blocking(move |storage| {
+                let bucket = storage.resolve_bucket(&bucket)?;
+                let mut metadata = storage.bucket_metadata(&bucket)?;
+                update(&mut metadata);
+                storage.update_bucket_metadata(&metadata)?;
+                Ok(bucket)
+            })
+            .await
+    }
+
+    /// A page of the keys `query` lists, with their summaries (see
+    /// [`summaries`]).
+    async fn list_page(
+        &self,
+        query: ListQuery,
+        owner: Option<Owner>,
+        restore_status: bool,
+    ) -> Result<(ListPage, Vec<ObjectSummary>)> {
+        let deadline = self.listing_deadline();
+        self.storage
+            .blocking(move |storage| {
+                let bucket = storage.resolve_bucket(&query.bucket)?;
+                let keys = storage.list_keys_eventual(&bucket, &query.prefix)?;
+                let mut page = paginate(
+                    &keys,
+                    &query.prefix,
+                    query.delimiter.as_deref(),
+                    query.marker.as_deref(),
+                    query.max_keys,
+                );
+                let contents =
+                    summaries(storage, &bucket, &mut page, owner, restore_status, deadline)?;
+                Ok((page, contents))
+            })
+            .await
+    }
+}
+
+/// The keys a listing request asks for: up to `max_keys` of those in
+/// `bucket` under `prefix` after `marker`, grouped by `delimiter`.
+struct ListQuery {
+    bucket: String,
+    prefix: String,
+    delimiter: Option<String>,
+    marker: Option<String>,
+    max_keys: usize,
+}
 
 #[async_trait::async_trait]
 impl BucketService for BucketServiceImpl {
-    async fn create_bucket(&self, name: &str) -> Result<Bucket> {
-        // TODO: Implement bucket creation logic
-        unimplemented!()
+    async fn create_bucket(&self, req: CreateBucketRequest) -> Result<BucketMetadata> {
+        {
+            let cfg = self.config.read().unwrap();
+            if cfg.system_logs.enabled && req.bucket == cfg.system_logs.bucket {
+                return Err(StorageError::InvalidBucketName(format!(
+                    "{} is reserved for system logs",
+                    req.bucket
+                ))
+                .into());
+            }
+        }
+        let metadata = BucketMetadata {
+            name: req.bucket,
+            region: req.location_constraint.filter(|region| !region.is_empty()),
+            created: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            created_by: None,
+            notification: None,
+            tags: Default::default(),
+            versioning: None,
+            lifecycle: None,
+            policy: None,
+            acl: acl(&self.config.read().unwrap(), req.headers.acl),
+            cors: None,
+            etag_algorithm: None,
+        };
+        self.storage
+            .blocking(move |storage| storage.create_bucket(&metadata).map(|()| metadata))
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn delete_bucket(&self, req: DeleteBucketRequest) -> Result<()> {
+        let tombstones = self.config.read().unwrap().bucket_tombstones.clone();
+        self.storage
+            .blocking(move |storage| {
+                if tombstones.enabled {
+                    let retention = tombstones.retention_secs;
+                    storage.tombstone_bucket(&req.bucket, &req.deleted_by, retention, false)?;
+                    return Ok(());
+                }
+                storage.delete_bucket(&req.bucket)
+            })
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn head_bucket(&self, bucket: &str) -> Result<BucketMetadata> {
+        self.bucket_metadata(bucket).await
+    }
+
+    async fn list_buckets(&self, _req: ListBucketsRequest) -> Result<ListBucketsResponse> {
+        let default_region = self.config.read().unwrap().region.default.clone();
+        let buckets = self
+            .storage
+            .blocking(move |storage| -> Result<_> {
+                let mut buckets = Vec::new();
+                for name in storage.list_buckets()? {
+                    let metadata = storage.bucket_metadata(&name)?;
+                    buckets.push(BucketSummary {
+                        name,
+                        creation_date: metadata.created,
+                        bucket_region: metadata.region.unwrap_or_else(|| default_region.clone()),
+                    });
+                }
+                Ok(buckets)
+            })
+            .await?;
+        Ok(ListBucketsResponse::new(self.owner(), buckets))
+    }
+
+    async fn list_objects(&self, req: ListObjectsRequest) -> Result<ListObjectsResponse> {
+        let prefix = req.prefix.unwrap_or_default();
+        let max_keys = req.max_keys.unwrap_or(MAX_KEYS).min(MAX_KEYS);
+        let query = ListQuery {
+            bucket: req.bucket.clone(),
+            prefix: prefix.clone(),
+            delimiter: req.delimiter.clone(),
+            marker: req.marker.clone(),
+            max_keys: max_keys as usize,
+        };
+        let (page, contents) = self
+            .list_page(query, Some(self.owner()), req.headers.restore_status())
+            .await?;
+        Ok(ListObjectsResponse {
+            xmlns: S3_XMLNS,
+            name: req.bucket,
+            contents,
+            common_prefixes: page.common_prefixes(),
+            // S3 only returns NextMarker when a delimiter is used; otherwise
+            // clients continue from the last key.
+            next_marker: req
+                .delimiter
+                .as_ref()
+                .and_then(|_| page.next_marker().map(str::to_string)),
+            is_truncated: page.is_truncated,
+            prefix,
+            marker: req.marker.unwrap_or_default(),
+            max_keys,
+            delimiter: req.delimiter,
+            encoding_type: None,
+        })
+    }
+
+    async fn list_objects_v2(&self, req: ListObjectsV2Request) -> Result<ListObjectsV2Response> {
+        let prefix = req.prefix.unwrap_or_default();
+        let max_keys = req.max_keys.unwrap_or(MAX_KEYS).min(MAX_KEYS);
+        // A continuation token takes precedence over start-after.
+        let marker = match &req.continuation_token {
+            Some(token) => Some(decode_continuation_token(token)?),
+            None => req.start_after.clone(),
+        };
+        let owner = req.fetch_owner.then(|| self.owner());
+        let query = ListQuery {
+            bucket: req.bucket.clone(),
+            prefix: prefix.clone(),
+            delimiter: req.delimiter.clone(),
+            marker,
+            max_keys: max_keys as usize,
+        };
+        let (page, contents) = self
+            .list_page(query, owner, req.headers.restore_status())
+            .await?;
+        Ok(ListObjectsV2Response {
+            xmlns: S3_XMLNS,
+            name: req.bucket,
+            key_count: page.entries.len() as u32,
+            contents,
+            common_prefixes: page.common_prefixes(),
+            next_continuation_token: page.next_marker().map(encode_continuation_token),
+            is_truncated: page.is_truncated,
+            prefix,
+            delimiter: req.delimiter,
+            max_keys,
+            encoding_type: None,
+            continuation_token: req.continuation_token,
+            start_after: req.start_after,
+        })
+    }
+
+    /// Lists every version of every key, newest first within a key. A page
+    /// can end partway through the versions of a key; the next one resumes
+    /// after `version-id-marker` in `key-marker`.
+    ///
+    /// With `as_of`, lists only the version of each key that was current at
+    /// that time, leaving out keys that did not exist or were deleted then.
+    async fn list_object_versions(
+        &self,
+        req: ListObjectVersionsRequest,
+    ) -> Result<ListObjectVersionsResponse> {
+        let deadline = self.listing_deadline();
+        let prefix = req.prefix.unwrap_or_default();
+        let max_keys = req.max_keys.unwrap_or(MAX_KEYS).min(MAX_KEYS);
+        let key_marker = req.key_marker.filter(|marker| !marker.is_empty());
+        let version_id_marker = req.version_id_marker.filter(|marker| !marker.is_empty());
+        if version_id_marker.is_some() && key_marker.is_none() {
+            return Err(S3Error::invalid_argument(
+                "A version-id marker cannot be specified without a key marker.",
+            )
+            .with_resource("ArgumentName", "version-id-marker")
+            .into());
+        }
+
+        let page = VersionPage::new(
+            max_keys as usize,
+            self.owner(),
+            req.headers.restore_status(),
+            deadline,
+        );
+        let query = ListQuery {
+            bucket: req.bucket.clone(),
+            prefix: prefix.clone(),
+            delimiter: req.delimiter.clone(),
+            marker: key_marker.clone(),
+            max_keys: max_keys as usize,
+        };
+        let (marker, as_of) = (version_id_marker.clone(), req.as_of);
+        let page = self
+            .storage
+            .blocking(move |storage| version_page(storage, page, query, marker.as_deref(), as_of))
+            .await?;
+
+        let (next_key_marker, next_version_id_marker) = match page.last {
+            Some((key, version_id)) if page.is_truncated => (Some(key), version_id),
+            _ => (None, None),
+        };
+        Ok(ListObjectVersionsResponse {
+            xmlns: S3_XMLNS,
+            name: req.bucket,
+            versions: page.versions,
+            common_prefixes: page.common_prefixes,
+            next_key_marker,
+            next_version_id_marker,
+            is_truncated: page.is_truncated,
+            prefix,
+            key_marker: key_marker.unwrap_or_default(),
+            version_id_marker: version_id_marker.unwrap_or_default(),
+            max_keys,
+            delimiter: req.delimiter,
+            encoding_type: None,
+        })
+    }
+
+    async fn inventory(&self, bucket: &str, prefix: &str) -> Result<Vec<InventoryEntry>> {
+        let (bucket, prefix) = (bucket.to_string(), prefix.to_string());
+        self.storage
+            .blocking(move |storage| {
+                let bucket = storage.resolve_bucket(&bucket)?;
+                let mut entries = Vec::new();
+                for key in storage.list_keys(&bucket, &prefix)? {
+                    match storage.head_object(&bucket, &key) {
+                        Ok(metadata) => entries.push(InventoryEntry {
+                            key: metadata.key,
+                            etag: Some(metadata.etag),
+                            size: Some(metadata.size),
+                            last_modified: Some(metadata.last_modified),
+                        }),
+                        // Deleted since the directory walk.
+                        Err(StorageError::NoSuchKey(_)) => continue,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                Ok(entries)
+            })
+            .await
+    }
+
+    async fn search_objects(&self, req: SearchObjectsRequest) -> Result<Vec<ObjectMetadata>> {
+        self.storage
+            .blocking(move |storage| {
+                let bucket = storage.resolve_bucket(&req.bucket)?;
+                let mut matches = Vec::new();
+                for key in storage.list_keys(&bucket, &req.prefix)? {
+                    if req.start_after.as_ref().is_some_and(|after| key <= *after) {
+                        continue;
+                    }
+                    match storage.head_object(&bucket, &key) {
+                        Ok(metadata) if req.predicates.iter().all(|p| p.matches(&metadata)) => {
+                            matches.push(metadata)
+                        }
+                        Ok(_) => {}
+                        // Deleted since the directory walk.
+                        Err(StorageError::NoSuchKey(_)) => continue,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                Ok(matches)
+            })
+            .await
+    }
+
+    async fn get_bucket_tagging(&self, bucket: &str) -> Result<BTreeMap<String, String>> {
+        Ok(self.bucket_metadata(bucket).await?.tags)
     }
-    async fn delete_bucket(&self, name: &str) -> Result<()> {
-        // TODO: Implement bucket deletion logic
-        unimplemented!()
+
+    async fn put_bucket_tagging(&self, bucket: &str, tags: BTreeMap<String, String>) -> Result<()> {
+        let count = tags.len();
+        let bucket = self
+            .update_bucket_metadata(bucket, move |metadata| metadata.tags = tags)
+            .await?;
+        info!("Set {} tag(s) on bucket {}", count, bucket);
+        Ok(())
+    }
+
+    async fn get_bucket_versioning(&self, bucket: &str) -> Result<Option<VersioningStatus>> {
+        Ok(self.bucket_metadata(bucket).await?.versioning)
+    }
+
+    async fn put_bucket_versioning(&self, bucket: &str, status: VersioningStatus) -> Result<()> {
+        let bucket = self
+            .update_bucket_metadata(bucket, move |metadata| metadata.versioning = Some(status))
+            .await?;
+        info!("Set versioning of bucket {} to {:?}", bucket, status);
+        Ok(())
+    }
+
+    async fn get_bucket_lifecycle(&self, bucket: &str) -> Result<Option<LifecycleConfiguration>> {
+        Ok(self.bucket_metadata(bucket).await?.lifecycle)
+    }
+
+    async fn put_bucket_lifecycle(
+        &self,
+        bucket: &str,
+        lifecycle: Option<LifecycleConfiguration>,
+    ) -> Result<()> {
+        let count = lifecycle
+            .as_ref()
+            .map_or(0, |lifecycle| lifecycle.rules.len());
+        let bucket = self
+            .update_bucket_metadata(bucket, move |metadata| metadata.lifecycle = lifecycle)
+            .await?;
+        info!("Set {} lifecycle rule(s) on bucket {}", count, bucket);
+        Ok(())
+    }
+
+    async fn get_bucket_policy(&self, bucket: &str) -> Result<Option<BucketPolicy>> {
+        Ok(self.bucket_metadata(bucket).await?.policy)
+    }
+
+    async fn put_bucket_policy(&self, bucket: &str, policy: Option<BucketPolicy>) -> Result<()> {
+        let count = policy
+            .as_ref()
+            .map_or(0, |policy| policy.statements().len());
+        let bucket = self
+            .update_bucket_metadata(bucket, move |metadata| metadata.policy = policy)
+            .await?;
+        info!("Set {} policy statement(s) on bucket {}", count, bucket);
+        Ok(())
+    }
+
+    async fn get_bucket_acl(&self, bucket: &str) -> Result<Vec<AclGrant>> {
+        Ok(self.bucket_metadata(bucket).await?.acl)
+    }
+
+    async fn put_bucket_acl(&self, bucket: &str, acl: Vec<AclGrant>) -> Result<()> {
+        let count = acl.len();
+        let bucket = self
+            .update_bucket_metadata(bucket, move |metadata| metadata.acl = acl)
+            .await?;
+        info!("Set {} ACL grant(s) on bucket {}", count, bucket);
+        Ok(())
+    }
+
+    async fn get_bucket_cors(&self, bucket: &str) -> Result<Option<CorsConfiguration>> {
+        Ok(self.bucket_metadata(bucket).await?.cors)
     }
-    async fn list_buckets(&self) -> Result<Vec<Bucket>> {
-        // TODO: Implement bucket listing logic
-        unimplemented!()
+
+    async fn put_bucket_cors(&self, bucket: &str, cors: Option<CorsConfiguration>) -> Result<()> {
+        let count = cors.as_ref().map_or(0, |cors| cors.rules.len());
+        let bucket = self
+            .update_bucket_metadata(bucket, move |metadata| metadata.cors = cors)
+            .await?;
+        info!("Set {} CORS rule(s) on bucket {}", count, bucket);
+        Ok(())
+    }
+}
+
+/// Loads the summaries for the keys on a page. Keys deleted since the
+/// directory walk are skipped. Once `deadline` has passed, the page is cut
+/// short after the entries returned so far (at least one, so clients make
+/// progress) and truncated, the next page resuming after them.
+fn summaries(
+    storage: &Storage,
+    bucket: &str,
+    page: &mut ListPage,
+    owner: Option<Owner>,
+    restore_status: bool,
+    deadline: Option<Instant>,
+) -> Result<Vec<ObjectSummary>> {
+    let mut contents = Vec::new();
+    let mut returned = 0;
+    let mut cut = None;
+    for (idx, entry) in page.entries.iter().enumerate() {
+        if returned > 0 && deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            cut = Some(idx);
+            break;
+        }
+        let ListEntry::Key(key) = entry else {
+            returned += 1;
+            continue;
+        };
+        let metadata = match storage.list_metadata_eventual(bucket, key) {
+            Ok(metadata) => metadata,
+            Err(StorageError::NoSuchKey(_)) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        contents.push(ObjectSummary {
+            key: metadata.key,
+            last_modified: metadata.last_modified,
+            etag: metadata.etag,
+            checksum_algorithm: metadata.attributes.checksum_algorithm.clone(),
+            size: metadata.size,
+            storage_class: metadata.attributes.storage_class().to_string(),
+            owner: owner.clone(),
+            restore_status: restore_status_of(&metadata.attributes, restore_status),
+        });
+        returned += 1;
+    }
+    if let Some(idx) = cut {
+        debug!(
+            "Listing of {} cut short at its time budget after {} of {} entries",
+            bucket,
+            idx,
+            page.entries.len()
+        );
+        page.entries.truncate(idx);
+        page.is_truncated = true;
+    }
+    Ok(contents)
+}
+
+/// Fills `page` with the versions of the keys `query` lists, from
+/// `version_id_marker` in its key marker on (see
+/// [`BucketService::list_object_versions`]).
+fn version_page(
+    storage: &Storage,
+    mut page: VersionPage,
+    query: ListQuery,
+    version_id_marker: Option<&str>,
+    as_of: Option<DateTime<Utc>>,
+) -> Result<VersionPage> {
+    let bucket = &storage.resolve_bucket(&query.bucket)?;
+    let (prefix, key_marker) = (query.prefix.as_str(), query.marker.as_deref());
+    // An as-of listing has one version per key, so a page never ends
+    // partway through a key.
+    if let (Some(key), Some(version_id), None) = (key_marker, version_id_marker, as_of) {
+        let rest = storage
+            .object_versions(bucket, key)?
+            .into_iter()
+            .skip_while(|version| version.version_id() != version_id)
+            .skip(1);
+        for version in rest {
+            if !page.push_version(version, false) {
+                break;
+            }
+        }
+    }
+    if page.is_truncated {
+        return Ok(page);
+    }
+    let mut keys = storage.versioned_keys(bucket, prefix)?;
+    // Keys without a version then are left out before paginating, so they
+    // do not end up as common prefixes or short pages.
+    let mut then = HashMap::new();
+    if let Some(as_of) = as_of {
+        for key in &keys {
+            let versions = storage.object_versions(bucket, key)?;
+            if let Some(version) = version_as_of(versions, as_of) {
+                then.insert(key.clone(), version);
+            }
+        }
+        keys.retain(|key| then.contains_key(key));
+    }
+    let listing = paginate(
+        &keys,
+        prefix,
+        query.delimiter.as_deref(),
+        key_marker,
+        query.max_keys,
+    );
+    'entries: for entry in listing.entries {
+        match entry {
+            ListEntry::CommonPrefix(common_prefix) => {
+                if !page.push_prefix(common_prefix) {
+                    break;
+                }
+            }
+            ListEntry::Key(key) if as_of.is_some() => {
+                if let Some((version, is_latest)) = then.remove(&key)
+                    && !page.push_version(version, is_latest)
+                {
+                    break;
+                }
+            }
+            ListEntry::Key(key) => {
+                let versions = storage.object_versions(bucket, &key)?;
+                for (idx, version) in versions.into_iter().enumerate() {
+                    if !page.push_version(version, idx == 0) {
+                        break 'entries;
+                    }
+                }
+            }
+        }
+    }
+    page.is_truncated |= listing.is_truncated;
+    Ok(page)
+}
+
+/// A ListObjectVersions page being filled, up to `max_keys` versions and
+/// common prefixes.
+struct VersionPage {
+    max_keys: usize,
+    /// After which the page takes no more entries, once it has one.
+    deadline: Option<Instant>,
+    owner: Owner,
+    /// Whether versions get a `RestoreStatus`.
+    restore_status: bool,
+    versions: Vec<VersionEntry>,
+    common_prefixes: Vec<CommonPrefix>,
+    /// Key, and version ID unless it is a common prefix, of the last entry.
+    last: Option<(String, Option<String>)>,
+    is_truncated: bool,
+}
+
+impl VersionPage {
+    fn new(max_keys: usize, owner: Owner, restore_status: bool, deadline: Option<Instant>) -> Self {
+        Self {
+            max_keys,
+            deadline,
+            owner,
+            restore_status,
+            versions: Vec::new(),
+            common_prefixes: Vec::new(),
+            last: None,
+            is_truncated: false,
+        }
+    }
+
+    /// Makes room for one more entry; `false`, and the page truncated, when
+    /// it is full or out of time.
+    fn has_room(&mut self) -> bool {
+        let len = self.versions.len() + self.common_prefixes.len();
+        if len > 0
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            debug!(
+                "Version listing cut short at its time budget after {} entries",
+                len
+            );
+            self.is_truncated = true;
+            return false;
+        }
+        if len < self.max_keys {
+            return true;
+        }
+        // With max-keys=0 nothing is returned and nothing is truncated.
+        self.is_truncated = self.max_keys > 0;
+        false
     }
-} 
\ No newline at end of file
+
+    fn push_version(&mut self, version: ObjectVersion, is_latest: bool) -> bool {
+        if !self.has_room() {
+            return false;
+        }
+        let version_id = version.version_id().to_string();
+        let entry = match version {
+            ObjectVersion::Object { metadata, .. } => VersionEntry::Version(ObjectVersionSummary {
+                key: metadata.key,
+                version_id: version_id.clone(),
+                is_latest,
+                last_modified: metadata.last_modified,
+                etag: metadata.etag,
+                checksum_algorithm: metadata.attributes.checksum_algorithm.clone(),
+                size: metadata.size,
+                storage_class: metadata.attributes.storage_class().to_string(),
+                owner: self.owner.clone(),
+                restore_status: restore_status_of(&metadata.attributes, self.restore_status),
+            }),
+            ObjectVersion::DeleteMarker {
+                key, last_modified, ..
+            } => VersionEntry::DeleteMarker(DeleteMarkerEntry {
+                key,
+                version_id: version_id.clone(),
+                is_latest,
+                last_modified,
+                owner: self.owner.clone(),
+            }),
+        };
+        let key = match &entry {
+            VersionEntry::Version(version) => version.key.clone(),
+            VersionEntry::DeleteMarker(marker) => marker.key.clone(),
+        };
+        self.last = Some((key, Some(version_id)));
+        self.versions.push(entry);
+        true
+    }
+
+    fn push_prefix(&mut self, prefix: String) -> bool {
+        if !self.has_room() {
+            return false;
+        }
+        self.last = Some((prefix.clone(), None));
+        self.common_prefixes.push(CommonPrefix { prefix });
+        true
+    }
+}
+
+/// One entry of a listing page. A common prefix stands for every key rolled
+/// up under it and counts as a single entry toward `max-keys`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListEntry {
+    Key(String),
+    CommonPrefix(String),
+}
+
+impl ListEntry {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ListEntry::Key(s) | ListEntry::CommonPrefix(s) => s,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ListPage {
+    pub entries: Vec<ListEntry>,
+    pub is_truncated: bool,
+}
+
+impl ListPage {
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().filter_map(|entry| match entry {
+            ListEntry::Key(key) => Some(key.as_str()),
+            ListEntry::CommonPrefix(_) => None,
+        })
+    }
+
+    pub fn common_prefixes(&self) -> Vec<CommonPrefix> {
+        self.entries
+            .iter()
+            .filter_map(|entry| match entry {
+                ListEntry::CommonPrefix(prefix) => Some(CommonPrefix {
+                    prefix: prefix.clone(),
+                }),
+                ListEntry::Key(_) => None,
+            })
+            .collect()
+    }
+
+    /// Where the next page starts: the last key or common prefix returned,
+    /// if the listing was truncated.
+    pub fn next_marker(&self) -> Option<&str> {
+        self.is_truncated
+            .then(|| self.entries.last().map(ListEntry::as_str))
+            .flatten()
+    }
+}
+
+/// Computes one page of a listing the way S3 does.
+///
+/// `keys` must be sorted and all start with `prefix`. Keys up to and
+/// including `marker` are skipped; when the marker is itself a common prefix
+/// (i.e. the previous page ended on one), every key rolled up under it is
+/// skipped too, so a page never repeats a common prefix. Each common prefix
+/// counts once toward `max_keys`, and the page is truncated only if another
+/// entry would follow.
+pub fn paginate(
+    keys: &[String],
+    prefix: &str,
+    delimiter: Option<&str>,
+    marker: Option<&str>,
+    max_keys: usize,
+) -> ListPage {
+    let delimiter = delimiter.filter(|d| !d.is_empty());
+    let marker_group = marker.filter(|m| {
+        delimiter.is_some_and(|d| common_prefix(m, prefix, d).is_some_and(|group| group == *m))
+    });
+
+    let mut page = ListPage::default();
+    for key in keys {
+        if marker.is_some_and(|m| key.as_str() <= m)
+            || marker_group.is_some_and(|group| key.starts_with(group))
+        {
+            continue;
+        }
+        let entry = match delimiter.and_then(|d| common_prefix(key, prefix, d)) {
+            Some(group) => {
+                if matches!(page.entries.last(), Some(ListEntry::CommonPrefix(last)) if last == group)
+                {
+                    continue;
+                }
+                ListEntry::CommonPrefix(group.to_string())
+            }
+            None => ListEntry::Key(key.clone()),
+        };
+        if page.entries.len() == max_keys {
+            // With max-keys=0 nothing is returned and nothing is truncated.
+            page.is_truncated = max_keys > 0;
+            break;
+        }
+        page.entries.push(entry);
+    }
+    page
+}
+
+/// The common prefix `key` rolls up into: `prefix` plus everything up to and
+/// including the first `delimiter` after it.
+fn common_prefix<'a>(key: &'a str, prefix: &str, delimiter: &str) -> Option<&'a str> {
+    let rest = key.strip_prefix(prefix)?;
+    let idx = rest.find(delimiter)?;
+    Some(&key[..prefix.len() + idx + delimiter.len()])
+}
+
+/// The `RestoreStatus` of a listed object, if `requested`: archived ones
+/// always read as restored.
+/// The version of `versions` (newest first) that was current at `as_of`,
+/// and whether it still is; `None` if the key did not exist then or its
+/// current version was a delete marker.
+fn version_as_of(
+    versions: Vec<ObjectVersion>,
+    as_of: DateTime<Utc>,
+) -> Option<(ObjectVersion, bool)> {
+    let (idx, version) = versions.into_iter().enumerate().find(|(_, version)| {
+        DateTime::parse_from_rfc3339(version.last_modified()).is_ok_and(|time| time <= as_of)
+    })?;
+    (!version.is_delete_marker()).then_some((version, idx == 0))
+}
+
+fn restore_status_of(attributes: &ObjectAttributes, requested: bool) -> Option<RestoreStatus> {
+    (requested && attributes.is_archived()).then_some(RestoreStatus {
+        is_restore_in_progress: false,
+    })
+}
+
+/// Continuation tokens are opaque to clients; they encode the last key or
+/// common prefix of the previous page.
+pub fn encode_continuation_token(marker: &str) -> String {
+    hex::encode(marker)
+}
+
+pub fn decode_continuation_token(token: &str) -> Result<String, S3Error> {
+    hex::decode(token)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or_else(|| {
+            S3Error::invalid_argument("The continuation token provided is incorrect")
+                .with_resource("ArgumentName", "continuation-token")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+    use crate::metrics::Metrics;
+    use crate::models::{ListObjectsHeaders, S3CommonHeaders};
+
+    const KEYS: [&str; 10] = [
+        "a/1", "a/2", "a/3", "b", "c/1", "c/2", "d", "e f/1", "g+h%", "i/j/k",
+    ];
+
+    fn keys(prefix: &str) -> Vec<String> {
+        KEYS.iter()
+            .filter(|key| key.starts_with(prefix))
+            .map(|key| key.to_string())
+            .collect()
+    }
+
+    /// Every page of a listing, the way clients page: each from where the
+    /// previous one said to continue.
+    fn walk(
+        prefix: &str,
+        delimiter: Option<&str>,
+        start_after: Option<&str>,
+        max_keys: usize,
+    ) -> Vec<Vec<String>> {
+        let keys = keys(prefix);
+        let mut marker = start_after.map(str::to_string);
+        let mut pages = Vec::new();
+        loop {
+            let page = paginate(&keys, prefix, delimiter, marker.as_deref(), max_keys);
+            pages.push(
+                page.entries
+                    .iter()
+                    .map(|e| e.as_str().to_string())
+                    .collect(),
+            );
+            match page.next_marker() {
+                Some(next) => marker = Some(next.to_string()),
+                None => return pages,
+            }
+            assert!(pages.len() <= KEYS.len(), "listing does not end");
+        }
+    }
+
+    #[test]
+    fn common_prefixes_count_toward_max_keys() {
+        assert_eq!(
+            walk("", Some("/"), None, 1),
+            [["a/"], ["b"], ["c/"], ["d"], ["e f/"], ["g+h%"], ["i/"]]
+        );
+        assert_eq!(
+            walk("", Some("/"), None, 3),
+            [vec!["a/", "b", "c/"], vec!["d", "e f/", "g+h%"], vec!["i/"]]
+        );
+        // Grouped under the prefix, the rest of the key is not delimited again.
+        assert_eq!(walk("i/", Some("/"), None, 10), [["i/j/"]]);
+        assert_eq!(walk("c", Some("/"), None, 10), [["c/"]]);
+        assert_eq!(
+            walk("", Some("2"), None, 3),
+            [
+                vec!["a/1", "a/2", "a/3"],
+                vec!["b", "c/1", "c/2"],
+                vec!["d", "e f/1", "g+h%"],
+                vec!["i/j/k"]
+            ]
+        );
+    }
+
+    #[test]
+    fn pages_ending_on_a_common_prefix_resume_after_the_whole_group() {
+        let page = paginate(&keys(""), "", Some("/"), None, 1);
+        assert_eq!(page.entries, [ListEntry::CommonPrefix("a/".to_string())]);
+        assert_eq!(page.next_marker(), Some("a/"));
+        // The next page starts after every key rolled up under `a/`.
+        let page = paginate(&keys(""), "", Some("/"), Some("a/"), 1);
+        assert_eq!(page.entries, [ListEntry::Key("b".to_string())]);
+        // Continuing from a token that ends on a common prefix does the same.
+        let token = encode_continuation_token("c/");
+        let marker = decode_continuation_token(&token).unwrap();
+        let page = paginate(&keys(""), "", Some("/"), Some(&marker), 1);
+        assert_eq!(page.entries, [ListEntry::Key("d".to_string())]);
+    }
+
+    #[test]
+    fn start_after_a_key_inside_a_group_repeats_the_group() {
+        assert_eq!(
+            walk("", Some("/"), Some("a/1"), 10),
+            [["a/", "b", "c/", "d", "e f/", "g+h%", "i/"]]
+        );
+        assert_eq!(walk("a/", Some("/"), Some("a/1"), 1), [["a/2"], ["a/3"]]);
+    }
+
+    #[test]
+    fn pages_are_truncated_only_if_another_entry_follows() {
+        let page = paginate(&keys(""), "", Some("/"), None, 7);
+        assert_eq!(page.entries.len(), 7);
+        assert!(!page.is_truncated);
+        assert_eq!(page.next_marker(), None);
+        let page = paginate(&keys(""), "", None, Some("g+h%"), 1);
+        assert_eq!(page.keys().collect::<Vec<_>>(), ["i/j/k"]);
+        assert!(!page.is_truncated);
+    }
+
+    #[test]
+    fn empty_prefixes_list_every_key() {
+        assert_eq!(
+            walk("", None, None, 4),
+            [
+                vec!["a/1", "a/2", "a/3", "b"],
+                vec!["c/1", "c/2", "d", "e f/1"],
+                vec!["g+h%", "i/j/k"]
+            ]
+        );
+        // An empty delimiter is no delimiter.
+        assert_eq!(walk("", Some(""), None, 10), [KEYS]);
+        assert_eq!(walk("z", Some("/"), None, 10), [Vec::<String>::new()]);
+    }
+
+    #[test]
+    fn max_keys_zero_returns_nothing_and_is_not_truncated() {
+        let page = paginate(&keys(""), "", Some("/"), None, 0);
+        assert!(page.entries.is_empty());
+        assert!(!page.is_truncated);
+        assert_eq!(page.next_marker(), None);
+    }
+
+    #[test]
+    fn start_after_beyond_the_last_key_returns_an_empty_page() {
+        for delimiter in [None, Some("/")] {
+            let page = paginate(&keys(""), "", delimiter, Some("zzz"), 10);
+            assert!(page.entries.is_empty());
+            assert!(!page.is_truncated);
+        }
+        assert_eq!(
+            walk("a/", Some("/"), Some("a/3"), 1),
+            [Vec::<String>::new()]
+        );
+    }
+
+    #[test]
+    fn continuation_tokens_are_opaque_and_checked() {
+        let token = encode_continuation_token("e f/");
+        assert_ne!(token, "e f/");
+        assert_eq!(decode_continuation_token(&token).unwrap(), "e f/");
+        let e = decode_continuation_token("not-a-token").unwrap_err();
+        assert_eq!(e.code, "InvalidArgument");
+        // Hex, but not of a UTF-8 key.
+        assert!(decode_continuation_token("ff").is_err());
+    }
+
+    const STORED: [&str; 7] = ["a/1", "a/b/2", "a/b/3", "b", "c/d/e", "c/x", "d"];
+
+    /// A service over a bucket `foo` holding the [`STORED`] keys.
+    fn service() -> BucketServiceImpl {
+        let config = Config::for_tests();
+        let metrics = Arc::new(Metrics::new());
+        let storage = Storage::new(&config.storage, Arc::new(SystemClock), metrics).unwrap();
+        let metadata = serde_json::json!({ "name": "foo", "created": "2024-01-01T00:00:00.000Z" });
+        storage
+            .create_bucket(&serde_json::from_value(metadata).unwrap())
+            .unwrap();
+        for key in STORED {
+            storage
+                .put_object("foo", key, key.as_bytes(), Default::default())
+                .unwrap();
+        }
+        BucketServiceImpl::new(Arc::new(storage), Arc::new(RwLock::new(config)))
+    }
+
+    fn headers() -> ListObjectsHeaders {
+        ListObjectsHeaders {
+            common: S3CommonHeaders {
+                date: String::new(),
+                host: String::new(),
+                authorization: None,
+            },
+            optional_object_attributes: None,
+        }
+    }
+
+    fn v2_request(delimiter: Option<&str>, max_keys: u32) -> ListObjectsV2Request {
+        ListObjectsV2Request {
+            bucket: "foo".to_string(),
+            prefix: None,
+            delimiter: delimiter.map(str::to_string),
+            start_after: None,
+            continuation_token: None,
+            max_keys: Some(max_keys),
+            fetch_owner: false,
+            headers: headers(),
+        }
+    }
+
+    /// The keys and common prefixes of a V2 page, in order.
+    fn entries(page: &ListObjectsV2Response) -> Vec<String> {
+        let mut entries: Vec<String> = page.contents.iter().map(|o| o.key.clone()).collect();
+        entries.extend(page.common_prefixes.iter().map(|p| p.prefix.clone()));
+        entries.sort();
+        entries
+    }
+
+    #[tokio::test]
+    async fn list_objects_v2_pages_through_every_key_with_continuation_tokens() {
+        let service = service();
+        let mut request = v2_request(None, 3);
+        let mut listed = Vec::new();
+        loop {
+            let page = service.list_objects_v2(request.clone()).await.unwrap();
+            assert_eq!(page.key_count as usize, page.contents.len());
+            assert!(page.key_count <= 3);
+            listed.extend(entries(&page));
+            if !page.is_truncated {
+                assert!(page.next_continuation_token.is_none());
+                break;
+            }
+            request.continuation_token = page.next_continuation_token;
+        }
+        assert_eq!(listed, STORED);
+
+        // start-after skips up to and including the key; a continuation
+        // token takes precedence over it.
+        let mut request = v2_request(None, 2);
+        request.start_after = Some("b".to_string());
+        let page = service.list_objects_v2(request.clone()).await.unwrap();
+        assert_eq!(entries(&page), ["c/d/e", "c/x"]);
+        assert!(page.is_truncated);
+        request.continuation_token = page.next_continuation_token;
+        let page = service.list_objects_v2(request.clone()).await.unwrap();
+        assert_eq!(entries(&page), ["d"]);
+        assert!(!page.is_truncated);
+
+        request.continuation_token = Some("not a token".to_string());
+        let error = service.list_objects_v2(request).await.unwrap_err();
+        assert_eq!(error.downcast::<S3Error>().unwrap().code, "InvalidArgument");
+    }
+
+    #[tokio::test]
+    async fn delimiters_group_nested_keys_counted_against_max_keys() {
+        let service = service();
+        let mut request = v2_request(Some("/"), 1);
+        let mut pages = Vec::new();
+        loop {
+            let page = service.list_objects_v2(request.clone()).await.unwrap();
+            assert_eq!(page.key_count, 1);
+            pages.extend(entries(&page));
+            if !page.is_truncated {
+                break;
+            }
+            request.continuation_token = page.next_continuation_token;
+        }
+        // A common prefix is one entry, never repeated on the next page.
+        assert_eq!(pages, ["a/", "b", "c/", "d"]);
+
+        let mut request = v2_request(Some("/"), 10);
+        request.prefix = Some("a/".to_string());
+        let page = service.list_objects_v2(request).await.unwrap();
+        assert_eq!(entries(&page), ["a/1", "a/b/"]);
+        assert_eq!(page.key_count, 2);
+
+        // ListObjects (V1) groups the same way and continues from NextMarker.
+        let mut request = ListObjectsRequest {
+            bucket: "foo".to_string(),
+            prefix: None,
+            delimiter: Some("/".to_string()),
+            marker: None,
+            max_keys: Some(2),
+            headers: headers(),
+        };
+        let page = service.list_objects(request.clone()).await.unwrap();
+        let prefixes: Vec<_> = page
+            .common_prefixes
+            .iter()
+            .map(|p| p.prefix.as_str())
+            .collect();
+        assert_eq!(prefixes, ["a/"]);
+        assert_eq!(page.contents[0].key, "b");
+        assert_eq!(page.next_marker.as_deref(), Some("b"));
+        request.marker = page.next_marker;
+        let page = service.list_objects(request).await.unwrap();
+        let prefixes: Vec<_> = page
+            .common_prefixes
+            .iter()
+            .map(|p| p.prefix.as_str())
+            .collect();
+        assert_eq!(prefixes, ["c/"]);
+        assert_eq!(page.contents[0].key, "d");
+        assert!(!page.is_truncated);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::clock::Clock;
+use crate::models::BucketDeletion;
+use crate::storage::{Storage, StorageError};
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::watch;
+
+/// Keys deleted between yields to the runtime and progress updates.
+const BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletionState {
+    Pending,
+    Running,
+    Completed,
+    /// The last pass failed; it is retried on the next run.
+    Failed,
+}
+
+/// Progress of a forced bucket deletion, as reported by the admin API.
+#[derive(Debug, Clone, Serialize)]
+pub struct BucketDeletionStatus {
+    #[serde(flatten)]
+    pub deletion: BucketDeletion,
+    pub state: DeletionState,
+    /// Keys found when the current pass started.
+    pub objects_total: Option<u64>,
+    pub objects_deleted: u64,
+    pub finished: Option<String>,
+    pub error: Option<String>,
+}
+
+#[async_trait::async_trait]
+pub trait BucketDeletionService: Send + Sync {
+    /// Queues the removal of `bucket` and everything in it. Requesting a
+    /// bucket that is already queued returns its current status.
+    async fn request(&self, bucket: &str, requested_by: String) -> Result<BucketDeletionStatus>;
+    /// Queued, running and (since the last restart) finished deletions.
+    fn statuses(&self) -> Vec<BucketDeletionStatus>;
+    /// Works through the queue and returns how many buckets were removed.
+    /// Stops between batches once `shutdown` is set; the rest of the queue is
+    /// picked up by a later run.
+    async fn run_pending(&self, shutdown: &watch::Receiver<bool>) -> Result<usize>;
+    /// Removes the tombstones of deleted buckets whose retention has passed
+    /// and returns how many were removed. Stops between buckets once
+    /// `shutdown` is set.
+    async fn purge_expired_tombstones(&self, shutdown: &watch::Receiver<bool>) -> Result<usize>;
+}
+
+/// Queue persisted in the storage's system directory, so deletions resume
+/// after a restart; progress is kept in memory.
+pub struct BucketDeletionServiceImpl {
+    storage: Arc<Storage>,
+    /// Tells when tombstones expire.
+    clock: Arc<dyn Clock>,
+    statuses: RwLock<BTreeMap<String, BucketDeletionStatus>>,
+    /// Serializes updates of the persisted queue.
+    queue: Mutex<()>,
+}
+
+impl BucketDeletionServiceImpl {
+    pub fn new(storage: Arc<Storage>, clock: Arc<dyn Clock>) -> Result<Self> {
+        let pending = storage.bucket_deletions()?;
+        if !pending.is_empty() {
+            info!("Resuming {} forced bucket deletion(s)", pending.len());
+        }
+        let statuses = pending
+            .into_iter()
+            .map(|deletion| (deletion.bucket.clone(), queued(deletion)))
+            .collect();
+        Ok(Self {
+            storage,
+            clock,
+            statuses: RwLock::new(statuses),
+            queue: Mutex::new(()),
+        })
+    }
+
+    fn update(&self, bucket: &str, f: impl FnOnce(&mut BucketDeletionStatus)) {
+        if let Some(status) = self.statuses.write().unwrap().get_mut(bucket) {
+            f(status);
+        }
+    }
+
+    fn dequeue(&self, bucket: &str) -> Result<()> {
+        let _guard = self.queue.lock().unwrap();
+        let mut deletions = self.storage.bucket_deletions()?;
+        deletions.retain(|d| d.bucket != bucket);
+        self.storage.write_bucket_deletions(&deletions)?;
+        Ok(())
+    }
+
+    /// One pass over a bucket: deletes every key, then the bucket itself.
+    /// Returns `false` when interrupted by shutdown.
+    async fn delete(&self, bucket: &str, shutdown: &watch::Receiver<bool>) -> Result<bool> {
+        loop {
+            let keys = match self.storage.versioned_keys(bucket, "") {
+                Ok(keys) => keys,
+                // Already gone, e.g. deleted through the S3 API once empty.
+                Err(StorageError::NoSuchBucket(_)) => return Ok(true),
+                Err(e) => return Err(e.into()),
+            };
+            self.update(bucket, |s| {
+                s.state = DeletionState::Running;
+                s.objects_total = Some(s.objects_deleted + keys.len() as u64);
+                s.error = None;
+            });
+            for batch in keys.chunks(BATCH_SIZE) {
+                if *shutdown.borrow() {
+                    return Ok(false);
+                }
+                for key in batch {
+                    // Every version goes, delete markers included.
+                    for version in self.storage.object_versions(bucket, key)? {
+                        self.storage
+                            .delete_object_version(bucket, key, version.version_id())?;
+                    }
+                }
+                self.update(bucket, |s| s.objects_deleted += batch.len() as u64);
+                tokio::task::yield_now().await;
+            }
+            match self.storage.delete_bucket(bucket) {
+                Ok(()) | Err(StorageError::NoSuchBucket(_)) => return Ok(true),
+                // Written to while we were deleting: go again.
+                Err(StorageError::BucketNotEmpty(_)) => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+fn queued(deletion: BucketDeletion) -> BucketDeletionStatus {
+    BucketDeletionStatus {
+        deletion,
+        state: DeletionState::Pending,
+        objects_total: None,
+        objects_deleted: 0,
+        finished: None,
+        error: None,
+    }
+}
+
+#[async_trait::async_trait]
+impl BucketDeletionService for BucketDeletionServiceImpl {
+    async fn request(&self, bucket: &str, requested_by: String) -> Result<BucketDeletionStatus> {
+        let bucket = self.storage.resolve_bucket(bucket)?;
+        if !self.storage.bucket_exists(&bucket) {
+            return Err(StorageError::NoSuchBucket(bucket).into());
+        }
+        let _guard = self.queue.lock().unwrap();
+        let mut statuses = self.statuses.write().unwrap();
+        if let Some(status) = statuses.get(&bucket)
+            && status.state != DeletionState::Completed
+        {
+            return Ok(status.clone());
+        }
+        let deletion = BucketDeletion {
+            bucket: bucket.clone(),
+            requested: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            requested_by,
+        };
+        let mut deletions = self.storage.bucket_deletions()?;
+        deletions.push(deletion.clone());
+        self.storage.write_bucket_deletions(&deletions)?;
+        info!(
+            "Queued forced deletion of bucket {} by {}",
+            bucket, deletion.requested_by
+        );
+        let status = queued(deletion);
+        statuses.insert(bucket, status.clone());
+        Ok(status)
+    }
+
+    fn statuses(&self) -> Vec<BucketDeletionStatus> {
+        self.statuses.read().unwrap().values().cloned().collect()
+    }
+
+    async fn run_pending(&self, shutdown: &watch::Receiver<bool>) -> Result<usize> {
+        let mut deleted = 0;
+        for deletion in self.storage.bucket_deletions()? {
+            let bucket = &deletion.bucket;
+            // Queued before a restart: the in-memory status is gone.
+            self.statuses
+                .write()
+                .unwrap()
+                .entry(bucket.clone())
+                .or_insert_with(|| queued(deletion.clone()));
+            match self.delete(bucket, shutdown).await {
+                Ok(true) => {
+                    self.dequeue(bucket)?;
+                    self.update(bucket, |s| {
+                        s.state = DeletionState::Completed;
+                        s.finished = Some(Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true));
+                    });
+                    info!("Force-deleted bucket {}", bucket);
+                    deleted += 1;
+                }
+                Ok(false) => {
+                    self.update(bucket, |s| s.state = DeletionState::Pending);
+                    break;
+                }
+                Err(e) => {
+                    warn!("forced deletion of bucket {} failed: {:#}", bucket, e);
+                    self.update(bucket, |s| {
+                        s.state = DeletionState::Failed;
+                        s.error = Some(format!("{:#}", e));
+                    });
+                }
+            }
+        }
+        Ok(deleted)
+    }
+
+    async fn purge_expired_tombstones(&self, shutdown: &watch::Receiver<bool>) -> Result<usize> {
+        let storage = self.storage.clone();
+        tokio::task::spawn_blocking(move || storage.finish_tombstone_purges()).await??;
+        let now = self.clock.now();
+        let mut purged = 0;
+        for tombstone in self.storage.bucket_tombstones()? {
+            if *shutdown.borrow() {
+                break;
+            }
+            // An unreadable expiry is kept rather than guessed at.
+            let expired = DateTime::parse_from_rfc3339(&tombstone.expires)
+                .is_ok_and(|expires| expires <= now);
+            if !expired {
+                continue;
+            }
+            let storage = self.storage.clone();
+            let bucket = tombstone.bucket.clone();
+            let purge = move || storage.purge_bucket_tombstone(&bucket);
+            match tokio::task::spawn_blocking(purge).await? {
+                Ok(()) => purged += 1,
+                // Restored or purged meanwhile.
+                Err(StorageError::NoSuchBucket(_)) => {}
+                Err(e) => warn!(
+                    "purging the tombstone of bucket {} failed: {}",
+                    tombstone.bucket, e
+                ),
+            }
+        }
+        Ok(purged)
+    }
+}
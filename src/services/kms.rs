@@ -0,0 +1,197 @@
+use crate::config::Config;
+use crate::error::S3Error;
+use crate::models::{ERROR_KMS_DISABLED, ERROR_KMS_NOT_FOUND, KmsKey};
+use crate::storage::Storage;
+use anyhow::{Result, bail};
+use axum::http::StatusCode;
+use chrono::{SecondsFormat, Utc};
+use log::info;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+/// Alias of the key used for `aws:kms` requests that name no key, as in AWS.
+pub const DEFAULT_KEY_ALIAS: &str = "aws/s3";
+
+#[async_trait::async_trait]
+pub trait KmsService: Send + Sync {
+    async fn create_key(
+        &self,
+        description: String,
+        alias: Option<String>,
+        created_by: String,
+    ) -> Result<KmsKey>;
+    async fn list_keys(&self) -> Result<Vec<KmsKey>>;
+    /// Returns `None` if there is no such key.
+    async fn set_enabled(&self, key_id: &str, enabled: bool) -> Result<Option<KmsKey>>;
+    /// Resolves the key to record for a new `aws:kms` object and returns its
+    /// ARN. Accepts a key ID, key ARN, `alias/<name>` or alias ARN; with no
+    /// key ID the `aws/s3` key is used, created on first use.
+    async fn key_for_write(&self, key_id: Option<&str>) -> Result<String>;
+    /// Fails with `KMS.DisabledException` when the object's key is disabled
+    /// (or `KMS.NotFoundException` if it is gone), as reads would in AWS.
+    fn check_read(&self, key_arn: &str) -> Result<()>;
+    fn key_arn(&self, key: &KmsKey) -> String;
+}
+
+/// Local key registry emulating SSE-KMS: keys only carry an ID and an
+/// enabled flag, and no data is actually encrypted. Persisted in the
+/// storage's system directory.
+pub struct KmsServiceImpl {
+    storage: Arc<Storage>,
+    config: Arc<RwLock<Config>>,
+    keys: RwLock<Vec<KmsKey>>,
+}
+
+impl KmsServiceImpl {
+    pub fn new(storage: Arc<Storage>, config: Arc<RwLock<Config>>) -> Result<Self> {
+        let keys = storage.kms_keys()?;
+        info!("Loaded {} KMS key(s)", keys.len());
+        Ok(Self {
+            storage,
+            config,
+            keys: RwLock::new(keys),
+        })
+    }
+
+    fn insert(&self, key: KmsKey) -> Result<KmsKey> {
+        let mut keys = self.keys.write().unwrap();
+        if let Some(alias) = &key.alias
+            && keys.iter().any(|k| k.alias.as_ref() == Some(alias))
+        {
+            bail!("alias {} is already in use", alias);
+        }
+        let mut updated = keys.clone();
+        updated.push(key.clone());
+        self.storage.write_kms_keys(&updated)?;
+        *keys = updated;
+        info!(
+            "Created KMS key {} ({}) by {}",
+            key.key_id, key.description, key.created_by
+        );
+        Ok(key)
+    }
+
+    /// Finds a key by any of the identifiers AWS accepts.
+    fn find(&self, key_id: &str) -> Option<KmsKey> {
+        // arn:aws:kms:<region>:<account>:key/<id> or ...:alias/<name>
+        let resource = match key_id.strip_prefix("arn:aws:kms:") {
+            Some(rest) => rest.splitn(3, ':').nth(2).unwrap_or_default(),
+            None => key_id,
+        };
+        let keys = self.keys.read().unwrap();
+        match resource.strip_prefix("alias/") {
+            Some(alias) => keys.iter().find(|k| k.alias.as_deref() == Some(alias)),
+            None => {
+                let id = resource.strip_prefix("key/").unwrap_or(resource);
+                keys.iter().find(|k| k.key_id == id)
+            }
+        }
+        .cloned()
+    }
+}
+
+#[async_trait::async_trait]
+impl KmsService for KmsServiceImpl {
+    async fn create_key(
+        &self,
+        description: String,
+        alias: Option<String>,
+        created_by: String,
+    ) -> Result<KmsKey> {
+        if let Some(alias) = &alias {
+            let valid_char = |c: char| c.is_ascii_alphanumeric() || "/_-".contains(c);
+            if alias.is_empty() || !alias.chars().all(valid_char) {
+                bail!("alias must be non-empty and contain only [A-Za-z0-9/_-]");
+            }
+            if alias.starts_with("aws/") {
+                bail!("aliases starting with aws/ are reserved");
+            }
+        }
+        self.insert(KmsKey {
+            key_id: Uuid::new_v4().to_string(),
+            alias,
+            description,
+            enabled: true,
+            created: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            created_by,
+        })
+    }
+
+    async fn list_keys(&self) -> Result<Vec<KmsKey>> {
+        Ok(self.keys.read().unwrap().clone())
+    }
+
+    async fn set_enabled(&self, key_id: &str, enabled: bool) -> Result<Option<KmsKey>> {
+        let mut keys = self.keys.write().unwrap();
+        let mut updated = keys.clone();
+        let Some(key) = updated.iter_mut().find(|k| k.key_id == key_id) else {
+            return Ok(None);
+        };
+        key.enabled = enabled;
+        let key = key.clone();
+        self.storage.write_kms_keys(&updated)?;
+        *keys = updated;
+        info!(
+            "{} KMS key {}",
+            if enabled { "Enabled" } else { "Disabled" },
+            key_id
+        );
+        Ok(Some(key))
+    }
+
+    async fn key_for_write(&self, key_id: Option<&str>) -> Result<String> {
+        let key = match key_id {
+            Some(key_id) => self.find(key_id).ok_or_else(|| kms_not_found(key_id))?,
+            None => match self.find(&format!("alias/{}", DEFAULT_KEY_ALIAS)) {
+                Some(key) => key,
+                None => self.insert(KmsKey {
+                    key_id: Uuid::new_v4().to_string(),
+                    alias: Some(DEFAULT_KEY_ALIAS.to_string()),
+                    description:
+                        "Default key that protects my S3 objects when no other key is defined"
+                            .to_string(),
+                    enabled: true,
+                    created: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+                    created_by: "system".to_string(),
+                })?,
+            },
+        };
+        let arn = self.key_arn(&key);
+        if !key.enabled {
+            return Err(kms_disabled(&arn).into());
+        }
+        Ok(arn)
+    }
+
+    fn check_read(&self, key_arn: &str) -> Result<()> {
+        match self.find(key_arn) {
+            Some(key) if key.enabled => Ok(()),
+            Some(_) => Err(kms_disabled(key_arn).into()),
+            None => Err(kms_not_found(key_arn).into()),
+        }
+    }
+
+    fn key_arn(&self, key: &KmsKey) -> String {
+        let cfg = self.config.read().unwrap();
+        format!(
+            "arn:aws:kms:{}:{}:key/{}",
+            cfg.region.default, cfg.owner.id, key.key_id
+        )
+    }
+}
+
+fn kms_not_found(key_id: &str) -> S3Error {
+    S3Error::new(
+        StatusCode::BAD_REQUEST,
+        ERROR_KMS_NOT_FOUND,
+        format!("Invalid keyId {}", key_id),
+    )
+}
+
+fn kms_disabled(key_arn: &str) -> S3Error {
+    S3Error::new(
+        StatusCode::BAD_REQUEST,
+        ERROR_KMS_DISABLED,
+        format!("{} is disabled.", key_arn),
+    )
+}
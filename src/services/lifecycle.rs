@@ -0,0 +1,292 @@
+use crate::clock::Clock;
+use crate::models::{
+    LifecycleConfiguration, LifecycleExpiration, LifecycleRule, NULL_VERSION_ID, ObjectVersion,
+};
+use crate::services::notification::{
+    LIFECYCLE_EXPIRATION_DELETE, LIFECYCLE_EXPIRATION_DELETE_MARKER_CREATED, NotificationService,
+    ObjectEvent,
+};
+use crate::storage::{Storage, StorageError, StorageResult};
+use anyhow::Result;
+use chrono::{DateTime, NaiveTime, TimeDelta, Utc};
+use log::{info, warn};
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Keys looked at between yields to the runtime and shutdown checks.
+const BATCH_SIZE: usize = 1000;
+
+/// What one pass over the lifecycle rules did.
+#[derive(Debug, Clone, Default)]
+pub struct LifecycleSweep {
+    /// Current versions removed, or hidden behind a delete marker in
+    /// versioned buckets.
+    pub expired: usize,
+    /// Delete markers removed with `ExpiredObjectDeleteMarker`.
+    pub delete_markers_removed: usize,
+    pub uploads_aborted: usize,
+}
+
+#[async_trait::async_trait]
+pub trait LifecycleService: Send + Sync {
+    /// Applies the enabled rules of every bucket's lifecycle configuration:
+    /// expires the objects and delete markers that are due and aborts
+    /// incomplete multipart uploads. Frozen buckets are left for a later
+    /// pass. Stops early once `shutdown` is set.
+    async fn apply_rules(&self, shutdown: &watch::Receiver<bool>) -> Result<LifecycleSweep>;
+}
+
+/// Enforces lifecycle rules on the filesystem [`Storage`], on the server's
+/// clock, so `--fake-time` moves expiry along.
+pub struct LifecycleServiceImpl {
+    storage: Arc<Storage>,
+    notifications: Arc<dyn NotificationService>,
+    clock: Arc<dyn Clock>,
+}
+
+impl LifecycleServiceImpl {
+    pub fn new(
+        storage: Arc<Storage>,
+        notifications: Arc<dyn NotificationService>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            storage,
+            notifications,
+            clock,
+        }
+    }
+
+    /// Applies `rule` to `bucket`. Returns `false` when interrupted by
+    /// shutdown.
+    async fn apply(
+        &self,
+        bucket: &str,
+        rule: &LifecycleRule,
+        versioned: bool,
+        now: DateTime<Utc>,
+        shutdown: &watch::Receiver<bool>,
+        sweep: &mut LifecycleSweep,
+    ) -> StorageResult<bool> {
+        if let Some(expiration) = &rule.expiration {
+            // Keys whose latest version is a delete marker only have a history.
+            let keys = if versioned {
+                self.storage.versioned_keys(bucket, rule.prefix())?
+            } else {
+                self.storage.list_keys(bucket, rule.prefix())?
+            };
+            for batch in keys.chunks(BATCH_SIZE) {
+                if *shutdown.borrow() {
+                    return Ok(false);
+                }
+                for key in batch {
+                    self.expire(bucket, key, rule, expiration, now, sweep)?;
+                }
+                tokio::task::yield_now().await;
+            }
+        }
+        if let Some(abort) = &rule.abort_incomplete_multipart_upload {
+            for upload in self.storage.multipart_uploads(bucket)? {
+                if !upload.key.starts_with(rule.prefix()) {
+                    continue;
+                }
+                let initiated = match DateTime::parse_from_rfc3339(&upload.initiated) {
+                    Ok(initiated) => initiated.to_utc(),
+                    Err(e) => {
+                        warn!(
+                            "Upload {} has an invalid initiation time: {}",
+                            upload.upload_id, e
+                        );
+                        continue;
+                    }
+                };
+                if now < expires_after(initiated, abort.days_after_initiation) {
+                    continue;
+                }
+                match self
+                    .storage
+                    .abort_multipart_upload(bucket, &upload.key, &upload.upload_id)
+                {
+                    Ok(()) => {
+                        info!(
+                            "Aborted upload {} of {}/{} (lifecycle rule {})",
+                            upload.upload_id,
+                            bucket,
+                            upload.key,
+                            rule_id(rule)
+                        );
+                        sweep.uploads_aborted += 1;
+                    }
+                    // Completed or aborted since it was listed.
+                    Err(StorageError::NoSuchUpload(_)) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Expires the current version of `bucket/key` if `rule` applies to it
+    /// and it is due, or removes its delete marker when that is all that
+    /// is left and the rule asks for it.
+    fn expire(
+        &self,
+        bucket: &str,
+        key: &str,
+        rule: &LifecycleRule,
+        expiration: &LifecycleExpiration,
+        now: DateTime<Utc>,
+        sweep: &mut LifecycleSweep,
+    ) -> StorageResult<()> {
+        let versions = self.storage.object_versions(bucket, key)?;
+        match versions.first() {
+            Some(ObjectVersion::Object { metadata, .. }) => {
+                if !rule.matches(key, metadata.size, &metadata.attributes.tags)
+                    || !due(expiration, &metadata.last_modified, now)
+                {
+                    return Ok(());
+                }
+                let marker = self.storage.delete_object(bucket, key)?;
+                info!(
+                    "Expired {}/{} (lifecycle rule {})",
+                    bucket,
+                    key,
+                    rule_id(rule)
+                );
+                sweep.expired += 1;
+                let (name, version_id) = match &marker {
+                    Some(marker) => (
+                        LIFECYCLE_EXPIRATION_DELETE_MARKER_CREATED,
+                        marker.version_id(),
+                    ),
+                    None => (LIFECYCLE_EXPIRATION_DELETE, metadata.version_id()),
+                };
+                self.publish(name, bucket, key, version_id);
+            }
+            Some(marker @ ObjectVersion::DeleteMarker { .. })
+                if versions.len() == 1 && expiration.expired_object_delete_marker == Some(true) =>
+            {
+                let version_id = marker.version_id();
+                if self
+                    .storage
+                    .delete_object_version(bucket, key, version_id)?
+                    .is_none()
+                {
+                    return Ok(());
+                }
+                info!(
+                    "Removed expired delete marker of {}/{} (lifecycle rule {})",
+                    bucket,
+                    key,
+                    rule_id(rule)
+                );
+                sweep.delete_markers_removed += 1;
+                self.publish(LIFECYCLE_EXPIRATION_DELETE, bucket, key, version_id);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn publish(&self, name: &'static str, bucket: &str, key: &str, version_id: &str) {
+        self.notifications.publish(ObjectEvent {
+            name,
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            version_id: (version_id != NULL_VERSION_ID).then(|| version_id.to_string()),
+            size: None,
+            etag: None,
+        });
+    }
+}
+
+#[async_trait::async_trait]
+impl LifecycleService for LifecycleServiceImpl {
+    async fn apply_rules(&self, shutdown: &watch::Receiver<bool>) -> Result<LifecycleSweep> {
+        let now = self.clock.now();
+        let mut sweep = LifecycleSweep::default();
+        for bucket in self.storage.list_buckets()? {
+            let metadata = match self.storage.bucket_metadata(&bucket) {
+                Ok(metadata) => metadata,
+                Err(StorageError::NoSuchBucket(_)) => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let Some(lifecycle) = metadata.lifecycle else {
+                continue;
+            };
+            let versioned = metadata.versioning.is_some();
+            for rule in lifecycle.rules.iter().filter(|rule| rule.enabled()) {
+                match self
+                    .apply(&bucket, rule, versioned, now, shutdown, &mut sweep)
+                    .await
+                {
+                    Ok(true) => {}
+                    Ok(false) => return Ok(sweep),
+                    // Deleted since it was listed, or left for a pass after
+                    // it is unfrozen.
+                    Err(StorageError::NoSuchBucket(_) | StorageError::BucketFrozen(..)) => break,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        Ok(sweep)
+    }
+}
+
+/// `Date` of an `Expiration`, RFC 3339.
+pub fn expiration_date(date: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(date)
+        .ok()
+        .map(|date| date.to_utc())
+}
+
+/// `days` after `time`, rounded up to the next midnight UTC as S3 does.
+pub fn expires_after(time: DateTime<Utc>, days: u32) -> DateTime<Utc> {
+    let time = time + TimeDelta::days(days.into());
+    let midnight = time.date_naive().and_time(NaiveTime::MIN).and_utc();
+    if midnight == time {
+        time
+    } else {
+        midnight + TimeDelta::days(1)
+    }
+}
+
+/// When an upload of `key` initiated at `initiated` (RFC 3339) is aborted
+/// by the enabled `AbortIncompleteMultipartUpload` rules of `lifecycle`, the
+/// earliest of them, and the ID of that rule. `None` if no rule applies;
+/// the `multipart.expiry_seconds` of the config applies either way.
+pub fn abort_date<'a>(
+    lifecycle: &'a LifecycleConfiguration,
+    key: &str,
+    initiated: &str,
+) -> Option<(DateTime<Utc>, &'a str)> {
+    let initiated = DateTime::parse_from_rfc3339(initiated).ok()?.to_utc();
+    lifecycle
+        .rules
+        .iter()
+        .filter(|rule| rule.enabled() && key.starts_with(rule.prefix()))
+        .filter_map(|rule| {
+            let abort = rule.abort_incomplete_multipart_upload.as_ref()?;
+            Some((
+                expires_after(initiated, abort.days_after_initiation),
+                rule_id(rule),
+            ))
+        })
+        .min_by_key(|(date, _)| *date)
+}
+
+/// Whether an object written at `last_modified` has expired by `now`.
+fn due(expiration: &LifecycleExpiration, last_modified: &str, now: DateTime<Utc>) -> bool {
+    if let Some(date) = expiration.date.as_deref().and_then(expiration_date) {
+        return now >= date;
+    }
+    let (Some(days), Ok(written)) = (expiration.days, DateTime::parse_from_rfc3339(last_modified))
+    else {
+        return false;
+    };
+    now >= expires_after(written.to_utc(), days)
+}
+
+fn rule_id(rule: &LifecycleRule) -> &str {
+    rule.id.as_deref().unwrap_or_default()
+}
@@ -0,0 +1,252 @@
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::metrics::{Metrics, Usage};
+use crate::storage::Storage;
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Counters of [`Metrics::usage`] by bucket and operation.
+type UsageCounters = HashMap<(String, String), Usage>;
+
+/// The usage of one interval between snapshots, as stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    /// End of the interval, RFC 3339.
+    pub time: String,
+    pub interval_secs: u64,
+    /// Buckets and operations with requests in the interval.
+    pub usage: Vec<Usage>,
+}
+
+/// What one snapshot stored and removed.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotRun {
+    /// Buckets and operations with requests since the last snapshot.
+    pub series: usize,
+    /// Days of snapshots removed for being past their retention.
+    pub days_removed: usize,
+}
+
+/// Which stored snapshots to report, and in what steps.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub bucket: Option<String>,
+    pub operation: Option<String>,
+    /// Sums the snapshots into steps of this many seconds, counted from the
+    /// Unix epoch, so `3600` gives hourly and `86400` daily (UTC) points.
+    pub step_secs: Option<u64>,
+}
+
+/// The usage of one snapshot, or one step of them.
+#[derive(Debug, Clone, Serialize)]
+pub struct UsagePoint {
+    /// End of the snapshot's interval, or start of the step.
+    pub time: String,
+    pub requests: u64,
+    pub errors: u64,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+}
+
+/// The usage of one bucket and operation over time.
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageSeries {
+    pub bucket: String,
+    pub operation: String,
+    /// Oldest first; intervals without requests have no point.
+    pub points: Vec<UsagePoint>,
+}
+
+#[async_trait::async_trait]
+pub trait MetricsHistoryService: Send + Sync {
+    /// Stores the usage since the last snapshot, or since the server
+    /// started, and removes snapshots past `metrics.snapshots.retention_days`.
+    /// `None` if `metrics.snapshots.enabled` is off: nothing is stored, and
+    /// the next snapshot starts from now.
+    async fn snapshot(&self) -> Result<Option<SnapshotRun>>;
+    /// The stored usage matching `query`, one series per bucket and
+    /// operation.
+    async fn history(&self, query: &HistoryQuery) -> Result<Vec<UsageSeries>>;
+}
+
+/// Turns the request counters of [`Metrics`] into snapshots of the usage of
+/// each interval, stored per day in the storage's system directory. The
+/// counters start at zero with the server, so the first snapshot after a
+/// restart covers the time since then and nothing is counted twice.
+pub struct MetricsHistoryServiceImpl {
+    storage: Arc<Storage>,
+    config: Arc<RwLock<Config>>,
+    metrics: Arc<Metrics>,
+    clock: Arc<dyn Clock>,
+    /// When the last snapshot was taken and the counters then.
+    last: Mutex<(DateTime<Utc>, UsageCounters)>,
+}
+
+impl MetricsHistoryServiceImpl {
+    pub fn new(
+        storage: Arc<Storage>,
+        config: Arc<RwLock<Config>>,
+        metrics: Arc<Metrics>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let started = clock.now();
+        Self {
+            storage,
+            config,
+            metrics,
+            clock,
+            last: Mutex::new((started, HashMap::new())),
+        }
+    }
+
+    fn snapshots(&self, day: &str) -> Result<Vec<MetricsSnapshot>> {
+        let mut snapshots = Vec::new();
+        for line in self.storage.metrics_snapshots(day)?.lines() {
+            match serde_json::from_str(line) {
+                Ok(snapshot) => snapshots.push(snapshot),
+                Err(e) => warn!("Skipping unreadable metrics snapshot of {}: {}", day, e),
+            }
+        }
+        Ok(snapshots)
+    }
+}
+
+#[async_trait::async_trait]
+impl MetricsHistoryService for MetricsHistoryServiceImpl {
+    async fn snapshot(&self) -> Result<Option<SnapshotRun>> {
+        let now = self.clock.now();
+        let current: UsageCounters = self
+            .metrics
+            .usage()
+            .into_iter()
+            .map(|usage| ((usage.bucket.clone(), usage.operation.clone()), usage))
+            .collect();
+        let snapshots = self.config.read().unwrap().metrics.snapshots.clone();
+        let (since, usage) = {
+            let mut last = self.last.lock().unwrap();
+            let mut usage: Vec<Usage> = current
+                .iter()
+                .map(|(id, usage)| match last.1.get(id) {
+                    Some(before) => Usage {
+                        requests: usage.requests - before.requests,
+                        errors: usage.errors - before.errors,
+                        request_bytes: usage.request_bytes - before.request_bytes,
+                        response_bytes: usage.response_bytes - before.response_bytes,
+                        ..usage.clone()
+                    },
+                    None => usage.clone(),
+                })
+                .filter(|usage| usage.requests > 0)
+                .collect();
+            usage.sort_by(|a, b| (&a.bucket, &a.operation).cmp(&(&b.bucket, &b.operation)));
+            let since = last.0;
+            if !snapshots.enabled {
+                *last = (now, current);
+                return Ok(None);
+            }
+            (since, usage)
+        };
+        let snapshot = MetricsSnapshot {
+            time: now.to_rfc3339_opts(SecondsFormat::Secs, true),
+            interval_secs: (now - since).num_seconds().max(0) as u64,
+            usage,
+        };
+        let day = now.format("%Y-%m-%d").to_string();
+        self.storage
+            .append_metrics_snapshot(&day, &serde_json::to_string(&snapshot)?)?;
+        // Only once stored, so a failed write is covered by the next one.
+        *self.last.lock().unwrap() = (now, current);
+
+        let oldest = (now - TimeDelta::days(snapshots.retention_days.into()))
+            .format("%Y-%m-%d")
+            .to_string();
+        let mut days_removed = 0;
+        for day in self.storage.metrics_snapshot_days()? {
+            if day < oldest {
+                self.storage.remove_metrics_snapshots(&day)?;
+                info!("Removed the metrics snapshots of {}", day);
+                days_removed += 1;
+            }
+        }
+        Ok(Some(SnapshotRun {
+            series: snapshot.usage.len(),
+            days_removed,
+        }))
+    }
+
+    async fn history(&self, query: &HistoryQuery) -> Result<Vec<UsageSeries>> {
+        let day = |time: DateTime<Utc>| time.format("%Y-%m-%d").to_string();
+        let first_day = query.since.map(day);
+        let last_day = query.until.map(day);
+        let mut series: BTreeMap<(String, String), BTreeMap<i64, UsagePoint>> = BTreeMap::new();
+        for day in self.storage.metrics_snapshot_days()? {
+            if first_day.as_ref().is_some_and(|first| day < *first)
+                || last_day.as_ref().is_some_and(|last| day > *last)
+            {
+                continue;
+            }
+            for snapshot in self.snapshots(&day)? {
+                let Ok(time) = DateTime::parse_from_rfc3339(&snapshot.time) else {
+                    continue;
+                };
+                let time = time.to_utc();
+                if query.since.is_some_and(|since| time < since)
+                    || query.until.is_some_and(|until| time > until)
+                {
+                    continue;
+                }
+                let (slot, label) = match query.step_secs {
+                    Some(step) => {
+                        let start = time.timestamp().div_euclid(step as i64) * step as i64;
+                        let label = DateTime::from_timestamp(start, 0)
+                            .unwrap_or_default()
+                            .to_rfc3339_opts(SecondsFormat::Secs, true);
+                        (start, label)
+                    }
+                    None => (time.timestamp(), snapshot.time.clone()),
+                };
+                for usage in snapshot.usage {
+                    if query
+                        .bucket
+                        .as_ref()
+                        .is_some_and(|bucket| usage.bucket != *bucket)
+                        || query.operation.as_ref().is_some_and(|operation| {
+                            !usage.operation.eq_ignore_ascii_case(operation)
+                        })
+                    {
+                        continue;
+                    }
+                    let point = series
+                        .entry((usage.bucket, usage.operation))
+                        .or_default()
+                        .entry(slot)
+                        .or_insert_with(|| UsagePoint {
+                            time: label.clone(),
+                            requests: 0,
+                            errors: 0,
+                            request_bytes: 0,
+                            response_bytes: 0,
+                        });
+                    point.requests += usage.requests;
+                    point.errors += usage.errors;
+                    point.request_bytes += usage.request_bytes;
+                    point.response_bytes += usage.response_bytes;
+                }
+            }
+        }
+        Ok(series
+            .into_iter()
+            .map(|((bucket, operation), points)| UsageSeries {
+                bucket,
+                operation,
+                points: points.into_values().collect(),
+            })
+            .collect())
+    }
+}
@@ -1,4 +1,23 @@
+pub mod auth;
+pub mod auth_provider;
 pub mod bucket;
-pub mod object;
+pub mod bucket_deletion;
+pub mod kms;
+pub mod lifecycle;
+pub mod metrics_history;
 pub mod multipart;
-pub mod auth; 
\ No newline at end of file
+pub mod notification;
+pub mod object;
+pub mod service_account;
+pub mod sts;
+pub mod system_log;
+
+use uuid::Uuid;
+
+/// Random lowercase hex, 32 characters per UUID asked for, for generated
+/// access keys, secrets and session tokens.
+pub(crate) fn random_hex(uuids: usize) -> String {
+    (0..uuids)
+        .map(|_| Uuid::new_v4().simple().to_string())
+        .collect()
+}
@@ -1,32 +1,343 @@
+use crate::checksum::{self, ChecksumAlgorithm};
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::error::S3Error;
+use crate::models::{
+    AbortMultipartUploadRequest, CompleteMultipartUploadRequest, CompletedPart,
+    InitiateMultipartUploadRequest, MultipartUpload, ObjectAttributes, ObjectMetadata, Part,
+    UploadPartRequest,
+};
+use crate::services::kms::KmsService;
+use crate::services::notification::{
+    NotificationService, OBJECT_CREATED_COMPLETE_MULTIPART_UPLOAD, ObjectEvent,
+};
+use crate::services::object::{acl, encryption, stage_body, storage_class};
+use crate::storage::{Storage, StorageError};
 use anyhow::Result;
-use crate::models::{Part, Object};
+use chrono::{DateTime, TimeDelta};
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, RwLock};
+use tokio::sync::watch;
+
+/// Part numbers S3 accepts.
+pub const PART_NUMBERS: RangeInclusive<u32> = 1..=10_000;
+/// Minimum size of every part but the last one of an upload (5 MiB).
+pub const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
 
 #[async_trait::async_trait]
 pub trait MultipartService: Send + Sync {
-    async fn initiate_multipart_upload(&self, bucket: &str, key: &str) -> Result<String>; // returns upload_id
-    async fn upload_part(&self, bucket: &str, key: &str, upload_id: &str, part_number: u32, data: &[u8]) -> Result<Part>;
-    async fn complete_multipart_upload(&self, bucket: &str, key: &str, upload_id: &str, parts: Vec<Part>) -> Result<Object>;
-    async fn abort_multipart_upload(&self, bucket: &str, key: &str, upload_id: &str) -> Result<()>;
+    async fn initiate_multipart_upload(
+        &self,
+        req: InitiateMultipartUploadRequest,
+    ) -> Result<MultipartUpload>;
+    async fn upload_part(&self, req: UploadPartRequest) -> Result<Part>;
+    async fn complete_multipart_upload(
+        &self,
+        req: CompleteMultipartUploadRequest,
+    ) -> Result<ObjectMetadata>;
+    async fn abort_multipart_upload(&self, req: AbortMultipartUploadRequest) -> Result<()>;
+    /// Aborts uploads initiated more than `multipart.expiry_seconds` ago and
+    /// returns how many. Stops early once `shutdown` is set.
+    async fn abort_expired(&self, shutdown: &watch::Receiver<bool>) -> Result<usize>;
+}
+
+/// Multipart uploads on the filesystem [`Storage`]: sessions and parts are
+/// kept in the storage's system directory until the upload is completed
+/// into an object or aborted.
+pub struct MultipartServiceImpl {
+    storage: Arc<Storage>,
+    config: Arc<RwLock<Config>>,
+    kms: Arc<dyn KmsService>,
+    notifications: Arc<dyn NotificationService>,
+    clock: Arc<dyn Clock>,
 }
 
-pub struct MultipartServiceImpl;
+impl MultipartServiceImpl {
+    pub fn new(
+        storage: Arc<Storage>,
+        config: Arc<RwLock<Config>>,
+        kms: Arc<dyn KmsService>,
+        notifications: Arc<dyn NotificationService>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            storage,
+            config,
+            kms,
+            notifications,
+            clock,
+        }
+    }
+
+    /// Writing parts of an SSE-KMS upload uses its key, so parts and the
+    /// completion fail while the key is disabled, as in AWS.
+    fn check_key(&self, upload: &MultipartUpload) -> Result<()> {
+        match &upload.attributes.sse_kms_key_id {
+            Some(arn) => self.kms.check_read(arn),
+            None => Ok(()),
+        }
+    }
+}
 
 #[async_trait::async_trait]
 impl MultipartService for MultipartServiceImpl {
-    async fn initiate_multipart_upload(&self, bucket: &str, key: &str) -> Result<String> {
-        // TODO: Implement initiation logic
-        unimplemented!()
+    async fn initiate_multipart_upload(
+        &self,
+        req: InitiateMultipartUploadRequest,
+    ) -> Result<MultipartUpload> {
+        let requested = req.bucket.clone();
+        let bucket = self
+            .storage
+            .blocking(move |storage| storage.resolve_bucket(&requested))
+            .await?;
+        let storage_class = storage_class(
+            &self.config.read().unwrap(),
+            &bucket,
+            req.headers.storage_class,
+        )?;
+        let (server_side_encryption, sse_kms_key_id) = encryption(
+            self.kms.as_ref(),
+            req.headers.server_side_encryption,
+            req.headers.sse_kms_key_id,
+        )
+        .await?;
+        let attributes = ObjectAttributes {
+            content_type: req.headers.content_type,
+            content_disposition: req.headers.content_disposition,
+            content_encoding: req.headers.content_encoding,
+            content_language: req.headers.content_language,
+            cache_control: req.headers.cache_control,
+            user_metadata: req.headers.user_metadata.into_iter().collect(),
+            tags: req.headers.tagging,
+            server_side_encryption,
+            sse_kms_key_id,
+            appendable: false,
+            storage_class: Some(storage_class),
+            acl: acl(&self.config.read().unwrap(), req.headers.acl),
+            checksum_algorithm: req
+                .headers
+                .checksum_algorithm
+                .map(|algorithm| algorithm.name().to_string()),
+            checksum: None,
+        };
+        let key = req.key;
+        Ok(self
+            .storage
+            .blocking(move |storage| storage.create_multipart_upload(&bucket, &key, attributes))
+            .await?)
     }
-    async fn upload_part(&self, bucket: &str, key: &str, upload_id: &str, part_number: u32, data: &[u8]) -> Result<Part> {
-        // TODO: Implement part upload logic
-        unimplemented!()
+
+    async fn upload_part(&self, req: UploadPartRequest) -> Result<Part> {
+        validate_part_number(req.part_number)?;
+        let (bucket, key, upload_id) = (req.bucket.clone(), req.key.clone(), req.upload_id.clone());
+        let (bucket, upload) = self
+            .storage
+            .blocking(move |storage| -> Result<_> {
+                let bucket = storage.resolve_bucket(&bucket)?;
+                let upload = storage.multipart_upload(&bucket, &key, &upload_id)?;
+                Ok((bucket, upload))
+            })
+            .await?;
+        self.check_key(&upload)?;
+        // As in S3, parts have a checksum of the upload's algorithm, and
+        // only then.
+        let expected = checksum_algorithm(&upload);
+        let actual = req
+            .headers
+            .checksum
+            .as_ref()
+            .map(|checksum| checksum.algorithm);
+        if expected != actual {
+            let name = |algorithm: Option<ChecksumAlgorithm>| match algorithm {
+                Some(algorithm) => algorithm.name().to_ascii_lowercase(),
+                None => "null".to_string(),
+            };
+            return Err(S3Error::invalid_request(format!(
+                "Checksum Type mismatch occurred, expected checksum Type: {}, actual checksum \
+                 Type: {}",
+                name(expected),
+                name(actual)
+            ))
+            .into());
+        }
+        let algorithm = upload.etag_algorithm.unwrap_or_default();
+        let staged = stage_body(&self.storage, algorithm, req.body).await?;
+        let checksum = req.headers.checksum.map(|checksum| checksum.value);
+        let (key, upload_id, part_number) = (req.key, req.upload_id, req.part_number);
+        Ok(self
+            .storage
+            .blocking(move |storage| {
+                storage.put_staged_part(&bucket, &key, &upload_id, part_number, staged, checksum)
+            })
+            .await?)
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        req: CompleteMultipartUploadRequest,
+    ) -> Result<ObjectMetadata> {
+        let (bucket, key, upload_id) = (req.bucket.clone(), req.key.clone(), req.upload_id.clone());
+        let (bucket, mut upload, uploaded) = self
+            .storage
+            .blocking(move |storage| -> Result<_> {
+                let bucket = storage.resolve_bucket(&bucket)?;
+                let upload = storage.multipart_upload(&bucket, &key, &upload_id)?;
+                let uploaded = storage.upload_parts(&bucket, &upload_id)?;
+                Ok((bucket, upload, uploaded))
+            })
+            .await?;
+        self.check_key(&upload)?;
+        let algorithm = checksum_algorithm(&upload);
+        let parts = validate_completed_parts(&req.parts, &uploaded, algorithm)?;
+        // The object gets the composite checksum of its parts.
+        upload.attributes.checksum = algorithm.and_then(|algorithm| {
+            checksum::composite(algorithm, parts.iter().map(|part| part.checksum.as_deref()))
+        });
+        let target = bucket.clone();
+        let metadata = self
+            .storage
+            .blocking(move |storage| storage.complete_multipart_upload(&target, &upload, &parts))
+            .await?;
+        let event = ObjectEvent {
+            name: OBJECT_CREATED_COMPLETE_MULTIPART_UPLOAD,
+            bucket,
+            key: metadata.key.clone(),
+            version_id: metadata.version_id.clone(),
+            size: Some(metadata.size),
+            etag: Some(metadata.etag.clone()),
+        };
+        // Queuing the event's messages writes them to storage.
+        let notifications = self.notifications.clone();
+        self.storage
+            .blocking(move |_| notifications.publish(event))
+            .await;
+        Ok(metadata)
     }
-    async fn complete_multipart_upload(&self, bucket: &str, key: &str, upload_id: &str, parts: Vec<Part>) -> Result<Object> {
-        // TODO: Implement completion logic
-        unimplemented!()
+
+    async fn abort_multipart_upload(&self, req: AbortMultipartUploadRequest) -> Result<()> {
+        let (bucket, key, upload_id) = (req.bucket, req.key, req.upload_id);
+        Ok(self
+            .storage
+            .blocking(move |storage| {
+                let bucket = storage.resolve_bucket(&bucket)?;
+                storage.abort_multipart_upload(&bucket, &key, &upload_id)
+            })
+            .await?)
     }
-    async fn abort_multipart_upload(&self, bucket: &str, key: &str, upload_id: &str) -> Result<()> {
-        // TODO: Implement abort logic
-        unimplemented!()
+
+    async fn abort_expired(&self, shutdown: &watch::Receiver<bool>) -> Result<usize> {
+        // Read on every sweep, so a reloaded expiry applies to the next one.
+        let expiry = self.config.read().unwrap().multipart.expiry_seconds;
+        let cutoff = self.clock.now() - TimeDelta::seconds(expiry as i64);
+        let mut aborted = 0;
+        for bucket in self.storage.list_buckets()? {
+            for upload in self.storage.multipart_uploads(&bucket)? {
+                if *shutdown.borrow() {
+                    return Ok(aborted);
+                }
+                let initiated = match DateTime::parse_from_rfc3339(&upload.initiated) {
+                    Ok(initiated) => initiated,
+                    Err(e) => {
+                        warn!(
+                            "Upload {} has an invalid initiation time: {}",
+                            upload.upload_id, e
+                        );
+                        continue;
+                    }
+                };
+                if initiated > cutoff {
+                    continue;
+                }
+                match self
+                    .storage
+                    .abort_multipart_upload(&bucket, &upload.key, &upload.upload_id)
+                {
+                    Ok(()) => {
+                        info!(
+                            "Aborted upload {} of {}/{}, initiated {}",
+                            upload.upload_id, bucket, upload.key, upload.initiated
+                        );
+                        aborted += 1;
+                    }
+                    // Completed or aborted since it was listed.
+                    Err(StorageError::NoSuchUpload(_)) => {}
+                    // Left for a sweep after the bucket is unfrozen.
+                    Err(StorageError::BucketFrozen(..)) => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        Ok(aborted)
     }
-} 
\ No newline at end of file
+}
+
+/// Checks the `partNumber` of UploadPart (and of a completed part).
+pub fn validate_part_number(part_number: u32) -> Result<(), S3Error> {
+    if PART_NUMBERS.contains(&part_number) {
+        return Ok(());
+    }
+    Err(S3Error::invalid_argument(format!(
+        "Part number must be an integer between {} and {}, inclusive",
+        PART_NUMBERS.start(),
+        PART_NUMBERS.end()
+    ))
+    .with_resource("ArgumentName", "partNumber"))
+}
+
+/// The algorithm of the parts of an upload, as given on initiation.
+fn checksum_algorithm(upload: &MultipartUpload) -> Option<ChecksumAlgorithm> {
+    ChecksumAlgorithm::parse(upload.attributes.checksum_algorithm.as_deref()?)
+}
+
+/// Checks the part list of CompleteMultipartUpload against the uploaded
+/// parts, in the order S3 does: part numbers in range (`InvalidArgument`)
+/// and strictly ascending (`InvalidPartOrder`), every part uploaded with a
+/// matching ETag and, if one is given, checksum of the upload's `algorithm`
+/// (`InvalidPart`), and every part but the last at least [`MIN_PART_SIZE`]
+/// (`EntityTooSmall`). Returns the parts to assemble, in order.
+pub fn validate_completed_parts(
+    requested: &[CompletedPart],
+    uploaded: &BTreeMap<u32, Part>,
+    algorithm: Option<ChecksumAlgorithm>,
+) -> Result<Vec<Part>, S3Error> {
+    if requested.is_empty() {
+        return Err(S3Error::malformed_xml("at least one Part is required"));
+    }
+    for part in requested {
+        validate_part_number(part.part_number)?;
+    }
+    if requested
+        .windows(2)
+        .any(|pair| pair[0].part_number >= pair[1].part_number)
+    {
+        return Err(S3Error::invalid_part_order());
+    }
+    let mut parts = Vec::with_capacity(requested.len());
+    for requested in requested {
+        let checksum_matches = |part: &Part| match &requested.checksum {
+            Some(checksum) => {
+                Some(checksum.algorithm) == algorithm
+                    && part.checksum.as_ref() == Some(&checksum.value)
+            }
+            None => true,
+        };
+        match uploaded.get(&requested.part_number) {
+            // ETags may be sent with or without their quotes.
+            Some(part)
+                if part.etag.trim_matches('"') == requested.etag.trim_matches('"')
+                    && checksum_matches(part) =>
+            {
+                parts.push(part.clone())
+            }
+            _ => return Err(S3Error::invalid_part(requested.part_number)),
+        }
+    }
+    if let Some((_, init)) = parts.split_last()
+        && let Some(small) = init.iter().find(|part| part.size < MIN_PART_SIZE)
+    {
+        return Err(S3Error::entity_too_small(small.part_number));
+    }
+    Ok(parts)
+}
@@ -0,0 +1,754 @@
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::error::S3Error;
+use crate::models::{NotificationConfiguration, NotificationFilter, NotificationRule, QueuedEvent};
+use crate::storage::{EVENTS_DEAD_LETTER, EVENTS_PENDING, Storage};
+use crate::webhook::{self, Endpoint};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{debug, info, warn};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::{Notify, watch};
+use uuid::Uuid;
+
+pub const OBJECT_CREATED_PUT: &str = "s3:ObjectCreated:Put";
+pub const OBJECT_CREATED_COPY: &str = "s3:ObjectCreated:Copy";
+pub const OBJECT_CREATED_COMPLETE_MULTIPART_UPLOAD: &str =
+    "s3:ObjectCreated:CompleteMultipartUpload";
+pub const OBJECT_REMOVED_DELETE: &str = "s3:ObjectRemoved:Delete";
+pub const OBJECT_REMOVED_DELETE_MARKER_CREATED: &str = "s3:ObjectRemoved:DeleteMarkerCreated";
+pub const OBJECT_TAGGING_PUT: &str = "s3:ObjectTagging:Put";
+pub const OBJECT_TAGGING_DELETE: &str = "s3:ObjectTagging:Delete";
+pub const OBJECT_ACL_PUT: &str = "s3:ObjectAcl:Put";
+pub const LIFECYCLE_EXPIRATION_DELETE: &str = "s3:LifecycleExpiration:Delete";
+pub const LIFECYCLE_EXPIRATION_DELETE_MARKER_CREATED: &str =
+    "s3:LifecycleExpiration:DeleteMarkerCreated";
+/// Extension: one of a bucket's configurations was replaced.
+pub const BUCKET_CONFIGURATION_PUT: &str = "s3:BucketConfiguration:Put";
+
+/// Event types a configuration can select, as in S3. `*` selects every
+/// event of its group.
+pub const EVENT_TYPES: &[&str] = &[
+    "s3:ObjectCreated:*",
+    OBJECT_CREATED_PUT,
+    "s3:ObjectCreated:Post",
+    OBJECT_CREATED_COPY,
+    OBJECT_CREATED_COMPLETE_MULTIPART_UPLOAD,
+    "s3:ObjectRemoved:*",
+    OBJECT_REMOVED_DELETE,
+    OBJECT_REMOVED_DELETE_MARKER_CREATED,
+    "s3:ObjectRestore:*",
+    "s3:ObjectRestore:Post",
+    "s3:ObjectRestore:Completed",
+    "s3:ObjectRestore:Delete",
+    "s3:ObjectTagging:*",
+    OBJECT_TAGGING_PUT,
+    OBJECT_TAGGING_DELETE,
+    OBJECT_ACL_PUT,
+    "s3:LifecycleExpiration:*",
+    LIFECYCLE_EXPIRATION_DELETE,
+    LIFECYCLE_EXPIRATION_DELETE_MARKER_CREATED,
+    "s3:LifecycleTransition",
+    "s3:IntelligentTiering",
+    "s3:Replication:*",
+    "s3:Replication:OperationFailedReplication",
+    "s3:Replication:OperationMissedThreshold",
+    "s3:Replication:OperationReplicatedAfterThreshold",
+    "s3:Replication:OperationNotTracked",
+    "s3:ReducedRedundancyLostObject",
+    "s3:BucketConfiguration:*",
+    BUCKET_CONFIGURATION_PUT,
+];
+
+/// S3 limit on filter rule values.
+const MAX_FILTER_VALUE_LENGTH: usize = 1024;
+
+/// Longest the dispatcher sleeps without being woken, so events become due
+/// even if a wake-up is missed.
+const MAX_IDLE: Duration = Duration::from_secs(60);
+
+/// Characters left as-is in the URL-encoded object key of event records.
+const KEY_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'/');
+
+/// Something that happened to an object, to be matched against the
+/// bucket's notification configuration.
+#[derive(Debug, Clone)]
+pub struct ObjectEvent {
+    /// One of the `s3:ObjectCreated:*`, `s3:ObjectRemoved:*`,
+    /// `s3:ObjectTagging:*` and `s3:LifecycleExpiration:*` event types.
+    pub name: &'static str,
+    pub bucket: String,
+    pub key: String,
+    /// `None` for the `null` version.
+    pub version_id: Option<String>,
+    pub size: Option<u64>,
+    pub etag: Option<String>,
+}
+
+/// A replaced bucket configuration (extension), published as
+/// [`BUCKET_CONFIGURATION_PUT`] with the documents before and after.
+#[derive(Debug, Clone)]
+pub struct ConfigurationChange {
+    pub bucket: String,
+    /// The sub-resource, e.g. `notification`.
+    pub configuration: &'static str,
+    /// Access key that made the change; `None` for anonymous requests.
+    pub principal: Option<String>,
+    pub before: String,
+    pub after: String,
+}
+
+#[async_trait::async_trait]
+pub trait NotificationService: Send + Sync {
+    async fn get_configuration(&self, bucket: &str) -> Result<NotificationConfiguration>;
+    /// Validates and stores a bucket's configuration; an empty one turns
+    /// notifications off.
+    async fn put_configuration(
+        &self,
+        bucket: &str,
+        config: NotificationConfiguration,
+    ) -> Result<()>;
+    /// Evaluates an event against its bucket's configuration and queues a
+    /// message for every matching destination. Never fails the operation
+    /// that caused the event. Writes to storage, so request handlers call
+    /// it on the blocking pool (see [`Storage::blocking`]).
+    fn publish(&self, event: ObjectEvent);
+    /// Like [`publish`](Self::publish) for a configuration change. Key
+    /// filters do not apply to these bucket-level events.
+    fn publish_configuration_change(&self, change: &ConfigurationChange);
+    /// Queue depth and delivery counters, as reported by the admin API.
+    fn queue_status(&self) -> NotificationQueueStatus;
+    /// Delivers queued messages as they become due until `shutdown` is set.
+    /// Every message is delivered at least once: it leaves the queue only
+    /// after its target acknowledged it or it ran out of attempts.
+    async fn run_dispatcher(&self, shutdown: watch::Receiver<bool>);
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NotificationQueueStatus {
+    pub pending: usize,
+    pub dead_letter: usize,
+    /// When the oldest pending message was queued.
+    pub oldest_pending: Option<String>,
+    /// Messages delivered since the server started.
+    pub delivered: u64,
+    /// Failed delivery attempts since the server started.
+    pub failed_attempts: u64,
+    pub destinations: BTreeMap<String, DestinationQueueStatus>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DestinationQueueStatus {
+    pub pending: usize,
+    pub dead_letter: usize,
+    /// Error of the most recent failed attempt still pending.
+    pub last_error: Option<String>,
+}
+
+/// In-memory view of the persisted queue.
+#[derive(Default)]
+struct Queue {
+    pending: BTreeMap<u64, QueuedEvent>,
+    dead_letter: BTreeMap<String, usize>,
+    next_seq: u64,
+    delivered: u64,
+    failed_attempts: u64,
+}
+
+/// Configurations are stored in the bucket's metadata; queued messages in
+/// the storage's event queue, so they survive restarts.
+pub struct NotificationServiceImpl {
+    storage: Arc<Storage>,
+    config: Arc<RwLock<Config>>,
+    clock: Arc<dyn Clock>,
+    /// Also serializes writes of the persisted queue.
+    queue: Mutex<Queue>,
+    wake: Notify,
+}
+
+impl NotificationServiceImpl {
+    pub fn new(
+        storage: Arc<Storage>,
+        config: Arc<RwLock<Config>>,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self> {
+        let pending = storage.queued_events(EVENTS_PENDING)?;
+        let dead_letter = storage.queued_events(EVENTS_DEAD_LETTER)?;
+        if !pending.is_empty() {
+            info!("Resuming delivery of {} queued event(s)", pending.len());
+        }
+        let mut queue = Queue {
+            next_seq: pending
+                .iter()
+                .chain(&dead_letter)
+                .map(|e| e.seq + 1)
+                .max()
+                .unwrap_or(1),
+            ..Queue::default()
+        };
+        for event in dead_letter {
+            *queue.dead_letter.entry(event.destination_arn).or_default() += 1;
+        }
+        queue.pending = pending.into_iter().map(|e| (e.seq, e)).collect();
+        Ok(Self {
+            storage,
+            config,
+            clock,
+            queue: Mutex::new(queue),
+            wake: Notify::new(),
+        })
+    }
+
+    fn now(&self) -> String {
+        self.clock
+            .now()
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+    }
+
+    /// IDs and destination ARNs of the configurations selecting `event`.
+    fn destinations(&self, event: &ObjectEvent) -> Result<Vec<(String, String)>> {
+        let metadata = self.storage.bucket_metadata(&event.bucket)?;
+        let Some(config) = metadata.notification else {
+            return Ok(Vec::new());
+        };
+        Ok(config
+            .rules()
+            .into_iter()
+            .filter(|rule| rule_matches(rule, event.name, &event.key))
+            .map(|rule| (rule.id.to_string(), rule.destination_arn.to_string()))
+            .collect())
+    }
+
+    /// Persists one message per destination before making it visible to the
+    /// dispatcher. `message` builds it from the configuration ID, sequence
+    /// number, region and time; `what` describes the event in logs.
+    fn enqueue(
+        &self,
+        what: &str,
+        destinations: Vec<(String, String)>,
+        message: impl Fn(&str, u64, &str, &str) -> serde_json::Value,
+    ) -> Result<()> {
+        let region = self.config.read().unwrap().region.default.clone();
+        let now = self.now();
+        let mut queue = self.queue.lock().unwrap();
+        for (configuration_id, destination_arn) in destinations {
+            let seq = queue.next_seq;
+            let queued = QueuedEvent {
+                seq,
+                destination_arn,
+                message: message(&configuration_id, seq, &region, &now),
+                queued: now.clone(),
+                attempts: 0,
+                next_attempt: now.clone(),
+                last_error: None,
+            };
+            self.storage.write_queued_event(EVENTS_PENDING, &queued)?;
+            debug!("queued {} for {} as #{}", what, queued.destination_arn, seq);
+            queue.next_seq += 1;
+            queue.pending.insert(seq, queued);
+        }
+        Ok(())
+    }
+
+    /// Pending messages due at `now`, in queue order, and how long until the
+    /// next one that is not.
+    fn due(&self, now: DateTime<Utc>) -> (Vec<QueuedEvent>, Duration) {
+        let queue = self.queue.lock().unwrap();
+        let mut due = Vec::new();
+        let mut wait = MAX_IDLE;
+        for event in queue.pending.values() {
+            let next = DateTime::parse_from_rfc3339(&event.next_attempt)
+                .map(|t| t.to_utc())
+                .unwrap_or(now);
+            match (next - now).to_std() {
+                Ok(delay) if !delay.is_zero() => wait = wait.min(delay),
+                _ => due.push(event.clone()),
+            }
+        }
+        (due, wait)
+    }
+
+    async fn deliver(&self, event: &QueuedEvent) -> Result<()> {
+        let (target, timeout) = {
+            let config = self.config.read().unwrap();
+            let notifications = &config.notifications;
+            (
+                notifications
+                    .target(&event.destination_arn)
+                    .map(|t| t.endpoint.clone()),
+                Duration::from_millis(notifications.timeout_ms),
+            )
+        };
+        let target = target.ok_or_else(|| anyhow!("no notification target is configured"))?;
+        let endpoint = Endpoint::parse(&target).map_err(|e| anyhow!(e))?;
+        let body = serde_json::to_vec(&event.message)?;
+        webhook::post_json(&endpoint, &body, timeout).await
+    }
+
+    /// Records the outcome of one delivery attempt: removes the message,
+    /// schedules its retry or moves it to the dead-letter area.
+    fn complete(&self, mut event: QueuedEvent, result: Result<()>) -> Result<()> {
+        let mut queue = self.queue.lock().unwrap();
+        let error = match result {
+            Ok(()) => {
+                self.storage
+                    .remove_queued_event(EVENTS_PENDING, event.seq)?;
+                queue.pending.remove(&event.seq);
+                queue.delivered += 1;
+                debug!("delivered #{} to {}", event.seq, event.destination_arn);
+                return Ok(());
+            }
+            Err(e) => format!("{:#}", e),
+        };
+        queue.failed_attempts += 1;
+        event.attempts += 1;
+        event.last_error = Some(error);
+        let (max_attempts, initial, max) = {
+            let config = self.config.read().unwrap();
+            let n = &config.notifications;
+            (n.max_attempts, n.initial_backoff_ms, n.max_backoff_ms)
+        };
+        let backoff = initial
+            .saturating_mul(1 << (event.attempts - 1).min(32))
+            .min(max);
+        let next = self.clock.now() + chrono::Duration::milliseconds(backoff as i64);
+        event.next_attempt = next.to_rfc3339_opts(SecondsFormat::Millis, true);
+        // Updated in memory before it is persisted, so a failed write still
+        // backs off.
+        queue.pending.insert(event.seq, event.clone());
+        if event.attempts < max_attempts {
+            info!(
+                "delivery of #{} to {} failed (attempt {}), retrying in {} ms: {}",
+                event.seq,
+                event.destination_arn,
+                event.attempts,
+                backoff,
+                event.last_error.as_deref().unwrap_or_default()
+            );
+            self.storage.write_queued_event(EVENTS_PENDING, &event)?;
+            return Ok(());
+        }
+        warn!(
+            "giving up on #{} to {} after {} attempt(s): {}",
+            event.seq,
+            event.destination_arn,
+            event.attempts,
+            event.last_error.as_deref().unwrap_or_default()
+        );
+        self.storage
+            .write_queued_event(EVENTS_DEAD_LETTER, &event)?;
+        self.storage
+            .remove_queued_event(EVENTS_PENDING, event.seq)?;
+        queue.pending.remove(&event.seq);
+        *queue.dead_letter.entry(event.destination_arn).or_default() += 1;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl NotificationService for NotificationServiceImpl {
+    async fn get_configuration(&self, bucket: &str) -> Result<NotificationConfiguration> {
+        let bucket = bucket.to_string();
+        let metadata = self
+            .storage
+            .blocking(move |storage| storage.bucket_metadata(&storage.resolve_bucket(&bucket)?))
+            .await?;
+        Ok(metadata.notification.unwrap_or_default())
+    }
+
+    async fn put_configuration(
+        &self,
+        bucket: &str,
+        config: NotificationConfiguration,
+    ) -> Result<()> {
+        let bucket = bucket.to_string();
+        let bucket = self
+            .storage
+            .blocking(move |storage| storage.resolve_bucket(&bucket))
+            .await?;
+        let config = validate(config)?;
+        validate_destinations(&config, &self.config.read().unwrap())?;
+        let rules = config.rules().len();
+        let target = bucket.clone();
+        self.storage
+            .blocking(move |storage| {
+                let mut metadata = storage.bucket_metadata(&target)?;
+                metadata.notification = (!config.is_empty()).then_some(config);
+                storage.update_bucket_metadata(&metadata)
+            })
+            .await?;
+        info!(
+            "Set {} notification configuration(s) on bucket {}",
+            rules, bucket
+        );
+        Ok(())
+    }
+
+    fn publish(&self, event: ObjectEvent) {
+        match self.destinations(&event) {
+            Ok(destinations) if destinations.is_empty() => {}
+            Ok(destinations) => match self.enqueue(
+                &format!("{} on {}/{}", event.name, event.bucket, event.key),
+                destinations,
+                |configuration_id, seq, region, now| {
+                    message(&event, configuration_id, seq, region, now)
+                },
+            ) {
+                Ok(()) => self.wake.notify_one(),
+                Err(e) => warn!(
+                    "failed to queue {} on {}/{}: {:#}",
+                    event.name, event.bucket, event.key, e
+                ),
+            },
+            Err(e) => warn!(
+                "failed to evaluate notifications for {}/{}: {:#}",
+                event.bucket, event.key, e
+            ),
+        }
+    }
+
+    fn publish_configuration_change(&self, change: &ConfigurationChange) {
+        let what = format!(
+            "{} of {} on {}",
+            BUCKET_CONFIGURATION_PUT, change.configuration, change.bucket
+        );
+        let destinations: Vec<(String, String)> = match self.storage.bucket_metadata(&change.bucket)
+        {
+            Ok(metadata) => metadata
+                .notification
+                .map(|config| {
+                    config
+                        .rules()
+                        .into_iter()
+                        .filter(|rule| {
+                            rule.events
+                                .iter()
+                                .any(|pattern| event_matches(pattern, BUCKET_CONFIGURATION_PUT))
+                        })
+                        .map(|rule| (rule.id.to_string(), rule.destination_arn.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            Err(e) => {
+                warn!("failed to evaluate notifications for {}: {:#}", what, e);
+                return;
+            }
+        };
+        if destinations.is_empty() {
+            return;
+        }
+        match self.enqueue(&what, destinations, |configuration_id, seq, region, now| {
+            configuration_message(change, configuration_id, seq, region, now)
+        }) {
+            Ok(()) => self.wake.notify_one(),
+            Err(e) => warn!("failed to queue {}: {:#}", what, e),
+        }
+    }
+
+    fn queue_status(&self) -> NotificationQueueStatus {
+        let queue = self.queue.lock().unwrap();
+        let mut status = NotificationQueueStatus {
+            pending: queue.pending.len(),
+            dead_letter: queue.dead_letter.values().sum(),
+            oldest_pending: queue.pending.values().next().map(|e| e.queued.clone()),
+            delivered: queue.delivered,
+            failed_attempts: queue.failed_attempts,
+            destinations: BTreeMap::new(),
+        };
+        for event in queue.pending.values() {
+            let destination = status
+                .destinations
+                .entry(event.destination_arn.clone())
+                .or_default();
+            destination.pending += 1;
+            if event.last_error.is_some() {
+                destination.last_error = event.last_error.clone();
+            }
+        }
+        for (arn, count) in &queue.dead_letter {
+            status
+                .destinations
+                .entry(arn.clone())
+                .or_default()
+                .dead_letter = *count;
+        }
+        status
+    }
+
+    async fn run_dispatcher(&self, mut shutdown: watch::Receiver<bool>) {
+        while !*shutdown.borrow() {
+            let (due, wait) = self.due(self.clock.now());
+            if due.is_empty() {
+                tokio::select! {
+                    _ = self.wake.notified() => {}
+                    _ = tokio::time::sleep(wait) => {}
+                    _ = shutdown.changed() => {}
+                }
+                continue;
+            }
+            for event in due {
+                if *shutdown.borrow() {
+                    return;
+                }
+                let result = self.deliver(&event).await;
+                let seq = event.seq;
+                if let Err(e) = self.complete(event, result) {
+                    // The message stays queued and is retried when due.
+                    warn!("failed to update queued event #{}: {:#}", seq, e);
+                }
+            }
+        }
+    }
+}
+
+/// The S3 event message for `event`, as sent to its destination.
+fn message(
+    event: &ObjectEvent,
+    configuration_id: &str,
+    seq: u64,
+    region: &str,
+    time: &str,
+) -> serde_json::Value {
+    let mut object = json!({
+        "key": utf8_percent_encode(&event.key, KEY_ENCODE_SET).to_string(),
+        "sequencer": format!("{:016X}", seq),
+    });
+    if let Some(size) = event.size {
+        object["size"] = size.into();
+    }
+    if let Some(etag) = &event.etag {
+        object["eTag"] = etag.trim_matches('"').into();
+    }
+    if let Some(version_id) = &event.version_id {
+        object["versionId"] = version_id.as_str().into();
+    }
+    json!({
+        "Records": [{
+            "eventVersion": "2.1",
+            "eventSource": "aws:s3",
+            "awsRegion": region,
+            "eventTime": time,
+            "eventName": event.name.trim_start_matches("s3:"),
+            "s3": {
+                "s3SchemaVersion": "1.0",
+                "configurationId": configuration_id,
+                "bucket": {
+                    "name": event.bucket,
+                    "arn": format!("arn:aws:s3:::{}", event.bucket),
+                },
+                "object": object,
+            },
+        }],
+    })
+}
+
+/// The message for a configuration change: an S3 event record without an
+/// `object`, with `userIdentity` and the documents under `configuration`.
+fn configuration_message(
+    change: &ConfigurationChange,
+    configuration_id: &str,
+    seq: u64,
+    region: &str,
+    time: &str,
+) -> serde_json::Value {
+    json!({
+        "Records": [{
+            "eventVersion": "2.1",
+            "eventSource": "aws:s3",
+            "awsRegion": region,
+            "eventTime": time,
+            "eventName": BUCKET_CONFIGURATION_PUT.trim_start_matches("s3:"),
+            "userIdentity": {
+                "principalId": change.principal.as_deref().unwrap_or("anonymous"),
+            },
+            "s3": {
+                "s3SchemaVersion": "1.0",
+                "configurationId": configuration_id,
+                "bucket": {
+                    "name": change.bucket,
+                    "arn": format!("arn:aws:s3:::{}", change.bucket),
+                },
+                "configuration": {
+                    "name": change.configuration,
+                    "before": change.before,
+                    "after": change.after,
+                    "sequencer": format!("{:016X}", seq),
+                },
+            },
+        }],
+    })
+}
+
+/// Like S3, refuses destinations it cannot deliver to: every ARN must be a
+/// configured `notifications.targets` entry.
+fn validate_destinations(
+    config: &NotificationConfiguration,
+    server: &Config,
+) -> Result<(), S3Error> {
+    let unknown: Vec<_> = config
+        .rules()
+        .into_iter()
+        .map(|rule| rule.destination_arn)
+        .filter(|arn| server.notifications.target(arn).is_none())
+        .collect();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    Err(invalid_configuration(
+        "Destination",
+        format!(
+            "Unable to validate the following destination configurations: {}",
+            unknown.join(", ")
+        ),
+    ))
+}
+
+/// Checks a configuration against the S3 rules and assigns IDs to
+/// configurations without one.
+fn validate(mut config: NotificationConfiguration) -> Result<NotificationConfiguration, S3Error> {
+    config.xmlns = None;
+    let ids = config
+        .topic_configurations
+        .iter_mut()
+        .map(|c| &mut c.id)
+        .chain(config.queue_configurations.iter_mut().map(|c| &mut c.id))
+        .chain(
+            config
+                .lambda_function_configurations
+                .iter_mut()
+                .map(|c| &mut c.id),
+        );
+    let mut seen = HashSet::new();
+    for id in ids {
+        let id = id.get_or_insert_with(|| Uuid::new_v4().to_string());
+        if !seen.insert(id.clone()) {
+            return Err(invalid_configuration(
+                "Id",
+                format!("Duplicate notification configuration ID {}", id),
+            ));
+        }
+    }
+
+    let rules = config.rules();
+    for rule in &rules {
+        if !rule.destination_arn.starts_with("arn:") {
+            return Err(invalid_configuration(
+                "Destination",
+                format!("The ARN is not well formed: {}", rule.destination_arn),
+            ));
+        }
+        if rule.events.is_empty() {
+            return Err(invalid_configuration(
+                "Event",
+                "At least one event must be specified",
+            ));
+        }
+        if let Some(event) = rule
+            .events
+            .iter()
+            .find(|event| !EVENT_TYPES.contains(&event.as_str()))
+        {
+            return Err(invalid_configuration(
+                "Event",
+                format!("The event is not supported for notifications: {}", event),
+            ));
+        }
+        if let Some(filter) = rule.filter {
+            validate_filter(filter)?;
+        }
+    }
+    for (i, a) in rules.iter().enumerate() {
+        if rules[i + 1..].iter().any(|b| rules_overlap(a, b)) {
+            return Err(invalid_configuration(
+                "Event",
+                "Configurations overlap. Configurations on the same bucket cannot share a \
+                 common event type.",
+            ));
+        }
+    }
+    Ok(config)
+}
+
+fn validate_filter(filter: &NotificationFilter) -> Result<(), S3Error> {
+    let mut seen = HashSet::new();
+    for rule in &filter.key.rules {
+        let name = rule.name.to_ascii_lowercase();
+        if name != "prefix" && name != "suffix" {
+            return Err(invalid_configuration(
+                "FilterRule",
+                "filter rule name must be either prefix or suffix",
+            ));
+        }
+        if !seen.insert(name.clone()) {
+            return Err(invalid_configuration(
+                "FilterRule",
+                format!("Cannot specify more than one {} rule in a filter.", name),
+            ));
+        }
+        if rule.value.len() > MAX_FILTER_VALUE_LENGTH {
+            return Err(invalid_configuration(
+                "FilterRule",
+                format!(
+                    "The filter rule value must be at most {} characters",
+                    MAX_FILTER_VALUE_LENGTH
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn invalid_configuration(argument: &'static str, message: impl Into<String>) -> S3Error {
+    S3Error::invalid_argument(message).with_resource("ArgumentName", argument)
+}
+
+/// Whether a configured event type (possibly `group:*`) selects `event`.
+fn event_matches(pattern: &str, event: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(group) => event.starts_with(group),
+        None => pattern == event,
+    }
+}
+
+fn filter_value<'a>(filter: Option<&'a NotificationFilter>, name: &str) -> &'a str {
+    filter.and_then(|f| f.rule(name)).unwrap_or_default()
+}
+
+fn rule_matches(rule: &NotificationRule, event: &str, key: &str) -> bool {
+    rule.events
+        .iter()
+        .any(|pattern| event_matches(pattern, event))
+        && key.starts_with(filter_value(rule.filter, "prefix"))
+        && key.ends_with(filter_value(rule.filter, "suffix"))
+}
+
+/// S3 rejects configurations that could both match one event: a shared
+/// event type and prefixes and suffixes that do not rule each other out.
+fn rules_overlap(a: &NotificationRule, b: &NotificationRule) -> bool {
+    let events = a.events.iter().any(|x| {
+        b.events
+            .iter()
+            .any(|y| event_matches(x, y) || event_matches(y, x))
+    });
+    let (prefix_a, prefix_b) = (
+        filter_value(a.filter, "prefix"),
+        filter_value(b.filter, "prefix"),
+    );
+    let (suffix_a, suffix_b) = (
+        filter_value(a.filter, "suffix"),
+        filter_value(b.filter, "suffix"),
+    );
+    events
+        && (prefix_a.starts_with(prefix_b) || prefix_b.starts_with(prefix_a))
+        && (suffix_a.ends_with(suffix_b) || suffix_b.ends_with(suffix_a))
+}
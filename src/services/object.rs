@@ -1,27 +1,846 @@
+use crate::checksum::Checksum;
+use crate::config::Config;
+use crate::error::S3Error;
+use crate::models::{
+    AclGrant, AppendObjectRequest, CopyObjectRequest, CopySourceConditions, DeleteObjectRequest,
+    DeleteObjectResponse, Directive, EtagAlgorithm, GetObjectRequest, NULL_VERSION_ID,
+    ObjectAttributes, ObjectMetadata, ObjectVersion, PutObjectRequest, canned_acl,
+};
+use crate::services::kms::KmsService;
+use crate::services::notification::{
+    NotificationService, OBJECT_ACL_PUT, OBJECT_CREATED_COPY, OBJECT_CREATED_PUT,
+    OBJECT_REMOVED_DELETE, OBJECT_REMOVED_DELETE_MARKER_CREATED, OBJECT_TAGGING_DELETE,
+    OBJECT_TAGGING_PUT, ObjectEvent,
+};
+use crate::storage::{ObjectReader, StagedData, Storage, StorageError, slice_reader};
 use anyhow::Result;
-use crate::models::Object;
+use axum::body::Body;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
 
 #[async_trait::async_trait]
 pub trait ObjectService: Send + Sync {
-    async fn put_object(&self, bucket: &str, key: &str, data: &[u8]) -> Result<Object>;
-    async fn get_object(&self, bucket: &str, key: &str) -> Result<Object>;
-    async fn delete_object(&self, bucket: &str, key: &str) -> Result<()>;
+    async fn put_object(&self, req: PutObjectRequest) -> Result<ObjectMetadata>;
+    async fn append_object(&self, req: AppendObjectRequest) -> Result<ObjectMetadata>;
+    async fn get_object(&self, req: GetObjectRequest) -> Result<(ObjectMetadata, Bytes)>;
+    /// [`get_object`](Self::get_object) reading only the `Range` of the
+    /// request, if any, and as a reader rather than into memory.
+    async fn get_object_range(&self, req: GetObjectRequest) -> Result<ObjectRange>;
+    async fn head_object(&self, req: GetObjectRequest) -> Result<ObjectMetadata>;
+    async fn delete_object(&self, req: DeleteObjectRequest) -> Result<DeleteObjectResponse>;
+    async fn copy_object(&self, req: CopyObjectRequest) -> Result<ObjectMetadata>;
+    async fn get_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<BTreeMap<String, String>>;
+    async fn put_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        tags: BTreeMap<String, String>,
+    ) -> Result<()>;
+    async fn delete_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<()>;
+    /// The grants of the object's ACL besides the owner's; empty for
+    /// `private`.
+    async fn get_object_acl(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<Vec<AclGrant>>;
+    async fn put_object_acl(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        acl: Vec<AclGrant>,
+    ) -> Result<()>;
 }
 
-pub struct ObjectServiceImpl;
+/// An object as read by [`ObjectService::get_object_range`].
+pub struct ObjectRange {
+    pub metadata: ObjectMetadata,
+    /// The inclusive `(first, last)` byte range read; `None` for the whole
+    /// object.
+    pub range: Option<(u64, u64)>,
+    pub data: ObjectReader,
+}
+
+pub struct ObjectServiceImpl {
+    storage: Arc<Storage>,
+    config: Arc<RwLock<Config>>,
+    kms: Arc<dyn KmsService>,
+    notifications: Arc<dyn NotificationService>,
+}
+
+impl ObjectServiceImpl {
+    pub fn new(
+        storage: Arc<Storage>,
+        config: Arc<RwLock<Config>>,
+        kms: Arc<dyn KmsService>,
+        notifications: Arc<dyn NotificationService>,
+    ) -> Self {
+        Self {
+            storage,
+            config,
+            kms,
+            notifications,
+        }
+    }
+
+    /// Publishes `event` on the blocking pool: queuing its messages writes
+    /// them to storage.
+    async fn publish(&self, event: ObjectEvent) {
+        let notifications = self.notifications.clone();
+        self.storage
+            .blocking(move |_| notifications.publish(event))
+            .await
+    }
+
+    async fn created(&self, name: &'static str, bucket: &str, metadata: &ObjectMetadata) {
+        self.publish(ObjectEvent {
+            name,
+            bucket: bucket.to_string(),
+            key: metadata.key.clone(),
+            version_id: metadata.version_id.clone(),
+            size: Some(metadata.size),
+            etag: Some(metadata.etag.clone()),
+        })
+        .await;
+    }
+
+    async fn encryption(
+        &self,
+        sse: Option<String>,
+        key_id: Option<String>,
+    ) -> Result<(Option<String>, Option<String>)> {
+        encryption(self.kms.as_ref(), sse, key_id).await
+    }
+
+    fn storage_class(&self, bucket: &str, requested: Option<String>) -> Result<String, S3Error> {
+        storage_class(&self.config.read().unwrap(), bucket, requested)
+    }
+
+    /// Replaces an object's tags and announces it as `name`.
+    async fn retag(
+        &self,
+        name: &'static str,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        tags: BTreeMap<String, String>,
+    ) -> Result<()> {
+        let (bucket, key) = (bucket.to_string(), key.to_string());
+        let version_id = version_id.map(str::to_string);
+        let (bucket, metadata) = self
+            .storage
+            .blocking(move |storage| -> Result<_> {
+                let bucket = storage.resolve_bucket(&bucket)?;
+                let metadata =
+                    storage.put_object_tags(&bucket, &key, version_id.as_deref(), tags)?;
+                Ok((bucket, metadata))
+            })
+            .await?;
+        self.updated(name, bucket, metadata).await;
+        Ok(())
+    }
+
+    /// Announces a change to an object's tags or ACL as `name`.
+    async fn updated(&self, name: &'static str, bucket: String, metadata: ObjectMetadata) {
+        self.publish(ObjectEvent {
+            name,
+            bucket,
+            key: metadata.key,
+            version_id: metadata.version_id,
+            size: Some(metadata.size),
+            etag: Some(metadata.etag),
+        })
+        .await;
+    }
+
+    /// The metadata of the object, or of one of its versions.
+    async fn object_metadata(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<ObjectMetadata> {
+        let (bucket, key) = (bucket.to_string(), key.to_string());
+        let version_id = version_id.map(str::to_string);
+        self.storage
+            .blocking(move |storage| {
+                let bucket = storage.resolve_bucket(&bucket)?;
+                Ok(match version_id {
+                    Some(version_id) => storage.head_object_version(&bucket, &key, &version_id)?,
+                    None => storage
+                        .head_object(&bucket, &key)
+                        .map_err(|e| not_found(storage, &bucket, &key, e))?,
+                })
+            })
+            .await
+    }
+
+    /// Reads of SSE-KMS objects fail while their key is disabled.
+    fn check_key(&self, metadata: &ObjectMetadata) -> Result<()> {
+        match &metadata.attributes.sse_kms_key_id {
+            Some(arn) => self.kms.check_read(arn),
+            None => Ok(()),
+        }
+    }
+}
 
 #[async_trait::async_trait]
 impl ObjectService for ObjectServiceImpl {
-    async fn put_object(&self, bucket: &str, key: &str, data: &[u8]) -> Result<Object> {
-        // TODO: Implement object upload logic
-        unimplemented!()
+    async fn put_object(&self, req: PutObjectRequest) -> Result<ObjectMetadata> {
+        let bucket = req.bucket.clone();
+        let (bucket, algorithm) = self
+            .storage
+            .blocking(move |storage| -> Result<_> {
+                let bucket = storage.resolve_bucket(&bucket)?;
+                let algorithm = storage.etag_algorithm(&bucket)?;
+                Ok((bucket, algorithm))
+            })
+            .await?;
+        let storage_class = self.storage_class(&bucket, req.headers.storage_class)?;
+        let (server_side_encryption, sse_kms_key_id) = self
+            .encryption(
+                req.headers.server_side_encryption,
+                req.headers.sse_kms_key_id,
+            )
+            .await?;
+        let mut attributes = ObjectAttributes {
+            content_type: req.headers.content_type,
+            content_disposition: req.headers.content_disposition,
+            content_encoding: req.headers.content_encoding,
+            content_language: req.headers.content_language,
+            cache_control: req.headers.cache_control,
+            user_metadata: req.headers.user_metadata.into_iter().collect(),
+            tags: req.headers.tagging,
+            server_side_encryption,
+            sse_kms_key_id,
+            appendable: false,
+            storage_class: Some(storage_class),
+            acl: acl(&self.config.read().unwrap(), req.headers.acl),
+            checksum_algorithm: None,
+            checksum: None,
+        };
+        attributes.set_checksum(req.headers.checksum);
+        let staged = stage_body(&self.storage, algorithm, req.body).await?;
+        let (target, key) = (bucket.clone(), req.key);
+        let metadata = self
+            .storage
+            .blocking(move |storage| storage.put_staged_object(&target, &key, staged, attributes))
+            .await?;
+        self.created(OBJECT_CREATED_PUT, &bucket, &metadata).await;
+        Ok(metadata)
+    }
+
+    async fn append_object(&self, req: AppendObjectRequest) -> Result<ObjectMetadata> {
+        let (bucket, key) = (req.bucket.clone(), req.key.clone());
+        let (bucket, metadata, existing) = self
+            .storage
+            .blocking(move |storage| -> Result<_> {
+                let bucket = storage.resolve_bucket(&bucket)?;
+                let metadata = storage.bucket_metadata(&bucket)?;
+                let existing = match storage.head_object(&bucket, &key) {
+                    Ok(existing) => Some(existing),
+                    Err(StorageError::NoSuchKey(_)) => None,
+                    Err(e) => return Err(e.into()),
+                };
+                Ok((bucket, metadata, existing))
+            })
+            .await?;
+        // An append changes the object in place, which would rewrite the
+        // version it already is.
+        if metadata.versioning.is_some() {
+            return Err(S3Error::invalid_request(
+                "Appendable objects are not supported in buckets with versioning",
+            )
+            .into());
+        }
+        // Appending writes with the object's key, as a new PUT would.
+        if let Some(existing) = &existing {
+            self.check_key(existing)?;
+        }
+        // Only used when the append creates the object.
+        let storage_class = self.storage_class(&bucket, req.headers.storage_class)?;
+        let (server_side_encryption, sse_kms_key_id) = self
+            .encryption(
+                req.headers.server_side_encryption,
+                req.headers.sse_kms_key_id,
+            )
+            .await?;
+        let attributes = ObjectAttributes {
+            content_type: req.headers.content_type,
+            content_disposition: req.headers.content_disposition,
+            content_encoding: req.headers.content_encoding,
+            content_language: req.headers.content_language,
+            cache_control: req.headers.cache_control,
+            user_metadata: req.headers.user_metadata.into_iter().collect(),
+            tags: req.headers.tagging,
+            server_side_encryption,
+            sse_kms_key_id,
+            appendable: true,
+            storage_class: Some(storage_class),
+            acl: acl(&self.config.read().unwrap(), req.headers.acl),
+            // A checksum of what was appended would not be the object's,
+            // whose data keeps changing.
+            checksum_algorithm: None,
+            checksum: None,
+        };
+        let algorithm = metadata.etag_algorithm.unwrap_or_default();
+        let staged = stage_body(&self.storage, algorithm, req.body).await?;
+        let (target, key, position) = (bucket.clone(), req.key, req.position);
+        let metadata = self
+            .storage
+            .blocking(move |storage| {
+                storage.append_object(&target, &key, position, staged, attributes)
+            })
+            .await?;
+        // S3 has no append event; an append is announced like the PUT it is.
+        self.created(OBJECT_CREATED_PUT, &bucket, &metadata).await;
+        Ok(metadata)
+    }
+
+    async fn get_object(&self, req: GetObjectRequest) -> Result<(ObjectMetadata, Bytes)> {
+        let (bucket, key, version_id) = (req.bucket, req.key, req.version_id);
+        let (metadata, data) = self
+            .storage
+            .blocking(move |storage| -> Result<_> {
+                let bucket = storage.resolve_bucket(&bucket)?;
+                Ok(match &version_id {
+                    Some(version_id) => storage.get_object_version(&bucket, &key, version_id)?,
+                    None => storage
+                        .get_object_eventual(&bucket, &key)
+                        .map_err(|e| not_found(storage, &bucket, &key, e))?,
+                })
+            })
+            .await?;
+        self.check_key(&metadata)?;
+        Ok((metadata, data))
+    }
+
+    async fn get_object_range(&self, req: GetObjectRequest) -> Result<ObjectRange> {
+        let requested = req.bucket.clone();
+        let bucket = self
+            .storage
+            .blocking(move |storage| storage.resolve_bucket(&requested))
+            .await?;
+        let resolve = |size| match &req.headers.range {
+            Some(header) => byte_range(header, size),
+            None => Ok(None),
+        };
+        if let Some(version_id) = &req.version_id {
+            let (bucket, key, version_id) = (bucket, req.key.clone(), version_id.clone());
+            let (metadata, data) = self
+                .storage
+                .blocking(move |storage| storage.get_object_version(&bucket, &key, &version_id))
+                .await?;
+            self.check_key(&metadata)?;
+            let range = resolve(metadata.size)?;
+            let (offset, len) = range_span(range, metadata.size);
+            return Ok(ObjectRange {
+                metadata,
+                range,
+                data: slice_reader(data, offset, len),
+            });
+        }
+        loop {
+            let (target, key) = (bucket.clone(), req.key.clone());
+            let head = self
+                .storage
+                .blocking(move |storage| {
+                    storage
+                        .head_object_eventual(&target, &key)
+                        .map_err(|e| not_found(storage, &target, &key, e))
+                })
+                .await?;
+            self.check_key(&head)?;
+            let range = resolve(head.size)?;
+            let (offset, len) = range_span(range, head.size);
+            let (target, key) = (bucket.clone(), req.key.clone());
+            let (metadata, data) = self
+                .storage
+                .blocking(move |storage| {
+                    storage
+                        .get_object_range_eventual(&target, &key, offset, len)
+                        .map_err(|e| not_found(storage, &target, &key, e))
+                })
+                .await?;
+            // Otherwise overwritten in between, and the range resolved
+            // against the size of the replaced object.
+            if metadata.etag == head.etag {
+                return Ok(ObjectRange {
+                    metadata,
+                    range,
+                    data,
+                });
+            }
+        }
+    }
+
+    async fn head_object(&self, req: GetObjectRequest) -> Result<ObjectMetadata> {
+        let (bucket, key, version_id) = (req.bucket, req.key, req.version_id);
+        let metadata = self
+            .storage
+            .blocking(move |storage| -> Result<_> {
+                let bucket = storage.resolve_bucket(&bucket)?;
+                Ok(match &version_id {
+                    Some(version_id) => storage.head_object_version(&bucket, &key, version_id)?,
+                    None => storage
+                        .head_object_eventual(&bucket, &key)
+                        .map_err(|e| not_found(storage, &bucket, &key, e))?,
+                })
+            })
+            .await?;
+        self.check_key(&metadata)?;
+        Ok(metadata)
+    }
+
+    async fn delete_object(&self, req: DeleteObjectRequest) -> Result<DeleteObjectResponse> {
+        let requested = req.bucket.clone();
+        let bucket = self
+            .storage
+            .blocking(move |storage| storage.resolve_bucket(&requested))
+            .await?;
+        if let Some(version_id) = req.version_id {
+            let (target, key, version) = (bucket.clone(), req.key.clone(), version_id.clone());
+            let deleted = self
+                .storage
+                .blocking(move |storage| storage.delete_object_version(&target, &key, &version))
+                .await?;
+            // Deleting a version that does not exist is a no-op, as in S3.
+            let delete_marker = deleted
+                .as_ref()
+                .is_some_and(ObjectVersion::is_delete_marker);
+            if deleted.is_some() {
+                self.publish(ObjectEvent {
+                    name: OBJECT_REMOVED_DELETE,
+                    bucket,
+                    key: req.key,
+                    version_id: (version_id != NULL_VERSION_ID).then(|| version_id.clone()),
+                    size: None,
+                    etag: None,
+                })
+                .await;
+            }
+            return Ok(DeleteObjectResponse {
+                delete_marker,
+                version_id: Some(version_id),
+            });
+        }
+        let (target, key) = (bucket.clone(), req.key.clone());
+        let deleted = self
+            .storage
+            .blocking(move |storage| storage.delete_object(&target, &key))
+            .await;
+        match deleted {
+            Ok(None) => {
+                self.publish(ObjectEvent {
+                    name: OBJECT_REMOVED_DELETE,
+                    bucket,
+                    key: req.key,
+                    version_id: None,
+                    size: None,
+                    etag: None,
+                })
+                .await;
+                Ok(DeleteObjectResponse::default())
+            }
+            Ok(Some(marker)) => {
+                let version_id = marker.version_id().to_string();
+                self.publish(ObjectEvent {
+                    name: OBJECT_REMOVED_DELETE_MARKER_CREATED,
+                    bucket,
+                    key: req.key,
+                    version_id: (version_id != NULL_VERSION_ID).then(|| version_id.clone()),
+                    size: None,
+                    etag: None,
+                })
+                .await;
+                Ok(DeleteObjectResponse {
+                    delete_marker: true,
+                    version_id: Some(version_id),
+                })
+            }
+            // Deleting a missing key succeeds in S3.
+            Err(StorageError::NoSuchKey(_)) => Ok(DeleteObjectResponse::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn copy_object(&self, req: CopyObjectRequest) -> Result<ObjectMetadata> {
+        let (source_bucket, bucket) = (req.source_bucket.clone(), req.bucket.clone());
+        let (source_bucket, bucket) = self
+            .storage
+            .blocking(move |storage| -> Result<_> {
+                Ok((
+                    storage.resolve_bucket(&source_bucket)?,
+                    storage.resolve_bucket(&bucket)?,
+                ))
+            })
+            .await?;
+        let headers = req.headers;
+        if source_bucket == bucket
+            && req.source_key == req.key
+            && headers.metadata_directive == Directive::Copy
+            && headers.server_side_encryption.is_none()
+            && headers.storage_class.is_none()
+        {
+            return Err(S3Error::invalid_request(
+                "This copy request is illegal because it is trying to copy an object to itself \
+                 without changing the object's metadata, storage class, website redirect location \
+                 or encryption attributes.",
+            )
+            .into());
+        }
+
+        let (source_key, version_id) = (req.source_key.clone(), req.source_version_id.clone());
+        let (source, data) = self
+            .storage
+            .blocking(move |storage| -> Result<_> {
+                Ok(match &version_id {
+                    Some(version_id) => {
+                        storage.get_object_version(&source_bucket, &source_key, version_id)?
+                    }
+                    None => storage
+                        .get_object(&source_bucket, &source_key)
+                        .map_err(|e| not_found(storage, &source_bucket, &source_key, e))?,
+                })
+            })
+            .await?;
+        check_copy_source_conditions(&headers.conditions, &source)?;
+        self.check_key(&source)?;
+        // As in S3, a copy does not keep the source's class.
+        let storage_class = self.storage_class(&bucket, headers.storage_class)?;
+        let (server_side_encryption, sse_kms_key_id) = self
+            .encryption(headers.server_side_encryption, headers.sse_kms_key_id)
+            .await?;
+
+        let mut attributes = match headers.metadata_directive {
+            Directive::Copy => source.attributes.clone(),
+            Directive::Replace => ObjectAttributes {
+                content_type: headers.content_type,
+                content_disposition: headers.content_disposition,
+                content_encoding: headers.content_encoding,
+                content_language: headers.content_language,
+                cache_control: headers.cache_control,
+                user_metadata: headers.user_metadata.into_iter().collect(),
+                tags: Default::default(),
+                server_side_encryption: None,
+                sse_kms_key_id: None,
+                appendable: false,
+                storage_class: None,
+                acl: Vec::new(),
+                checksum_algorithm: None,
+                checksum: None,
+            },
+        };
+        // The data is the source's, and so is its checksum, unless another
+        // algorithm is asked for. A composite checksum is computed anew, as
+        // the copy is no multipart upload.
+        let source_checksum = source.attributes.checksum();
+        let algorithm = headers
+            .checksum_algorithm
+            .or(source_checksum.as_ref().map(|checksum| checksum.algorithm));
+        let checksum = match source_checksum {
+            Some(checksum) if Some(checksum.algorithm) == algorithm && !checksum.is_composite() => {
+                Some(checksum)
+            }
+            _ => algorithm.map(|algorithm| Checksum {
+                algorithm,
+                value: algorithm.checksum(&data),
+            }),
+        };
+        attributes.appendable = false;
+        attributes.storage_class = Some(storage_class);
+        attributes.server_side_encryption = server_side_encryption;
+        attributes.sse_kms_key_id = sse_kms_key_id;
+        attributes.tags = match headers.tagging_directive {
+            Directive::Copy => source.attributes.tags,
+            Directive::Replace => headers.tagging,
+        };
+        attributes.acl = acl(&self.config.read().unwrap(), headers.acl);
+        attributes.set_checksum(checksum);
+        let (target, key) = (bucket.clone(), req.key);
+        let metadata = self
+            .storage
+            .blocking(move |storage| storage.put_object(&target, &key, &data, attributes))
+            .await?;
+        self.created(OBJECT_CREATED_COPY, &bucket, &metadata).await;
+        Ok(metadata)
+    }
+
+    async fn get_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<BTreeMap<String, String>> {
+        Ok(self
+            .object_metadata(bucket, key, version_id)
+            .await?
+            .attributes
+            .tags)
+    }
+
+    async fn put_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        tags: BTreeMap<String, String>,
+    ) -> Result<()> {
+        self.retag(OBJECT_TAGGING_PUT, bucket, key, version_id, tags)
+            .await
+    }
+
+    async fn delete_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<()> {
+        self.retag(
+            OBJECT_TAGGING_DELETE,
+            bucket,
+            key,
+            version_id,
+            BTreeMap::new(),
+        )
+        .await
     }
-    async fn get_object(&self, bucket: &str, key: &str) -> Result<Object> {
-        // TODO: Implement object retrieval logic
-        unimplemented!()
+
+    async fn get_object_acl(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<Vec<AclGrant>> {
+        Ok(self
+            .object_metadata(bucket, key, version_id)
+            .await?
+            .attributes
+            .acl)
+    }
+
+    async fn put_object_acl(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        acl: Vec<AclGrant>,
+    ) -> Result<()> {
+        let (bucket, key) = (bucket.to_string(), key.to_string());
+        let version_id = version_id.map(str::to_string);
+        let (bucket, metadata) = self
+            .storage
+            .blocking(move |storage| -> Result<_> {
+                let bucket = storage.resolve_bucket(&bucket)?;
+                let metadata = storage.put_object_acl(&bucket, &key, version_id.as_deref(), acl)?;
+                Ok((bucket, metadata))
+            })
+            .await?;
+        self.updated(OBJECT_ACL_PUT, bucket, metadata).await;
+        Ok(())
     }
-    async fn delete_object(&self, bucket: &str, key: &str) -> Result<()> {
-        // TODO: Implement object deletion logic
-        unimplemented!()
+}
+
+/// Validates the requested server-side encryption and returns the
+/// `(server_side_encryption, sse_kms_key_id)` attributes to record.
+pub async fn encryption(
+    kms: &dyn KmsService,
+    sse: Option<String>,
+    key_id: Option<String>,
+) -> Result<(Option<String>, Option<String>)> {
+    match sse.as_deref() {
+        None | Some("AES256") if key_id.is_some() => Err(S3Error::invalid_argument(
+            "Server Side Encryption with AWS KMS managed key requires HTTP header \
+             x-amz-server-side-encryption : aws:kms",
+        )
+        .into()),
+        None => Ok((None, None)),
+        Some("AES256") => Ok((sse, None)),
+        Some("aws:kms") | Some("aws:kms:dsse") => {
+            let arn = kms.key_for_write(key_id.as_deref()).await?;
+            Ok((sse, Some(arn)))
+        }
+        Some(_) => Err(S3Error::invalid_argument(
+            "The encryption method specified is not supported",
+        )
+        .with_resource("ArgumentName", "x-amz-server-side-encryption")
+        .into()),
+    }
+}
+
+/// Receives an upload `body` into a staging file of `storage`, a piece at a
+/// time as it arrives, with an ETag of `algorithm`. A body failing with an
+/// [`S3Error`] (a chunk signature that does not match, a size limit) fails
+/// with it, one cut short with IncompleteBody.
+pub async fn stage_body(
+    storage: &Arc<Storage>,
+    algorithm: EtagAlgorithm,
+    body: Body,
+) -> Result<StagedData> {
+    let mut staged = storage
+        .blocking(move |storage| storage.stage(algorithm))
+        .await?;
+    let mut chunks = body.into_data_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(S3Error::from)?;
+        staged = storage
+            .blocking(move |_| staged.write(&chunk).map(|()| staged))
+            .await?;
+    }
+    Ok(staged)
+}
+
+/// `e`, unless it is a NoSuchKey for a key whose latest version is a
+/// delete marker, which S3 flags with the marker.
+fn not_found(storage: &Storage, bucket: &str, key: &str, e: StorageError) -> anyhow::Error {
+    if matches!(e, StorageError::NoSuchKey(_))
+        && let Ok(history) = storage.object_history(bucket, key)
+        && let Some(marker @ ObjectVersion::DeleteMarker { .. }) = history.first()
+    {
+        return S3Error::no_such_key(key)
+            .with_delete_marker(marker.version_id())
+            .into();
+    }
+    e.into()
+}
+
+/// The requested storage class if it is allowed, else the bucket's default.
+pub fn storage_class(
+    config: &Config,
+    bucket: &str,
+    requested: Option<String>,
+) -> Result<String, S3Error> {
+    match requested {
+        Some(class) if config.storage_classes.is_allowed(&class) => Ok(class),
+        Some(class) => Err(S3Error::invalid_storage_class(&class)),
+        None => Ok(config.storage_classes.default_for(bucket).to_string()),
     }
-} 
\ No newline at end of file
+}
+
+/// The requested ACL of a new bucket or object, else `public-read` with
+/// `default_acls.public` and `private` without.
+pub fn acl(config: &Config, requested: Option<Vec<AclGrant>>) -> Vec<AclGrant> {
+    requested.unwrap_or_else(|| match config.default_acls.public {
+        true => canned_acl("public-read").unwrap_or_default(),
+        false => Vec::new(),
+    })
+}
+
+/// Evaluates `x-amz-copy-source-if-*` the way S3 does: a matching If-Match
+/// overrides a failing If-Unmodified-Since, and a non-matching If-None-Match
+/// overrides a failing If-Modified-Since.
+fn check_copy_source_conditions(
+    conditions: &CopySourceConditions,
+    source: &ObjectMetadata,
+) -> Result<(), S3Error> {
+    let last_modified = DateTime::parse_from_rfc3339(&source.last_modified)
+        .map(|t| t.with_timezone(&Utc).timestamp())
+        .map_err(|e| S3Error::internal(format!("corrupt object metadata: {}", e)))?;
+
+    match &conditions.if_match {
+        Some(etags) if !etag_matches(etags, &source.etag) => {
+            return Err(S3Error::precondition_failed("x-amz-copy-source-If-Match"));
+        }
+        Some(_) => {}
+        None => {
+            if let Some(since) = &conditions.if_unmodified_since
+                && parse_http_date(since).is_some_and(|since| last_modified > since)
+            {
+                return Err(S3Error::precondition_failed(
+                    "x-amz-copy-source-If-Unmodified-Since",
+                ));
+            }
+        }
+    }
+    match &conditions.if_none_match {
+        Some(etags) if etag_matches(etags, &source.etag) => {
+            return Err(S3Error::precondition_failed(
+                "x-amz-copy-source-If-None-Match",
+            ));
+        }
+        Some(_) => {}
+        None => {
+            if let Some(since) = &conditions.if_modified_since
+                && parse_http_date(since).is_some_and(|since| last_modified <= since)
+            {
+                return Err(S3Error::precondition_failed(
+                    "x-amz-copy-source-If-Modified-Since",
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Resolves a `Range` header against an object of `size` bytes to an
+/// inclusive `(first, last)` byte range. As in S3, only a single range is
+/// supported and anything else (malformed or multiple ranges) is ignored,
+/// serving the whole object.
+pub fn byte_range(header: &str, size: u64) -> Result<Option<(u64, u64)>, S3Error> {
+    let Some((first, last)) = header
+        .trim()
+        .strip_prefix("bytes=")
+        .and_then(|spec| spec.split_once('-'))
+    else {
+        return Ok(None);
+    };
+    let (first, last) = (first.trim(), last.trim());
+    let range = match (first.parse::<u64>(), last.parse::<u64>()) {
+        // bytes=-N: the last N bytes.
+        (Err(_), Ok(suffix)) if first.is_empty() => {
+            if suffix == 0 || size == 0 {
+                return Err(S3Error::invalid_range(size));
+            }
+            (size.saturating_sub(suffix), size - 1)
+        }
+        (Ok(first), Err(_)) if last.is_empty() => (first, size.saturating_sub(1)),
+        (Ok(first), Ok(last)) if first <= last => (first, last.min(size.saturating_sub(1))),
+        _ => return Ok(None),
+    };
+    if range.0 >= size {
+        return Err(S3Error::invalid_range(size));
+    }
+    Ok(Some(range))
+}
+
+/// The offset and length of the bytes of `range`, or of all `size` bytes.
+fn range_span(range: Option<(u64, u64)>, size: u64) -> (u64, u64) {
+    match range {
+        Some((first, last)) => (first, last - first + 1),
+        None => (0, size),
+    }
+}
+
+/// Matches a comma-separated list of (optionally quoted) ETags or `*`.
+pub fn etag_matches(header: &str, etag: &str) -> bool {
+    let etag = etag.trim_matches('"');
+    header
+        .split(',')
+        .map(|candidate| candidate.trim().trim_start_matches("W/").trim_matches('"'))
+        .any(|candidate| candidate == "*" || candidate == etag)
+}
+
+/// Parses an HTTP date (RFC 7231 / RFC 2822) or RFC 3339 timestamp to Unix
+/// seconds. Unparseable dates yield `None` and, as in HTTP, the condition is ignored.
+pub fn parse_http_date(value: &str) -> Option<i64> {
+    DateTime::parse_from_rfc2822(value)
+        .or_else(|_| DateTime::parse_from_rfc3339(value))
+        .map(|t| t.timestamp())
+        .ok()
+}
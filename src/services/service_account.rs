@@ -0,0 +1,216 @@
+use crate::clock::Clock;
+use crate::models::{Permission, ServiceAccount};
+use crate::services::auth::S3_ACTIONS;
+use crate::services::random_hex;
+use crate::storage::{Storage, validate_bucket_name};
+use anyhow::{Result, bail};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use log::info;
+use std::sync::{Arc, RwLock};
+
+/// Actions granted when the request does not list any.
+pub const DEFAULT_ACTIONS: &[&str] = &[
+    "s3:GetObject",
+    "s3:PutObject",
+    "s3:DeleteObject",
+    "s3:ListBucket",
+];
+
+/// Listing actions: granted on the bucket itself, with an `s3:prefix`
+/// condition limiting them to listings within the prefix.
+const BUCKET_ACTIONS: &[&str] = &[
+    "s3:ListBucket",
+    "s3:ListBucketVersions",
+    "s3:ListBucketMultipartUploads",
+];
+
+/// Actions a service account may never get, whatever its scope.
+const FORBIDDEN_ACTIONS: &[&str] = &["s3:ListAllMyBuckets", "s3:CreateBucket", "s3:DeleteBucket"];
+
+/// What to mint: the scope and lifetime of a new service account.
+#[derive(Debug, Clone)]
+pub struct NewServiceAccount {
+    pub name: String,
+    pub bucket: String,
+    pub prefix: String,
+    pub actions: Option<Vec<String>>,
+    pub ttl_seconds: Option<u64>,
+    pub created_by: String,
+}
+
+#[async_trait::async_trait]
+pub trait ServiceAccountService: Send + Sync {
+    /// Mints a credential; the returned value is the only time the secret is shown.
+    async fn create(&self, spec: NewServiceAccount) -> Result<ServiceAccount>;
+    async fn list(&self) -> Result<Vec<ServiceAccount>>;
+    /// Returns false if no such service account exists.
+    async fn delete(&self, access_key: &str) -> Result<bool>;
+    /// Removes expired service accounts and returns how many were removed.
+    async fn purge_expired(&self) -> Result<usize>;
+    /// Looks up a non-expired service account.
+    fn find(&self, access_key: &str) -> Option<ServiceAccount>;
+}
+
+/// Keeps service accounts in memory, writing through to the storage's system
+/// directory so they survive restarts.
+pub struct ServiceAccountServiceImpl {
+    storage: Arc<Storage>,
+    accounts: RwLock<Vec<ServiceAccount>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ServiceAccountServiceImpl {
+    pub fn new(storage: Arc<Storage>, clock: Arc<dyn Clock>) -> Result<Self> {
+        let accounts = storage.service_accounts()?;
+        info!("Loaded {} service account(s)", accounts.len());
+        Ok(Self {
+            storage,
+            accounts: RwLock::new(accounts),
+            clock,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl ServiceAccountService for ServiceAccountServiceImpl {
+    async fn create(&self, spec: NewServiceAccount) -> Result<ServiceAccount> {
+        validate_bucket_name(&spec.bucket)?;
+        if spec.prefix.contains(['*', '?']) {
+            bail!("prefix must not contain '*' or '?'");
+        }
+        let actions = match spec.actions {
+            Some(actions) if actions.is_empty() => bail!("actions must not be empty"),
+            Some(actions) => actions
+                .iter()
+                .map(|action| normalize_action(action))
+                .collect::<Result<Vec<_>>>()?,
+            None => DEFAULT_ACTIONS.iter().map(|a| a.to_string()).collect(),
+        };
+        let now = self.clock.now();
+        let expires = match spec.ttl_seconds {
+            Some(0) => bail!("ttl_seconds must be > 0"),
+            Some(ttl) => {
+                let ttl = i64::try_from(ttl)
+                    .ok()
+                    .and_then(Duration::try_seconds)
+                    .ok_or_else(|| anyhow::anyhow!("ttl_seconds is too large"))?;
+                Some((now + ttl).to_rfc3339_opts(SecondsFormat::Secs, true))
+            }
+            None => None,
+        };
+        let account = ServiceAccount {
+            access_key: format!("SA{}", &random_hex(2)[..18]).to_uppercase(),
+            secret_key: random_hex(2)[..40].to_string(),
+            name: spec.name,
+            bucket: spec.bucket,
+            prefix: spec.prefix,
+            actions,
+            created: now.to_rfc3339_opts(SecondsFormat::Secs, true),
+            expires,
+            created_by: spec.created_by,
+        };
+
+        let mut accounts = self.accounts.write().unwrap();
+        let mut updated = accounts.clone();
+        updated.push(account.clone());
+        self.storage.write_service_accounts(&updated)?;
+        *accounts = updated;
+        info!(
+            "Created service account {} ({}) for {}/{}* by {}",
+            account.access_key, account.name, account.bucket, account.prefix, account.created_by
+        );
+        Ok(account)
+    }
+
+    async fn list(&self) -> Result<Vec<ServiceAccount>> {
+        Ok(self.accounts.read().unwrap().clone())
+    }
+
+    async fn delete(&self, access_key: &str) -> Result<bool> {
+        let mut accounts = self.accounts.write().unwrap();
+        let updated: Vec<ServiceAccount> = accounts
+            .iter()
+            .filter(|a| a.access_key != access_key)
+            .cloned()
+            .collect();
+        if updated.len() == accounts.len() {
+            return Ok(false);
+        }
+        self.storage.write_service_accounts(&updated)?;
+        *accounts = updated;
+        info!("Deleted service account {}", access_key);
+        Ok(true)
+    }
+
+    async fn purge_expired(&self) -> Result<usize> {
+        let now = self.clock.now();
+        let mut accounts = self.accounts.write().unwrap();
+        let updated: Vec<ServiceAccount> = accounts
+            .iter()
+            .filter(|a| !is_expired(a, now))
+            .cloned()
+            .collect();
+        let removed = accounts.len() - updated.len();
+        if removed > 0 {
+            self.storage.write_service_accounts(&updated)?;
+            *accounts = updated;
+        }
+        Ok(removed)
+    }
+
+    fn find(&self, access_key: &str) -> Option<ServiceAccount> {
+        self.accounts
+            .read()
+            .unwrap()
+            .iter()
+            .find(|a| a.access_key == access_key && !is_expired(a, self.clock.now()))
+            .cloned()
+    }
+}
+
+/// The permissions a service account's scope translates to: object actions on
+/// `bucket/prefix*`, listing actions on the bucket for listings within the
+/// prefix.
+pub fn permissions(account: &ServiceAccount) -> Vec<Permission> {
+    account
+        .actions
+        .iter()
+        .map(|action| match BUCKET_ACTIONS.contains(&action.as_str()) {
+            true => Permission {
+                action: action.clone(),
+                resource: account.bucket.clone(),
+                prefix: (!account.prefix.is_empty()).then(|| account.prefix.clone()),
+            },
+            false => Permission {
+                action: action.clone(),
+                resource: format!("{}/{}*", account.bucket, account.prefix),
+                prefix: None,
+            },
+        })
+        .collect()
+}
+
+pub fn is_expired(account: &ServiceAccount, now: DateTime<Utc>) -> bool {
+    account
+        .expires
+        .as_deref()
+        .and_then(|expires| DateTime::parse_from_rfc3339(expires).ok())
+        .is_some_and(|expires| expires <= now)
+}
+
+/// Accepts exact action names (with or without `s3:`); wildcards are not
+/// allowed so the scope stays explicit.
+fn normalize_action(action: &str) -> Result<String> {
+    let action = if action.starts_with("s3:") {
+        action.to_string()
+    } else {
+        format!("s3:{}", action)
+    };
+    if !S3_ACTIONS.contains(&action.as_str()) {
+        bail!("unknown action {}", action);
+    }
+    if FORBIDDEN_ACTIONS.contains(&action.as_str()) {
+        bail!("action {} cannot be granted to a service account", action);
+    }
+    Ok(action)
+}
@@ -0,0 +1,309 @@
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::error::S3Error;
+use crate::models::{
+    AssumeRoleRequest, AssumeRoleResponse, AssumeRoleResult, AssumedRoleUser, Credentials,
+    ERROR_MALFORMED_POLICY_DOCUMENT, ERROR_PACKED_POLICY_TOO_LARGE, ERROR_VALIDATION_ERROR,
+    Permission, ResponseMetadata, S3_ARN_PREFIX, STS_XMLNS, SessionCredentials,
+};
+use crate::services::auth::is_known_action;
+use crate::services::random_hex;
+use anyhow::Result;
+use axum::http::StatusCode;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use log::info;
+use md5::{Digest, Md5};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+pub const DEFAULT_DURATION_SECONDS: u32 = 3600;
+pub const MIN_DURATION_SECONDS: u32 = 900;
+pub const MAX_DURATION_SECONDS: u32 = 43200;
+/// AWS limits the plain-text session policy to 2048 characters.
+pub const MAX_POLICY_LENGTH: usize = 2048;
+
+#[async_trait::async_trait]
+pub trait StsService: Send + Sync {
+    /// Issues temporary credentials for a role in the `roles` config section.
+    /// The caller must already be authorized for `sts:AssumeRole` on the role.
+    async fn assume_role(
+        &self,
+        req: AssumeRoleRequest,
+        request_id: &str,
+    ) -> Result<AssumeRoleResponse>;
+    /// Looks up temporary credentials. Fails with ExpiredToken once the
+    /// session is over; `None` means the key is not a session key.
+    fn find(&self, access_key: &str) -> Result<Option<Credentials>>;
+}
+
+struct Session {
+    secret_key: String,
+    session_token: String,
+    role: String,
+    policy: Option<Vec<Permission>>,
+    expires: DateTime<Utc>,
+}
+
+/// Keeps sessions in memory only: temporary credentials do not survive a
+/// restart. The role's permissions are resolved from the live config on
+/// every request, so role edits apply to running sessions on reload.
+pub struct StsServiceImpl {
+    config: Arc<RwLock<Config>>,
+    sessions: RwLock<HashMap<String, Session>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl StsServiceImpl {
+    pub fn new(config: Arc<RwLock<Config>>, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            config,
+            sessions: RwLock::new(HashMap::new()),
+            clock,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl StsService for StsServiceImpl {
+    async fn assume_role(
+        &self,
+        req: AssumeRoleRequest,
+        request_id: &str,
+    ) -> Result<AssumeRoleResponse> {
+        let role = role_name(&req.role_arn)?;
+        validate_session_name(&req.role_session_name)?;
+        let duration = req.duration_seconds.unwrap_or(DEFAULT_DURATION_SECONDS);
+        if !(MIN_DURATION_SECONDS..=MAX_DURATION_SECONDS).contains(&duration) {
+            return Err(validation_error(format!(
+                "1 validation error detected: Value '{}' at 'durationSeconds' failed to satisfy constraint: Member must have value between {} and {}",
+                duration, MIN_DURATION_SECONDS, MAX_DURATION_SECONDS
+            ))
+            .into());
+        }
+        let policy = req
+            .policy
+            .as_deref()
+            .map(parse_session_policy)
+            .transpose()?;
+        let account = {
+            let cfg = self.config.read().unwrap();
+            if !cfg.roles.contains_key(&role) {
+                // AWS does not reveal whether the role exists.
+                return Err(S3Error::access_denied().into());
+            }
+            cfg.owner.id.clone()
+        };
+
+        let now = self.clock.now();
+        let expires = now + Duration::seconds(i64::from(duration));
+        let access_key = format!("ASIA{}", &random_hex(1)[..16]).to_uppercase();
+        let session = Session {
+            secret_key: random_hex(2)[..40].to_string(),
+            session_token: random_hex(3),
+            role: role.clone(),
+            policy,
+            expires,
+        };
+        let credentials = SessionCredentials {
+            access_key_id: access_key.clone(),
+            secret_access_key: session.secret_key.clone(),
+            session_token: session.session_token.clone(),
+            expiration: expires.to_rfc3339_opts(SecondsFormat::Secs, true),
+        };
+        {
+            let mut sessions = self.sessions.write().unwrap();
+            sessions.retain(|_, s| s.expires > now);
+            sessions.insert(access_key.clone(), session);
+        }
+        info!(
+            "Issued session {} for role {} ({}), expires {}",
+            access_key, role, req.role_session_name, credentials.expiration
+        );
+        Ok(AssumeRoleResponse {
+            xmlns: STS_XMLNS,
+            result: AssumeRoleResult {
+                credentials,
+                assumed_role_user: AssumedRoleUser {
+                    arn: format!(
+                        "arn:aws:sts::{}:assumed-role/{}/{}",
+                        account, role, req.role_session_name
+                    ),
+                    assumed_role_id: format!("{}:{}", role_id(&role), req.role_session_name),
+                },
+            },
+            response_metadata: ResponseMetadata {
+                request_id: request_id.to_string(),
+            },
+        })
+    }
+
+    fn find(&self, access_key: &str) -> Result<Option<Credentials>> {
+        let sessions = self.sessions.read().unwrap();
+        let Some(session) = sessions.get(access_key) else {
+            return Ok(None);
+        };
+        if session.expires <= self.clock.now() {
+            return Err(S3Error::expired_token().into());
+        }
+        let cfg = self.config.read().unwrap();
+        let Some(role) = cfg.roles.get(&session.role) else {
+            // The role was removed from the config since the session began.
+            return Err(S3Error::invalid_token().into());
+        };
+        Ok(Some(Credentials {
+            access_key: access_key.to_string(),
+            secret_key: session.secret_key.clone(),
+            permissions: role
+                .permissions
+                .iter()
+                .map(|p| Permission {
+                    action: p.action.clone(),
+                    resource: p.resource.clone(),
+                    prefix: None,
+                })
+                .collect(),
+            session_token: Some(session.session_token.clone()),
+            session_policy: session.policy.clone(),
+        }))
+    }
+}
+
+/// Extracts the role name from `arn:aws:iam::<account>:role[/path]/<name>`.
+/// The account ID is not checked.
+pub fn role_name(role_arn: &str) -> Result<String, S3Error> {
+    role_arn
+        .strip_prefix("arn:aws:iam::")
+        .and_then(|rest| rest.split_once(":role/"))
+        .and_then(|(_, path)| path.rsplit('/').next())
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| validation_error(format!("{} is invalid", role_arn)))
+}
+
+/// Role session names are 2-64 characters of `[\w+=,.@-]`.
+fn validate_session_name(name: &str) -> Result<(), S3Error> {
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || "_+=,.@-".contains(c);
+    if (2..=64).contains(&name.len()) && name.chars().all(valid_char) {
+        Ok(())
+    } else {
+        Err(validation_error(format!(
+            "1 validation error detected: Value '{}' at 'roleSessionName' failed to satisfy constraint: Member must satisfy regular expression pattern: [\\w+=,.@-]*",
+            name
+        )))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyDocument {
+    #[serde(rename = "Version")]
+    _version: Option<String>,
+    #[serde(rename = "Id")]
+    _id: Option<String>,
+    #[serde(rename = "Statement")]
+    statement: Vec<PolicyStatement>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyStatement {
+    #[serde(rename = "Sid")]
+    _sid: Option<String>,
+    #[serde(rename = "Effect")]
+    effect: String,
+    #[serde(rename = "Action")]
+    action: OneOrMany,
+    #[serde(rename = "Resource")]
+    resource: OneOrMany,
+}
+
+/// Parses an inline session policy into the permission model: every
+/// `Allow` statement grants each of its actions on each of its resources.
+/// `Deny`, `NotAction`, `NotResource` and `Condition` are not supported and
+/// are rejected rather than silently widening the session.
+pub fn parse_session_policy(policy: &str) -> Result<Vec<Permission>, S3Error> {
+    if policy.len() > MAX_POLICY_LENGTH {
+        return Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            ERROR_PACKED_POLICY_TOO_LARGE,
+            format!(
+                "Session policy is {} characters; the limit is {}",
+                policy.len(),
+                MAX_POLICY_LENGTH
+            ),
+        ));
+    }
+    let document: PolicyDocument = serde_json::from_str(policy)
+        .map_err(|e| malformed_policy(format!("Syntax errors in policy: {}", e)))?;
+    let mut permissions = Vec::new();
+    for statement in document.statement {
+        if statement.effect != "Allow" {
+            return Err(malformed_policy(format!(
+                "Unsupported policy effect {:?}; session policies may only Allow",
+                statement.effect
+            )));
+        }
+        let resources: Vec<String> = statement
+            .resource
+            .into_vec()
+            .into_iter()
+            .map(|resource| match resource.as_str() {
+                "*" => Ok(resource),
+                _ => resource
+                    .strip_prefix(S3_ARN_PREFIX)
+                    .map(str::to_string)
+                    .ok_or_else(|| malformed_policy(format!("Invalid resource {}", resource))),
+            })
+            .collect::<Result<_, _>>()?;
+        for action in statement.action.into_vec() {
+            if !is_known_action(&action) {
+                return Err(malformed_policy(format!("Invalid action {}", action)));
+            }
+            for resource in &resources {
+                permissions.push(Permission {
+                    action: action.clone(),
+                    resource: resource.clone(),
+                    prefix: None,
+                });
+            }
+        }
+    }
+    Ok(permissions)
+}
+
+fn malformed_policy(message: String) -> S3Error {
+    S3Error::new(
+        StatusCode::BAD_REQUEST,
+        ERROR_MALFORMED_POLICY_DOCUMENT,
+        message,
+    )
+}
+
+fn validation_error(message: String) -> S3Error {
+    S3Error::new(StatusCode::BAD_REQUEST, ERROR_VALIDATION_ERROR, message)
+}
+
+/// Stable ID of a role, shaped like the AWS `AROA...` role IDs.
+fn role_id(role: &str) -> String {
+    format!(
+        "AROA{}",
+        hex::encode_upper(&Md5::digest(role.as_bytes())[..8])
+    )
+}
@@ -0,0 +1,505 @@
+use crate::config::Config;
+use crate::models::{BucketMetadata, NULL_VERSION_ID, ObjectAttributes, ObjectVersion};
+use crate::storage::{Storage, StorageError};
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
+use log::{info, warn};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::io;
+use std::sync::{Arc, RwLock};
+
+pub const ACCESS_LOG: &str = "access";
+pub const AUDIT_LOG: &str = "audit";
+
+/// One S3 API request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessLogEntry {
+    pub time: String,
+    pub request_id: Option<String>,
+    pub client_ip: Option<String>,
+    /// Access key the request was authenticated with; `None` for anonymous
+    /// and rejected requests.
+    pub requester: Option<String>,
+    pub method: String,
+    pub uri: String,
+    pub bucket: Option<String>,
+    /// The `x-amz-version-id` of the response: the version a write
+    /// created, or the one a read or sub-resource request named.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<String>,
+    pub status: u16,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+    pub duration_ms: u64,
+}
+
+/// One state-changing admin API call.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditLogEntry {
+    pub time: String,
+    pub admin_token: String,
+    pub client_ip: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+}
+
+/// A bucket configuration replaced through the S3 API, recorded in the
+/// audit log with the documents before and after, so drift can be traced.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigurationChangeLogEntry {
+    pub time: String,
+    pub request_id: Option<String>,
+    pub client_ip: Option<String>,
+    pub requester: Option<String>,
+    pub bucket: String,
+    /// The sub-resource, e.g. `notification`.
+    pub configuration: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// One version or delete marker of a key and the request that wrote it.
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionLineage {
+    pub version_id: String,
+    pub delete_marker: bool,
+    pub is_latest: bool,
+    pub last_modified: String,
+    /// `None` for delete markers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// `None` when no access log entry matches, e.g. for versions written
+    /// while `system_logs` was off or expired by a lifecycle rule.
+    pub writer: Option<VersionWriter>,
+}
+
+/// The request that wrote a version, from its access log entry.
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionWriter {
+    /// e.g. `PutObject` or `DeleteObject`.
+    pub operation: String,
+    pub requester: Option<String>,
+    pub client_ip: Option<String>,
+    pub request_id: Option<String>,
+    pub time: String,
+    pub method: String,
+    pub uri: String,
+}
+
+#[async_trait::async_trait]
+pub trait SystemLogService: Send + Sync {
+    /// Whether `system_logs.enabled` is set; nothing is recorded otherwise.
+    fn enabled(&self) -> bool;
+    fn record_access(&self, entry: &AccessLogEntry);
+    fn record_audit(&self, entry: &AuditLogEntry);
+    fn record_configuration_change(&self, entry: &ConfigurationChangeLogEntry);
+    /// Uploads the logs of completed (UTC) days into the system bucket,
+    /// creating it if needed, and returns how many objects were written.
+    async fn publish_completed_days(&self) -> Result<usize>;
+    /// Every version and delete marker of `bucket/key`, newest first, each
+    /// with the request that wrote it, found in the access logs of its day,
+    /// published or still staged.
+    async fn lineage(&self, bucket: &str, key: &str) -> Result<Vec<VersionLineage>>;
+}
+
+/// Stages log lines in the storage's system directory, one file per kind
+/// and day, until [`SystemLogService::publish_completed_days`] turns them
+/// into `<kind>/<YYYY-MM-DD>.log` objects. Recording never fails a request:
+/// write errors are only logged.
+pub struct SystemLogServiceImpl {
+    storage: Arc<Storage>,
+    config: Arc<RwLock<Config>>,
+}
+
+impl SystemLogServiceImpl {
+    pub fn new(storage: Arc<Storage>, config: Arc<RwLock<Config>>) -> Self {
+        Self { storage, config }
+    }
+
+    fn record(&self, kind: &str, time: &str, entry: &impl Serialize) {
+        if !self.enabled() {
+            return;
+        }
+        let line = match serde_json::to_string(entry) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("failed to serialize {} log entry: {}", kind, e);
+                return;
+            }
+        };
+        // RFC 3339 timestamps start with the day.
+        let day = time.get(..10).unwrap_or_default();
+        if let Err(e) = self.storage.append_system_log(kind, day, &line) {
+            warn!("failed to write {} log: {}", kind, e);
+        }
+    }
+}
+
+/// The operation of a successful request that creates a version of `key`
+/// in `bucket`, named as in the S3 API; `None` for any other request.
+fn write_operation(entry: &AccessLogEntry, bucket: &str, key: &str) -> Option<&'static str> {
+    if entry.bucket.as_deref() != Some(bucket) || !(200..300).contains(&entry.status) {
+        return None;
+    }
+    let (path, query) = entry.uri.split_once('?').unwrap_or((&entry.uri, ""));
+    let path = percent_decode_str(path).decode_utf8_lossy();
+    let object = path
+        .trim_start_matches('/')
+        .split_once('/')
+        .map(|(_, object)| object)
+        .filter(|object| !object.is_empty());
+    let params: BTreeSet<&str> = query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| param.split_once('=').map_or(param, |(name, _)| name))
+        .collect();
+    let only = |name: &str| params.len() == 1 && params.contains(name);
+    match (entry.method.as_str(), object) {
+        ("PUT", Some(object)) if object == key && params.is_empty() => Some("PutObject"),
+        ("PUT", Some(object)) if object == key && only("append") => Some("AppendObject"),
+        ("POST", Some(object)) if object == key && only("uploadId") => {
+            Some("CompleteMultipartUpload")
+        }
+        ("DELETE", Some(object)) if object == key && params.is_empty() => Some("DeleteObject"),
+        // Form uploads and multi-object deletes name the key in the body.
+        ("POST", None) if params.is_empty() => Some("PostObject"),
+        ("POST", None) if only("delete") => Some("DeleteObjects"),
+        _ => None,
+    }
+}
+
+/// Whether the request of `entry` was in progress at `time`: versions are
+/// stamped while the request writing them runs.
+fn in_progress_at(entry: &AccessLogEntry, time: DateTime<Utc>) -> bool {
+    let Ok(start) = DateTime::parse_from_rfc3339(&entry.time) else {
+        return false;
+    };
+    let start = start.to_utc();
+    // Both ends are truncated to milliseconds.
+    let end = start + TimeDelta::milliseconds(entry.duration_ms as i64 + 1);
+    start <= time && time <= end
+}
+
+#[async_trait::async_trait]
+impl SystemLogService for SystemLogServiceImpl {
+    fn enabled(&self) -> bool {
+        self.config.read().unwrap().system_logs.enabled
+    }
+
+    fn record_access(&self, entry: &AccessLogEntry) {
+        self.record(ACCESS_LOG, &entry.time, entry);
+    }
+
+    fn record_audit(&self, entry: &AuditLogEntry) {
+        self.record(AUDIT_LOG, &entry.time, entry);
+    }
+
+    fn record_configuration_change(&self, entry: &ConfigurationChangeLogEntry) {
+        self.record(AUDIT_LOG, &entry.time, entry);
+    }
+
+    async fn publish_completed_days(&self) -> Result<usize> {
+        let bucket = self.config.read().unwrap().system_logs.bucket.clone();
+        let today = Utc::now().format("%Y-%m-%d").to_string();
+        let mut published = 0;
+        for (kind, day) in self.storage.system_logs()? {
+            if day >= today {
+                continue;
+            }
+            if !self.storage.bucket_exists(&bucket) {
+                let metadata = BucketMetadata {
+                    name: bucket.clone(),
+                    region: None,
+                    created: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+                    created_by: None,
+                    notification: None,
+                    tags: Default::default(),
+                    versioning: None,
+                    lifecycle: None,
+                    policy: None,
+                    acl: Vec::new(),
+                    cors: None,
+                    etag_algorithm: None,
+                };
+                match self.storage.create_bucket(&metadata) {
+                    Ok(()) => info!("Created system log bucket {}", bucket),
+                    Err(StorageError::BucketAlreadyExists(_)) => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            let data = self.storage.system_log(&kind, &day)?;
+            let attributes = ObjectAttributes {
+                content_type: Some("application/x-ndjson".to_string()),
+                ..Default::default()
+            };
+            let key = format!("{}/{}.log", kind, day);
+            // Publishing again after a crash rewrites the same object.
+            self.storage.put_object(&bucket, &key, &data, attributes)?;
+            self.storage.remove_system_log(&kind, &day)?;
+            info!("Published {} log for {} to {}/{}", kind, day, bucket, key);
+            published += 1;
+        }
+        Ok(published)
+    }
+
+    async fn lineage(&self, bucket: &str, key: &str) -> Result<Vec<VersionLineage>> {
+        let log_bucket = self.config.read().unwrap().system_logs.bucket.clone();
+        let (bucket, key) = (bucket.to_string(), key.to_string());
+        self.storage
+            .blocking(move |storage| version_lineage(storage, &log_bucket, &bucket, &key))
+            .await
+    }
+}
+
+/// The access log entries of `day`: those published to the system bucket
+/// `log_bucket`, then those still staged.
+fn access_entries(storage: &Storage, log_bucket: &str, day: &str) -> Result<Vec<AccessLogEntry>> {
+    let key = format!("{}/{}.log", ACCESS_LOG, day);
+    let mut data = match storage.get_object(log_bucket, &key) {
+        Ok((_, data)) => data.to_vec(),
+        Err(StorageError::NoSuchBucket(_) | StorageError::NoSuchKey(_)) => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    match storage.system_log(ACCESS_LOG, day) {
+        Ok(staged) => data.extend(staged),
+        Err(StorageError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let mut entries = Vec::new();
+    for line in String::from_utf8_lossy(&data).lines() {
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => warn!("Skipping unreadable access log entry of {}: {}", day, e),
+        }
+    }
+    Ok(entries)
+}
+
+/// [`SystemLogService::lineage`], from the access logs published to
+/// `log_bucket` and staged in `storage`.
+fn version_lineage(
+    storage: &Storage,
+    log_bucket: &str,
+    bucket: &str,
+    key: &str,
+) -> Result<Vec<VersionLineage>> {
+    let versions = storage.object_versions(bucket, key)?;
+    if versions.is_empty() {
+        return Err(StorageError::NoSuchKey(key.to_string()).into());
+    }
+    let mut days = BTreeSet::new();
+    for version in &versions {
+        if let Ok(time) = DateTime::parse_from_rfc3339(version.last_modified()) {
+            // The request may have started the day before.
+            let time = time.to_utc();
+            days.insert(time.format("%Y-%m-%d").to_string());
+            days.insert((time - TimeDelta::days(1)).format("%Y-%m-%d").to_string());
+        }
+    }
+    let mut entries = Vec::new();
+    for day in &days {
+        entries.extend(
+            access_entries(storage, log_bucket, day)?
+                .into_iter()
+                .filter_map(|entry| Some((write_operation(&entry, bucket, key)?, entry))),
+        );
+    }
+
+    let mut lineage = Vec::new();
+    // Newest first, so the first is the latest.
+    for (index, version) in versions.into_iter().enumerate() {
+        let version_id = version.version_id().to_string();
+        let last_modified = version.last_modified().to_string();
+        let time = DateTime::parse_from_rfc3339(&last_modified).map(|t| t.to_utc());
+        // Entries are logged as requests finish, so of overlapping
+        // writes the last one logged stored the version.
+        let writer = entries
+            .iter()
+            .rev()
+            .find(|(_, entry)| match entry.version_id.as_deref() {
+                Some(id) if id != NULL_VERSION_ID => id == version_id,
+                // The `null` version and multi-object deletes carry no
+                // unique ID: they are matched to the write running when
+                // the version was stamped.
+                _ => time.is_ok_and(|time| in_progress_at(entry, time)),
+            })
+            .map(|(operation, entry)| VersionWriter {
+                operation: operation.to_string(),
+                requester: entry.requester.clone(),
+                client_ip: entry.client_ip.clone(),
+                request_id: entry.request_id.clone(),
+                time: entry.time.clone(),
+                method: entry.method.clone(),
+                uri: entry.uri.clone(),
+            });
+        let (etag, size) = match &version {
+            ObjectVersion::Object { metadata, .. } => {
+                (Some(metadata.etag.clone()), Some(metadata.size))
+            }
+            ObjectVersion::DeleteMarker { .. } => (None, None),
+        };
+        lineage.push(VersionLineage {
+            version_id,
+            delete_marker: version.is_delete_marker(),
+            is_latest: index == 0,
+            last_modified,
+            etag,
+            size,
+            writer,
+        });
+    }
+    Ok(lineage)
+}
+
+/// Current time as used in log entries.
+pub fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use crate::metrics::Metrics;
+
+    /// A service logging into a storage of its own.
+    fn service() -> SystemLogServiceImpl {
+        let mut config = Config::for_tests();
+        config.system_logs.enabled = true;
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let metrics = Arc::new(Metrics::new());
+        let storage = Arc::new(Storage::new(&config.storage, clock, metrics).unwrap());
+        SystemLogServiceImpl::new(storage, Arc::new(RwLock::new(config)))
+    }
+
+    fn bucket(name: &str, versioning: Option<&str>) -> BucketMetadata {
+        let metadata = serde_json::json!({
+            "name": name,
+            "created": "2024-01-01T00:00:00.000Z",
+            "versioning": versioning,
+        });
+        serde_json::from_value(metadata).unwrap()
+    }
+
+    fn entry(method: &str, uri: &str, version_id: Option<&str>, ip: &str) -> AccessLogEntry {
+        AccessLogEntry {
+            time: now(),
+            request_id: None,
+            client_ip: Some(ip.to_string()),
+            requester: Some(format!("key-{}", ip)),
+            method: method.to_string(),
+            uri: uri.to_string(),
+            bucket: Some("foo".to_string()),
+            version_id: version_id.map(str::to_string),
+            status: 200,
+            request_bytes: 0,
+            response_bytes: 0,
+            duration_ms: 0,
+        }
+    }
+
+    fn writer(version: &VersionLineage) -> Option<(&str, &str)> {
+        let writer = version.writer.as_ref()?;
+        Some((writer.operation.as_str(), writer.client_ip.as_deref()?))
+    }
+
+    #[tokio::test]
+    async fn lineage_names_the_writer_of_every_version() {
+        let service = service();
+        let storage = service.storage.clone();
+        storage
+            .create_bucket(&bucket("foo", Some("Enabled")))
+            .unwrap();
+        let attributes = ObjectAttributes::default();
+        let first = storage
+            .put_object("foo", "a b", b"one", attributes.clone())
+            .unwrap();
+        let first = first.version_id.unwrap();
+        let second = storage
+            .put_object("foo", "a b", b"two", attributes)
+            .unwrap();
+        let second = second.version_id.unwrap();
+        let marker = storage.delete_object("foo", "a b").unwrap().unwrap();
+
+        // The first write's log is already published, the others staged.
+        let published = entry("PUT", "/foo/a%20b", Some(&first), "10.0.0.1");
+        let line = format!("{}\n", serde_json::to_string(&published).unwrap());
+        storage
+            .create_bucket(&bucket("s3clone-system", None))
+            .unwrap();
+        let key = format!("access/{}.log", &published.time[..10]);
+        storage
+            .put_object("s3clone-system", &key, line.as_bytes(), Default::default())
+            .unwrap();
+        let uri = "/foo/a%20b?uploadId=1";
+        service.record_access(&entry("POST", uri, Some(&second), "10.0.0.2"));
+        // Reads and sub-resources naming a version did not write it.
+        let uri = format!("/foo/a%20b?versionId={}", second);
+        service.record_access(&entry("GET", &uri, Some(&second), "10.0.0.9"));
+        let uri = format!("/foo/a%20b?tagging&versionId={}", first);
+        service.record_access(&entry("PUT", &uri, Some(&first), "10.0.0.9"));
+        let id = marker.version_id();
+        service.record_access(&entry("DELETE", "/foo/a%20b", Some(id), "10.0.0.3"));
+
+        let lineage = service.lineage("foo", "a b").await.unwrap();
+        let ids: Vec<_> = lineage.iter().map(|v| v.version_id.as_str()).collect();
+        assert_eq!(ids, [marker.version_id(), &second, &first]);
+        assert!(lineage[0].delete_marker && lineage[0].is_latest);
+        assert!(!lineage[1].delete_marker && !lineage[1].is_latest);
+        assert_eq!(lineage[2].size, Some(3));
+        assert_eq!(writer(&lineage[0]), Some(("DeleteObject", "10.0.0.3")));
+        assert_eq!(
+            writer(&lineage[1]),
+            Some(("CompleteMultipartUpload", "10.0.0.2"))
+        );
+        assert_eq!(writer(&lineage[2]), Some(("PutObject", "10.0.0.1")));
+
+        assert!(matches!(
+            service
+                .lineage("foo", "missing")
+                .await
+                .unwrap_err()
+                .downcast(),
+            Ok(StorageError::NoSuchKey(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn lineage_matches_the_null_version_to_the_write_running_then() {
+        let service = service();
+        let storage = service.storage.clone();
+        storage.create_bucket(&bucket("foo", None)).unwrap();
+        let mut earlier = entry("PUT", "/foo/a", None, "10.0.0.1");
+        earlier.time = "2000-01-01T00:00:00.000Z".to_string();
+        service.record_access(&earlier);
+        let mut write = entry("PUT", "/foo/a", None, "10.0.0.2");
+        let started = Utc::now();
+        storage
+            .put_object("foo", "a", b"data", Default::default())
+            .unwrap();
+        write.duration_ms = (Utc::now() - started).num_milliseconds() as u64;
+        service.record_access(&write);
+        // Running at the same time, but not writing the key.
+        for (method, uri) in [
+            ("GET", "/foo/a"),
+            ("PUT", "/foo/a?tagging"),
+            ("PUT", "/foo/b"),
+        ] {
+            let mut other = entry(method, uri, None, "10.0.0.9");
+            other.time = write.time.clone();
+            other.duration_ms = write.duration_ms;
+            service.record_access(&other);
+        }
+
+        let lineage = service.lineage("foo", "a").await.unwrap();
+        assert_eq!(lineage.len(), 1);
+        assert_eq!(lineage[0].version_id, NULL_VERSION_ID);
+        assert!(lineage[0].is_latest && !lineage[0].delete_marker);
+        assert_eq!(writer(&lineage[0]), Some(("PutObject", "10.0.0.2")));
+    }
+}
@@ -0,0 +1,109 @@
+//! Where [`Storage`] keeps its files (`storage.backend`). The layout of
+//! buckets, keys and metadata is that of [`Storage`]; a backend only stores
+//! the files and directories of that layout, with the semantics and error
+//! kinds of a POSIX filesystem (`NotFound`, `AlreadyExists`, `IsADirectory`,
+//! `NotADirectory`, `DirectoryNotEmpty`) that [`Storage`] relies on, e.g.
+//! atomic renames to replace files readers may have open.
+//!
+//! [`Storage`]: super::Storage
+
+use super::ObjectReader;
+use chrono::{DateTime, Utc};
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+
+/// What [`StorageBackend::metadata`] tells about a file or directory.
+#[derive(Debug, Clone, Copy)]
+pub struct FileMetadata {
+    pub is_dir: bool,
+    /// Size in bytes; 0 for directories.
+    pub len: u64,
+    pub modified: DateTime<Utc>,
+}
+
+/// An entry of [`StorageBackend::read_dir`].
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: OsString,
+    pub is_dir: bool,
+}
+
+/// File and directory operations on paths below the storage root.
+pub trait StorageBackend: Send + Sync {
+    /// `filesystem` or `memory`, for logs.
+    fn name(&self) -> &'static str;
+
+    /// The whole content of a file.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// A reader of a file from `offset` on, to stream it without reading it
+    /// into memory first.
+    fn reader(&self, path: &Path, offset: u64) -> io::Result<ObjectReader>;
+
+    /// A blocking reader of a whole file, for hashing or copying it.
+    fn open(&self, path: &Path) -> io::Result<Box<dyn io::Read + Send>>;
+
+    /// Creates or truncates a file to write to a piece at a time. What is
+    /// written is in the file once the writer is flushed.
+    fn create(&self, path: &Path) -> io::Result<Box<dyn io::Write + Send>>;
+
+    /// Creates or replaces a file with `data`.
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()>;
+
+    /// [`write`](Self::write) for files holding secrets: only the server's
+    /// user may read them.
+    fn write_private(&self, path: &Path, data: &[u8]) -> io::Result<()>;
+
+    /// Appends `data` to a file in one write, so concurrent appends do not
+    /// interleave. A missing file is created only with `create`.
+    fn append(&self, path: &Path, data: &[u8], create: bool) -> io::Result<()>;
+
+    /// Moves a file or directory, replacing a file (or an empty directory)
+    /// at `to` in one step.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Makes `to` a file with the content of `from`, failing with
+    /// `AlreadyExists` rather than replacing anything at `to`.
+    fn link(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Creates a directory whose parent exists; `AlreadyExists` if there is
+    /// one already.
+    fn create_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Creates a directory and any missing parents.
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// Removes an empty directory.
+    fn remove_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Removes a directory and everything in it.
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// The entries of a directory, in no particular order.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>>;
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata>;
+
+    /// Flushes a file, or the entries of a directory, to stable storage.
+    fn sync(&self, path: &Path) -> io::Result<()>;
+
+    fn exists(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok_and(|m| m.is_dir)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.metadata(path).is_ok_and(|m| !m.is_dir)
+    }
+}
@@ -0,0 +1,172 @@
+//! In-memory read cache of small objects (`storage.read_cache`), for
+//! workloads such as thumbnails where the same few small keys are read over
+//! and over. Entries are dropped by every write through [`Storage`] and
+//! expire after the TTL, which bounds how long edits made outside the server
+//! go unseen.
+//!
+//! The data is copied onto the heap rather than memory-mapped: objects are
+//! overwritten in place, and a mapping of a file truncated underneath it
+//! faults (SIGBUS) on access, taking the server down with it.
+//!
+//! [`Storage`]: super::Storage
+
+use crate::config::ReadCacheConfig;
+use crate::models::ObjectMetadata;
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Bucket and key of a cached object.
+type ObjectId = (String, String);
+
+pub struct ReadCache {
+    max_object_size: u64,
+    max_size: u64,
+    ttl: Duration,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    objects: HashMap<ObjectId, Entry>,
+    /// Cached objects by last use, least recent first.
+    by_use: BTreeMap<u64, ObjectId>,
+    uses: u64,
+    /// Total size of the cached data.
+    size: u64,
+    /// Bumped by every invalidation. A read that began before one may have
+    /// seen the old object, so it does not fill the cache.
+    generation: u64,
+}
+
+struct Entry {
+    metadata: ObjectMetadata,
+    data: Bytes,
+    cached_at: Instant,
+    last_use: u64,
+}
+
+impl Entries {
+    fn remove(&mut self, id: &ObjectId) {
+        if let Some(entry) = self.objects.remove(id) {
+            self.by_use.remove(&entry.last_use);
+            self.size -= entry.data.len() as u64;
+        }
+    }
+}
+
+impl ReadCache {
+    /// `None` unless the cache is enabled.
+    pub fn new(config: &ReadCacheConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        Some(Self {
+            max_object_size: config.max_object_size,
+            max_size: config.max_size,
+            ttl: Duration::from_secs(config.ttl_secs),
+            entries: Mutex::new(Entries::default()),
+        })
+    }
+
+    /// The cached object, unless it is missing or has expired.
+    pub fn get(&self, bucket: &str, key: &str) -> Option<(ObjectMetadata, Bytes)> {
+        let mut entries = self.entries.lock().unwrap();
+        let id = (bucket.to_string(), key.to_string());
+        if entries
+            .objects
+            .get(&id)
+            .is_some_and(|entry| entry.cached_at.elapsed() >= self.ttl)
+        {
+            entries.remove(&id);
+        }
+        let Entries {
+            objects,
+            by_use,
+            uses,
+            ..
+        } = &mut *entries;
+        let entry = objects.get_mut(&id)?;
+        by_use.remove(&entry.last_use);
+        *uses += 1;
+        entry.last_use = *uses;
+        by_use.insert(*uses, id);
+        Some((entry.metadata.clone(), entry.data.clone()))
+    }
+
+    /// Whether objects of `size` bytes are cached once read.
+    pub fn caches(&self, size: u64) -> bool {
+        size <= self.max_object_size && size <= self.max_size
+    }
+
+    /// To be taken before reading an object from disk and passed to
+    /// [`ReadCache::insert`] afterwards.
+    pub fn generation(&self) -> u64 {
+        self.entries.lock().unwrap().generation
+    }
+
+    /// Caches an object read from disk, evicting the least recently used
+    /// ones to make room. Objects over `max_object_size`, and reads that an
+    /// invalidation raced with since `generation` was taken, are skipped.
+    pub fn insert(
+        &self,
+        generation: u64,
+        bucket: &str,
+        key: &str,
+        metadata: &ObjectMetadata,
+        data: &Bytes,
+    ) {
+        let size = data.len() as u64;
+        if !self.caches(size) {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.generation != generation {
+            return;
+        }
+        let id = (bucket.to_string(), key.to_string());
+        entries.remove(&id);
+        while entries.size + size > self.max_size {
+            let Some((_, oldest)) = entries.by_use.pop_first() else {
+                break;
+            };
+            entries.remove(&oldest);
+        }
+        entries.uses += 1;
+        let last_use = entries.uses;
+        entries.by_use.insert(last_use, id.clone());
+        entries.size += size;
+        entries.objects.insert(
+            id,
+            Entry {
+                metadata: metadata.clone(),
+                data: data.clone(),
+                cached_at: Instant::now(),
+                last_use,
+            },
+        );
+    }
+
+    /// Drops `bucket/key`; called once it has been written or deleted.
+    pub fn invalidate(&self, bucket: &str, key: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.generation += 1;
+        entries.remove(&(bucket.to_string(), key.to_string()));
+    }
+
+    /// Drops every object of `bucket`, for bucket deletions and renames.
+    pub fn invalidate_bucket(&self, bucket: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.generation += 1;
+        let ids: Vec<ObjectId> = entries
+            .objects
+            .keys()
+            .filter(|(b, _)| b == bucket)
+            .cloned()
+            .collect();
+        for id in &ids {
+            entries.remove(id);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Eventual consistency test mode (`storage.eventual_consistency`): for a
+//! while after a write, reads and listings through [`Storage`]'s
+//! `*_eventual` operations see the object as it was before, the way S3
+//! behaved until December 2020. Applications written against that model
+//! can be checked for the assumptions it allowed.
+//!
+//! Only the state before the latest write of a key is remembered, with its
+//! data if small enough, so successive writes in quick succession each
+//! start the delay over.
+//!
+//! [`Storage`]: super::Storage
+
+use crate::config::EventualConsistencyConfig;
+use crate::models::ObjectMetadata;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Bucket and key of a recently written object.
+type ObjectId = (String, String);
+
+pub struct EventualConsistency {
+    read_delay: Duration,
+    listing_delay: Duration,
+    max_object_size: u64,
+    writes: Mutex<HashMap<ObjectId, Write>>,
+}
+
+/// A write still propagating.
+struct Write {
+    at: Instant,
+    /// The object replaced, `None` when the key was new.
+    before: Option<Previous>,
+}
+
+#[derive(Clone)]
+struct Previous {
+    metadata: ObjectMetadata,
+    /// `None` for objects larger than `max_object_size`.
+    data: Option<Bytes>,
+}
+
+/// What a listing may still show of a recently written key.
+pub enum Listed {
+    /// The key was new and is not listed yet.
+    Hidden,
+    /// The key is listed as it was.
+    Stale(Box<ObjectMetadata>),
+}
+
+impl EventualConsistency {
+    /// `None` unless the test mode is enabled.
+    pub fn new(config: &EventualConsistencyConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        Some(Self {
+            read_delay: Duration::from_millis(config.read_delay_ms),
+            listing_delay: Duration::from_millis(config.listing_delay_ms),
+            max_object_size: config.max_object_size,
+            writes: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Whether the data of an object of `size` is kept for stale GETs.
+    pub fn keeps_data(&self, size: u64) -> bool {
+        size <= self.max_object_size
+    }
+
+    /// Records a write of `bucket/key` that replaces `before` (with its data
+    /// if [`keeps_data`](Self::keeps_data)), or that creates the key.
+    pub fn record(&self, bucket: &str, key: &str, before: Option<(ObjectMetadata, Option<Bytes>)>) {
+        let longest = self.read_delay.max(self.listing_delay);
+        let mut writes = self.writes.lock().unwrap();
+        writes.retain(|_, write| write.at.elapsed() < longest);
+        writes.insert(
+            (bucket.to_string(), key.to_string()),
+            Write {
+                at: Instant::now(),
+                before: before.map(|(metadata, data)| Previous { metadata, data }),
+            },
+        );
+    }
+
+    /// The object a GET may still see instead of the current one: the one
+    /// replaced or deleted less than the read delay ago. `None` when reads
+    /// are current, also for new keys and objects whose data was not kept.
+    pub fn read(&self, bucket: &str, key: &str) -> Option<(ObjectMetadata, Bytes)> {
+        let previous = self.previous(bucket, key, self.read_delay)?;
+        Some((previous.metadata, previous.data?))
+    }
+
+    /// Like [`read`](Self::read) for a HEAD, which does not need the data.
+    pub fn head(&self, bucket: &str, key: &str) -> Option<ObjectMetadata> {
+        Some(self.previous(bucket, key, self.read_delay)?.metadata)
+    }
+
+    /// How listings show `bucket/key` while its latest write propagates;
+    /// `None` once it is listed as it is.
+    pub fn listed(&self, bucket: &str, key: &str) -> Option<Listed> {
+        let writes = self.writes.lock().unwrap();
+        let write = writes.get(&(bucket.to_string(), key.to_string()))?;
+        if write.at.elapsed() >= self.listing_delay {
+            return None;
+        }
+        Some(match &write.before {
+            Some(previous) => Listed::Stale(Box::new(previous.metadata.clone())),
+            None => Listed::Hidden,
+        })
+    }
+
+    /// Keys of `bucket` under `prefix` with a write still propagating to
+    /// listings.
+    pub fn listing_writes(&self, bucket: &str, prefix: &str) -> Vec<String> {
+        let writes = self.writes.lock().unwrap();
+        writes
+            .iter()
+            .filter(|((b, key), write)| {
+                b == bucket && key.starts_with(prefix) && write.at.elapsed() < self.listing_delay
+            })
+            .map(|((_, key), _)| key.clone())
+            .collect()
+    }
+
+    fn previous(&self, bucket: &str, key: &str, delay: Duration) -> Option<Previous> {
+        let writes = self.writes.lock().unwrap();
+        let write = writes.get(&(bucket.to_string(), key.to_string()))?;
+        if write.at.elapsed() >= delay {
+            return None;
+        }
+        write.before.clone()
+    }
+}
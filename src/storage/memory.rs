@@ -0,0 +1,376 @@
+//! `storage.backend: memory`: the files of the layout are kept in a map in
+//! memory and are gone when the server stops. Meant for tests and
+//! throwaway environments, where it saves the disk I/O and the cleanup.
+
+use super::ObjectReader;
+use super::backend::{DirEntry, FileMetadata, StorageBackend};
+use crate::clock::Clock;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Clones share the files, so open writers can hold on to them.
+#[derive(Clone)]
+pub struct Memory {
+    clock: Arc<dyn Clock>,
+    /// By path. Paths order by component, so the entries below a directory
+    /// directly follow it.
+    nodes: Arc<RwLock<BTreeMap<PathBuf, Node>>>,
+}
+
+#[derive(Clone)]
+enum Node {
+    Dir {
+        modified: DateTime<Utc>,
+    },
+    File {
+        data: Bytes,
+        modified: DateTime<Utc>,
+    },
+}
+
+type Nodes = BTreeMap<PathBuf, Node>;
+
+impl Memory {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            nodes: Arc::new(RwLock::new(BTreeMap::new())),
+        }
+    }
+
+    fn nodes(&self) -> RwLockReadGuard<'_, Nodes> {
+        self.nodes.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn nodes_mut(&self) -> RwLockWriteGuard<'_, Nodes> {
+        self.nodes.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn file(&self, data: Bytes) -> Node {
+        Node::File {
+            data,
+            modified: self.clock.now(),
+        }
+    }
+
+    fn data(&self, path: &Path) -> io::Result<Bytes> {
+        match self.nodes().get(path) {
+            Some(Node::File { data, .. }) => Ok(data.clone()),
+            Some(Node::Dir { .. }) => Err(error(io::ErrorKind::IsADirectory, path)),
+            None => Err(error(io::ErrorKind::NotFound, path)),
+        }
+    }
+
+    /// Stores `data` as the file at `path`, which an open writer created.
+    fn commit(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let mut nodes = self.nodes_mut();
+        match nodes.get(path) {
+            Some(Node::File { .. }) => {
+                nodes.insert(path.to_path_buf(), self.file(Bytes::copy_from_slice(data)));
+                Ok(())
+            }
+            // Removed (or replaced by a directory) while being written.
+            _ => Err(error(io::ErrorKind::NotFound, path)),
+        }
+    }
+}
+
+impl StorageBackend for Memory {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        Ok(self.data(path)?.to_vec())
+    }
+
+    fn reader(&self, path: &Path, offset: u64) -> io::Result<ObjectReader> {
+        let data = self.data(path)?;
+        let offset = offset.min(data.len() as u64) as usize;
+        Ok(Box::new(io::Cursor::new(data.slice(offset..))))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn io::Read + Send>> {
+        Ok(Box::new(io::Cursor::new(self.data(path)?)))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn io::Write + Send>> {
+        self.write(path, &[])?;
+        Ok(Box::new(Writer {
+            memory: self.clone(),
+            path: path.to_path_buf(),
+            data: Vec::new(),
+            dirty: false,
+        }))
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let mut nodes = self.nodes_mut();
+        check_parent(&nodes, path)?;
+        if let Some(Node::Dir { .. }) = nodes.get(path) {
+            return Err(error(io::ErrorKind::IsADirectory, path));
+        }
+        nodes.insert(path.to_path_buf(), self.file(Bytes::copy_from_slice(data)));
+        Ok(())
+    }
+
+    fn write_private(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.write(path, data)
+    }
+
+    fn append(&self, path: &Path, data: &[u8], create: bool) -> io::Result<()> {
+        let mut nodes = self.nodes_mut();
+        let appended = match nodes.get(path) {
+            Some(Node::File { data: existing, .. }) => [existing.as_ref(), data].concat(),
+            Some(Node::Dir { .. }) => return Err(error(io::ErrorKind::IsADirectory, path)),
+            None if create => {
+                check_parent(&nodes, path)?;
+                data.to_vec()
+            }
+            None => return Err(error(io::ErrorKind::NotFound, path)),
+        };
+        nodes.insert(path.to_path_buf(), self.file(Bytes::from(appended)));
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes_mut();
+        let Some(node) = nodes.get(from) else {
+            return Err(error(io::ErrorKind::NotFound, from));
+        };
+        if from == to {
+            return Ok(());
+        }
+        check_parent(&nodes, to)?;
+        match (node, nodes.get(to)) {
+            (Node::File { .. }, Some(Node::Dir { .. })) => {
+                return Err(error(io::ErrorKind::IsADirectory, to));
+            }
+            (Node::Dir { .. }, Some(Node::File { .. })) => {
+                return Err(error(io::ErrorKind::NotADirectory, to));
+            }
+            (Node::Dir { .. }, Some(Node::Dir { .. })) if has_children(&nodes, to) => {
+                return Err(error(io::ErrorKind::DirectoryNotEmpty, to));
+            }
+            (Node::Dir { .. }, _) if to.starts_with(from) => {
+                return Err(error(io::ErrorKind::InvalidInput, to));
+            }
+            _ => {}
+        }
+        let moved: Vec<PathBuf> = std::iter::once(from.to_path_buf())
+            .chain(below(&nodes, from).map(|(path, _)| path.clone()))
+            .collect();
+        for path in moved {
+            let Some(node) = nodes.remove(&path) else {
+                continue;
+            };
+            let relative = path.strip_prefix(from).unwrap_or(Path::new(""));
+            nodes.insert(to.join(relative), node);
+        }
+        Ok(())
+    }
+
+    fn link(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes_mut();
+        let data = match nodes.get(from) {
+            Some(Node::File { data, .. }) => data.clone(),
+            Some(Node::Dir { .. }) => return Err(error(io::ErrorKind::PermissionDenied, from)),
+            None => return Err(error(io::ErrorKind::NotFound, from)),
+        };
+        if nodes.contains_key(to) {
+            return Err(error(io::ErrorKind::AlreadyExists, to));
+        }
+        check_parent(&nodes, to)?;
+        nodes.insert(to.to_path_buf(), self.file(data));
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes_mut();
+        match nodes.get(path) {
+            Some(Node::File { .. }) => {
+                nodes.remove(path);
+                Ok(())
+            }
+            Some(Node::Dir { .. }) => Err(error(io::ErrorKind::IsADirectory, path)),
+            None => Err(error(io::ErrorKind::NotFound, path)),
+        }
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes_mut();
+        if nodes.contains_key(path) {
+            return Err(error(io::ErrorKind::AlreadyExists, path));
+        }
+        check_parent(&nodes, path)?;
+        nodes.insert(
+            path.to_path_buf(),
+            Node::Dir {
+                modified: self.clock.now(),
+            },
+        );
+        Ok(())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes_mut();
+        let mut ancestors: Vec<&Path> = path
+            .ancestors()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect();
+        ancestors.reverse();
+        for dir in ancestors {
+            match nodes.get(dir) {
+                Some(Node::Dir { .. }) => {}
+                Some(Node::File { .. }) if dir == path => {
+                    return Err(error(io::ErrorKind::AlreadyExists, dir));
+                }
+                Some(Node::File { .. }) => return Err(error(io::ErrorKind::NotADirectory, dir)),
+                None => {
+                    nodes.insert(
+                        dir.to_path_buf(),
+                        Node::Dir {
+                            modified: self.clock.now(),
+                        },
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes_mut();
+        match nodes.get(path) {
+            Some(Node::Dir { .. }) if has_children(&nodes, path) => {
+                Err(error(io::ErrorKind::DirectoryNotEmpty, path))
+            }
+            Some(Node::Dir { .. }) => {
+                nodes.remove(path);
+                Ok(())
+            }
+            Some(Node::File { .. }) => Err(error(io::ErrorKind::NotADirectory, path)),
+            None => Err(error(io::ErrorKind::NotFound, path)),
+        }
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes_mut();
+        match nodes.get(path) {
+            Some(Node::Dir { .. }) => {}
+            Some(Node::File { .. }) => return Err(error(io::ErrorKind::NotADirectory, path)),
+            None => return Err(error(io::ErrorKind::NotFound, path)),
+        }
+        let removed: Vec<PathBuf> = below(&nodes, path).map(|(path, _)| path.clone()).collect();
+        for below in removed {
+            nodes.remove(&below);
+        }
+        nodes.remove(path);
+        Ok(())
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let nodes = self.nodes();
+        match nodes.get(path) {
+            Some(Node::Dir { .. }) => {}
+            Some(Node::File { .. }) => return Err(error(io::ErrorKind::NotADirectory, path)),
+            None => return Err(error(io::ErrorKind::NotFound, path)),
+        }
+        Ok(below(&nodes, path)
+            .filter(|(child, _)| child.parent() == Some(path))
+            .filter_map(|(child, node)| {
+                Some(DirEntry {
+                    name: child.file_name()?.to_os_string(),
+                    is_dir: matches!(node, Node::Dir { .. }),
+                })
+            })
+            .collect())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        match self.nodes().get(path) {
+            Some(Node::Dir { modified }) => Ok(FileMetadata {
+                is_dir: true,
+                len: 0,
+                modified: *modified,
+            }),
+            Some(Node::File { data, modified }) => Ok(FileMetadata {
+                is_dir: false,
+                len: data.len() as u64,
+                modified: *modified,
+            }),
+            None => Err(error(io::ErrorKind::NotFound, path)),
+        }
+    }
+
+    /// Nothing outlives the process anyway.
+    fn sync(&self, path: &Path) -> io::Result<()> {
+        self.metadata(path).map(|_| ())
+    }
+}
+
+/// A file being written by [`Memory::create`]; its data replaces the file
+/// when flushed or dropped.
+struct Writer {
+    memory: Memory,
+    path: PathBuf,
+    data: Vec<u8>,
+    dirty: bool,
+}
+
+impl io::Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        self.dirty = true;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.dirty {
+            self.memory.commit(&self.path, &self.data)?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        let _ = io::Write::flush(self);
+    }
+}
+
+/// The entries below directory `dir`, at any depth.
+fn below<'a>(nodes: &'a Nodes, dir: &'a Path) -> impl Iterator<Item = (&'a PathBuf, &'a Node)> {
+    nodes
+        .range::<Path, _>((Bound::Excluded(dir), Bound::Unbounded))
+        .take_while(move |(path, _)| path.starts_with(dir))
+}
+
+fn has_children(nodes: &Nodes, dir: &Path) -> bool {
+    below(nodes, dir).next().is_some()
+}
+
+/// Fails unless the directory `path` goes in exists.
+fn check_parent(nodes: &Nodes, path: &Path) -> io::Result<()> {
+    let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    else {
+        return Ok(());
+    };
+    match nodes.get(parent) {
+        Some(Node::Dir { .. }) => Ok(()),
+        Some(Node::File { .. }) => Err(error(io::ErrorKind::NotADirectory, parent)),
+        None => Err(error(io::ErrorKind::NotFound, parent)),
+    }
+}
+
+fn error(kind: io::ErrorKind, path: &Path) -> io::Error {
+    io::Error::new(kind, format!("{}: {}", path.display(), kind))
+}
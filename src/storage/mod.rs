@@ -0,0 +1,3232 @@
+mod backend;
+mod cache;
+mod consistency;
+mod filesystem;
+mod memory;
+mod trace;
+
+use crate::clock::Clock;
+use crate::config::{Durability, StorageBackendKind, StorageConfig};
+use crate::crypto::Sha256;
+use crate::metrics::Metrics;
+use crate::models::{
+    AclGrant, BucketDeletion, BucketFreeze, BucketMetadata, BucketTombstone, EtagAlgorithm, KmsKey,
+    MultipartUpload, NULL_VERSION_ID, ObjectAttributes, ObjectMetadata, ObjectVersion, Part,
+    QueuedEvent, ServiceAccount, VersioningStatus,
+};
+use backend::StorageBackend;
+use bytes::Bytes;
+use cache::ReadCache;
+use chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
+use consistency::{EventualConsistency, Listed};
+use filesystem::Filesystem;
+use log::{debug, info, warn};
+use md5::{Digest, Md5};
+use memory::Memory;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use trace::{Span, Tracer};
+
+/// Directory under the storage root holding server-owned state (bucket and
+/// object metadata, aliases). Bucket names cannot start with `.`, so it never
+/// collides with a bucket.
+const SYSTEM_DIR: &str = ".s3clone";
+/// Below [`SYSTEM_DIR`]: uploads being received, see [`StagedData`].
+const STAGING_DIR: &str = "staging";
+
+/// Event queue area of notifications awaiting delivery.
+pub const EVENTS_PENDING: &str = "pending";
+/// Event queue area of notifications that ran out of delivery attempts.
+pub const EVENTS_DEAD_LETTER: &str = "dead-letter";
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    NoSuchBucket(String),
+    NoSuchKey(String),
+    NoSuchVersion(String),
+    /// The version asked for is a delete marker; carries its version ID.
+    DeleteMarker(String),
+    BucketAlreadyExists(String),
+    BucketAlreadyOwnedByYou(String),
+    BucketNotEmpty(String),
+    InvalidBucketName(String),
+    InvalidObjectName(String, String),
+    /// The key exceeds [`MAX_KEY_LENGTH`]; carries its length in bytes.
+    KeyTooLong(usize),
+    ObjectNotAppendable(String),
+    /// The append position did not match; carries the object's size.
+    PositionNotEqualToLength(String, u64),
+    /// Writes to the bucket are refused; carries the retry delay in seconds.
+    BucketFrozen(String, u64),
+    NoSuchUpload(String),
+    /// A part to assemble is missing or no longer matches its ETag.
+    InvalidPart(u32),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(e) => write!(f, "IO error: {}", e),
+            StorageError::NoSuchBucket(b) => write!(f, "Bucket {} does not exist", b),
+            StorageError::NoSuchKey(k) => write!(f, "Key {} does not exist", k),
+            StorageError::NoSuchVersion(v) => write!(f, "Version {} does not exist", v),
+            StorageError::DeleteMarker(v) => write!(f, "Version {} is a delete marker", v),
+            StorageError::BucketAlreadyExists(b) => write!(f, "Bucket {} already exists", b),
+            StorageError::BucketAlreadyOwnedByYou(b) => {
+                write!(f, "Bucket {} is already owned by you", b)
+            }
+            StorageError::BucketNotEmpty(b) => write!(f, "Bucket {} is not empty", b),
+            StorageError::InvalidBucketName(msg) => write!(f, "Invalid bucket name: {}", msg),
+            StorageError::InvalidObjectName(key, reason) => {
+                write!(f, "Invalid object name {}: {}", key, reason)
+            }
+            StorageError::KeyTooLong(len) => {
+                write!(
+                    f,
+                    "Key is {} bytes long, at most {} allowed",
+                    len, MAX_KEY_LENGTH
+                )
+            }
+            StorageError::ObjectNotAppendable(k) => write!(f, "Key {} is not appendable", k),
+            StorageError::PositionNotEqualToLength(k, size) => {
+                write!(f, "Key {} is {} bytes long", k, size)
+            }
+            StorageError::BucketFrozen(b, _) => write!(f, "Bucket {} is frozen", b),
+            StorageError::NoSuchUpload(id) => write!(f, "Upload {} does not exist", id),
+            StorageError::InvalidPart(n) => write!(f, "Part {} is missing or changed", n),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<io::Error> for StorageError {
+    fn from(e: io::Error) -> Self {
+        StorageError::Io(e)
+    }
+}
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+/// Object data to read without holding it all in memory, from its file or
+/// from a copy the read cache or the eventual consistency mode kept.
+pub type ObjectReader = Box<dyn AsyncRead + Send + Unpin>;
+
+/// Bucket and object storage rooted at `storage.location`, kept in files
+/// by the configured [`StorageBackend`] (on disk by default).
+///
+/// Layout:
+/// - `<root>/<bucket>/...` object data
+/// - `<root>/.s3clone/buckets/<bucket>.json` bucket metadata, including the
+///   creation date
+/// - `<root>/.s3clone/objects/<bucket>/<key>.json` object metadata
+/// - `<root>/.s3clone/versions/<bucket>/<key>.json` version history of a key
+///   in a versioned bucket: its noncurrent versions and delete markers,
+///   newest first
+/// - `<root>/.s3clone/version-data/<bucket>/<file>` data of noncurrent
+///   versions
+/// - `<root>/.s3clone/multipart/<bucket>/<upload_id>/` multipart uploads in
+///   progress: `upload.json`, and each part as `<part_number>` with its
+///   metadata in `<part_number>.json`
+/// - `<root>/.s3clone/aliases.json` old bucket name -> current bucket name
+/// - `<root>/.s3clone/service_accounts.json` credentials minted via the admin API
+/// - `<root>/.s3clone/kms_keys.json` SSE-KMS emulation key registry
+/// - `<root>/.s3clone/bucket_deletions.json` forced bucket deletions in progress
+/// - `<root>/.s3clone/frozen_buckets.json` buckets refusing writes
+/// - `<root>/.s3clone/tombstones/<bucket>/` a deleted bucket kept for
+///   restoring: `tombstone.json`, and the bucket's `data/`, `bucket.json`,
+///   `objects/`, `versions/` and `version-data/` moved there
+/// - `<root>/.s3clone/logs/<kind>-<YYYY-MM-DD>.log` system logs not yet published
+/// - `<root>/.s3clone/events/<area>/<seq>.json` event notifications awaiting
+///   delivery (`pending`) or given up on (`dead-letter`)
+/// - `<root>/.s3clone/staging/<uuid>` uploads being received (see
+///   [`StagedData`]); emptied at startup
+pub struct Storage {
+    base_path: PathBuf,
+    backend: Arc<dyn StorageBackend>,
+    /// Serializes bucket namespace changes (create/delete/rename/aliases).
+    namespace: Mutex<()>,
+    /// Serializes appends, so concurrent appenders see each other's sizes.
+    appends: Mutex<()>,
+    /// Serializes writes that move versions in and out of version histories.
+    /// Taken after a `writes` guard.
+    versions: Mutex<()>,
+    /// Held shared by every write to a bucket and exclusively while freezing
+    /// one, so no write is in flight once a freeze returns.
+    writes: RwLock<()>,
+    /// Held shared from creating the directories of a key until its file is
+    /// in them, and exclusively while removing directories a delete left
+    /// empty, so a write never loses its directory to a concurrent delete.
+    dirs: RwLock<()>,
+    /// `storage.escape_keys`: key segments are stored under their escaped
+    /// names (see [`escape_key_segment`]).
+    escape_keys: bool,
+    /// `storage.durability: fsync`: files are synced before they are renamed
+    /// into place, and directories once they got new entries.
+    fsync: bool,
+    /// Source of `Last-Modified`.
+    clock: Arc<dyn Clock>,
+    read_cache: Option<ReadCache>,
+    /// `None` unless the eventual consistency test mode is enabled.
+    consistency: Option<EventualConsistency>,
+    tracer: Tracer,
+}
+
+impl Storage {
+    pub fn new(
+        config: &StorageConfig,
+        clock: Arc<dyn Clock>,
+        metrics: Arc<Metrics>,
+    ) -> StorageResult<Self> {
+        let base_path = PathBuf::from(&config.location);
+        let backend: Arc<dyn StorageBackend> = match config.backend {
+            StorageBackendKind::Filesystem => Arc::new(Filesystem),
+            StorageBackendKind::Memory => Arc::new(Memory::new(clock.clone())),
+        };
+        backend.create_dir_all(&base_path.join(SYSTEM_DIR).join("buckets"))?;
+        // What is left there is from uploads a crash or restart cut short.
+        let staging = base_path.join(SYSTEM_DIR).join(STAGING_DIR);
+        match backend.remove_dir_all(&staging) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        backend.create_dir(&staging)?;
+        debug!("storage rooted at {:?} ({})", base_path, backend.name());
+        if config.backend == StorageBackendKind::Memory {
+            warn!("memory storage backend: buckets and objects are lost on restart");
+        }
+        let consistency = EventualConsistency::new(&config.eventual_consistency);
+        if consistency.is_some() {
+            warn!("eventual consistency test mode: reads and listings may be stale after writes");
+        }
+        Ok(Self {
+            base_path,
+            backend,
+            namespace: Mutex::new(()),
+            appends: Mutex::new(()),
+            versions: Mutex::new(()),
+            writes: RwLock::new(()),
+            dirs: RwLock::new(()),
+            escape_keys: config.escape_keys,
+            fsync: config.durability == Durability::Fsync,
+            clock,
+            read_cache: ReadCache::new(&config.read_cache),
+            consistency,
+            tracer: Tracer::new(&config.tracing, metrics),
+        })
+    }
+
+    /// The configured backend, `filesystem` or `memory`.
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    /// Runs `f` with this storage on the blocking thread pool. Storage calls
+    /// do synchronous I/O, so request handlers make them through here rather
+    /// than on a runtime worker: a slow or hung disk then holds up a
+    /// blocking thread, and the request's deadline can still fire. What a
+    /// deadline cuts off keeps running to its end in the background.
+    pub async fn blocking<T, F>(self: &Arc<Self>, f: F) -> T
+    where
+        F: FnOnce(&Storage) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let storage = self.clone();
+        match tokio::task::spawn_blocking(move || f(&storage)).await {
+            Ok(value) => value,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    /// Times a public operation until the returned span is dropped.
+    fn span<'a>(
+        &'a self,
+        operation: &'static str,
+        bucket: Option<&'a str>,
+        key: Option<&'a str>,
+    ) -> Span<'a> {
+        self.tracer.span(operation, bucket, key)
+    }
+
+    /// Drops `bucket/key` from the read cache after it changed on disk.
+    fn uncache(&self, bucket: &str, key: &str) {
+        if let Some(cache) = &self.read_cache {
+            cache.invalidate(bucket, key);
+        }
+    }
+
+    /// Remembers `bucket/key` as it is before a write replaces or deletes it,
+    /// for the eventual consistency test mode to serve for a while.
+    fn remember(&self, bucket: &str, key: &str) {
+        let Some(consistency) = &self.consistency else {
+            return;
+        };
+        let before = match self.head_object(bucket, key) {
+            Ok(metadata) => {
+                let data = if is_folder_marker(key) {
+                    Some(Bytes::new())
+                } else if consistency.keeps_data(metadata.size) {
+                    self.object_path(bucket, key)
+                        .ok()
+                        .and_then(|path| self.backend.read(&path).ok())
+                        .map(Bytes::from)
+                } else {
+                    None
+                };
+                Some((metadata, data))
+            }
+            Err(StorageError::NoSuchKey(_)) => None,
+            // The write is going to fail as well.
+            Err(_) => return,
+        };
+        consistency.record(bucket, key, before);
+    }
+
+    fn uncache_bucket(&self, bucket: &str) {
+        if let Some(cache) = &self.read_cache {
+            cache.invalidate_bucket(bucket);
+        }
+    }
+
+    fn bucket_path(&self, bucket: &str) -> PathBuf {
+        self.base_path.join(bucket)
+    }
+
+    fn bucket_metadata_path(&self, bucket: &str) -> PathBuf {
+        self.base_path
+            .join(SYSTEM_DIR)
+            .join("buckets")
+            .join(format!("{}.json", bucket))
+    }
+
+    fn objects_metadata_dir(&self, bucket: &str) -> PathBuf {
+        self.base_path.join(SYSTEM_DIR).join("objects").join(bucket)
+    }
+
+    /// The path of `key` (or of a key prefix ending in `/`) below the bucket
+    /// directory, and with `.json` appended below the metadata directories:
+    /// the key itself, or its escaped segments with `storage.escape_keys`.
+    fn key_path<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        if !self.escape_keys {
+            return Cow::Borrowed(key);
+        }
+        Cow::Owned(
+            key.split('/')
+                .map(escape_key_segment)
+                .collect::<Vec<_>>()
+                .join("/"),
+        )
+    }
+
+    fn object_path(&self, bucket: &str, key: &str) -> StorageResult<PathBuf> {
+        validate_object_key(key, self.escape_keys)?;
+        Ok(self.bucket_path(bucket).join(&*self.key_path(key)))
+    }
+
+    fn object_metadata_path(&self, bucket: &str, key: &str) -> PathBuf {
+        self.objects_metadata_dir(bucket)
+            .join(format!("{}.json", self.key_path(key)))
+    }
+
+    fn versions_dir(&self, bucket: &str) -> PathBuf {
+        self.base_path
+            .join(SYSTEM_DIR)
+            .join("versions")
+            .join(bucket)
+    }
+
+    fn history_path(&self, bucket: &str, key: &str) -> PathBuf {
+        self.versions_dir(bucket)
+            .join(format!("{}.json", self.key_path(key)))
+    }
+
+    fn version_data_dir(&self, bucket: &str) -> PathBuf {
+        self.base_path
+            .join(SYSTEM_DIR)
+            .join("version-data")
+            .join(bucket)
+    }
+
+    fn multipart_dir(&self, bucket: &str) -> PathBuf {
+        self.base_path
+            .join(SYSTEM_DIR)
+            .join("multipart")
+            .join(bucket)
+    }
+
+    /// Directory of an upload. Upload IDs are generated hex strings, so
+    /// anything else names no upload (and no path outside the area).
+    fn upload_path(&self, bucket: &str, upload_id: &str) -> StorageResult<PathBuf> {
+        if upload_id.is_empty() || !upload_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(StorageError::NoSuchUpload(upload_id.to_string()));
+        }
+        Ok(self.multipart_dir(bucket).join(upload_id))
+    }
+
+    fn aliases_path(&self) -> PathBuf {
+        self.base_path.join(SYSTEM_DIR).join("aliases.json")
+    }
+
+    fn service_accounts_path(&self) -> PathBuf {
+        self.base_path
+            .join(SYSTEM_DIR)
+            .join("service_accounts.json")
+    }
+
+    fn bucket_deletions_path(&self) -> PathBuf {
+        self.base_path
+            .join(SYSTEM_DIR)
+            .join("bucket_deletions.json")
+    }
+
+    fn frozen_buckets_path(&self) -> PathBuf {
+        self.base_path.join(SYSTEM_DIR).join("frozen_buckets.json")
+    }
+
+    fn tombstones_dir(&self) -> PathBuf {
+        self.base_path.join(SYSTEM_DIR).join("tombstones")
+    }
+
+    fn tombstone_dir(&self, bucket: &str) -> PathBuf {
+        self.tombstones_dir().join(bucket)
+    }
+
+    /// The files of `bucket`, each with where its tombstone keeps it. The
+    /// data directory comes first: the bucket is gone as soon as it moved.
+    fn tombstone_parts(&self, bucket: &str) -> [(PathBuf, PathBuf); 5] {
+        let dir = self.tombstone_dir(bucket);
+        [
+            (self.bucket_path(bucket), dir.join("data")),
+            (self.bucket_metadata_path(bucket), dir.join("bucket.json")),
+            (self.objects_metadata_dir(bucket), dir.join("objects")),
+            (self.versions_dir(bucket), dir.join("versions")),
+            (self.version_data_dir(bucket), dir.join("version-data")),
+        ]
+    }
+
+    fn kms_keys_path(&self) -> PathBuf {
+        self.base_path.join(SYSTEM_DIR).join("kms_keys.json")
+    }
+
+    fn metrics_snapshots_dir(&self) -> PathBuf {
+        self.base_path.join(SYSTEM_DIR).join("metrics")
+    }
+
+    fn metrics_snapshots_path(&self, day: &str) -> PathBuf {
+        self.metrics_snapshots_dir().join(format!("{}.ndjson", day))
+    }
+
+    fn system_log_path(&self, kind: &str, day: &str) -> PathBuf {
+        self.base_path
+            .join(SYSTEM_DIR)
+            .join("logs")
+            .join(format!("{}-{}.log", kind, day))
+    }
+
+    pub fn bucket_exists(&self, bucket: &str) -> bool {
+        let _span = self.span("bucket_exists", Some(bucket), None);
+        // Invalid names could reach outside the base path (`../x`), or name
+        // the system directory.
+        validate_bucket_name(bucket).is_ok() && self.backend.is_dir(&self.bucket_path(bucket))
+    }
+
+    pub fn list_buckets(&self) -> StorageResult<Vec<String>> {
+        let _span = self.span("list_buckets", None, None);
+        let mut buckets = Vec::new();
+        for entry in self.backend.read_dir(&self.base_path)? {
+            let name = entry.name.to_string_lossy().to_string();
+            if entry.is_dir && validate_bucket_name(&name).is_ok() {
+                buckets.push(name);
+            }
+        }
+        buckets.sort();
+        Ok(buckets)
+    }
+
+    /// Creates an empty bucket and records its metadata. Names held by an
+    /// alias of a renamed bucket or by a tombstone are taken.
+    pub fn create_bucket(&self, metadata: &BucketMetadata) -> StorageResult<()> {
+        let _span = self.span("create_bucket", Some(&metadata.name), None);
+        let bucket = &metadata.name;
+        validate_bucket_name(bucket)?;
+        let _guard = serialize(&self.namespace);
+        if self.bucket_exists(bucket) {
+            return Err(StorageError::BucketAlreadyOwnedByYou(bucket.to_string()));
+        }
+        if self.backend.exists(&self.bucket_path(bucket))
+            || self.aliases()?.contains_key(bucket)
+            || self.backend.exists(&self.tombstone_dir(bucket))
+        {
+            return Err(StorageError::BucketAlreadyExists(bucket.to_string()));
+        }
+        self.backend.create_dir(&self.bucket_path(bucket))?;
+        self.sync_parent(&self.bucket_path(bucket))?;
+        let content = serde_json::to_string_pretty(metadata)
+            .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        self.replace_file(&self.bucket_metadata_path(bucket), content.as_bytes())?;
+        info!("Created bucket {}", bucket);
+        Ok(())
+    }
+
+    /// Deletes an empty bucket, its metadata and any aliases pointing at it.
+    pub fn delete_bucket(&self, bucket: &str) -> StorageResult<()> {
+        let _span = self.span("delete_bucket", Some(bucket), None);
+        let _guard = serialize(&self.namespace);
+        if !self.bucket_exists(bucket) {
+            return Err(StorageError::NoSuchBucket(bucket.to_string()));
+        }
+        let _writes = self.writable(bucket)?;
+        // As in S3, noncurrent versions and delete markers count too.
+        if !self.versioned_keys(bucket, "")?.is_empty() {
+            return Err(StorageError::BucketNotEmpty(bucket.to_string()));
+        }
+        // Only empty directories left behind by deleted keys remain.
+        self.backend.remove_dir_all(&self.bucket_path(bucket))?;
+        self.uncache_bucket(bucket);
+        match self.backend.remove_file(&self.bucket_metadata_path(bucket)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        for dir in [
+            self.objects_metadata_dir(bucket),
+            self.versions_dir(bucket),
+            self.version_data_dir(bucket),
+        ] {
+            if self.backend.exists(&dir) {
+                self.backend.remove_dir_all(&dir)?;
+            }
+        }
+        // As in S3, uploads in progress do not keep a bucket from deletion.
+        let uploads = self.multipart_dir(bucket);
+        if self.backend.exists(&uploads) {
+            self.backend.remove_dir_all(&uploads)?;
+        }
+        let mut aliases = self.aliases()?;
+        let before = aliases.len();
+        aliases.retain(|_, target| target != bucket);
+        if aliases.len() != before {
+            self.write_aliases(&aliases)?;
+        }
+        info!("Deleted bucket {}", bucket);
+        Ok(())
+    }
+
+    /// Deletes `bucket` into a tombstone kept for `retention_secs`: the name
+    /// stays reserved and [`Storage::restore_bucket`] brings the bucket back
+    /// with its metadata, aliases and, `with_objects`, its objects. Without
+    /// `with_objects` the bucket must be empty, as for
+    /// [`Storage::delete_bucket`]. Uploads in progress are aborted either way.
+    pub fn tombstone_bucket(
+        &self,
+        bucket: &str,
+        deleted_by: &str,
+        retention_secs: u64,
+        with_objects: bool,
+    ) -> StorageResult<BucketTombstone> {
+        let _span = self.span("tombstone_bucket", Some(bucket), None);
+        let _guard = serialize(&self.namespace);
+        if !self.bucket_exists(bucket) {
+            return Err(StorageError::NoSuchBucket(bucket.to_string()));
+        }
+        let _writes = self.writable(bucket)?;
+        if !with_objects && !self.versioned_keys(bucket, "")?.is_empty() {
+            return Err(StorageError::BucketNotEmpty(bucket.to_string()));
+        }
+        let dir = self.tombstone_dir(bucket);
+        if self.backend.exists(&dir) {
+            // Only for a bucket made outside the server under a reserved name.
+            return Err(StorageError::Io(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("bucket {} already has a tombstone", bucket),
+            )));
+        }
+        let mut aliases = self.aliases()?;
+        let now = self.clock.now();
+        let expires = i64::try_from(retention_secs)
+            .ok()
+            .and_then(TimeDelta::try_seconds)
+            .and_then(|retention| now.checked_add_signed(retention))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+        let tombstone = BucketTombstone {
+            bucket: bucket.to_string(),
+            deleted: now.to_rfc3339_opts(SecondsFormat::Secs, true),
+            deleted_by: deleted_by.to_string(),
+            expires: expires.to_rfc3339_opts(SecondsFormat::Secs, true),
+            aliases: aliases
+                .iter()
+                .filter(|(_, target)| *target == bucket)
+                .map(|(alias, _)| alias.clone())
+                .collect(),
+            with_objects,
+        };
+        self.backend.create_dir_all(&dir)?;
+        // Written first, so that a tombstone interrupted halfway can still be
+        // restored.
+        self.write_tombstone(&tombstone)?;
+        for (live, kept) in self.tombstone_parts(bucket) {
+            if self.backend.exists(&live) {
+                self.backend.rename(&live, &kept)?;
+            }
+        }
+        self.uncache_bucket(bucket);
+        let uploads = self.multipart_dir(bucket);
+        if self.backend.exists(&uploads) {
+            self.backend.remove_dir_all(&uploads)?;
+        }
+        if !tombstone.aliases.is_empty() {
+            aliases.retain(|_, target| target != bucket);
+            self.write_aliases(&aliases)?;
+        }
+        info!(
+            "Deleted bucket {} into a tombstone kept until {}",
+            bucket, tombstone.expires
+        );
+        Ok(tombstone)
+    }
+
+    /// The tombstones of deleted buckets, by bucket name.
+    pub fn bucket_tombstones(&self) -> StorageResult<Vec<BucketTombstone>> {
+        let _span = self.span("bucket_tombstones", None, None);
+        let mut tombstones = Vec::new();
+        let dir = self.tombstones_dir();
+        if !self.backend.is_dir(&dir) {
+            return Ok(tombstones);
+        }
+        for entry in self.backend.read_dir(&dir)? {
+            let name = entry.name.to_string_lossy().to_string();
+            // Tombstones being purged (see `purge_bucket_tombstone`).
+            if name.starts_with('.') {
+                continue;
+            }
+            tombstones.extend(self.tombstone(&name)?);
+        }
+        tombstones.sort_by(|a, b| a.bucket.cmp(&b.bucket));
+        Ok(tombstones)
+    }
+
+    fn tombstone(&self, bucket: &str) -> StorageResult<Option<BucketTombstone>> {
+        if validate_bucket_name(bucket).is_err() {
+            return Ok(None);
+        }
+        match self
+            .backend
+            .read_to_string(&self.tombstone_dir(bucket).join("tombstone.json"))
+        {
+            Ok(content) => serde_json::from_str(&content)
+                .map(Some)
+                .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_tombstone(&self, tombstone: &BucketTombstone) -> StorageResult<()> {
+        let content = serde_json::to_string_pretty(tombstone)
+            .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        let path = self.tombstone_dir(&tombstone.bucket).join("tombstone.json");
+        self.replace_file(&path, content.as_bytes())?;
+        Ok(())
+    }
+
+    /// Brings a tombstoned bucket back as it was deleted, with those of its
+    /// aliases that have not been taken since.
+    pub fn restore_bucket(&self, bucket: &str) -> StorageResult<BucketTombstone> {
+        let _span = self.span("restore_bucket", Some(bucket), None);
+        let _guard = serialize(&self.namespace);
+        let Some(tombstone) = self.tombstone(bucket)? else {
+            return Err(StorageError::NoSuchBucket(bucket.to_string()));
+        };
+        // The data directory last, so the bucket is complete once it exists.
+        for (live, kept) in self.tombstone_parts(bucket).into_iter().rev() {
+            if self.backend.exists(&kept) {
+                if self.backend.exists(&live) {
+                    return Err(StorageError::BucketAlreadyExists(bucket.to_string()));
+                }
+                self.backend.rename(&kept, &live)?;
+            }
+        }
+        self.backend.create_dir_all(&self.bucket_path(bucket))?;
+        let mut aliases = self.aliases()?;
+        let before = aliases.len();
+        for alias in &tombstone.aliases {
+            if !aliases.contains_key(alias)
+                && !self.backend.exists(&self.bucket_path(alias))
+                && !self.backend.exists(&self.tombstone_dir(alias))
+            {
+                aliases.insert(alias.clone(), bucket.to_string());
+            }
+        }
+        if aliases.len() != before {
+            self.write_aliases(&aliases)?;
+        }
+        self.backend.remove_dir_all(&self.tombstone_dir(bucket))?;
+        info!("Restored bucket {} from its tombstone", bucket);
+        Ok(tombstone)
+    }
+
+    /// Removes a tombstoned bucket for good, freeing its name. The
+    /// tombstone is first renamed out of the way, so the bucket cannot be
+    /// restored while its files are removed; a purge interrupted halfway is
+    /// finished by [`Storage::finish_tombstone_purges`].
+    pub fn purge_bucket_tombstone(&self, bucket: &str) -> StorageResult<()> {
+        let _span = self.span("purge_bucket_tombstone", Some(bucket), None);
+        let purging =
+            self.tombstones_dir()
+                .join(format!(".{}.{}", bucket, uuid::Uuid::new_v4().simple()));
+        {
+            let _guard = serialize(&self.namespace);
+            if self.tombstone(bucket)?.is_none() {
+                return Err(StorageError::NoSuchBucket(bucket.to_string()));
+            }
+            self.backend.rename(&self.tombstone_dir(bucket), &purging)?;
+        }
+        self.backend.remove_dir_all(&purging)?;
+        info!("Purged the tombstone of bucket {}", bucket);
+        Ok(())
+    }
+
+    /// Removes what purges interrupted by a shutdown left behind.
+    pub fn finish_tombstone_purges(&self) -> StorageResult<()> {
+        let _span = self.span("finish_tombstone_purges", None, None);
+        let dir = self.tombstones_dir();
+        if !self.backend.is_dir(&dir) {
+            return Ok(());
+        }
+        for entry in self.backend.read_dir(&dir)? {
+            if entry.name.to_string_lossy().starts_with('.') {
+                self.backend.remove_dir_all(&dir.join(&entry.name))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Bucket metadata, derived from the directory for buckets created
+    /// outside the server (e.g. with `mkdir`).
+    pub fn bucket_metadata(&self, bucket: &str) -> StorageResult<BucketMetadata> {
+        let _span = self.span("bucket_metadata", Some(bucket), None);
+        if !self.bucket_exists(bucket) {
+            return Err(StorageError::NoSuchBucket(bucket.to_string()));
+        }
+        match self
+            .backend
+            .read_to_string(&self.bucket_metadata_path(bucket))
+        {
+            Ok(content) => serde_json::from_str(&content)
+                // The stored name is stale after a rename.
+                .map(|metadata| BucketMetadata {
+                    name: bucket.to_string(),
+                    ..metadata
+                })
+                .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => self.backfill_bucket_metadata(bucket),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Records metadata for a bucket directory that has none (created outside
+    /// the API), dated by the directory's modification time. Stored on first
+    /// read, so the reported creation date does not move as keys are added.
+    fn backfill_bucket_metadata(&self, bucket: &str) -> StorageResult<BucketMetadata> {
+        let fs_meta = self.backend.metadata(&self.bucket_path(bucket))?;
+        let created = fs_meta.modified;
+        let metadata = BucketMetadata {
+            name: bucket.to_string(),
+            region: None,
+            created: created.to_rfc3339_opts(SecondsFormat::Millis, true),
+            created_by: None,
+            notification: None,
+            tags: Default::default(),
+            versioning: None,
+            lifecycle: None,
+            policy: None,
+            acl: Vec::new(),
+            cors: None,
+            etag_algorithm: None,
+        };
+        let path = self.bucket_metadata_path(bucket);
+        let content = serde_json::to_string_pretty(&metadata)
+            .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        let tmp = path.with_extension(format!("json.{}.tmp", uuid::Uuid::new_v4().simple()));
+        self.backend.write(&tmp, content.as_bytes())?;
+        // Linking never replaces metadata stored concurrently, e.g. by
+        // another backfill or a configuration update.
+        let linked = self.backend.link(&tmp, &path);
+        self.backend.remove_file(&tmp)?;
+        match linked {
+            Ok(()) => {
+                debug!("Backfilled metadata of bucket {}", bucket);
+                Ok(metadata)
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => self.bucket_metadata(bucket),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replaces the stored metadata of an existing bucket, e.g. its
+    /// notification configuration.
+    pub fn update_bucket_metadata(&self, metadata: &BucketMetadata) -> StorageResult<()> {
+        let _span = self.span("update_bucket_metadata", Some(&metadata.name), None);
+        let _guard = serialize(&self.namespace);
+        if !self.bucket_exists(&metadata.name) {
+            return Err(StorageError::NoSuchBucket(metadata.name.clone()));
+        }
+        let content = serde_json::to_string_pretty(metadata)
+            .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        self.replace_file(
+            &self.bucket_metadata_path(&metadata.name),
+            content.as_bytes(),
+        )?;
+        Ok(())
+    }
+
+    /// Maps a requested bucket name to the bucket directory, following an
+    /// alias left behind by a rename. Unknown names are returned unchanged.
+    pub fn resolve_bucket(&self, bucket: &str) -> StorageResult<String> {
+        let _span = self.span("resolve_bucket", Some(bucket), None);
+        if self.bucket_exists(bucket) {
+            return Ok(bucket.to_string());
+        }
+        Ok(self
+            .aliases()?
+            .remove(bucket)
+            .unwrap_or_else(|| bucket.to_string()))
+    }
+
+    /// Old bucket names that still resolve to a renamed bucket.
+    pub fn aliases(&self) -> StorageResult<BTreeMap<String, String>> {
+        let _span = self.span("aliases", None, None);
+        match self.backend.read_to_string(&self.aliases_path()) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_aliases(&self, aliases: &BTreeMap<String, String>) -> StorageResult<()> {
+        let content = serde_json::to_string_pretty(aliases)
+            .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        self.replace_file(&self.aliases_path(), content.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn service_accounts(&self) -> StorageResult<Vec<ServiceAccount>> {
+        let _span = self.span("service_accounts", None, None);
+        match self.backend.read_to_string(&self.service_accounts_path()) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replaces the stored service accounts. The file holds secrets, so it is
+    /// only readable by the server's user.
+    pub fn write_service_accounts(&self, accounts: &[ServiceAccount]) -> StorageResult<()> {
+        let _span = self.span("write_service_accounts", None, None);
+        let content = serde_json::to_string_pretty(accounts)
+            .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        let tmp = self.staging_path();
+        self.backend.write_private(&tmp, content.as_bytes())?;
+        self.commit(&tmp, &self.service_accounts_path())
+            .inspect_err(|_| {
+                let _ = self.backend.remove_file(&tmp);
+            })?;
+        Ok(())
+    }
+
+    pub fn kms_keys(&self) -> StorageResult<Vec<KmsKey>> {
+        let _span = self.span("kms_keys", None, None);
+        match self.backend.read_to_string(&self.kms_keys_path()) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write_kms_keys(&self, keys: &[KmsKey]) -> StorageResult<()> {
+        let _span = self.span("write_kms_keys", None, None);
+        let content = serde_json::to_string_pretty(keys)
+            .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        self.replace_file(&self.kms_keys_path(), content.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn bucket_deletions(&self) -> StorageResult<Vec<BucketDeletion>> {
+        let _span = self.span("bucket_deletions", None, None);
+        match self.backend.read_to_string(&self.bucket_deletions_path()) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write_bucket_deletions(&self, deletions: &[BucketDeletion]) -> StorageResult<()> {
+        let _span = self.span("write_bucket_deletions", None, None);
+        let content = serde_json::to_string_pretty(deletions)
+            .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        self.replace_file(&self.bucket_deletions_path(), content.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn frozen_buckets(&self) -> StorageResult<Vec<BucketFreeze>> {
+        let _span = self.span("frozen_buckets", None, None);
+        match self.backend.read_to_string(&self.frozen_buckets_path()) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_frozen_buckets(&self, frozen: &[BucketFreeze]) -> StorageResult<()> {
+        let content = serde_json::to_string_pretty(frozen)
+            .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        self.replace_file(&self.frozen_buckets_path(), content.as_bytes())?;
+        Ok(())
+    }
+
+    /// Refuses writes to `freeze.bucket` until it is unfrozen. Waits for
+    /// writes in flight, so the bucket's files are stable once this returns.
+    /// Freezing a frozen bucket returns the existing freeze.
+    pub fn freeze_bucket(&self, freeze: BucketFreeze) -> StorageResult<BucketFreeze> {
+        let bucket = freeze.bucket.clone();
+        let _span = self.span("freeze_bucket", Some(&bucket), None);
+        if !self.bucket_exists(&freeze.bucket) {
+            return Err(StorageError::NoSuchBucket(freeze.bucket));
+        }
+        let _writes = self.writes.write().unwrap_or_else(PoisonError::into_inner);
+        let mut frozen = self.frozen_buckets()?;
+        if let Some(existing) = frozen.iter().find(|f| f.bucket == freeze.bucket) {
+            return Ok(existing.clone());
+        }
+        frozen.push(freeze.clone());
+        self.write_frozen_buckets(&frozen)?;
+        info!("Froze bucket {} for {}", freeze.bucket, freeze.frozen_by);
+        Ok(freeze)
+    }
+
+    /// Lifts a freeze. Returns whether the bucket was frozen.
+    pub fn unfreeze_bucket(&self, bucket: &str) -> StorageResult<bool> {
+        let _span = self.span("unfreeze_bucket", Some(bucket), None);
+        let _writes = self.writes.write().unwrap_or_else(PoisonError::into_inner);
+        let mut frozen = self.frozen_buckets()?;
+        let before = frozen.len();
+        frozen.retain(|f| f.bucket != bucket);
+        if frozen.len() == before {
+            if !self.bucket_exists(bucket) {
+                return Err(StorageError::NoSuchBucket(bucket.to_string()));
+            }
+            return Ok(false);
+        }
+        self.write_frozen_buckets(&frozen)?;
+        info!("Unfroze bucket {}", bucket);
+        Ok(true)
+    }
+
+    /// Admits a write to `bucket`, which lasts as long as the returned guard.
+    fn writable(&self, bucket: &str) -> StorageResult<RwLockReadGuard<'_, ()>> {
+        let guard = self.writes.read().unwrap_or_else(PoisonError::into_inner);
+        match self
+            .frozen_buckets()?
+            .into_iter()
+            .find(|f| f.bucket == bucket)
+        {
+            Some(freeze) => Err(StorageError::BucketFrozen(
+                bucket.to_string(),
+                freeze.retry_after_secs,
+            )),
+            None => Ok(guard),
+        }
+    }
+
+    /// Renames `from` to `to` with a single directory rename, moving its
+    /// metadata along. With `keep_alias`, the old name keeps resolving to the
+    /// new bucket and stays reserved.
+    pub fn rename_bucket(&self, from: &str, to: &str, keep_alias: bool) -> StorageResult<()> {
+        let _span = self.span("rename_bucket", Some(from), None);
+        validate_bucket_name(to)?;
+        let _guard = serialize(&self.namespace);
+        if !self.bucket_exists(from) {
+            return Err(StorageError::NoSuchBucket(from.to_string()));
+        }
+        let _writes = self.writable(from)?;
+        let mut aliases = self.aliases()?;
+        // Renaming back onto one of our own aliases reclaims that name.
+        if self.backend.exists(&self.bucket_path(to))
+            || aliases.get(to).is_some_and(|target| target != from)
+            || self.backend.exists(&self.tombstone_dir(to))
+        {
+            return Err(StorageError::BucketAlreadyExists(to.to_string()));
+        }
+
+        self.backend
+            .rename(&self.bucket_path(from), &self.bucket_path(to))?;
+        self.uncache_bucket(from);
+        let metadata = self.bucket_metadata_path(from);
+        if self.backend.exists(&metadata) {
+            self.backend
+                .rename(&metadata, &self.bucket_metadata_path(to))?;
+        }
+        for (from_dir, to_dir) in [
+            (
+                self.objects_metadata_dir(from),
+                self.objects_metadata_dir(to),
+            ),
+            (self.versions_dir(from), self.versions_dir(to)),
+            (self.version_data_dir(from), self.version_data_dir(to)),
+            (self.multipart_dir(from), self.multipart_dir(to)),
+        ] {
+            if self.backend.exists(&from_dir) {
+                self.backend.rename(&from_dir, &to_dir)?;
+            }
+        }
+
+        aliases.remove(to);
+        for target in aliases.values_mut() {
+            if target == from {
+                *target = to.to_string();
+            }
+        }
+        if keep_alias {
+            aliases.insert(from.to_string(), to.to_string());
+        }
+        self.write_aliases(&aliases)?;
+        info!(
+            "Renamed bucket {} to {}{}",
+            from,
+            to,
+            if keep_alias { " (alias kept)" } else { "" }
+        );
+        Ok(())
+    }
+
+    /// Writes `data` as `bucket/key`, replacing any existing object, and
+    /// records its metadata. In a versioned bucket the object replaced is
+    /// kept as a noncurrent version (see [`Storage::supersede`]). Returns the
+    /// stored metadata.
+    pub fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        data: &[u8],
+        attributes: ObjectAttributes,
+    ) -> StorageResult<ObjectMetadata> {
+        let mut staged = self.stage(self.etag_algorithm(bucket)?)?;
+        staged.write(data)?;
+        self.put_staged_object(bucket, key, staged, attributes)
+    }
+
+    /// [`put_object`](Self::put_object) with data received into a staging
+    /// file, which is moved into place rather than copied.
+    pub fn put_staged_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        staged: StagedData,
+        attributes: ObjectAttributes,
+    ) -> StorageResult<ObjectMetadata> {
+        let _span = self.span("put_object", Some(bucket), Some(key));
+        if !self.bucket_exists(bucket) {
+            return Err(StorageError::NoSuchBucket(bucket.to_string()));
+        }
+        let _writes = self.writable(bucket)?;
+        self.remember(bucket, key);
+        self.write_object(bucket, key, staged, attributes)
+    }
+
+    /// A new staging file to receive an upload into a piece at a time,
+    /// for [`put_staged_object`](Self::put_staged_object) or
+    /// [`put_staged_part`](Self::put_staged_part), hashed for an ETag of
+    /// `algorithm`: the bucket's for an object, the upload's for a part.
+    pub fn stage(&self, algorithm: EtagAlgorithm) -> StorageResult<StagedData> {
+        let path = self.staging_path();
+        let file = self.backend.create(&path)?;
+        Ok(StagedData {
+            backend: self.backend.clone(),
+            path,
+            file: Some(file),
+            hasher: EtagHasher::new(algorithm),
+            size: 0,
+            stored: false,
+        })
+    }
+
+    /// The algorithm of the ETags of objects written to `bucket` now.
+    pub fn etag_algorithm(&self, bucket: &str) -> StorageResult<EtagAlgorithm> {
+        Ok(self
+            .bucket_metadata(bucket)?
+            .etag_algorithm
+            .unwrap_or_default())
+    }
+
+    fn write_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        staged: StagedData,
+        attributes: ObjectAttributes,
+    ) -> StorageResult<ObjectMetadata> {
+        let object_path = self.object_path(bucket, key)?;
+        if is_folder_marker(key) && staged.size() > 0 {
+            return Err(StorageError::InvalidObjectName(
+                key.to_string(),
+                "keys ending in '/' are folder markers and must be empty".to_string(),
+            ));
+        }
+        let versioning = self.versioning(bucket)?;
+        let _versions = versioning.map(|_| serialize(&self.versions));
+        let version_id = match versioning {
+            Some(status) => self.supersede(bucket, key, status)?,
+            None => None,
+        };
+        let (size, etag, etag_algorithm) = (staged.size(), staged.etag(), staged.algorithm());
+        if is_folder_marker(key) {
+            // Its directory is all a marker has on disk; the metadata file
+            // is what makes it an object rather than just a prefix.
+            self.create_marker_dir(key, &object_path)?;
+        } else {
+            let _dirs = self.create_parent_dirs(key, &object_path)?;
+            staged
+                .store(self, &object_path)
+                .map_err(|e| key_conflict(key, e))?;
+        }
+        self.uncache(bucket, key);
+
+        let metadata = ObjectMetadata {
+            key: key.to_string(),
+            version_id,
+            size,
+            etag,
+            last_modified: self
+                .clock
+                .now()
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+            etag_algorithm: Some(etag_algorithm),
+            attributes,
+        };
+        self.write_object_metadata(bucket, &metadata)?;
+        if is_folder_marker(key) {
+            // Again, in case a delete under the marker removed it before
+            // the metadata file was there to keep it.
+            self.create_marker_dir(key, &object_path)?;
+        }
+        Ok(metadata)
+    }
+
+    /// Creates the directories `path` goes in; the returned guard is to be
+    /// held until its file is in them (see `dirs`).
+    fn create_parent_dirs(&self, key: &str, path: &Path) -> StorageResult<RwLockReadGuard<'_, ()>> {
+        let guard = self.dirs.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some(parent) = path.parent() {
+            self.create_dirs(parent).map_err(|e| key_conflict(key, e))?;
+        }
+        Ok(guard)
+    }
+
+    /// Creates the directory of folder marker `key`. Until its metadata file
+    /// is written, an empty marker directory can be removed by a delete of a
+    /// key under it, so it is created again afterwards.
+    fn create_marker_dir(&self, key: &str, path: &Path) -> StorageResult<()> {
+        let _dirs = self.dirs.read().unwrap_or_else(PoisonError::into_inner);
+        self.create_dirs(path).map_err(|e| key_conflict(key, e))?;
+        Ok(())
+    }
+
+    /// Creates a directory and any missing parents, syncing the directories
+    /// that got one of them as a new entry (see `fsync`).
+    fn create_dirs(&self, dir: &Path) -> io::Result<()> {
+        if !self.fsync || self.backend.is_dir(dir) {
+            return self.backend.create_dir_all(dir);
+        }
+        let mut first_missing = dir;
+        while let Some(parent) = first_missing.parent()
+            && !self.backend.is_dir(parent)
+        {
+            first_missing = parent;
+        }
+        self.backend.create_dir_all(dir)?;
+        for created in dir.ancestors().take_while(|d| d.starts_with(first_missing)) {
+            self.sync_parent(created)?;
+        }
+        Ok(())
+    }
+
+    /// A new file name in the staging directory, for files written before
+    /// they are renamed into place.
+    fn staging_path(&self) -> PathBuf {
+        self.base_path
+            .join(SYSTEM_DIR)
+            .join(STAGING_DIR)
+            .join(uuid::Uuid::new_v4().simple().to_string())
+    }
+
+    /// Moves the file at `from` to `to`, replacing any file there at once,
+    /// so that readers (and a crash) see either file but never part of one.
+    /// With `fsync`, the file reaches the disk before it is moved, and the
+    /// directory entry after.
+    fn commit(&self, from: &Path, to: &Path) -> io::Result<()> {
+        if self.fsync {
+            self.backend.sync(from)?;
+        }
+        self.backend.rename(from, to)?;
+        self.sync_parent(to)
+    }
+
+    /// Replaces the file at `path` with `data`, written aside first (see
+    /// [`commit`](Self::commit)).
+    fn replace_file(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let tmp = self.staging_path();
+        self.backend.write(&tmp, data)?;
+        self.commit(&tmp, path).inspect_err(|_| {
+            let _ = self.backend.remove_file(&tmp);
+        })
+    }
+
+    /// With `fsync`, flushes the entries of the directory holding `path`.
+    fn sync_parent(&self, path: &Path) -> io::Result<()> {
+        match path.parent() {
+            Some(parent) if self.fsync => self.backend.sync(parent),
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes the directories of the prefixes of `key` that its removal
+    /// left empty, deepest first, in the data and in the metadata tree, so
+    /// that they do not keep a key of the same name from being written.
+    /// Folder marker directories stay, and so do those above them.
+    fn prune_dirs(&self, bucket: &str, key: &str) {
+        let _dirs = self.dirs.write().unwrap_or_else(PoisonError::into_inner);
+        let data_dir = self.bucket_path(bucket);
+        let metadata_dir = self.objects_metadata_dir(bucket);
+        let mut prefix = key.rsplit_once('/').map(|(prefix, _)| prefix);
+        while let Some(dir) = prefix.filter(|dir| !dir.is_empty()) {
+            // The metadata file of folder marker `<dir>/`.
+            let path = self.key_path(dir);
+            if self
+                .backend
+                .exists(&metadata_dir.join(&*path).join(".json"))
+            {
+                break;
+            }
+            // Best effort: a directory that is not empty (or already gone)
+            // is simply left as it is.
+            let _ = self.backend.remove_dir(&metadata_dir.join(&*path));
+            let data = data_dir.join(&*path);
+            if self.backend.remove_dir(&data).is_err() && self.backend.exists(&data) {
+                break;
+            }
+            prefix = dir.rsplit_once('/').map(|(prefix, _)| prefix);
+        }
+    }
+
+    /// Appends `data` to an appendable object whose size is `position`. A
+    /// missing object is created with `attributes` when `position` is 0;
+    /// otherwise its stored attributes are kept.
+    pub fn append_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        position: u64,
+        mut staged: StagedData,
+        attributes: ObjectAttributes,
+    ) -> StorageResult<ObjectMetadata> {
+        let _span = self.span("append_object", Some(bucket), Some(key));
+        if is_folder_marker(key) {
+            return Err(StorageError::ObjectNotAppendable(key.to_string()));
+        }
+        let _guard = serialize(&self.appends);
+        let mut metadata = match self.head_object(bucket, key) {
+            Ok(metadata) => metadata,
+            Err(StorageError::NoSuchKey(_)) if position == 0 => {
+                let attributes = ObjectAttributes {
+                    appendable: true,
+                    ..attributes
+                };
+                let _writes = self.writable(bucket)?;
+                self.remember(bucket, key);
+                return self.write_object(bucket, key, staged, attributes);
+            }
+            Err(StorageError::NoSuchKey(_)) => {
+                return Err(StorageError::PositionNotEqualToLength(key.to_string(), 0));
+            }
+            Err(e) => return Err(e),
+        };
+        if !metadata.attributes.appendable {
+            return Err(StorageError::ObjectNotAppendable(key.to_string()));
+        }
+        if metadata.size != position {
+            return Err(StorageError::PositionNotEqualToLength(
+                key.to_string(),
+                metadata.size,
+            ));
+        }
+        let _writes = self.writable(bucket)?;
+        self.remember(bucket, key);
+        let object_path = self.object_path(bucket, key)?;
+        let appended = self.append_staged(&mut staged, &object_path);
+        self.uncache(bucket, key);
+        appended?;
+
+        // The ETag stays the digest of the whole object, in the algorithm it
+        // was created with, which means re-reading it.
+        let mut hasher = EtagHasher::new(metadata.etag_algorithm.unwrap_or_default());
+        io::copy(&mut self.backend.open(&object_path)?, &mut hasher)?;
+        metadata.size += staged.size();
+        metadata.etag = hasher.etag();
+        metadata.last_modified = self
+            .clock
+            .now()
+            .to_rfc3339_opts(SecondsFormat::Millis, true);
+        self.write_object_metadata(bucket, &metadata)?;
+        Ok(metadata)
+    }
+
+    /// Appends what was staged to the file at `path`, a chunk at a time.
+    fn append_staged(&self, staged: &mut StagedData, path: &Path) -> io::Result<()> {
+        // The writer goes first: dropping it may still write the file.
+        if let Some(mut file) = staged.file.take() {
+            file.flush()?;
+        }
+        let mut source = self.backend.open(&staged.path)?;
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let n = io::Read::read(&mut source, &mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+            self.backend.append(path, &buf[..n], false)?;
+        }
+    }
+
+    /// The metadata and data of `bucket/key`, from the read cache when it
+    /// is enabled and holds the object.
+    pub fn get_object(&self, bucket: &str, key: &str) -> StorageResult<(ObjectMetadata, Bytes)> {
+        let _span = self.span("get_object", Some(bucket), Some(key));
+        let generation = match &self.read_cache {
+            Some(cache) => match cache.get(bucket, key) {
+                Some(cached) => return Ok(cached),
+                None => Some(cache.generation()),
+            },
+            None => None,
+        };
+        let metadata = self.head_object(bucket, key)?;
+        let data = if is_folder_marker(key) {
+            Bytes::new()
+        } else {
+            Bytes::from(self.backend.read(&self.object_path(bucket, key)?)?)
+        };
+        if let (Some(cache), Some(generation)) = (&self.read_cache, generation) {
+            cache.insert(generation, bucket, key, &metadata, &data);
+        }
+        Ok((metadata, data))
+    }
+
+    /// Opens the data of `bucket/key`, to stream it without reading it into
+    /// memory. Folder markers have no data file; opening one is an error.
+    pub fn open_object(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> StorageResult<(ObjectMetadata, ObjectReader)> {
+        let _span = self.span("open_object", Some(bucket), Some(key));
+        let metadata = self.head_object(bucket, key)?;
+        if is_folder_marker(key) {
+            return Err(StorageError::InvalidObjectName(
+                key.to_string(),
+                "folder markers have no data".to_string(),
+            ));
+        }
+        let reader = self.backend.reader(&self.object_path(bucket, key)?, 0)?;
+        Ok((metadata, reader))
+    }
+
+    /// The metadata of `bucket/key` and a reader of all its data.
+    pub fn object_reader(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> StorageResult<(ObjectMetadata, ObjectReader)> {
+        self.get_object_range(bucket, key, 0, u64::MAX)
+    }
+
+    /// The metadata of `bucket/key` and a reader of `len` bytes of its data
+    /// from `offset`, fewer where the object ends first. The file is read
+    /// from the offset on rather than into memory; objects the read cache
+    /// holds, or would hold, go through it.
+    pub fn get_object_range(
+        &self,
+        bucket: &str,
+        key: &str,
+        offset: u64,
+        len: u64,
+    ) -> StorageResult<(ObjectMetadata, ObjectReader)> {
+        let _span = self.span("get_object_range", Some(bucket), Some(key));
+        if let Some((metadata, data)) = self.read_cache.as_ref().and_then(|c| c.get(bucket, key)) {
+            return Ok((metadata, slice_reader(data, offset, len)));
+        }
+        let metadata = self.head_object(bucket, key)?;
+        // Read whole objects small enough to fill the cache for next time.
+        if self
+            .read_cache
+            .as_ref()
+            .is_some_and(|c| c.caches(metadata.size))
+        {
+            let (metadata, data) = self.get_object(bucket, key)?;
+            return Ok((metadata, slice_reader(data, offset, len)));
+        }
+        if is_folder_marker(key) {
+            return Ok((metadata, Box::new(tokio::io::empty())));
+        }
+        let reader = self
+            .backend
+            .reader(&self.object_path(bucket, key)?, offset)?;
+        let len = len.min(metadata.size.saturating_sub(offset));
+        Ok((metadata, Box::new(reader.take(len))))
+    }
+
+    /// [`get_object_range`](Self::get_object_range) as clients see it, like
+    /// [`get_object_eventual`](Self::get_object_eventual).
+    pub fn get_object_range_eventual(
+        &self,
+        bucket: &str,
+        key: &str,
+        offset: u64,
+        len: u64,
+    ) -> StorageResult<(ObjectMetadata, ObjectReader)> {
+        let _span = self.span("get_object_range_eventual", Some(bucket), Some(key));
+        if let Some((metadata, data)) = self.consistency.as_ref().and_then(|c| c.read(bucket, key))
+        {
+            return Ok((metadata, slice_reader(data, offset, len)));
+        }
+        self.get_object_range(bucket, key, offset, len)
+    }
+
+    /// [`get_object`](Self::get_object) as clients see it: in the eventual
+    /// consistency test mode, the object as it was before a recent
+    /// overwrite or delete.
+    pub fn get_object_eventual(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> StorageResult<(ObjectMetadata, Bytes)> {
+        let _span = self.span("get_object_eventual", Some(bucket), Some(key));
+        if let Some(stale) = self.consistency.as_ref().and_then(|c| c.read(bucket, key)) {
+            return Ok(stale);
+        }
+        self.get_object(bucket, key)
+    }
+
+    /// [`head_object`](Self::head_object) as clients see it, like
+    /// [`get_object_eventual`](Self::get_object_eventual).
+    pub fn head_object_eventual(&self, bucket: &str, key: &str) -> StorageResult<ObjectMetadata> {
+        let _span = self.span("head_object_eventual", Some(bucket), Some(key));
+        if let Some(stale) = self.consistency.as_ref().and_then(|c| c.head(bucket, key)) {
+            return Ok(stale);
+        }
+        self.head_object(bucket, key)
+    }
+
+    /// [`list_keys`](Self::list_keys) as listings show it: in the eventual
+    /// consistency test mode, recently created keys are left out and
+    /// recently deleted ones still listed.
+    pub fn list_keys_eventual(&self, bucket: &str, prefix: &str) -> StorageResult<Vec<String>> {
+        let _span = self.span("list_keys_eventual", Some(bucket), None);
+        let mut keys = self.list_keys(bucket, prefix)?;
+        let Some(consistency) = &self.consistency else {
+            return Ok(keys);
+        };
+        for key in consistency.listing_writes(bucket, prefix) {
+            let position = keys.binary_search(&key);
+            match (consistency.listed(bucket, &key), position) {
+                (Some(Listed::Hidden), Ok(index)) => {
+                    keys.remove(index);
+                }
+                (Some(Listed::Stale(_)), Err(index)) => keys.insert(index, key),
+                _ => {}
+            }
+        }
+        Ok(keys)
+    }
+
+    /// The metadata a listing shows for `bucket/key`, one of the keys of
+    /// [`list_keys_eventual`](Self::list_keys_eventual): as it was before a
+    /// recent write in the eventual consistency test mode.
+    pub fn list_metadata_eventual(&self, bucket: &str, key: &str) -> StorageResult<ObjectMetadata> {
+        let _span = self.span("list_metadata_eventual", Some(bucket), Some(key));
+        if let Some(Listed::Stale(metadata)) = self
+            .consistency
+            .as_ref()
+            .and_then(|c| c.listed(bucket, key))
+        {
+            return Ok(*metadata);
+        }
+        self.head_object(bucket, key)
+    }
+
+    /// All keys in `bucket` starting with `prefix`, sorted in S3 order
+    /// (byte-wise UTF-8), folder markers included. Only the directory the
+    /// prefix points into is walked.
+    pub fn list_keys(&self, bucket: &str, prefix: &str) -> StorageResult<Vec<String>> {
+        let _span = self.span("list_keys", Some(bucket), None);
+        if !self.bucket_exists(bucket) {
+            return Err(StorageError::NoSuchBucket(bucket.to_string()));
+        }
+        let dir_prefix = prefix_dir(prefix);
+        let mut keys = Vec::new();
+        if !is_key_dir(dir_prefix) {
+            return Ok(keys);
+        }
+        let start = self.bucket_path(bucket).join(&*self.key_path(dir_prefix));
+        if self.backend.is_dir(&start) {
+            let metadata_dir = self
+                .objects_metadata_dir(bucket)
+                .join(&*self.key_path(dir_prefix));
+            if !dir_prefix.is_empty() && self.backend.is_file(&metadata_dir.join(".json")) {
+                keys.push(dir_prefix.to_string());
+            }
+            let backend = &*self.backend;
+            collect_keys(
+                backend,
+                &start,
+                &metadata_dir,
+                dir_prefix,
+                self.escape_keys,
+                &mut keys,
+            )?;
+        }
+        keys.retain(|key| key.starts_with(prefix));
+        keys.sort();
+        Ok(keys)
+    }
+
+    pub fn head_object(&self, bucket: &str, key: &str) -> StorageResult<ObjectMetadata> {
+        let _span = self.span("head_object", Some(bucket), Some(key));
+        if !self.bucket_exists(bucket) {
+            return Err(StorageError::NoSuchBucket(bucket.to_string()));
+        }
+        let object_path = self.object_path(bucket, key)?;
+        let exists = if is_folder_marker(key) {
+            self.backend.is_dir(&object_path)
+                && self
+                    .backend
+                    .is_file(&self.object_metadata_path(bucket, key))
+        } else {
+            self.backend.is_file(&object_path)
+        };
+        if !exists {
+            return Err(StorageError::NoSuchKey(key.to_string()));
+        }
+        match self
+            .backend
+            .read_to_string(&self.object_metadata_path(bucket, key))
+        {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                // File placed directly into the bucket directory: derive what we can.
+                warn!(
+                    "no metadata for {}/{}, deriving it from the file",
+                    bucket, key
+                );
+                let algorithm = self.etag_algorithm(bucket)?;
+                let metadata = derived_metadata(&*self.backend, key, &object_path, algorithm)?;
+                self.backfill_object_metadata(bucket, &metadata)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Stores metadata derived for a file that has none, so the file is
+    /// hashed once rather than on every HEAD and listing, and its
+    /// Last-Modified stays put. Returns the metadata stored for the key,
+    /// which is another's if a write stored some meanwhile.
+    fn backfill_object_metadata(
+        &self,
+        bucket: &str,
+        metadata: &ObjectMetadata,
+    ) -> StorageResult<ObjectMetadata> {
+        let path = self.object_metadata_path(bucket, &metadata.key);
+        let content = serde_json::to_string(metadata)
+            .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        let tmp = path.with_extension(format!("json.{}.tmp", uuid::Uuid::new_v4().simple()));
+        let dirs = self.dirs.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some(parent) = path.parent() {
+            self.backend.create_dir_all(parent)?;
+        }
+        self.backend.write(&tmp, content.as_bytes())?;
+        // Linking never replaces metadata a concurrent write stored.
+        let linked = self.backend.link(&tmp, &path);
+        let removed = self.backend.remove_file(&tmp);
+        drop(dirs);
+        removed?;
+        match linked {
+            Ok(()) => {
+                debug!("Backfilled metadata of {}/{}", bucket, metadata.key);
+                Ok(metadata.clone())
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                self.head_object(bucket, &metadata.key)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replaces the tags of an object, or of one of its versions, leaving
+    /// its data, other attributes and Last-Modified as they are.
+    pub fn put_object_tags(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        tags: BTreeMap<String, String>,
+    ) -> StorageResult<ObjectMetadata> {
+        let _span = self.span("put_object_tags", Some(bucket), Some(key));
+        self.update_object_attributes(bucket, key, version_id, |attributes| attributes.tags = tags)
+    }
+
+    /// Replaces the ACL of an object, or of one of its versions, like
+    /// [`put_object_tags`](Self::put_object_tags).
+    pub fn put_object_acl(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        acl: Vec<AclGrant>,
+    ) -> StorageResult<ObjectMetadata> {
+        let _span = self.span("put_object_acl", Some(bucket), Some(key));
+        self.update_object_attributes(bucket, key, version_id, |attributes| attributes.acl = acl)
+    }
+
+    fn update_object_attributes(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        update: impl FnOnce(&mut ObjectAttributes),
+    ) -> StorageResult<ObjectMetadata> {
+        let _writes = self.writable(bucket)?;
+        let _versions = serialize(&self.versions);
+        if let Some(version_id) = version_id
+            && self.current_version(bucket, key, version_id)?.is_none()
+        {
+            let mut history = self.object_history(bucket, key)?;
+            let version = history
+                .iter_mut()
+                .find(|version| version.version_id() == version_id)
+                .ok_or_else(|| StorageError::NoSuchVersion(version_id.to_string()))?;
+            let ObjectVersion::Object { metadata, .. } = version else {
+                return Err(StorageError::DeleteMarker(version_id.to_string()));
+            };
+            update(&mut metadata.attributes);
+            let metadata = (**metadata).clone();
+            self.write_history(bucket, key, &history)?;
+            return Ok(metadata);
+        }
+        let mut metadata = self.head_object(bucket, key)?;
+        update(&mut metadata.attributes);
+        self.write_object_metadata(bucket, &metadata)?;
+        Ok(metadata)
+    }
+
+    /// Checks the metadata of one key against its data and rewrites it when
+    /// it is missing, unreadable or has the wrong size or ETag. Attributes of
+    /// readable metadata are kept, but for the additional checksum, and the
+    /// ETag is checked in the algorithm it records; new metadata gets the
+    /// bucket's. The ETag of a multipart upload cannot be derived without
+    /// its part sizes, so only its size is checked.
+    pub fn reindex_object(&self, bucket: &str, key: &str) -> StorageResult<ReindexOutcome> {
+        let _span = self.span("reindex_object", Some(bucket), Some(key));
+        let _writes = self.writable(bucket)?;
+        let stored = match self
+            .backend
+            .read_to_string(&self.object_metadata_path(bucket, key))
+        {
+            Ok(content) => Some(serde_json::from_str::<ObjectMetadata>(&content)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let algorithm = match &stored {
+            Some(Ok(stored)) => stored.etag_algorithm.unwrap_or_default(),
+            _ => self.etag_algorithm(bucket)?,
+        };
+        let object_path = self.object_path(bucket, key)?;
+        let derived = derived_metadata(&*self.backend, key, &object_path, algorithm)?;
+        let (metadata, outcome) = match stored {
+            Some(Ok(stored))
+                if stored.key == key
+                    && stored.size == derived.size
+                    && (stored.etag == derived.etag || is_multipart_etag(&stored.etag)) =>
+            {
+                return Ok(ReindexOutcome::Consistent);
+            }
+            Some(Ok(stored)) => (
+                ObjectMetadata {
+                    version_id: stored.version_id,
+                    // The checksum was of other data.
+                    attributes: ObjectAttributes {
+                        checksum_algorithm: None,
+                        checksum: None,
+                        ..stored.attributes
+                    },
+                    ..derived
+                },
+                ReindexOutcome::Repaired,
+            ),
+            Some(Err(_)) => (derived, ReindexOutcome::Replaced),
+            None => (derived, ReindexOutcome::Created),
+        };
+        self.write_object_metadata(bucket, &metadata)?;
+        Ok(outcome)
+    }
+
+    /// Keys with stored metadata but no data (or, for folder markers, no
+    /// directory), sorted.
+    pub fn orphaned_object_metadata(&self, bucket: &str) -> StorageResult<Vec<String>> {
+        let _span = self.span("orphaned_object_metadata", Some(bucket), None);
+        let mut keys = Vec::new();
+        let dir = self.objects_metadata_dir(bucket);
+        if self.backend.is_dir(&dir) {
+            collect_metadata_keys(&*self.backend, &dir, "", self.escape_keys, &mut keys)?;
+        }
+        let bucket_path = self.bucket_path(bucket);
+        keys.retain(|key| {
+            let path = bucket_path.join(&*self.key_path(key));
+            if is_folder_marker(key) {
+                !self.backend.is_dir(&path)
+            } else {
+                !self.backend.is_file(&path)
+            }
+        });
+        keys.sort();
+        Ok(keys)
+    }
+
+    pub fn remove_object_metadata(&self, bucket: &str, key: &str) -> StorageResult<()> {
+        let _span = self.span("remove_object_metadata", Some(bucket), Some(key));
+        let _writes = self.writable(bucket)?;
+        let removed = self
+            .backend
+            .remove_file(&self.object_metadata_path(bucket, key));
+        self.uncache(bucket, key);
+        match removed {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Deletes `bucket/key`. In a versioned bucket the object is kept as a
+    /// noncurrent version (unless it is the `null` version and versioning
+    /// is suspended) and a delete marker, returned, becomes the latest
+    /// version; deleting a key with no current object is then no error.
+    pub fn delete_object(&self, bucket: &str, key: &str) -> StorageResult<Option<ObjectVersion>> {
+        let _span = self.span("delete_object", Some(bucket), Some(key));
+        if !self.bucket_exists(bucket) {
+            return Err(StorageError::NoSuchBucket(bucket.to_string()));
+        }
+        self.object_path(bucket, key)?;
+        let _writes = self.writable(bucket)?;
+        self.remember(bucket, key);
+        let Some(status) = self.versioning(bucket)? else {
+            self.remove_current(bucket, key)?;
+            self.prune_dirs(bucket, key);
+            return Ok(None);
+        };
+        let _versions = serialize(&self.versions);
+        let marker = ObjectVersion::DeleteMarker {
+            key: key.to_string(),
+            version_id: self.supersede(bucket, key, status)?,
+            last_modified: self
+                .clock
+                .now()
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+        };
+        let mut history = self.object_history(bucket, key)?;
+        history.insert(0, marker.clone());
+        self.write_history(bucket, key, &history)?;
+        self.prune_dirs(bucket, key);
+        Ok(Some(marker))
+    }
+
+    /// Permanently deletes version `version_id` of `bucket/key`, object or
+    /// delete marker, and returns it; `None` when there is no such version.
+    /// When it was the latest version, the newest remaining one, if an
+    /// object, becomes current again.
+    pub fn delete_object_version(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: &str,
+    ) -> StorageResult<Option<ObjectVersion>> {
+        let _span = self.span("delete_object_version", Some(bucket), Some(key));
+        if !self.bucket_exists(bucket) {
+            return Err(StorageError::NoSuchBucket(bucket.to_string()));
+        }
+        self.object_path(bucket, key)?;
+        let _writes = self.writable(bucket)?;
+        self.remember(bucket, key);
+        let _versions = serialize(&self.versions);
+        if let Some(metadata) = self.current_version(bucket, key, version_id)? {
+            self.remove_current(bucket, key)?;
+            self.promote(bucket, key)?;
+            self.prune_dirs(bucket, key);
+            return Ok(Some(ObjectVersion::Object {
+                metadata: Box::new(metadata),
+                data: None,
+            }));
+        }
+        let mut history = self.object_history(bucket, key)?;
+        let Some(idx) = history.iter().position(|v| v.version_id() == version_id) else {
+            return Ok(None);
+        };
+        let removed = history.remove(idx);
+        self.remove_version_data(bucket, &removed)?;
+        self.write_history(bucket, key, &history)?;
+        self.promote(bucket, key)?;
+        Ok(Some(removed))
+    }
+
+    /// The metadata of version `version_id` of `bucket/key`, current or not.
+    pub fn head_object_version(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: &str,
+    ) -> StorageResult<ObjectMetadata> {
+        let _span = self.span("head_object_version", Some(bucket), Some(key));
+        if let Some(metadata) = self.current_version(bucket, key, version_id)? {
+            return Ok(metadata);
+        }
+        Ok(self.noncurrent_version(bucket, key, version_id)?.0)
+    }
+
+    /// The metadata and data of version `version_id` of `bucket/key`,
+    /// current or not.
+    pub fn get_object_version(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: &str,
+    ) -> StorageResult<(ObjectMetadata, Bytes)> {
+        let _span = self.span("get_object_version", Some(bucket), Some(key));
+        if self.current_version(bucket, key, version_id)?.is_some() {
+            let (metadata, data) = self.get_object(bucket, key)?;
+            // Unless superseded in the meantime, in which case it is in the
+            // history by now.
+            if metadata.version_id() == version_id {
+                return Ok((metadata, data));
+            }
+        }
+        let (metadata, data) = self.noncurrent_version(bucket, key, version_id)?;
+        let data = match data {
+            Some(file) => Bytes::from(
+                self.backend
+                    .read(&self.version_data_dir(bucket).join(file))?,
+            ),
+            None => Bytes::new(),
+        };
+        Ok((metadata, data))
+    }
+
+    /// Keys in `bucket` starting with `prefix` that have any version: a
+    /// current object, noncurrent versions or delete markers. Sorted like
+    /// [`Storage::list_keys`].
+    pub fn versioned_keys(&self, bucket: &str, prefix: &str) -> StorageResult<Vec<String>> {
+        let _span = self.span("versioned_keys", Some(bucket), None);
+        let mut keys = self.list_keys(bucket, prefix)?;
+        let dir_prefix = prefix_dir(prefix);
+        let dir = self.versions_dir(bucket).join(&*self.key_path(dir_prefix));
+        if is_key_dir(dir_prefix) && self.backend.is_dir(&dir) {
+            let start = keys.len();
+            collect_metadata_keys(
+                &*self.backend,
+                &dir,
+                dir_prefix,
+                self.escape_keys,
+                &mut keys,
+            )?;
+            let mut histories = keys.split_off(start);
+            histories.retain(|key| key.starts_with(prefix));
+            keys.append(&mut histories);
+            keys.sort();
+            keys.dedup();
+        }
+        Ok(keys)
+    }
+
+    /// Every version of `bucket/key`, newest first: the current object, if
+    /// any, then its version history.
+    pub fn object_versions(&self, bucket: &str, key: &str) -> StorageResult<Vec<ObjectVersion>> {
+        let _span = self.span("object_versions", Some(bucket), Some(key));
+        let mut versions = match self.head_object(bucket, key) {
+            Ok(metadata) => vec![ObjectVersion::Object {
+                metadata: Box::new(metadata),
+                data: None,
+            }],
+            Err(StorageError::NoSuchKey(_)) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        versions.extend(self.object_history(bucket, key)?);
+        Ok(versions)
+    }
+
+    /// Noncurrent versions and delete markers of `bucket/key`, newest first.
+    pub fn object_history(&self, bucket: &str, key: &str) -> StorageResult<Vec<ObjectVersion>> {
+        match self.backend.read_to_string(&self.history_path(bucket, key)) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_history(
+        &self,
+        bucket: &str,
+        key: &str,
+        history: &[ObjectVersion],
+    ) -> StorageResult<()> {
+        let path = self.history_path(bucket, key);
+        if history.is_empty() {
+            return match self.backend.remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            };
+        }
+        if let Some(parent) = path.parent() {
+            self.create_dirs(parent)?;
+        }
+        let content = serde_json::to_string_pretty(history)
+            .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        self.replace_file(&path, content.as_bytes())?;
+        Ok(())
+    }
+
+    /// Versioning state of `bucket`.
+    fn versioning(&self, bucket: &str) -> StorageResult<Option<VersioningStatus>> {
+        Ok(self.bucket_metadata(bucket)?.versioning)
+    }
+
+    /// The current object of `bucket/key` when it is version `version_id`.
+    fn current_version(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: &str,
+    ) -> StorageResult<Option<ObjectMetadata>> {
+        match self.head_object(bucket, key) {
+            Ok(metadata) if metadata.version_id() == version_id => Ok(Some(metadata)),
+            Ok(_) | Err(StorageError::NoSuchKey(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Metadata and data file of a version in the history of `bucket/key`.
+    fn noncurrent_version(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: &str,
+    ) -> StorageResult<(ObjectMetadata, Option<String>)> {
+        let history = self.object_history(bucket, key)?;
+        match history.into_iter().find(|v| v.version_id() == version_id) {
+            Some(ObjectVersion::Object { metadata, data }) => Ok((*metadata, data)),
+            Some(ObjectVersion::DeleteMarker { .. }) => {
+                Err(StorageError::DeleteMarker(version_id.to_string()))
+            }
+            None => Err(StorageError::NoSuchVersion(version_id.to_string())),
+        }
+    }
+
+    /// Makes way for a new latest version of `bucket/key`, object or delete
+    /// marker, and returns its version ID. With versioning enabled that is a
+    /// new ID and the current object is kept in the history; suspended, it
+    /// is `null` and any existing `null` version, current or not, is removed.
+    /// The caller holds the `versions` lock.
+    fn supersede(
+        &self,
+        bucket: &str,
+        key: &str,
+        status: VersioningStatus,
+    ) -> StorageResult<Option<String>> {
+        let mut history = self.object_history(bucket, key)?;
+        let version_id = match status {
+            VersioningStatus::Enabled => {
+                self.archive_current(bucket, key, &mut history)?;
+                Some(uuid::Uuid::new_v4().simple().to_string())
+            }
+            VersioningStatus::Suspended => {
+                match self.current_version(bucket, key, NULL_VERSION_ID)? {
+                    Some(_) => self.remove_current(bucket, key)?,
+                    None => self.archive_current(bucket, key, &mut history)?,
+                }
+                let (null, kept) = history
+                    .into_iter()
+                    .partition::<Vec<_>, _>(|v| v.version_id() == NULL_VERSION_ID);
+                for version in &null {
+                    self.remove_version_data(bucket, version)?;
+                }
+                history = kept;
+                None
+            }
+        };
+        self.write_history(bucket, key, &history)?;
+        Ok(version_id)
+    }
+
+    /// Moves the current object of `bucket/key`, if any, to the front of
+    /// `history`, its data to the bucket's version data directory.
+    fn archive_current(
+        &self,
+        bucket: &str,
+        key: &str,
+        history: &mut Vec<ObjectVersion>,
+    ) -> StorageResult<()> {
+        let metadata = match self.head_object(bucket, key) {
+            Ok(metadata) => metadata,
+            Err(StorageError::NoSuchKey(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let data = if is_folder_marker(key) {
+            None
+        } else {
+            let file = uuid::Uuid::new_v4().simple().to_string();
+            let dir = self.version_data_dir(bucket);
+            self.create_dirs(&dir)?;
+            self.backend
+                .rename(&self.object_path(bucket, key)?, &dir.join(&file))?;
+            self.sync_parent(&dir.join(&file))?;
+            Some(file)
+        };
+        let removed = self
+            .backend
+            .remove_file(&self.object_metadata_path(bucket, key));
+        self.uncache(bucket, key);
+        match removed {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        history.insert(
+            0,
+            ObjectVersion::Object {
+                metadata: Box::new(metadata),
+                data,
+            },
+        );
+        Ok(())
+    }
+
+    /// Makes the newest version in the history of `bucket/key` current
+    /// again when there is no current object and that version is not a
+    /// delete marker.
+    fn promote(&self, bucket: &str, key: &str) -> StorageResult<()> {
+        match self.head_object(bucket, key) {
+            Err(StorageError::NoSuchKey(_)) => {}
+            result => return result.map(|_| ()),
+        }
+        let mut history = self.object_history(bucket, key)?;
+        let Some(ObjectVersion::Object { metadata, data }) = history.first().cloned() else {
+            return Ok(());
+        };
+        let object_path = self.object_path(bucket, key)?;
+        match data {
+            Some(file) => {
+                let _dirs = self.create_parent_dirs(key, &object_path)?;
+                self.backend
+                    .rename(&self.version_data_dir(bucket).join(file), &object_path)
+                    .map_err(|e| key_conflict(key, e))?;
+            }
+            // A folder marker.
+            None => self.create_marker_dir(key, &object_path)?,
+        }
+        self.uncache(bucket, key);
+        self.write_object_metadata(bucket, &metadata)?;
+        if is_folder_marker(key) {
+            self.create_marker_dir(key, &object_path)?;
+        }
+        history.remove(0);
+        self.write_history(bucket, key, &history)
+    }
+
+    /// Removes the data file of a noncurrent version, if it has one.
+    fn remove_version_data(&self, bucket: &str, version: &ObjectVersion) -> StorageResult<()> {
+        let ObjectVersion::Object {
+            data: Some(file), ..
+        } = version
+        else {
+            return Ok(());
+        };
+        match self
+            .backend
+            .remove_file(&self.version_data_dir(bucket).join(file))
+        {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes the current object of `bucket/key`, data and metadata.
+    fn remove_current(&self, bucket: &str, key: &str) -> StorageResult<()> {
+        let object_path = self.object_path(bucket, key)?;
+        if is_folder_marker(key) {
+            // Keys under the marker keep its directory; only the marker goes.
+            let metadata_path = self.object_metadata_path(bucket, key);
+            if !self.backend.is_dir(&object_path) || !self.backend.is_file(&metadata_path) {
+                return Err(StorageError::NoSuchKey(key.to_string()));
+            }
+            self.backend.remove_file(&metadata_path)?;
+            self.uncache(bucket, key);
+            return Ok(());
+        }
+        if !self.backend.is_file(&object_path) {
+            return Err(StorageError::NoSuchKey(key.to_string()));
+        }
+        self.backend.remove_file(&object_path)?;
+        self.uncache(bucket, key);
+        match self
+            .backend
+            .remove_file(&self.object_metadata_path(bucket, key))
+        {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Starts a multipart upload of `bucket/key`; `attributes` are applied to
+    /// the object when it is completed.
+    pub fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        attributes: ObjectAttributes,
+    ) -> StorageResult<MultipartUpload> {
+        let _span = self.span("create_multipart_upload", Some(bucket), Some(key));
+        if !self.bucket_exists(bucket) {
+            return Err(StorageError::NoSuchBucket(bucket.to_string()));
+        }
+        validate_object_key(key, self.escape_keys)?;
+        if is_folder_marker(key) {
+            return Err(StorageError::InvalidObjectName(
+                key.to_string(),
+                "keys ending in '/' are folder markers and must be empty".to_string(),
+            ));
+        }
+        let _writes = self.writable(bucket)?;
+        let upload = MultipartUpload {
+            upload_id: uuid::Uuid::new_v4().simple().to_string(),
+            key: key.to_string(),
+            initiated: self
+                .clock
+                .now()
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+            attributes,
+            etag_algorithm: Some(self.etag_algorithm(bucket)?),
+        };
+        let dir = self.upload_path(bucket, &upload.upload_id)?;
+        self.create_dirs(&dir)?;
+        let content = serde_json::to_string_pretty(&upload)
+            .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        self.replace_file(&dir.join("upload.json"), content.as_bytes())?;
+        debug!("Started upload {} of {}/{}", upload.upload_id, bucket, key);
+        Ok(upload)
+    }
+
+    /// The upload `upload_id` of `bucket/key`. As in S3, an upload ID does
+    /// not resolve with another key.
+    pub fn multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> StorageResult<MultipartUpload> {
+        let _span = self.span("multipart_upload", Some(bucket), Some(key));
+        if !self.bucket_exists(bucket) {
+            return Err(StorageError::NoSuchBucket(bucket.to_string()));
+        }
+        let path = self.upload_path(bucket, upload_id)?.join("upload.json");
+        let upload: MultipartUpload = match self.backend.read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(StorageError::NoSuchUpload(upload_id.to_string()));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if upload.key != key {
+            return Err(StorageError::NoSuchUpload(upload_id.to_string()));
+        }
+        Ok(upload)
+    }
+
+    /// In-progress uploads of a bucket, by key and then initiation time.
+    /// Upload directories without a readable `upload.json` (an initiate
+    /// still writing it, or a damaged one) are skipped.
+    pub fn multipart_uploads(&self, bucket: &str) -> StorageResult<Vec<MultipartUpload>> {
+        let _span = self.span("multipart_uploads", Some(bucket), None);
+        let dir = self.multipart_dir(bucket);
+        let entries = match self.backend.read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut uploads = Vec::new();
+        for entry in entries {
+            let path = dir.join(&entry.name).join("upload.json");
+            let content = match self.backend.read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) => {
+                    debug!("Skipping upload {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            match serde_json::from_str::<MultipartUpload>(&content) {
+                Ok(upload) => uploads.push(upload),
+                Err(e) => warn!("Skipping upload {}: {}", path.display(), e),
+            }
+        }
+        uploads.sort_by(|a, b| (&a.key, &a.initiated).cmp(&(&b.key, &b.initiated)));
+        Ok(uploads)
+    }
+
+    /// Stores part `part_number` of an upload, replacing an earlier upload
+    /// of the same part number. `checksum` is the part's additional
+    /// checksum, as verified on upload.
+    pub fn put_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: &[u8],
+        checksum: Option<String>,
+    ) -> StorageResult<Part> {
+        let upload = self.multipart_upload(bucket, key, upload_id)?;
+        let mut staged = self.stage(upload.etag_algorithm.unwrap_or_default())?;
+        staged.write(data)?;
+        self.put_staged_part(bucket, key, upload_id, part_number, staged, checksum)
+    }
+
+    /// [`put_part`](Self::put_part) with data received into a staging file.
+    pub fn put_staged_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        staged: StagedData,
+        checksum: Option<String>,
+    ) -> StorageResult<Part> {
+        let _span = self.span("put_part", Some(bucket), Some(key));
+        self.multipart_upload(bucket, key, upload_id)?;
+        let _writes = self.writable(bucket)?;
+        let dir = self.upload_path(bucket, upload_id)?;
+        // The directory disappears when the upload is aborted meanwhile.
+        let gone = |e: io::Error| match e.kind() {
+            io::ErrorKind::NotFound => StorageError::NoSuchUpload(upload_id.to_string()),
+            _ => StorageError::Io(e),
+        };
+        let part = Part {
+            part_number,
+            etag: staged.etag(),
+            size: staged.size(),
+            last_modified: self
+                .clock
+                .now()
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+            checksum,
+        };
+        // Moved in by a rename, so concurrent uploads of the same part
+        // number leave one of them intact.
+        staged
+            .store(self, &dir.join(part_number.to_string()))
+            .map_err(gone)?;
+        let content = serde_json::to_string(&part)
+            .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        self.replace_file(
+            &dir.join(format!("{}.json", part_number)),
+            content.as_bytes(),
+        )
+        .map_err(gone)?;
+        Ok(part)
+    }
+
+    /// Opens the data of part `part_number` of an upload; also returns its
+    /// size.
+    pub fn open_part(
+        &self,
+        bucket: &str,
+        upload_id: &str,
+        part_number: u32,
+    ) -> StorageResult<(ObjectReader, u64)> {
+        let _span = self.span("open_part", Some(bucket), None);
+        let path = self
+            .upload_path(bucket, upload_id)?
+            .join(part_number.to_string());
+        let gone = |e: io::Error| match e.kind() {
+            io::ErrorKind::NotFound => StorageError::NoSuchUpload(upload_id.to_string()),
+            _ => StorageError::Io(e),
+        };
+        // Parts are replaced by renames, never changed in place.
+        let size = self.backend.metadata(&path).map_err(gone)?.len;
+        Ok((self.backend.reader(&path, 0).map_err(gone)?, size))
+    }
+
+    /// The parts uploaded so far, by part number.
+    pub fn upload_parts(
+        &self,
+        bucket: &str,
+        upload_id: &str,
+    ) -> StorageResult<BTreeMap<u32, Part>> {
+        let _span = self.span("upload_parts", Some(bucket), None);
+        let dir = self.upload_path(bucket, upload_id)?;
+        let entries = match self.backend.read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(StorageError::NoSuchUpload(upload_id.to_string()));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut parts = BTreeMap::new();
+        for entry in entries {
+            let name = entry.name;
+            let Some(part_number) = name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".json"))
+                .and_then(|stem| stem.parse::<u32>().ok())
+            else {
+                continue;
+            };
+            let content = match self.backend.read_to_string(&dir.join(&name)) {
+                Ok(content) => content,
+                // Aborted, or completed, in the meantime.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    return Err(StorageError::NoSuchUpload(upload_id.to_string()));
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let part: Part = serde_json::from_str(&content)
+                .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+            parts.insert(part_number, part);
+        }
+        Ok(parts)
+    }
+
+    /// Assembles `parts` (as validated against [`Storage::upload_parts`])
+    /// into the upload's key, replacing any existing object, and ends the
+    /// upload. The object is assembled next to the parts and renamed into
+    /// place, so readers never see a partial object. Parts are hashed again
+    /// while copying, so one uploaded anew since it was validated fails with
+    /// [`StorageError::InvalidPart`].
+    pub fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        upload: &MultipartUpload,
+        parts: &[Part],
+    ) -> StorageResult<ObjectMetadata> {
+        let _span = self.span("complete_multipart_upload", Some(bucket), Some(&upload.key));
+        let dir = self.upload_path(bucket, &upload.upload_id)?;
+        let assembled = dir.join(format!("{}.tmp", uuid::Uuid::new_v4().simple()));
+        let algorithm = upload.etag_algorithm.unwrap_or_default();
+        let (size, etag) = match assemble_parts(&*self.backend, &dir, parts, algorithm, &assembled)
+        {
+            Ok(assembled) => assembled,
+            Err(e) => {
+                let _ = self.backend.remove_file(&assembled);
+                return Err(match e {
+                    StorageError::Io(e) if e.kind() == io::ErrorKind::NotFound => {
+                        StorageError::NoSuchUpload(upload.upload_id.clone())
+                    }
+                    e => e,
+                });
+            }
+        };
+
+        let placed = self.writable(bucket).and_then(|writes| {
+            let object_path = self.object_path(bucket, &upload.key)?;
+            self.remember(bucket, &upload.key);
+            let versioning = self.versioning(bucket)?;
+            let versions = versioning.map(|_| serialize(&self.versions));
+            let version_id = match versioning {
+                Some(status) => self.supersede(bucket, &upload.key, status)?,
+                None => None,
+            };
+            let dirs = self.create_parent_dirs(&upload.key, &object_path)?;
+            self.commit(&assembled, &object_path)
+                .map_err(|e| key_conflict(&upload.key, e))?;
+            drop(dirs);
+            self.uncache(bucket, &upload.key);
+            Ok((writes, versions, version_id))
+        });
+        let (_writes, _versions, version_id) = match placed {
+            Ok(placed) => placed,
+            Err(e) => {
+                let _ = self.backend.remove_file(&assembled);
+                return Err(e);
+            }
+        };
+        let metadata = ObjectMetadata {
+            key: upload.key.clone(),
+            version_id,
+            size,
+            etag,
+            last_modified: self
+                .clock
+                .now()
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+            etag_algorithm: Some(algorithm),
+            attributes: upload.attributes.clone(),
+        };
+        self.write_object_metadata(bucket, &metadata)?;
+        self.backend.remove_dir_all(&dir)?;
+        debug!(
+            "Completed upload {} of {}/{} from {} parts",
+            upload.upload_id,
+            bucket,
+            upload.key,
+            parts.len()
+        );
+        Ok(metadata)
+    }
+
+    /// Ends an upload, discarding its parts.
+    pub fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> StorageResult<()> {
+        let _span = self.span("abort_multipart_upload", Some(bucket), Some(key));
+        self.multipart_upload(bucket, key, upload_id)?;
+        let _writes = self.writable(bucket)?;
+        match self
+            .backend
+            .remove_dir_all(&self.upload_path(bucket, upload_id)?)
+        {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Err(StorageError::NoSuchUpload(upload_id.to_string()))
+            }
+            Err(e) => Err(e.into()),
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// Appends one line to the staged `kind` log of `day`.
+    pub fn append_system_log(&self, kind: &str, day: &str, line: &str) -> StorageResult<()> {
+        let _span = self.span("append_system_log", None, None);
+        let path = self.system_log_path(kind, day);
+        if let Some(parent) = path.parent() {
+            self.backend.create_dir_all(parent)?;
+        }
+        // One write per line, so concurrent appends do not interleave.
+        self.backend
+            .append(&path, format!("{}\n", line).as_bytes(), true)?;
+        Ok(())
+    }
+
+    /// Staged system logs as `(kind, day)`, oldest day first.
+    pub fn system_logs(&self) -> StorageResult<Vec<(String, String)>> {
+        let _span = self.span("system_logs", None, None);
+        let dir = self.base_path.join(SYSTEM_DIR).join("logs");
+        let entries = match self.backend.read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut logs = Vec::new();
+        for entry in entries {
+            let name = entry.name.to_string_lossy().to_string();
+            // <kind>-YYYY-MM-DD.log
+            if let Some(stem) = name.strip_suffix(".log")
+                && stem.len() > 11
+                && stem.is_char_boundary(stem.len() - 11)
+            {
+                let (kind, day) = stem.split_at(stem.len() - 11);
+                logs.push((kind.to_string(), day[1..].to_string()));
+            }
+        }
+        logs.sort_by(|a, b| (&a.1, &a.0).cmp(&(&b.1, &b.0)));
+        Ok(logs)
+    }
+
+    pub fn system_log(&self, kind: &str, day: &str) -> StorageResult<Vec<u8>> {
+        let _span = self.span("system_log", None, None);
+        Ok(self.backend.read(&self.system_log_path(kind, day))?)
+    }
+
+    pub fn remove_system_log(&self, kind: &str, day: &str) -> StorageResult<()> {
+        let _span = self.span("remove_system_log", None, None);
+        self.backend.remove_file(&self.system_log_path(kind, day))?;
+        Ok(())
+    }
+
+    /// Appends one metrics snapshot, a JSON line, to the snapshots of `day`.
+    pub fn append_metrics_snapshot(&self, day: &str, line: &str) -> StorageResult<()> {
+        let _span = self.span("append_metrics_snapshot", None, None);
+        self.backend.create_dir_all(&self.metrics_snapshots_dir())?;
+        let path = self.metrics_snapshots_path(day);
+        self.backend
+            .append(&path, format!("{}\n", line).as_bytes(), true)?;
+        Ok(())
+    }
+
+    /// Days (`YYYY-MM-DD`) with stored metrics snapshots, oldest first.
+    pub fn metrics_snapshot_days(&self) -> StorageResult<Vec<String>> {
+        let _span = self.span("metrics_snapshot_days", None, None);
+        let entries = match self.backend.read_dir(&self.metrics_snapshots_dir()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut days: Vec<String> = entries
+            .iter()
+            .filter_map(|entry| {
+                let name = entry.name.to_string_lossy();
+                name.strip_suffix(".ndjson").map(str::to_string)
+            })
+            .collect();
+        days.sort();
+        Ok(days)
+    }
+
+    /// The metrics snapshots of `day`, one JSON line each.
+    pub fn metrics_snapshots(&self, day: &str) -> StorageResult<String> {
+        let _span = self.span("metrics_snapshots", None, None);
+        Ok(self
+            .backend
+            .read_to_string(&self.metrics_snapshots_path(day))?)
+    }
+
+    pub fn remove_metrics_snapshots(&self, day: &str) -> StorageResult<()> {
+        let _span = self.span("remove_metrics_snapshots", None, None);
+        self.backend
+            .remove_file(&self.metrics_snapshots_path(day))?;
+        Ok(())
+    }
+
+    fn queued_event_path(&self, area: &str, seq: u64) -> PathBuf {
+        self.base_path
+            .join(SYSTEM_DIR)
+            .join("events")
+            .join(area)
+            .join(format!("{:016x}.json", seq))
+    }
+
+    /// Events queued in `area` (see [`EVENTS_PENDING`]), in queue order.
+    pub fn queued_events(&self, area: &str) -> StorageResult<Vec<QueuedEvent>> {
+        let _span = self.span("queued_events", None, None);
+        let dir = self.base_path.join(SYSTEM_DIR).join("events").join(area);
+        let entries = match self.backend.read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut events = Vec::new();
+        for entry in entries {
+            let path = dir.join(&entry.name);
+            // Leftovers of an interrupted write end in .json.tmp.
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let content = self.backend.read_to_string(&path)?;
+            match serde_json::from_str::<QueuedEvent>(&content) {
+                Ok(event) => events.push(event),
+                Err(e) => warn!("skipping unreadable queued event {:?}: {}", path, e),
+            }
+        }
+        events.sort_by_key(|e| e.seq);
+        Ok(events)
+    }
+
+    /// Stores `event` in `area`, replacing an earlier version of it.
+    pub fn write_queued_event(&self, area: &str, event: &QueuedEvent) -> StorageResult<()> {
+        let _span = self.span("write_queued_event", None, None);
+        let path = self.queued_event_path(area, event.seq);
+        if let Some(parent) = path.parent() {
+            self.backend.create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string_pretty(event)
+            .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        self.replace_file(&path, content.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn remove_queued_event(&self, area: &str, seq: u64) -> StorageResult<()> {
+        let _span = self.span("remove_queued_event", None, None);
+        match self.backend.remove_file(&self.queued_event_path(area, seq)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn write_object_metadata(&self, bucket: &str, metadata: &ObjectMetadata) -> StorageResult<()> {
+        let path = self.object_metadata_path(bucket, &metadata.key);
+        let content = serde_json::to_string(metadata)
+            .map_err(|e| StorageError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        let dirs = self.dirs.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some(parent) = path.parent() {
+            self.create_dirs(parent)?;
+        }
+        let written = self.replace_file(&path, content.as_bytes());
+        drop(dirs);
+        self.uncache(bucket, &metadata.key);
+        written?;
+        Ok(())
+    }
+}
+
+/// Takes one of the unit locks of [`Storage`]. They guard no data, so one
+/// poisoned by a panicking request is taken anyway rather than failing
+/// every later write.
+fn serialize(lock: &Mutex<()>) -> MutexGuard<'_, ()> {
+    lock.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Concatenates the part files in `dir` into `out`, checking every part
+/// against its ETag, of `algorithm`. Returns the size and ETag of the whole,
+/// which as in S3 is the digest of the parts' digests followed by `-` and
+/// the number of parts.
+fn assemble_parts(
+    backend: &dyn StorageBackend,
+    dir: &Path,
+    parts: &[Part],
+    algorithm: EtagAlgorithm,
+    out: &Path,
+) -> StorageResult<(u64, String)> {
+    let mut file = backend.create(out)?;
+    let mut part_digests = EtagHasher::new(algorithm);
+    let mut size = 0;
+    let mut buf = vec![0; 64 * 1024];
+    for part in parts {
+        let mut source = match backend.open(&dir.join(part.part_number.to_string())) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && backend.exists(dir) => {
+                return Err(StorageError::InvalidPart(part.part_number));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut part_hasher = EtagHasher::new(algorithm);
+        loop {
+            let n = io::Read::read(&mut source, &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            part_hasher.update(&buf[..n]);
+            io::Write::write_all(&mut file, &buf[..n])?;
+            size += n as u64;
+        }
+        let digest = part_hasher.finish();
+        if format!("\"{}\"", hex::encode(&digest)) != part.etag {
+            return Err(StorageError::InvalidPart(part.part_number));
+        }
+        part_digests.update(&digest);
+    }
+    io::Write::flush(&mut file)?;
+    let etag = format!("\"{}-{}\"", hex::encode(part_digests.finish()), parts.len());
+    Ok((size, etag))
+}
+
+/// What [`Storage::reindex_object`] found for a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReindexOutcome {
+    /// The metadata matched the data.
+    Consistent,
+    /// There was no metadata; it was derived from the file.
+    Created,
+    /// Size or ETag did not match the data and were recomputed.
+    Repaired,
+    /// The metadata could not be parsed and was derived anew, losing the
+    /// client-supplied attributes.
+    Replaced,
+}
+
+/// Metadata for a file without any: size, ETag of `algorithm` and
+/// modification time, no attributes. A directory (a folder marker) is empty.
+fn derived_metadata(
+    backend: &dyn StorageBackend,
+    key: &str,
+    object_path: &Path,
+    algorithm: EtagAlgorithm,
+) -> StorageResult<ObjectMetadata> {
+    let fs_meta = backend.metadata(object_path)?;
+    let modified = fs_meta.modified;
+    let mut hasher = EtagHasher::new(algorithm);
+    if !fs_meta.is_dir {
+        io::copy(&mut backend.open(object_path)?, &mut hasher)?;
+    }
+    Ok(ObjectMetadata {
+        key: key.to_string(),
+        version_id: None,
+        size: if fs_meta.is_dir { 0 } else { fs_meta.len },
+        etag: hasher.etag(),
+        last_modified: modified.to_rfc3339_opts(SecondsFormat::Millis, true),
+        etag_algorithm: Some(algorithm),
+        attributes: ObjectAttributes::default(),
+    })
+}
+
+/// Recursively collects the keys of the metadata files under `dir`, the
+/// object metadata directory of `key_prefix`. `.json` in a directory is the
+/// metadata of the folder marker named by the directory. `escaped`: file
+/// names are escaped key segments.
+fn collect_metadata_keys(
+    backend: &dyn StorageBackend,
+    dir: &Path,
+    key_prefix: &str,
+    escaped: bool,
+    keys: &mut Vec<String>,
+) -> StorageResult<()> {
+    for entry in backend.read_dir(dir)? {
+        let Some(name) = entry.name.to_str().map(str::to_string) else {
+            warn!("skipping non UTF-8 file name in {:?}", dir);
+            continue;
+        };
+        if entry.is_dir {
+            let Some(segment) = key_segment(&name, escaped) else {
+                warn!("skipping wrongly escaped file name {:?} in {:?}", name, dir);
+                continue;
+            };
+            let prefix = format!("{}{}/", key_prefix, segment);
+            collect_metadata_keys(backend, &dir.join(&entry.name), &prefix, escaped, keys)?;
+        } else if name == ".json" {
+            if !key_prefix.is_empty() {
+                keys.push(key_prefix.to_string());
+            }
+        } else if let Some(name) = name.strip_suffix(".json") {
+            let Some(segment) = key_segment(name, escaped) else {
+                warn!(
+                    "skipping wrongly escaped file name {:?}.json in {:?}",
+                    name, dir
+                );
+                continue;
+            };
+            keys.push(format!("{}{}", key_prefix, segment));
+        }
+    }
+    Ok(())
+}
+
+/// The directory part of a listing prefix: up to and including its last
+/// `/`, the directory it points into.
+fn prefix_dir(prefix: &str) -> &str {
+    match prefix.rfind('/') {
+        Some(idx) => &prefix[..=idx],
+        None => "",
+    }
+}
+
+/// An upload received into a staging file a piece at a time and hashed on
+/// the way, so that its size and ETag are known without reading it again
+/// and it never has to be held in memory. Dropped without being stored, the
+/// staging file is removed.
+pub struct StagedData {
+    backend: Arc<dyn StorageBackend>,
+    path: PathBuf,
+    /// Only `None` while being dropped or stored.
+    file: Option<Box<dyn io::Write + Send>>,
+    hasher: EtagHasher,
+    size: u64,
+    stored: bool,
+}
+
+impl StagedData {
+    pub fn write(&mut self, data: &[u8]) -> StorageResult<()> {
+        if let Some(file) = &mut self.file {
+            file.write_all(data)?;
+        }
+        self.hasher.update(data);
+        self.size += data.len() as u64;
+        Ok(())
+    }
+
+    /// Bytes written so far.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The ETag of what was written so far: its quoted hex digest.
+    pub fn etag(&self) -> String {
+        self.hasher.clone().etag()
+    }
+
+    /// The algorithm of [`etag`](Self::etag).
+    pub fn algorithm(&self) -> EtagAlgorithm {
+        self.hasher.algorithm()
+    }
+
+    /// Moves the staging file of `storage` to `path`, replacing any file
+    /// there.
+    fn store(mut self, storage: &Storage, path: &Path) -> io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+        storage.commit(&self.path, path)?;
+        self.stored = true;
+        Ok(())
+    }
+}
+
+impl Drop for StagedData {
+    fn drop(&mut self) {
+        // The writer goes first: dropping it may still write the file.
+        drop(self.file.take());
+        if !self.stored {
+            let _ = self.backend.remove_file(&self.path);
+        }
+    }
+}
+
+/// A reader of `len` bytes of `data` from `offset`, or fewer where it ends.
+pub fn slice_reader(data: Bytes, offset: u64, len: u64) -> ObjectReader {
+    let first = offset.min(data.len() as u64) as usize;
+    let last = offset.saturating_add(len).min(data.len() as u64) as usize;
+    Box::new(io::Cursor::new(data.slice(first..last)))
+}
+
+/// Keys ending in `/`: zero-byte "folders" as created by consoles and GUIs.
+pub fn is_folder_marker(key: &str) -> bool {
+    key.ends_with('/')
+}
+
+/// Recursively collects file paths under `dir` as keys, `key_prefix` being
+/// the key of `dir` itself (empty or ending in `/`). Subdirectories with
+/// metadata in `metadata_dir` (the metadata directory matching `dir`) are
+/// folder markers and are collected too. `escaped`: file names are escaped
+/// key segments.
+fn collect_keys(
+    backend: &dyn StorageBackend,
+    dir: &Path,
+    metadata_dir: &Path,
+    key_prefix: &str,
+    escaped: bool,
+    keys: &mut Vec<String>,
+) -> StorageResult<()> {
+    for entry in backend.read_dir(dir)? {
+        let Some(name) = entry.name.to_str().map(str::to_string) else {
+            warn!("skipping non UTF-8 file name in {:?}", dir);
+            continue;
+        };
+        let Some(segment) = key_segment(&name, escaped) else {
+            warn!("skipping wrongly escaped file name {:?} in {:?}", name, dir);
+            continue;
+        };
+        let key = format!("{}{}", key_prefix, segment);
+        if entry.is_dir {
+            let metadata_dir = metadata_dir.join(&name);
+            if backend.is_file(&metadata_dir.join(".json")) {
+                keys.push(format!("{}/", key));
+            }
+            let subdir = dir.join(&entry.name);
+            collect_keys(
+                backend,
+                &subdir,
+                &metadata_dir,
+                &format!("{}/", key),
+                escaped,
+                keys,
+            )?;
+        } else {
+            keys.push(key);
+        }
+    }
+    Ok(())
+}
+
+/// Checks S3 bucket naming rules: 3-63 characters of lowercase letters,
+/// digits, `.` and `-`, starting and ending with a letter or digit, and not
+/// formatted like an IPv4 address.
+pub fn validate_bucket_name(name: &str) -> StorageResult<()> {
+    let invalid = |reason: &str| {
+        Err(StorageError::InvalidBucketName(format!(
+            "{}: {}",
+            name, reason
+        )))
+    };
+    if name.len() < 3 || name.len() > 63 {
+        return invalid("must be between 3 and 63 characters long");
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.' || c == '-')
+    {
+        return invalid("may only contain lowercase letters, digits, '.' and '-'");
+    }
+    let alnum = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+    if !name.starts_with(alnum) || !name.ends_with(alnum) {
+        return invalid("must start and end with a letter or digit");
+    }
+    if name.contains("..") {
+        return invalid("must not contain two adjacent periods");
+    }
+    if name.parse::<std::net::Ipv4Addr>().is_ok() {
+        return invalid("must not be formatted as an IP address");
+    }
+    Ok(())
+}
+
+/// S3's limit on the UTF-8 encoded length of a key.
+pub const MAX_KEY_LENGTH: usize = 1024;
+
+/// Longest path segment of a key: with `.json` appended for the metadata
+/// file, it must still fit the 255-byte file name limit of common
+/// filesystems.
+pub const MAX_KEY_SEGMENT_LENGTH: usize = 250;
+
+/// Rejects keys that cannot be mapped safely onto the bucket directory:
+/// empty or over-long keys, keys with segments too long for a file name,
+/// and keys with empty, `.` or `..` path segments that would escape or alias
+/// other paths. A single trailing `/` (a folder marker) is allowed. On
+/// Windows, keys that are not valid file paths there are rejected too (see
+/// [`windows_path_problem`]), unless they are stored `escaped` (see
+/// [`escape_key_segment`]); the length of segments is then that of their
+/// escaped names.
+pub fn validate_object_key(key: &str, escaped: bool) -> StorageResult<()> {
+    let invalid = |reason: &str| {
+        Err(StorageError::InvalidObjectName(
+            key.to_string(),
+            reason.to_string(),
+        ))
+    };
+    if key.is_empty() {
+        return invalid("object key must not be empty");
+    }
+    if key.len() > MAX_KEY_LENGTH {
+        return Err(StorageError::KeyTooLong(key.len()));
+    }
+    if key.contains('\0') {
+        return invalid("object key must not contain NUL characters");
+    }
+    let path = key.strip_suffix('/').unwrap_or(key);
+    if path
+        .split('/')
+        .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+        return invalid("object key must not contain empty, '.' or '..' path segments");
+    }
+    let segment_length = |segment: &str| {
+        if escaped {
+            escape_key_segment(segment).len()
+        } else {
+            segment.len()
+        }
+    };
+    if path
+        .split('/')
+        .any(|segment| segment_length(segment) > MAX_KEY_SEGMENT_LENGTH)
+    {
+        return invalid("object key segments between '/' must be at most 250 bytes long");
+    }
+    if cfg!(windows)
+        && !escaped
+        && let Some(problem) = path.split('/').find_map(windows_path_problem)
+    {
+        return invalid(problem);
+    }
+    Ok(())
+}
+
+/// Device names Windows reserves in every directory, with any extension.
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Why a key segment cannot be a Windows file name: `\` would split it into
+/// directories, and reserved characters, device names (`CON`, `nul.txt`) and
+/// trailing dots or spaces are refused or silently altered by the
+/// filesystem.
+pub fn windows_path_problem(segment: &str) -> Option<&'static str> {
+    if segment.contains('\\') {
+        return Some("object key must not contain '\\' on Windows");
+    }
+    if segment
+        .chars()
+        .any(|c| c.is_control() || matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+    {
+        return Some("object key must not contain control characters or <>:\"|?* on Windows");
+    }
+    if segment.ends_with('.') || segment.ends_with(' ') {
+        return Some("object key segments must not end in '.' or ' ' on Windows");
+    }
+    let stem = segment.split('.').next().unwrap_or_default().trim_end();
+    if WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|name| stem.eq_ignore_ascii_case(name))
+    {
+        return Some("object key segments must not be Windows device names such as CON or NUL");
+    }
+    None
+}
+
+/// Whether `dir`, empty or ending in `/`, can be the directory of keys:
+/// keys have no empty, `.` or `..` segments, and a listing prefix with one
+/// must not lead the walk out of the bucket directory.
+fn is_key_dir(dir: &str) -> bool {
+    dir.is_empty()
+        || dir
+            .trim_end_matches('/')
+            .split('/')
+            .all(|segment| !segment.is_empty() && segment != "." && segment != "..")
+}
+
+/// The file name of key segment `segment` with `storage.escape_keys`: `%`,
+/// and what Windows filesystems refuse or alter (see
+/// [`windows_path_problem`]), as `%XX` for each byte, so that keys with
+/// them can be stored on any platform and read back from their paths. Of a
+/// device name only the first character is escaped (`%43ON.txt`), of
+/// trailing dots and spaces only the last.
+pub fn escape_key_segment(segment: &str) -> Cow<'_, str> {
+    let stem = segment.split('.').next().unwrap_or_default().trim_end();
+    let device = WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|name| stem.eq_ignore_ascii_case(name));
+    let last = segment.len().saturating_sub(1);
+    let escape = |index: usize, c: char| {
+        c.is_control()
+            || matches!(c, '%' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*')
+            || (index == 0 && device)
+            || (index == last && matches!(c, '.' | ' '))
+    };
+    if !segment.char_indices().any(|(index, c)| escape(index, c)) {
+        return Cow::Borrowed(segment);
+    }
+    let mut name = String::with_capacity(segment.len() + 8);
+    for (index, c) in segment.char_indices() {
+        if escape(index, c) {
+            for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                name.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            name.push(c);
+        }
+    }
+    Cow::Owned(name)
+}
+
+/// The key segment stored under file name `name`, unescaped if `escaped`
+/// (see [`escape_key_segment`]); `None` for a malformed escape.
+fn key_segment(name: &str, escaped: bool) -> Option<Cow<'_, str>> {
+    if !escaped || !name.contains('%') {
+        return Some(Cow::Borrowed(name));
+    }
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut rest = name.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail
+                .get(..2)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))?;
+            bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok().map(Cow::Owned)
+}
+
+/// A key whose path collides with an existing object or prefix (e.g. `a`
+/// and `a/b`) surfaces as an IO error from the filesystem.
+fn key_conflict(key: &str, e: io::Error) -> StorageError {
+    match e.kind() {
+        io::ErrorKind::NotADirectory
+        | io::ErrorKind::IsADirectory
+        | io::ErrorKind::AlreadyExists => StorageError::InvalidObjectName(
+            key.to_string(),
+            "object key conflicts with an existing key prefix".to_string(),
+        ),
+        _ => StorageError::Io(e),
+    }
+}
+
+/// Whether `etag` is that of a multipart upload, `"{digest}-{parts}"`.
+fn is_multipart_etag(etag: &str) -> bool {
+    etag.trim_matches('"').contains('-')
+}
+
+/// Computes a digest of [`EtagAlgorithm`] over data arriving in pieces.
+#[derive(Clone)]
+enum EtagHasher {
+    Md5(Md5),
+    Sha256(Sha256),
+}
+
+impl EtagHasher {
+    fn new(algorithm: EtagAlgorithm) -> Self {
+        match algorithm {
+            EtagAlgorithm::Md5 => EtagHasher::Md5(Md5::new()),
+            EtagAlgorithm::Sha256 => EtagHasher::Sha256(Sha256::new()),
+        }
+    }
+
+    fn algorithm(&self) -> EtagAlgorithm {
+        match self {
+            EtagHasher::Md5(_) => EtagAlgorithm::Md5,
+            EtagHasher::Sha256(_) => EtagAlgorithm::Sha256,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            EtagHasher::Md5(hasher) => hasher.update(data),
+            EtagHasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        match self {
+            EtagHasher::Md5(hasher) => hasher.finalize().to_vec(),
+            EtagHasher::Sha256(hasher) => hasher.finish().to_vec(),
+        }
+    }
+
+    /// The digest as an ETag: quoted hex.
+    fn etag(self) -> String {
+        format!("\"{}\"", hex::encode(self.finish()))
+    }
+}
+
+impl io::Write for EtagHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+    use crate::config::Config;
+
+    fn memory_storage() -> Storage {
+        let config = Config::for_tests().storage;
+        Storage::new(&config, Arc::new(SystemClock), Arc::new(Metrics::new())).unwrap()
+    }
+
+    fn bucket(name: &str) -> BucketMetadata {
+        let metadata = serde_json::json!({ "name": name, "created": "2024-01-01T00:00:00.000Z" });
+        serde_json::from_value(metadata).unwrap()
+    }
+
+    #[test]
+    fn derived_object_metadata_is_stored_and_survives_a_restart() {
+        let dir = std::env::temp_dir().join(format!("s3clone-{}", uuid::Uuid::new_v4().simple()));
+        let mut config = Config::for_tests().storage;
+        config.location = dir.display().to_string();
+        config.backend = StorageBackendKind::Filesystem;
+        let open = || Storage::new(&config, Arc::new(SystemClock), Arc::new(Metrics::new()));
+        let storage = open().unwrap();
+        storage.create_bucket(&bucket("foo")).unwrap();
+        // Copied in by hand, without metadata.
+        std::fs::create_dir_all(dir.join("foo/docs")).unwrap();
+        std::fs::write(dir.join("foo/docs/a.txt"), b"hello").unwrap();
+        let derived = storage.head_object("foo", "docs/a.txt").unwrap();
+        assert_eq!(
+            derived.etag,
+            format!("\"{}\"", hex::encode(Md5::digest(b"hello")))
+        );
+        assert_eq!(storage.list_keys("foo", "").unwrap(), ["docs/a.txt"]);
+        drop(storage);
+
+        // Other bytes of the same size: the restarted storage answers from
+        // the stored metadata rather than hashing the file again.
+        std::fs::write(dir.join("foo/docs/a.txt"), b"HELLO").unwrap();
+        let storage = open().unwrap();
+        let stored = storage.head_object("foo", "docs/a.txt").unwrap();
+        assert_eq!(stored.etag, derived.etag);
+        assert_eq!(stored.last_modified, derived.last_modified);
+        // Reindexing is what catches up with such changes.
+        assert!(matches!(
+            storage.reindex_object("foo", "docs/a.txt"),
+            Ok(ReindexOutcome::Repaired)
+        ));
+        let repaired = storage.head_object("foo", "docs/a.txt").unwrap();
+        assert_eq!(
+            repaired.etag,
+            format!("\"{}\"", hex::encode(Md5::digest(b"HELLO")))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bucket_names_cannot_leave_the_base_path() {
+        let storage = memory_storage();
+        storage.create_bucket(&bucket("foo")).unwrap();
+        // Decoded from `/foo%2F..%2F../secret.txt`.
+        assert!(!storage.bucket_exists("foo/../.."));
+        assert!(!storage.bucket_exists(".."));
+        assert!(!storage.bucket_exists(SYSTEM_DIR));
+        assert!(matches!(
+            storage.bucket_metadata("foo/../.."),
+            Err(StorageError::NoSuchBucket(_))
+        ));
+        assert_eq!(storage.list_buckets().unwrap(), vec!["foo".to_string()]);
+    }
+
+    fn staged(storage: &Storage, data: &[u8]) -> StagedData {
+        let mut staged = storage.stage(EtagAlgorithm::Md5).unwrap();
+        staged.write(data).unwrap();
+        staged
+    }
+
+    #[test]
+    fn appends_add_the_staged_data() {
+        let storage = memory_storage();
+        storage.create_bucket(&bucket("foo")).unwrap();
+        let attributes = ObjectAttributes::default();
+        let chunk = vec![b'a'; 100 * 1024];
+        storage
+            .append_object(
+                "foo",
+                "log",
+                0,
+                staged(&storage, b"head "),
+                attributes.clone(),
+            )
+            .unwrap();
+        let metadata = storage
+            .append_object(
+                "foo",
+                "log",
+                5,
+                staged(&storage, &chunk),
+                attributes.clone(),
+            )
+            .unwrap();
+        let (_, data) = storage.get_object("foo", "log").unwrap();
+        assert_eq!(metadata.size, 5 + chunk.len() as u64);
+        assert_eq!(data, [b"head ".as_slice(), &chunk].concat());
+        assert_eq!(
+            metadata.etag,
+            format!("\"{}\"", hex::encode(Md5::digest(&data)))
+        );
+        assert!(matches!(
+            storage.append_object("foo", "log", 5, staged(&storage, b"x"), attributes),
+            Err(StorageError::PositionNotEqualToLength(_, _))
+        ));
+        // Nothing staged is left behind.
+        let staging = storage.base_path.join(SYSTEM_DIR).join(STAGING_DIR);
+        assert!(
+            storage
+                .backend
+                .read_dir(&staging)
+                .unwrap_or_default()
+                .is_empty()
+        );
+    }
+}
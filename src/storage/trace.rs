@@ -0,0 +1,107 @@
+use crate::config::StorageTracingConfig;
+use crate::metrics::Metrics;
+use log::{Level, log, log_enabled};
+use md5::{Digest, Md5};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Times storage operations: every one is recorded in the
+/// `s3clone_storage_operation_duration_seconds` histogram and, when it
+/// ends, logged as a span with its bucket, key and duration (at `warn` when
+/// slow, else at `trace`).
+pub struct Tracer {
+    metrics: Arc<Metrics>,
+    slow: Option<Duration>,
+    max_keys: usize,
+    /// `bucket/key` of the keys logged as they are.
+    keys: Mutex<HashSet<String>>,
+}
+
+impl Tracer {
+    pub fn new(config: &StorageTracingConfig, metrics: Arc<Metrics>) -> Self {
+        Self {
+            metrics,
+            slow: (config.slow_operation_ms > 0)
+                .then(|| Duration::from_millis(config.slow_operation_ms)),
+            max_keys: config.max_traced_keys,
+            keys: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Starts the span of `operation`, ended when it is dropped.
+    pub fn span<'a>(
+        &'a self,
+        operation: &'static str,
+        bucket: Option<&'a str>,
+        key: Option<&'a str>,
+    ) -> Span<'a> {
+        Span {
+            tracer: self,
+            operation,
+            bucket,
+            key,
+            start: Instant::now(),
+        }
+    }
+
+    /// `key` as logged: as it is for the first `max_keys` distinct keys,
+    /// then as `#` and the start of its MD5, which still tells repeated
+    /// operations on one key apart without growing the set of values.
+    fn key_label<'k>(&self, bucket: &str, key: &'k str) -> Cow<'k, str> {
+        let mut keys = self.keys.lock().unwrap();
+        let qualified = format!("{}/{}", bucket, key);
+        if keys.contains(&qualified) {
+            return Cow::Borrowed(key);
+        }
+        if keys.len() < self.max_keys {
+            keys.insert(qualified);
+            return Cow::Borrowed(key);
+        }
+        Cow::Owned(format!("#{}", &hex::encode(Md5::digest(key))[..16]))
+    }
+}
+
+/// A storage operation in progress.
+pub struct Span<'a> {
+    tracer: &'a Tracer,
+    operation: &'static str,
+    bucket: Option<&'a str>,
+    key: Option<&'a str>,
+    start: Instant,
+}
+
+impl Drop for Span<'_> {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        let tracer = self.tracer;
+        tracer.metrics.observe_storage(self.operation, elapsed);
+        let level = match tracer.slow {
+            Some(slow) if elapsed >= slow => Level::Warn,
+            _ => Level::Trace,
+        };
+        if !log_enabled!(level) {
+            return;
+        }
+        let mut fields = String::new();
+        if let Some(bucket) = self.bucket {
+            fields.push_str(&format!(" bucket={}", bucket));
+            if let Some(key) = self.key {
+                fields.push_str(&format!(" key={}", tracer.key_label(bucket, key)));
+            }
+        }
+        let prefix = match level {
+            Level::Warn => "Slow storage ",
+            _ => "storage ",
+        };
+        log!(
+            level,
+            "{}{}{} took {:.3} ms",
+            prefix,
+            self.operation,
+            fields,
+            elapsed.as_secs_f64() * 1000.0
+        );
+    }
+}
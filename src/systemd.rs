@@ -0,0 +1,127 @@
+//! systemd integration without libsystemd: socket activation (listening
+//! sockets passed in `LISTEN_FDS`) and `sd_notify` messages for
+//! `Type=notify` units (readiness, watchdog pings, stopping). Both are
+//! no-ops on other platforms.
+
+use log::{debug, warn};
+use std::env;
+use std::io;
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::fd::{FromRawFd, RawFd};
+use std::time::Duration;
+
+/// The first file descriptor passed by socket activation.
+#[cfg(unix)]
+const LISTEN_FDS_START: RawFd = 3;
+
+/// `FileDescriptorName=` of the socket serving the admin API on its own port.
+pub const ADMIN_SOCKET_NAME: &str = "admin";
+
+/// Listening sockets passed by systemd, with their `FileDescriptorName=`
+/// (the socket unit's name unless set).
+#[derive(Debug, Default)]
+pub struct ActivatedSockets(Vec<(String, TcpListener)>);
+
+impl ActivatedSockets {
+    /// Takes over the sockets passed to this process. Must be called at most
+    /// once, since the descriptors are owned by the returned listeners.
+    #[cfg(unix)]
+    pub fn from_env() -> Self {
+        let pid_matches = env::var("LISTEN_PID")
+            .ok()
+            .and_then(|pid| pid.parse::<u32>().ok())
+            .is_some_and(|pid| pid == std::process::id());
+        let count = env::var("LISTEN_FDS")
+            .ok()
+            .and_then(|count| count.parse::<RawFd>().ok())
+            .unwrap_or(0);
+        if !pid_matches || count <= 0 {
+            return Self::default();
+        }
+        let names: Vec<String> = env::var("LISTEN_FDNAMES")
+            .unwrap_or_default()
+            .split(':')
+            .map(str::to_string)
+            .collect();
+        let mut sockets = Vec::new();
+        for (i, fd) in (LISTEN_FDS_START..LISTEN_FDS_START + count).enumerate() {
+            let name = names.get(i).cloned().unwrap_or_default();
+            // Safety: systemd hands these descriptors to this process, and
+            // nothing else in it refers to them.
+            let listener = unsafe { TcpListener::from_raw_fd(fd) };
+            match listener.local_addr() {
+                Ok(addr) => {
+                    debug!("systemd passed socket {:?} on {}", name, addr);
+                    sockets.push((name, listener));
+                }
+                Err(e) => warn!("ignoring socket {:?} passed by systemd: {}", name, e),
+            }
+        }
+        Self(sockets)
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_env() -> Self {
+        Self::default()
+    }
+
+    /// The socket named `name`.
+    pub fn take(&mut self, name: &str) -> Option<TcpListener> {
+        let i = self.0.iter().position(|(n, _)| n == name)?;
+        Some(self.0.remove(i).1)
+    }
+
+    /// The first remaining socket not named [`ADMIN_SOCKET_NAME`].
+    pub fn take_s3(&mut self) -> Option<TcpListener> {
+        let i = self.0.iter().position(|(n, _)| n != ADMIN_SOCKET_NAME)?;
+        Some(self.0.remove(i).1)
+    }
+}
+
+/// Sends `state` (e.g. `READY=1`) to the service manager when running under
+/// a unit with `NotifyAccess=`; a no-op otherwise.
+pub fn notify(state: &str) {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = send(path.to_string_lossy().as_ref(), state) {
+        warn!("failed to notify systemd of {:?}: {}", state, e);
+    }
+}
+
+#[cfg(unix)]
+fn send(path: &str, state: &str) -> io::Result<()> {
+    let socket = std::os::unix::net::UnixDatagram::unbound()?;
+    match path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => return Err(io::Error::from(io::ErrorKind::Unsupported)),
+        None => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_path: &str, _state: &str) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// How often to send `WATCHDOG=1` when the unit sets `WatchdogSec=`: half
+/// the watchdog timeout, as systemd recommends.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID")
+        && pid.parse::<u32>().ok() != Some(std::process::id())
+    {
+        return None;
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
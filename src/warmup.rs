@@ -0,0 +1,83 @@
+//! Startup warm-up of hot buckets. Listings walk the bucket directory and
+//! read every key's metadata file, so the first listings after a deploy (or
+//! on a fresh host) are slow until the kernel has those in its caches. The
+//! warm-up does the same walk for `warmup.buckets` before the listeners
+//! open, in parallel per bucket and within `warmup.timeout_secs`.
+
+use crate::config::WarmupConfig;
+use crate::storage::{Storage, StorageError};
+use log::{info, warn};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Walks every configured bucket; returns once all are done or the timeout
+/// has passed, leaving unfinished walks to stop at the deadline.
+pub async fn run(storage: Arc<Storage>, config: &WarmupConfig) {
+    if config.buckets.is_empty() {
+        return;
+    }
+    let started = Instant::now();
+    let deadline = started + Duration::from_secs(config.timeout_secs);
+    let walks: Vec<_> = config
+        .buckets
+        .iter()
+        .map(|bucket| {
+            let storage = storage.clone();
+            let bucket = bucket.clone();
+            tokio::task::spawn_blocking(move || {
+                let result = walk(&storage, &bucket, deadline);
+                (bucket, result)
+            })
+        })
+        .collect();
+    let (mut warmed, mut keys) = (0, 0);
+    for walk in walks {
+        match walk.await {
+            Ok((bucket, Ok(Walk { keys: n, complete }))) => {
+                warmed += 1;
+                keys += n;
+                if !complete {
+                    warn!(
+                        "Warm-up of {} stopped after {} keys at the {} s timeout",
+                        bucket, n, config.timeout_secs
+                    );
+                }
+            }
+            Ok((bucket, Err(e))) => warn!("Warm-up of {} failed: {}", bucket, e),
+            Err(e) => warn!("Warm-up task failed: {}", e),
+        }
+    }
+    info!(
+        "Warmed up {} of {} buckets ({} keys) in {} ms",
+        warmed,
+        config.buckets.len(),
+        keys,
+        started.elapsed().as_millis()
+    );
+}
+
+struct Walk {
+    keys: usize,
+    /// False when the deadline passed first.
+    complete: bool,
+}
+
+/// Lists the bucket and reads the metadata of every key, as a listing does.
+fn walk(storage: &Storage, bucket: &str, deadline: Instant) -> Result<Walk, StorageError> {
+    let bucket = storage.resolve_bucket(bucket)?;
+    let mut walk = Walk {
+        keys: 0,
+        complete: false,
+    };
+    for key in storage.list_keys(&bucket, "")? {
+        if Instant::now() >= deadline {
+            return Ok(walk);
+        }
+        // A key deleted meanwhile, or with damaged metadata, is not worth
+        // failing the warm-up over; the listing will report it.
+        let _ = storage.head_object(&bucket, &key);
+        walk.keys += 1;
+    }
+    walk.complete = true;
+    Ok(walk)
+}
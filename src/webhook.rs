@@ -0,0 +1,101 @@
+//! Minimal HTTP/1.1 client for delivering event notifications to webhook
+//! targets: one `POST` per connection (`Connection: close`), plain `http://`
+//! only, success being any `2xx` status.
+
+use anyhow::{Context, Result, bail};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Longest status line accepted from a target.
+const MAX_STATUS_LINE: usize = 8 * 1024;
+
+/// A parsed `http://host[:port]/path` target URL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    /// `host:port`, as connected to and sent in `Host`.
+    pub authority: String,
+    /// Path and query, starting with `/`.
+    pub path: String,
+}
+
+impl Endpoint {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let Some(rest) = url.strip_prefix("http://") else {
+            return Err(format!("{} must start with http://", url));
+        };
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(i) if rest[i..].starts_with('/') => (&rest[..i], rest[i..].to_string()),
+            Some(i) => (&rest[..i], format!("/{}", &rest[i..])),
+            None => (rest, "/".to_string()),
+        };
+        if authority.is_empty() || authority.contains('@') {
+            return Err(format!("{} has no valid host", url));
+        }
+        let authority = match authority.rsplit_once(':') {
+            Some((_, port)) if !authority.ends_with(']') => {
+                port.parse::<u16>()
+                    .map_err(|_| format!("{} has an invalid port", url))?;
+                authority.to_string()
+            }
+            _ => format!("{}:80", authority),
+        };
+        Ok(Self { authority, path })
+    }
+}
+
+/// POSTs `body` as JSON to `endpoint`, failing on connection errors,
+/// timeouts and non-`2xx` responses.
+pub async fn post_json(endpoint: &Endpoint, body: &[u8], timeout: Duration) -> Result<()> {
+    match tokio::time::timeout(timeout, post(endpoint, body)).await {
+        Ok(result) => result,
+        Err(_) => bail!("no response within {} ms", timeout.as_millis()),
+    }
+}
+
+async fn post(endpoint: &Endpoint, body: &[u8]) -> Result<()> {
+    let mut stream = TcpStream::connect(&endpoint.authority)
+        .await
+        .with_context(|| format!("failed to connect to {}", endpoint.authority))?;
+    let head = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: s3-clone\r\n\
+         Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        endpoint.path,
+        endpoint.authority,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.flush().await?;
+
+    let status = read_status(&mut stream).await?;
+    if !(200..300).contains(&status) {
+        bail!("target responded with status {}", status);
+    }
+    Ok(())
+}
+
+/// Reads the response up to the end of the status line and returns the
+/// status code; the rest of the response is ignored.
+pub async fn read_status(stream: &mut TcpStream) -> Result<u16> {
+    let mut line = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !line.contains(&b'\n') {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            bail!("connection closed before a response was received");
+        }
+        line.extend_from_slice(&buf[..n]);
+        if line.len() > MAX_STATUS_LINE {
+            bail!("response status line is too long");
+        }
+    }
+    let line = String::from_utf8_lossy(&line);
+    let line = line.lines().next().unwrap_or_default();
+    // HTTP/1.1 200 OK
+    let mut parts = line.split_whitespace();
+    match (parts.next(), parts.next().map(str::parse::<u16>)) {
+        (Some(version), Some(Ok(status))) if version.starts_with("HTTP/") => Ok(status),
+        _ => bail!("malformed response status line {:?}", line),
+    }
+}
@@ -0,0 +1,127 @@
+#!/bin/bash
+# ListObjects / ListObjectsV2 pagination tests: CommonPrefixes count toward
+# max-keys and continuation tokens / markers resume after a prefix group.
+# Needs a running server, the aws CLI and jq; credentials come from the
+# usual AWS_ACCESS_KEY_ID / AWS_SECRET_ACCESS_KEY environment variables.
+set -e
+
+# Configuration
+ENDPOINT="${ENDPOINT:-http://localhost:8088}"
+BUCKET="test-listing-$(date +%s)"
+REGION="us-east-1"
+KEYS=("a/1" "a/2" "a/3" "b" "c/1" "c/2" "d" "e f/1" "g+h%")
+
+FAILED=0
+
+s3api() {
+    aws --endpoint-url "$ENDPOINT" --region "$REGION" s3api "$@"
+}
+
+# Entries (keys and common prefixes) of one response, in listing order.
+entries() {
+    jq -r '[(.Contents // [])[].Key, (.CommonPrefixes // [])[].Prefix] | sort | join(",")'
+}
+
+# Walks all ListObjectsV2 pages, printing each page as [entry,entry,...].
+walk_v2() {
+    local token="" out="" resp
+    while :; do
+        if [ -n "$token" ]; then
+            resp=$(s3api list-objects-v2 --no-paginate --bucket "$BUCKET" "$@" --continuation-token "$token")
+        else
+            resp=$(s3api list-objects-v2 --no-paginate --bucket "$BUCKET" "$@")
+        fi
+        out="$out[$(echo "$resp" | entries)]"
+        [ "$(echo "$resp" | jq -r '.IsTruncated')" = "true" ] || break
+        token=$(echo "$resp" | jq -r '.NextContinuationToken')
+    done
+    echo "$out"
+}
+
+# Walks all ListObjects (V1) pages, continuing from NextMarker or, without a
+# delimiter, from the last key, as clients do.
+walk_v1() {
+    local marker="" out="" resp
+    while :; do
+        if [ -n "$marker" ]; then
+            resp=$(s3api list-objects --no-paginate --bucket "$BUCKET" "$@" --marker "$marker")
+        else
+            resp=$(s3api list-objects --no-paginate --bucket "$BUCKET" "$@")
+        fi
+        out="$out[$(echo "$resp" | entries)]"
+        [ "$(echo "$resp" | jq -r '.IsTruncated')" = "true" ] || break
+        marker=$(echo "$resp" | jq -r '.NextMarker // (.Contents | last | .Key)')
+    done
+    echo "$out"
+}
+
+check() {
+    local name="$1" expected="$2" actual="$3"
+    if [ "$expected" = "$actual" ]; then
+        echo "ok   $name"
+    else
+        echo "FAIL $name"
+        echo "     expected: $expected"
+        echo "     actual:   $actual"
+        FAILED=1
+    fi
+}
+
+echo "Creating bucket: $BUCKET"
+s3api create-bucket --bucket "$BUCKET" > /dev/null
+echo "data" > listing-body.txt
+for key in "${KEYS[@]}"; do
+    s3api put-object --bucket "$BUCKET" --key "$key" --body listing-body.txt > /dev/null
+done
+
+check "v2 delimiter, max-keys 1" \
+    "[a/][b][c/][d][e f/][g+h%]" "$(walk_v2 --delimiter / --max-keys 1)"
+check "v2 delimiter, max-keys 2" \
+    "[a/,b][c/,d][e f/,g+h%]" "$(walk_v2 --delimiter / --max-keys 2)"
+check "v2 delimiter, page exactly full is not truncated" \
+    "[a/,b,c/,d,e f/,g+h%]" "$(walk_v2 --delimiter / --max-keys 6)"
+check "v2 no delimiter, max-keys 4" \
+    "[a/1,a/2,a/3,b][c/1,c/2,d,e f/1][g+h%]" "$(walk_v2 --max-keys 4)"
+check "v2 start-after inside a prefix group repeats the group" \
+    "[a/,b,c/,d,e f/,g+h%]" "$(walk_v2 --delimiter / --start-after a/1)"
+check "v2 start-after a common prefix skips the group" \
+    "[b,c/,d,e f/,g+h%]" "$(walk_v2 --delimiter / --start-after a/)"
+check "v2 prefix and delimiter" \
+    "[a/1,a/2][a/3]" "$(walk_v2 --prefix a/ --delimiter / --max-keys 2)"
+check "v2 prefix without trailing delimiter" \
+    "[c/]" "$(walk_v2 --prefix c --delimiter /)"
+check "v2 non-slash delimiter" \
+    "[a/1,a/2,a/3][b,c/1,c/2][d,e f/1,g+h%]" "$(walk_v2 --delimiter 2 --max-keys 3)"
+check "v1 delimiter, max-keys 1" \
+    "[a/][b][c/][d][e f/][g+h%]" "$(walk_v1 --delimiter / --max-keys 1)"
+check "v1 delimiter, max-keys 4" \
+    "[a/,b,c/,d][e f/,g+h%]" "$(walk_v1 --delimiter / --max-keys 4)"
+check "v1 no delimiter, max-keys 4" \
+    "[a/1,a/2,a/3,b][c/1,c/2,d,e f/1][g+h%]" "$(walk_v1 --max-keys 4)"
+
+resp=$(s3api list-objects-v2 --no-paginate --bucket "$BUCKET" --max-keys 0)
+check "v2 max-keys 0" "0 false" "$(echo "$resp" | jq -r '"\(.KeyCount) \(.IsTruncated)"')"
+
+if s3api list-objects-v2 --no-paginate --bucket "$BUCKET" --continuation-token not-a-token > /dev/null 2>&1; then
+    check "v2 invalid continuation token is rejected" "error" "ok"
+else
+    check "v2 invalid continuation token is rejected" "error" "error"
+fi
+
+rm -rf listing-sync
+aws --endpoint-url "$ENDPOINT" --region "$REGION" s3 sync "s3://$BUCKET" listing-sync --page-size 1 > /dev/null
+check "s3 sync with page size 1 fetches every key" \
+    "9" "$(find listing-sync -type f | wc -l | tr -d ' ')"
+
+echo "Cleaning up"
+for key in "${KEYS[@]}"; do
+    s3api delete-object --bucket "$BUCKET" --key "$key"
+done
+s3api delete-bucket --bucket "$BUCKET"
+rm -rf listing-body.txt listing-sync
+
+if [ "$FAILED" -ne 0 ]; then
+    echo "Listing tests failed."
+    exit 1
+fi
+echo "Listing tests completed successfully."